@@ -0,0 +1,52 @@
+//! Install fake `ping`/`traceroute`/`mtr` shims that replay captured output,
+//! to reproduce a parsing problem without the original platform.
+//!
+//! ```sh
+//! cargo run --example fake_tools --features test-harness -- shims \
+//!     ping=captured-ping.txt traceroute=captured-trace.txt:1
+//! PATH="$PWD/shims:$PATH" icmpmolester --config lines.toml
+//! ```
+//!
+//! Each `tool=file[:exit_code]` answers for every target.
+
+use std::env;
+use std::process::ExitCode;
+
+use icmpmolester::harness::{FakeTools, Script};
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let Some(dir) = args.next() else {
+        eprintln!("usage: fake_tools <dir> <tool>=<file>[:exit_code]...");
+        return ExitCode::FAILURE;
+    };
+    let mut tools = match FakeTools::in_dir(&dir) {
+        Ok(tools) => tools,
+        Err(err) => {
+            eprintln!("Cannot create {dir}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    for spec in args {
+        let Some((tool, source)) = spec.split_once('=') else {
+            eprintln!("Expected <tool>=<file>[:exit_code], got '{spec}'");
+            return ExitCode::FAILURE;
+        };
+        let (file, exit_code) = source
+            .rsplit_once(':')
+            .and_then(|(file, code)| Some((file, code.parse().ok()?)))
+            .unwrap_or((source, 0));
+        let installed = Script::from_file(file).and_then(|script| {
+            tools
+                .fallback(tool, script.exit_code(exit_code))
+                .map(|_| ())
+        });
+        if let Err(err) = installed {
+            eprintln!("Cannot install {tool} from {file}: {err}");
+            return ExitCode::FAILURE;
+        }
+    }
+    let dir = tools.keep();
+    println!("Shims installed; run with PATH=\"{}:$PATH\"", dir.display());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,15 @@
+#![no_main]
+
+use icmpmolester::parse;
+use libfuzzer_sys::fuzz_target;
+
+// Every parser must accept arbitrary text without panicking.
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    parse::ping_summary(&text);
+    parse::traceroute_hops(&text);
+    parse::mtr_hops(&text);
+    // Use the start of the input as the target so both hop_count paths run.
+    let target = text.split_whitespace().next().unwrap_or_default();
+    parse::hop_count(&text, target);
+});
@@ -0,0 +1,1092 @@
+//! Alert policy: turns check outcomes into alert events for notification sinks.
+//!
+//! Thresholds, hold-back and flap windows and dependencies are judged here
+//! rather than in the summary formatters or the run loop, so the
+//! presentation layer only renders verdicts and sinks receive structured
+//! [`AlertEvent`]s instead of parsing text.
+
+use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::checks::CheckOutcome;
+use crate::config::CustomStatus;
+use crate::diagnostics::DualStackReport;
+use crate::report::RunMetadata;
+use crate::runner::{LineResult, LineStatus};
+use crate::state::{AlertState, FlapChange, Transition};
+
+/// Verdict for a single check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assessment {
+    pub status: LineStatus,
+    /// Why the check is not OK; `None` when it passed.
+    pub reason: Option<String>,
+}
+
+impl Assessment {
+    fn ok() -> Self {
+        Self {
+            status: LineStatus::Ok,
+            reason: None,
+        }
+    }
+
+    fn alert(reason: String) -> Self {
+        Self {
+            status: LineStatus::Alert,
+            reason: Some(reason),
+        }
+    }
+
+    fn unknown(reason: String) -> Self {
+        Self {
+            status: LineStatus::Unknown,
+            reason: Some(reason),
+        }
+    }
+}
+
+/// How urgently an event needs attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The check could not be judged (e.g. loss could not be parsed).
+    Warning,
+    /// A threshold was breached or the check failed outright.
+    Critical,
+}
+
+impl Severity {
+    /// Alert level of a line or check in `status`: UNKNOWN is a warning,
+    /// ALERT and ERRORED are critical, and the rest raise no alert.
+    pub fn of(status: LineStatus) -> Option<Self> {
+        match status {
+            LineStatus::Snoozed
+            | LineStatus::Ok
+            | LineStatus::Interrupted
+            | LineStatus::DependencyDown => None,
+            LineStatus::Unknown => Some(Severity::Warning),
+            LineStatus::Alert | LineStatus::Errored => Some(Severity::Critical),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Warning => "WARNING",
+            Severity::Critical => "CRITICAL",
+        }
+    }
+}
+
+/// One failing check on one line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertEvent {
+    pub line: String,
+    pub check: String,
+    pub severity: Severity,
+    pub reason: String,
+    /// The line is covered by an active silence; sinks should not page for it.
+    pub silenced: bool,
+    /// The line's configured note and runbook link, for whoever is paged.
+    pub note: Option<String>,
+    pub runbook_url: Option<String>,
+}
+
+/// A line that is OK again after a warning or critical status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovery {
+    pub line: String,
+    /// The status it recovered from.
+    pub from: LineStatus,
+    /// How long it was in that status, when known.
+    pub after: Option<TimeDelta>,
+}
+
+impl Recovery {
+    pub fn describe(&self) -> String {
+        let level = Severity::of(self.from).map_or("", Severity::as_str);
+        let after = self
+            .after
+            .map(|after| format!(" after {}", format_span(after)))
+            .unwrap_or_default();
+        format!(
+            "{} recovered from {level} ({}){after}",
+            self.line,
+            self.from.as_str()
+        )
+    }
+}
+
+/// Everything a sink receives for one run.
+pub struct AlertReport<'a> {
+    pub run: &'a RunMetadata,
+    pub results: &'a [LineResult],
+    pub events: &'a [AlertEvent],
+    /// Lines that came back to OK with this run.
+    pub recoveries: &'a [Recovery],
+    /// Text that opens the notification, e.g. the warm-start summary of `serve`.
+    pub headline: Option<String>,
+    /// Summary rendered from the channel's `[notifications.templates]` body
+    /// or `--report-template`, replacing the built-in one.
+    pub summary: Option<&'a str>,
+    /// Rendered `subject` of the channel's template: the email's Subject
+    /// and the title of chat messages.
+    pub subject: Option<&'a str>,
+    /// `--only-alerts`: the built-in summary leaves out OK and snoozed lines.
+    pub only_alerts: bool,
+}
+
+/// Destination that delivers a run's report (email, Telegram, ...).
+pub trait AlertSink {
+    /// Channel name used for delivery statistics.
+    fn channel(&self) -> &'static str;
+    /// Recipient description for log output.
+    fn destination(&self) -> String;
+    /// Tells recipients apart in the record of what was sent. Sinks whose
+    /// [`destination`](Self::destination) leaves part of the recipient out
+    /// return a digest of all of it, so no secret ends up in the state file.
+    fn recipient_key(&self) -> String {
+        self.destination()
+    }
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()>;
+}
+
+/// Judge one check against its thresholds; `None` when it was skipped.
+pub fn assess(check: &CheckOutcome) -> Option<Assessment> {
+    let assessment = match check {
+        CheckOutcome::Ping {
+            report,
+            loss_threshold,
+            duplicate_alert,
+            reorder_alert,
+            confirmation,
+        } => {
+            // Loss that re-probes did not confirm is not alerted on.
+            let unconfirmed = confirmation.as_ref().is_some_and(|c| !c.confirmed());
+            let confirmed = confirmation
+                .as_ref()
+                .map(|c| format!(" ({})", c.describe()))
+                .unwrap_or_default();
+            match report.packet_loss_pct {
+                _ if !report.success && !unconfirmed => {
+                    Assessment::alert(format!("ping failed{confirmed}"))
+                }
+                Some(loss) if report.loss_breached(*loss_threshold) && !unconfirmed => {
+                    Assessment::alert(format!(
+                        "packet loss {loss:.2}% above {loss_threshold:.2}%{confirmed}"
+                    ))
+                }
+                _ if duplicate_alert.is_some_and(|limit| report.duplicates > limit) => {
+                    Assessment::alert(format!("{} duplicate replies", report.duplicates))
+                }
+                _ if reorder_alert.is_some_and(|limit| report.reordered > limit) => {
+                    Assessment::alert(format!("{} replies out of order", report.reordered))
+                }
+                Some(_) => Assessment::ok(),
+                None => Assessment::unknown("packet loss unavailable".into()),
+            }
+        }
+        CheckOutcome::Traceroute {
+            report,
+            expected_gateway,
+            hop_tolerance,
+            route_change,
+            hop_loss,
+        } => {
+            let report = report.as_ref()?;
+            let first_hop = report.first_hop_address();
+            let path_loss = hop_loss
+                .as_ref()
+                .and_then(|policy| report.persistent_loss(policy));
+            let route_change = route_change.as_ref().map(|change| change.differing);
+            match (*hop_tolerance, route_change, path_loss) {
+                _ if !report.success => Assessment::alert("traceroute failed".into()),
+                _ if expected_gateway.is_some() && first_hop != *expected_gateway => {
+                    let expected = expected_gateway
+                        .map(|ip| ip.to_string())
+                        .unwrap_or_default();
+                    Assessment::alert(match first_hop {
+                        Some(seen) => {
+                            format!("first hop {seen} is not expected gateway {expected}")
+                        }
+                        None => format!("first hop did not answer (expected gateway {expected})"),
+                    })
+                }
+                (_, _, Some(loss)) => Assessment::alert(format!(
+                    "loss from hop {} ({:.1}%) persists to hop {} ({:.1}%)",
+                    loss.from_hop, loss.from_loss_pct, loss.final_hop, loss.final_loss_pct
+                )),
+                (Some(tolerance), Some(changed), _) if changed > tolerance => Assessment::alert(
+                    format!("path differs from baseline at {changed} hops (tolerance {tolerance})"),
+                ),
+                _ => Assessment::ok(),
+            }
+        }
+        CheckOutcome::Tls {
+            report,
+            expiry_alert_days,
+        } => match report.days_until_expiry {
+            _ if !report.success => {
+                Assessment::alert(format!("TLS handshake with {} failed", report.endpoint))
+            }
+            Some(days) if report.expiry_breached(*expiry_alert_days) => Assessment::alert(format!(
+                "certificate for {} expires in {days} days (alert below {expiry_alert_days})",
+                report.endpoint
+            )),
+            _ => Assessment::ok(),
+        },
+        CheckOutcome::Ntp {
+            report,
+            offset_alert_ms,
+        } => match report.offset_ms {
+            _ if !report.success => {
+                Assessment::alert(format!("NTP server {} unreachable", report.server))
+            }
+            Some(offset) if report.offset_breached(*offset_alert_ms) => Assessment::alert(format!(
+                "clock offset {offset:+.1} ms beyond ±{offset_alert_ms:.0} ms"
+            )),
+            _ => Assessment::ok(),
+        },
+        CheckOutcome::Dns {
+            report,
+            latency_alert_ms,
+        } => match report.latency_ms {
+            _ if !report.success => Assessment::alert(format!(
+                "{} resolver {} failed",
+                report.transport, report.resolver
+            )),
+            Some(latency) if report.latency_breached(*latency_alert_ms) => {
+                Assessment::alert(format!(
+                    "{} resolver {} answered in {latency:.1} ms (above {latency_alert_ms:.0} ms)",
+                    report.transport, report.resolver
+                ))
+            }
+            _ => Assessment::ok(),
+        },
+        CheckOutcome::Http {
+            report,
+            latency_alert_ms,
+        } => match (report.status_code, report.latency_ms) {
+            _ if !report.success => Assessment::alert(match report.status_code {
+                Some(code) => format!("{} returned HTTP {code}", report.url),
+                None => format!("{} did not respond", report.url),
+            }),
+            (_, Some(latency)) if report.latency_breached(*latency_alert_ms) => {
+                Assessment::alert(format!(
+                    "{} took {latency:.1} ms (above {latency_alert_ms:.0} ms)",
+                    report.url
+                ))
+            }
+            _ => Assessment::ok(),
+        },
+        CheckOutcome::PublicIp {
+            report,
+            changed_from,
+            wan_address,
+            cgnat_alert,
+        } => {
+            let translation = wan_address.and_then(|wan| report.translation(wan));
+            match (report.address, changed_from, translation) {
+                _ if !report.success => {
+                    Assessment::alert(format!("public IP lookup via {} failed", report.url))
+                }
+                (Some(address), Some(previous), _) => {
+                    Assessment::alert(format!("public IP changed from {previous} to {address}"))
+                }
+                (Some(address), None, Some(translation)) if *cgnat_alert => {
+                    Assessment::alert(format!(
+                        "behind {} (WAN {}, public {address})",
+                        translation.as_str(),
+                        wan_address.map(|wan| wan.to_string()).unwrap_or_default()
+                    ))
+                }
+                _ => Assessment::ok(),
+            }
+        }
+        CheckOutcome::DualStack {
+            report,
+            loss_threshold,
+            delta_alert_ms,
+        } => assess_dual_stack(report, *loss_threshold, *delta_alert_ms)?,
+        CheckOutcome::Custom { report } => {
+            let reason = || match (&report.message, report.exit_code) {
+                (Some(message), _) => message.clone(),
+                (None, Some(code)) => format!("{} exited with {code}", report.command),
+                (None, None) => format!("{} was killed", report.command),
+            };
+            match report.status {
+                LineStatus::Ok => Assessment::ok(),
+                LineStatus::Alert => Assessment::alert(reason()),
+                _ => Assessment::unknown(reason()),
+            }
+        }
+    };
+    Some(assessment)
+}
+
+/// Judge both legs of a dual-stack ping; `None` when the target is single-stack.
+fn assess_dual_stack(
+    report: &DualStackReport,
+    loss_threshold: f32,
+    delta_alert_ms: Option<f32>,
+) -> Option<Assessment> {
+    if let Some(error) = &report.error {
+        return Some(Assessment::unknown(format!(
+            "could not resolve target: {error}"
+        )));
+    }
+    let legs = [
+        ("IPv4", report.ipv4.as_ref()?),
+        ("IPv6", report.ipv6.as_ref()?),
+    ];
+    for (family, leg) in legs {
+        let ping = &leg.report;
+        if !ping.success {
+            return Some(Assessment::alert(format!(
+                "{family} ping to {} failed",
+                leg.address
+            )));
+        }
+        if let Some(loss) = ping
+            .packet_loss_pct
+            .filter(|_| ping.loss_breached(loss_threshold))
+        {
+            return Some(Assessment::alert(format!(
+                "{family} packet loss {loss:.2}% above {loss_threshold:.2}%"
+            )));
+        }
+    }
+    let Some(delta) = report.latency_delta_ms() else {
+        return Some(Assessment::unknown("latency unavailable".into()));
+    };
+    Some(match delta_alert_ms {
+        Some(limit) if delta.abs() > limit => {
+            let (slower, faster) = if delta > 0.0 {
+                ("IPv6", "IPv4")
+            } else {
+                ("IPv4", "IPv6")
+            };
+            Assessment::alert(format!(
+                "{slower} is {:.1} ms slower than {faster}",
+                delta.abs()
+            ))
+        }
+        _ => Assessment::ok(),
+    })
+}
+
+/// First custom status whose rule matches a line that otherwise passed.
+///
+/// Custom statuses only refine OK: a line with any alerting or unknown check
+/// keeps its built-in status.
+pub fn classify_custom<'a>(
+    result: &LineResult,
+    statuses: &'a [CustomStatus],
+) -> Option<&'a CustomStatus> {
+    if result.status() != LineStatus::Ok {
+        return None;
+    }
+    let ping = result.ping()?;
+    statuses.iter().find(|status| {
+        let lossy = status
+            .loss_above_pct
+            .is_some_and(|threshold| ping.loss_breached(threshold));
+        let slow = status.latency_above_ms.is_some_and(|threshold| {
+            ping.average_latency_ms
+                .is_some_and(|latency| latency > threshold)
+        });
+        lossy || slow
+    })
+}
+
+/// Whether a line whose dependency ended in `status` is reported
+/// DEPENDENCY_DOWN instead of being probed.
+pub fn dependency_down(status: LineStatus) -> bool {
+    status.is_down()
+}
+
+/// Count consecutive failing runs of lines that hold their alerts back.
+pub fn record_failures(state: &mut AlertState, results: &mut [LineResult]) {
+    for result in results.iter_mut() {
+        let key = result.key();
+        let status = result.status();
+        let Some(delay) = result.alert_delay.as_mut() else {
+            state.failing_runs.remove(&key);
+            continue;
+        };
+        // A snoozed, interrupted or dependency-down line did not run, so its
+        // count is left as it was.
+        if !matches!(
+            status,
+            LineStatus::Snoozed | LineStatus::Interrupted | LineStatus::DependencyDown
+        ) {
+            delay.failing_runs = Some(state.record_failure(&key, status > LineStatus::Ok));
+        }
+    }
+}
+
+/// What [`record_transitions`] found in a run.
+#[derive(Debug, Default, PartialEq)]
+pub struct Observed {
+    /// Status changes to notify about.
+    pub changes: Vec<Transition>,
+    /// Every status change and flapping start or end, for the run's output.
+    pub log: Vec<String>,
+}
+
+/// Fold this run's statuses into the alert state and report changes.
+///
+/// A line first seen in a warning or critical status counts as a change
+/// from nothing. Changes of a line that is already flapping are not
+/// reported; once it
+/// settles, its status is reported again if it differs from the one last
+/// notified.
+pub fn record_transitions(
+    state: &mut AlertState,
+    results: &mut [LineResult],
+    now: DateTime<Utc>,
+) -> Observed {
+    state.prune_silences(now);
+
+    let mut observed = Observed::default();
+    // A snoozed or interrupted line keeps its last observed status until it
+    // runs again.
+    for result in results
+        .iter_mut()
+        .filter(|result| result.snooze.is_none() && !result.interrupted)
+    {
+        let key = result.key();
+        let status = result.status();
+        let transition = state.observe(&key, status, now);
+        let mut suppressed = false;
+        match result.flap_detection.as_mut() {
+            Some(flap) => {
+                let window = chrono::Duration::minutes(i64::from(flap.window_minutes));
+                let (count, change) = state.record_flapping(&key, flap.threshold, window, now);
+                flap.changes = Some(count);
+                flap.flapping = state.lines[&key].flapping.is_some();
+                flap.suppressed = flap.flapping && change != Some(FlapChange::Started);
+                suppressed = flap.suppressed;
+                match change {
+                    Some(FlapChange::Started) => observed.log.push(format!(
+                        "Line {key} is flapping: {count} status changes in the last {} min",
+                        flap.window_minutes
+                    )),
+                    Some(FlapChange::Settled { notified }) => {
+                        observed.log.push(format!(
+                            "Line {key} stopped flapping, now {}",
+                            status.as_str()
+                        ));
+                        if notified != status {
+                            observed.changes.push(Transition {
+                                line: key.clone(),
+                                from: Some(notified),
+                                to: status,
+                                lasted: None,
+                            });
+                        }
+                    }
+                    None => {}
+                }
+            }
+            None => {
+                if let Some(entry) = state.lines.get_mut(&key) {
+                    entry.flapping = None;
+                }
+            }
+        }
+        let Some(transition) = transition else {
+            continue;
+        };
+        let Some(from) = transition.from else {
+            // A line seen for the first time (a fresh state file, or a line
+            // just added to the config) is news when it is not OK.
+            if Severity::of(transition.to).is_some() {
+                observed.changes.push(transition);
+            }
+            continue;
+        };
+        let quiet = if suppressed {
+            " (flapping)"
+        } else if state.is_silenced(&transition.line, now) {
+            " (silenced)"
+        } else {
+            ""
+        };
+        observed.log.push(format!(
+            "State change: {} {} -> {}{quiet}",
+            transition.line,
+            from.as_str(),
+            transition.to.as_str()
+        ));
+        if !suppressed {
+            observed.changes.push(transition);
+        }
+    }
+    observed
+}
+
+/// Produce one event per non-OK check, flagging lines under an active silence
+/// or whose flapping is suppressed. Lines still short of their `alert_after_failures` produce none.
+pub fn evaluate(
+    results: &[LineResult],
+    state: Option<&AlertState>,
+    now: DateTime<Utc>,
+) -> Vec<AlertEvent> {
+    let mut events = Vec::new();
+    for result in results.iter().filter(|result| !result.alert_held()) {
+        let silenced = result.flap_suppressed()
+            || state.is_some_and(|state| state.is_silenced(&result.key(), now));
+        if let Some(reason) = result.execution_note() {
+            events.push(AlertEvent {
+                line: result.key(),
+                check: "execution".into(),
+                severity: if result.is_disabled() {
+                    Severity::Critical
+                } else {
+                    Severity::Warning
+                },
+                reason,
+                silenced,
+                note: result.note.clone(),
+                runbook_url: result.runbook_url.clone(),
+            });
+        }
+        for check in &result.checks {
+            let Some(Assessment {
+                status,
+                reason: Some(reason),
+            }) = assess(check)
+            else {
+                continue;
+            };
+            let Some(severity) = Severity::of(status) else {
+                continue;
+            };
+            let reason = match &result.simulated {
+                Some(_) => format!("[simulated] {reason}"),
+                None => reason,
+            };
+            events.push(AlertEvent {
+                line: result.key(),
+                check: check.label(),
+                severity,
+                reason,
+                silenced,
+                note: result.note.clone(),
+                runbook_url: result.runbook_url.clone(),
+            });
+        }
+    }
+    events
+}
+
+/// Whether any line is in a status that raises an alert, which is what
+/// `--notify-only-on-alert` waits for.
+pub fn raises_alert(results: &[LineResult]) -> bool {
+    results
+        .iter()
+        .any(|result| Severity::of(result.status()).is_some())
+}
+
+/// Recovery events among a run's status changes: lines that are OK again
+/// after a status that raised a warning or critical alert.
+pub fn recoveries(changes: &[Transition]) -> Vec<Recovery> {
+    changes
+        .iter()
+        .filter(|change| change.to == LineStatus::Ok)
+        .filter_map(|change| {
+            let from = change.from.filter(|from| Severity::of(*from).is_some())?;
+            Some(Recovery {
+                line: change.line.clone(),
+                from,
+                after: change.lasted,
+            })
+        })
+        .collect()
+}
+
+/// Render recovery events as a plain-text section.
+pub fn format_recoveries(recoveries: &[Recovery]) -> String {
+    if recoveries.is_empty() {
+        return String::new();
+    }
+    let mut text = String::from("Recovered:\n");
+    for recovery in recoveries {
+        text.push_str(&format!("- {}\n", recovery.describe()));
+    }
+    text
+}
+
+/// Rough length of an outage for humans, e.g. `45 s`, `12 min` or `3 h 5 min`.
+fn format_span(span: TimeDelta) -> String {
+    let seconds = span.num_seconds().max(0);
+    match seconds {
+        0..60 => format!("{seconds} s"),
+        60..3_600 => format!("{} min", seconds / 60),
+        3_600..86_400 => format!("{} h {} min", seconds / 3_600, seconds % 3_600 / 60),
+        _ => format!("{} d {} h", seconds / 86_400, seconds % 86_400 / 3_600),
+    }
+}
+
+/// Headline sent when monitoring starts: how many lines are in each status,
+/// then every line whose status changed while ICMPMolester was not running.
+pub fn format_startup(results: &[LineResult], changes: &[Transition]) -> String {
+    let statuses = [
+        LineStatus::Snoozed,
+        LineStatus::Ok,
+        LineStatus::Interrupted,
+        LineStatus::Unknown,
+        LineStatus::Alert,
+        LineStatus::Errored,
+    ];
+    let counts: Vec<String> = statuses
+        .iter()
+        .filter_map(|wanted| {
+            let count = results.iter().filter(|r| r.status() == *wanted).count();
+            (count > 0).then(|| format!("{count} {}", wanted.as_str()))
+        })
+        .collect();
+    let mut text = format!(
+        "Monitoring started, current state: {}\n",
+        if counts.is_empty() {
+            "no lines".to_string()
+        } else {
+            counts.join(", ")
+        }
+    );
+
+    let changed: Vec<_> = changes
+        .iter()
+        .filter_map(|change| Some((change, change.from?)))
+        .collect();
+    if !changed.is_empty() {
+        text.push_str("Changed while down:\n");
+        for (change, from) in changed {
+            let recovered = if change.to == LineStatus::Ok {
+                " (recovered)"
+            } else {
+                ""
+            };
+            text.push_str(&format!(
+                "- {}: {} -> {}{recovered}\n",
+                change.line,
+                from.as_str(),
+                change.to.as_str()
+            ));
+        }
+    }
+    text
+}
+
+/// The active alerts of `events` as `<severity> <line> <check>`, sorted, so
+/// two runs with the same problems compare equal whatever the measurements.
+pub fn alert_keys(events: &[AlertEvent]) -> Vec<String> {
+    let mut keys: Vec<String> = events
+        .iter()
+        .filter(|event| !event.silenced)
+        .map(|event| format!("{} {} {}", event.severity.as_str(), event.line, event.check))
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Render active (non-silenced) events as a plain-text section.
+pub fn format_events(events: &[AlertEvent]) -> String {
+    let active: Vec<_> = events.iter().filter(|event| !event.silenced).collect();
+    let silenced = events.len() - active.len();
+    if active.is_empty() && silenced == 0 {
+        return String::new();
+    }
+
+    let mut text = String::from("Active alerts:\n");
+    for event in &active {
+        text.push_str(&format!(
+            "- [{}] {} {}: {}\n",
+            event.severity.as_str(),
+            event.line,
+            event.check,
+            event.reason
+        ));
+    }
+    if silenced > 0 {
+        text.push_str(&format!("({silenced} silenced)\n"));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::Confirmation;
+    use crate::diagnostics::{FamilyPing, PingReport};
+    use crate::runner::tests::sample_result;
+    use crate::runner::{AlertDelay, AutoDisable, FlapDetection};
+    use crate::state::Silence;
+    use chrono::TimeZone;
+
+    fn ping_line(name: &str, success: bool, loss: Option<f32>) -> LineResult {
+        sample_result(name, success, loss, Some(10.0), 1.0, None)
+    }
+
+    #[test]
+    fn assesses_thresholds_and_skips() {
+        let lossy = ping_line("Lossy", true, Some(4.0));
+        let verdict = assess(&lossy.checks[0]).unwrap();
+        assert_eq!(verdict.status, LineStatus::Alert);
+        assert_eq!(
+            verdict.reason.as_deref(),
+            Some("packet loss 4.00% above 1.00%")
+        );
+        assert_eq!(assess(&lossy.checks[1]), None);
+
+        let mut healthy = ping_line("Healthy", true, Some(0.0));
+        assert_eq!(assess(&healthy.checks[0]), Some(Assessment::ok()));
+
+        let CheckOutcome::Ping {
+            report,
+            duplicate_alert,
+            reorder_alert,
+            ..
+        } = &mut healthy.checks[0]
+        else {
+            unreachable!();
+        };
+        report.duplicates = 2;
+        report.reordered = 1;
+        *duplicate_alert = Some(2);
+        *reorder_alert = Some(1);
+        assert_eq!(assess(&healthy.checks[0]), Some(Assessment::ok()));
+
+        if let CheckOutcome::Ping {
+            duplicate_alert, ..
+        } = &mut healthy.checks[0]
+        {
+            *duplicate_alert = Some(1);
+        }
+        let verdict = assess(&healthy.checks[0]).unwrap();
+        assert_eq!(verdict.status, LineStatus::Alert);
+        assert_eq!(verdict.reason.as_deref(), Some("2 duplicate replies"));
+    }
+
+    #[test]
+    fn alerts_on_loss_only_when_re_probes_confirm_it() {
+        let mut lossy = ping_line("Lossy", true, Some(4.0));
+        let CheckOutcome::Ping { confirmation, .. } = &mut lossy.checks[0] else {
+            unreachable!();
+        };
+        *confirmation = Some(Confirmation {
+            probes: 3,
+            breached: 1,
+            required: 2,
+        });
+        assert_eq!(assess(&lossy.checks[0]), Some(Assessment::ok()));
+        assert_eq!(lossy.status(), LineStatus::Ok);
+        assert_eq!(
+            lossy.confirmation_note().as_deref(),
+            Some("loss not confirmed: 1 of 3 re-probes above threshold, 2 needed")
+        );
+
+        if let CheckOutcome::Ping {
+            confirmation: Some(confirmation),
+            ..
+        } = &mut lossy.checks[0]
+        {
+            confirmation.breached = 2;
+        }
+        let verdict = assess(&lossy.checks[0]).unwrap();
+        assert_eq!(verdict.status, LineStatus::Alert);
+        assert_eq!(
+            verdict.reason.as_deref(),
+            Some("packet loss 4.00% above 1.00% (loss confirmed by 2 of 3 re-probes)")
+        );
+    }
+
+    #[test]
+    fn custom_status_refines_only_passing_lines() {
+        let degraded = CustomStatus {
+            name: "DEGRADED".into(),
+            color: None,
+            emoji: Some("🟡".into()),
+            loss_above_pct: Some(0.2),
+            latency_above_ms: Some(80.0),
+        };
+        let statuses = [degraded.clone()];
+
+        assert_eq!(
+            classify_custom(&ping_line("Lossy", true, Some(0.5)), &statuses),
+            Some(&degraded)
+        );
+        assert_eq!(
+            classify_custom(&ping_line("Clean", true, Some(0.0)), &statuses),
+            None
+        );
+        assert_eq!(
+            classify_custom(&ping_line("Down", false, Some(100.0)), &statuses),
+            None
+        );
+    }
+
+    #[test]
+    fn compares_dual_stack_legs() {
+        let leg = |address: &str, loss: f32, latency: f32| FamilyPing {
+            address: address.parse().unwrap(),
+            report: PingReport {
+                success: true,
+                packet_loss_pct: Some(loss),
+                average_latency_ms: Some(latency),
+                jitter_ms: None,
+                duplicates: 0,
+                reordered: 0,
+                raw_output: String::new(),
+            },
+        };
+        let mut report = DualStackReport {
+            ipv4: Some(leg("192.0.2.1", 0.0, 12.0)),
+            ipv6: None,
+            error: None,
+        };
+        assert_eq!(assess_dual_stack(&report, 1.0, Some(20.0)), None);
+
+        report.ipv6 = Some(leg("2001:db8::1", 0.0, 45.5));
+        assert_eq!(report.latency_delta_ms(), Some(33.5));
+        assert_eq!(
+            assess_dual_stack(&report, 1.0, None),
+            Some(Assessment::ok())
+        );
+        let verdict = assess_dual_stack(&report, 1.0, Some(20.0)).unwrap();
+        assert_eq!(verdict.status, LineStatus::Alert);
+        assert_eq!(
+            verdict.reason.as_deref(),
+            Some("IPv6 is 33.5 ms slower than IPv4")
+        );
+
+        report.ipv6 = Some(leg("2001:db8::1", 40.0, 12.0));
+        assert_eq!(
+            assess_dual_stack(&report, 1.0, Some(20.0))
+                .unwrap()
+                .reason
+                .as_deref(),
+            Some("IPv6 packet loss 40.00% above 1.00%")
+        );
+    }
+
+    #[test]
+    fn evaluates_events_with_severity_and_silences() {
+        let now = Utc.timestamp_opt(1_000, 0).unwrap();
+        let mut state = AlertState::default();
+        state.silences.push(Silence {
+            line: "Backup".into(),
+            until: Utc.timestamp_opt(2_000, 0).unwrap(),
+            reason: None,
+        });
+        let results = vec![
+            ping_line("Primary", true, Some(0.0)),
+            ping_line("Backup", false, Some(100.0)),
+            ping_line("Lab", true, None),
+        ];
+
+        let events = evaluate(&results, Some(&state), now);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].line, "Backup");
+        assert_eq!(events[0].severity, Severity::Critical);
+        assert!(events[0].silenced);
+        assert_eq!(events[1].line, "Lab");
+        assert_eq!(events[1].severity, Severity::Warning);
+        assert!(!events[1].silenced);
+
+        let text = format_events(&events);
+        assert!(text.contains("- [WARNING] Lab ping: packet loss unavailable"));
+        assert!(text.contains("(1 silenced)"));
+        assert!(!text.contains("Backup"));
+        assert_eq!(format_events(&evaluate(&results[..1], None, now)), "");
+    }
+
+    #[test]
+    fn holds_alerts_until_enough_failing_runs() {
+        let now = Utc.timestamp_opt(1_000, 0).unwrap();
+        let mut state = AlertState::default();
+        let run = |state: &mut AlertState, failing: bool| {
+            let mut result = ping_line("Backup", !failing, Some(if failing { 100.0 } else { 0.0 }));
+            result.alert_delay = Some(AlertDelay {
+                after_failures: 3,
+                failing_runs: None,
+            });
+            record_failures(state, std::slice::from_mut(&mut result));
+            (result.alert_held(), result.alert_delay_note())
+        };
+
+        let (held, note) = run(&mut state, true);
+        assert!(held);
+        assert_eq!(note.as_deref(), Some("failing run 1 of 3 before alerting"));
+        assert!(run(&mut state, true).0);
+        assert!(!run(&mut state, true).0);
+        assert_eq!(state.failing_runs["Backup"], 3);
+        run(&mut state, false);
+        assert!(state.failing_runs.is_empty());
+
+        let mut result = ping_line("Backup", false, Some(100.0));
+        result.alert_delay = Some(AlertDelay {
+            after_failures: 3,
+            failing_runs: Some(1),
+        });
+        assert!(evaluate(&[result], Some(&state), now).is_empty());
+        let mut unstated = ping_line("Backup", false, Some(100.0));
+        unstated.alert_delay = Some(AlertDelay {
+            after_failures: 3,
+            failing_runs: None,
+        });
+        assert_eq!(evaluate(&[unstated], None, now).len(), 1);
+    }
+
+    #[test]
+    fn suppresses_changes_of_flapping_lines_until_they_settle() {
+        let mut state = AlertState::default();
+        let mut observe = |healthy: bool, minute: i64| {
+            let mut line = match healthy {
+                true => ping_line("Primary", true, Some(0.0)),
+                false => ping_line("Primary", false, Some(100.0)),
+            };
+            line.flap_detection = Some(FlapDetection {
+                threshold: 1,
+                window_minutes: 10,
+                changes: None,
+                flapping: false,
+                suppressed: false,
+            });
+            let now = Utc.timestamp_opt(minute * 60, 0).unwrap();
+            let observed = record_transitions(&mut state, std::slice::from_mut(&mut line), now);
+            let changes: Vec<_> = observed
+                .changes
+                .iter()
+                .map(|change| (change.from, change.to))
+                .collect();
+            (changes, observed.log, line.flap_suppressed())
+        };
+
+        assert_eq!(observe(true, 0), (Vec::new(), Vec::new(), false));
+        let (changes, log, _) = observe(false, 1);
+        assert_eq!(changes, [(Some(LineStatus::Ok), LineStatus::Alert)]);
+        assert_eq!(log, ["State change: Primary OK -> ALERT"]);
+
+        // The change that tips the line into flapping is still reported...
+        let (changes, log, suppressed) = observe(true, 2);
+        assert_eq!(changes, [(Some(LineStatus::Alert), LineStatus::Ok)]);
+        assert_eq!(
+            log,
+            [
+                "Line Primary is flapping: 2 status changes in the last 10 min",
+                "State change: Primary ALERT -> OK",
+            ]
+        );
+        assert!(!suppressed);
+
+        // ...the ones after it are not.
+        let (changes, log, suppressed) = observe(false, 3);
+        assert!(changes.is_empty());
+        assert_eq!(log, ["State change: Primary OK -> ALERT (flapping)"]);
+        assert!(suppressed);
+
+        // Once settled, the status differs from the last notified one.
+        let (changes, log, suppressed) = observe(false, 20);
+        assert_eq!(changes, [(Some(LineStatus::Ok), LineStatus::Alert)]);
+        assert_eq!(log, ["Line Primary stopped flapping, now ALERT"]);
+        assert!(!suppressed);
+    }
+
+    #[test]
+    fn reports_lines_first_seen_alerting() {
+        let mut state = AlertState::default();
+        let now = Utc.timestamp_opt(1_000, 0).unwrap();
+        let mut results = vec![
+            ping_line("Primary", true, Some(0.0)),
+            ping_line("Backup", false, Some(100.0)),
+        ];
+
+        let observed = record_transitions(&mut state, &mut results, now);
+        assert_eq!(observed.changes.len(), 1);
+        assert_eq!(observed.changes[0].line, "Backup");
+        assert_eq!(observed.changes[0].from, None);
+        assert_eq!(observed.changes[0].to, LineStatus::Alert);
+        // Nothing recovered, and there is nothing to log before a change.
+        assert!(recoveries(&observed.changes).is_empty());
+        assert!(observed.log.is_empty());
+
+        // Still down on the next run is not a change.
+        let observed = record_transitions(&mut state, &mut results, now);
+        assert!(observed.changes.is_empty());
+    }
+
+    #[test]
+    fn skips_dependents_of_lines_that_are_down() {
+        assert!(dependency_down(LineStatus::Alert));
+        assert!(dependency_down(LineStatus::DependencyDown));
+        assert!(!dependency_down(LineStatus::Unknown));
+        assert!(!dependency_down(LineStatus::Ok));
+    }
+
+    #[test]
+    fn summarises_state_on_startup() {
+        let results = vec![
+            ping_line("Primary", true, Some(0.0)),
+            ping_line("Backup", false, Some(100.0)),
+            ping_line("Lab", true, Some(0.0)),
+        ];
+        let changes = [
+            Transition {
+                line: "Primary".into(),
+                from: Some(LineStatus::Alert),
+                to: LineStatus::Ok,
+                lasted: Some(TimeDelta::minutes(12)),
+            },
+            Transition {
+                line: "Backup".into(),
+                from: Some(LineStatus::Ok),
+                to: LineStatus::Alert,
+                lasted: None,
+            },
+        ];
+        assert_eq!(
+            format_startup(&results, &changes),
+            "Monitoring started, current state: 2 OK, 1 ALERT\n\
+             Changed while down:\n\
+             - Primary: ALERT -> OK (recovered)\n\
+             - Backup: OK -> ALERT\n"
+        );
+        let recovered = recoveries(&changes);
+        assert_eq!(
+            format_recoveries(&recovered),
+            "Recovered:\n- Primary recovered from CRITICAL (ALERT) after 12 min\n"
+        );
+        assert_eq!(
+            format_span(TimeDelta::seconds(3 * 3_600 + 5 * 60)),
+            "3 h 5 min"
+        );
+        assert_eq!(
+            format_startup(&[], &[]),
+            "Monitoring started, current state: no lines\n"
+        );
+    }
+
+    #[test]
+    fn only_alerting_statuses_raise_an_alert() {
+        let healthy = sample_result("Primary", true, Some(0.0), Some(4.0), 1.0, None);
+        let mut interrupted = sample_result("Backup", false, Some(100.0), None, 1.0, None);
+        interrupted.interrupted = true;
+        assert!(!raises_alert(&[healthy.clone(), interrupted]));
+
+        let lossy = sample_result("Backup", false, Some(50.0), Some(4.0), 1.0, None);
+        assert!(raises_alert(&[healthy.clone(), lossy]));
+
+        // A line disabled after repeated execution errors is ERRORED, which
+        // is as critical as ALERT.
+        let mut errored = sample_result("Backup", true, Some(0.0), Some(4.0), 1.0, None);
+        errored.auto_disable = Some(AutoDisable {
+            after_errors: 3,
+            fingerprint: 1,
+            active: true,
+        });
+        assert_eq!(errored.status(), LineStatus::Errored);
+        assert!(raises_alert(&[healthy, errored]));
+    }
+}
@@ -0,0 +1,524 @@
+//! Throttled ICMP sweep of very large target lists for the `bulk` subcommand.
+//!
+//! Unlike a normal run, which spawns `ping` per line and reports every check
+//! in detail, a sweep sends all echo requests through one shared ICMP socket
+//! per address family, paces them with a token bucket and prints a compact
+//! table. It is meant for checking thousands of customer CPEs at once.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use futures::{StreamExt, stream};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::resolver;
+use crate::runner::LineStatus;
+use crate::style;
+
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMP_HEADER_LEN: usize = 8;
+/// Marks our echo payloads, so replies to other pingers on a raw socket are ignored.
+const PAYLOAD_MAGIC: [u8; 4] = *b"ICMo";
+/// Hostnames resolved at once before the sweep starts.
+const RESOLVE_CONCURRENCY: usize = 64;
+
+/// Parameters of a bulk sweep.
+pub struct BulkOptions {
+    /// File listing one `address [label]` per line.
+    pub targets: PathBuf,
+    /// Echo requests sent per second across all targets.
+    pub rate: u32,
+    /// Echo requests per target.
+    pub count: u32,
+    /// How long to wait for each reply.
+    pub timeout: Duration,
+    /// Loss percentage above which a target alerts.
+    pub loss_threshold: f32,
+}
+
+/// One entry of the targets file.
+#[derive(Debug, Clone, PartialEq)]
+struct BulkTarget {
+    address: String,
+    label: Option<String>,
+}
+
+/// Aggregated replies for one target.
+#[derive(Debug, Clone, PartialEq)]
+struct BulkResult {
+    target: BulkTarget,
+    resolved: Option<IpAddr>,
+    sent: u32,
+    received: u32,
+    average_latency_ms: Option<f32>,
+    error: Option<String>,
+}
+
+impl BulkResult {
+    fn loss_pct(&self) -> Option<f32> {
+        (self.sent > 0).then(|| 100.0 * (self.sent - self.received) as f32 / self.sent as f32)
+    }
+
+    fn status(&self, loss_threshold: f32) -> LineStatus {
+        match self.loss_pct() {
+            _ if self.error.is_some() => LineStatus::Unknown,
+            Some(loss) if loss > loss_threshold => LineStatus::Alert,
+            Some(_) => LineStatus::Ok,
+            None => LineStatus::Unknown,
+        }
+    }
+}
+
+/// Send and receive state of a single echo request.
+#[derive(Debug, Clone, Copy, Default)]
+struct Probe {
+    sent: Option<(Instant, IpAddr)>,
+    rtt_ms: Option<f32>,
+}
+
+/// Token bucket that admits `rate` probes per second with bursts of up to `burst`.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        // Allow a tenth of a second's worth of probes at once, at least one.
+        let burst = (f64::from(rate) / 10.0).max(1.0);
+        Self {
+            rate: f64::from(rate),
+            burst,
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    /// Take a token, or return how long to wait until one is available.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+
+    async fn acquire(&mut self) {
+        while let Err(wait) = self.take(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Sweep every target in `options.targets` and print the results table.
+pub async fn run_bulk(options: &BulkOptions) -> Result<()> {
+    if options.rate == 0 || options.count == 0 {
+        anyhow::bail!("--rate and --count must be greater than zero");
+    }
+    let targets = read_targets(&options.targets)?;
+    if targets.is_empty() {
+        anyhow::bail!("No targets in {}", options.targets.display());
+    }
+
+    let started = Instant::now();
+    let resolved = resolve_targets(&targets).await;
+    let results = sweep(targets, resolved, options).await?;
+    print_table(&results, options.loss_threshold);
+    print_totals(&results, options.loss_threshold, started.elapsed());
+    Ok(())
+}
+
+fn read_targets(path: &Path) -> Result<Vec<BulkTarget>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read targets from {}", path.display()))?;
+    Ok(parse_targets(&contents))
+}
+
+/// Parse `address [label]` rows, skipping blank lines and `#` comments.
+fn parse_targets(contents: &str) -> Vec<BulkTarget> {
+    contents
+        .lines()
+        .map(|row| row.split('#').next().unwrap_or_default().trim())
+        .filter(|row| !row.is_empty())
+        .map(|row| {
+            let (address, label) = match row.split_once(char::is_whitespace) {
+                Some((address, label)) => (address, Some(label.trim().to_string())),
+                None => (row, None),
+            };
+            BulkTarget {
+                address: address.to_string(),
+                label,
+            }
+        })
+        .collect()
+}
+
+/// First address of each target, or why it could not be resolved.
+async fn resolve_targets(targets: &[BulkTarget]) -> Vec<Result<IpAddr, String>> {
+    stream::iter(targets)
+        .map(|target| async move {
+            resolver::shared()
+                .resolve(&target.address, 0)
+                .await
+                .map(|addresses| addresses[0].ip())
+                .map_err(|err| err.to_string())
+        })
+        .buffered(RESOLVE_CONCURRENCY)
+        .collect()
+        .await
+}
+
+async fn sweep(
+    targets: Vec<BulkTarget>,
+    resolved: Vec<Result<IpAddr, String>>,
+    options: &BulkOptions,
+) -> Result<Vec<BulkResult>> {
+    let addresses: Vec<Option<IpAddr>> =
+        resolved.iter().map(|r| r.as_ref().ok().copied()).collect();
+    let sockets = IcmpSockets::open(
+        addresses.iter().flatten().any(IpAddr::is_ipv4),
+        addresses.iter().flatten().any(IpAddr::is_ipv6),
+    )?;
+
+    // Probe `round * targets + index` is the `round`-th request to target `index`;
+    // sending round by round spreads each target's requests over the sweep.
+    let probe_count = targets.len() * options.count as usize;
+    let probes = Arc::new(Mutex::new(vec![Probe::default(); probe_count]));
+    let receivers: Vec<_> = sockets
+        .iter()
+        .map(|socket| tokio::spawn(receive(socket.clone(), probes.clone(), options.timeout)))
+        .collect();
+
+    let mut bucket = TokenBucket::new(options.rate, Instant::now());
+    let mut send_errors: HashMap<usize, String> = HashMap::new();
+    for id in 0..probe_count {
+        let index = id % targets.len();
+        let Some(address) = addresses[index] else {
+            continue;
+        };
+        if send_errors.contains_key(&index) {
+            continue;
+        }
+        bucket.acquire().await;
+        let socket = sockets.for_address(address);
+        let packet = echo_request(address.is_ipv6(), id as u32);
+        probes.lock().unwrap_or_else(PoisonError::into_inner)[id].sent =
+            Some((Instant::now(), address));
+        if let Err(err) = socket.send_to(&packet, SocketAddr::new(address, 0)).await {
+            send_errors.insert(index, err.to_string());
+        }
+    }
+    tokio::time::sleep(options.timeout).await;
+    for receiver in receivers {
+        receiver.abort();
+    }
+
+    let probes = probes.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(targets
+        .into_iter()
+        .zip(resolved)
+        .enumerate()
+        .map(|(index, (target, resolved))| {
+            let sent: Vec<&Probe> = probes
+                .iter()
+                .skip(index)
+                .step_by(addresses.len())
+                .filter(|probe| probe.sent.is_some())
+                .collect();
+            let rtts: Vec<f32> = sent.iter().filter_map(|probe| probe.rtt_ms).collect();
+            BulkResult {
+                target,
+                resolved: resolved.as_ref().ok().copied(),
+                sent: sent.len() as u32,
+                received: rtts.len() as u32,
+                average_latency_ms: (!rtts.is_empty())
+                    .then(|| rtts.iter().sum::<f32>() / rtts.len() as f32),
+                error: resolved.err().or_else(|| send_errors.remove(&index)),
+            }
+        })
+        .collect())
+}
+
+/// Record replies on `socket` until aborted; late replies are ignored.
+async fn receive(socket: Arc<UdpSocket>, probes: Arc<Mutex<Vec<Probe>>>, timeout: Duration) {
+    let mut buffer = [0u8; 1500];
+    while let Ok((len, source)) = socket.recv_from(&mut buffer).await {
+        let received = Instant::now();
+        let Some(id) = parse_echo_reply(&buffer[..len]) else {
+            continue;
+        };
+        let mut probes = probes.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(probe) = probes.get_mut(id as usize) else {
+            continue;
+        };
+        let Some((sent, address)) = probe.sent else {
+            continue;
+        };
+        let rtt = received.saturating_duration_since(sent);
+        if address == source.ip() && rtt <= timeout && probe.rtt_ms.is_none() {
+            probe.rtt_ms = Some(rtt.as_secs_f32() * 1000.0);
+        }
+    }
+}
+
+/// The shared ICMP sockets, one per address family in use.
+struct IcmpSockets {
+    v4: Option<Arc<UdpSocket>>,
+    v6: Option<Arc<UdpSocket>>,
+}
+
+impl IcmpSockets {
+    fn open(v4: bool, v6: bool) -> Result<Self> {
+        Ok(Self {
+            v4: v4
+                .then(|| open_icmp_socket(Domain::IPV4, Protocol::ICMPV4))
+                .transpose()?,
+            v6: v6
+                .then(|| open_icmp_socket(Domain::IPV6, Protocol::ICMPV6))
+                .transpose()?,
+        })
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Arc<UdpSocket>> {
+        self.v4.iter().chain(self.v6.iter())
+    }
+
+    fn for_address(&self, address: IpAddr) -> &UdpSocket {
+        let socket = if address.is_ipv6() {
+            &self.v6
+        } else {
+            &self.v4
+        };
+        socket
+            .as_ref()
+            .expect("a socket is opened for every family with targets")
+    }
+}
+
+/// Open an unprivileged ping socket, falling back to a raw socket when the
+/// system does not allow those (e.g. Linux outside `net.ipv4.ping_group_range`).
+fn open_icmp_socket(domain: Domain, protocol: Protocol) -> Result<Arc<UdpSocket>> {
+    let socket = Socket::new(domain, Type::DGRAM, Some(protocol))
+        .or_else(|_| Socket::new(domain, Type::RAW, Some(protocol)))
+        .context(
+            "Failed to open an ICMP socket; allow unprivileged ping sockets \
+             (sysctl net.ipv4.ping_group_range) or run with CAP_NET_RAW",
+        )?;
+    socket.set_nonblocking(true)?;
+    let socket = UdpSocket::from_std(std::net::UdpSocket::from(socket))
+        .context("Failed to register the ICMP socket with the runtime")?;
+    Ok(Arc::new(socket))
+}
+
+/// Echo request carrying probe `id` in its sequence number and payload.
+///
+/// Ping sockets on Linux replace the identifier with their own, so replies
+/// are matched on the payload rather than the identifier.
+fn echo_request(ipv6: bool, id: u32) -> Vec<u8> {
+    let kind = if ipv6 {
+        ICMPV6_ECHO_REQUEST
+    } else {
+        ICMP_ECHO_REQUEST
+    };
+    let mut packet = vec![kind, 0, 0, 0];
+    packet.extend_from_slice(&(std::process::id() as u16).to_be_bytes());
+    packet.extend_from_slice(&(id as u16).to_be_bytes());
+    packet.extend_from_slice(&PAYLOAD_MAGIC);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // The kernel fills in the ICMPv6 checksum, which covers a pseudo-header.
+    if !ipv6 {
+        let checksum = internet_checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+/// Probe id of an echo reply sent by this sweep, if `packet` is one.
+///
+/// Raw IPv4 sockets (and ping sockets on macOS) deliver the IP header too.
+fn parse_echo_reply(packet: &[u8]) -> Option<u32> {
+    let icmp = match packet.first() {
+        Some(first) if first >> 4 == 4 && packet.len() >= 20 => {
+            packet.get(usize::from(first & 0x0f) * 4..)?
+        }
+        _ => packet,
+    };
+    if !matches!(icmp.first(), Some(&(ICMP_ECHO_REPLY | ICMPV6_ECHO_REPLY))) {
+        return None;
+    }
+    let payload = icmp.get(ICMP_HEADER_LEN..ICMP_HEADER_LEN + 8)?;
+    if payload[..4] != PAYLOAD_MAGIC {
+        return None;
+    }
+    Some(u32::from_be_bytes(payload[4..].try_into().ok()?))
+}
+
+/// RFC 1071 ones' complement checksum.
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn print_table(results: &[BulkResult], loss_threshold: f32) {
+    println!(
+        "{:<40} {:<39} {:>4} {:>4} {:>6} {:>9}  STATUS",
+        "TARGET", "ADDRESS", "SENT", "RECV", "LOSS%", "AVG MS"
+    );
+    for result in results {
+        let status = result.status(loss_threshold);
+        let label = match &result.target.label {
+            Some(label) => format!("{} ({label})", result.target.address),
+            None => result.target.address.clone(),
+        };
+        let address = result
+            .resolved
+            .map_or_else(|| "-".to_string(), |ip| ip.to_string());
+        let loss = result
+            .loss_pct()
+            .map_or_else(|| "-".to_string(), |loss| format!("{loss:.1}"));
+        let latency = result
+            .average_latency_ms
+            .map_or_else(|| "-".to_string(), |ms| format!("{ms:.2}"));
+        let status_text = style::paint(status.as_str(), status.color());
+        print!(
+            "{label:<40} {address:<39} {:>4} {:>4} {loss:>6} {latency:>9}  {status_text}",
+            result.sent, result.received
+        );
+        match &result.error {
+            Some(error) => println!(" ({error})"),
+            None => println!(),
+        }
+    }
+}
+
+fn print_totals(results: &[BulkResult], loss_threshold: f32, elapsed: Duration) {
+    let count = |wanted: LineStatus| {
+        results
+            .iter()
+            .filter(|result| result.status(loss_threshold) == wanted)
+            .count()
+    };
+    println!();
+    println!(
+        "{} targets in {:.1}s: {} OK, {} ALERT, {} UNKNOWN",
+        results.len(),
+        elapsed.as_secs_f32(),
+        count(LineStatus::Ok),
+        count(LineStatus::Alert),
+        count(LineStatus::Unknown)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_targets_with_labels_and_comments() {
+        let targets = parse_targets("# CPEs\n10.0.0.1\n\n10.0.0.2  Rossi, via Roma 1 # fiber\n");
+        assert_eq!(
+            targets,
+            [
+                BulkTarget {
+                    address: "10.0.0.1".into(),
+                    label: None,
+                },
+                BulkTarget {
+                    address: "10.0.0.2".into(),
+                    label: Some("Rossi, via Roma 1".into()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn token_bucket_paces_after_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(100, start);
+        for _ in 0..10 {
+            assert!(bucket.take(start).is_ok());
+        }
+        let wait = bucket.take(start).unwrap_err();
+        assert!((wait.as_secs_f64() - 0.01).abs() < 1e-6);
+        assert!(bucket.take(start + Duration::from_millis(10)).is_ok());
+        assert!(bucket.take(start + Duration::from_millis(10)).is_err());
+        // Idle time only refills up to the burst size.
+        let later = start + Duration::from_secs(60);
+        assert_eq!((0..20).filter(|_| bucket.take(later).is_ok()).count(), 10);
+    }
+
+    #[test]
+    fn builds_and_matches_echo_packets() {
+        let request = echo_request(false, 70_000);
+        assert_eq!(request[0], ICMP_ECHO_REQUEST);
+        assert_eq!(internet_checksum(&request), 0);
+
+        // A reply as delivered by a ping socket: ICMP only.
+        let mut reply = request.clone();
+        reply[0] = ICMP_ECHO_REPLY;
+        assert_eq!(parse_echo_reply(&reply), Some(70_000));
+
+        // The same reply behind a 20-byte IPv4 header, as a raw socket sees it.
+        let mut with_header = vec![0x45; 1];
+        with_header.extend_from_slice(&[0; 19]);
+        with_header.extend_from_slice(&reply);
+        assert_eq!(parse_echo_reply(&with_header), Some(70_000));
+
+        let mut v6_reply = echo_request(true, 3);
+        v6_reply[0] = ICMPV6_ECHO_REPLY;
+        assert_eq!(parse_echo_reply(&v6_reply), Some(3));
+
+        assert_eq!(parse_echo_reply(&request), None);
+        reply[8] = b'X';
+        assert_eq!(parse_echo_reply(&reply), None);
+    }
+
+    #[test]
+    fn classifies_targets_by_loss() {
+        let result = BulkResult {
+            target: BulkTarget {
+                address: "10.0.0.1".into(),
+                label: None,
+            },
+            resolved: Some("10.0.0.1".parse().unwrap()),
+            sent: 4,
+            received: 3,
+            average_latency_ms: Some(8.0),
+            error: None,
+        };
+        assert_eq!(result.loss_pct(), Some(25.0));
+        assert_eq!(result.status(50.0), LineStatus::Ok);
+        assert_eq!(result.status(10.0), LineStatus::Alert);
+
+        let unresolved = BulkResult {
+            resolved: None,
+            sent: 0,
+            received: 0,
+            average_latency_ms: None,
+            error: Some("cpe.invalid has no addresses".into()),
+            ..result
+        };
+        assert_eq!(unresolved.status(50.0), LineStatus::Unknown);
+    }
+}
@@ -0,0 +1,516 @@
+//! Offline config validation for the `check-config` subcommand.
+//!
+//! Loads every config like a run would, then checks what parsing alone
+//! cannot: that targets are IPs or resolvable names, thresholds are in range
+//! and line names are unique. Each line's settings are printed with defaults
+//! applied. Nothing is probed.
+
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::net::lookup_host;
+
+use crate::config::{
+    CheckKind, Config, CustomStatus, LineSettings, LoadOptions, expand_config_paths,
+    load_config_file,
+};
+use crate::notify::discord_destination;
+
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Validate every config, print the resolved lines, and fail on any problem.
+/// With a profile, lines are shown as that profile runs them.
+pub async fn run_check_config(paths: &[PathBuf], options: &LoadOptions) -> Result<()> {
+    let mut problems = Vec::new();
+    let mut line_count = 0;
+    let sources = expand_config_paths(paths)?;
+    for source in &sources {
+        let path = &source.path;
+        let config = match source
+            .read()
+            .and_then(|file| load_config_file(&file, options))
+        {
+            Ok(config) => config,
+            Err(err) => {
+                println!("{}: failed to load", path.display());
+                problems.push(format!("{err:#}"));
+                continue;
+            }
+        };
+        println!("{}: {} line(s)", path.display(), config.lines.len());
+        let mut found = duplicate_names(&config);
+        for status in &config.statuses {
+            found.extend(status_problems(status));
+        }
+        for line in &config.lines {
+            println!();
+            println!("  {}", line.name);
+            for (key, value) in resolved_settings(line) {
+                println!("    {key} = {value}");
+            }
+            found.extend(line_problems(line));
+            found.extend(unresolvable_targets(line).await);
+        }
+        println!();
+        line_count += config.lines.len();
+        problems.extend(
+            found
+                .into_iter()
+                .map(|problem| format!("{}: {problem}", path.display())),
+        );
+    }
+
+    if !problems.is_empty() {
+        println!("Problems:");
+        for problem in &problems {
+            println!("  - {problem}");
+        }
+        anyhow::bail!("check-config found {} problem(s)", problems.len());
+    }
+    println!("Config OK: {line_count} line(s) in {} file(s)", paths.len());
+    Ok(())
+}
+
+/// Settings of `line` after defaults, as `(config key, value)` pairs.
+///
+/// Settings of checks the line does not run are left out, as are optional
+/// settings that are unset.
+fn resolved_settings(line: &LineSettings) -> Vec<(&'static str, String)> {
+    let runs = |check: CheckKind| line.checks.contains(&check);
+    let mut settings = vec![("target", line.target.clone())];
+    let optional = |settings: &mut Vec<_>, key, value: Option<String>| {
+        if let Some(value) = value {
+            settings.push((key, value));
+        }
+    };
+    if !line.fallback_targets.is_empty() {
+        settings.push(("fallback_targets", list(&line.fallback_targets)));
+    }
+    settings.push((
+        "checks",
+        list(line.checks.iter().map(|check| check.as_str())),
+    ));
+
+    if (runs(CheckKind::Ping) || runs(CheckKind::Traceroute)) && line.retries > 0 {
+        settings.push(("retries", line.retries.to_string()));
+        settings.push(("retry_delay_ms", line.retry_delay_ms.to_string()));
+    }
+    if runs(CheckKind::Ping) || runs(CheckKind::DualStack) {
+        settings.push(("ping_count", line.ping_count.to_string()));
+        settings.push(("ping_timeout_ms", line.ping_timeout_ms.to_string()));
+        if let Some(probes) = line.confirm_probes {
+            settings.push((
+                "confirm_probes",
+                format!("{} of {probes}", line.confirm_required),
+            ));
+        }
+        optional(
+            &mut settings,
+            "ping_deadline_ms",
+            line.ping_deadline_ms.map(|ms| ms.to_string()),
+        );
+        settings.push((
+            "packet_loss_alert_threshold",
+            line.packet_loss_alert_threshold.to_string(),
+        ));
+        optional(
+            &mut settings,
+            "duplicate_alert_count",
+            line.duplicate_alert_count.map(|count| count.to_string()),
+        );
+        optional(
+            &mut settings,
+            "reorder_alert_count",
+            line.reorder_alert_count.map(|count| count.to_string()),
+        );
+    }
+    if runs(CheckKind::DualStack) {
+        optional(
+            &mut settings,
+            "dual_stack_delta_alert_ms",
+            line.dual_stack_delta_alert_ms.map(|ms| ms.to_string()),
+        );
+    }
+    if runs(CheckKind::Traceroute) {
+        settings.push(("traceroute_max_hops", line.traceroute_max_hops.to_string()));
+        if let Some(cycles) = line.mtr_cycles {
+            settings.push(("mtr_cycles", cycles.to_string()));
+            settings.push(("hop_loss_alert_pct", line.hop_loss.alert_pct.to_string()));
+            if !line.hop_loss.overrides.is_empty() {
+                let overrides = line
+                    .hop_loss
+                    .overrides
+                    .iter()
+                    .map(|(hop, pct)| format!("hop {hop}: {pct}"));
+                settings.push(("hop_loss_overrides", list(overrides)));
+            }
+        }
+        settings.push(("resolve_hops", line.resolve_hops.to_string()));
+        if line.resolve_hops {
+            settings.push((
+                "hop_resolve_timeout_ms",
+                line.hop_resolve_timeout_ms.to_string(),
+            ));
+        }
+        optional(
+            &mut settings,
+            "hop_enrichment",
+            line.hop_enrichment
+                .map(|source| format!("{source:?}").to_lowercase()),
+        );
+        optional(
+            &mut settings,
+            "maxmind_asn_db",
+            line.maxmind_asn_db
+                .as_ref()
+                .map(|path| path.display().to_string()),
+        );
+        optional(
+            &mut settings,
+            "maxmind_country_db",
+            line.maxmind_country_db
+                .as_ref()
+                .map(|path| path.display().to_string()),
+        );
+        optional(
+            &mut settings,
+            "route_hop_tolerance",
+            line.route_hop_tolerance.map(|hops| hops.to_string()),
+        );
+        optional(
+            &mut settings,
+            "expected_gateway",
+            line.expected_gateway.map(|gateway| gateway.to_string()),
+        );
+    }
+    if runs(CheckKind::Tls) {
+        optional(&mut settings, "tls_endpoint", line.tls_endpoint.clone());
+        settings.push(("tls_timeout_ms", line.tls_timeout_ms.to_string()));
+        settings.push((
+            "tls_expiry_alert_days",
+            line.tls_expiry_alert_days.to_string(),
+        ));
+    }
+    if runs(CheckKind::Ntp) {
+        optional(&mut settings, "ntp_server", line.ntp_server.clone());
+        settings.push(("ntp_timeout_ms", line.ntp_timeout_ms.to_string()));
+        settings.push(("ntp_offset_alert_ms", line.ntp_offset_alert_ms.to_string()));
+    }
+    if runs(CheckKind::Dns) {
+        if !line.doh_resolvers.is_empty() {
+            settings.push(("doh_resolvers", list(&line.doh_resolvers)));
+        }
+        if !line.dot_resolvers.is_empty() {
+            settings.push(("dot_resolvers", list(&line.dot_resolvers)));
+        }
+        settings.push(("dns_query_name", line.dns_query_name.clone()));
+        settings.push(("dns_timeout_ms", line.dns_timeout_ms.to_string()));
+        settings.push((
+            "dns_latency_alert_ms",
+            line.dns_latency_alert_ms.to_string(),
+        ));
+    }
+    if runs(CheckKind::Http) {
+        optional(&mut settings, "http_url", line.http_url.clone());
+        optional(
+            &mut settings,
+            "http_expected_status",
+            line.http_expected_status.map(|status| status.to_string()),
+        );
+        settings.push(("http_timeout_ms", line.http_timeout_ms.to_string()));
+        settings.push((
+            "http_latency_alert_ms",
+            line.http_latency_alert_ms.to_string(),
+        ));
+    }
+    if runs(CheckKind::Custom) {
+        optional(&mut settings, "custom_command", line.custom_command.clone());
+        settings.push(("custom_timeout_ms", line.custom_timeout_ms.to_string()));
+    }
+    if runs(CheckKind::PublicIp) {
+        optional(&mut settings, "public_ip_url", line.public_ip_url.clone());
+        settings.push((
+            "public_ip_timeout_ms",
+            line.public_ip_timeout_ms.to_string(),
+        ));
+        optional(
+            &mut settings,
+            "source_address",
+            line.source_address.map(|address| address.to_string()),
+        );
+        optional(
+            &mut settings,
+            "wan_address",
+            line.wan_address.map(|address| address.to_string()),
+        );
+        settings.push(("cgnat_alert", line.cgnat_alert.to_string()));
+    }
+
+    optional(
+        &mut settings,
+        "disable_after_errors",
+        line.disable_after_errors.map(|errors| errors.to_string()),
+    );
+    optional(
+        &mut settings,
+        "alert_after_failures",
+        line.alert_after_failures.map(|runs| runs.to_string()),
+    );
+    if let Some(threshold) = line.flap_threshold {
+        settings.push(("flap_threshold", threshold.to_string()));
+        settings.push(("flap_window_minutes", line.flap_window_minutes.to_string()));
+    }
+    optional(&mut settings, "note", line.note.clone());
+    optional(&mut settings, "runbook_url", line.runbook_url.clone());
+    optional(
+        &mut settings,
+        "discord_webhook",
+        line.discord_webhook.as_deref().map(discord_destination),
+    );
+    settings.push(("weight", line.weight.to_string()));
+    if !line.tags.is_empty() {
+        settings.push(("tags", list(&line.tags)));
+    }
+    if !line.enabled {
+        settings.push(("enabled", "false".to_string()));
+    }
+    optional(
+        &mut settings,
+        "snooze_until",
+        line.snooze_until.map(|until| until.to_rfc3339()),
+    );
+    optional(
+        &mut settings,
+        "schedule",
+        line.schedule.as_ref().map(|schedule| schedule.to_string()),
+    );
+    settings
+}
+
+fn list<T: Display>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Names used by more than one line, which would share alert state.
+fn duplicate_names(config: &Config) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    let mut reported = BTreeSet::new();
+    config
+        .lines
+        .iter()
+        .filter(|line| !seen.insert(&line.name) && reported.insert(&line.name))
+        .map(|line| format!("Line name '{}' is used more than once", line.name))
+        .collect()
+}
+
+/// Counts and timeouts that must be positive and thresholds outside the
+/// range where they can ever trigger.
+fn line_problems(line: &LineSettings) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut check = |ok: bool, problem: String| {
+        if !ok {
+            problems.push(format!("Line '{}': {problem}", line.name));
+        }
+    };
+    let positive = |value: f32| value.is_finite() && value > 0.0;
+    let percentage = |value: f32| (0.0..100.0).contains(&value);
+
+    check(line.ping_count > 0, "ping_count must be at least 1".into());
+    check(
+        line.traceroute_max_hops > 0,
+        "traceroute_max_hops must be at least 1".into(),
+    );
+    for (key, timeout) in [
+        ("ping_timeout_ms", line.ping_timeout_ms),
+        ("hop_resolve_timeout_ms", line.hop_resolve_timeout_ms),
+        ("tls_timeout_ms", line.tls_timeout_ms),
+        ("ntp_timeout_ms", line.ntp_timeout_ms),
+        ("dns_timeout_ms", line.dns_timeout_ms),
+        ("http_timeout_ms", line.http_timeout_ms),
+        ("custom_timeout_ms", line.custom_timeout_ms),
+        ("public_ip_timeout_ms", line.public_ip_timeout_ms),
+    ] {
+        check(timeout > 0, format!("{key} must be greater than 0"));
+    }
+    if let Some(deadline) = line.ping_deadline_ms {
+        check(
+            deadline >= line.ping_timeout_ms,
+            format!(
+                "ping_deadline_ms ({deadline}) is shorter than ping_timeout_ms ({})",
+                line.ping_timeout_ms
+            ),
+        );
+    }
+    check(
+        percentage(line.packet_loss_alert_threshold),
+        format!(
+            "packet_loss_alert_threshold {} must be at least 0 and below 100",
+            line.packet_loss_alert_threshold
+        ),
+    );
+    // Unless set, the hop threshold is the line's, which is reported above.
+    if line.hop_loss.alert_pct != line.packet_loss_alert_threshold {
+        check(
+            percentage(line.hop_loss.alert_pct),
+            format!(
+                "hop_loss_alert_pct {} must be at least 0 and below 100",
+                line.hop_loss.alert_pct
+            ),
+        );
+    }
+    for (hop, pct) in &line.hop_loss.overrides {
+        // 100% is allowed here: it silences a hop that never answers.
+        check(
+            (0.0..=100.0).contains(pct),
+            format!("hop_loss_overrides sets hop {hop} to {pct}, outside 0-100"),
+        );
+    }
+    for (key, limit) in [
+        ("ntp_offset_alert_ms", Some(line.ntp_offset_alert_ms)),
+        ("dns_latency_alert_ms", Some(line.dns_latency_alert_ms)),
+        ("http_latency_alert_ms", Some(line.http_latency_alert_ms)),
+        ("dual_stack_delta_alert_ms", line.dual_stack_delta_alert_ms),
+    ] {
+        if let Some(limit) = limit {
+            check(
+                positive(limit),
+                format!("{key} {limit} must be greater than 0"),
+            );
+        }
+    }
+    if let Some(status) = line.http_expected_status {
+        check(
+            (100..=599).contains(&status),
+            format!("http_expected_status {status} is not an HTTP status code"),
+        );
+    }
+    problems
+}
+
+/// Custom status rules that can never match.
+fn status_problems(status: &CustomStatus) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(loss) = status.loss_above_pct {
+        if !(0.0..100.0).contains(&loss) {
+            problems.push(format!(
+                "Status '{}': loss_above_pct {loss} must be at least 0 and below 100",
+                status.name
+            ));
+        }
+    }
+    if let Some(latency) = status.latency_above_ms {
+        if !latency.is_finite() || latency < 0.0 {
+            problems.push(format!(
+                "Status '{}': latency_above_ms {latency} must be zero or more",
+                status.name
+            ));
+        }
+    }
+    problems
+}
+
+/// Targets that are neither IP addresses nor resolvable host names.
+async fn unresolvable_targets(line: &LineSettings) -> Vec<String> {
+    let mut problems = Vec::new();
+    for target in std::iter::once(&line.target).chain(&line.fallback_targets) {
+        if target.parse::<IpAddr>().is_ok() {
+            continue;
+        }
+        let resolved = tokio::time::timeout(RESOLVE_TIMEOUT, async {
+            lookup_host((target.as_str(), 0)).await.map(Iterator::count)
+        })
+        .await;
+        let reason = match resolved {
+            Ok(Ok(0)) => "it has no addresses".to_string(),
+            Ok(Ok(_)) => continue,
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => format!("no answer within {}s", RESOLVE_TIMEOUT.as_secs()),
+        };
+        problems.push(format!(
+            "Line '{}': target '{target}' is not an IP address and cannot be resolved ({reason})",
+            line.name
+        ));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_out_of_range_settings_and_duplicate_names() {
+        let mut line = LineSettings::with_defaults("Fiber", "192.0.2.1");
+        assert!(line_problems(&line).is_empty());
+
+        line.ping_count = 0;
+        line.packet_loss_alert_threshold = 100.0;
+        line.ping_deadline_ms = Some(500);
+        line.hop_loss.overrides.insert(3, 100.0);
+        line.http_expected_status = Some(42);
+        let problems = line_problems(&line);
+        assert_eq!(problems.len(), 4, "{problems:?}");
+        assert!(problems[0].starts_with("Line 'Fiber': ping_count"));
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.contains("packet_loss_alert_threshold 100"))
+        );
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.contains("ping_deadline_ms (500)"))
+        );
+
+        let config = Config {
+            lines: vec![
+                line.clone(),
+                LineSettings::with_defaults("LTE", "192.0.2.2"),
+                line.clone(),
+                line,
+            ],
+            statuses: Vec::new(),
+        };
+        assert_eq!(
+            duplicate_names(&config),
+            ["Line name 'Fiber' is used more than once"]
+        );
+    }
+
+    #[test]
+    fn lists_resolved_settings_of_enabled_checks() {
+        let mut line = LineSettings::with_defaults("Fiber", "192.0.2.1");
+        line.fallback_targets = vec!["192.0.2.9".into()];
+        line.tags = vec!["north".into()];
+        let settings = resolved_settings(&line);
+        let value = |key: &str| {
+            settings
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(value("checks"), Some("ping, traceroute"));
+        assert_eq!(value("fallback_targets"), Some("192.0.2.9"));
+        assert_eq!(value("ping_count"), Some("5"));
+        assert_eq!(value("tags"), Some("north"));
+        assert_eq!(value("tls_timeout_ms"), None);
+        assert_eq!(value("schedule"), None);
+    }
+
+    #[tokio::test]
+    async fn reports_unresolvable_targets() {
+        let mut line = LineSettings::with_defaults("Fiber", "192.0.2.1");
+        line.fallback_targets = vec!["2001:db8::1".into(), "no-such-host.invalid".into()];
+        let problems = unresolvable_targets(&line).await;
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("'no-such-host.invalid'"));
+    }
+}
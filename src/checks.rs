@@ -0,0 +1,407 @@
+//! Execution and evaluation of the individual checks configured on a line.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::future::try_join_all;
+use serde::Serialize;
+
+use crate::alerting::assess;
+use crate::config::{CheckKind, HopLossPolicy, LineSettings};
+use crate::diagnostics::{
+    DualStackReport, PingReport, TracerouteReport, run_dual_stack_ping, run_ping, run_traceroute,
+};
+use crate::probes::custom::{CustomReport, run_custom_probe};
+use crate::probes::dns::{DnsReport, run_doh_probe, run_dot_probe};
+use crate::probes::http::{HttpReport, run_http_probe};
+use crate::probes::ntp::{NtpReport, run_ntp_probe};
+use crate::probes::public_ip::{PublicIpReport, run_public_ip_probe};
+use crate::probes::tls::{TlsReport, run_tls_probe};
+use crate::runner::LineStatus;
+use crate::state::RouteChange;
+
+/// Typed result of one check, carrying the threshold it is judged against.
+#[derive(Debug, Clone)]
+pub enum CheckOutcome {
+    /// `duplicate_alert` and `reorder_alert` are the reply counts above
+    /// which the line alerts, when configured. `confirmation` is set when
+    /// the loss was above the threshold and the line re-probes before
+    /// alerting on it.
+    Ping {
+        report: PingReport,
+        loss_threshold: f32,
+        duplicate_alert: Option<u32>,
+        reorder_alert: Option<u32>,
+        confirmation: Option<Confirmation>,
+    },
+    /// `report` is `None` when traceroute was skipped for this run.
+    /// `route_change` compares the path with the saved baseline, filled in
+    /// once the alert state is available. `hop_loss` is
+    /// set for MTR-mode traceroutes, whose hops carry loss statistics.
+    Traceroute {
+        report: Option<TracerouteReport>,
+        expected_gateway: Option<IpAddr>,
+        hop_tolerance: Option<u32>,
+        route_change: Option<RouteChange>,
+        hop_loss: Option<HopLossPolicy>,
+    },
+    Tls {
+        report: TlsReport,
+        expiry_alert_days: u32,
+    },
+    Ntp {
+        report: NtpReport,
+        offset_alert_ms: f32,
+    },
+    Dns {
+        report: DnsReport,
+        latency_alert_ms: f32,
+    },
+    Http {
+        report: HttpReport,
+        latency_alert_ms: f32,
+    },
+    /// `changed_from` is the previously stored address when the public IP
+    /// differs from it, filled in once the alert state is available.
+    /// `wan_address` is the router or interface address checked for CGNAT.
+    PublicIp {
+        report: PublicIpReport,
+        changed_from: Option<IpAddr>,
+        wan_address: Option<IpAddr>,
+        cgnat_alert: bool,
+    },
+    /// Both legs are judged against the line's loss threshold.
+    DualStack {
+        report: DualStackReport,
+        loss_threshold: f32,
+        delta_alert_ms: Option<f32>,
+    },
+    /// The command's own verdict; it carries no threshold.
+    Custom { report: CustomReport },
+}
+
+/// Re-probes of a ping whose packet loss was above the threshold.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Confirmation {
+    pub probes: u32,
+    /// Re-probes that failed or whose loss was also above the threshold.
+    pub breached: u32,
+    /// How many must breach for the loss to alert.
+    pub required: u32,
+}
+
+impl Confirmation {
+    pub fn confirmed(&self) -> bool {
+        self.breached >= self.required
+    }
+
+    pub fn describe(&self) -> String {
+        if self.confirmed() {
+            format!(
+                "loss confirmed by {} of {} re-probes",
+                self.breached, self.probes
+            )
+        } else {
+            format!(
+                "loss not confirmed: {} of {} re-probes above threshold, {} needed",
+                self.breached, self.probes, self.required
+            )
+        }
+    }
+}
+
+impl CheckOutcome {
+    pub fn kind(&self) -> CheckKind {
+        match self {
+            CheckOutcome::Ping { .. } => CheckKind::Ping,
+            CheckOutcome::Traceroute { .. } => CheckKind::Traceroute,
+            CheckOutcome::Tls { .. } => CheckKind::Tls,
+            CheckOutcome::Ntp { .. } => CheckKind::Ntp,
+            CheckOutcome::Dns { .. } => CheckKind::Dns,
+            CheckOutcome::Http { .. } => CheckKind::Http,
+            CheckOutcome::PublicIp { .. } => CheckKind::PublicIp,
+            CheckOutcome::DualStack { .. } => CheckKind::DualStack,
+            CheckOutcome::Custom { .. } => CheckKind::Custom,
+        }
+    }
+
+    /// Short label used as the sub-row key in summaries.
+    pub fn label(&self) -> String {
+        match self {
+            CheckOutcome::Dns { report, .. } => report.transport.to_string().to_lowercase(),
+            other => other.kind().as_str().to_string(),
+        }
+    }
+
+    /// Status of this check, or `None` when it was skipped and should not count.
+    pub fn status(&self) -> Option<LineStatus> {
+        assess(self).map(|assessment| assessment.status)
+    }
+}
+
+/// Run one configured check for `line`; DNS yields one outcome per resolver.
+///
+/// A ping or traceroute that fails or alerts is tried again up to
+/// `line.retries` times before its last outcome is reported. A ping whose
+/// loss is still above the threshold is then re-probed to confirm it when
+/// `line.confirm_probes` is set.
+pub async fn run_check(
+    line: &LineSettings,
+    kind: CheckKind,
+    skip_traceroute: bool,
+) -> Result<Vec<CheckOutcome>> {
+    if !matches!(kind, CheckKind::Ping | CheckKind::Traceroute) {
+        return run_check_once(line, kind, skip_traceroute).await;
+    }
+    let mut outcomes = with_retries(
+        line.retries,
+        Duration::from_millis(line.retry_delay_ms),
+        || run_check_once(line, kind, skip_traceroute),
+        |outcomes| {
+            outcomes
+                .iter()
+                .any(|outcome| outcome.status() == Some(LineStatus::Alert))
+        },
+    )
+    .await?;
+    if let Some(probes) = line.confirm_probes {
+        for outcome in &mut outcomes {
+            confirm_loss(line, probes, outcome).await;
+        }
+    }
+    Ok(outcomes)
+}
+
+/// Ping `line` again `probes` times, one after another, when `outcome` is a
+/// ping whose loss breached its threshold, and count the re-probes that
+/// breach it too. A re-probe that cannot run counts as breaching.
+async fn confirm_loss(line: &LineSettings, probes: u32, outcome: &mut CheckOutcome) {
+    let CheckOutcome::Ping {
+        report,
+        loss_threshold,
+        confirmation,
+        ..
+    } = outcome
+    else {
+        return;
+    };
+    if !report.loss_breached(*loss_threshold) {
+        return;
+    }
+    let mut breached = 0;
+    for _ in 0..probes {
+        let breach = run_ping(line).await.map_or(true, |probe| {
+            !probe.success || probe.loss_breached(*loss_threshold)
+        });
+        breached += u32::from(breach);
+    }
+    *confirmation = Some(Confirmation {
+        probes,
+        breached,
+        required: line.confirm_required,
+    });
+}
+
+/// Run `attempt` again while it errors or its result is `failed`, at most
+/// `retries` more times, waiting `delay` before the first retry and twice as
+/// long before each further one.
+async fn with_retries<T, F, Fut>(
+    retries: u32,
+    mut delay: Duration,
+    mut attempt: F,
+    failed: impl Fn(&T) -> bool,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut remaining = retries;
+    loop {
+        let outcome = attempt().await;
+        let retry = outcome.as_ref().map_or(true, &failed);
+        if !retry || remaining == 0 {
+            return outcome;
+        }
+        remaining -= 1;
+        tokio::time::sleep(delay).await;
+        delay = delay.saturating_mul(2);
+    }
+}
+
+async fn run_check_once(
+    line: &LineSettings,
+    kind: CheckKind,
+    skip_traceroute: bool,
+) -> Result<Vec<CheckOutcome>> {
+    let outcomes = match kind {
+        CheckKind::Ping => {
+            let report = run_ping(line)
+                .await
+                .with_context(|| format!("Ping check failed for line '{}'", line.name))?;
+            vec![CheckOutcome::Ping {
+                report,
+                loss_threshold: line.packet_loss_alert_threshold,
+                duplicate_alert: line.duplicate_alert_count,
+                reorder_alert: line.reorder_alert_count,
+                confirmation: None,
+            }]
+        }
+        CheckKind::Traceroute => {
+            let report = if skip_traceroute {
+                None
+            } else {
+                Some(
+                    run_traceroute(line)
+                        .await
+                        .with_context(|| format!("Traceroute failed for line '{}'", line.name))?,
+                )
+            };
+            vec![CheckOutcome::Traceroute {
+                report,
+                expected_gateway: line.expected_gateway,
+                hop_tolerance: line.route_hop_tolerance,
+                route_change: None,
+                hop_loss: line.mtr_cycles.map(|_| line.hop_loss.clone()),
+            }]
+        }
+        CheckKind::Tls => {
+            let endpoint = line.tls_endpoint.as_deref().unwrap_or_default();
+            let report = run_tls_probe(endpoint, Duration::from_millis(line.tls_timeout_ms))
+                .await
+                .with_context(|| format!("TLS probe failed for line '{}'", line.name))?;
+            vec![CheckOutcome::Tls {
+                report,
+                expiry_alert_days: line.tls_expiry_alert_days,
+            }]
+        }
+        CheckKind::Ntp => {
+            let server = line.ntp_server.as_deref().unwrap_or_default();
+            let report = run_ntp_probe(server, Duration::from_millis(line.ntp_timeout_ms))
+                .await
+                .with_context(|| format!("NTP probe failed for line '{}'", line.name))?;
+            vec![CheckOutcome::Ntp {
+                report,
+                offset_alert_ms: line.ntp_offset_alert_ms,
+            }]
+        }
+        CheckKind::Dns => {
+            let timeout = Duration::from_millis(line.dns_timeout_ms);
+            let doh = line
+                .doh_resolvers
+                .iter()
+                .map(|url| run_doh_probe(url, &line.dns_query_name, timeout));
+            let dot = line
+                .dot_resolvers
+                .iter()
+                .map(|endpoint| run_dot_probe(endpoint, &line.dns_query_name, timeout));
+            let (mut reports, dot_reports) =
+                futures::try_join!(try_join_all(doh), try_join_all(dot))
+                    .with_context(|| format!("DNS probe failed for line '{}'", line.name))?;
+            reports.extend(dot_reports);
+            reports
+                .into_iter()
+                .map(|report| CheckOutcome::Dns {
+                    report,
+                    latency_alert_ms: line.dns_latency_alert_ms,
+                })
+                .collect()
+        }
+        CheckKind::Http => {
+            let url = line.http_url.as_deref().unwrap_or_default();
+            let report = run_http_probe(
+                url,
+                line.http_expected_status,
+                Duration::from_millis(line.http_timeout_ms),
+            )
+            .await
+            .with_context(|| format!("HTTP probe failed for line '{}'", line.name))?;
+            vec![CheckOutcome::Http {
+                report,
+                latency_alert_ms: line.http_latency_alert_ms,
+            }]
+        }
+        CheckKind::PublicIp => {
+            let url = line.public_ip_url.as_deref().unwrap_or_default();
+            let report = run_public_ip_probe(
+                url,
+                line.source_address,
+                Duration::from_millis(line.public_ip_timeout_ms),
+            )
+            .await
+            .with_context(|| format!("Public IP lookup failed for line '{}'", line.name))?;
+            vec![CheckOutcome::PublicIp {
+                report,
+                changed_from: None,
+                wan_address: line.wan_address.or(line.source_address),
+                cgnat_alert: line.cgnat_alert,
+            }]
+        }
+        CheckKind::DualStack => {
+            let report = run_dual_stack_ping(line)
+                .await
+                .with_context(|| format!("Dual-stack ping failed for line '{}'", line.name))?;
+            vec![CheckOutcome::DualStack {
+                report,
+                loss_threshold: line.packet_loss_alert_threshold,
+                delta_alert_ms: line.dual_stack_delta_alert_ms,
+            }]
+        }
+        CheckKind::Custom => {
+            let command = line.custom_command.as_deref().unwrap_or_default();
+            let report = run_custom_probe(
+                command,
+                &line.name,
+                &line.target,
+                Duration::from_millis(line.custom_timeout_ms),
+            )
+            .await
+            .with_context(|| format!("Custom check failed for line '{}'", line.name))?;
+            vec![CheckOutcome::Custom { report }]
+        }
+    };
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn retries_failed_attempts_until_one_passes() {
+        let mut calls = 0;
+        let outcome = with_retries(
+            3,
+            Duration::ZERO,
+            || {
+                calls += 1;
+                let call = calls;
+                async move {
+                    match call {
+                        1 => anyhow::bail!("fork failed"),
+                        2 => Ok(100.0),
+                        _ => Ok(0.0),
+                    }
+                }
+            },
+            |loss: &f32| *loss > 1.0,
+        )
+        .await;
+        assert_eq!(outcome.unwrap(), 0.0);
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let outcome = with_retries(
+            1,
+            Duration::ZERO,
+            || {
+                calls += 1;
+                async { Ok(100.0) }
+            },
+            |loss: &f32| *loss > 1.0,
+        )
+        .await;
+        assert_eq!(outcome.unwrap(), 100.0);
+        assert_eq!(calls, 2);
+    }
+}
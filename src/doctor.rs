@@ -0,0 +1,333 @@
+//! Environment self-check for the `doctor` subcommand.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+use crate::config::{ConfigSource, LoadOptions, expand_config_paths, load_config_file};
+use crate::diagnostics::{ping_command, traceroute_command};
+use crate::notify::{SMTP_RELAY_PORT, TELEGRAM_API_HOST};
+use crate::tunnel::Tunnel;
+
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Inputs the doctor inspects, taken from the same flags as a normal run.
+pub struct DoctorOptions {
+    pub config: Vec<PathBuf>,
+    pub history: Option<PathBuf>,
+    pub state: Option<PathBuf>,
+    pub email_smtp: Option<String>,
+    pub telegram_enabled: bool,
+    /// Tunnel notifications are sent through, checked instead of direct access.
+    pub notify_via: Option<Tunnel>,
+}
+
+/// Result of one environment check.
+#[derive(Debug)]
+struct Finding {
+    name: String,
+    ok: bool,
+    detail: String,
+    fix: Option<String>,
+}
+
+impl Finding {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ok: true,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ok: false,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Run every check, print the findings, and fail if any check did not pass.
+pub async fn run_doctor(options: &DoctorOptions) -> Result<()> {
+    let mut findings = check_configs(&options.config);
+    findings.extend([
+        check_tool(
+            "ping",
+            ping_command(),
+            "-V",
+            "Install ping (e.g. `apt install iputils-ping` or `apk add iputils`)",
+        )
+        .await,
+        check_tool(
+            "traceroute",
+            traceroute_command(),
+            "--version",
+            "Install traceroute (e.g. `apt install traceroute`) or run with --skip-traceroute",
+        )
+        .await,
+    ]);
+    findings.extend(check_raw_socket());
+
+    if let Some(path) = &options.history {
+        findings.push(check_writable("history", path));
+    }
+    if let Some(path) = &options.state {
+        findings.push(check_writable("state", path));
+    }
+    if let Some(server) = &options.email_smtp {
+        findings.push(
+            check_reachable(
+                "smtp",
+                server,
+                SMTP_RELAY_PORT,
+                options.notify_via.as_ref(),
+                "Allow outbound TCP 465 to the SMTP relay or use a reachable relay host",
+            )
+            .await,
+        );
+    }
+    if options.telegram_enabled {
+        findings.push(
+            check_reachable(
+                "telegram",
+                TELEGRAM_API_HOST,
+                443,
+                options.notify_via.as_ref(),
+                "Allow outbound HTTPS to api.telegram.org",
+            )
+            .await,
+        );
+    }
+
+    let failures = print_findings(&findings);
+    if failures > 0 {
+        anyhow::bail!("doctor found {failures} problem(s)");
+    }
+    println!("All checks passed.");
+    Ok(())
+}
+
+fn print_findings(findings: &[Finding]) -> usize {
+    let mut failures = 0;
+    for finding in findings {
+        let marker = if finding.ok { "OK  " } else { "FAIL" };
+        println!("[{marker}] {}: {}", finding.name, finding.detail);
+        if let Some(fix) = &finding.fix {
+            println!("       fix: {fix}");
+        }
+        if !finding.ok {
+            failures += 1;
+        }
+    }
+    failures
+}
+
+fn check_configs(paths: &[PathBuf]) -> Vec<Finding> {
+    match expand_config_paths(paths) {
+        Ok(sources) => sources.iter().map(check_config).collect(),
+        Err(err) => vec![Finding::fail(
+            "config",
+            format!("{err:#}"),
+            "Point --config at a directory containing .toml configs",
+        )],
+    }
+}
+
+fn check_config(source: &ConfigSource) -> Finding {
+    let path = &source.path;
+    match source
+        .read()
+        .and_then(|file| load_config_file(&file, &LoadOptions::default()))
+    {
+        Ok(config) => Finding::ok(
+            "config",
+            format!("{} ({} lines)", path.display(), config.lines.len()),
+        ),
+        Err(err) => Finding::fail(
+            "config",
+            format!("{err:#}"),
+            "Create the file from lines.toml or pass --config with the correct path",
+        ),
+    }
+}
+
+async fn check_tool(name: &str, program: &str, version_flag: &str, fix: &str) -> Finding {
+    match Command::new(program).arg(version_flag).output().await {
+        Ok(output) => {
+            let text = String::from_utf8_lossy(&output.stdout).to_string()
+                + &String::from_utf8_lossy(&output.stderr);
+            let version = text
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .unwrap_or("version unknown");
+            Finding::ok(name, format!("{program} found ({version})"))
+        }
+        Err(err) => Finding::fail(name, format!("cannot execute {program}: {err}"), fix),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check_raw_socket() -> Option<Finding> {
+    const CAP_NET_RAW: u32 = 13;
+
+    let has_cap = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("CapEff:"))
+                .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+        })
+        .is_some_and(|caps| caps & (1 << CAP_NET_RAW) != 0);
+    if has_cap {
+        return Some(Finding::ok("raw-socket", "process holds CAP_NET_RAW"));
+    }
+
+    let group_range = fs::read_to_string("/proc/sys/net/ipv4/ping_group_range").ok();
+    let unprivileged_icmp = group_range.as_deref().and_then(parse_ping_group_range);
+    let gid = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Gid:"))
+                .and_then(|ids| ids.split_whitespace().nth(1))
+                .and_then(|gid| gid.parse::<u32>().ok())
+        });
+
+    match (unprivileged_icmp, gid) {
+        (Some((low, high)), Some(gid)) if (low..=high).contains(&gid) => Some(Finding::ok(
+            "raw-socket",
+            format!("unprivileged ICMP allowed for gid {gid} (ping_group_range {low}-{high})"),
+        )),
+        _ => Some(Finding::fail(
+            "raw-socket",
+            "no CAP_NET_RAW and unprivileged ICMP is not enabled for this group",
+            "Grant the capability (`setcap cap_net_raw+ep $(which ping)`, or `--cap-add NET_RAW` in Docker) \
+             or widen net.ipv4.ping_group_range",
+        )),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_raw_socket() -> Option<Finding> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn parse_ping_group_range(raw: &str) -> Option<(u32, u32)> {
+    let mut parts = raw.split_whitespace().map(|v| v.parse::<u32>().ok());
+    let low = parts.next()??;
+    let high = parts.next()??;
+    (low <= high).then_some((low, high))
+}
+
+fn check_writable(name: &str, path: &Path) -> Finding {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let probe = dir.join(format!(".icmpmolester-doctor-{}", std::process::id()));
+
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            if path.exists() && fs::OpenOptions::new().append(true).open(path).is_err() {
+                return Finding::fail(
+                    name,
+                    format!("{} exists but is not writable", path.display()),
+                    format!("Fix ownership/permissions of {}", path.display()),
+                );
+            }
+            Finding::ok(name, format!("{} is writable", path.display()))
+        }
+        Err(err) => Finding::fail(
+            name,
+            format!("cannot write in {}: {err}", dir.display()),
+            format!(
+                "Create {} and make it writable by the user running ICMPMolester",
+                dir.display()
+            ),
+        ),
+    }
+}
+
+async fn check_reachable(
+    name: &str,
+    host: &str,
+    port: u16,
+    tunnel: Option<&Tunnel>,
+    fix: &str,
+) -> Finding {
+    let Some(tunnel) = tunnel else {
+        let address = format!("{host}:{port}");
+        return match tokio::time::timeout(REACHABILITY_TIMEOUT, TcpStream::connect(&address)).await
+        {
+            Ok(Ok(_)) => Finding::ok(name, format!("{address} reachable")),
+            Ok(Err(err)) => Finding::fail(name, format!("{address} unreachable: {err}"), fix),
+            Err(_) => Finding::fail(
+                name,
+                format!(
+                    "{address} timed out after {}s",
+                    REACHABILITY_TIMEOUT.as_secs()
+                ),
+                fix,
+            ),
+        };
+    };
+
+    let address = format!("{host}:{port} via {tunnel}");
+    let probe = {
+        let (tunnel, host) = (tunnel.clone(), host.to_string());
+        tokio::task::spawn_blocking(move || tunnel.probe(&host, port))
+    };
+    let fix = format!("Check that {tunnel} is reachable and allows connections to {host}:{port}");
+    match tokio::time::timeout(REACHABILITY_TIMEOUT, probe).await {
+        Ok(Ok(Ok(()))) => Finding::ok(name, format!("{address} reachable")),
+        Ok(Ok(Err(err))) => Finding::fail(name, format!("{address} unreachable: {err}"), fix),
+        Ok(Err(err)) => Finding::fail(name, format!("{address} unreachable: {err}"), fix),
+        Err(_) => Finding::fail(
+            name,
+            format!(
+                "{address} timed out after {}s",
+                REACHABILITY_TIMEOUT.as_secs()
+            ),
+            fix,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_unwritable_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let ok = check_writable("state", &dir.path().join("state.json"));
+        assert!(ok.ok, "{ok:?}");
+
+        let missing = check_writable("state", &dir.path().join("missing/state.json"));
+        assert!(!missing.ok);
+        assert!(missing.fix.unwrap().contains("missing"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_ping_group_range() {
+        assert_eq!(
+            parse_ping_group_range("0\t2147483647\n"),
+            Some((0, 2147483647))
+        );
+        assert_eq!(parse_ping_group_range("1\t0\n"), None);
+    }
+}
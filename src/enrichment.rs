@@ -0,0 +1,221 @@
+//! ASN and country annotation of traceroute hops.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::config::{HopEnrichment, LineSettings};
+use crate::diagnostics::TracerouteHop;
+
+const CYMRU_WHOIS: &str = "whois.cymru.com:43";
+const CYMRU_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Origin AS and registration country for one address.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Origin {
+    asn: Option<u32>,
+    as_name: Option<String>,
+    country: Option<String>,
+}
+
+/// Annotate public hop addresses using the line's configured source.
+pub async fn annotate_hops(
+    hops: &mut [TracerouteHop],
+    source: HopEnrichment,
+    line: &LineSettings,
+) -> Result<()> {
+    let mut addresses: Vec<IpAddr> = hops
+        .iter()
+        .filter_map(|hop| hop.address)
+        .filter(is_public)
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    if addresses.is_empty() {
+        return Ok(());
+    }
+
+    let origins = match source {
+        HopEnrichment::Cymru => lookup_cymru(&addresses).await?,
+        HopEnrichment::Maxmind => lookup_maxmind(addresses, line).await?,
+    };
+    for hop in hops {
+        if let Some(origin) = hop.address.and_then(|ip| origins.get(&ip)) {
+            hop.asn = origin.asn;
+            hop.as_name = origin.as_name.clone();
+            hop.country = origin.country.clone();
+        }
+    }
+    Ok(())
+}
+
+/// Private, loopback, link-local and CGNAT addresses have no meaningful origin AS.
+pub fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || is_cgnat(ip))
+        }
+        IpAddr::V6(v6) => {
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_unique_local()
+                || v6.is_unicast_link_local())
+        }
+    }
+}
+
+/// Whether `ip` is in the carrier-grade NAT shared range 100.64.0.0/10.
+pub fn is_cgnat(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.octets()[0] == 100 && (v4.octets()[1] & 0xc0) == 64,
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// Query Team Cymru's bulk whois interface for every address in one connection.
+async fn lookup_cymru(addresses: &[IpAddr]) -> Result<HashMap<IpAddr, Origin>> {
+    let mut request = String::from("begin\nverbose\n");
+    for ip in addresses {
+        request.push_str(&format!("{ip}\n"));
+    }
+    request.push_str("end\n");
+
+    let exchange = async {
+        let mut stream = TcpStream::connect(CYMRU_WHOIS).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok::<_, std::io::Error>(response)
+    };
+    let response = tokio::time::timeout(CYMRU_TIMEOUT, exchange)
+        .await
+        .context("Team Cymru whois lookup timed out")?
+        .context("Team Cymru whois lookup failed")?;
+    Ok(parse_cymru_response(&response))
+}
+
+/// Parse verbose bulk rows: `AS | IP | BGP Prefix | CC | Registry | Allocated | AS Name`.
+fn parse_cymru_response(response: &str) -> HashMap<IpAddr, Origin> {
+    let mut origins = HashMap::new();
+    for row in response.lines() {
+        let fields: Vec<&str> = row.split('|').map(str::trim).collect();
+        let [
+            asn,
+            ip,
+            _prefix,
+            country,
+            _registry,
+            _allocated,
+            as_name,
+            ..,
+        ] = fields[..]
+        else {
+            continue;
+        };
+        let Ok(ip) = ip.parse::<IpAddr>() else {
+            continue;
+        };
+        let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        origins.insert(
+            ip,
+            Origin {
+                asn: asn.parse().ok(),
+                as_name: non_empty(as_name),
+                country: non_empty(country),
+            },
+        );
+    }
+    origins
+}
+
+#[cfg(feature = "maxmind")]
+async fn lookup_maxmind(
+    addresses: Vec<IpAddr>,
+    line: &LineSettings,
+) -> Result<HashMap<IpAddr, Origin>> {
+    use maxminddb::{Reader, geoip2};
+
+    let asn_db = line.maxmind_asn_db.clone();
+    let country_db = line.maxmind_country_db.clone();
+    tokio::task::spawn_blocking(move || {
+        let open = |path: &std::path::Path| {
+            Reader::open_readfile(path)
+                .with_context(|| format!("Failed to open MaxMind database {}", path.display()))
+        };
+        let asn_reader = asn_db.as_deref().map(open).transpose()?;
+        let country_reader = country_db.as_deref().map(open).transpose()?;
+
+        let mut origins = HashMap::new();
+        for ip in addresses {
+            let mut origin = Origin::default();
+            if let Some(asn) = asn_reader
+                .as_ref()
+                .and_then(|reader| reader.lookup::<geoip2::Asn>(ip).ok())
+            {
+                origin.asn = asn.autonomous_system_number;
+                origin.as_name = asn.autonomous_system_organization.map(str::to_string);
+            }
+            if let Some(country) = country_reader
+                .as_ref()
+                .and_then(|reader| reader.lookup::<geoip2::Country>(ip).ok())
+            {
+                origin.country = country
+                    .country
+                    .and_then(|country| country.iso_code)
+                    .map(str::to_string);
+            }
+            origins.insert(ip, origin);
+        }
+        Ok(origins)
+    })
+    .await
+    .context("MaxMind lookup task panicked")?
+}
+
+#[cfg(not(feature = "maxmind"))]
+async fn lookup_maxmind(
+    _addresses: Vec<IpAddr>,
+    _line: &LineSettings,
+) -> Result<HashMap<IpAddr, Origin>> {
+    anyhow::bail!("MaxMind enrichment requires building with the `maxmind` feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cymru_verbose_rows() {
+        let response = "\
+Bulk mode; whois.cymru.com [2024-05-01 10:00:00 +0000]
+3269    | 151.99.0.1       | 151.99.0.0/16       | IT | ripencc  | 1991-07-30 | ASN-IBSNAZ, IT
+NA      | 203.0.113.10     | NA                  |    | other    |            | NA
+";
+        let origins = parse_cymru_response(response);
+        let tim = &origins[&"151.99.0.1".parse::<IpAddr>().unwrap()];
+        assert_eq!(tim.asn, Some(3269));
+        assert_eq!(tim.country.as_deref(), Some("IT"));
+        assert_eq!(tim.as_name.as_deref(), Some("ASN-IBSNAZ, IT"));
+
+        let unknown = &origins[&"203.0.113.10".parse::<IpAddr>().unwrap()];
+        assert_eq!(unknown.asn, None);
+        assert_eq!(unknown.country, None);
+        assert_eq!(origins.len(), 2);
+    }
+
+    #[test]
+    fn skips_non_routable_hops() {
+        assert!(!is_public(&"192.168.1.1".parse().unwrap()));
+        assert!(!is_public(&"100.64.0.1".parse().unwrap()));
+        assert!(!is_public(&"fe80::1".parse().unwrap()));
+        assert!(is_public(&"151.99.0.1".parse().unwrap()));
+    }
+}
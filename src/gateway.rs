@@ -0,0 +1,83 @@
+//! Detection of the system's default IPv4 gateway.
+
+use std::net::Ipv4Addr;
+
+use anyhow::{Context, Result};
+
+/// Default gateway currently used by the host.
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Result<Ipv4Addr> {
+    let table =
+        std::fs::read_to_string("/proc/net/route").context("Failed to read /proc/net/route")?;
+    parse_proc_route(&table).context("No default route in /proc/net/route")
+}
+
+/// Default gateway currently used by the host.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn default_gateway() -> Result<Ipv4Addr> {
+    let output = std::process::Command::new("route")
+        .args(["-n", "get", "default"])
+        .output()
+        .context("Failed to execute `route -n get default`")?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("gateway:"))
+        .and_then(|gateway| gateway.trim().parse().ok())
+        .context("`route -n get default` reported no gateway")
+}
+
+/// Default gateway currently used by the host.
+#[cfg(windows)]
+pub fn default_gateway() -> Result<Ipv4Addr> {
+    let output = std::process::Command::new("route")
+        .args(["print", "0.0.0.0"])
+        .output()
+        .context("Failed to execute `route print 0.0.0.0`")?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("0.0.0.0"), Some("0.0.0.0"), Some(gateway)) => gateway.parse().ok(),
+                _ => None,
+            }
+        })
+        .context("`route print` reported no default gateway")
+}
+
+/// Pick the lowest-metric default route; addresses are little-endian hex.
+#[cfg(target_os = "linux")]
+fn parse_proc_route(table: &str) -> Option<Ipv4Addr> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|row| {
+            let fields: Vec<&str> = row.split_whitespace().collect();
+            let (destination, gateway, metric) = (fields.get(1)?, fields.get(2)?, fields.get(6)?);
+            if *destination != "00000000" {
+                return None;
+            }
+            let raw = u32::from_str_radix(gateway, 16).ok()?;
+            let metric: u32 = metric.parse().ok()?;
+            (raw != 0).then(|| (metric, Ipv4Addr::from(raw.swap_bytes())))
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, gateway)| gateway)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lowest_metric_default_route() {
+        let table = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+eth0\t00000000\t0100000A\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0000000A\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+";
+        assert_eq!(parse_proc_route(table), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(parse_proc_route("Iface\tDestination\n"), None);
+    }
+}
@@ -0,0 +1,356 @@
+//! `graph`: Smokeping-style charts of each line's latency and packet loss
+//! from the history database, as PNG or SVG files or email attachments.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::ValueEnum;
+use image::ImageEncoder;
+use image::codecs::png::PngEncoder;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::FontStyle;
+
+use crate::history::{MetricSample, load_metric_samples};
+use crate::raw_output::file_stem;
+use crate::redact;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 400;
+/// Share of the height given to the latency panel; loss gets the rest.
+const LATENCY_PANEL_PCT: u32 = 65;
+const FONT_FAMILY: &str = "sans-serif";
+/// Fonts tried when `--font` is not given, on Linux, macOS and Windows.
+const SYSTEM_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu-sans-fonts/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/// The font labels are drawn with, registered once per process.
+static FONT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Image format written by the `graph` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    Png,
+    Svg,
+}
+
+impl GraphFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            GraphFormat::Png => "png",
+            GraphFormat::Svg => "svg",
+        }
+    }
+}
+
+/// Options for the `graph` subcommand.
+pub struct GraphOptions {
+    pub history: PathBuf,
+    pub output: PathBuf,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub format: GraphFormat,
+    /// Only graph these lines, keyed like [`crate::runner::LineResult::key`];
+    /// all of them when empty.
+    pub lines: Vec<String>,
+    pub font: Option<PathBuf>,
+}
+
+/// What is drawn for one line: a title and its samples in time order.
+#[derive(Debug, Clone, PartialEq)]
+struct LineGraph {
+    line: String,
+    title: String,
+    /// `(time, latency, loss)` of each recorded run.
+    points: Vec<(DateTime<Utc>, Option<f32>, Option<f32>)>,
+}
+
+/// Write one chart per line recorded between `since` and `until` into the
+/// output directory, named after the line.
+pub fn run_graph(options: &GraphOptions) -> Result<()> {
+    load_font(options.font.as_deref())?;
+    let graphs = load_graphs(
+        &options.history,
+        options.since,
+        options.until,
+        &options.lines,
+    )?;
+    if graphs.is_empty() {
+        anyhow::bail!(
+            "No history recorded between {} and {}{}",
+            options.since.to_rfc3339_opts(SecondsFormat::Secs, true),
+            options.until.to_rfc3339_opts(SecondsFormat::Secs, true),
+            if options.lines.is_empty() {
+                String::new()
+            } else {
+                format!(" for {}", options.lines.join(", "))
+            }
+        );
+    }
+    fs::create_dir_all(&options.output)
+        .with_context(|| format!("Failed to create {}", options.output.display()))?;
+    for graph in &graphs {
+        let path = options.output.join(format!(
+            "{}.{}",
+            file_stem(&graph.line),
+            options.format.extension()
+        ));
+        let image = match options.format {
+            GraphFormat::Png => render_png(graph, options.since, options.until)?,
+            GraphFormat::Svg => render_svg(graph, options.since, options.until)?.into_bytes(),
+        };
+        fs::write(&path, image).with_context(|| format!("Failed to write {}", path.display()))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// PNG charts of `lines` over the `window` before now, as `(file name,
+/// image)` pairs for attaching to an email. Lines without history are left
+/// out.
+pub fn png_attachments(
+    history: &Path,
+    window: std::time::Duration,
+    lines: &[String],
+) -> Result<Vec<(String, Vec<u8>)>> {
+    load_font(None)?;
+    let until = Utc::now();
+    let since = until - chrono::Duration::from_std(window).context("Graph window is too long")?;
+    load_graphs(history, since, until, lines)?
+        .iter()
+        .map(|graph| {
+            let image = render_png(graph, since, until)?;
+            Ok((format!("{}.png", file_stem(&graph.line)), image))
+        })
+        .collect()
+}
+
+fn load_graphs(
+    history: &Path,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    lines: &[String],
+) -> Result<Vec<LineGraph>> {
+    let samples = load_metric_samples(history, since.timestamp(), until.timestamp())?;
+    Ok(LineGraph::group(&samples)
+        .into_iter()
+        .filter(|graph| lines.is_empty() || lines.contains(&graph.line))
+        .collect())
+}
+
+/// Register `path`, or the first system font found, for drawing labels.
+/// Later calls keep the font registered first.
+fn load_font(path: Option<&Path>) -> Result<()> {
+    if FONT.get().is_some() {
+        return Ok(());
+    }
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => SYSTEM_FONTS
+            .iter()
+            .map(PathBuf::from)
+            .find(|path| path.is_file())
+            .context("No TrueType font found for graph labels; pass one with --font")?,
+    };
+    let bytes =
+        fs::read(&path).with_context(|| format!("Failed to read font {}", path.display()))?;
+    // The font stays registered for the rest of the process.
+    plotters::style::register_font(FONT_FAMILY, FontStyle::Normal, Box::leak(bytes.into()))
+        .map_err(|_| anyhow!("{} is not a TrueType or OpenType font", path.display()))?;
+    let _ = FONT.set(path);
+    Ok(())
+}
+
+impl LineGraph {
+    /// One graph per line, in line order.
+    fn group(samples: &[MetricSample]) -> Vec<Self> {
+        let mut by_line: BTreeMap<&str, Vec<&MetricSample>> = BTreeMap::new();
+        for sample in samples {
+            by_line.entry(&sample.line).or_default().push(sample);
+        }
+        by_line
+            .into_iter()
+            .filter_map(|(line, samples)| {
+                let latest = samples.last()?;
+                Some(LineGraph {
+                    line: line.to_string(),
+                    title: format!("{line} ({})", redact::target(&latest.target)),
+                    points: samples
+                        .iter()
+                        .filter_map(|sample| {
+                            let at = DateTime::from_timestamp(sample.recorded_at, 0)?;
+                            Some((at, sample.average_latency_ms, sample.packet_loss_pct))
+                        })
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Runs of consecutive replies, split wherever a run had no latency so
+    /// the line is not drawn across the gap.
+    fn latency_segments(&self) -> Vec<Vec<(DateTime<Utc>, f32)>> {
+        let mut segments = vec![Vec::new()];
+        for &(at, latency, _) in &self.points {
+            match latency {
+                Some(latency) => segments
+                    .last_mut()
+                    .expect("never empty")
+                    .push((at, latency)),
+                None if segments.last().is_some_and(|segment| !segment.is_empty()) => {
+                    segments.push(Vec::new())
+                }
+                None => {}
+            }
+        }
+        segments.retain(|segment| !segment.is_empty());
+        segments
+    }
+
+    /// Top of the latency axis: a little above the slowest run.
+    fn latency_ceiling(&self) -> f32 {
+        let slowest = self
+            .points
+            .iter()
+            .filter_map(|&(_, latency, _)| latency)
+            .fold(0.0, f32::max);
+        (slowest * 1.1).max(1.0)
+    }
+}
+
+fn render_png(graph: &LineGraph, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<u8>> {
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (WIDTH, HEIGHT)).into_drawing_area();
+        draw(&root, graph, since, until)?;
+        root.present()?;
+    }
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&pixels, WIDTH, HEIGHT, image::ColorType::Rgb8)
+        .context("Failed to encode the graph as PNG")?;
+    Ok(png)
+}
+
+fn render_svg(graph: &LineGraph, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<String> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, (WIDTH, HEIGHT)).into_drawing_area();
+        draw(&root, graph, since, until)?;
+        root.present()?;
+    }
+    Ok(svg)
+}
+
+/// Latency as a line over the top panel and loss as a filled area below,
+/// on a shared time axis.
+fn draw<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    graph: &LineGraph,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let time_label = |at: &DateTime<Utc>| at.format("%m-%d %H:%M").to_string();
+    root.fill(&WHITE)?;
+    let (upper, lower) = root.split_vertically(HEIGHT * LATENCY_PANEL_PCT / 100);
+
+    let mut latency = ChartBuilder::on(&upper)
+        .caption(&graph.title, (FONT_FAMILY, 18))
+        .margin(10)
+        .y_label_area_size(60)
+        .build_cartesian_2d(since..until, 0f32..graph.latency_ceiling())?;
+    latency
+        .configure_mesh()
+        .x_labels(6)
+        .y_desc("Latency (ms)")
+        .label_style((FONT_FAMILY, 12))
+        .draw()?;
+    for segment in graph.latency_segments() {
+        latency.draw_series(LineSeries::new(segment.iter().copied(), &BLUE))?;
+        latency.draw_series(
+            segment
+                .iter()
+                .map(|&point| Circle::new(point, 2, BLUE.filled())),
+        )?;
+    }
+
+    let mut loss = ChartBuilder::on(&lower)
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(since..until, 0f32..100f32)?;
+    loss.configure_mesh()
+        .x_labels(6)
+        .x_label_formatter(&time_label)
+        .y_labels(3)
+        .y_desc("Loss (%)")
+        .label_style((FONT_FAMILY, 12))
+        .draw()?;
+    loss.draw_series(
+        AreaSeries::new(
+            graph
+                .points
+                .iter()
+                .filter_map(|&(at, _, loss)| Some((at, loss?))),
+            0.0,
+            RED.mix(0.3),
+        )
+        .border_style(RED),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_latency_gaps_and_loss() {
+        let sample = |at: i64, latency: Option<f32>, loss: f32| MetricSample {
+            line: "Office/Fibre".into(),
+            target: "192.0.2.1".into(),
+            recorded_at: 1_777_000_000 + at * 300,
+            packet_loss_pct: Some(loss),
+            average_latency_ms: latency,
+        };
+        let samples = [
+            sample(0, Some(12.0), 0.0),
+            sample(1, Some(15.0), 0.0),
+            sample(2, None, 100.0),
+            sample(3, Some(40.0), 20.0),
+        ];
+        let graphs = LineGraph::group(&samples);
+        assert_eq!(graphs.len(), 1);
+        let graph = &graphs[0];
+        assert_eq!(graph.title, "Office/Fibre (192.0.2.1)");
+        let segments: Vec<usize> = graph.latency_segments().iter().map(Vec::len).collect();
+        assert_eq!(segments, vec![2, 1]);
+        assert_eq!(graph.latency_ceiling(), 44.0);
+
+        // Labels need a system font, which minimal build hosts may lack.
+        if load_font(None).is_err() {
+            return;
+        }
+        let since = DateTime::from_timestamp(1_777_000_000, 0).unwrap();
+        let until = since + chrono::Duration::hours(1);
+        let svg = render_svg(graph, since, until).unwrap();
+        assert!(svg.starts_with("<svg"), "{svg}");
+        assert!(svg.contains("Latency (ms)") && svg.contains("Loss (%)"));
+        assert!(svg.contains("Office/Fibre"));
+    }
+}
@@ -0,0 +1,248 @@
+//! End-to-end test harness (`test-harness` feature, Unix only).
+//!
+//! [`FakeTools`] writes `ping`, `traceroute` and `mtr` shims into a scratch
+//! directory. Put it first on `PATH` and every check runs against scripted
+//! output, with the delay and exit code of your choosing, so a whole run can
+//! be exercised from the command line to the notification it sends, which
+//! [`WebhookReceiver`] captures. It is also the quickest way to replay output
+//! captured on another platform when a parser gets it wrong.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What a fake tool prints and how it exits.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    pub output: String,
+    pub exit_code: i32,
+    /// Sleep before printing, to simulate slow probes.
+    pub delay: Duration,
+}
+
+impl Script {
+    pub fn new(output: impl Into<String>) -> Self {
+        Self {
+            output: output.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Replay output captured from a real tool.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path).map(Self::new)
+    }
+
+    pub fn exit_code(mut self, code: i32) -> Self {
+        self.exit_code = code;
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// A directory of scripted `ping`/`traceroute`/`mtr` shims.
+///
+/// Shims pick their script by their last argument, which is the target for
+/// every tool ICMPMolester runs; unknown targets fail like an unresolvable
+/// host. Each invocation's arguments are logged, see [`FakeTools::invocations`].
+/// The directory is removed on drop unless [`FakeTools::keep`] is called.
+pub struct FakeTools {
+    dir: PathBuf,
+    scripts: BTreeMap<String, Vec<(Option<String>, Script)>>,
+    keep: bool,
+}
+
+impl FakeTools {
+    /// Shims in a fresh directory under the system temp dir.
+    pub fn new() -> io::Result<Self> {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let dir = std::env::temp_dir().join(format!(
+            "icmpmolester-fake-tools-{}-{}-{nanos}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        Self::in_dir(dir)
+    }
+
+    /// Shims in `dir`, which is created if needed.
+    pub fn in_dir(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        // Shims refer to their outputs by path, so it must not be relative.
+        let dir = fs::canonicalize(dir)?;
+        Ok(Self {
+            dir,
+            scripts: BTreeMap::new(),
+            keep: false,
+        })
+    }
+
+    /// Script `tool` (e.g. `ping`) for one target.
+    pub fn script(&mut self, tool: &str, target: &str, script: Script) -> io::Result<&mut Self> {
+        self.add(tool, Some(target.to_string()), script)
+    }
+
+    /// Script `tool` for every target that has no script of its own.
+    pub fn fallback(&mut self, tool: &str, script: Script) -> io::Result<&mut Self> {
+        self.add(tool, None, script)
+    }
+
+    fn add(&mut self, tool: &str, target: Option<String>, script: Script) -> io::Result<&mut Self> {
+        let scripts = self.scripts.entry(tool.to_string()).or_default();
+        scripts.retain(|(existing, _)| *existing != target);
+        scripts.push((target, script));
+        self.write_shim(tool)?;
+        Ok(self)
+    }
+
+    fn write_shim(&self, tool: &str) -> io::Result<()> {
+        let scripts = &self.scripts[tool];
+        let log = self.dir.join(format!("{tool}.log"));
+        let mut shim = format!(
+            "#!/bin/sh\necho \"$*\" >> {}\nfor last in \"$@\"; do :; done\ncase \"$last\" in\n",
+            quote(&log.to_string_lossy())
+        );
+        // The fallback, if any, goes last so specific targets win.
+        let mut ordered: Vec<_> = scripts.iter().enumerate().collect();
+        ordered.sort_by_key(|(_, (target, _))| target.is_none());
+        for (index, (target, script)) in ordered {
+            let output = self.dir.join(format!("{tool}.{index}.out"));
+            fs::write(&output, &script.output)?;
+            let pattern = target.as_deref().map_or_else(|| "*".to_string(), quote);
+            shim.push_str(&format!(
+                "  {pattern}) sleep {:.3}; cat {}; exit {} ;;\n",
+                script.delay.as_secs_f64(),
+                quote(&output.to_string_lossy()),
+                script.exit_code
+            ));
+        }
+        shim.push_str(&format!(
+            "  *) echo \"{tool}: $last: Name or service not known\" >&2; exit 2 ;;\nesac\n"
+        ));
+
+        let path = self.dir.join(tool);
+        fs::write(&path, shim)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// `PATH` with the shim directory first, for the process under test.
+    pub fn path_env(&self) -> OsString {
+        let mut paths = vec![self.dir.clone()];
+        if let Some(current) = std::env::var_os("PATH") {
+            paths.extend(std::env::split_paths(&current));
+        }
+        std::env::join_paths(paths).unwrap_or_else(|_| self.dir.clone().into_os_string())
+    }
+
+    /// Arguments of every call to `tool` so far, space separated.
+    pub fn invocations(&self, tool: &str) -> Vec<String> {
+        fs::read_to_string(self.dir.join(format!("{tool}.log")))
+            .map(|log| log.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    /// Leave the shims in place after drop, e.g. for manual runs.
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        self.dir.clone()
+    }
+}
+
+impl Drop for FakeTools {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// Single-quote `text` for `sh`.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Local HTTP endpoint that records every request body POSTed to it and
+/// answers `200 OK`, for use as `--webhook-url`.
+pub struct WebhookReceiver {
+    addr: SocketAddr,
+    bodies: Arc<Mutex<Vec<String>>>,
+}
+
+impl WebhookReceiver {
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let addr = listener.local_addr()?;
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&bodies);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Ok(mut writer) = stream.try_clone() else {
+                    continue;
+                };
+                if let Ok(body) = read_request_body(&mut BufReader::new(stream)) {
+                    received
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .push(body);
+                }
+                let _ = writer.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+            }
+        });
+        Ok(Self { addr, bodies })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/hook", self.addr)
+    }
+
+    /// Bodies received so far, parsed as JSON.
+    pub fn payloads(&self) -> Vec<serde_json::Value> {
+        self.bodies
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .filter_map(|body| serde_json::from_str(body).ok())
+            .collect()
+    }
+}
+
+fn read_request_body(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
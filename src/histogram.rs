@@ -0,0 +1,177 @@
+//! Histograms of a ping's individual round-trip times. Their shape shows what
+//! an average hides: two peaks when replies alternate between paths, a long
+//! tail when a queue along the way fills up.
+
+use icmpmolester::parse;
+use serde::Serialize;
+
+use crate::diagnostics::PingReport;
+
+/// Replies a ping needs before a histogram of them says anything.
+pub const MIN_REPLIES: usize = 10;
+const MAX_BUCKETS: usize = 8;
+const BAR_WIDTH: usize = 30;
+
+/// Replies with a round-trip time from `low_ms` up to `high_ms`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bucket {
+    pub low_ms: f32,
+    pub high_ms: f32,
+    pub count: usize,
+    /// `count` as a percentage of the fullest bucket's, to size bars by.
+    pub scale_pct: f32,
+}
+
+/// Round-trip times counted into equal-width buckets from the fastest reply
+/// to the slowest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub buckets: Vec<Bucket>,
+}
+
+impl Histogram {
+    /// Histogram of the replies in `report`'s output, when it has at least
+    /// [`MIN_REPLIES`].
+    pub fn of_ping(report: &PingReport) -> Option<Self> {
+        Self::of(&parse::reply_rtts(&report.raw_output))
+    }
+
+    /// Bucket `rtts` at the narrowest 1-2-5 width (0.1 ms, 0.2 ms, 0.5 ms,
+    /// 1 ms, ...) that needs no more than eight buckets.
+    pub fn of(rtts: &[f32]) -> Option<Self> {
+        if rtts.len() < MIN_REPLIES {
+            return None;
+        }
+        let low = rtts.iter().copied().fold(f32::INFINITY, f32::min);
+        let high = rtts.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let width = bucket_width(low, high);
+        let first = (low / width).floor();
+        let bucket_count = span(low, high, width);
+        let mut counts = vec![0usize; bucket_count];
+        for rtt in rtts {
+            let index = ((rtt / width).floor() - first) as usize;
+            counts[index.min(bucket_count - 1)] += 1;
+        }
+        let fullest = counts.iter().copied().max().unwrap_or(1) as f32;
+        let buckets = counts
+            .iter()
+            .enumerate()
+            .map(|(index, &count)| {
+                let low_ms = (first + index as f32) * width;
+                Bucket {
+                    low_ms,
+                    high_ms: low_ms + width,
+                    count,
+                    scale_pct: count as f32 / fullest * 100.0,
+                }
+            })
+            .collect();
+        Some(Self { buckets })
+    }
+
+    /// Replies counted across every bucket.
+    pub fn replies(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.count).sum()
+    }
+
+    /// One text row per bucket: its range, a bar of `#` scaled to the fullest
+    /// bucket and the number of replies.
+    pub fn rows(&self) -> Vec<String> {
+        let sub_ms = self
+            .buckets
+            .first()
+            .is_some_and(|bucket| bucket.high_ms - bucket.low_ms < 1.0);
+        let label = |ms: f32| match sub_ms {
+            true => format!("{ms:.1}"),
+            false => format!("{ms:.0}"),
+        };
+        let ranges: Vec<String> = self
+            .buckets
+            .iter()
+            .map(|bucket| format!("{}-{} ms", label(bucket.low_ms), label(bucket.high_ms)))
+            .collect();
+        let width = ranges.iter().map(String::len).max().unwrap_or(0);
+        self.buckets
+            .iter()
+            .zip(ranges)
+            .map(|(bucket, range)| {
+                let bar_len = (bucket.scale_pct / 100.0 * BAR_WIDTH as f32).round() as usize;
+                match bucket.count {
+                    0 => format!("{range:>width$} | 0"),
+                    count => format!("{range:>width$} | {} {count}", "#".repeat(bar_len.max(1))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Buckets of `width` needed to cover `low` to `high`.
+fn span(low: f32, high: f32, width: f32) -> usize {
+    ((high / width).floor() - (low / width).floor()) as usize + 1
+}
+
+fn bucket_width(low: f32, high: f32) -> f32 {
+    let mut exponent = -1;
+    loop {
+        for step in [1.0, 2.0, 5.0] {
+            let width = step * 10f32.powi(exponent);
+            if span(low, high, width) <= MAX_BUCKETS {
+                return width;
+            }
+        }
+        exponent += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_replies_at_a_readable_width() {
+        assert_eq!(Histogram::of(&[10.0; MIN_REPLIES - 1]), None);
+
+        // Two clusters, around 10 ms and 40 ms, as when replies alternate
+        // between two paths.
+        let rtts = [
+            10.2, 11.0, 10.8, 41.5, 10.4, 40.9, 12.3, 42.0, 10.1, 44.7, 11.6,
+        ];
+        let histogram = Histogram::of(&rtts).unwrap();
+        assert_eq!(histogram.replies(), rtts.len());
+        let counts: Vec<(f32, usize)> = histogram
+            .buckets
+            .iter()
+            .map(|bucket| (bucket.low_ms, bucket.count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                (10.0, 7),
+                (15.0, 0),
+                (20.0, 0),
+                (25.0, 0),
+                (30.0, 0),
+                (35.0, 0),
+                (40.0, 4)
+            ]
+        );
+        assert_eq!(
+            histogram.rows(),
+            vec![
+                "10-15 ms | ############################## 7",
+                "15-20 ms | 0",
+                "20-25 ms | 0",
+                "25-30 ms | 0",
+                "30-35 ms | 0",
+                "35-40 ms | 0",
+                "40-45 ms | ################# 4",
+            ]
+        );
+
+        let lan = Histogram::of(&[0.42; MIN_REPLIES]).unwrap();
+        assert_eq!(
+            lan.rows(),
+            vec!["0.4-0.5 ms | ############################## 10"]
+        );
+    }
+}
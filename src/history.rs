@@ -0,0 +1,580 @@
+//! SQLite-backed history of per-line diagnostic results.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::Utc;
+use rusqlite::{Connection, params};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::diagnostics::PingReport;
+use crate::runner::{LineResult, LineStatus};
+
+/// Maximum number of queued records the writer commits in a single transaction.
+const WRITE_BATCH_SIZE: usize = 64;
+/// Bounded queue depth between probing tasks and the writer.
+const WRITE_QUEUE_DEPTH: usize = 256;
+/// How far back the baseline a run is compared against reaches.
+pub const BASELINE_DAYS: i64 = 7;
+/// Latest results shown in each line's latency sparkline.
+pub const TREND_SAMPLES: usize = 20;
+/// Standard deviations above the baseline mean that count as a regression.
+const BASELINE_SIGMAS: f64 = 3.0;
+/// Runs a line needs in the window before its baseline is trusted.
+const BASELINE_MIN_SAMPLES: u32 = 12;
+/// Smallest spread assumed, so a line that never varied (typically 0% loss)
+/// is not flagged for noise: 1 ms of latency, 1 point of loss.
+const MIN_LATENCY_STDDEV_MS: f64 = 1.0;
+const MIN_LOSS_STDDEV_PCT: f64 = 1.0;
+
+/// A single persisted measurement for one line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRecord {
+    pub line: String,
+    pub target: String,
+    pub recorded_at: i64,
+    pub ping_success: Option<bool>,
+    pub packet_loss_pct: Option<f32>,
+    pub average_latency_ms: Option<f32>,
+    pub traceroute_success: Option<bool>,
+    pub hop_count: Option<u32>,
+    pub status: LineStatus,
+}
+
+impl HistoryRecord {
+    /// Capture the metrics of a completed line result at the current time.
+    pub fn from_result(result: &LineResult) -> Self {
+        Self {
+            line: result.key(),
+            target: result.target.clone(),
+            recorded_at: Utc::now().timestamp(),
+            ping_success: result.ping().map(|p| p.success),
+            packet_loss_pct: result.ping().and_then(|p| p.packet_loss_pct),
+            average_latency_ms: result.ping().and_then(|p| p.average_latency_ms),
+            traceroute_success: result.traceroute().map(|t| t.success),
+            hop_count: result.traceroute().and_then(|t| t.hop_count),
+            status: result.status(),
+        }
+    }
+}
+
+/// Mean and standard deviation of one metric over the baseline window.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Spread {
+    pub samples: u32,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+impl Spread {
+    fn from_sums(samples: u32, mean: Option<f64>, mean_of_squares: Option<f64>) -> Option<Self> {
+        let (mean, mean_of_squares) = (mean?, mean_of_squares?);
+        (samples >= BASELINE_MIN_SAMPLES).then(|| Spread {
+            samples,
+            mean,
+            stddev: (mean_of_squares - mean * mean).max(0.0).sqrt(),
+        })
+    }
+
+    /// `value` when it lies more than [`BASELINE_SIGMAS`] deviations above
+    /// the mean, with `min_stddev` as the smallest deviation assumed.
+    fn exceeded_by(&self, value: f64, min_stddev: f64) -> bool {
+        value > self.mean + BASELINE_SIGMAS * self.stddev.max(min_stddev)
+    }
+}
+
+/// A line's usual ping latency and loss over the last [`BASELINE_DAYS`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+    pub latency_ms: Option<Spread>,
+    pub loss_pct: Option<Spread>,
+}
+
+/// A metric of this run well above the line's baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Regression {
+    /// `latency` or `loss`.
+    pub metric: &'static str,
+    pub value: f64,
+    pub baseline: Spread,
+}
+
+impl Regression {
+    pub fn describe(&self) -> String {
+        let unit = if self.metric == "latency" { " ms" } else { "%" };
+        format!(
+            "{} {:.1}{unit} (usually {:.1} ± {:.1}{unit})",
+            self.metric, self.value, self.baseline.mean, self.baseline.stddev
+        )
+    }
+}
+
+impl Baseline {
+    /// Metrics of this run's ping that regressed against the baseline.
+    pub fn regressions(&self, ping: &PingReport) -> Vec<Regression> {
+        let mut regressions = Vec::new();
+        let metrics = [
+            (
+                "latency",
+                self.latency_ms,
+                ping.average_latency_ms,
+                MIN_LATENCY_STDDEV_MS,
+            ),
+            (
+                "loss",
+                self.loss_pct,
+                ping.packet_loss_pct,
+                MIN_LOSS_STDDEV_PCT,
+            ),
+        ];
+        for (metric, spread, value, min_stddev) in metrics {
+            let (Some(baseline), Some(value)) = (spread, value) else {
+                continue;
+            };
+            let value = f64::from(value);
+            if baseline.exceeded_by(value, min_stddev) {
+                regressions.push(Regression {
+                    metric,
+                    value,
+                    baseline,
+                });
+            }
+        }
+        regressions
+    }
+}
+
+/// Baselines of every line with results recorded since `since` (a unix
+/// timestamp), keyed like [`LineResult::key`]. An absent database has none.
+pub fn load_baselines(path: &Path, since: i64) -> Result<BTreeMap<String, Baseline>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let conn = open_database(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT line,
+                COUNT(average_latency_ms), AVG(average_latency_ms),
+                AVG(average_latency_ms * average_latency_ms),
+                COUNT(packet_loss_pct), AVG(packet_loss_pct),
+                AVG(packet_loss_pct * packet_loss_pct)
+         FROM results WHERE recorded_at >= ?1 GROUP BY line",
+    )?;
+    let rows = stmt.query_map(params![since], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            Baseline {
+                latency_ms: Spread::from_sums(row.get(1)?, row.get(2)?, row.get(3)?),
+                loss_pct: Spread::from_sums(row.get(4)?, row.get(5)?, row.get(6)?),
+            },
+        ))
+    })?;
+    rows.collect::<rusqlite::Result<_>>()
+        .with_context(|| format!("Failed to read baselines from {}", path.display()))
+}
+
+/// Average latency of each line's last `samples` results, oldest first and
+/// `None` where no reply came back, keyed like [`LineResult::key`]. An
+/// absent database has none.
+pub fn load_latency_trends(
+    path: &Path,
+    samples: usize,
+) -> Result<BTreeMap<String, Vec<Option<f32>>>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let conn = open_database(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT line, average_latency_ms FROM (
+             SELECT line, average_latency_ms, recorded_at, id,
+                    ROW_NUMBER() OVER (PARTITION BY line ORDER BY recorded_at DESC, id DESC) AS age
+             FROM results)
+         WHERE age <= ?1 ORDER BY line, recorded_at, id",
+    )?;
+    let rows = stmt.query_map(params![samples as i64], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<f64>>(1)?.map(|latency| latency as f32),
+        ))
+    })?;
+    let mut trends: BTreeMap<String, Vec<Option<f32>>> = BTreeMap::new();
+    for row in rows {
+        let (line, latency) =
+            row.with_context(|| format!("Failed to read history from {}", path.display()))?;
+        trends.entry(line).or_default().push(latency);
+    }
+    Ok(trends)
+}
+
+/// A line's status at one recorded run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusSample {
+    pub line: String,
+    pub target: String,
+    pub recorded_at: i64,
+    pub status: LineStatus,
+}
+
+/// Every recorded status since `since` (a unix timestamp), ordered by line
+/// and time. Rows written before statuses were stored count as OK or ALERT
+/// by whether the ping succeeded; rows with neither are skipped.
+pub fn load_status_samples(path: &Path, since: i64) -> Result<Vec<StatusSample>> {
+    if !path.exists() {
+        anyhow::bail!("History database {} does not exist", path.display());
+    }
+    let conn = open_database(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT line, target, recorded_at, status, ping_success FROM results
+         WHERE recorded_at >= ?1 ORDER BY line, recorded_at, id",
+    )?;
+    let rows = stmt.query_map(params![since], |row| {
+        let status = row.get::<_, Option<String>>(3)?;
+        let ping_success = row.get::<_, Option<bool>>(4)?;
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            status,
+            ping_success,
+        ))
+    })?;
+    let mut samples = Vec::new();
+    for row in rows {
+        let (line, target, recorded_at, status, ping_success) =
+            row.with_context(|| format!("Failed to read history from {}", path.display()))?;
+        let status = match status {
+            Some(status) => LineStatus::ALL
+                .into_iter()
+                .find(|known| known.as_str() == status),
+            None => ping_success.map(|up| {
+                if up {
+                    LineStatus::Ok
+                } else {
+                    LineStatus::Alert
+                }
+            }),
+        };
+        if let Some(status) = status {
+            samples.push(StatusSample {
+                line,
+                target,
+                recorded_at,
+                status,
+            });
+        }
+    }
+    Ok(samples)
+}
+
+/// A line's ping measurements at one recorded run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricSample {
+    pub line: String,
+    pub target: String,
+    pub recorded_at: i64,
+    pub packet_loss_pct: Option<f32>,
+    pub average_latency_ms: Option<f32>,
+}
+
+/// Every recorded ping measurement from `since` up to `until` (unix
+/// timestamps), ordered by line and time.
+pub fn load_metric_samples(path: &Path, since: i64, until: i64) -> Result<Vec<MetricSample>> {
+    if !path.exists() {
+        anyhow::bail!("History database {} does not exist", path.display());
+    }
+    let conn = open_database(path)?;
+    let mut stmt = conn.prepare(
+        "SELECT line, target, recorded_at, packet_loss_pct, average_latency_ms FROM results
+         WHERE recorded_at >= ?1 AND recorded_at <= ?2 ORDER BY line, recorded_at, id",
+    )?;
+    let rows = stmt.query_map(params![since, until], |row| {
+        Ok(MetricSample {
+            line: row.get(0)?,
+            target: row.get(1)?,
+            recorded_at: row.get(2)?,
+            packet_loss_pct: row.get::<_, Option<f64>>(3)?.map(|loss| loss as f32),
+            average_latency_ms: row.get::<_, Option<f64>>(4)?.map(|latency| latency as f32),
+        })
+    })?;
+    rows.collect::<rusqlite::Result<_>>()
+        .with_context(|| format!("Failed to read history from {}", path.display()))
+}
+
+/// Cloneable handle used by concurrent line tasks to queue history writes.
+#[derive(Debug, Clone)]
+pub struct HistorySender {
+    sender: mpsc::Sender<HistoryRecord>,
+}
+
+impl HistorySender {
+    /// Queue a record for the writer task; waits only if the queue is full.
+    pub async fn record(&self, record: HistoryRecord) -> Result<()> {
+        self.sender
+            .send(record)
+            .await
+            .map_err(|_| anyhow!("History writer stopped before all results were recorded"))
+    }
+}
+
+/// Dedicated writer that owns the SQLite connection and batches inserts.
+///
+/// Parallel lines never touch the database directly; they push records onto a
+/// channel and this single task commits them, so writers never contend for
+/// SQLite locks.
+pub struct HistoryWriter {
+    sender: HistorySender,
+    handle: JoinHandle<Result<usize>>,
+}
+
+impl HistoryWriter {
+    /// Open (or create) the history database and start the writer task.
+    pub fn spawn(path: &Path) -> Result<Self> {
+        let conn = open_database(path)?;
+        let (sender, mut receiver) = mpsc::channel::<HistoryRecord>(WRITE_QUEUE_DEPTH);
+        let db_path = path.to_path_buf();
+
+        let handle = tokio::task::spawn_blocking(move || {
+            let mut conn = conn;
+            let mut written = 0;
+            let mut batch = Vec::with_capacity(WRITE_BATCH_SIZE);
+            while let Some(first) = receiver.blocking_recv() {
+                batch.push(first);
+                while batch.len() < WRITE_BATCH_SIZE {
+                    match receiver.try_recv() {
+                        Ok(record) => batch.push(record),
+                        Err(_) => break,
+                    }
+                }
+                written += insert_batch(&mut conn, &batch)
+                    .with_context(|| format!("Failed to write history to {}", db_path.display()))?;
+                batch.clear();
+            }
+            Ok(written)
+        });
+
+        Ok(Self {
+            sender: HistorySender { sender },
+            handle,
+        })
+    }
+
+    /// Handle that line tasks clone to submit records.
+    pub fn sender(&self) -> HistorySender {
+        self.sender.clone()
+    }
+
+    /// Close the queue, wait for pending batches to commit, and return the row count.
+    pub async fn finish(self) -> Result<usize> {
+        drop(self.sender);
+        self.handle.await.context("History writer task panicked")?
+    }
+}
+
+fn open_database(path: &Path) -> Result<Connection> {
+    let conn = Connection::open(path)
+        .with_context(|| format!("Failed to open history database {}", path.display()))?;
+    // WAL lets report readers proceed while the writer commits, and a busy
+    // timeout covers other processes (e.g. a concurrent cron run) briefly
+    // holding the write lock.
+    conn.pragma_update(None, "journal_mode", "WAL")
+        .context("Failed to enable WAL mode on history database")?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.busy_timeout(std::time::Duration::from_secs(5))?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS results (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            line TEXT NOT NULL,
+            target TEXT NOT NULL,
+            recorded_at INTEGER NOT NULL,
+            ping_success INTEGER,
+            packet_loss_pct REAL,
+            average_latency_ms REAL,
+            traceroute_success INTEGER,
+            hop_count INTEGER,
+            status TEXT
+        );
+        CREATE INDEX IF NOT EXISTS results_line_time ON results (line, recorded_at);",
+    )
+    .context("Failed to initialise history schema")?;
+    // Databases created before statuses were recorded lack the column.
+    let has_status: bool = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('results') WHERE name = 'status'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_status {
+        conn.execute("ALTER TABLE results ADD COLUMN status TEXT", [])
+            .context("Failed to add the status column to the history database")?;
+    }
+    Ok(conn)
+}
+
+fn insert_batch(conn: &mut Connection, batch: &[HistoryRecord]) -> Result<usize> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO results (line, target, recorded_at, ping_success, packet_loss_pct,
+                                  average_latency_ms, traceroute_success, hop_count, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for record in batch {
+            stmt.execute(params![
+                record.line,
+                record.target,
+                record.recorded_at,
+                record.ping_success,
+                record.packet_loss_pct.map(f64::from),
+                record.average_latency_ms.map(f64::from),
+                record.traceroute_success,
+                record.hop_count,
+                record.status.as_str(),
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(batch.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: &str, recorded_at: i64, latency: f32) -> HistoryRecord {
+        HistoryRecord {
+            line: line.into(),
+            target: "192.0.2.1".into(),
+            recorded_at,
+            ping_success: Some(true),
+            packet_loss_pct: Some(0.0),
+            average_latency_ms: Some(latency),
+            traceroute_success: None,
+            hop_count: None,
+            status: LineStatus::Ok,
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_senders_are_serialised_by_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let writer = HistoryWriter::spawn(&path).unwrap();
+
+        let tasks: Vec<_> = (0..8)
+            .map(|i| {
+                let sender = writer.sender();
+                tokio::spawn(async move {
+                    for n in 0..25 {
+                        sender
+                            .record(record(&format!("line-{i}"), n, n as f32))
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(writer.finish().await.unwrap(), 200);
+
+        let conn = Connection::open(&path).unwrap();
+        let journal: String = conn
+            .pragma_query_value(None, "journal_mode", |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal, "wal");
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM results WHERE line = 'line-3' AND recorded_at >= 10",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 15);
+    }
+
+    #[tokio::test]
+    async fn flags_runs_well_above_the_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        assert!(load_baselines(&path, 0).unwrap().is_empty());
+
+        let writer = HistoryWriter::spawn(&path).unwrap();
+        let sender = writer.sender();
+        sender.record(record("A", 10, 500.0)).await.unwrap();
+        for n in 0..20 {
+            let latency = if n % 2 == 0 { 20.0 } else { 22.0 };
+            sender.record(record("A", 100 + n, latency)).await.unwrap();
+        }
+        for n in 0..5 {
+            sender.record(record("B", 100 + n, 20.0)).await.unwrap();
+        }
+        drop(sender);
+        writer.finish().await.unwrap();
+
+        let baselines = load_baselines(&path, 50).unwrap();
+        let latency = baselines["A"].latency_ms.unwrap();
+        assert_eq!(latency.samples, 20);
+        assert!((latency.mean - 21.0).abs() < 1e-9);
+        assert!((latency.stddev - 1.0).abs() < 1e-9);
+        assert_eq!(baselines["B"].latency_ms, None, "too few samples");
+
+        let ping = |latency: f32, loss: f32| PingReport {
+            success: true,
+            packet_loss_pct: Some(loss),
+            average_latency_ms: Some(latency),
+            jitter_ms: None,
+            duplicates: 0,
+            reordered: 0,
+            raw_output: String::new(),
+        };
+        assert!(baselines["A"].regressions(&ping(23.5, 0.0)).is_empty());
+        let regressions = baselines["A"].regressions(&ping(40.0, 5.0));
+        let metrics: Vec<_> = regressions.iter().map(|r| r.metric).collect();
+        assert_eq!(metrics, ["latency", "loss"]);
+        assert_eq!(
+            regressions[0].describe(),
+            "latency 40.0 ms (usually 21.0 ± 1.0 ms)"
+        );
+
+        let trends = load_latency_trends(&path, 3).unwrap();
+        assert_eq!(trends["A"], [Some(22.0), Some(20.0), Some(22.0)]);
+        assert_eq!(trends["B"].len(), 3);
+    }
+
+    #[test]
+    fn reads_statuses_from_databases_without_the_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE results (
+                id INTEGER PRIMARY KEY AUTOINCREMENT, line TEXT NOT NULL,
+                target TEXT NOT NULL, recorded_at INTEGER NOT NULL, ping_success INTEGER,
+                packet_loss_pct REAL, average_latency_ms REAL, traceroute_success INTEGER,
+                hop_count INTEGER);
+             INSERT INTO results (line, target, recorded_at, ping_success)
+             VALUES ('A', '192.0.2.1', 10, 0), ('A', '192.0.2.1', 20, NULL);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut conn = open_database(&path).unwrap();
+        let mut unknown = record("A", 30, 20.0);
+        unknown.status = LineStatus::Unknown;
+        insert_batch(&mut conn, &[unknown]).unwrap();
+
+        let statuses: Vec<_> = load_status_samples(&path, 0)
+            .unwrap()
+            .into_iter()
+            .map(|sample| (sample.recorded_at, sample.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [(10, LineStatus::Alert), (30, LineStatus::Unknown)]
+        );
+        assert!(load_status_samples(&dir.path().join("missing.db"), 0).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Starter config generation for the `init` subcommand.
+//!
+//! Lines and thresholds come from flags or, when no line is given, from
+//! questions on stdin, so field technicians never have to write TOML by
+//! hand. The result is a commented `lines.toml` that is loaded back before
+//! `init` reports success.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::config::{LoadOptions, load_config};
+
+const DEFAULT_PING_COUNT: u32 = 5;
+const DEFAULT_LOSS_THRESHOLD: f32 = 1.5;
+
+/// What `init` writes and where.
+pub struct InitOptions {
+    pub output: PathBuf,
+    /// `(name, target)` pairs; asked for interactively when empty.
+    pub lines: Vec<(String, String)>,
+    pub ping_count: Option<u32>,
+    pub loss_threshold: Option<f32>,
+    /// Replace an existing file.
+    pub force: bool,
+}
+
+/// Parse `--line "Name=target"`.
+pub fn parse_line(raw: &str) -> Result<(String, String), String> {
+    let (name, target) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=TARGET, got '{raw}'"))?;
+    let (name, target) = (name.trim(), target.trim());
+    if name.is_empty() || target.is_empty() {
+        return Err(format!("expected NAME=TARGET, got '{raw}'"));
+    }
+    Ok((name.to_string(), target.to_string()))
+}
+
+/// Write a starter config, asking for whatever the flags left out.
+pub fn run_init(options: &InitOptions) -> Result<()> {
+    if options.output.exists() && !options.force {
+        anyhow::bail!(
+            "{} already exists; pass --force to replace it",
+            options.output.display()
+        );
+    }
+    let answers = if options.lines.is_empty() {
+        let stdin = io::stdin();
+        ask(&mut stdin.lock(), &mut io::stdout(), options)?
+    } else {
+        Answers {
+            lines: options.lines.clone(),
+            ping_count: options.ping_count.unwrap_or(DEFAULT_PING_COUNT),
+            loss_threshold: options.loss_threshold.unwrap_or(DEFAULT_LOSS_THRESHOLD),
+        }
+    };
+    validate(&answers)?;
+
+    fs::write(&options.output, render(&answers))
+        .with_context(|| format!("Failed to write {}", options.output.display()))?;
+    let config = load_config(&options.output, &LoadOptions::default()).with_context(|| {
+        format!(
+            "Wrote {} but it does not load; please report this",
+            options.output.display()
+        )
+    })?;
+    println!(
+        "Wrote {} with {} line(s). Run `icmpmolester --config {} check-config` to review it.",
+        options.output.display(),
+        config.lines.len(),
+        options.output.display()
+    );
+    Ok(())
+}
+
+/// Everything the generated config is built from.
+#[derive(Debug, PartialEq)]
+struct Answers {
+    lines: Vec<(String, String)>,
+    ping_count: u32,
+    loss_threshold: f32,
+}
+
+/// Ask for lines until an empty name, then for the thresholds not given as flags.
+fn ask(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    options: &InitOptions,
+) -> Result<Answers> {
+    writeln!(
+        output,
+        "Describe each broadband line to check; leave the name empty when done."
+    )?;
+    let mut lines: Vec<(String, String)> = Vec::new();
+    loop {
+        let name = prompt(input, output, "Line name")?;
+        if name.is_empty() {
+            if lines.is_empty() {
+                anyhow::bail!("No lines entered; nothing to write");
+            }
+            break;
+        }
+        if lines.iter().any(|(existing, _)| *existing == name) {
+            writeln!(output, "There is already a line named '{name}'.")?;
+            continue;
+        }
+        let target = loop {
+            let target = prompt(
+                input,
+                output,
+                &format!("Target to ping for {name} (IP or hostname)"),
+            )?;
+            if !target.is_empty() {
+                break target;
+            }
+            writeln!(output, "A target is required.")?;
+        };
+        lines.push((name, target));
+    }
+
+    let ping_count = match options.ping_count {
+        Some(count) => count,
+        None => ask_number(
+            input,
+            output,
+            "Pings per run",
+            DEFAULT_PING_COUNT,
+            |count| *count > 0,
+        )?,
+    };
+    let loss_threshold = match options.loss_threshold {
+        Some(threshold) => threshold,
+        None => ask_number(
+            input,
+            output,
+            "Alert above this packet loss (%)",
+            DEFAULT_LOSS_THRESHOLD,
+            |pct| (0.0..100.0).contains(pct),
+        )?,
+    };
+    Ok(Answers {
+        lines,
+        ping_count,
+        loss_threshold,
+    })
+}
+
+/// Ask until the answer parses and passes `valid`; an empty answer takes `default`.
+fn ask_number<T>(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    default: T,
+    valid: impl Fn(&T) -> bool,
+) -> Result<T>
+where
+    T: std::str::FromStr + std::fmt::Display + Copy,
+{
+    loop {
+        let answer = prompt(input, output, &format!("{question} [{default}]"))?;
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        match answer.parse() {
+            Ok(value) if valid(&value) => return Ok(value),
+            _ => writeln!(output, "'{answer}' is not a valid value.")?,
+        }
+    }
+}
+
+fn prompt(input: &mut impl BufRead, output: &mut impl Write, question: &str) -> Result<String> {
+    write!(output, "{question}: ")?;
+    output.flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        anyhow::bail!("Input ended before the config was complete");
+    }
+    Ok(answer.trim().to_string())
+}
+
+/// Reject flag values the prompts would not have accepted.
+fn validate(answers: &Answers) -> Result<()> {
+    if answers.ping_count == 0 {
+        anyhow::bail!("--ping-count must be at least 1");
+    }
+    if !(0.0..100.0).contains(&answers.loss_threshold) {
+        anyhow::bail!("--loss-threshold must be between 0 and 100");
+    }
+    for (index, (name, _)) in answers.lines.iter().enumerate() {
+        if answers.lines[..index]
+            .iter()
+            .any(|(other, _)| other == name)
+        {
+            anyhow::bail!("Line '{name}' is given more than once");
+        }
+    }
+    Ok(())
+}
+
+/// The config file, with comments pointing at the settings worth knowing.
+fn render(answers: &Answers) -> String {
+    let quote = |text: &str| toml::Value::String(text.to_string()).to_string();
+    let mut text = format!(
+        "# Generated by `icmpmolester init`. Every setting is explained in the README.\n\
+         \n\
+         [defaults]\n\
+         ping_count = {}  # echo requests per run\n\
+         ping_timeout_ms = 1000  # how long to wait for each reply\n\
+         packet_loss_alert_threshold = {:?}  # alert above this loss (%)\n\
+         traceroute_max_hops = 30\n\
+         # alert_after_failures = 3  # only alert after 3 failing runs in a row (needs --state)\n",
+        answers.ping_count, answers.loss_threshold
+    );
+    for (name, target) in &answers.lines {
+        text.push_str(&format!(
+            "\n[[lines]]\n\
+             name = {}\n\
+             target = {}\n\
+             # note = \"Who to call and the contract number\"  # shown with alerts\n\
+             # checks = [\"ping\"]  # skip traceroute for this line\n",
+            quote(name),
+            quote(target)
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn asks_for_lines_and_writes_a_loadable_config() {
+        let options = InitOptions {
+            output: PathBuf::new(),
+            lines: Vec::new(),
+            ping_count: None,
+            loss_threshold: Some(2.5),
+            force: false,
+        };
+        let mut input = Cursor::new(
+            "Shop \"FTTH\"\n\n192.0.2.1\nShop \"FTTH\"\nLTE backup\nlte.example.net\n\n0\n8\n",
+        );
+        let mut output = Vec::new();
+        let answers = ask(&mut input, &mut output, &options).unwrap();
+        assert_eq!(
+            answers,
+            Answers {
+                lines: vec![
+                    ("Shop \"FTTH\"".into(), "192.0.2.1".into()),
+                    ("LTE backup".into(), "lte.example.net".into()),
+                ],
+                ping_count: 8,
+                loss_threshold: 2.5,
+            }
+        );
+        let transcript = String::from_utf8(output).unwrap();
+        assert!(transcript.contains("A target is required."));
+        assert!(transcript.contains("There is already a line named"));
+        assert!(transcript.contains("'0' is not a valid value."));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.toml");
+        fs::write(&path, render(&answers)).unwrap();
+        let config = load_config(&path, &LoadOptions::default()).unwrap();
+        assert_eq!(config.lines[0].name, "Shop \"FTTH\"");
+        assert_eq!(config.lines[1].target, "lte.example.net");
+        assert_eq!(config.lines[1].ping_count, 8);
+        assert_eq!(config.lines[1].packet_loss_alert_threshold, 2.5);
+
+        assert!(ask(&mut Cursor::new("\n"), &mut Vec::new(), &options).is_err());
+        assert_eq!(
+            parse_line("Fiber = 192.0.2.1").unwrap(),
+            ("Fiber".into(), "192.0.2.1".into())
+        );
+        assert!(parse_line("Fiber").is_err());
+    }
+}
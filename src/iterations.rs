@@ -0,0 +1,182 @@
+//! Repeated runs of every line for `--iterations`, folded into one result per
+//! line with latency percentiles and the loss over every iteration.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::checks::CheckOutcome;
+use crate::runner::LineResult;
+
+/// Ping statistics of one line over every iteration of a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IterationStats {
+    /// Iterations in which the line's ping ran.
+    pub samples: usize,
+    /// Mean of the per-iteration loss, i.e. the loss over every packet sent.
+    pub loss_pct: Option<f32>,
+    /// Nearest-rank percentiles of the per-iteration average latency.
+    pub latency_p50_ms: Option<f32>,
+    pub latency_p95_ms: Option<f32>,
+    pub latency_max_ms: Option<f32>,
+}
+
+impl IterationStats {
+    fn of(samples: &[PingSample]) -> Self {
+        let losses: Vec<f32> = samples.iter().filter_map(|s| s.loss_pct).collect();
+        let mut latencies: Vec<f32> = samples.iter().filter_map(|s| s.latency_ms).collect();
+        latencies.sort_by(f32::total_cmp);
+        Self {
+            samples: samples.len(),
+            loss_pct: mean(&losses),
+            latency_p50_ms: percentile(&latencies, 50.0),
+            latency_p95_ms: percentile(&latencies, 95.0),
+            latency_max_ms: latencies.last().copied(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let loss = self
+            .loss_pct
+            .map_or_else(|| "n/a".into(), |loss| format!("{loss:.2}%"));
+        let latency = match (
+            self.latency_p50_ms,
+            self.latency_p95_ms,
+            self.latency_max_ms,
+        ) {
+            (Some(p50), Some(p95), Some(max)) => {
+                format!("p50 {p50:.1} ms, p95 {p95:.1} ms, max {max:.1} ms")
+            }
+            _ => "n/a".into(),
+        };
+        format!(
+            "{} iterations: loss {loss}, latency {latency}",
+            self.samples
+        )
+    }
+}
+
+/// What one iteration's ping measured.
+struct PingSample {
+    success: bool,
+    loss_pct: Option<f32>,
+    latency_ms: Option<f32>,
+}
+
+/// Fold the results of every iteration into the last one's.
+///
+/// Each line's ping is then judged on the loss over all iterations and their
+/// mean latency, and the line carries [`IterationStats`]. Its other checks
+/// are those of the last iteration. The last iteration's loss confirmation
+/// judged only that iteration's ping, so it is dropped. A single iteration is
+/// returned unchanged.
+pub fn merge(mut iterations: Vec<Vec<LineResult>>) -> Vec<LineResult> {
+    let Some(mut last) = iterations.pop() else {
+        return Vec::new();
+    };
+    if iterations.is_empty() {
+        return last;
+    }
+    let mut samples: HashMap<String, Vec<PingSample>> = HashMap::new();
+    for result in iterations.iter().flatten().chain(&last) {
+        if let Some(report) = result.ping() {
+            samples.entry(result.key()).or_default().push(PingSample {
+                success: report.success,
+                loss_pct: report.packet_loss_pct,
+                latency_ms: report.average_latency_ms,
+            });
+        }
+    }
+    for result in &mut last {
+        let Some(samples) = samples.get(&result.key()) else {
+            continue;
+        };
+        let stats = IterationStats::of(samples);
+        for check in &mut result.checks {
+            if let CheckOutcome::Ping {
+                report,
+                confirmation,
+                ..
+            } = check
+            {
+                let latencies: Vec<f32> = samples.iter().filter_map(|s| s.latency_ms).collect();
+                report.success = samples.iter().any(|s| s.success);
+                report.packet_loss_pct = stats.loss_pct;
+                report.average_latency_ms = mean(&latencies);
+                *confirmation = None;
+            }
+        }
+        result.iterations = Some(stats);
+    }
+    last
+}
+
+pub fn mean(values: &[f32]) -> Option<f32> {
+    (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+}
+
+/// Nearest-rank percentile of `sorted`, which must be in ascending order.
+pub fn percentile(sorted: &[f32], pct: f32) -> Option<f32> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f32).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::Confirmation;
+    use crate::runner::LineStatus;
+    use crate::runner::tests::sample_result;
+
+    #[test]
+    fn merges_iterations_into_percentiles_and_overall_loss() {
+        let iteration = |loss, latency| {
+            vec![
+                sample_result("Primary", true, Some(loss), Some(latency), 5.0, None),
+                sample_result("Backup", true, Some(0.0), Some(9.0), 5.0, None),
+            ]
+        };
+        let single = merge(vec![iteration(20.0, 11.0)]);
+        assert_eq!(single[0].iterations, None);
+        assert_eq!(single[0].status(), LineStatus::Alert);
+
+        let mut last = iteration(20.0, 12.0);
+        // The last iteration's loss was re-probed and not confirmed.
+        if let CheckOutcome::Ping { confirmation, .. } = &mut last[0].checks[0] {
+            *confirmation = Some(Confirmation {
+                probes: 3,
+                breached: 0,
+                required: 2,
+            });
+        }
+        let merged = merge(vec![
+            iteration(0.0, 11.0),
+            iteration(0.0, 30.0),
+            iteration(0.0, 10.0),
+            last,
+        ]);
+        let stats = merged[0].iterations.as_ref().unwrap();
+        assert_eq!(stats.samples, 4);
+        assert_eq!(stats.loss_pct, Some(5.0));
+        assert_eq!(stats.latency_p50_ms, Some(11.0));
+        assert_eq!(stats.latency_p95_ms, Some(30.0));
+        assert_eq!(merged[0].ping().unwrap().average_latency_ms, Some(15.75));
+        assert!(matches!(
+            merged[0].checks[0],
+            CheckOutcome::Ping {
+                confirmation: None,
+                ..
+            }
+        ));
+        // 5% over every iteration does not breach the 5% threshold.
+        assert_eq!(merged[0].status(), LineStatus::Ok);
+        assert_eq!(
+            merged[0].iteration_note().as_deref(),
+            Some("4 iterations: loss 5.00%, latency p50 11.0 ms, p95 30.0 ms, max 30.0 ms")
+        );
+        assert_eq!(merged[1].iterations.as_ref().unwrap().loss_pct, Some(0.0));
+    }
+}
@@ -0,0 +1,9 @@
+//! Reusable pieces of ICMPMolester.
+//!
+//! The binary is the main product; this library only exposes the parts that
+//! are useful to other tools, starting with [`parse`] for ping and traceroute
+//! output, plus the `harness` module behind the `test-harness` feature.
+
+#[cfg(all(unix, feature = "test-harness"))]
+pub mod harness;
+pub mod parse;
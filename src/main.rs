@@ -1,35 +1,251 @@
 //! Command-line interface for ICMPMolester.
 
+mod alerting;
+mod bulk;
+mod check_config;
+mod checks;
 mod config;
 mod diagnostics;
+mod doctor;
+mod enrichment;
+mod gateway;
+mod graph;
+mod histogram;
+mod history;
+mod init;
+mod iterations;
+mod metrics;
 mod notify;
+mod probes;
+mod raw_output;
+mod redact;
+mod remote;
+mod report;
+mod resolver;
 mod runner;
+mod schedule;
+mod schema;
+mod secrets;
+mod server;
+mod simulate;
+mod sinks;
+mod site;
+mod sla;
+mod soak;
+mod state;
+mod status;
+mod style;
+mod template;
+mod tui;
+mod tunnel;
+mod xmpp;
 
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use tokio::time::MissedTickBehavior;
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, info, warn};
 
-use crate::config::load_config;
-use crate::notify::{EmailConfig, TelegramConfig, send_email, send_telegram};
-use crate::runner::{RunOptions, format_summary, print_cli, run_lines};
+use crate::alerting::{AlertReport, AlertSink, Recovery};
+use crate::bulk::{BulkOptions, run_bulk};
+use crate::check_config::run_check_config;
+use crate::checks::CheckOutcome;
+use crate::config::{
+    Config, ConfigFile, Credentials, LoadOptions, OutputFormat, RunSettings, load_config_files,
+    load_credentials, load_notification_templates, load_notification_webhooks, load_output_sinks,
+    load_run_settings, read_configs,
+};
+use crate::doctor::{DoctorOptions, run_doctor};
+use crate::graph::{GraphFormat, GraphOptions, run_graph};
+use crate::history::{
+    BASELINE_DAYS, Baseline, HistoryWriter, TREND_SAMPLES, load_baselines, load_latency_trends,
+};
+use crate::init::{InitOptions, parse_line, run_init};
+use crate::notify::{
+    DiscordConfig, EmailConfig, EmailGraphs, JsonWebhookConfig, MatrixConfig, MattermostConfig,
+    SignalConfig, SignalTransport, TeamsConfig, TelegramConfig, WebhookConfig, WebhookFormat,
+    XmppConfig,
+};
+use crate::redact::RedactMode;
+use crate::remote::RemoteOptions;
+use crate::report::{RunMetadata, RunReport, format_csv, format_markdown, format_ndjson_line};
+use crate::runner::{
+    LineCallback, LineResult, RunOptions, RunOutcome, batch_name, print_cli, run_loaded,
+};
+use crate::schedule::{Tick, run_schedules};
+use crate::secrets::Secret;
+use crate::server::{ServeOptions, serve, spawn_metrics};
+use crate::simulate::{Scenario, Simulation, parse_scenario};
+use crate::site::{SiteOptions, run_site};
+use crate::sla::{SlaFormat, SlaOptions, run_sla};
+use crate::soak::{SoakOptions, parse_duration, run_soak};
+use crate::state::AlertState;
+use crate::status::print_status;
+use crate::style::ColorChoice;
+use crate::template::{ChannelTemplate, ReportTemplate};
+use crate::tui::{TuiOptions, run_tui};
+use crate::tunnel::{Tunnel, parse_tunnel};
 
 /// Command-line arguments controlling an ICMPMolester run.
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 #[command(name = "ICMPMolester", about = "Fixed broadband diagnostics runner")]
 struct Cli {
-    /// Path to the ICMPMolester configuration file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to an ICMPMolester configuration file, a directory of them, or an
+    /// http(s) URL to fetch one from; repeat to run several configs as
+    /// batches merged into one report
     #[arg(short, long, default_value = "lines.toml")]
-    config: PathBuf,
+    config: Vec<PathBuf>,
+
+    /// Bearer token sent when fetching `--config` URLs (visible to other
+    /// local users; prefer the file, env or keyring variants)
+    #[arg(long, conflicts_with_all = ["config_token_file", "config_token_env", "config_token_keyring"])]
+    config_token: Option<String>,
+
+    /// File whose first line is the config bearer token
+    #[arg(long, conflicts_with_all = ["config_token_env", "config_token_keyring"])]
+    config_token_file: Option<PathBuf>,
+
+    /// Environment variable holding the config bearer token
+    #[arg(long, conflicts_with = "config_token_keyring")]
+    config_token_env: Option<String>,
+
+    /// OS keyring entry holding the config bearer token (needs the `keyring` feature)
+    #[arg(long)]
+    config_token_keyring: Option<String>,
+
+    /// Directory where fetched configs and their ETags are cached (defaults
+    /// to ~/.cache/icmpmolester/configs)
+    #[arg(long)]
+    config_cache: Option<PathBuf>,
+
+    /// Only run lines carrying this tag (repeatable; a line matching any
+    /// listed tag runs)
+    #[arg(long, visible_alias = "group")]
+    tag: Vec<String>,
 
-    /// Skip traceroute checks
+    /// Apply the config's `[profiles.<name>]` overrides to every line
     #[arg(long)]
-    skip_traceroute: bool,
+    profile: Option<String>,
 
-    /// Maximum number of lines to probe concurrently (defaults to CPU count)
+    /// Fail on config keys no setting reads, such as misspelt settings,
+    /// instead of warning about them
+    #[arg(long)]
+    strict_config: bool,
+
+    /// Skip traceroute checks (`--skip-traceroute=false` overrides a
+    /// config's `[run]` table)
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    skip_traceroute: Option<bool>,
+
+    /// Maximum number of lines to probe concurrently across all configs
+    /// (defaults to 32)
     #[arg(long)]
     concurrency: Option<usize>,
 
+    /// Give up on a run that takes longer than this (e.g. 90s, 5m)
+    #[arg(long, value_parser = parse_duration)]
+    run_timeout: Option<Duration>,
+
+    /// Run every line this many times and report latency percentiles and the
+    /// loss over all of them
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+
+    /// Time between iterations, in seconds or as a duration (e.g. 30, 1m)
+    #[arg(long, value_parser = parse_interval, default_value = "30")]
+    pause: Duration,
+
+    /// Log to stderr in more detail: -v for progress, -vv for the commands
+    /// run, how their output was parsed and notification attempts, -vvv for
+    /// raw tool output
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Log only errors to stderr
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// When to color the text output; `auto` colors a terminal unless
+    /// NO_COLOR is set
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// How results are printed (defaults to text)
+    #[arg(long, visible_alias = "format", value_enum)]
+    output: Option<OutputFormat>,
+
+    /// Print the JSON Schema of the `--format json` report (or, with
+    /// `--format ndjson`, of each line record) and exit
+    #[arg(long)]
+    print_schema: bool,
+
+    /// Tera template rendering the text summary and notification body
+    /// instead of the built-in wording
+    #[arg(long, value_name = "FILE")]
+    report_template: Option<PathBuf>,
+
+    /// Leave OK and snoozed lines out of the text summary and notification
+    /// summaries, counting them in a footer instead
+    #[arg(long)]
+    only_alerts: bool,
+
+    /// Replace addresses and hostnames in printed output, notifications,
+    /// raw output files and status pages, for sharing them publicly:
+    /// `mask` numbers them in order of appearance, `hash` derives them from
+    /// the original so they match across reports
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "mask")]
+    redact: Option<RedactMode>,
+
+    /// SQLite database to append per-line results to (created if missing)
+    #[arg(long)]
+    history: Option<PathBuf>,
+
+    /// Prometheus textfile to replace with this run's metrics, e.g. in
+    /// node_exporter's textfile collector directory
+    #[arg(long)]
+    prom_textfile: Option<PathBuf>,
+
+    /// Directory to keep each line's raw ping and traceroute output in, one
+    /// subdirectory per run
+    #[arg(long)]
+    raw_output_dir: Option<PathBuf>,
+
+    /// JSON file persisting alert state (statuses, silences, acks) between runs
+    #[arg(long)]
+    state: Option<PathBuf>,
+
+    /// Accept the current traceroute paths as the new route baselines
+    #[arg(long, requires = "state")]
+    rebaseline_routes: bool,
+
+    /// Send notifications only when at least one line's status changed
+    /// since the previous run
+    #[arg(long, requires = "state")]
+    notify_on_change: bool,
+
+    /// Send notifications only when a line is in ALERT, ERRORED or UNKNOWN,
+    /// or has just recovered (`--notify-only-on-alert=false` overrides a config's
+    /// `[run]` table)
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    notify_only_on_alert: Option<bool>,
+
+    /// Hold back a notification that carries the same alerts as the last
+    /// one sent to that channel and destination within this window (e.g.
+    /// 1h); needs `--state` except in `watch`
+    #[arg(long, value_parser = parse_duration)]
+    notify_dedup_window: Option<Duration>,
+
     /// SMTP server address for email notifications (e.g. smtp.example.com)
     #[arg(long)]
     email_smtp: Option<String>,
@@ -38,10 +254,23 @@ struct Cli {
     #[arg(long)]
     email_username: Option<String>,
 
-    /// SMTP password if authentication is required
-    #[arg(long)]
+    /// SMTP password if authentication is required (visible to other local
+    /// users; prefer the file, env or keyring variants)
+    #[arg(long, conflicts_with_all = ["email_password_file", "email_password_env", "email_password_keyring"])]
     email_password: Option<String>,
 
+    /// File whose first line is the SMTP password
+    #[arg(long, conflicts_with_all = ["email_password_env", "email_password_keyring"])]
+    email_password_file: Option<PathBuf>,
+
+    /// Environment variable holding the SMTP password
+    #[arg(long, conflicts_with = "email_password_keyring")]
+    email_password_env: Option<String>,
+
+    /// OS keyring entry holding the SMTP password (needs the `keyring` feature)
+    #[arg(long)]
+    email_password_keyring: Option<String>,
+
     /// Sender email address for notifications
     #[arg(long)]
     email_from: Option<String>,
@@ -50,54 +279,1474 @@ struct Cli {
     #[arg(long, value_delimiter = ',')]
     email_to: Vec<String>,
 
-    /// Telegram bot token for notifications
+    /// Attach a PNG chart of each reported line's latency and loss over
+    /// this much history (e.g. 24h) to email notifications
+    #[arg(long, value_parser = parse_duration)]
+    email_graphs: Option<Duration>,
+
+    /// Also attach the JSON result of each line in ALERT to email
+    /// notifications, next to its raw ping and traceroute output
     #[arg(long)]
+    email_attach_json: bool,
+
+    /// Telegram bot token for notifications (visible to other local users;
+    /// prefer the file, env or keyring variants)
+    #[arg(long, conflicts_with_all = ["telegram_token_file", "telegram_token_env", "telegram_token_keyring"])]
     telegram_token: Option<String>,
 
+    /// File whose first line is the Telegram bot token
+    #[arg(long, conflicts_with_all = ["telegram_token_env", "telegram_token_keyring"])]
+    telegram_token_file: Option<PathBuf>,
+
+    /// Environment variable holding the Telegram bot token
+    #[arg(long, conflicts_with = "telegram_token_keyring")]
+    telegram_token_env: Option<String>,
+
+    /// OS keyring entry holding the Telegram bot token (needs the `keyring` feature)
+    #[arg(long)]
+    telegram_token_keyring: Option<String>,
+
     /// Telegram chat ID to deliver notifications to
     #[arg(long)]
     telegram_chat_id: Option<String>,
+
+    /// Matrix homeserver to post notifications through, e.g. https://matrix.org
+    #[arg(long)]
+    matrix_homeserver: Option<String>,
+
+    /// Matrix room ID to post notifications in, e.g. !abc123:example.org
+    #[arg(long)]
+    matrix_room: Option<String>,
+
+    /// Matrix access token of the account that posts (visible to other local
+    /// users; prefer the file, env or keyring variants)
+    #[arg(long, conflicts_with_all = ["matrix_token_file", "matrix_token_env", "matrix_token_keyring"])]
+    matrix_token: Option<String>,
+
+    /// File whose first line is the Matrix access token
+    #[arg(long, conflicts_with_all = ["matrix_token_env", "matrix_token_keyring"])]
+    matrix_token_file: Option<PathBuf>,
+
+    /// Environment variable holding the Matrix access token
+    #[arg(long, conflicts_with = "matrix_token_keyring")]
+    matrix_token_env: Option<String>,
+
+    /// OS keyring entry holding the Matrix access token (needs the `keyring` feature)
+    #[arg(long)]
+    matrix_token_keyring: Option<String>,
+
+    /// XMPP account (bare JID) that sends notifications, e.g. probe@example.org
+    #[arg(long)]
+    xmpp_jid: Option<String>,
+
+    /// Password of the XMPP account (visible to other local users; prefer the
+    /// file, env or keyring variants)
+    #[arg(long, conflicts_with_all = ["xmpp_password_file", "xmpp_password_env", "xmpp_password_keyring"])]
+    xmpp_password: Option<String>,
+
+    /// File whose first line is the XMPP password
+    #[arg(long, conflicts_with_all = ["xmpp_password_env", "xmpp_password_keyring"])]
+    xmpp_password_file: Option<PathBuf>,
+
+    /// Environment variable holding the XMPP password
+    #[arg(long, conflicts_with = "xmpp_password_keyring")]
+    xmpp_password_env: Option<String>,
+
+    /// OS keyring entry holding the XMPP password (needs the `keyring` feature)
+    #[arg(long)]
+    xmpp_password_keyring: Option<String>,
+
+    /// JID to send XMPP notifications to (repeat flag or comma separated)
+    #[arg(long, value_delimiter = ',')]
+    xmpp_to: Vec<String>,
+
+    /// Multi-user chat room to post XMPP notifications in (repeatable)
+    #[arg(long)]
+    xmpp_room: Vec<String>,
+
+    /// Nickname used in --xmpp-room rooms
+    #[arg(long, default_value = "ICMPMolester")]
+    xmpp_nick: String,
+
+    /// XMPP server as host[:port], when it is not the JID's domain on port 5222
+    #[arg(long)]
+    xmpp_server: Option<String>,
+
+    /// Phone number of the signal-cli account that sends notifications, e.g. +391234567890
+    #[arg(long)]
+    signal_account: Option<String>,
+
+    /// Signal group ID to post notifications in (see `signal-cli listGroups`)
+    #[arg(long)]
+    signal_group: Option<String>,
+
+    /// signal-cli executable to run for each notification [default: signal-cli]
+    #[arg(long, conflicts_with = "signal_rpc_url")]
+    signal_cli: Option<PathBuf>,
+
+    /// JSON-RPC endpoint of a running `signal-cli daemon --http`, used instead
+    /// of starting signal-cli, e.g. http://127.0.0.1:8080/api/v1/rpc
+    #[arg(long)]
+    signal_rpc_url: Option<String>,
+
+    /// URL to POST a JSON notification to after each run
+    #[arg(long)]
+    webhook_url: Option<String>,
+
+    /// Payload shape for the webhook
+    #[arg(long, value_enum, default_value = "flat", requires = "webhook_url")]
+    webhook_format: WebhookFormat,
+
+    /// JSON file with `{{field}}` placeholders, used with --webhook-format template
+    #[arg(long, requires = "webhook_url")]
+    webhook_template: Option<PathBuf>,
+
+    /// PagerDuty integration key, used with --webhook-format pagerduty
+    #[arg(long, requires = "webhook_url")]
+    webhook_routing_key: Option<String>,
+
+    /// Discord webhook URL to post the run's summary to; lines with their
+    /// own `discord_webhook` in the config go to that one instead
+    #[arg(long)]
+    discord_webhook: Option<String>,
+
+    /// Microsoft Teams incoming webhook (or Workflows) URL to post an
+    /// Adaptive Card summary to
+    #[arg(long)]
+    teams_webhook: Option<String>,
+
+    /// Mattermost incoming webhook URL to post a Markdown summary to
+    #[arg(long)]
+    mattermost_webhook: Option<String>,
+
+    /// Mattermost channel to post in instead of the webhook's default
+    #[arg(long, requires = "mattermost_webhook")]
+    mattermost_channel: Option<String>,
+
+    /// Send notifications through a SOCKS5 proxy or SSH jump host
+    /// (socks5://[user:pass@]host:port or ssh://[user@]host[:port])
+    #[arg(long, value_parser = parse_tunnel)]
+    notify_via: Option<Tunnel>,
+
+    /// Inject a synthetic failure (loss=<pct>, timeout or route-change) to
+    /// rehearse alerting; repeat to combine scenarios
+    #[arg(long, value_parser = parse_scenario)]
+    simulate: Vec<Scenario>,
+
+    /// Only simulate failures on this line (repeatable; defaults to every line)
+    #[arg(long, requires = "simulate")]
+    simulate_line: Vec<String>,
+}
+
+impl Cli {
+    /// SMTP password source given on the command line.
+    fn email_password(&self) -> Option<Secret> {
+        Secret::first_of(
+            self.email_password.as_ref(),
+            self.email_password_file.as_ref(),
+            self.email_password_env.as_ref(),
+            self.email_password_keyring.as_ref(),
+        )
+    }
+
+    /// Bearer token source for `--config` URLs.
+    fn config_token(&self) -> Option<Secret> {
+        Secret::first_of(
+            self.config_token.as_ref(),
+            self.config_token_file.as_ref(),
+            self.config_token_env.as_ref(),
+            self.config_token_keyring.as_ref(),
+        )
+    }
+
+    /// Fill in settings the flags leave out from the configs' `[run]` tables.
+    fn apply_run_settings(&mut self, settings: RunSettings) {
+        self.concurrency = self.concurrency.or(settings.concurrency);
+        self.run_timeout = self.run_timeout.or(settings.timeout);
+        self.skip_traceroute = self.skip_traceroute.or(settings.skip_traceroute);
+        self.notify_only_on_alert = self.notify_only_on_alert.or(settings.notify_only_on_alert);
+        self.output = self.output.or(settings.output);
+        self.history = self.history.take().or(settings.history);
+    }
+
+    fn skip_traceroute(&self) -> bool {
+        self.skip_traceroute.unwrap_or(false)
+    }
+
+    fn notify_only_on_alert(&self) -> bool {
+        self.notify_only_on_alert.unwrap_or(false)
+    }
+
+    /// Print a progress message: on stdout next to text output, and on
+    /// stderr when stdout carries a machine-readable format.
+    fn note(&self, message: &str) {
+        match self.output {
+            Some(OutputFormat::Text) | None => println!("{message}"),
+            Some(_) => eprintln!("{message}"),
+        }
+    }
+
+    /// How `--config` files are loaded.
+    fn load_options(&self) -> LoadOptions {
+        LoadOptions {
+            profile: self.profile.clone(),
+            strict: self.strict_config,
+        }
+    }
+
+    /// Telegram bot token source given on the command line.
+    fn telegram_token(&self) -> Option<Secret> {
+        Secret::first_of(
+            self.telegram_token.as_ref(),
+            self.telegram_token_file.as_ref(),
+            self.telegram_token_env.as_ref(),
+            self.telegram_token_keyring.as_ref(),
+        )
+    }
+
+    /// XMPP password source given on the command line.
+    fn xmpp_password(&self) -> Option<Secret> {
+        Secret::first_of(
+            self.xmpp_password.as_ref(),
+            self.xmpp_password_file.as_ref(),
+            self.xmpp_password_env.as_ref(),
+            self.xmpp_password_keyring.as_ref(),
+        )
+    }
+
+    /// Matrix access token source given on the command line.
+    fn matrix_token(&self) -> Option<Secret> {
+        Secret::first_of(
+            self.matrix_token.as_ref(),
+            self.matrix_token_file.as_ref(),
+            self.matrix_token_env.as_ref(),
+            self.matrix_token_keyring.as_ref(),
+        )
+    }
+}
+
+/// Auxiliary commands; without one, ICMPMolester runs diagnostics.
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// Check tools, permissions, paths and notification reachability
+    Doctor,
+    /// Show persisted line statuses and notification channel health (requires --state)
+    Status,
+    /// Repeatedly ping one line for a bounded period and report statistics
+    Soak {
+        /// Name of the line to soak, as written in the config
+        #[arg(long)]
+        line: String,
+        /// Total soak duration (e.g. 30m, 1h, 1h30m)
+        #[arg(long, value_parser = parse_duration)]
+        duration: Duration,
+        /// Time between ping rounds
+        #[arg(long, value_parser = parse_duration, default_value = "10s")]
+        interval: Duration,
+    },
+    /// Re-enable a line auto-disabled after repeated execution errors (requires --state)
+    Enable {
+        /// Line to re-enable; use `<config>/<line>` when several configs are run
+        #[arg(long)]
+        line: String,
+    },
+    /// Ping thousands of targets through a shared ICMP socket and print a compact table
+    Bulk {
+        /// File listing one target per line, optionally followed by a label
+        #[arg(long)]
+        targets: PathBuf,
+        /// Echo requests sent per second across all targets
+        #[arg(long, default_value_t = 200)]
+        rate: u32,
+        /// Echo requests per target
+        #[arg(long, default_value_t = 3)]
+        count: u32,
+        /// How long to wait for each reply
+        #[arg(long, value_parser = parse_duration, default_value = "2s")]
+        timeout: Duration,
+        /// Packet loss percentage above which a target alerts
+        #[arg(long, default_value_t = 50.0)]
+        loss_threshold: f32,
+    },
+    /// Validate the config and print each line's resolved settings without probing
+    CheckConfig,
+    /// Write a commented starter config to --config, asking for lines on
+    /// stdin unless they are given with --line
+    Init {
+        /// Line to add as `NAME=TARGET` (repeatable)
+        #[arg(long = "line", value_parser = parse_line)]
+        lines: Vec<(String, String)>,
+        /// Echo requests per run (asked for when no --line is given)
+        #[arg(long)]
+        ping_count: Option<u32>,
+        /// Packet loss percentage above which a line alerts
+        #[arg(long)]
+        loss_threshold: Option<f32>,
+        /// Replace the file if it already exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Store a credential read from stdin in the OS keyring, for use with
+    /// --email-password-keyring, --telegram-token-keyring,
+    /// --matrix-token-keyring or --xmpp-password-keyring
+    #[cfg(feature = "keyring")]
+    SetSecret {
+        /// Entry name to store it under
+        #[arg(long)]
+        name: String,
+    },
+    /// Run every line at a fixed interval until interrupted, keeping alert
+    /// state between runs and notifying only when a line changes status
+    Watch {
+        /// Time between run starts, in seconds or as a duration (e.g. 300, 5m)
+        #[arg(long, value_parser = parse_interval, default_value = "300")]
+        interval: Duration,
+        /// Also serve Prometheus metrics at http://ADDR/metrics
+        #[arg(long, value_name = "ADDR")]
+        metrics_listen: Option<SocketAddr>,
+    },
+    /// Show a live dashboard of every line, re-run at a fixed interval;
+    /// select a line and press r to re-probe it straight away
+    Tui {
+        /// Time between runs, in seconds or as a duration (e.g. 60, 5m)
+        #[arg(long, value_parser = parse_interval, default_value = "60")]
+        interval: Duration,
+    },
+    /// Draw each line's latency and packet loss from the history database
+    /// as PNG or SVG charts (requires --history)
+    Graph {
+        /// Directory to write one chart per line into
+        #[arg(long)]
+        output: PathBuf,
+        /// How much history each chart covers (e.g. 24h, 7d)
+        #[arg(long, value_parser = parse_duration, default_value = "24h")]
+        range: Duration,
+        /// End of the charted range as an RFC 3339 time (defaults to now)
+        #[arg(long)]
+        end: Option<DateTime<Utc>>,
+        /// Image format
+        #[arg(long, value_enum, default_value_t = GraphFormat::Png)]
+        format: GraphFormat,
+        /// Only chart this line; use `<config>/<line>` when several configs
+        /// are run (repeatable)
+        #[arg(long)]
+        line: Vec<String>,
+        /// TrueType or OpenType font for the labels (defaults to a system
+        /// font such as DejaVu Sans or Arial)
+        #[arg(long)]
+        font: Option<PathBuf>,
+    },
+    /// Build reports from the history database (requires --history)
+    Report {
+        #[command(subcommand)]
+        command: ReportCommand,
+    },
+    /// Serve an HTTP API that triggers runs on demand (POST /api/run)
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+        /// Do not run one full cycle and send a "monitoring started" notification on startup
+        #[arg(long)]
+        no_warm_start: bool,
+        /// Also serve Prometheus metrics at http://ADDR/metrics, on a
+        /// listener separate from the API
+        #[arg(long, value_name = "ADDR")]
+        metrics_listen: Option<SocketAddr>,
+    },
+}
+
+/// Reports built by the `report` subcommand.
+#[derive(Debug, Clone, Subcommand)]
+enum ReportCommand {
+    /// Write a static HTML status page with per-line uptime bars, current
+    /// statuses and recent incidents
+    Site {
+        /// Directory to write index.html into
+        #[arg(long)]
+        output: PathBuf,
+        /// Days of history shown, today included
+        #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u32).range(1..=3650))]
+        days: u32,
+        /// Page title
+        #[arg(long, default_value = "Line status")]
+        title: String,
+    },
+    /// Print each line's availability, mean loss, p95 latency and outages
+    /// over a period
+    Sla {
+        /// Length of the period (e.g. 30d)
+        #[arg(long, value_parser = parse_duration, default_value = "30d")]
+        period: Duration,
+        /// End of the period as an RFC 3339 time (defaults to now)
+        #[arg(long)]
+        end: Option<DateTime<Utc>>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = SlaFormat::Text)]
+        format: SlaFormat,
+    },
 }
 
 #[tokio::main(flavor = "multi_thread")]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let config = load_config(&cli.config)?;
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(outcome) => ExitCode::from(outcome.exit_code()),
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(RunOutcome::ExecutionError.exit_code())
+        }
+    }
+}
+
+/// Run the requested command. Only a plain run reports how the lines did;
+/// the other commands end OK unless they fail.
+async fn run(mut cli: Cli) -> Result<RunOutcome> {
+    style::init(cli.color);
+    if let Some(mode) = cli.redact {
+        redact::init(mode);
+    }
+    if cli.print_schema {
+        let schema = match cli.output {
+            Some(OutputFormat::Ndjson) => schema::ndjson_line_schema(),
+            _ => schema::report_schema(),
+        };
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+        return Ok(RunOutcome::Ok);
+    }
+    // The dashboard owns the terminal; log lines would tear it.
+    if !matches!(cli.command, Some(Command::Tui { .. })) {
+        init_logging(cli.verbose, cli.quiet);
+    }
+    remote::configure(RemoteOptions {
+        token: cli.config_token(),
+        cache_dir: cli
+            .config_cache
+            .clone()
+            .unwrap_or_else(remote::default_cache_dir),
+    });
+
+    // Only runs take settings from `[run]`; the other commands must work
+    // with configs that do not load.
+    let mut files = None;
+    if matches!(
+        cli.command,
+        None | Some(Command::Watch { .. })
+            | Some(Command::Tui { .. })
+            | Some(Command::Serve { .. })
+    ) {
+        let read = read_configs(&cli.config)?;
+        cli.apply_run_settings(load_run_settings(&read)?);
+        files = Some(read.into());
+    }
+
+    match cli.command {
+        Some(Command::Doctor) => {
+            return run_doctor(&DoctorOptions {
+                config: cli.config.clone(),
+                history: cli.history.clone(),
+                state: cli.state.clone(),
+                email_smtp: cli.email_smtp.clone(),
+                telegram_enabled: cli.telegram_token().is_some() || cli.telegram_chat_id.is_some(),
+                notify_via: cli.notify_via.clone(),
+            })
+            .await
+            .map(|()| RunOutcome::Ok);
+        }
+        Some(Command::Init {
+            ref lines,
+            ping_count,
+            loss_threshold,
+            force,
+        }) => {
+            let [output] = &cli.config[..] else {
+                anyhow::bail!("init writes a single config; pass one --config path");
+            };
+            if remote::url(output).is_some() || output.is_dir() {
+                anyhow::bail!(
+                    "init needs a file path for --config, not {}",
+                    output.display()
+                );
+            }
+            return run_init(&InitOptions {
+                output: output.clone(),
+                lines: lines.clone(),
+                ping_count,
+                loss_threshold,
+                force,
+            })
+            .map(|()| RunOutcome::Ok);
+        }
+        Some(Command::CheckConfig) => {
+            return run_check_config(&cli.config, &cli.load_options())
+                .await
+                .map(|()| RunOutcome::Ok);
+        }
+        #[cfg(feature = "keyring")]
+        Some(Command::SetSecret { ref name }) => {
+            let mut secret = String::new();
+            std::io::stdin()
+                .read_line(&mut secret)
+                .context("Failed to read the secret from stdin")?;
+            let secret = secret.trim_end_matches(['\r', '\n']);
+            if secret.is_empty() {
+                anyhow::bail!("No secret given on stdin");
+            }
+            secrets::keyring_set(name, secret)?;
+            println!("Stored keyring entry {}/{name}", secrets::KEYRING_SERVICE);
+            return Ok(RunOutcome::Ok);
+        }
+        Some(Command::Status) => {
+            let path = cli
+                .state
+                .as_deref()
+                .context("--state is required for the status command")?;
+            print_status(&AlertState::load(path)?);
+            return Ok(RunOutcome::Ok);
+        }
+        Some(Command::Soak {
+            ref line,
+            duration,
+            interval,
+        }) => {
+            return run_soak(&SoakOptions {
+                config: cli.config.clone(),
+                line: line.clone(),
+                duration,
+                interval,
+                load: cli.load_options(),
+            })
+            .await
+            .map(|()| RunOutcome::Ok);
+        }
+        Some(Command::Graph {
+            ref output,
+            range,
+            end,
+            format,
+            ref line,
+            ref font,
+        }) => {
+            let history = cli
+                .history
+                .clone()
+                .context("--history is required for the graph command")?;
+            let until = end.unwrap_or_else(Utc::now);
+            run_graph(&GraphOptions {
+                history,
+                output: output.clone(),
+                since: until - chrono::Duration::from_std(range).context("--range is too long")?,
+                until,
+                format,
+                lines: line.clone(),
+                font: font.clone(),
+            })?;
+            return Ok(RunOutcome::Ok);
+        }
+        Some(Command::Report {
+            command:
+                ReportCommand::Site {
+                    ref output,
+                    days,
+                    ref title,
+                },
+        }) => {
+            let history = cli
+                .history
+                .clone()
+                .context("--history is required for the report command")?;
+            run_site(&SiteOptions {
+                history,
+                output: output.clone(),
+                days,
+                title: title.clone(),
+            })?;
+            return Ok(RunOutcome::Ok);
+        }
+        Some(Command::Report {
+            command:
+                ReportCommand::Sla {
+                    period,
+                    end,
+                    format,
+                },
+        }) => {
+            let history = cli
+                .history
+                .clone()
+                .context("--history is required for the report command")?;
+            let until = end.unwrap_or_else(Utc::now);
+            run_sla(&SlaOptions {
+                history,
+                since: until
+                    - chrono::Duration::from_std(period).context("--period is too long")?,
+                until,
+                format,
+            })?;
+            return Ok(RunOutcome::Ok);
+        }
+        Some(Command::Enable { ref line }) => {
+            let path = cli
+                .state
+                .as_deref()
+                .context("--state is required for the enable command")?;
+            let mut state = AlertState::load(path)?;
+            if !state.enable_line(line) {
+                anyhow::bail!("Line '{line}' is not disabled");
+            }
+            state.save(path)?;
+            println!("Re-enabled {line}");
+            return Ok(RunOutcome::Ok);
+        }
+        Some(Command::Bulk {
+            ref targets,
+            rate,
+            count,
+            timeout,
+            loss_threshold,
+        }) => {
+            return run_bulk(&BulkOptions {
+                targets: targets.clone(),
+                rate,
+                count,
+                timeout,
+                loss_threshold,
+            })
+            .await
+            .map(|()| RunOutcome::Ok);
+        }
+        Some(Command::Serve {
+            listen,
+            no_warm_start,
+            metrics_listen,
+        }) => {
+            if let Some(addr) = metrics_listen {
+                spawn_metrics(addr).await?;
+            }
+            if !no_warm_start {
+                // A line that cannot be checked must not keep the API from starting.
+                let warm_start = Cycle {
+                    startup: true,
+                    files: files.take(),
+                    ..Cycle::default()
+                };
+                if let Err(err) = run_cycle(&cli, warm_start, None).await {
+                    metrics::exporter().record_failed_run();
+                    println!("Warm-start run failed: {err:#}");
+                }
+            }
+            let scheduled = cli.clone();
+            tokio::spawn(async move {
+                run_schedules(&scheduled.config, &scheduled.load_options(), |tick| {
+                    let cycle = Cycle {
+                        tick: Some(tick),
+                        changes_only: scheduled.notify_on_change,
+                        ..Cycle::default()
+                    };
+                    let scheduled = &scheduled;
+                    async move {
+                        run_cycle(scheduled, cycle, None)
+                            .await
+                            .map(drop)
+                            .inspect_err(|_| metrics::exporter().record_failed_run())
+                    }
+                })
+                .await;
+            });
+            return serve(ServeOptions {
+                listen,
+                config: cli.config.clone(),
+                skip_traceroute: cli.skip_traceroute(),
+                concurrency_limit: cli.concurrency,
+                load: cli.load_options(),
+            })
+            .await
+            .map(|()| RunOutcome::Ok);
+        }
+        Some(Command::Watch {
+            interval,
+            metrics_listen,
+        }) => {
+            if let Some(addr) = metrics_listen {
+                spawn_metrics(addr).await?;
+            }
+            return run_watch(&cli, interval, files)
+                .await
+                .map(|()| RunOutcome::Ok);
+        }
+        Some(Command::Tui { interval }) => {
+            return run_tui(TuiOptions {
+                config: cli.config.clone(),
+                interval,
+                skip_traceroute: cli.skip_traceroute(),
+                concurrency_limit: cli.concurrency,
+                tags: cli.tag.clone(),
+                load: cli.load_options(),
+            })
+            .await
+            .map(|()| RunOutcome::Ok);
+        }
+        None => {}
+    }
+
+    // `watch` remembers what it sent in memory; a single run needs the file.
+    if cli.notify_dedup_window.is_some() && cli.state.is_none() {
+        anyhow::bail!("--notify-dedup-window needs --state to remember what was sent");
+    }
+    let cycle = Cycle {
+        changes_only: cli.notify_on_change,
+        interruptible: true,
+        files,
+        ..Cycle::default()
+    };
+    run_cycle(&cli, cycle, None).await
+}
+
+/// Send log events to stderr, warnings and errors by default. Results keep
+/// going to stdout whatever the level.
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .init();
+}
+
+/// `--interval`: plain seconds or a duration such as `5m`.
+fn parse_interval(raw: &str) -> Result<Duration, String> {
+    match raw.parse::<u64>() {
+        Ok(0) => Err("interval must be greater than zero".into()),
+        Ok(seconds) => Ok(Duration::from_secs(seconds)),
+        Err(_) => parse_duration(raw),
+    }
+}
+
+/// Run every line each `interval` until Ctrl-C.
+///
+/// Runs start at fixed times; one that overruns delays the next rather than
+/// piling up. Without `--state`, alert state is kept in memory for as long
+/// as the watch runs. The first run reuses `files`, the configs read at
+/// startup.
+async fn run_watch(
+    cli: &Cli,
+    interval: Duration,
+    mut files: Option<Arc<[ConfigFile]>>,
+) -> Result<()> {
+    cli.note(&format!(
+        "Watching every {}s; press Ctrl-C to stop.",
+        interval.as_secs()
+    ));
+    let mut memory = AlertState::default();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut startup = true;
+    loop {
+        let cycle = Cycle {
+            startup,
+            changes_only: true,
+            interruptible: true,
+            files: files.take(),
+            ..Cycle::default()
+        };
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = ticker.tick() => {}
+        }
+        // The run handles Ctrl-C itself, so the lines that finished are
+        // still reported.
+        match run_cycle(cli, cycle, Some(&mut memory)).await {
+            Ok(RunOutcome::Interrupted) => break,
+            Ok(_) => {}
+            // A failed run must not end the watch.
+            Err(err) => {
+                metrics::exporter().record_failed_run();
+                cli.note(&format!("Run failed: {err:#}"));
+            }
+        }
+        startup = false;
+    }
+    cli.note("Stopped watching.");
+    Ok(())
+}
+
+/// What one run of every line is for.
+#[derive(Default)]
+struct Cycle {
+    /// Open the notification with the current state and every line whose
+    /// status changed since the state was last saved.
+    startup: bool,
+    /// Only run the lines scheduled for this minute, from the scheduler's
+    /// config snapshot.
+    tick: Option<Tick>,
+    /// Skip notifications unless a line changed status (`watch` and
+    /// `--notify-on-change`); the startup notification is always sent.
+    changes_only: bool,
+    /// Ctrl-C stops the run, keeping the lines that finished.
+    interruptible: bool,
+    /// The `--config` files, when already read for this run; otherwise the
+    /// run reads them.
+    files: Option<Arc<[ConfigFile]>>,
+}
+
+/// What a run reads before it reports, so every step sees the same configs
+/// and a remote config is fetched once.
+struct RunInputs {
+    /// The `--config` files, for the settings outside the lines.
+    files: Arc<[ConfigFile]>,
+    /// The lines of each config, or the scheduler's snapshot of them.
+    configs: Vec<(PathBuf, Config)>,
+    /// `--report-template`, when given.
+    template: Option<ReportTemplate>,
+}
+
+/// Run every configured line once, reconcile alert state and notify.
+///
+/// Alert state comes from `--state`, or else from `memory` when the caller
+/// keeps it between runs.
+async fn run_cycle(cli: &Cli, cycle: Cycle, memory: Option<&mut AlertState>) -> Result<RunOutcome> {
+    let Cycle {
+        startup,
+        tick,
+        changes_only,
+        interruptible,
+        files,
+    } = cycle;
+    let files = match (files, &tick) {
+        (Some(files), _) => files,
+        (None, Some(tick)) => Arc::clone(&tick.files),
+        (None, None) => read_configs(&cli.config)?.into(),
+    };
+    let configs = match &tick {
+        Some(tick) => tick.configs.clone(),
+        None => load_config_files(&files, &cli.load_options())?,
+    };
+    let due = tick.as_ref().map(|tick| tick.due);
+    // Read before this run is recorded, so it is compared only with earlier ones.
+    let baselines = match cli.history.as_deref() {
+        Some(path) => {
+            let since = Utc::now() - chrono::Duration::days(BASELINE_DAYS);
+            load_baselines(path, since.timestamp())?
+        }
+        None => BTreeMap::new(),
+    };
+    let history_writer = cli
+        .history
+        .as_deref()
+        .map(HistoryWriter::spawn)
+        .transpose()?;
+
+    let mut loaded = cli.state.as_deref().map(AlertState::load).transpose()?;
+    let mut state = loaded.as_mut().or(memory);
     let options = RunOptions {
-        skip_traceroute: cli.skip_traceroute,
+        skip_traceroute: cli.skip_traceroute(),
         concurrency_limit: cli.concurrency,
+        history: history_writer.as_ref().map(HistoryWriter::sender),
+        batch: None,
+        disabled: state
+            .as_deref()
+            .map(AlertState::disabled_lines)
+            .unwrap_or_default(),
+        simulation: (!cli.simulate.is_empty()).then(|| Simulation {
+            scenarios: cli.simulate.clone(),
+            lines: cli.simulate_line.clone(),
+        }),
+        tags: cli.tag.clone(),
+        due,
+        interruptible,
+        load: cli.load_options(),
+        on_line_finished: (cli.output == Some(OutputFormat::Ndjson)).then(|| {
+            Arc::new(|result: &LineResult| {
+                let shown = redact::results(std::slice::from_ref(result));
+                match format_ndjson_line(&shown[0]) {
+                    Ok(line) => println!("{}", redact::text(&line)),
+                    Err(err) => warn!(line = %result.key(), "Failed to encode the result: {err}"),
+                }
+            }) as LineCallback
+        }),
     };
+    let started_at = Utc::now();
+    info!(iterations = cli.iterations, "Starting run");
+    let run = async {
+        // Owned by the run, so the history sender it holds is dropped
+        // before the writer is waited for.
+        let options = options;
+        let mut iterations = Vec::new();
+        for iteration in 1..=cli.iterations {
+            if iteration > 1 {
+                eprintln!(
+                    "Iteration {} of {} done; next in {}s",
+                    iteration - 1,
+                    cli.iterations,
+                    cli.pause.as_secs()
+                );
+                // Ctrl-C between iterations reports the ones that finished.
+                tokio::select! {
+                    _ = tokio::time::sleep(cli.pause) => {}
+                    _ = tokio::signal::ctrl_c(), if interruptible => break,
+                }
+            }
+            let options = options.clone();
+            let results = run_loaded(configs.clone(), options).await?;
+            let interrupted = results.iter().any(|result| result.interrupted);
+            iterations.push(results);
+            if interrupted {
+                break;
+            }
+        }
+        anyhow::Ok(iterations::merge(iterations))
+    };
+    let mut results = match cli.run_timeout {
+        Some(limit) => tokio::time::timeout(limit, run)
+            .await
+            .map_err(|_| anyhow::anyhow!("Run did not finish within {}s", limit.as_secs_f32()))??,
+        None => run.await?,
+    };
+    let run = RunMetadata::new(started_at, Utc::now());
+    info!(
+        run_id = %run.run_id,
+        lines = results.len(),
+        duration_s = run.duration_secs(),
+        "Run finished"
+    );
+    let unfinished = results.iter().filter(|result| result.interrupted).count();
+    if unfinished > 0 {
+        eprintln!(
+            "Interrupted: {unfinished} of {} lines did not finish",
+            results.len()
+        );
+    }
+    if let Some(writer) = history_writer {
+        writer.finish().await?;
+    }
+    if let Some(due) = due {
+        if results.is_empty() {
+            return Ok(RunOutcome::Ok);
+        }
+        cli.note(&format!(
+            "Scheduled run at {}",
+            due.format("%Y-%m-%d %H:%M")
+        ));
+    }
 
-    let results = run_lines(config, options).await?;
-    print_cli(&results);
+    flag_regressions(&baselines, &mut results);
+    if let Some(state) = state.as_deref_mut() {
+        record_execution_errors(state, &mut results);
+        alerting::record_failures(state, &mut results);
+        compare_routes(state, &mut results, cli.rebaseline_routes);
+        compare_public_ips(state, &mut results);
+    }
+    let inputs = RunInputs {
+        files,
+        configs,
+        template: cli
+            .report_template
+            .as_deref()
+            .map(ReportTemplate::load)
+            .transpose()?,
+    };
+    {
+        let (results, run) = (redact::results(&results), redact::run(&run));
+        print_results(cli, &results, &run, inputs.template.as_ref())?;
+        if let Some(dir) = &cli.raw_output_dir {
+            let saved = raw_output::save(dir, &results, &run)?;
+            info!(dir = %saved.display(), "Saved raw output");
+        }
+    }
+    // Like a failed notification, a failed write is reported once the
+    // state has been saved.
+    let sinks = sinks::write(&load_output_sinks(&inputs.files)?, &results, &run);
+    let changes = match state.as_deref_mut() {
+        Some(state) => {
+            let observed = alerting::record_transitions(state, &mut results, Utc::now());
+            for line in &observed.log {
+                cli.note(line);
+            }
+            observed.changes
+        }
+        None => Vec::new(),
+    };
+    let headline = startup
+        .then(|| alerting::format_startup(&results, &changes))
+        .map(|headline| redact::text(&headline).into_owned());
+    // The startup headline already lists every change.
+    let recoveries = if startup {
+        Vec::new()
+    } else {
+        alerting::recoveries(&changes)
+    };
+    // The headline would break JSON, CSV and Markdown output; it still goes
+    // out with the notifications.
+    if let Some(OutputFormat::Text) | None = cli.output {
+        if let Some(headline) = &headline {
+            print!("{headline}");
+        }
+        print!("{}", alerting::format_recoveries(&recoveries));
+    }
+
+    let quiet = !alerting::raises_alert(&results);
+    let delivery = if changes_only && !startup && changes.is_empty() {
+        Ok(())
+    } else if cli.notify_only_on_alert() && quiet && recoveries.is_empty() {
+        debug!("No line raises an alert; not notifying");
+        Ok(())
+    } else {
+        dispatch_notifications(
+            cli,
+            &inputs,
+            &run,
+            &results,
+            headline,
+            &recoveries,
+            state.as_deref_mut(),
+        )
+    };
 
-    let summary = format_summary(&results);
+    // Metrics go out after the notifications, so the channel statistics
+    // include this run's deliveries.
+    let channels = state
+        .as_deref()
+        .map(|state| state.channels.clone())
+        .unwrap_or_default();
+    metrics::exporter().record_run(&results, &run, state.is_some().then_some(&channels));
+    let textfile = cli
+        .prom_textfile
+        .as_deref()
+        .map(|path| metrics::write_textfile(path, &results, &run, &channels))
+        .transpose();
 
-    if let Some(email_cfg) = build_email_config(&cli)? {
-        send_email(&summary, &email_cfg)?;
-        println!(
-            "Email notification dispatched to {}",
-            email_cfg.to.join(", ")
-        );
+    if let (Some(path), Some(state)) = (&cli.state, state) {
+        state.save(path)?;
     }
 
-    if let Some(telegram_cfg) = build_telegram_config(&cli)? {
-        let compact_summary = runner::format_compact_summary(&results);
-        send_telegram(&compact_summary, &telegram_cfg)?;
-        println!(
-            "Telegram notification dispatched to {}",
-            telegram_cfg.chat_id
-        );
+    textfile?;
+    delivery?;
+    sinks?;
+    let failed: Vec<String> = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .map(LineResult::key)
+        .collect();
+    if !failed.is_empty() {
+        eprintln!("Checks could not be executed for {}", failed.join(", "));
     }
+    Ok(RunOutcome::of(&results))
+}
 
+/// Count consecutive execution errors and auto-disable lines that reach their limit.
+fn record_execution_errors(state: &mut AlertState, results: &mut [LineResult]) {
+    let now = Utc::now();
+    // Snoozed, interrupted and dependency-down lines did not run, so their
+    // error streak is left as it was.
+    for result in results.iter_mut().filter(|result| {
+        result.snooze.is_none() && !result.interrupted && result.dependency_down.is_none()
+    }) {
+        let key = result.key();
+        let error = result.error.clone();
+        let Some(auto) = result.auto_disable.as_mut() else {
+            state.execution_errors.remove(&key);
+            continue;
+        };
+        if auto.active {
+            continue;
+        }
+        if state.record_execution(
+            &key,
+            error.as_deref(),
+            auto.after_errors,
+            auto.fingerprint,
+            now,
+        ) {
+            auto.active = true;
+            warn!(
+                line = %key,
+                "Line disabled after {} consecutive execution errors",
+                auto.after_errors
+            );
+        }
+    }
+}
+
+/// Measure each traced path against the line's saved route baseline.
+fn compare_routes(state: &mut AlertState, results: &mut [LineResult], rebaseline: bool) {
+    let now = Utc::now();
+    // A fallback target's path says nothing about the baselined one.
+    for result in results.iter_mut().filter(|r| r.failed_targets.is_empty()) {
+        let key = result.key();
+        // A simulated path must never become (or replace) the baseline.
+        let simulated = result.simulated.is_some();
+        if simulated && !state.routes.contains_key(&key) {
+            continue;
+        }
+        for check in &mut result.checks {
+            let CheckOutcome::Traceroute {
+                report: Some(report),
+                hop_tolerance: Some(_),
+                route_change,
+                ..
+            } = check
+            else {
+                continue;
+            };
+            if !report.success || report.hops.is_empty() {
+                continue;
+            }
+            let path: Vec<_> = report.hops.iter().map(|hop| hop.address).collect();
+            *route_change = Some(state.compare_route(&key, &path, rebaseline && !simulated, now));
+        }
+    }
+}
+
+/// Note lines whose latency or loss regressed against their history baseline.
+///
+/// Simulated and snoozed runs are not measurements of the line, so they are
+/// left alone. Regressions are informational and do not change the status.
+fn flag_regressions(baselines: &BTreeMap<String, Baseline>, results: &mut [LineResult]) {
+    for result in results {
+        if result.simulated.is_some() || result.snooze.is_some() {
+            continue;
+        }
+        if let (Some(baseline), Some(ping)) = (baselines.get(&result.key()), result.ping()) {
+            result.regressions = baseline.regressions(ping);
+        }
+    }
+}
+
+/// Record each line's public IP, flagging addresses that changed since the last run.
+fn compare_public_ips(state: &mut AlertState, results: &mut [LineResult]) {
+    let now = Utc::now();
+    for result in results {
+        let key = result.key();
+        for check in &mut result.checks {
+            if let CheckOutcome::PublicIp {
+                report,
+                changed_from,
+                ..
+            } = check
+            {
+                if let Some(address) = report.address {
+                    *changed_from = state.observe_public_ip(&key, address, now);
+                }
+            }
+        }
+    }
+}
+
+/// Print a run's results in the `--format` asked for; NDJSON lines were
+/// already printed as they finished.
+fn print_results(
+    cli: &Cli,
+    results: &[LineResult],
+    run: &RunMetadata,
+    template: Option<&ReportTemplate>,
+) -> Result<()> {
+    match cli.output.unwrap_or(OutputFormat::Text) {
+        OutputFormat::Text => match template {
+            Some(template) => print!("{}", template.render(results, run)?),
+            None => {
+                let trends = match cli.history.as_deref() {
+                    Some(path) => load_latency_trends(path, TREND_SAMPLES)?,
+                    None => BTreeMap::new(),
+                };
+                print_cli(results, &trends, cli.only_alerts);
+                println!("{}", run.describe());
+            }
+        },
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&RunReport::new(results, run))?
+        ),
+        OutputFormat::Csv => print!("{}", format_csv(results, run)),
+        OutputFormat::Markdown => print!("{}", format_markdown(results, run)),
+        OutputFormat::Ndjson => {}
+    }
+    Ok(())
+}
+
+/// Evaluate alert events and hand the run report to every configured sink.
+fn dispatch_notifications(
+    cli: &Cli,
+    inputs: &RunInputs,
+    run: &RunMetadata,
+    results: &[LineResult],
+    headline: Option<String>,
+    recoveries: &[Recovery],
+    mut state: Option<&mut AlertState>,
+) -> Result<()> {
+    let credentials = load_credentials(&inputs.files)?;
+    let mut sinks: Vec<Box<dyn AlertSink>> = Vec::new();
+    if let Some(email_cfg) = build_email_config(cli, &credentials)? {
+        sinks.push(Box::new(email_cfg));
+    }
+    if let Some(telegram_cfg) = build_telegram_config(cli, &credentials)? {
+        sinks.push(Box::new(telegram_cfg));
+    }
+    if let Some(matrix_cfg) = build_matrix_config(cli, &credentials)? {
+        sinks.push(Box::new(matrix_cfg));
+    }
+    if let Some(xmpp_cfg) = build_xmpp_config(cli, &credentials)? {
+        sinks.push(Box::new(xmpp_cfg));
+    }
+    if let Some(signal_cfg) = build_signal_config(cli)? {
+        sinks.push(Box::new(signal_cfg));
+    }
+    if let Some(webhook_cfg) = build_webhook_config(cli)? {
+        sinks.push(Box::new(webhook_cfg));
+    }
+    for webhook in load_notification_webhooks(&inputs.files)? {
+        let secret = webhook
+            .secret
+            .map(|source| {
+                source.reveal().with_context(|| {
+                    format!("Failed to read the webhook signing secret from {source}")
+                })
+            })
+            .transpose()?;
+        sinks.push(Box::new(JsonWebhookConfig {
+            url: webhook.url,
+            headers: webhook.headers,
+            secret,
+            signature_header: webhook.signature_header,
+            retries: webhook.retries,
+            tunnel: cli.notify_via.clone(),
+        }));
+    }
+    if let Some(url) = &cli.teams_webhook {
+        sinks.push(Box::new(TeamsConfig {
+            url: url.clone(),
+            tunnel: cli.notify_via.clone(),
+        }));
+    }
+    if let Some(url) = &cli.mattermost_webhook {
+        sinks.push(Box::new(MattermostConfig {
+            url: url.clone(),
+            channel: cli.mattermost_channel.clone(),
+            tunnel: cli.notify_via.clone(),
+        }));
+    }
+    let discord_routes = discord_routes(cli, &inputs.configs, results);
+    if sinks.is_empty() && discord_routes.is_empty() {
+        debug!("No notification channel is configured");
+        return Ok(());
+    }
+
+    let dedup_window = cli
+        .notify_dedup_window
+        .map(|window| {
+            chrono::Duration::from_std(window).context("--notify-dedup-window is too long")
+        })
+        .transpose()?;
+
+    let (results, run) = (&*redact::results(results), &*redact::run(run));
+    let now = Utc::now();
+    let events = alerting::evaluate(results, state.as_deref(), now);
+    debug!(events = events.len(), "Evaluated alert events");
+    let template = inputs.template.as_ref();
+    let summary = template
+        .map(|template| template.render(results, run))
+        .transpose()?;
+    let channel_templates = load_notification_templates(&inputs.files)?
+        .iter()
+        .map(|(channel, settings)| Ok((channel.clone(), ChannelTemplate::load(channel, settings)?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    let mut failures = Vec::new();
+    for sink in &sinks {
+        let (subject, body) = channel_templates
+            .get(sink.channel())
+            .map(|template| template.render(results, run))
+            .transpose()?
+            .unwrap_or_default();
+        let report = AlertReport {
+            run,
+            results,
+            events: &events,
+            recoveries,
+            headline: headline.clone(),
+            summary: body.as_deref().or(summary.as_deref()),
+            subject: subject.as_deref(),
+            only_alerts: cli.only_alerts,
+        };
+        if let Err(err) = deliver_deduplicated(
+            cli,
+            state.as_deref_mut(),
+            dedup_window,
+            now,
+            sink.as_ref(),
+            &report,
+        ) {
+            failures.push(format!("{}: {err:#}", sink.channel()));
+        }
+    }
+
+    // Each Discord webhook hears only about the lines routed to it.
+    for (url, indices) in discord_routes {
+        let routed: Vec<LineResult> = indices
+            .iter()
+            .map(|&index| results[index].clone())
+            .collect();
+        let lines: Vec<String> = routed.iter().map(LineResult::key).collect();
+        let routed_events: Vec<_> = events
+            .iter()
+            .filter(|event| lines.contains(&event.line))
+            .cloned()
+            .collect();
+        let routed_recoveries: Vec<_> = recoveries
+            .iter()
+            .filter(|recovery| lines.contains(&recovery.line))
+            .cloned()
+            .collect();
+        let summary = template
+            .map(|template| template.render(&routed, run))
+            .transpose()?;
+        let (subject, body) = channel_templates
+            .get("discord")
+            .map(|template| template.render(&routed, run))
+            .transpose()?
+            .unwrap_or_default();
+        let report = AlertReport {
+            run,
+            results: &routed,
+            events: &routed_events,
+            recoveries: &routed_recoveries,
+            headline: headline.clone(),
+            summary: body.as_deref().or(summary.as_deref()),
+            subject: subject.as_deref(),
+            only_alerts: cli.only_alerts,
+        };
+        let sink = DiscordConfig {
+            url,
+            tunnel: cli.notify_via.clone(),
+        };
+        if let Err(err) =
+            deliver_deduplicated(cli, state.as_deref_mut(), dedup_window, now, &sink, &report)
+        {
+            failures.push(format!("{}: {err:#}", sink.channel()));
+        }
+    }
+
+    // A failing channel must not keep the others from hearing about the run.
+    if !failures.is_empty() {
+        anyhow::bail!("Notification delivery failed: {}", failures.join("; "));
+    }
     Ok(())
 }
 
+/// Indices of `results` grouped by the Discord webhook that should hear
+/// about them: the line's own `discord_webhook` in the config the run
+/// loaded, else `--discord-webhook`.
+fn discord_routes(
+    cli: &Cli,
+    configs: &[(PathBuf, Config)],
+    results: &[LineResult],
+) -> BTreeMap<String, Vec<usize>> {
+    let batched = configs.len() > 1;
+    let mut own = BTreeMap::new();
+    for (path, config) in configs {
+        for line in &config.lines {
+            let Some(url) = &line.discord_webhook else {
+                continue;
+            };
+            let key = match batched {
+                true => format!("{}/{}", batch_name(path), line.name),
+                false => line.name.clone(),
+            };
+            own.insert(key, url);
+        }
+    }
+    let mut routes: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    if own.is_empty() && cli.discord_webhook.is_none() {
+        return routes;
+    }
+    for (index, result) in results.iter().enumerate() {
+        let url = own
+            .get(&result.key())
+            .copied()
+            .or(cli.discord_webhook.as_ref());
+        if let Some(url) = url {
+            routes.entry(url.clone()).or_default().push(index);
+        }
+    }
+    routes
+}
+
+/// Deliver `report` through `sink` unless, within `window`, the same alerts
+/// already went to that channel and destination.
+fn deliver_deduplicated(
+    cli: &Cli,
+    mut state: Option<&mut AlertState>,
+    window: Option<chrono::Duration>,
+    now: DateTime<Utc>,
+    sink: &dyn AlertSink,
+    report: &AlertReport<'_>,
+) -> Result<()> {
+    let sent_key = format!("{} {}", sink.channel(), sink.recipient_key());
+    let alerts = alerting::alert_keys(report.events);
+    if let (Some(window), Some(state)) = (window, state.as_deref()) {
+        if report.headline.is_none()
+            && report.recoveries.is_empty()
+            && state.is_repeat(&sent_key, &alerts, window, now)
+        {
+            cli.note(&format!(
+                "Notification via {} to {} held back: same alerts as last sent",
+                sink.channel(),
+                sink.destination()
+            ));
+            return Ok(());
+        }
+    }
+    deliver(state.as_deref_mut(), sink.channel(), || {
+        sink.deliver(report)
+    })?;
+    if let (Some(_), Some(state)) = (window, state) {
+        state.record_sent(&sent_key, alerts, now);
+    }
+    cli.note(&format!(
+        "Notification dispatched via {} to {}",
+        sink.channel(),
+        sink.destination()
+    ));
+    Ok(())
+}
+
+/// Run one channel delivery, recording attempt, outcome and latency when state is enabled.
+fn deliver(
+    state: Option<&mut AlertState>,
+    channel: &str,
+    send: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    debug!(channel, "Sending notification");
+    let started = Instant::now();
+    // Channels send with blocking I/O, and webhooks back off between
+    // retries; keep the runtime's listeners and schedule running meanwhile.
+    let outcome = tokio::task::block_in_place(send);
+    match &outcome {
+        Ok(()) => debug!(channel, elapsed_ms = ?started.elapsed().as_millis(), "Notification sent"),
+        Err(err) => debug!(channel, error = %format!("{err:#}"), "Notification failed"),
+    }
+    if let Some(state) = state {
+        let recorded = outcome
+            .as_ref()
+            .map(|_| ())
+            .map_err(|err| format!("{err:#}"));
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        state
+            .channel_mut(channel)
+            .record(recorded, elapsed_ms, Utc::now());
+    }
+    outcome
+}
+
 /// Validate and construct email notification configuration when requested.
-fn build_email_config(cli: &Cli) -> Result<Option<EmailConfig>> {
+fn build_email_config(cli: &Cli, credentials: &Credentials) -> Result<Option<EmailConfig>> {
+    let password = cli.email_password();
     let email_requested = cli.email_smtp.is_some()
         || cli.email_username.is_some()
-        || cli.email_password.is_some()
+        || password.is_some()
         || cli.email_from.is_some()
         || !cli.email_to.is_empty();
 
@@ -121,32 +1770,228 @@ fn build_email_config(cli: &Cli) -> Result<Option<EmailConfig>> {
         );
     }
 
+    let password = password
+        .or_else(|| credentials.email_password.clone())
+        .map(|source| {
+            source
+                .reveal()
+                .with_context(|| format!("Failed to read the SMTP password from {source}"))
+        })
+        .transpose()?;
+
+    // `[run] history` has been merged into `cli.history` by now.
+    let graphs = match cli.email_graphs {
+        Some(window) => Some(EmailGraphs {
+            history: cli.history.clone().context(
+                "--email-graphs needs a history file; pass --history or set history under [run]",
+            )?,
+            window,
+        }),
+        None => None,
+    };
+
     Ok(Some(EmailConfig {
         smtp_server: smtp,
         username: cli.email_username.clone(),
-        password: cli.email_password.clone(),
+        password,
         from,
         to: cli.email_to.clone(),
+        tunnel: cli.notify_via.clone(),
+        graphs,
+        attach_json: cli.email_attach_json,
     }))
 }
 
 /// Validate and construct Telegram notification configuration when requested.
-fn build_telegram_config(cli: &Cli) -> Result<Option<TelegramConfig>> {
-    let telegram_requested = cli.telegram_token.is_some() || cli.telegram_chat_id.is_some();
+fn build_telegram_config(cli: &Cli, credentials: &Credentials) -> Result<Option<TelegramConfig>> {
+    let token = cli.telegram_token();
+    let telegram_requested = token.is_some() || cli.telegram_chat_id.is_some();
     if !telegram_requested {
         return Ok(None);
     }
 
-    let token = cli
-        .telegram_token
-        .as_ref()
-        .context("Telegram bot token required when enabling Telegram notifications")?
-        .clone();
+    let source = token
+        .or_else(|| credentials.telegram_token.clone())
+        .context("Telegram bot token required when enabling Telegram notifications")?;
+    let token = source
+        .reveal()
+        .with_context(|| format!("Failed to read the Telegram bot token from {source}"))?;
     let chat_id = cli
         .telegram_chat_id
         .as_ref()
         .context("Telegram chat ID required when enabling Telegram notifications")?
         .clone();
 
-    Ok(Some(TelegramConfig { token, chat_id }))
+    Ok(Some(TelegramConfig {
+        token,
+        chat_id,
+        tunnel: cli.notify_via.clone(),
+    }))
+}
+
+/// Validate and construct Matrix notification configuration when requested.
+fn build_matrix_config(cli: &Cli, credentials: &Credentials) -> Result<Option<MatrixConfig>> {
+    let token = cli.matrix_token();
+    let matrix_requested =
+        token.is_some() || cli.matrix_homeserver.is_some() || cli.matrix_room.is_some();
+    if !matrix_requested {
+        return Ok(None);
+    }
+
+    let homeserver = cli
+        .matrix_homeserver
+        .as_ref()
+        .context("Matrix homeserver required when enabling Matrix notifications")?;
+    if !(homeserver.starts_with("http://") || homeserver.starts_with("https://")) {
+        anyhow::bail!("--matrix-homeserver must be an http(s) URL");
+    }
+    let room_id = cli
+        .matrix_room
+        .as_ref()
+        .context("Matrix room ID required when enabling Matrix notifications")?
+        .clone();
+    if !room_id.starts_with('!') {
+        anyhow::bail!("--matrix-room takes a room ID such as !abc123:example.org, not an alias");
+    }
+    let source = token
+        .or_else(|| credentials.matrix_token.clone())
+        .context("Matrix access token required when enabling Matrix notifications")?;
+    let token = source
+        .reveal()
+        .with_context(|| format!("Failed to read the Matrix access token from {source}"))?;
+
+    Ok(Some(MatrixConfig {
+        homeserver: homeserver.trim_end_matches('/').to_string(),
+        room_id,
+        token,
+        tunnel: cli.notify_via.clone(),
+    }))
+}
+
+/// Validate and construct XMPP notification configuration when requested.
+fn build_xmpp_config(cli: &Cli, credentials: &Credentials) -> Result<Option<XmppConfig>> {
+    let password = cli.xmpp_password();
+    let xmpp_requested = password.is_some()
+        || cli.xmpp_jid.is_some()
+        || !cli.xmpp_to.is_empty()
+        || !cli.xmpp_room.is_empty();
+    if !xmpp_requested {
+        return Ok(None);
+    }
+
+    let jid = cli
+        .xmpp_jid
+        .as_ref()
+        .context("XMPP JID required when enabling XMPP notifications")?
+        .clone();
+    xmpp::split_jid(&jid)?;
+    let recipients: Vec<xmpp::Recipient> = cli
+        .xmpp_to
+        .iter()
+        .map(|to| xmpp::Recipient::Chat(to.clone()))
+        .chain(cli.xmpp_room.iter().map(|room| xmpp::Recipient::Room {
+            jid: room.clone(),
+            nick: cli.xmpp_nick.clone(),
+        }))
+        .collect();
+    if recipients.is_empty() {
+        anyhow::bail!(
+            "At least one --xmpp-to or --xmpp-room required when enabling XMPP notifications"
+        );
+    }
+    let source = password
+        .or_else(|| credentials.xmpp_password.clone())
+        .context("XMPP password required when enabling XMPP notifications")?;
+    let password = source
+        .reveal()
+        .with_context(|| format!("Failed to read the XMPP password from {source}"))?;
+
+    Ok(Some(XmppConfig {
+        jid,
+        password,
+        server: cli.xmpp_server.clone(),
+        recipients,
+        tunnel: cli.notify_via.clone(),
+    }))
+}
+
+/// Validate and construct Signal notification configuration when requested.
+fn build_signal_config(cli: &Cli) -> Result<Option<SignalConfig>> {
+    let signal_requested = cli.signal_account.is_some()
+        || cli.signal_group.is_some()
+        || cli.signal_cli.is_some()
+        || cli.signal_rpc_url.is_some();
+    if !signal_requested {
+        return Ok(None);
+    }
+
+    let account = cli
+        .signal_account
+        .as_ref()
+        .context("Signal account required when enabling Signal notifications")?
+        .clone();
+    if !account.starts_with('+') {
+        anyhow::bail!("--signal-account takes the account's phone number, e.g. +391234567890");
+    }
+    let group = cli
+        .signal_group
+        .as_ref()
+        .context("Signal group ID required when enabling Signal notifications")?
+        .clone();
+    let transport = match &cli.signal_rpc_url {
+        Some(url) => {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                anyhow::bail!("--signal-rpc-url must be an http(s) URL");
+            }
+            SignalTransport::JsonRpc(url.clone())
+        }
+        None => SignalTransport::Command(
+            cli.signal_cli
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("signal-cli")),
+        ),
+    };
+
+    Ok(Some(SignalConfig {
+        account,
+        group,
+        transport,
+        tunnel: cli.notify_via.clone(),
+    }))
+}
+
+/// Validate and construct webhook notification configuration when requested.
+fn build_webhook_config(cli: &Cli) -> Result<Option<WebhookConfig>> {
+    let Some(url) = cli.webhook_url.clone() else {
+        return Ok(None);
+    };
+
+    let template = match (cli.webhook_format, &cli.webhook_template) {
+        (WebhookFormat::Template, Some(path)) => {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read webhook template {}", path.display()))?;
+            let template = serde_json::from_str(&contents).with_context(|| {
+                format!("Webhook template {} is not valid JSON", path.display())
+            })?;
+            Some(template)
+        }
+        (WebhookFormat::Template, None) => {
+            anyhow::bail!("--webhook-template required with --webhook-format template")
+        }
+        (_, Some(_)) => {
+            anyhow::bail!("--webhook-template only applies to --webhook-format template")
+        }
+        (_, None) => None,
+    };
+    if cli.webhook_format == WebhookFormat::Pagerduty && cli.webhook_routing_key.is_none() {
+        anyhow::bail!("--webhook-routing-key required with --webhook-format pagerduty");
+    }
+
+    Ok(Some(WebhookConfig {
+        url,
+        format: cli.webhook_format,
+        template,
+        routing_key: cli.webhook_routing_key.clone(),
+        tunnel: cli.notify_via.clone(),
+    }))
 }
@@ -0,0 +1,422 @@
+//! Prometheus text exposition of a run's results, for node_exporter's
+//! textfile collector or the `/metrics` endpoint of `serve` and `watch`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+
+use crate::report::RunMetadata;
+use crate::runner::{LineResult, LineStatus};
+use crate::state::{ChannelStats, write_atomically};
+
+/// Render `results` as Prometheus gauges labelled with each line's key and
+/// target, followed by the delivery statistics of each notification
+/// channel. Values a line did not measure are left out.
+pub fn render(
+    results: &[LineResult],
+    run: &RunMetadata,
+    channels: &BTreeMap<String, ChannelStats>,
+) -> String {
+    let mut out = String::new();
+    let labels: Vec<String> = results
+        .iter()
+        .map(|result| {
+            format!(
+                "line=\"{}\",target=\"{}\"",
+                escape(&result.key()),
+                escape(&result.target)
+            )
+        })
+        .collect();
+    let mut gauge = |name: &str, help: &str, samples: Vec<(String, f64)>| {
+        if samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        for (labels, value) in samples {
+            if labels.is_empty() {
+                let _ = writeln!(out, "{name} {value}");
+            } else {
+                let _ = writeln!(out, "{name}{{{labels}}} {value}");
+            }
+        }
+    };
+    let per_line = |value: &dyn Fn(&LineResult) -> Option<f64>| -> Vec<(String, f64)> {
+        results
+            .iter()
+            .zip(&labels)
+            .filter_map(|(result, labels)| Some((labels.clone(), value(result)?)))
+            .collect()
+    };
+
+    gauge(
+        "icmpmolester_packet_loss_percent",
+        "Packet loss of the line's last ping.",
+        per_line(&|result| Some(f64::from(result.ping()?.packet_loss_pct?))),
+    );
+    gauge(
+        "icmpmolester_latency_seconds",
+        "Average round-trip time of the line's last ping.",
+        per_line(&|result| Some(f64::from(result.ping()?.average_latency_ms?) / 1000.0)),
+    );
+    gauge(
+        "icmpmolester_jitter_seconds",
+        "Round-trip time deviation of the line's last ping.",
+        per_line(&|result| Some(f64::from(result.ping()?.jitter_ms?) / 1000.0)),
+    );
+    gauge(
+        "icmpmolester_hop_count",
+        "Hops to the line's target in its last traceroute.",
+        per_line(&|result| Some(f64::from(result.traceroute()?.hop_count?))),
+    );
+    gauge(
+        "icmpmolester_line_duration_seconds",
+        "How long the line's checks took.",
+        per_line(&|result| Some(f64::from(result.duration_ms?) / 1000.0)),
+    );
+    let statuses = results
+        .iter()
+        .zip(&labels)
+        .flat_map(|(result, labels)| {
+            let current = result.status();
+            LineStatus::ALL.iter().map(move |status| {
+                (
+                    format!("{labels},status=\"{}\"", status.as_str()),
+                    f64::from(u8::from(*status == current)),
+                )
+            })
+        })
+        .collect();
+    gauge(
+        "icmpmolester_line_status",
+        "1 for the line's current status, 0 for the others.",
+        statuses,
+    );
+    gauge(
+        "icmpmolester_last_run_timestamp_seconds",
+        "When the last run finished, as a Unix timestamp.",
+        vec![(String::new(), run.finished_at.timestamp() as f64)],
+    );
+    gauge(
+        "icmpmolester_run_duration_seconds",
+        "How long the last run took.",
+        vec![(String::new(), run.duration_secs())],
+    );
+    render_channels(&mut out, channels);
+    out
+}
+
+/// Append per-channel notification delivery series, as recorded in the
+/// alert state.
+fn render_channels(out: &mut String, channels: &BTreeMap<String, ChannelStats>) {
+    let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, f64)>| {
+        if samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{name}{{{labels}}} {value}");
+        }
+    };
+    let per_channel = |value: &dyn Fn(&ChannelStats) -> Option<f64>| -> Vec<(String, f64)> {
+        channels
+            .iter()
+            .filter_map(|(channel, stats)| {
+                Some((format!("channel=\"{}\"", escape(channel)), value(stats)?))
+            })
+            .collect()
+    };
+
+    family(
+        "icmpmolester_notification_attempts_total",
+        "counter",
+        "Notifications the channel tried to deliver.",
+        per_channel(&|stats| Some(stats.attempts as f64)),
+    );
+    family(
+        "icmpmolester_notification_failures_total",
+        "counter",
+        "Notifications the channel failed to deliver.",
+        per_channel(&|stats| Some(stats.failures as f64)),
+    );
+    family(
+        "icmpmolester_notification_last_success_timestamp_seconds",
+        "gauge",
+        "When the channel last delivered a notification, as a Unix timestamp.",
+        per_channel(&|stats| Some(stats.last_success?.timestamp() as f64)),
+    );
+    family(
+        "icmpmolester_notification_send_seconds",
+        "gauge",
+        "Mean time the channel spent per delivery attempt.",
+        per_channel(&|stats| Some(stats.average_send_ms()? / 1000.0)),
+    );
+}
+
+/// Replace the textfile at `path` with this run's metrics. The file is
+/// written under a temporary name first, so the collector never reads half
+/// of it.
+pub fn write_textfile(
+    path: &Path,
+    results: &[LineResult],
+    run: &RunMetadata,
+    channels: &BTreeMap<String, ChannelStats>,
+) -> Result<()> {
+    write_atomically(path, render(results, run, channels).as_bytes())
+        .with_context(|| format!("Failed to write metrics to {}", path.display()))
+}
+
+/// Process-wide metrics of the runs so far, served at `/metrics`.
+pub fn exporter() -> &'static Exporter {
+    static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+    EXPORTER.get_or_init(Exporter::default)
+}
+
+/// Each line's latest gauges plus counters over every run of the process.
+#[derive(Default)]
+pub struct Exporter {
+    inner: Mutex<ExporterState>,
+}
+
+#[derive(Default)]
+struct ExporterState {
+    /// The latest result of every line seen so far, by line key, so lines
+    /// that were not due in the last run keep their gauges.
+    lines: BTreeMap<String, LineResult>,
+    /// The last run, for the run gauges.
+    run: Option<RunMetadata>,
+    /// Delivery statistics of the notification channels, as last recorded.
+    channels: BTreeMap<String, ChannelStats>,
+    runs: u64,
+    failed_runs: u64,
+    /// Execution errors per line key.
+    line_errors: BTreeMap<String, u64>,
+}
+
+impl Exporter {
+    /// Update the gauges of the lines a finished run reported and count its
+    /// errors. Without `channels`, the channel statistics recorded earlier
+    /// are kept.
+    pub fn record_run(
+        &self,
+        results: &[LineResult],
+        run: &RunMetadata,
+        channels: Option<&BTreeMap<String, ChannelStats>>,
+    ) {
+        let mut state = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        for result in results {
+            state.lines.insert(result.key(), result.clone());
+        }
+        state.run = Some(run.clone());
+        if let Some(channels) = channels {
+            state.channels = channels.clone();
+        }
+        state.runs += 1;
+        for result in results.iter().filter(|result| result.error.is_some()) {
+            *state.line_errors.entry(result.key()).or_default() += 1;
+        }
+    }
+
+    /// Count a run that failed before it produced results.
+    pub fn record_failed_run(&self) {
+        let mut state = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        state.failed_runs += 1;
+    }
+
+    /// The exposition served at `/metrics`.
+    pub fn render(&self) -> String {
+        let state = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+        let mut out = match &state.run {
+            Some(run) => {
+                let lines: Vec<LineResult> = state.lines.values().cloned().collect();
+                render(&lines, run, &state.channels)
+            }
+            None => String::new(),
+        };
+        let mut counter = |name: &str, help: &str, samples: Vec<(String, u64)>| {
+            if samples.is_empty() {
+                return;
+            }
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            for (labels, value) in samples {
+                if labels.is_empty() {
+                    let _ = writeln!(out, "{name} {value}");
+                } else {
+                    let _ = writeln!(out, "{name}{{{labels}}} {value}");
+                }
+            }
+        };
+        counter(
+            "icmpmolester_runs_total",
+            "Runs that finished since the process started.",
+            vec![(String::new(), state.runs)],
+        );
+        counter(
+            "icmpmolester_failed_runs_total",
+            "Runs that failed before reporting any line.",
+            vec![(String::new(), state.failed_runs)],
+        );
+        counter(
+            "icmpmolester_line_errors_total",
+            "Runs in which the line's checks could not be executed.",
+            state
+                .line_errors
+                .iter()
+                .map(|(line, count)| (format!("line=\"{}\"", escape(line)), *count))
+                .collect(),
+        );
+        out
+    }
+}
+
+/// Escape a label value: backslashes, double quotes and line breaks.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckOutcome;
+    use crate::runner;
+    use chrono::Utc;
+
+    fn sample_result() -> LineResult {
+        let mut result = runner::tests::sample_result(
+            "Primary \"FTTH\"",
+            true,
+            Some(20.0),
+            Some(12.5),
+            1.0,
+            None,
+        );
+        if let CheckOutcome::Ping { report, .. } = &mut result.checks[0] {
+            report.jitter_ms = Some(1.5);
+        }
+        result
+    }
+
+    #[test]
+    fn renders_gauges_per_line() {
+        let now = Utc::now();
+        let text = render(
+            &[sample_result()],
+            &RunMetadata::new(now, now),
+            &BTreeMap::new(),
+        );
+        let labels = r#"line="Primary \"FTTH\"",target="10.0.0.1""#;
+        assert!(text.contains("# TYPE icmpmolester_packet_loss_percent gauge\n"));
+        assert!(text.contains(&format!(
+            "icmpmolester_packet_loss_percent{{{labels}}} 20\n"
+        )));
+        assert!(text.contains(&format!(
+            "icmpmolester_latency_seconds{{{labels}}} 0.0125\n"
+        )));
+        assert!(text.contains(&format!("icmpmolester_jitter_seconds{{{labels}}} 0.0015\n")));
+        assert!(text.contains(&format!(
+            "icmpmolester_line_status{{{labels},status=\"ALERT\"}} 1\n"
+        )));
+        assert!(text.contains(&format!(
+            "icmpmolester_line_status{{{labels},status=\"OK\"}} 0\n"
+        )));
+        // Nothing was traced, and the line's duration was not measured.
+        assert!(!text.contains("icmpmolester_hop_count"));
+        assert!(!text.contains("icmpmolester_line_duration_seconds"));
+        assert!(text.contains("icmpmolester_run_duration_seconds 0\n"));
+        // No notification was ever attempted.
+        assert!(!text.contains("icmpmolester_notification_"));
+    }
+
+    #[test]
+    fn renders_notification_channel_stats() {
+        let now = Utc::now();
+        let mut telegram = ChannelStats::default();
+        telegram.record(Ok(()), 250.0, now);
+        telegram.record(Err("timed out".into()), 750.0, now);
+        let mut email = ChannelStats::default();
+        email.record(Err("connection refused".into()), 100.0, now);
+        let channels = BTreeMap::from([("email".into(), email), ("telegram".into(), telegram)]);
+
+        let text = render(&[], &RunMetadata::new(now, now), &channels);
+        assert!(text.contains("# TYPE icmpmolester_notification_attempts_total counter\n"));
+        assert!(
+            text.contains("icmpmolester_notification_attempts_total{channel=\"telegram\"} 2\n")
+        );
+        assert!(
+            text.contains("icmpmolester_notification_failures_total{channel=\"telegram\"} 1\n")
+        );
+        assert!(text.contains("icmpmolester_notification_failures_total{channel=\"email\"} 1\n"));
+        assert!(text.contains(&format!(
+            "icmpmolester_notification_last_success_timestamp_seconds{{channel=\"telegram\"}} {}\n",
+            now.timestamp()
+        )));
+        assert!(
+            text.contains("icmpmolester_notification_send_seconds{channel=\"telegram\"} 0.5\n")
+        );
+        // Email never got through.
+        assert!(!text.contains("last_success_timestamp_seconds{channel=\"email\"}"));
+    }
+
+    #[test]
+    fn exporter_counts_runs_and_line_errors() {
+        let exporter = Exporter::default();
+        assert!(exporter.render().contains("icmpmolester_runs_total 0\n"));
+        assert!(!exporter.render().contains("icmpmolester_line_errors_total"));
+
+        let mut errored = sample_result();
+        errored.error = Some("ping not found".into());
+        let now = Utc::now();
+        let mut webhook = ChannelStats::default();
+        webhook.record(Ok(()), 100.0, now);
+        let channels = BTreeMap::from([("webhook".into(), webhook)]);
+        exporter.record_run(&[errored], &RunMetadata::new(now, now), Some(&channels));
+        exporter.record_run(&[sample_result()], &RunMetadata::new(now, now), None);
+        exporter.record_failed_run();
+
+        let text = exporter.render();
+        assert!(text.contains("# TYPE icmpmolester_runs_total counter\n"));
+        assert!(text.contains("icmpmolester_runs_total 2\n"));
+        assert!(text.contains("icmpmolester_failed_runs_total 1\n"));
+        assert!(text.contains(r#"icmpmolester_line_errors_total{line="Primary \"FTTH\""} 1"#));
+        assert!(text.contains("icmpmolester_packet_loss_percent{"));
+        // A run that recorded no channels keeps the earlier statistics.
+        assert!(text.contains("icmpmolester_notification_attempts_total{channel=\"webhook\"} 1\n"));
+    }
+
+    #[test]
+    fn exporter_keeps_lines_that_were_not_due() {
+        let exporter = Exporter::default();
+        let now = Utc::now();
+        let backup = runner::tests::sample_result("Backup", false, Some(0.0), None, 1.0, None);
+        exporter.record_run(
+            &[sample_result(), backup],
+            &RunMetadata::new(now, now),
+            None,
+        );
+        let mut recovered = sample_result();
+        if let CheckOutcome::Ping { report, .. } = &mut recovered.checks[0] {
+            report.packet_loss_pct = Some(0.0);
+        }
+        exporter.record_run(&[recovered], &RunMetadata::new(now, now), None);
+
+        let text = exporter.render();
+        assert!(text.contains(
+            r#"icmpmolester_packet_loss_percent{line="Primary \"FTTH\"",target="10.0.0.1"} 0"#
+        ));
+        assert!(text.contains(r#"icmpmolester_line_status{line="Backup","#));
+        assert_eq!(
+            text.matches("# TYPE icmpmolester_packet_loss_percent")
+                .count(),
+            1
+        );
+    }
+}
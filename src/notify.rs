@@ -1,9 +1,39 @@
-//! Notification helpers for email and Telegram delivery.
+//! Notification helpers for email, Telegram and webhook delivery.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, anyhow};
-use lettre::message::Mailbox;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart};
 use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
 use lettre::{Message, SmtpTransport, Transport};
+use ring::hmac;
+use serde_json::{Value, json};
+use tracing::debug;
+
+use crate::alerting::{
+    AlertEvent, AlertReport, AlertSink, Recovery, Severity, format_events, format_recoveries,
+};
+use crate::graph;
+use crate::raw_output;
+use crate::report::{RunReport, markdown_table};
+use crate::runner::{
+    LineResult, LineStatus, Rollup, format_compact_summary, format_summary, hidden_lines,
+    needs_attention, summary_rows,
+};
+use crate::tunnel::Tunnel;
+use crate::xmpp;
+
+/// `SmtpTransport::relay` connects with implicit TLS on the submissions port.
+pub const SMTP_RELAY_PORT: u16 = 465;
+pub const TELEGRAM_API_HOST: &str = "api.telegram.org";
 
 /// Runtime configuration required to deliver email notifications.
 pub struct EmailConfig {
@@ -12,30 +42,1278 @@ pub struct EmailConfig {
     pub password: Option<String>,
     pub from: String,
     pub to: Vec<String>,
+    /// Reach the relay through this tunnel instead of directly.
+    pub tunnel: Option<Tunnel>,
+    /// Attach latency and loss charts from history, with `--email-graphs`.
+    pub graphs: Option<EmailGraphs>,
+    /// Attach the JSON result of lines in ALERT next to their raw output.
+    pub attach_json: bool,
+}
+
+/// History charted into PNG attachments for each line in the report.
+pub struct EmailGraphs {
+    pub history: PathBuf,
+    /// How far back each chart reaches.
+    pub window: Duration,
 }
 
 /// Runtime configuration required to deliver Telegram notifications.
 pub struct TelegramConfig {
     pub token: String,
     pub chat_id: String,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Runtime configuration required to deliver Discord notifications.
+pub struct DiscordConfig {
+    /// Webhook URL, `https://discord.com/api/webhooks/<id>/<token>`.
+    pub url: String,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Runtime configuration required to deliver Microsoft Teams notifications.
+pub struct TeamsConfig {
+    /// Incoming webhook or Workflows URL; both carry their secret in the URL.
+    pub url: String,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Runtime configuration required to deliver Mattermost notifications.
+pub struct MattermostConfig {
+    /// Incoming webhook URL, `https://<server>/hooks/<key>`.
+    pub url: String,
+    /// Channel to post in instead of the webhook's own, e.g. `noc-alerts`.
+    pub channel: Option<String>,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Runtime configuration required to deliver the JSON run report to a
+/// `[[notifications.webhook]]`.
+pub struct JsonWebhookConfig {
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+    /// HMAC-SHA256 key the body is signed with.
+    pub secret: Option<String>,
+    pub signature_header: String,
+    /// Further attempts after a connection error, 429 or 5xx response.
+    pub retries: u32,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Runtime configuration required to deliver Matrix notifications.
+pub struct MatrixConfig {
+    /// Homeserver base URL, without a trailing slash.
+    pub homeserver: String,
+    /// Room ID such as `!abc123:example.org`; the account must have joined it.
+    pub room_id: String,
+    pub token: String,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Runtime configuration required to deliver XMPP notifications.
+pub struct XmppConfig {
+    /// Bare JID the probe logs in as, e.g. `probe@example.org`.
+    pub jid: String,
+    pub password: String,
+    /// `host[:port]` to connect to instead of the JID's domain.
+    pub server: Option<String>,
+    pub recipients: Vec<xmpp::Recipient>,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Runtime configuration required to deliver Signal notifications.
+pub struct SignalConfig {
+    /// Phone number of the account registered with signal-cli.
+    pub account: String,
+    /// Base64 group ID, as listed by `signal-cli listGroups`.
+    pub group: String,
+    pub transport: SignalTransport,
+    /// Reach the JSON-RPC endpoint through this tunnel instead of directly.
+    pub tunnel: Option<Tunnel>,
+}
+
+/// How to reach signal-cli.
+pub enum SignalTransport {
+    /// Run this signal-cli executable for every message.
+    Command(PathBuf),
+    /// POST to the JSON-RPC endpoint of `signal-cli daemon --http`, e.g.
+    /// `http://127.0.0.1:8080/api/v1/rpc`.
+    JsonRpc(String),
+}
+
+/// Runtime configuration required to deliver webhook notifications.
+pub struct WebhookConfig {
+    pub url: String,
+    pub format: WebhookFormat,
+    /// Parsed JSON template, required by [`WebhookFormat::Template`].
+    pub template: Option<Value>,
+    /// PagerDuty integration key, required by [`WebhookFormat::Pagerduty`].
+    pub routing_key: Option<String>,
+    pub tunnel: Option<Tunnel>,
+}
+
+/// Payload shape POSTed by the webhook sink.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WebhookFormat {
+    /// One JSON object with the overall status, every alert and the text summary.
+    Flat,
+    /// An array of alerts for Alertmanager's `/api/v2/alerts` endpoint.
+    Alertmanager,
+    /// One PagerDuty Events API v2 `trigger` per alert.
+    Pagerduty,
+    /// A user-supplied JSON document with placeholders filled in.
+    Template,
+}
+
+impl AlertSink for EmailConfig {
+    fn channel(&self) -> &'static str {
+        "email"
+    }
+
+    fn destination(&self) -> String {
+        self.to.join(", ")
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let mut attachments =
+            raw_output::alert_attachments(report.results, report.run, self.attach_json)?;
+        let mut graph_error = None;
+        if let Some(graphs) = &self.graphs {
+            let lines: Vec<String> = report
+                .results
+                .iter()
+                .filter(|result| !report.only_alerts || needs_attention(result))
+                .map(LineResult::key)
+                .collect();
+            // A report without its charts beats no report at all.
+            match graph::png_attachments(&graphs.history, graphs.window, &lines) {
+                Ok(charts) => attachments.extend(charts),
+                Err(err) => graph_error = Some(format!("Graphs could not be drawn: {err:#}")),
+            }
+        }
+        let mut body = text_summary(report);
+        if let Some(error) = &graph_error {
+            body.push_str(&format!("\n{error}\n"));
+        }
+        let html = email_html(report, graph_error.as_deref());
+        let subject = report.subject.unwrap_or("ICMPMolester report");
+        send_email(subject, &body, &html, &attachments, self)
+    }
+}
+
+impl AlertSink for TelegramConfig {
+    fn channel(&self) -> &'static str {
+        "telegram"
+    }
+
+    fn destination(&self) -> String {
+        self.chat_id.clone()
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let summary = report
+            .summary
+            .map_or_else(|| format_compact_summary(report.results), str::to_string);
+        let body = with_headline(report, with_subject(report, summary));
+        send_telegram(&body, self)
+    }
+}
+
+impl AlertSink for WebhookConfig {
+    fn channel(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn destination(&self) -> String {
+        url_origin(&self.url)
+    }
+
+    fn recipient_key(&self) -> String {
+        url_digest(&self.url)
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let agent = http_agent(self.tunnel.as_ref());
+        for payload in webhook_payloads(self, report, Utc::now()) {
+            post_webhook(&agent, &self.url, payload)?;
+        }
+        Ok(())
+    }
+}
+
+impl AlertSink for DiscordConfig {
+    fn channel(&self) -> &'static str {
+        "discord"
+    }
+
+    fn destination(&self) -> String {
+        discord_destination(&self.url)
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let agent = http_agent(self.tunnel.as_ref());
+        for payload in discord_payloads(report) {
+            post_webhook(&agent, &self.url, payload)?;
+        }
+        Ok(())
+    }
+}
+
+/// A Discord webhook URL without its secret token, for logs.
+pub fn discord_destination(url: &str) -> String {
+    match url.rsplit_once('/') {
+        Some((base, _token)) if base.contains("/webhooks/") => base.to_string(),
+        _ => url.to_string(),
+    }
+}
+
+/// Discord's limits: characters of message text, of an embed title and of
+/// its description, embeds per message and characters across them.
+const DISCORD_CONTENT_LEN: usize = 2000;
+const DISCORD_TITLE_LEN: usize = 256;
+const DISCORD_DESCRIPTION_LEN: usize = 4096;
+const DISCORD_EMBEDS: usize = 10;
+const DISCORD_EMBED_CHARS: usize = 6000;
+
+/// Messages for one run: the rollup, alerts and recoveries as text, then one
+/// embed per line in its status color (one embed with the rendered
+/// `--report-template` instead, when there is one). Embeds that do not fit
+/// one message go into further ones.
+fn discord_payloads(report: &AlertReport<'_>) -> Vec<Value> {
+    let rollup = Rollup::of(report.results);
+    let mut content = format!("{}\n", title(report));
+    content.push_str(&format_events(report.events));
+    if let Some(hidden) = report
+        .only_alerts
+        .then(|| hidden_lines(report.results))
+        .flatten()
+    {
+        content.push_str(&format!("{hidden}\n"));
+    }
+    let content = with_headline(report, content);
+
+    let embeds: Vec<Value> = match report.summary {
+        Some(summary) => vec![json!({
+            "description": truncate(summary, DISCORD_DESCRIPTION_LEN),
+            "color": rollup.status.color().rgb(),
+        })],
+        None => report
+            .results
+            .iter()
+            .filter(|result| !report.only_alerts || needs_attention(result))
+            .map(|result| {
+                let title = format!(
+                    "{} {} ({}): {}",
+                    result.status_emoji(),
+                    result.name,
+                    result.target,
+                    result.display_status()
+                );
+                let mut embed = json!({
+                    "title": truncate(&title, DISCORD_TITLE_LEN),
+                    "color": result.status_color().rgb(),
+                });
+                let rows = summary_rows(result);
+                if !rows.is_empty() {
+                    embed["description"] =
+                        json!(truncate(&rows.join("\n"), DISCORD_DESCRIPTION_LEN));
+                }
+                embed
+            })
+            .collect(),
+    };
+
+    let mut messages: Vec<Vec<Value>> = vec![Vec::new()];
+    let mut chars = 0;
+    for embed in embeds {
+        let size = ["title", "description"]
+            .iter()
+            .filter_map(|field| embed[field].as_str())
+            .map(|text| text.chars().count())
+            .sum::<usize>();
+        let current = messages.last().expect("never empty");
+        if current.len() == DISCORD_EMBEDS
+            || (!current.is_empty() && chars + size > DISCORD_EMBED_CHARS)
+        {
+            messages.push(Vec::new());
+            chars = 0;
+        }
+        chars += size;
+        messages.last_mut().expect("never empty").push(embed);
+    }
+    messages
+        .into_iter()
+        .enumerate()
+        .map(|(index, embeds)| {
+            let mut payload = json!({ "username": "ICMPMolester", "embeds": embeds });
+            if index == 0 {
+                payload["content"] = json!(truncate(&content, DISCORD_CONTENT_LEN));
+            }
+            payload
+        })
+        .collect()
+}
+
+/// `text` cut to `max` characters, ending in `…` when shortened.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max - 1).collect();
+    cut.push('…');
+    cut
+}
+
+impl AlertSink for TeamsConfig {
+    fn channel(&self) -> &'static str {
+        "teams"
+    }
+
+    fn destination(&self) -> String {
+        url_origin(&self.url)
+    }
+
+    fn recipient_key(&self) -> String {
+        url_digest(&self.url)
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let agent = http_agent(self.tunnel.as_ref());
+        post_webhook(&agent, &self.url, teams_payload(report))
+    }
+}
+
+/// Scheme and host of `url`, leaving out a path or query that may hold a
+/// secret.
+fn url_origin(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("https", url));
+    let host = rest.split(['/', '?']).next().unwrap_or(rest);
+    format!("{scheme}://{host}")
+}
+
+/// Digest of the whole of `url`, telling apart webhooks on one host
+/// without keeping the secret in their path or query.
+fn url_digest(url: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, url.as_bytes());
+    let hex: String = digest.as_ref()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("{} #{hex}", url_origin(url))
+}
+
+/// Bytes Teams accepts in one message, with room for the envelope.
+const TEAMS_PAYLOAD_LEN: usize = 27_000;
+
+/// An Adaptive Card with the rollup as its title, one block per line in its
+/// status color (or the rendered `--report-template`), the alerts and
+/// recoveries, and the run. Lines that would push the card past what Teams
+/// accepts are counted at the end instead.
+fn teams_payload(report: &AlertReport<'_>) -> Value {
+    let rollup = Rollup::of(report.results);
+    let mut head = Vec::new();
+    if let Some(headline) = &report.headline {
+        head.push(text_block(headline));
+    }
+    head.push(json!({
+        "type": "TextBlock",
+        "text": title(report),
+        "size": "Large",
+        "weight": "Bolder",
+        "color": rollup.status.color().card_color(),
+        "wrap": true,
+    }));
+
+    let lines: Vec<Value> = match report.summary {
+        Some(summary) => vec![text_block(summary)],
+        None => report
+            .results
+            .iter()
+            .filter(|result| !report.only_alerts || needs_attention(result))
+            .map(|result| {
+                let mut items = vec![json!({
+                    "type": "TextBlock",
+                    "text": format!(
+                        "{} {} ({}): {}",
+                        result.status_emoji(),
+                        result.name,
+                        result.target,
+                        result.display_status()
+                    ),
+                    "weight": "Bolder",
+                    "color": result.status_color().card_color(),
+                    "wrap": true,
+                })];
+                items.extend(summary_rows(result).iter().map(|row| {
+                    json!({
+                        "type": "TextBlock",
+                        "text": row.trim(),
+                        "isSubtle": true,
+                        "spacing": "None",
+                        "wrap": true,
+                    })
+                }));
+                json!({ "type": "Container", "separator": true, "items": items })
+            })
+            .collect(),
+    };
+
+    let mut tail = Vec::new();
+    if let Some(hidden) = report
+        .only_alerts
+        .then(|| hidden_lines(report.results))
+        .flatten()
+    {
+        tail.push(text_block(&hidden));
+    }
+    for text in [
+        format_events(report.events),
+        format_recoveries(report.recoveries),
+    ] {
+        if !text.is_empty() {
+            tail.push(
+                json!({ "type": "Container", "separator": true, "items": [text_block(&text)] }),
+            );
+        }
+    }
+    tail.push(json!({
+        "type": "TextBlock",
+        "text": report.run.describe(),
+        "isSubtle": true,
+        "size": "Small",
+        "wrap": true,
+    }));
+
+    let card = |shown: &[Value], left_out: usize| {
+        let mut body = head.clone();
+        body.extend_from_slice(shown);
+        if left_out > 0 {
+            body.push(text_block(&format!("... and {left_out} more lines")));
+        }
+        body.extend(tail.iter().cloned());
+        json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": {
+                    "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                    "type": "AdaptiveCard",
+                    "version": "1.4",
+                    "msteams": { "width": "Full" },
+                    "body": body,
+                },
+            }],
+        })
+    };
+    let mut shown = lines.len();
+    loop {
+        let payload = card(&lines[..shown], lines.len() - shown);
+        if shown == 0 || payload.to_string().len() <= TEAMS_PAYLOAD_LEN {
+            return payload;
+        }
+        shown -= 1;
+    }
+}
+
+/// Wrapped text; Adaptive Cards need a blank line for a line break.
+fn text_block(text: &str) -> Value {
+    json!({
+        "type": "TextBlock",
+        "text": text.trim_end().replace('\n', "\n\n"),
+        "wrap": true,
+    })
+}
+
+impl AlertSink for MattermostConfig {
+    fn channel(&self) -> &'static str {
+        "mattermost"
+    }
+
+    fn destination(&self) -> String {
+        let origin = url_origin(&self.url);
+        match &self.channel {
+            Some(channel) => format!("{origin} ~{channel}"),
+            None => origin,
+        }
+    }
+
+    fn recipient_key(&self) -> String {
+        match &self.channel {
+            Some(channel) => format!("{} ~{channel}", url_digest(&self.url)),
+            None => url_digest(&self.url),
+        }
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let agent = http_agent(self.tunnel.as_ref());
+        post_webhook(&agent, &self.url, mattermost_payload(self, report))
+    }
+}
+
+/// Characters Mattermost keeps of a post.
+const MATTERMOST_TEXT_LEN: usize = 16_383;
+
+/// One Markdown post: the rollup as a heading, a table of the lines (or the
+/// rendered `--report-template`), the alerts and recoveries, and the run.
+fn mattermost_payload(config: &MattermostConfig, report: &AlertReport<'_>) -> Value {
+    let mut text = format!("#### {}\n\n", title(report));
+    match report.summary {
+        Some(summary) => text.push_str(&format!("```\n{}\n```\n", summary.trim_end())),
+        None => {
+            let shown: Vec<LineResult> = report
+                .results
+                .iter()
+                .filter(|result| !report.only_alerts || needs_attention(result))
+                .cloned()
+                .collect();
+            if !shown.is_empty() {
+                text.push_str(&markdown_table(&shown, "; "));
+            }
+            if let Some(hidden) = report
+                .only_alerts
+                .then(|| hidden_lines(report.results))
+                .flatten()
+            {
+                text.push_str(&format!("\n{hidden}\n"));
+            }
+        }
+    }
+    let alerts = format_events(report.events);
+    if !alerts.is_empty() {
+        text.push_str(&format!("\n{alerts}"));
+    }
+    let text = with_headline(report, text);
+    let mut payload = json!({
+        "username": "ICMPMolester",
+        "text": truncate(&text, MATTERMOST_TEXT_LEN),
+    });
+    if let Some(channel) = &config.channel {
+        payload["channel"] = json!(channel);
+    }
+    payload
+}
+
+impl AlertSink for JsonWebhookConfig {
+    fn channel(&self) -> &'static str {
+        "json_webhook"
+    }
+
+    fn destination(&self) -> String {
+        url_origin(&self.url)
+    }
+
+    fn recipient_key(&self) -> String {
+        url_digest(&self.url)
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let agent = http_agent(self.tunnel.as_ref());
+        let body = json_webhook_body(report).to_string();
+        let mut delay = JSON_WEBHOOK_RETRY_DELAY;
+        let mut waited = Duration::ZERO;
+        let mut attempt = 0;
+        loop {
+            let mut request = agent
+                .post(&self.url)
+                .set("Content-Type", "application/json");
+            for (name, value) in &self.headers {
+                request = request.set(name, value);
+            }
+            if let Some(secret) = &self.secret {
+                request = request.set(&self.signature_header, &sign_body(secret, &body));
+            }
+            let err = match request.send_string(&body) {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+            let retryable = match &err {
+                ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+                ureq::Error::Transport(_) => true,
+            };
+            let wait = delay.min(JSON_WEBHOOK_MAX_BACKOFF - waited);
+            if !retryable || attempt == self.retries || wait.is_zero() {
+                return Err(webhook_error(err));
+            }
+            attempt += 1;
+            debug!(url = %self.destination(), attempt, "Retrying webhook delivery");
+            std::thread::sleep(wait);
+            waited += wait;
+            delay *= 2;
+        }
+    }
+}
+
+/// Wait before the first retry of a `[[notifications.webhook]]`, doubled
+/// for each one after.
+const JSON_WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Longest a `[[notifications.webhook]]` waits between attempts in total.
+const JSON_WEBHOOK_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The JSON run report (as `--output json` prints it) with the run's alerts,
+/// recoveries and headline.
+fn json_webhook_body(report: &AlertReport<'_>) -> Value {
+    let mut body = serde_json::to_value(RunReport::new(report.results, report.run))
+        .expect("run reports serialize to JSON");
+    body["alerts"] = report.events.iter().map(event_json).collect();
+    body["recoveries"] = report.recoveries.iter().map(recovery_json).collect();
+    if let Some(headline) = &report.headline {
+        body["headline"] = json!(headline);
+    }
+    body
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body` keyed with `secret`.
+fn sign_body(secret: &str, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, body.as_bytes());
+    let hex: String = tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256={hex}")
+}
+
+impl AlertSink for MatrixConfig {
+    fn channel(&self) -> &'static str {
+        "matrix"
+    }
+
+    fn destination(&self) -> String {
+        self.room_id.clone()
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        // The run ID keeps a retried delivery from posting twice.
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/icmpmolester-{}",
+            self.homeserver,
+            url_encode(&self.room_id),
+            report.run.run_id
+        );
+        http_agent(self.tunnel.as_ref())
+            .put(&url)
+            .set("Authorization", &format!("Bearer {}", self.token.trim()))
+            .send_json(matrix_message(report))
+            .map(drop)
+            .map_err(webhook_error)
+            .context("Failed to post to the Matrix room")
+    }
 }
 
-/// Send the textual summary via SMTP using the supplied credentials.
-pub fn send_email(summary: &str, config: &EmailConfig) -> Result<()> {
+/// An `m.text` message: the email summary as plain text, and as HTML with
+/// the rollup in bold and each line's heading in its status color (the
+/// rendered `--report-template` is sent preformatted instead).
+/// The HTML part of the email: one table row per line with its status in
+/// its color, the details of lines needing attention beneath them, then the
+/// alerts and recoveries. Styles are inline, as mail clients drop `<style>`.
+fn email_html(report: &AlertReport<'_>, note: Option<&str>) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width\"></head>\n\
+         <body style=\"font-family: sans-serif; color: #222;\">\n",
+    );
+    if let Some(headline) = &report.headline {
+        html.push_str(&format!("<p>{}</p>\n", html_escape(headline)));
+    }
+    html.push_str(&format!(
+        "<h2 style=\"font-size: 1.2em;\">{}</h2>\n",
+        html_escape(&title(report))
+    ));
+    match report.summary {
+        Some(summary) => html.push_str(&format!("<pre>{}</pre>\n", html_escape(summary))),
+        None => {
+            html.push_str(&format!(
+                "<table style=\"border-collapse: collapse; width: 100%;\">\n<tr>{}</tr>\n",
+                ["Line", "Status", "Loss", "Latency"]
+                    .map(|header| format!("<th {EMAIL_CELL}>{header}</th>"))
+                    .concat()
+            ));
+            for result in report
+                .results
+                .iter()
+                .filter(|result| !report.only_alerts || needs_attention(result))
+            {
+                let ping = result.ping();
+                html.push_str(&format!(
+                    "<tr><td {EMAIL_CELL}><b>{}</b><br><small>{}</small></td>\
+                     <td {EMAIL_CELL}><span style=\"background: #{:06X}; color: #fff; \
+                     padding: 2px 6px; border-radius: 3px;\">{}</span></td>\
+                     <td {EMAIL_CELL}>{}</td><td {EMAIL_CELL}>{}</td></tr>\n",
+                    html_escape(&result.name),
+                    html_escape(&result.target),
+                    result.status_color().rgb(),
+                    html_escape(result.display_status()),
+                    ping.and_then(|ping| ping.packet_loss_pct)
+                        .map_or_else(|| "n/a".into(), |loss| format!("{loss:.2}%")),
+                    ping.and_then(|ping| ping.average_latency_ms)
+                        .map_or_else(|| "n/a".into(), |latency| format!("{latency:.2} ms")),
+                ));
+                if needs_attention(result) {
+                    let rows: Vec<String> = summary_rows(result)
+                        .iter()
+                        .map(|row| html_escape(row.trim()))
+                        .collect();
+                    html.push_str(&format!(
+                        "<tr><td colspan=\"4\" {EMAIL_CELL}><small>{}</small></td></tr>\n",
+                        rows.join("<br>")
+                    ));
+                }
+            }
+            html.push_str("</table>\n");
+            if let Some(hidden) = report
+                .only_alerts
+                .then(|| hidden_lines(report.results))
+                .flatten()
+            {
+                html.push_str(&format!("<p>{}</p>\n", html_escape(&hidden)));
+            }
+        }
+    }
+    let texts = [
+        format_events(report.events),
+        format_recoveries(report.recoveries),
+        note.map(str::to_string).unwrap_or_default(),
+    ];
+    for text in texts {
+        if !text.is_empty() {
+            html.push_str(&format!(
+                "<p>{}</p>\n",
+                html_escape(text.trim_end()).replace('\n', "<br>")
+            ));
+        }
+    }
+    html.push_str(&format!(
+        "<p style=\"color: #666; font-size: 0.9em;\">{}</p>\n</body></html>\n",
+        html_escape(&report.run.describe())
+    ));
+    html
+}
+
+const EMAIL_CELL: &str =
+    "style=\"text-align: left; padding: 4px 8px; border-bottom: 1px solid #ddd;\"";
+
+fn matrix_message(report: &AlertReport<'_>) -> Value {
+    let body = text_summary(report);
+    let alerts = format_events(report.events);
+
+    let mut html = String::new();
+    if let Some(headline) = &report.headline {
+        html.push_str(&format!("<p>{}</p>", html_escape(headline)));
+    }
+    html.push_str(&format!("<p><b>{}</b></p>", html_escape(&title(report))));
+    match report.summary {
+        Some(summary) => html.push_str(&format!("<pre>{}</pre>", html_escape(summary))),
+        None => {
+            for result in report
+                .results
+                .iter()
+                .filter(|result| !report.only_alerts || needs_attention(result))
+            {
+                html.push_str(&format!(
+                    "<p><font color=\"#{:06X}\">{} <b>{}</b> ({}): {}</font>",
+                    result.status_color().rgb(),
+                    html_escape(result.status_emoji()),
+                    html_escape(&result.name),
+                    html_escape(&result.target),
+                    html_escape(result.display_status())
+                ));
+                for row in summary_rows(result) {
+                    html.push_str(&format!("<br>{}", html_escape(row.trim())));
+                }
+                html.push_str("</p>");
+            }
+            if let Some(hidden) = report
+                .only_alerts
+                .then(|| hidden_lines(report.results))
+                .flatten()
+            {
+                html.push_str(&format!("<p>{}</p>", html_escape(&hidden)));
+            }
+        }
+    }
+    for text in [alerts, format_recoveries(report.recoveries)] {
+        if !text.is_empty() {
+            html.push_str(&format!(
+                "<p>{}</p>",
+                html_escape(text.trim_end()).replace('\n', "<br>")
+            ));
+        }
+    }
+    html.push_str(&format!(
+        "<p><sub>{}</sub></p>",
+        html_escape(&report.run.describe())
+    ));
+
+    json!({
+        "msgtype": "m.text",
+        "body": body,
+        "format": "org.matrix.custom.html",
+        "formatted_body": html,
+    })
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Percent-encode everything but RFC 3986's unreserved characters, for a
+/// path segment.
+fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+impl AlertSink for XmppConfig {
+    fn channel(&self) -> &'static str {
+        "xmpp"
+    }
+
+    fn destination(&self) -> String {
+        self.recipients
+            .iter()
+            .map(|recipient| match recipient {
+                xmpp::Recipient::Chat(jid) => jid.clone(),
+                xmpp::Recipient::Room { jid, .. } => jid.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let body = truncate(&text_summary(report), XMPP_BODY_LEN);
+        xmpp::send(
+            &self.jid,
+            &self.password,
+            self.server.as_deref(),
+            self.tunnel.as_ref(),
+            &self.recipients,
+            &body,
+        )
+    }
+}
+
+impl AlertSink for SignalConfig {
+    fn channel(&self) -> &'static str {
+        "signal"
+    }
+
+    fn destination(&self) -> String {
+        format!("group {}", self.group)
+    }
+
+    fn deliver(&self, report: &AlertReport<'_>) -> Result<()> {
+        let body = truncate(&text_summary(report), SIGNAL_BODY_LEN);
+        match &self.transport {
+            SignalTransport::Command(program) => run_signal_cli(program, self, &body),
+            SignalTransport::JsonRpc(url) => {
+                let request = signal_rpc_request(self, &body, &report.run.run_id.to_string());
+                let response: Value = http_agent(self.tunnel.as_ref())
+                    .post(url)
+                    .send_json(request)
+                    .map_err(webhook_error)?
+                    .into_json()
+                    .context("signal-cli returned invalid JSON")?;
+                match response.get("error") {
+                    Some(error) => Err(anyhow!(
+                        "signal-cli refused the message: {}",
+                        error["message"].as_str().unwrap_or("unknown error")
+                    )),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Characters of a Signal message; longer ones are sent as a text attachment
+/// that some clients only show when tapped.
+const SIGNAL_BODY_LEN: usize = 2_000;
+
+/// How long signal-cli, a JVM that may also sync the account first, gets to
+/// send one message.
+const SIGNAL_CLI_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// `send` arguments for signal-cli; the message itself goes to stdin so it
+/// never shows up in the process list.
+fn signal_cli_args(config: &SignalConfig) -> [&str; 6] {
+    [
+        "-a",
+        &config.account,
+        "send",
+        "-g",
+        &config.group,
+        "--message-from-stdin",
+    ]
+}
+
+fn run_signal_cli(program: &Path, config: &SignalConfig, body: &str) -> Result<()> {
+    let mut child = std::process::Command::new(program)
+        .args(signal_cli_args(config))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program.display()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(body.as_bytes())
+            .context("Failed to pass the message to signal-cli")?;
+    }
+    let deadline = Instant::now() + SIGNAL_CLI_TIMEOUT;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            anyhow::bail!(
+                "signal-cli did not finish within {} s",
+                SIGNAL_CLI_TIMEOUT.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "signal-cli failed ({}): {}",
+            output.status,
+            stderr.lines().last().unwrap_or("no output").trim()
+        );
+    }
+    Ok(())
+}
+
+/// A JSON-RPC `send` call; the run ID doubles as the request ID.
+fn signal_rpc_request(config: &SignalConfig, body: &str, id: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "send",
+        "params": {
+            "account": config.account,
+            "groupId": config.group,
+            "message": body,
+        },
+        "id": id,
+    })
+}
+
+/// Characters of a message body, well under the stanza size servers accept.
+const XMPP_BODY_LEN: usize = 16_000;
+
+/// Build the request bodies for one run; the alert-only formats send nothing
+/// when no unsilenced alert is active.
+fn webhook_payloads(
+    config: &WebhookConfig,
+    report: &AlertReport<'_>,
+    now: DateTime<Utc>,
+) -> Vec<Value> {
+    let status = report
+        .results
+        .iter()
+        .map(|result| result.status())
+        .max()
+        .unwrap_or(LineStatus::Ok);
+    let active = || report.events.iter().filter(|event| !event.silenced);
+    match config.format {
+        WebhookFormat::Flat => vec![flat_payload(report, status, now)],
+        WebhookFormat::Alertmanager => {
+            let alerts: Vec<Value> = active()
+                .map(|event| {
+                    let mut annotations = json!({
+                        "summary": event.reason,
+                        "run_id": report.run.run_id,
+                    });
+                    if let Some(note) = &event.note {
+                        annotations["description"] = json!(note);
+                    }
+                    if let Some(url) = &event.runbook_url {
+                        annotations["runbook_url"] = json!(url);
+                    }
+                    json!({
+                        "labels": {
+                            "alertname": "ICMPMolester",
+                            "line": event.line,
+                            "check": event.check,
+                            "severity": severity_label(event.severity),
+                        },
+                        "annotations": annotations,
+                        "startsAt": now.to_rfc3339(),
+                    })
+                })
+                .collect();
+            if alerts.is_empty() {
+                Vec::new()
+            } else {
+                vec![Value::Array(alerts)]
+            }
+        }
+        WebhookFormat::Pagerduty => active()
+            .map(|event| {
+                let mut trigger = json!({
+                    "routing_key": config.routing_key.as_deref().unwrap_or_default(),
+                    "event_action": "trigger",
+                    "dedup_key": format!("icmpmolester/{}/{}", event.line, event.check),
+                    "payload": {
+                        "summary": format!("{} {}: {}", event.line, event.check, event.reason),
+                        "source": event.line,
+                        "severity": severity_label(event.severity),
+                        "component": event.line,
+                        "class": event.check,
+                        "timestamp": now.to_rfc3339(),
+                        "custom_details": {
+                            "run_id": report.run.run_id,
+                            "hostname": report.run.hostname,
+                        },
+                    },
+                });
+                if let Some(note) = &event.note {
+                    trigger["payload"]["custom_details"]["note"] = json!(note);
+                }
+                if let Some(url) = &event.runbook_url {
+                    trigger["links"] = json!([{ "href": url, "text": "Runbook" }]);
+                }
+                trigger
+            })
+            .collect(),
+        WebhookFormat::Template => {
+            let flat = flat_payload(report, status, now);
+            let mut payload = config.template.clone().unwrap_or(Value::Null);
+            fill_template(&mut payload, &flat);
+            vec![payload]
+        }
+    }
+}
+
+/// `body` preceded by the report's headline, if it has one, and followed by
+/// any recoveries and the run's ID and host.
+/// The full text summary, as emailed: the summary, recoveries and run, then
+/// the active alerts.
+fn text_summary(report: &AlertReport<'_>) -> String {
+    let summary = report.summary.map_or_else(
+        || format_summary(report.results, report.only_alerts),
+        str::to_string,
+    );
+    let mut body = with_headline(report, with_subject(report, summary));
+    let alerts = format_events(report.events);
+    if !alerts.is_empty() {
+        body.push('\n');
+        body.push_str(&alerts);
+    }
+    body
+}
+
+/// The template's subject, or the rollup, e.g. `ICMPMolester: ALERT, 50% of
+/// line weight impacted`.
+fn title(report: &AlertReport<'_>) -> String {
+    report.subject.map_or_else(
+        || format!("ICMPMolester: {}", Rollup::of(report.results).describe()),
+        str::to_string,
+    )
+}
+
+/// `body` under the template's subject, for channels without a title.
+fn with_subject(report: &AlertReport<'_>, body: String) -> String {
+    match report.subject {
+        Some(subject) => format!("{subject}\n\n{body}"),
+        None => body,
+    }
+}
+
+fn with_headline(report: &AlertReport<'_>, body: String) -> String {
+    let recoveries = format_recoveries(report.recoveries);
+    let body = format!("{body}\n{recoveries}{}\n", report.run.describe());
+    match &report.headline {
+        Some(headline) => format!("{headline}\n{body}"),
+        None => body,
+    }
+}
+
+fn flat_payload(report: &AlertReport<'_>, status: LineStatus, now: DateTime<Utc>) -> Value {
+    let alerts: Vec<Value> = report.events.iter().map(event_json).collect();
+    json!({
+        "source": "icmpmolester",
+        "run_id": report.run.run_id,
+        "hostname": report.run.hostname,
+        "started_at": report.run.started_at.to_rfc3339(),
+        "finished_at": report.run.finished_at.to_rfc3339(),
+        "status": status.as_str(),
+        "headline": report.headline,
+        "subject": report.subject,
+        "timestamp": now.to_rfc3339(),
+        "alert_count": alerts.len(),
+        "alerts": alerts,
+        "recoveries": report.recoveries.iter().map(recovery_json).collect::<Vec<_>>(),
+        "summary": report
+            .summary
+            .map_or_else(|| format_summary(report.results, report.only_alerts), str::to_string),
+    })
+}
+
+fn event_json(event: &AlertEvent) -> Value {
+    json!({
+        "line": event.line,
+        "check": event.check,
+        "severity": severity_label(event.severity),
+        "reason": event.reason,
+        "silenced": event.silenced,
+        "note": event.note,
+        "runbook_url": event.runbook_url,
+    })
+}
+
+fn recovery_json(recovery: &Recovery) -> Value {
+    json!({
+        "line": recovery.line,
+        "from": recovery.from.as_str(),
+        "after_secs": recovery.after.map(|after| after.num_seconds()),
+    })
+}
+
+fn severity_label(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Warning => "warning",
+        Severity::Critical => "critical",
+    }
+}
+
+/// Replace `{{field}}` placeholders in template strings with values from the
+/// flat payload. A string that is exactly one placeholder takes the value
+/// as-is, so `"{{alerts}}"` becomes the alert array and `"{{alert_count}}"` a
+/// number; placeholders inside longer strings are substituted as text.
+fn fill_template(template: &mut Value, fields: &Value) {
+    match template {
+        Value::String(text) => {
+            let whole = text
+                .strip_prefix("{{")
+                .and_then(|rest| rest.strip_suffix("}}"))
+                .and_then(|name| fields.get(name.trim()));
+            if let Some(value) = whole {
+                *template = value.clone();
+                return;
+            }
+            let Some(fields) = fields.as_object() else {
+                return;
+            };
+            for (name, value) in fields {
+                let placeholder = format!("{{{{{name}}}}}");
+                if text.contains(&placeholder) {
+                    let replacement = match value {
+                        Value::String(value) => value.clone(),
+                        other => other.to_string(),
+                    };
+                    *text = text.replace(&placeholder, &replacement);
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| fill_template(item, fields)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|item| fill_template(item, fields)),
+        _ => {}
+    }
+}
+
+/// HTTP client for notifications; with a tunnel, every host resolves to a
+/// local forward through it.
+fn http_agent(tunnel: Option<&Tunnel>) -> ureq::Agent {
+    let Some(tunnel) = tunnel.cloned() else {
+        return ureq::Agent::new();
+    };
+    ureq::AgentBuilder::new()
+        .resolver(move |netloc: &str| {
+            let (host, port) = netloc
+                .rsplit_once(':')
+                .ok_or_else(|| std::io::Error::other(format!("missing port in '{netloc}'")))?;
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            let port = port.parse().map_err(std::io::Error::other)?;
+            Ok(vec![tunnel.forward(host, port)?])
+        })
+        .build()
+}
+
+fn post_webhook(agent: &ureq::Agent, url: &str, payload: Value) -> Result<()> {
+    agent
+        .post(url)
+        .send_json(payload)
+        .map(drop)
+        .map_err(webhook_error)
+}
+
+fn webhook_error(err: ureq::Error) -> anyhow::Error {
+    match err {
+        ureq::Error::Status(code, resp) => {
+            let text = resp
+                .into_string()
+                .unwrap_or_else(|_| "<no body>".to_string());
+            anyhow!("Webhook responded with status {}: {}", code, text)
+        }
+        err => anyhow!(err).context("Failed to call webhook"),
+    }
+}
+
+/// Send the summary via SMTP under `subject` using the supplied credentials,
+/// as plain text
+/// with an HTML alternative and `attachments` as `(file name, contents)`
+/// pairs.
+pub fn send_email(
+    subject: &str,
+    summary: &str,
+    html: &str,
+    attachments: &[(String, Vec<u8>)],
+    config: &EmailConfig,
+) -> Result<()> {
     let mut builder = Message::builder()
         .from(parse_mailbox(&config.from).context("Invalid sender email address")?)
-        .subject("ICMPMolester report");
+        .subject(subject);
 
     for recipient in &config.to {
         builder = builder.to(parse_mailbox(recipient).context("Invalid recipient email address")?);
     }
 
-    let email = builder
-        .body(summary.to_string())
-        .context("Failed to build email message body")?;
+    let text = MultiPart::alternative_plain_html(summary.to_string(), html.to_string());
+    let email = if attachments.is_empty() {
+        builder.multipart(text)
+    } else {
+        let parts =
+            attachments
+                .iter()
+                .fold(MultiPart::mixed().multipart(text), |parts, (name, body)| {
+                    parts.singlepart(
+                        Attachment::new(name.clone()).body(body.clone(), attachment_type(name)),
+                    )
+                });
+        builder.multipart(parts)
+    }
+    .context("Failed to build email message body")?;
 
-    let mut transport_builder = SmtpTransport::relay(&config.smtp_server)
-        .with_context(|| format!("Failed to configure SMTP relay {}", config.smtp_server))?;
+    let mut transport_builder = match &config.tunnel {
+        // Connect to the local forward but keep verifying the relay's own name.
+        Some(tunnel) => {
+            let local = tunnel
+                .forward(&config.smtp_server, SMTP_RELAY_PORT)
+                .with_context(|| format!("Failed to open tunnel via {tunnel}"))?;
+            let tls = TlsParameters::new(config.smtp_server.clone()).with_context(|| {
+                format!("Failed to configure SMTP relay {}", config.smtp_server)
+            })?;
+            SmtpTransport::builder_dangerous(local.ip().to_string())
+                .port(local.port())
+                .tls(Tls::Wrapper(tls))
+        }
+        None => SmtpTransport::relay(&config.smtp_server)
+            .with_context(|| format!("Failed to configure SMTP relay {}", config.smtp_server))?,
+    };
 
     if let (Some(username), Some(password)) = (&config.username, &config.password) {
         transport_builder =
@@ -50,21 +1328,34 @@ pub fn send_email(summary: &str, config: &EmailConfig) -> Result<()> {
     Ok(())
 }
 
+/// Content type of an attachment, by its file name's extension: PNG charts,
+/// JSON results, otherwise raw command output.
+fn attachment_type(name: &str) -> ContentType {
+    let content_type = match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some("png") => "image/png",
+        Some("json") => "application/json",
+        _ => "text/plain; charset=utf-8",
+    };
+    ContentType::parse(content_type).expect("valid content type")
+}
+
 /// Send the textual summary via the Telegram Bot API.
 pub fn send_telegram(summary: &str, config: &TelegramConfig) -> Result<()> {
     let url = format!(
-        "https://api.telegram.org/bot{}/sendMessage",
+        "https://{TELEGRAM_API_HOST}/bot{}/sendMessage",
         config.token.trim()
     );
 
     let mut body = summary.to_string();
     ensure_telegram_size(&mut body);
 
-    let response = ureq::post(&url).send_json(ureq::json!({
-        "chat_id": config.chat_id,
-        "text": body,
-        "disable_web_page_preview": true,
-    }));
+    let response = http_agent(config.tunnel.as_ref())
+        .post(&url)
+        .send_json(ureq::json!({
+            "chat_id": config.chat_id,
+            "text": body,
+            "disable_web_page_preview": true,
+        }));
 
     match response {
         Ok(resp) => {
@@ -112,6 +1403,9 @@ fn ensure_telegram_size(body: &mut String) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::report::RunMetadata;
+    use crate::runner;
+    use chrono::TimeDelta;
 
     #[test]
     fn truncates_long_messages() {
@@ -121,6 +1415,398 @@ mod tests {
         assert!(long.ends_with("..."));
     }
 
+    #[test]
+    fn shapes_webhook_payloads() {
+        let event = |line: &str, silenced| AlertEvent {
+            line: line.into(),
+            check: "ping".into(),
+            severity: Severity::Critical,
+            reason: "ping failed".into(),
+            silenced,
+            note: silenced.then(|| "Call the NOC".into()),
+            runbook_url: Some("https://wiki.example.com/runbooks/ping".into()),
+        };
+        let events = [event("Primary", false), event("Backup", true)];
+        let run = RunMetadata::new(Utc::now(), Utc::now());
+        let report = AlertReport {
+            run: &run,
+            results: &[],
+            events: &events,
+            recoveries: &[],
+            headline: None,
+            summary: None,
+            subject: None,
+            only_alerts: false,
+        };
+        let now = Utc::now();
+        let mut config = WebhookConfig {
+            url: "http://127.0.0.1/hook?token=s3cr3t".into(),
+            format: WebhookFormat::Flat,
+            template: None,
+            routing_key: Some("R0UT1NG".into()),
+            tunnel: None,
+        };
+        // The path and query may carry a token, so logs only see the origin.
+        assert_eq!(config.destination(), "http://127.0.0.1");
+
+        let flat = webhook_payloads(&config, &report, now);
+        assert_eq!(flat[0]["alert_count"], 2);
+        assert_eq!(flat[0]["alerts"][1]["silenced"], true);
+        assert_eq!(flat[0]["alerts"][1]["note"], "Call the NOC");
+        assert_eq!(flat[0]["run_id"], run.run_id.to_string());
+
+        config.format = WebhookFormat::Alertmanager;
+        let alerts = webhook_payloads(&config, &report, now);
+        assert_eq!(alerts[0].as_array().unwrap().len(), 1);
+        assert_eq!(alerts[0][0]["labels"]["line"], "Primary");
+        assert_eq!(alerts[0][0]["labels"]["severity"], "critical");
+        assert_eq!(
+            alerts[0][0]["annotations"]["runbook_url"],
+            "https://wiki.example.com/runbooks/ping"
+        );
+        assert!(alerts[0][0]["annotations"].get("description").is_none());
+
+        config.format = WebhookFormat::Pagerduty;
+        let triggers = webhook_payloads(&config, &report, now);
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0]["routing_key"], "R0UT1NG");
+        assert_eq!(triggers[0]["dedup_key"], "icmpmolester/Primary/ping");
+        assert_eq!(triggers[0]["links"][0]["text"], "Runbook");
+
+        config.format = WebhookFormat::Template;
+        config.template = Some(json!({
+            "text": "ICMPMolester is {{status}} with {{alert_count}} alerts",
+            "count": "{{alert_count}}",
+            "items": "{{alerts}}",
+        }));
+        let filled = &webhook_payloads(&config, &report, now)[0];
+        assert_eq!(filled["text"], "ICMPMolester is OK with 2 alerts");
+        assert_eq!(filled["count"], 2);
+        assert_eq!(filled["items"][0]["line"], "Primary");
+
+        let recoveries = [Recovery {
+            line: "Backup".into(),
+            from: LineStatus::Errored,
+            after: Some(TimeDelta::seconds(90)),
+        }];
+        let quiet = AlertReport {
+            run: &run,
+            results: &[],
+            events: &events[1..],
+            recoveries: &recoveries,
+            headline: Some("Monitoring started, current state: no lines\n".into()),
+            summary: None,
+            subject: None,
+            only_alerts: false,
+        };
+        config.format = WebhookFormat::Alertmanager;
+        assert!(webhook_payloads(&config, &quiet, now).is_empty());
+        config.format = WebhookFormat::Flat;
+        let started = webhook_payloads(&config, &quiet, now);
+        assert!(
+            started[0]["headline"]
+                .as_str()
+                .unwrap()
+                .starts_with("Monitoring started")
+        );
+        assert_eq!(started[0]["recoveries"][0]["from"], "ERRORED");
+        assert_eq!(started[0]["recoveries"][0]["after_secs"], 90);
+    }
+
+    fn sample_result(name: &str, loss: f32) -> LineResult {
+        let mut result =
+            runner::tests::sample_result(name, true, Some(loss), Some(12.0), 5.0, None);
+        result.target = "192.0.2.1".into();
+        result
+    }
+
+    #[test]
+    fn splits_discord_embeds_across_messages() {
+        let mut results: Vec<LineResult> = (1..=11)
+            .map(|n| sample_result(&format!("Line {n}"), 0.0))
+            .collect();
+        results.push(sample_result("Backup", 40.0));
+        let run = RunMetadata::new(Utc::now(), Utc::now());
+        let mut report = AlertReport {
+            run: &run,
+            results: &results,
+            events: &[],
+            recoveries: &[],
+            headline: None,
+            summary: None,
+            subject: None,
+            only_alerts: false,
+        };
+
+        let messages = discord_payloads(&report);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["embeds"].as_array().unwrap().len(), 10);
+        assert!(
+            messages[0]["content"]
+                .as_str()
+                .unwrap()
+                .starts_with("ICMPMolester: ALERT")
+        );
+        assert!(messages[1].get("content").is_none());
+        let backup = &messages[1]["embeds"][1];
+        assert_eq!(backup["title"], "⚠️ Backup (192.0.2.1): ALERT");
+        assert_eq!(backup["color"], 0xD0392B);
+        assert_eq!(messages[0]["embeds"][0]["color"], 0x2E9E4F);
+
+        report.only_alerts = true;
+        let messages = discord_payloads(&report);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["embeds"].as_array().unwrap().len(), 1);
+        assert!(
+            messages[0]["content"]
+                .as_str()
+                .unwrap()
+                .contains("11 lines OK")
+        );
+
+        assert_eq!(
+            discord_destination("https://discord.com/api/webhooks/123/s3cr3t"),
+            "https://discord.com/api/webhooks/123"
+        );
+    }
+
+    #[test]
+    fn builds_teams_adaptive_card() {
+        let results = vec![sample_result("Fibre", 0.0), sample_result("Backup", 40.0)];
+        let run = RunMetadata::new(Utc::now(), Utc::now());
+        let mut report = AlertReport {
+            run: &run,
+            results: &results,
+            events: &[],
+            recoveries: &[],
+            headline: None,
+            summary: None,
+            subject: None,
+            only_alerts: true,
+        };
+
+        let payload = teams_payload(&report);
+        let card = &payload["attachments"][0]["content"];
+        assert_eq!(card["type"], "AdaptiveCard");
+        let body = card["body"].as_array().unwrap();
+        assert_eq!(body[0]["color"], "attention");
+        assert_eq!(body[1]["items"][0]["text"], "⚠️ Backup (192.0.2.1): ALERT");
+        assert_eq!(body[1]["items"][0]["color"], "attention");
+        assert_eq!(body[2]["text"], "1 line OK");
+
+        report.only_alerts = false;
+        report.summary = Some("Fibre up\nBackup down");
+        let payload = teams_payload(&report);
+        let body = payload["attachments"][0]["content"]["body"]
+            .as_array()
+            .unwrap();
+        assert_eq!(body[1]["text"], "Fibre up\n\nBackup down");
+
+        assert_eq!(
+            url_origin("https://example.webhook.office.com/webhookb2/abc@def/IncomingWebhook/1"),
+            "https://example.webhook.office.com"
+        );
+    }
+
+    #[test]
+    fn keys_webhooks_on_one_host_apart() {
+        let teams = |url: &str| TeamsConfig {
+            url: url.into(),
+            tunnel: None,
+        };
+        let first = teams("https://hooks.example.com/services/T1/secret-one");
+        let second = teams("https://hooks.example.com/services/T2/secret-two");
+        assert_eq!(first.destination(), second.destination());
+        assert_ne!(first.recipient_key(), second.recipient_key());
+        assert!(
+            first
+                .recipient_key()
+                .starts_with("https://hooks.example.com #")
+        );
+        assert!(!first.recipient_key().contains("secret"));
+    }
+
+    #[test]
+    fn posts_mattermost_markdown_table() {
+        let results = vec![sample_result("Fibre", 0.0), sample_result("Backup", 40.0)];
+        let run = RunMetadata::new(Utc::now(), Utc::now());
+        let report = AlertReport {
+            run: &run,
+            results: &results,
+            events: &[],
+            recoveries: &[],
+            headline: None,
+            summary: None,
+            subject: None,
+            only_alerts: true,
+        };
+        let config = MattermostConfig {
+            url: "https://chat.example.com/hooks/s3cr3t".into(),
+            channel: Some("noc-alerts".into()),
+            tunnel: None,
+        };
+
+        let payload = mattermost_payload(&config, &report);
+        assert_eq!(payload["channel"], "noc-alerts");
+        let text = payload["text"].as_str().unwrap();
+        assert!(text.starts_with("#### ICMPMolester: ALERT"), "{text}");
+        assert!(
+            text.contains("| **Backup** | **192.0.2.1** | **ALERT** |"),
+            "{text}"
+        );
+        assert!(!text.contains("| Fibre |"), "{text}");
+        assert!(text.contains("\n1 line OK\n"), "{text}");
+        assert!(!text.contains("<br>"), "{text}");
+        assert_eq!(config.destination(), "https://chat.example.com ~noc-alerts");
+    }
+
+    #[test]
+    fn signs_json_webhook_body() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign_body("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+
+        let results = vec![sample_result("Backup", 40.0)];
+        let run = RunMetadata::new(Utc::now(), Utc::now());
+        let events = crate::alerting::evaluate(&results, None, Utc::now());
+        let report = AlertReport {
+            run: &run,
+            results: &results,
+            events: &events,
+            recoveries: &[],
+            headline: None,
+            summary: None,
+            subject: None,
+            only_alerts: false,
+        };
+        let body = json_webhook_body(&report);
+        assert_eq!(body["run_id"], json!(run.run_id));
+        assert_eq!(body["lines"][0]["name"], "Backup");
+        assert_eq!(body["alerts"][0]["line"], "Backup");
+        assert_eq!(body["recoveries"], json!([]));
+        assert!(body.get("headline").is_none());
+    }
+
+    #[test]
+    fn passes_signal_messages_out_of_band() {
+        let config = SignalConfig {
+            account: "+391234567890".into(),
+            group: "aGVsbG8gd29ybGQ=".into(),
+            transport: SignalTransport::Command("signal-cli".into()),
+            tunnel: None,
+        };
+        assert_eq!(
+            signal_cli_args(&config),
+            [
+                "-a",
+                "+391234567890",
+                "send",
+                "-g",
+                "aGVsbG8gd29ybGQ=",
+                "--message-from-stdin"
+            ]
+        );
+        assert_eq!(
+            signal_rpc_request(&config, "Line down", "run-1"),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "send",
+                "params": {
+                    "account": "+391234567890",
+                    "groupId": "aGVsbG8gd29ybGQ=",
+                    "message": "Line down",
+                },
+                "id": "run-1",
+            })
+        );
+    }
+
+    #[test]
+    fn formats_matrix_message() {
+        let mut results = vec![sample_result("Fibre", 0.0), sample_result("<Backup>", 40.0)];
+        results[1].note = Some("Call the ISP".into());
+        let run = RunMetadata::new(Utc::now(), Utc::now());
+        let events = crate::alerting::evaluate(&results, None, Utc::now());
+        let report = AlertReport {
+            run: &run,
+            results: &results,
+            events: &events,
+            recoveries: &[],
+            headline: None,
+            summary: None,
+            subject: None,
+            only_alerts: true,
+        };
+
+        let message = matrix_message(&report);
+        assert_eq!(message["msgtype"], "m.text");
+        assert!(message["body"].as_str().unwrap().contains("Active alerts:"));
+        let html = message["formatted_body"].as_str().unwrap();
+        assert!(
+            html.starts_with("<p><b>ICMPMolester: ALERT, 50% of line weight impacted</b></p>"),
+            "{html}"
+        );
+        assert!(
+            html.contains(
+                "<p><font color=\"#D0392B\">⚠️ <b>&lt;Backup&gt;</b> (192.0.2.1): ALERT</font>"
+            ),
+            "{html}"
+        );
+        assert!(html.contains("<br>Call the ISP<br>"), "{html}");
+        assert!(!html.contains("<b>Fibre</b>"), "{html}");
+        assert!(html.contains("<p>1 line OK</p>"), "{html}");
+        assert!(
+            html.contains("<p>Active alerts:<br>- [CRITICAL] &lt;Backup&gt;"),
+            "{html}"
+        );
+
+        assert_eq!(url_encode("!abc:example.org"), "%21abc%3Aexample.org");
+    }
+
+    #[test]
+    fn renders_email_status_table() {
+        let mut results = vec![sample_result("Fibre", 0.0), sample_result("<Backup>", 40.0)];
+        results[1].note = Some("Call the ISP".into());
+        let run = RunMetadata::new(Utc::now(), Utc::now());
+        let events = crate::alerting::evaluate(&results, None, Utc::now());
+        let report = AlertReport {
+            run: &run,
+            results: &results,
+            events: &events,
+            recoveries: &[],
+            headline: None,
+            summary: None,
+            subject: None,
+            only_alerts: false,
+        };
+
+        let html = email_html(&report, Some("Graphs could not be drawn: no history"));
+        assert!(
+            html.contains(">ICMPMolester: ALERT, 50% of line weight impacted</h2>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<b>Fibre</b><br><small>192.0.2.1</small>"),
+            "{html}"
+        );
+        assert!(
+            html.contains("<span style=\"background: #2E9E4F; color: #fff; padding: 2px 6px; border-radius: 3px;\">OK</span>"),
+            "{html}"
+        );
+        assert!(html.contains("#D0392B; color: #fff;"), "{html}");
+        assert!(html.contains(">40.00%</td>"), "{html}");
+        // Only the line needing attention gets a details row.
+        assert_eq!(html.matches("colspan").count(), 1, "{html}");
+        assert!(html.contains("<small>Call the ISP<br>"), "{html}");
+        assert!(
+            html.contains("<p>Active alerts:<br>- [CRITICAL] &lt;Backup&gt;"),
+            "{html}"
+        );
+        assert!(html.contains("<p>Graphs could not be drawn: no history</p>"));
+    }
+
     #[test]
     fn keeps_short_messages() {
         let mut short = String::from("ok");
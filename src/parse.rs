@@ -0,0 +1,384 @@
+//! Parsers for the text printed by `ping`, `traceroute`, `tracert` and
+//! `mtr --report`.
+//!
+//! Every function takes the tool's combined stdout/stderr and never panics,
+//! whatever the input; anything it cannot find is reported as `None` or left
+//! out. Supported variants:
+//!
+//! - ping: Linux iputils and BusyBox, macOS/BSD, and English Windows output.
+//! - traceroute: Linux and macOS/BSD `traceroute`, Windows `tracert`.
+//! - mtr: `mtr --report` (and `--report-wide`).
+//!
+//! Samples that do not parse as expected can be added to `tests/corpus`,
+//! which the test suite runs every parser against.
+
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Everything [`ping_summary`] extracts from one ping run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PingSummary {
+    pub packet_loss_pct: Option<f32>,
+    pub average_latency_ms: Option<f32>,
+    /// Round-trip time deviation (`mdev`, or `stddev` on macOS/BSD).
+    pub jitter_ms: Option<f32>,
+    /// Replies marked `DUP!`.
+    pub duplicates: u32,
+    /// Replies whose sequence number is lower than one already received.
+    pub reordered: u32,
+}
+
+/// One hop row; `address` is `None` for unanswered (`* * *` or `???`) hops.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hop {
+    pub number: u32,
+    #[serde(default)]
+    pub address: Option<IpAddr>,
+    /// Name printed by the tool itself, as in `host (ip)` or `host [ip]`.
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// First RTT of the row, or mtr's average.
+    #[serde(default)]
+    pub rtt_ms: Option<f32>,
+    /// Only `mtr` reports per-hop loss.
+    #[serde(default)]
+    pub loss_pct: Option<f32>,
+}
+
+/// Parse loss, latency, jitter and reply anomalies from ping output.
+pub fn ping_summary(output: &str) -> PingSummary {
+    let (duplicates, reordered) = reply_anomalies(output);
+    PingSummary {
+        packet_loss_pct: packet_loss_pct(output),
+        average_latency_ms: average_latency_ms(output),
+        jitter_ms: jitter_ms(output),
+        duplicates,
+        reordered,
+    }
+}
+
+/// Packet loss percentage from the statistics line (`0% packet loss`,
+/// `(0% loss)` on Windows).
+pub fn packet_loss_pct(output: &str) -> Option<f32> {
+    static LOSS_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = LOSS_REGEX
+        .get_or_init(|| Regex::new(r"(?P<loss>\d+(?:\.\d+)?)%\s*(?:packet\s+loss|loss)").unwrap());
+    regex
+        .captures_iter(output)
+        .last()
+        .and_then(|caps| caps.name("loss"))
+        .and_then(|m| m.as_str().parse::<f32>().ok())
+}
+
+/// Average round-trip time from the `min/avg/max` summary, or Windows'
+/// `Average = Nms`.
+pub fn average_latency_ms(output: &str) -> Option<f32> {
+    static UNIX_REGEX: OnceLock<Regex> = OnceLock::new();
+    static WINDOWS_REGEX: OnceLock<Regex> = OnceLock::new();
+
+    let unix_regex = UNIX_REGEX.get_or_init(|| Regex::new(r"= [\d\.]+/([\d\.]+)/[\d\.]+").unwrap());
+    unix_regex
+        .captures_iter(output)
+        .last()
+        .and_then(|caps| caps.get(1))
+        .and_then(|m| m.as_str().parse::<f32>().ok())
+        .or_else(|| {
+            let windows_regex =
+                WINDOWS_REGEX.get_or_init(|| Regex::new(r"Average = (\d+)ms").unwrap());
+            windows_regex
+                .captures_iter(output)
+                .last()
+                .and_then(|caps| caps.get(1))
+                .and_then(|m| m.as_str().parse::<f32>().ok())
+        })
+}
+
+/// Round-trip time deviation from the `min/avg/max/mdev` (or `stddev`)
+/// summary. BusyBox and Windows print none.
+pub fn jitter_ms(output: &str) -> Option<f32> {
+    static JITTER_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex =
+        JITTER_REGEX.get_or_init(|| Regex::new(r"= [\d\.]+/[\d\.]+/[\d\.]+/([\d\.]+)").unwrap());
+    regex
+        .captures_iter(output)
+        .last()
+        .and_then(|caps| caps.get(1))
+        .and_then(|m| m.as_str().parse::<f32>().ok())
+}
+
+/// Count duplicate and out-of-order echo replies in Unix ping output, as
+/// `(duplicates, reordered)`.
+///
+/// Windows ping prints neither sequence numbers nor `DUP!` markers, so both
+/// counts stay at zero there.
+pub fn reply_anomalies(output: &str) -> (u32, u32) {
+    static SEQ_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = SEQ_REGEX.get_or_init(|| Regex::new(r"icmp_seq=(\d+)").unwrap());
+
+    let mut duplicates = 0u32;
+    let mut reordered = 0u32;
+    let mut highest = None;
+    for row in output.lines() {
+        if row.contains("DUP!") {
+            duplicates = duplicates.saturating_add(1);
+            continue;
+        }
+        let Some(seq) = regex
+            .captures(row)
+            .and_then(|caps| caps[1].parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if highest.is_some_and(|highest| seq < highest) {
+            reordered = reordered.saturating_add(1);
+        } else {
+            highest = Some(seq);
+        }
+    }
+    (duplicates, reordered)
+}
+
+/// Round-trip time of every echo reply, in the order they arrived.
+///
+/// Replies marked `DUP!` are left out. Windows' `time<1ms` counts as 1 ms.
+pub fn reply_rtts(output: &str) -> Vec<f32> {
+    static TIME_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = TIME_REGEX.get_or_init(|| Regex::new(r"time[=<]\s*(\d+(?:\.\d+)?)\s*ms").unwrap());
+    output
+        .lines()
+        .filter(|row| !row.contains("DUP!"))
+        .filter_map(|row| regex.captures(row))
+        .filter_map(|caps| caps[1].parse::<f32>().ok())
+        .collect()
+}
+
+/// Number of the hop that mentions `target`, or of the last hop when none does.
+pub fn hop_count(output: &str, target: &str) -> Option<u32> {
+    let mut last_seen = None;
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let mut parts = trimmed.split_whitespace();
+        let hop = match parts.next().and_then(|s| s.parse::<u32>().ok()) {
+            Some(num) => num,
+            None => continue,
+        };
+        last_seen = Some(hop);
+        if trimmed.contains(target) {
+            return Some(hop);
+        }
+    }
+    last_seen
+}
+
+/// Parse hop rows from Unix traceroute or Windows tracert output.
+///
+/// Only the first responder of each hop is kept. A name printed by the tool
+/// itself (`host (ip)` or `host [ip]`) is used as the hop's hostname.
+pub fn traceroute_hops(output: &str) -> Vec<Hop> {
+    let mut hops = Vec::new();
+    for line in output.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(number) = tokens.first().and_then(|t| t.parse::<u32>().ok()) else {
+            continue;
+        };
+
+        let mut address = None;
+        let mut hostname = None;
+        for (idx, token) in tokens.iter().enumerate().skip(1) {
+            let bare = token.trim_matches(|c| matches!(c, '(' | ')' | '[' | ']'));
+            let Ok(ip) = bare.parse::<IpAddr>() else {
+                continue;
+            };
+            address = Some(ip);
+            if bare.len() != token.len() && idx > 1 {
+                hostname = Some(tokens[idx - 1].to_string());
+            }
+            break;
+        }
+
+        let rtt_ms = tokens.windows(2).find_map(|pair| match pair {
+            [value, "ms"] => value.trim_start_matches('<').parse::<f32>().ok(),
+            _ => None,
+        });
+
+        hops.push(Hop {
+            number,
+            address,
+            hostname,
+            rtt_ms,
+            loss_pct: None,
+        });
+    }
+    hops
+}
+
+/// Parse hop rows from `mtr --report` output, keeping per-hop loss and the
+/// average RTT. Unanswered hops (`???`) have no address.
+pub fn mtr_hops(output: &str) -> Vec<Hop> {
+    static MTR_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = MTR_REGEX.get_or_init(|| {
+        Regex::new(
+            r"^\s*(?P<hop>\d+)\.\|--\s+(?P<host>\S+)\s+(?P<loss>\d+(?:\.\d+)?)%?\s+\d+\s+[\d.]+\s+(?P<avg>[\d.]+)",
+        )
+        .unwrap()
+    });
+    output
+        .lines()
+        .filter_map(|line| regex.captures(line))
+        .filter_map(|caps| {
+            let address = caps["host"].parse::<IpAddr>().ok();
+            Some(Hop {
+                number: caps["hop"].parse().ok()?,
+                address,
+                hostname: None,
+                rtt_ms: address.and(caps["avg"].parse().ok()),
+                loss_pct: caps["loss"].parse().ok(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_unix_packet_loss_and_latency() {
+        let sample = r#"
+PING 8.8.8.8 (8.8.8.8) 56(84) bytes of data.
+64 bytes from 8.8.8.8: icmp_seq=1 ttl=115 time=19.2 ms
+
+--- 8.8.8.8 ping statistics ---
+4 packets transmitted, 4 received, 0% packet loss, time 3004ms
+rtt min/avg/max/mdev = 18.677/19.002/19.543/0.352 ms
+"#;
+
+        assert_eq!(packet_loss_pct(sample), Some(0.0));
+        assert_eq!(average_latency_ms(sample), Some(19.002));
+        assert_eq!(jitter_ms(sample), Some(0.352));
+    }
+
+    #[test]
+    fn counts_duplicate_and_reordered_replies() {
+        let output = "\
+PING 8.8.8.8 (8.8.8.8) 56(84) bytes of data.
+64 bytes from 8.8.8.8: icmp_seq=1 ttl=117 time=10.1 ms
+64 bytes from 8.8.8.8: icmp_seq=3 ttl=117 time=10.4 ms
+64 bytes from 8.8.8.8: icmp_seq=2 ttl=117 time=31.0 ms
+64 bytes from 8.8.8.8: icmp_seq=3 ttl=117 time=10.5 ms (DUP!)
+64 bytes from 8.8.8.8: icmp_seq=4 ttl=117 time=10.2 ms
+64 bytes from 8.8.8.8: icmp_seq=4 ttl=117 time=10.3 ms (DUP!)
+
+--- 8.8.8.8 ping statistics ---
+4 packets transmitted, 4 received, +2 duplicates, 0% packet loss, time 3004ms
+";
+        assert_eq!(reply_anomalies(output), (2, 1));
+        assert_eq!(
+            reply_anomalies("Reply from 8.8.8.8: bytes=32 time=12ms TTL=117"),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn collects_reply_round_trip_times() {
+        let unix = "\
+64 bytes from 8.8.8.8: icmp_seq=1 ttl=117 time=10.1 ms
+64 bytes from 8.8.8.8: icmp_seq=1 ttl=117 time=10.5 ms (DUP!)
+64 bytes from 8.8.8.8: icmp_seq=2 ttl=117 time=31 ms
+
+4 packets transmitted, 2 received, +1 duplicates, 50% packet loss, time 3004ms
+";
+        assert_eq!(reply_rtts(unix), vec![10.1, 31.0]);
+        let windows = "\
+Reply from 1.1.1.1: bytes=32 time<1ms TTL=57
+Reply from 1.1.1.1: bytes=32 time=12ms TTL=57
+";
+        assert_eq!(reply_rtts(windows), vec![1.0, 12.0]);
+    }
+
+    #[test]
+    fn parses_windows_packet_loss_and_latency() {
+        let sample = r#"
+Ping statistics for 1.1.1.1:
+    Packets: Sent = 4, Received = 4, Lost = 0 (0% loss),
+Approximate round trip times in milli-seconds:
+    Minimum = 35ms, Maximum = 40ms, Average = 37ms
+"#;
+
+        assert_eq!(packet_loss_pct(sample), Some(0.0));
+        assert_eq!(average_latency_ms(sample), Some(37.0));
+    }
+
+    #[test]
+    fn extracts_hop_count_when_target_seen() {
+        let sample = r#"
+ 1  192.0.2.1  1.234 ms
+ 2  198.51.100.1  4.567 ms
+ 3  example.com (203.0.113.10)  8.901 ms
+"#;
+        assert_eq!(hop_count(sample, "203.0.113.10"), Some(3));
+        assert_eq!(hop_count(sample, "example.com"), Some(3));
+    }
+
+    #[test]
+    fn extracts_last_hop_when_target_missing() {
+        let sample = r#"
+ 1  192.0.2.1  1.234 ms
+ 2  * * *
+ 3  198.51.100.1  4.567 ms
+"#;
+        assert_eq!(hop_count(sample, "unreachable.example"), Some(3));
+    }
+
+    #[test]
+    fn parses_unix_and_windows_hops() {
+        let unix = r#"
+traceroute to example.com (203.0.113.10), 30 hops max, 60 byte packets
+ 1  192.0.2.1  1.234 ms  1.100 ms  1.050 ms
+ 2  * * *
+ 3  ae-1.bbr01.milan (203.0.113.10)  8.901 ms  9.002 ms  8.950 ms
+"#;
+        let hops = traceroute_hops(unix);
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops[0].address, Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(hops[0].hostname, None);
+        assert_eq!(hops[0].rtt_ms, Some(1.234));
+        assert_eq!(hops[1].address, None);
+        assert_eq!(hops[2].hostname.as_deref(), Some("ae-1.bbr01.milan"));
+
+        let windows = r#"
+  1    <1 ms    <1 ms    <1 ms  192.168.1.1
+  2    10 ms     9 ms    10 ms  core.example.net [198.51.100.1]
+"#;
+        let hops = traceroute_hops(windows);
+        assert_eq!(hops[0].rtt_ms, Some(1.0));
+        assert_eq!(hops[0].hostname, None);
+        assert_eq!(hops[1].address, Some("198.51.100.1".parse().unwrap()));
+        assert_eq!(hops[1].hostname.as_deref(), Some("core.example.net"));
+    }
+
+    #[test]
+    fn parses_mtr_report_hops() {
+        let sample = r#"
+Start: 2026-10-16T10:00:00+0000
+HOST: probe                    Loss%   Snt   Last   Avg  Best  Wrst StDev
+  1.|-- 192.168.1.1             0.0%    10    0.5   0.6   0.4   0.9   0.1
+  2.|-- ???                    100.0    10    0.0   0.0   0.0   0.0   0.0
+  3.|-- 198.51.100.1           40.0%    10    9.1   9.3   8.8  10.2   0.4
+"#;
+        let hops = mtr_hops(sample);
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops[1].address, None);
+        assert_eq!(hops[1].rtt_ms, None);
+        assert_eq!(hops[1].loss_pct, Some(100.0));
+        assert_eq!(hops[2].rtt_ms, Some(9.3));
+        assert_eq!(hops[2].loss_pct, Some(40.0));
+    }
+}
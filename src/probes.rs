@@ -0,0 +1,8 @@
+//! Additional per-line probes that complement ping and traceroute.
+
+pub mod custom;
+pub mod dns;
+pub mod http;
+pub mod ntp;
+pub mod public_ip;
+pub mod tls;
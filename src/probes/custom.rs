@@ -0,0 +1,199 @@
+//! External probe commands, for checks this crate does not implement.
+//!
+//! The command's exit status follows the Nagios plugin convention (0 OK,
+//! 3 UNKNOWN, anything else ALERT). Its stdout is either plain text, whose
+//! first line becomes the message, or a JSON object that may also set the
+//! status and report numeric metrics.
+
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::runner::LineStatus;
+
+/// Exit status plugins use when they could not tell.
+const UNKNOWN_EXIT_CODE: i32 = 3;
+
+/// Outcome of one run of a line's `custom_command`.
+#[derive(Debug, Clone)]
+pub struct CustomReport {
+    /// The command as run, with placeholders filled in.
+    pub command: String,
+    pub status: LineStatus,
+    /// `None` when the command timed out or was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub message: Option<String>,
+    /// Numeric `metrics` from JSON output.
+    pub metrics: BTreeMap<String, f64>,
+    pub duration_ms: f32,
+}
+
+/// JSON a command may print instead of plain text.
+#[derive(Debug, Deserialize)]
+struct CustomOutput {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    metrics: BTreeMap<String, f64>,
+}
+
+/// Split `template` on whitespace and fill `{name}` and `{target}` into each
+/// argument. No shell is involved, so targets cannot inject commands.
+pub fn command_args(template: &str, name: &str, target: &str) -> Result<Vec<String>> {
+    let args: Vec<String> = template
+        .split_whitespace()
+        .map(|arg| arg.replace("{name}", name).replace("{target}", target))
+        .collect();
+    if args.is_empty() {
+        anyhow::bail!("custom_command is empty");
+    }
+    Ok(args)
+}
+
+/// Run the command for one line, killing it after `timeout`.
+///
+/// A command that cannot be started is an error; one that times out is
+/// reported as UNKNOWN.
+pub async fn run_custom_probe(
+    template: &str,
+    name: &str,
+    target: &str,
+    timeout: Duration,
+) -> Result<CustomReport> {
+    let args = command_args(template, name, target)?;
+    let command_line = args.join(" ");
+    debug!(line = %name, command = %command_line, "Running custom check");
+    let child = Command::new(&args[0])
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run custom command {command_line}"))?;
+
+    let started = Instant::now();
+    let outcome = tokio::time::timeout(timeout, child.wait_with_output()).await;
+    let duration_ms = started.elapsed().as_secs_f32() * 1000.0;
+    let output = match outcome {
+        Ok(output) => output.with_context(|| format!("Custom command {command_line} failed"))?,
+        Err(_) => {
+            return Ok(CustomReport {
+                command: command_line,
+                status: LineStatus::Unknown,
+                exit_code: None,
+                message: Some(format!("timed out after {} ms", timeout.as_millis())),
+                metrics: BTreeMap::new(),
+                duration_ms,
+            });
+        }
+    };
+
+    let exit_code = output.status.code();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (status, message, metrics) = interpret(exit_code, &stdout, &stderr);
+    debug!(
+        line = %name,
+        exit_code = ?exit_code,
+        status = %status.as_str(),
+        detail = ?message,
+        "Interpreted custom check output"
+    );
+    Ok(CustomReport {
+        command: command_line,
+        status,
+        exit_code,
+        message,
+        metrics,
+        duration_ms,
+    })
+}
+
+/// Status, message and metrics from what the command printed and returned.
+fn interpret(
+    exit_code: Option<i32>,
+    stdout: &str,
+    stderr: &str,
+) -> (LineStatus, Option<String>, BTreeMap<String, f64>) {
+    let mut status = match exit_code {
+        Some(0) => LineStatus::Ok,
+        Some(UNKNOWN_EXIT_CODE) | None => LineStatus::Unknown,
+        Some(_) => LineStatus::Alert,
+    };
+    let first_line = |text: &str| {
+        text.lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+    };
+    let parsed = stdout
+        .trim_start()
+        .starts_with('{')
+        .then(|| serde_json::from_str::<CustomOutput>(stdout).ok())
+        .flatten();
+    let Some(parsed) = parsed else {
+        let message = first_line(stdout).or_else(|| first_line(stderr));
+        return (status, message, BTreeMap::new());
+    };
+    let mut message = parsed.message;
+    match parsed.status.as_deref().map(str::to_lowercase).as_deref() {
+        None => {}
+        Some("ok") => status = LineStatus::Ok,
+        Some("alert") => status = LineStatus::Alert,
+        Some("unknown") => status = LineStatus::Unknown,
+        Some(other) => {
+            status = LineStatus::Unknown;
+            message = Some(format!("command reported unrecognised status '{other}'"));
+        }
+    }
+    (status, message, parsed.metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interprets_exit_codes_and_json_output() {
+        assert_eq!(
+            command_args(
+                "/usr/local/bin/check_vdsl.sh --line {name} {target}",
+                "A",
+                "10.0.0.1"
+            )
+            .unwrap(),
+            vec!["/usr/local/bin/check_vdsl.sh", "--line", "A", "10.0.0.1"]
+        );
+        assert!(command_args("  ", "A", "10.0.0.1").is_err());
+
+        let (status, message, _) = interpret(Some(0), "SNR OK\nmore detail\n", "");
+        assert_eq!(status, LineStatus::Ok);
+        assert_eq!(message.as_deref(), Some("SNR OK"));
+        let (status, message, _) = interpret(Some(2), "", "modem unreachable\n");
+        assert_eq!(status, LineStatus::Alert);
+        assert_eq!(message.as_deref(), Some("modem unreachable"));
+        assert_eq!(interpret(Some(3), "", "").0, LineStatus::Unknown);
+        assert_eq!(interpret(None, "", "").0, LineStatus::Unknown);
+
+        let (status, message, metrics) = interpret(
+            Some(0),
+            r#"{"status": "ALERT", "message": "SNR 4.5 dB", "metrics": {"snr_db": 4.5}}"#,
+            "",
+        );
+        assert_eq!(status, LineStatus::Alert);
+        assert_eq!(message.as_deref(), Some("SNR 4.5 dB"));
+        assert_eq!(metrics["snr_db"], 4.5);
+        assert_eq!(
+            interpret(Some(0), r#"{"status": "degraded"}"#, "").0,
+            LineStatus::Unknown
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Encrypted DNS (DoH / DoT) resolver latency probe.
+
+use std::fmt;
+use std::io::Read;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+
+use super::tls::{client_config, split_endpoint};
+use crate::resolver;
+
+pub(crate) const DNS_TYPE_A: u16 = 1;
+pub(crate) const DNS_TYPE_AAAA: u16 = 28;
+const DNS_CLASS_IN: u16 = 1;
+pub(crate) const DNS_HEADER_LEN: usize = 12;
+const MAX_DOH_RESPONSE_BYTES: u64 = 64 * 1024;
+
+/// Encrypted transport used to reach a resolver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsTransport {
+    Doh,
+    Dot,
+}
+
+impl fmt::Display for DnsTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnsTransport::Doh => f.write_str("DoH"),
+            DnsTransport::Dot => f.write_str("DoT"),
+        }
+    }
+}
+
+/// Outcome of one encrypted DNS query.
+#[derive(Debug, Clone)]
+pub struct DnsReport {
+    pub transport: DnsTransport,
+    pub resolver: String,
+    pub success: bool,
+    pub latency_ms: Option<f32>,
+    pub answers: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl DnsReport {
+    /// Whether the query took longer than `alert_ms`.
+    pub fn latency_breached(&self, alert_ms: f32) -> bool {
+        self.latency_ms.is_some_and(|latency| latency > alert_ms)
+    }
+}
+
+/// Query `name` (A record) over DNS-over-HTTPS (RFC 8484 POST) at `url`.
+pub async fn run_doh_probe(url: &str, name: &str, timeout: Duration) -> Result<DnsReport> {
+    let id = query_id();
+    let query = build_query(id, name, DNS_TYPE_A)?;
+    let url_owned = url.to_string();
+
+    let resolver = resolver::ureq_resolver();
+    let outcome = tokio::task::spawn_blocking(move || -> Result<(Vec<u8>, f32)> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(timeout)
+            .resolver(resolver)
+            .build();
+        let started = Instant::now();
+        let response = agent
+            .post(&url_owned)
+            .set("content-type", "application/dns-message")
+            .set("accept", "application/dns-message")
+            .send_bytes(&query)?;
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(MAX_DOH_RESPONSE_BYTES)
+            .read_to_end(&mut body)?;
+        Ok((body, started.elapsed().as_secs_f32() * 1000.0))
+    })
+    .await
+    .context("DoH probe task panicked")?;
+
+    Ok(match outcome {
+        Ok((body, latency)) => evaluated_report(DnsTransport::Doh, url, id, &body, latency),
+        Err(err) => failed_report(DnsTransport::Doh, url, format!("{err:#}")),
+    })
+}
+
+/// Query `name` (A record) over DNS-over-TLS at `endpoint` (`host:port`, usually port 853).
+pub async fn run_dot_probe(endpoint: &str, name: &str, timeout: Duration) -> Result<DnsReport> {
+    let (host, port) = split_endpoint(endpoint)?;
+    let server_name = ServerName::try_from(host.to_string())
+        .with_context(|| format!("Invalid DoT server name '{host}'"))?;
+    let id = query_id();
+    let query = build_query(id, name, DNS_TYPE_A)?;
+
+    let started = Instant::now();
+    let attempt = tokio::time::timeout(timeout, async {
+        let addresses = resolver::shared().resolve(host, port).await?;
+        let tcp = TcpStream::connect(&addresses[..]).await?;
+        let mut tls = TlsConnector::from(client_config())
+            .connect(server_name, tcp)
+            .await?;
+
+        // DNS over TCP frames each message with a two-byte length prefix.
+        let mut framed = Vec::with_capacity(query.len() + 2);
+        framed.extend_from_slice(&(query.len() as u16).to_be_bytes());
+        framed.extend_from_slice(&query);
+        tls.write_all(&framed).await?;
+
+        let len = tls.read_u16().await? as usize;
+        let mut body = vec![0u8; len];
+        tls.read_exact(&mut body).await?;
+        Ok::<_, std::io::Error>(body)
+    })
+    .await;
+
+    Ok(match attempt {
+        Ok(Ok(body)) => {
+            let latency = started.elapsed().as_secs_f32() * 1000.0;
+            evaluated_report(DnsTransport::Dot, endpoint, id, &body, latency)
+        }
+        Ok(Err(err)) => failed_report(DnsTransport::Dot, endpoint, err.to_string()),
+        Err(_) => failed_report(
+            DnsTransport::Dot,
+            endpoint,
+            format!("no answer within {} ms", timeout.as_millis()),
+        ),
+    })
+}
+
+fn evaluated_report(
+    transport: DnsTransport,
+    resolver: &str,
+    id: u16,
+    body: &[u8],
+    latency_ms: f32,
+) -> DnsReport {
+    match parse_response(id, body) {
+        Ok(answers) => DnsReport {
+            transport,
+            resolver: resolver.to_string(),
+            success: true,
+            latency_ms: Some(latency_ms),
+            answers: Some(answers),
+            error: None,
+        },
+        Err(err) => DnsReport {
+            latency_ms: Some(latency_ms),
+            ..failed_report(transport, resolver, format!("{err:#}"))
+        },
+    }
+}
+
+fn failed_report(transport: DnsTransport, resolver: &str, error: String) -> DnsReport {
+    DnsReport {
+        transport,
+        resolver: resolver.to_string(),
+        success: false,
+        latency_ms: None,
+        answers: None,
+        error: Some(error),
+    }
+}
+
+pub(crate) fn query_id() -> u16 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u16)
+        .unwrap_or(0)
+}
+
+/// Encode a recursive query for `name` of record type `qtype`.
+pub(crate) fn build_query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(DNS_HEADER_LEN + name.len() + 6);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&0x0100u16.to_be_bytes()); // RD=1
+    packet.extend_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+    packet.extend_from_slice(&[0; 6]); // AN/NS/AR counts
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            anyhow::bail!("Invalid DNS query name '{name}'");
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+    Ok(packet)
+}
+
+/// Validate a response header and return its answer count.
+fn parse_response(id: u16, body: &[u8]) -> Result<u16> {
+    if body.len() < DNS_HEADER_LEN {
+        anyhow::bail!("short DNS response ({} bytes)", body.len());
+    }
+    let response_id = u16::from_be_bytes([body[0], body[1]]);
+    if response_id != id {
+        anyhow::bail!("DNS response ID {response_id} does not match query {id}");
+    }
+    if body[2] & 0x80 == 0 {
+        anyhow::bail!("DNS message is not a response");
+    }
+    let rcode = body[3] & 0x0f;
+    if rcode != 0 {
+        anyhow::bail!("resolver returned rcode {rcode}");
+    }
+    Ok(u16::from_be_bytes([body[6], body[7]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_query_labels() {
+        let packet = build_query(0xabcd, "example.com.", DNS_TYPE_A).unwrap();
+        assert_eq!(&packet[..2], &[0xab, 0xcd]);
+        assert_eq!(
+            &packet[DNS_HEADER_LEN..],
+            b"\x07example\x03com\x00\x00\x01\x00\x01"
+        );
+        assert!(build_query(1, "bad..name", DNS_TYPE_A).is_err());
+    }
+
+    #[test]
+    fn validates_response_header() {
+        let mut response = build_query(7, "example.com", DNS_TYPE_A).unwrap();
+        response[2] |= 0x80;
+        response[7] = 2;
+        assert_eq!(parse_response(7, &response).unwrap(), 2);
+        assert!(parse_response(8, &response).is_err());
+
+        response[3] = 0x03; // NXDOMAIN
+        assert!(
+            parse_response(7, &response)
+                .unwrap_err()
+                .to_string()
+                .contains("rcode 3")
+        );
+    }
+}
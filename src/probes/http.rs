@@ -0,0 +1,75 @@
+//! HTTP(S) reachability and latency probe.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::resolver;
+
+/// Outcome of a single HTTP GET.
+#[derive(Debug, Clone)]
+pub struct HttpReport {
+    pub url: String,
+    pub success: bool,
+    pub status_code: Option<u16>,
+    pub latency_ms: Option<f32>,
+    pub error: Option<String>,
+}
+
+impl HttpReport {
+    /// Whether the request took longer than `alert_ms`.
+    pub fn latency_breached(&self, alert_ms: f32) -> bool {
+        self.latency_ms.is_some_and(|latency| latency > alert_ms)
+    }
+}
+
+/// Fetch `url` and time the response headers.
+///
+/// Success means the status matches `expected_status`, or is 2xx/3xx when no
+/// specific status is expected. Connection failures are reported, not raised.
+pub async fn run_http_probe(
+    url: &str,
+    expected_status: Option<u16>,
+    timeout: Duration,
+) -> Result<HttpReport> {
+    let url_owned = url.to_string();
+    let resolver = resolver::ureq_resolver();
+    let outcome = tokio::task::spawn_blocking(move || {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(timeout)
+            .resolver(resolver)
+            .build();
+        let started = Instant::now();
+        let status = match agent.get(&url_owned).call() {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(code, _)) => Ok(code),
+            Err(err) => Err(err.to_string()),
+        };
+        (status, started.elapsed().as_secs_f32() * 1000.0)
+    })
+    .await
+    .context("HTTP probe task panicked")?;
+
+    Ok(match outcome {
+        (Ok(code), latency) => {
+            let success = match expected_status {
+                Some(expected) => code == expected,
+                None => (200..400).contains(&code),
+            };
+            HttpReport {
+                url: url.to_string(),
+                success,
+                status_code: Some(code),
+                latency_ms: Some(latency),
+                error: (!success).then(|| format!("unexpected HTTP status {code}")),
+            }
+        }
+        (Err(error), _) => HttpReport {
+            url: url.to_string(),
+            success: false,
+            status_code: None,
+            latency_ms: None,
+            error: Some(error),
+        },
+    })
+}
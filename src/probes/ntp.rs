@@ -0,0 +1,220 @@
+//! SNTP offset/delay probe.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tokio::net::UdpSocket;
+
+use super::tls::split_endpoint;
+use crate::resolver;
+
+/// Seconds between the NTP era start (1900) and the unix epoch (1970).
+const NTP_UNIX_OFFSET_SECS: f64 = 2_208_988_800.0;
+const NTP_PACKET_LEN: usize = 48;
+const NTP_DEFAULT_PORT: u16 = 123;
+
+/// Outcome of a single SNTP exchange.
+#[derive(Debug, Clone)]
+pub struct NtpReport {
+    pub server: String,
+    pub success: bool,
+    pub offset_ms: Option<f32>,
+    pub delay_ms: Option<f32>,
+    pub stratum: Option<u8>,
+    pub error: Option<String>,
+}
+
+impl NtpReport {
+    /// Whether the absolute clock offset exceeds `alert_ms`.
+    pub fn offset_breached(&self, alert_ms: f32) -> bool {
+        self.offset_ms.is_some_and(|offset| offset.abs() > alert_ms)
+    }
+}
+
+/// Query `server` (host or host:port) once and compute local clock offset and round-trip delay.
+///
+/// Timeouts and malformed replies produce an unsuccessful report rather than an error.
+pub async fn run_ntp_probe(server: &str, timeout: Duration) -> Result<NtpReport> {
+    let address = with_default_port(server);
+
+    let attempt = tokio::time::timeout(timeout, async {
+        let (host, port) = split_endpoint(&address).map_err(std::io::Error::other)?;
+        let remote = resolver::shared().resolve(host, port).await?[0];
+        let local = if remote.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(remote).await?;
+
+        let mut request = [0u8; NTP_PACKET_LEN];
+        // LI = 0 (no warning), VN = 4, Mode = 3 (client).
+        request[0] = 0b00_100_011;
+        let t1 = unix_now_secs();
+        request[40..48].copy_from_slice(&to_ntp_timestamp(t1));
+        socket.send(&request).await?;
+
+        let mut response = [0u8; NTP_PACKET_LEN];
+        let len = socket.recv(&mut response).await?;
+        let t4 = unix_now_secs();
+        Ok::<_, std::io::Error>((response, len, t1, t4))
+    })
+    .await;
+
+    let report = match attempt {
+        Ok(Ok((response, len, t1, t4))) => match evaluate_response(&response[..len], t1, t4) {
+            Ok(sample) => NtpReport {
+                server: server.to_string(),
+                success: true,
+                offset_ms: Some((sample.offset_secs * 1000.0) as f32),
+                delay_ms: Some((sample.delay_secs * 1000.0) as f32),
+                stratum: Some(sample.stratum),
+                error: None,
+            },
+            Err(err) => failed_report(server, format!("{err:#}")),
+        },
+        Ok(Err(err)) => failed_report(server, err.to_string()),
+        Err(_) => failed_report(
+            server,
+            format!("no reply within {} ms", timeout.as_millis()),
+        ),
+    };
+    Ok(report)
+}
+
+/// Append the NTP port unless `server` already names one; bare IPv6 literals are bracketed.
+fn with_default_port(server: &str) -> String {
+    match server.matches(':').count() {
+        0 => format!("{server}:{NTP_DEFAULT_PORT}"),
+        1 => server.to_string(),
+        _ if server.starts_with('[') && server.contains("]:") => server.to_string(),
+        _ if server.starts_with('[') => format!("{server}:{NTP_DEFAULT_PORT}"),
+        _ => format!("[{server}]:{NTP_DEFAULT_PORT}"),
+    }
+}
+
+#[derive(Debug)]
+struct NtpSample {
+    offset_secs: f64,
+    delay_secs: f64,
+    stratum: u8,
+}
+
+/// Derive offset/delay from a server reply using the standard four-timestamp formula.
+fn evaluate_response(packet: &[u8], t1: f64, t4: f64) -> Result<NtpSample> {
+    if packet.len() < NTP_PACKET_LEN {
+        anyhow::bail!("short NTP reply ({} bytes)", packet.len());
+    }
+    let mode = packet[0] & 0b111;
+    if mode != 4 {
+        anyhow::bail!("unexpected NTP mode {mode} in reply");
+    }
+    let stratum = packet[1];
+    if stratum == 0 {
+        let code = String::from_utf8_lossy(&packet[12..16]).into_owned();
+        anyhow::bail!("server sent kiss-of-death ({code})");
+    }
+
+    let t2 = from_ntp_timestamp(&packet[32..40]).context("missing receive timestamp")?;
+    let t3 = from_ntp_timestamp(&packet[40..48]).context("missing transmit timestamp")?;
+
+    Ok(NtpSample {
+        offset_secs: ((t2 - t1) + (t3 - t4)) / 2.0,
+        delay_secs: (t4 - t1) - (t3 - t2),
+        stratum,
+    })
+}
+
+fn failed_report(server: &str, error: String) -> NtpReport {
+    NtpReport {
+        server: server.to_string(),
+        success: false,
+        offset_ms: None,
+        delay_ms: None,
+        stratum: None,
+        error: Some(error),
+    }
+}
+
+fn unix_now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+fn to_ntp_timestamp(unix_secs: f64) -> [u8; 8] {
+    let ntp = unix_secs + NTP_UNIX_OFFSET_SECS;
+    let secs = ntp.trunc() as u32;
+    let fraction = (ntp.fract() * f64::from(u32::MAX)) as u32;
+    let mut out = [0u8; 8];
+    out[..4].copy_from_slice(&secs.to_be_bytes());
+    out[4..].copy_from_slice(&fraction.to_be_bytes());
+    out
+}
+
+fn from_ntp_timestamp(bytes: &[u8]) -> Option<f64> {
+    let secs = u32::from_be_bytes(bytes[..4].try_into().ok()?);
+    let fraction = u32::from_be_bytes(bytes[4..8].try_into().ok()?);
+    if secs == 0 && fraction == 0 {
+        return None;
+    }
+    Some(f64::from(secs) - NTP_UNIX_OFFSET_SECS + f64::from(fraction) / f64::from(u32::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(stratum: u8, t2: f64, t3: f64) -> [u8; NTP_PACKET_LEN] {
+        let mut packet = [0u8; NTP_PACKET_LEN];
+        packet[0] = 0b00_100_100;
+        packet[1] = stratum;
+        packet[32..40].copy_from_slice(&to_ntp_timestamp(t2));
+        packet[40..48].copy_from_slice(&to_ntp_timestamp(t3));
+        packet
+    }
+
+    #[test]
+    fn appends_default_port() {
+        assert_eq!(with_default_port("pool.ntp.org"), "pool.ntp.org:123");
+        assert_eq!(with_default_port("10.0.0.1:1123"), "10.0.0.1:1123");
+        assert_eq!(with_default_port("2001:db8::1"), "[2001:db8::1]:123");
+        assert_eq!(
+            with_default_port("[2001:db8::1]:1123"),
+            "[2001:db8::1]:1123"
+        );
+    }
+
+    #[test]
+    fn computes_offset_and_delay() {
+        let t1 = 1_700_000_000.0;
+        // Server clock runs 0.5s ahead; 20ms each way; 1ms processing.
+        let packet = reply(2, t1 + 0.020 + 0.5, t1 + 0.021 + 0.5);
+        let sample = evaluate_response(&packet, t1, t1 + 0.041).unwrap();
+
+        assert!((sample.offset_secs - 0.5).abs() < 1e-3);
+        assert!((sample.delay_secs - 0.040).abs() < 1e-3);
+        assert_eq!(sample.stratum, 2);
+    }
+
+    #[test]
+    fn rejects_kiss_of_death_and_short_replies() {
+        let mut packet = reply(0, 1.0, 1.0);
+        packet[12..16].copy_from_slice(b"RATE");
+        let err = evaluate_response(&packet, 0.0, 0.0).unwrap_err();
+        assert!(err.to_string().contains("RATE"));
+
+        assert!(evaluate_response(&packet[..20], 0.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn offset_threshold_uses_absolute_value() {
+        let mut report = failed_report("pool.ntp.org", String::new());
+        report.offset_ms = Some(-750.0);
+        assert!(report.offset_breached(500.0));
+        report.offset_ms = Some(120.0);
+        assert!(!report.offset_breached(500.0));
+    }
+}
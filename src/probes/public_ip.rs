@@ -0,0 +1,286 @@
+//! Public IP lookup through a what-is-my-ip endpoint.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+
+use super::tls::client_config;
+use crate::enrichment::{is_cgnat, is_public};
+use crate::resolver;
+
+/// Largest response accepted from the lookup endpoint.
+const MAX_RESPONSE_BYTES: usize = 16 * 1024;
+
+/// Public address reported by the lookup endpoint for one line.
+#[derive(Debug, Clone)]
+pub struct PublicIpReport {
+    pub url: String,
+    pub success: bool,
+    pub address: Option<IpAddr>,
+    pub error: Option<String>,
+}
+
+/// Address translation found between a line's WAN address and its public IP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Translation {
+    /// The WAN address is in 100.64.0.0/10: the ISP runs carrier-grade NAT.
+    CarrierGrade,
+    /// The WAN address is public but the Internet sees another one.
+    Upstream,
+}
+
+impl Translation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Translation::CarrierGrade => "carrier-grade NAT",
+            Translation::Upstream => "upstream NAT",
+        }
+    }
+}
+
+impl PublicIpReport {
+    /// Translation between `wan` and the observed public address, if any.
+    ///
+    /// A private `wan` is ordinary home-router NAT and is not reported.
+    pub fn translation(&self, wan: IpAddr) -> Option<Translation> {
+        let public = self.address.filter(|public| *public != wan)?;
+        if public.is_ipv4() != wan.is_ipv4() {
+            return None;
+        }
+        if is_cgnat(&wan) {
+            Some(Translation::CarrierGrade)
+        } else if is_public(&wan) {
+            Some(Translation::Upstream)
+        } else {
+            None
+        }
+    }
+}
+
+/// Fetch `url` (which must answer with a bare IP address) and parse the result.
+///
+/// When `source` is set the connection is bound to that local address, so the
+/// lookup leaves through the line's own interface on multi-WAN hosts. Network
+/// failures are reported rather than raised.
+pub async fn run_public_ip_probe(
+    url: &str,
+    source: Option<IpAddr>,
+    timeout: Duration,
+) -> Result<PublicIpReport> {
+    let target = LookupUrl::parse(url)?;
+    let outcome = tokio::time::timeout(timeout, fetch(&target, source))
+        .await
+        .unwrap_or_else(|_| {
+            Err(anyhow::anyhow!(
+                "lookup timed out after {} ms",
+                timeout.as_millis()
+            ))
+        });
+
+    Ok(match outcome.and_then(|body| parse_address(&body)) {
+        Ok(address) => PublicIpReport {
+            url: url.to_string(),
+            success: true,
+            address: Some(address),
+            error: None,
+        },
+        Err(err) => PublicIpReport {
+            url: url.to_string(),
+            success: false,
+            address: None,
+            error: Some(format!("{err:#}")),
+        },
+    })
+}
+
+/// Components of an `http://` or `https://` lookup URL.
+#[derive(Debug, PartialEq)]
+struct LookupUrl<'a> {
+    tls: bool,
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+impl<'a> LookupUrl<'a> {
+    fn parse(url: &'a str) -> Result<Self> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            anyhow::bail!("Public IP URL '{url}' must start with http:// or https://");
+        };
+        let (authority, path) = rest.find('/').map_or((rest, "/"), |idx| rest.split_at(idx));
+        let default_port = if tls { 443 } else { 80 };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                port.parse()
+                    .with_context(|| format!("Invalid port in public IP URL '{url}'"))?,
+            ),
+            _ => (authority, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            anyhow::bail!("Public IP URL '{url}' has an empty host");
+        }
+        Ok(Self {
+            tls,
+            host,
+            port,
+            path,
+        })
+    }
+}
+
+async fn fetch(target: &LookupUrl<'_>, source: Option<IpAddr>) -> Result<String> {
+    let remote = resolver::shared()
+        .resolve(target.host, target.port)
+        .await
+        .with_context(|| format!("failed to resolve {}", target.host))?
+        .into_iter()
+        .find(|addr| source.is_none_or(|source| source.is_ipv4() == addr.is_ipv4()))
+        .with_context(|| format!("{} has no address matching the source", target.host))?;
+    let tcp = connect(remote, source).await?;
+
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: ICMPMolester\r\nAccept: text/plain\r\nConnection: close\r\n\r\n",
+        target.path, target.host
+    );
+    let response = if target.tls {
+        let server_name = ServerName::try_from(target.host.to_string())
+            .with_context(|| format!("invalid TLS server name '{}'", target.host))?;
+        let stream = TlsConnector::from(client_config())
+            .connect(server_name, tcp)
+            .await
+            .context("TLS handshake failed")?;
+        exchange(stream, &request).await?
+    } else {
+        exchange(tcp, &request).await?
+    };
+    response_body(&response)
+}
+
+async fn connect(remote: SocketAddr, source: Option<IpAddr>) -> Result<TcpStream> {
+    let Some(source) = source else {
+        return Ok(TcpStream::connect(remote).await?);
+    };
+    let socket = if source.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket
+        .bind(SocketAddr::new(source, 0))
+        .with_context(|| format!("failed to bind source address {source}"))?;
+    Ok(socket.connect(remote).await?)
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    request: &str,
+) -> Result<Vec<u8>> {
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    let mut chunk = [0u8; 2048];
+    loop {
+        match stream.read(&mut chunk).await {
+            Ok(0) => break,
+            Ok(read) => response.extend_from_slice(&chunk[..read]),
+            // Many servers close without a TLS close_notify once the body is sent.
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof && !response.is_empty() => {
+                break;
+            }
+            Err(err) => return Err(err.into()),
+        }
+        if response.len() > MAX_RESPONSE_BYTES {
+            anyhow::bail!("response larger than {MAX_RESPONSE_BYTES} bytes");
+        }
+    }
+    Ok(response)
+}
+
+/// Body of a successful HTTP/1.x response.
+fn response_body(response: &[u8]) -> Result<String> {
+    let text = String::from_utf8_lossy(response);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .context("malformed HTTP response")?;
+    let code = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .context("malformed HTTP status line")?;
+    if code != 200 {
+        anyhow::bail!("endpoint returned HTTP {code}");
+    }
+    Ok(body.to_string())
+}
+
+fn parse_address(body: &str) -> Result<IpAddr> {
+    let trimmed = body.trim();
+    trimmed
+        .parse()
+        .with_context(|| format!("response '{trimmed}' is not an IP address"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lookup_urls() {
+        assert_eq!(
+            LookupUrl::parse("https://api.ipify.org").unwrap(),
+            LookupUrl {
+                tls: true,
+                host: "api.ipify.org",
+                port: 443,
+                path: "/",
+            }
+        );
+        assert_eq!(
+            LookupUrl::parse("http://[2001:db8::1]:8080/ip?format=text").unwrap(),
+            LookupUrl {
+                tls: false,
+                host: "2001:db8::1",
+                port: 8080,
+                path: "/ip?format=text",
+            }
+        );
+        assert!(LookupUrl::parse("ftp://example.com").is_err());
+    }
+
+    #[test]
+    fn extracts_address_from_response() {
+        let ok = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n203.0.113.7\n";
+        assert_eq!(
+            parse_address(&response_body(ok).unwrap()).unwrap(),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        assert!(response_body(b"HTTP/1.1 429 Too Many Requests\r\n\r\n").is_err());
+        assert!(parse_address("<html>").is_err());
+    }
+
+    #[test]
+    fn detects_carrier_grade_nat() {
+        let report = PublicIpReport {
+            url: "https://api.ipify.org".into(),
+            success: true,
+            address: Some("203.0.113.7".parse().unwrap()),
+            error: None,
+        };
+        let translation = |wan: &str| report.translation(wan.parse().unwrap());
+        assert_eq!(translation("100.72.4.9"), Some(Translation::CarrierGrade));
+        assert_eq!(translation("198.51.100.2"), Some(Translation::Upstream));
+        assert_eq!(translation("203.0.113.7"), None);
+        assert_eq!(translation("192.168.1.2"), None);
+        assert_eq!(translation("100.128.0.1"), Some(Translation::Upstream));
+    }
+}
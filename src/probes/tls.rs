@@ -0,0 +1,167 @@
+//! TLS handshake and certificate expiry probe.
+
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, ProtocolVersion, RootCertStore};
+
+use crate::resolver;
+
+/// Outcome of a TLS handshake against a `host:port` endpoint.
+#[derive(Debug, Clone)]
+pub struct TlsReport {
+    pub endpoint: String,
+    pub success: bool,
+    pub handshake_ms: Option<f32>,
+    pub protocol: Option<String>,
+    pub days_until_expiry: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl TlsReport {
+    /// Whether the leaf certificate expires within `alert_days`.
+    pub fn expiry_breached(&self, alert_days: u32) -> bool {
+        self.days_until_expiry
+            .is_some_and(|days| days < i64::from(alert_days))
+    }
+}
+
+/// Connect to `endpoint`, complete a verified TLS handshake, and inspect the leaf certificate.
+///
+/// Network and verification failures are reported in the returned report
+/// rather than as errors, mirroring how a failed ping is an ALERT not a crash.
+pub async fn run_tls_probe(endpoint: &str, timeout: Duration) -> Result<TlsReport> {
+    let (host, port) = split_endpoint(endpoint)?;
+    let server_name = ServerName::try_from(host.to_string())
+        .with_context(|| format!("Invalid TLS server name '{host}'"))?;
+
+    let started = Instant::now();
+    let attempt = tokio::time::timeout(timeout, async {
+        let addresses = resolver::shared().resolve(host, port).await?;
+        let tcp = TcpStream::connect(&addresses[..]).await?;
+        TlsConnector::from(client_config())
+            .connect(server_name, tcp)
+            .await
+    })
+    .await;
+
+    let stream = match attempt {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(err)) => return Ok(failed_report(endpoint, err.to_string())),
+        Err(_) => {
+            return Ok(failed_report(
+                endpoint,
+                format!("handshake timed out after {} ms", timeout.as_millis()),
+            ));
+        }
+    };
+    let handshake_ms = started.elapsed().as_secs_f32() * 1000.0;
+
+    let (_, session) = stream.get_ref();
+    let protocol = session.protocol_version().map(protocol_label);
+    let days_until_expiry = session
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .and_then(|leaf| leaf_not_after(leaf.as_ref()))
+        .map(|not_after| (not_after - Utc::now().timestamp()).div_euclid(86_400));
+
+    Ok(TlsReport {
+        endpoint: endpoint.to_string(),
+        success: true,
+        handshake_ms: Some(handshake_ms),
+        protocol,
+        days_until_expiry,
+        error: None,
+    })
+}
+
+fn failed_report(endpoint: &str, error: String) -> TlsReport {
+    TlsReport {
+        endpoint: endpoint.to_string(),
+        success: false,
+        handshake_ms: None,
+        protocol: None,
+        days_until_expiry: None,
+        error: Some(error),
+    }
+}
+
+pub(crate) fn client_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let mut roots = RootCertStore::empty();
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+            let config = ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .expect("ring provider supports the default protocol versions")
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// Split `host:port`, accepting bracketed IPv6 literals such as `[2001:db8::1]:443`.
+pub(super) fn split_endpoint(endpoint: &str) -> Result<(&str, u16)> {
+    let (host, port) = endpoint
+        .rsplit_once(':')
+        .with_context(|| format!("TLS endpoint '{endpoint}' must be in host:port form"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = port
+        .parse::<u16>()
+        .with_context(|| format!("Invalid port in TLS endpoint '{endpoint}'"))?;
+    if host.is_empty() {
+        anyhow::bail!("TLS endpoint '{endpoint}' has an empty host");
+    }
+    Ok((host, port))
+}
+
+fn protocol_label(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_3 => "TLSv1.3".into(),
+        ProtocolVersion::TLSv1_2 => "TLSv1.2".into(),
+        other => format!("{other:?}"),
+    }
+}
+
+fn leaf_not_after(der: &[u8]) -> Option<i64> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    Some(cert.validity().not_after.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(
+            split_endpoint("vpn.example.com:443").unwrap(),
+            ("vpn.example.com", 443)
+        );
+        assert_eq!(
+            split_endpoint("[2001:db8::1]:8443").unwrap(),
+            ("2001:db8::1", 8443)
+        );
+        assert!(split_endpoint("vpn.example.com").is_err());
+        assert!(split_endpoint(":443").is_err());
+    }
+
+    #[test]
+    fn flags_certificates_expiring_inside_threshold() {
+        let mut report = failed_report("vpn.example.com:443", String::new());
+        assert!(!report.expiry_breached(14));
+
+        report.days_until_expiry = Some(10);
+        assert!(report.expiry_breached(14));
+        report.days_until_expiry = Some(30);
+        assert!(!report.expiry_breached(14));
+    }
+}
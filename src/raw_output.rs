@@ -0,0 +1,157 @@
+//! Raw ping and traceroute output kept on disk for `--raw-output-dir` or
+//! attached to alert emails, as evidence beyond the parsed summary.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::report::{RunMetadata, RunReport};
+use crate::runner::{LineResult, LineStatus};
+
+/// Write each line's raw output under `<dir>/<run start>/`, as
+/// `<line>-ping.txt` and `<line>-traceroute.txt`, and return that directory.
+/// Lines whose checks did not run leave no files.
+pub fn save(dir: &Path, results: &[LineResult], run: &RunMetadata) -> Result<PathBuf> {
+    let run_dir = dir.join(run.started_at.format("%Y-%m-%dT%H-%M-%SZ").to_string());
+    fs::create_dir_all(&run_dir)
+        .with_context(|| format!("Failed to create {}", run_dir.display()))?;
+    for result in results {
+        for (file_name, output) in outputs(result) {
+            let path = run_dir.join(file_name);
+            fs::write(&path, output)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+    }
+    Ok(run_dir)
+}
+
+/// The raw output of every line in ALERT as `(file name, contents)` pairs,
+/// named as [`save`] names its files, for attaching to an email. With
+/// `json`, each such line's result also comes as `<line>.json`, in the
+/// [`RunReport`] schema.
+pub fn alert_attachments(
+    results: &[LineResult],
+    run: &RunMetadata,
+    json: bool,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut attachments = Vec::new();
+    for result in results
+        .iter()
+        .filter(|result| result.status() == LineStatus::Alert)
+    {
+        for (file_name, output) in outputs(result) {
+            attachments.push((file_name, output.as_bytes().to_vec()));
+        }
+        if json {
+            let report = RunReport::new(std::slice::from_ref(result), run);
+            let body = serde_json::to_vec_pretty(&report)
+                .context("Failed to serialize the line's result")?;
+            attachments.push((format!("{}.json", file_stem(&result.key())), body));
+        }
+    }
+    Ok(attachments)
+}
+
+/// `<line>-ping.txt` and `<line>-traceroute.txt` with the output of the
+/// checks that ran.
+fn outputs(result: &LineResult) -> impl Iterator<Item = (String, &String)> {
+    let name = file_stem(&result.key());
+    [
+        ("ping", result.ping().map(|ping| &ping.raw_output)),
+        (
+            "traceroute",
+            result.traceroute().map(|traceroute| &traceroute.raw_output),
+        ),
+    ]
+    .into_iter()
+    .filter_map(move |(check, output)| Some((format!("{name}-{check}.txt"), output?)))
+}
+
+/// A line key as a file name: anything but letters, digits, `.`, `-` and `_`
+/// becomes `_`, so batch separators cannot create subdirectories.
+pub fn file_stem(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckOutcome;
+    use crate::runner::tests::sample_result;
+    use chrono::{TimeZone, Utc};
+
+    fn backup_line(loss: f32) -> LineResult {
+        let mut result = sample_result("Backup LTE", true, Some(loss), Some(30.0), 1.0, Some(true));
+        result.target = "10.0.0.2".into();
+        result.batch = Some("site-a".into());
+        for check in &mut result.checks {
+            match check {
+                CheckOutcome::Ping { report, .. } => {
+                    report.raw_output = "4 packets transmitted, 4 received\n".into();
+                }
+                CheckOutcome::Traceroute {
+                    report: Some(report),
+                    ..
+                } => report.raw_output = " 1  10.0.0.2  1.2 ms\n".into(),
+                _ => {}
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn writes_ping_and_traceroute_output_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let run_dir = save(dir.path(), &[backup_line(0.0)], &RunMetadata::new(at, at)).unwrap();
+        assert_eq!(run_dir, dir.path().join("2026-05-01T12-00-00Z"));
+        assert_eq!(
+            fs::read_to_string(run_dir.join("site-a_Backup_LTE-ping.txt")).unwrap(),
+            "4 packets transmitted, 4 received\n"
+        );
+        assert_eq!(
+            fs::read_to_string(run_dir.join("site-a_Backup_LTE-traceroute.txt")).unwrap(),
+            " 1  10.0.0.2  1.2 ms\n"
+        );
+    }
+
+    #[test]
+    fn attaches_output_of_alert_lines() {
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let run = RunMetadata::new(at, at);
+        assert!(
+            alert_attachments(&[backup_line(0.0)], &run, true)
+                .unwrap()
+                .is_empty()
+        );
+
+        let attachments = alert_attachments(&[backup_line(50.0)], &run, true).unwrap();
+        let names: Vec<&str> = attachments.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "site-a_Backup_LTE-ping.txt",
+                "site-a_Backup_LTE-traceroute.txt",
+                "site-a_Backup_LTE.json"
+            ]
+        );
+        assert_eq!(attachments[0].1, b"4 packets transmitted, 4 received\n");
+        let json: serde_json::Value = serde_json::from_slice(&attachments[2].1).unwrap();
+        assert_eq!(json["lines"][0]["status"], "ALERT");
+        assert_eq!(
+            alert_attachments(&[backup_line(50.0)], &run, false)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,448 @@
+//! `--redact`: addresses and hostnames replaced with stand-ins in everything
+//! meant to be shared, so diagnostics can be posted publicly without
+//! exposing internal addressing.
+//!
+//! Stand-ins are themselves addresses (from the documentation ranges) and
+//! `.example` names, so reports keep their shape and the same address reads
+//! the same everywhere in a run's output. The mapping lives for the process,
+//! keeping `watch` runs consistent with each other.
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Mutex, OnceLock};
+
+use clap::ValueEnum;
+use regex::Regex;
+
+use crate::checks::CheckOutcome;
+use crate::diagnostics::PingReport;
+use crate::report::RunMetadata;
+use crate::runner::LineResult;
+use crate::state::HopDiff;
+
+/// How `--redact` replaces addresses and hostnames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RedactMode {
+    /// Numbered stand-ins in order of appearance (192.0.2.1, 192.0.2.2, ...,
+    /// `host-1.example`).
+    Mask,
+    /// Stand-ins derived from a hash of the original, the same in every
+    /// report.
+    Hash,
+}
+
+static REDACTOR: OnceLock<Mutex<Redactor>> = OnceLock::new();
+
+/// Turn redaction on for the rest of the process. The local hostname is
+/// always redacted.
+pub fn init(mode: RedactMode) {
+    let mut redactor = Redactor::new(mode);
+    redactor.register_host(&gethostname::gethostname().to_string_lossy());
+    let _ = REDACTOR.set(Mutex::new(redactor));
+}
+
+fn with<T>(redact: impl FnOnce(&mut Redactor) -> T) -> Option<T> {
+    let redactor = REDACTOR.get()?;
+    let mut redactor = redactor.lock().unwrap_or_else(|err| err.into_inner());
+    Some(redact(&mut redactor))
+}
+
+/// `results` as they may be shown, or as they are when redaction is off.
+pub fn results(results: &[LineResult]) -> Cow<'_, [LineResult]> {
+    with(|redactor| {
+        let mut results = results.to_vec();
+        for result in &results {
+            redactor.register_hosts(result);
+        }
+        for result in &mut results {
+            redactor.result(result);
+        }
+        Cow::Owned(results)
+    })
+    .unwrap_or(Cow::Borrowed(results))
+}
+
+/// `run` with its hostname redacted.
+pub fn run(run: &RunMetadata) -> Cow<'_, RunMetadata> {
+    with(|redactor| {
+        let mut run = run.clone();
+        run.hostname = redactor.text(&run.hostname);
+        Cow::Owned(run)
+    })
+    .unwrap_or(Cow::Borrowed(run))
+}
+
+/// A line's target, redacted like the targets of `results`.
+pub fn target(target: &str) -> Cow<'_, str> {
+    with(|redactor| {
+        redactor.register_host(target);
+        Cow::Owned(redactor.text(target))
+    })
+    .unwrap_or(Cow::Borrowed(target))
+}
+
+/// Free text with every address and known hostname replaced.
+pub fn text(text: &str) -> Cow<'_, str> {
+    with(|redactor| Cow::Owned(redactor.text(text))).unwrap_or(Cow::Borrowed(text))
+}
+
+struct Redactor {
+    mode: RedactMode,
+    /// Original to stand-in. Stand-ins map to themselves, so text that was
+    /// already redacted comes out unchanged.
+    stand_ins: HashMap<String, String>,
+    hosts: BTreeSet<String>,
+    /// Matches every name in `hosts`; rebuilt when one is added.
+    host_pattern: Option<Regex>,
+    ipv4_pattern: Regex,
+    ipv6_pattern: Regex,
+    next_ipv4: u32,
+    next_ipv6: u32,
+    next_host: u32,
+}
+
+impl Redactor {
+    fn new(mode: RedactMode) -> Self {
+        Self {
+            mode,
+            stand_ins: HashMap::new(),
+            hosts: BTreeSet::new(),
+            host_pattern: None,
+            ipv4_pattern: Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b").expect("valid pattern"),
+            ipv6_pattern: Regex::new(r"(?i)[0-9a-f]{0,4}(?::[0-9a-f]{0,4}){2,7}")
+                .expect("valid pattern"),
+            next_ipv4: 0,
+            next_ipv6: 0,
+            next_host: 0,
+        }
+    }
+
+    /// Learn the hostnames a result mentions, so they are also replaced in
+    /// free text such as raw command output.
+    fn register_hosts(&mut self, result: &LineResult) {
+        let mut hosts = vec![result.target.clone()];
+        hosts.extend(result.failed_targets.iter().cloned());
+        for check in &result.checks {
+            match check {
+                CheckOutcome::Traceroute {
+                    report: Some(report),
+                    ..
+                } => hosts.extend(report.hops.iter().filter_map(|hop| hop.hostname.clone())),
+                CheckOutcome::Tls { report, .. } => hosts.push(host_of(&report.endpoint)),
+                CheckOutcome::Ntp { report, .. } => hosts.push(host_of(&report.server)),
+                CheckOutcome::Dns { report, .. } => hosts.push(host_of(&report.resolver)),
+                CheckOutcome::Http { report, .. } => hosts.push(host_of(&report.url)),
+                CheckOutcome::PublicIp { report, .. } => hosts.push(host_of(&report.url)),
+                _ => {}
+            }
+        }
+        for host in hosts {
+            self.register_host(&host);
+        }
+    }
+
+    fn register_host(&mut self, host: &str) {
+        let host = host.trim().trim_end_matches('.').to_lowercase();
+        if host.is_empty() || host.parse::<IpAddr>().is_ok() || self.stand_ins.contains_key(&host) {
+            return;
+        }
+        if self.hosts.insert(host) {
+            self.host_pattern = None;
+        }
+    }
+
+    fn result(&mut self, result: &mut LineResult) {
+        result.target = self.text(&result.target);
+        for target in &mut result.failed_targets {
+            *target = self.text(target);
+        }
+        for text in [&mut result.error, &mut result.note].into_iter().flatten() {
+            *text = self.text(text);
+        }
+        for check in &mut result.checks {
+            self.check(check);
+        }
+    }
+
+    fn check(&mut self, check: &mut CheckOutcome) {
+        match check {
+            CheckOutcome::Ping { report, .. } => self.ping(report),
+            CheckOutcome::Traceroute {
+                report,
+                expected_gateway,
+                route_change,
+                ..
+            } => {
+                if let Some(report) = report {
+                    for hop in &mut report.hops {
+                        hop.address = hop.address.map(|address| self.ip(address));
+                        hop.hostname = hop.hostname.as_deref().map(|name| self.text(name));
+                    }
+                    report.enrichment_error =
+                        report.enrichment_error.as_deref().map(|e| self.text(e));
+                    report.raw_output = self.text(&report.raw_output);
+                }
+                *expected_gateway = expected_gateway.map(|address| self.ip(address));
+                for hop in route_change.iter_mut().flat_map(|change| &mut change.hops) {
+                    match hop {
+                        HopDiff::Same { address, .. }
+                        | HopDiff::Added { address, .. }
+                        | HopDiff::Removed { address, .. } => {
+                            *address = address.map(|address| self.ip(address));
+                        }
+                        HopDiff::Changed { before, after, .. } => {
+                            *before = before.map(|address| self.ip(address));
+                            *after = after.map(|address| self.ip(address));
+                        }
+                    }
+                }
+            }
+            CheckOutcome::Tls { report, .. } => {
+                report.endpoint = self.text(&report.endpoint);
+                report.error = report.error.as_deref().map(|e| self.text(e));
+            }
+            CheckOutcome::Ntp { report, .. } => {
+                report.server = self.text(&report.server);
+                report.error = report.error.as_deref().map(|e| self.text(e));
+            }
+            CheckOutcome::Dns { report, .. } => {
+                report.resolver = self.text(&report.resolver);
+                report.error = report.error.as_deref().map(|e| self.text(e));
+            }
+            CheckOutcome::Http { report, .. } => {
+                report.url = self.text(&report.url);
+                report.error = report.error.as_deref().map(|e| self.text(e));
+            }
+            CheckOutcome::PublicIp {
+                report,
+                changed_from,
+                wan_address,
+                ..
+            } => {
+                report.url = self.text(&report.url);
+                report.address = report.address.map(|address| self.ip(address));
+                report.error = report.error.as_deref().map(|e| self.text(e));
+                *changed_from = changed_from.map(|address| self.ip(address));
+                *wan_address = wan_address.map(|address| self.ip(address));
+            }
+            CheckOutcome::DualStack { report, .. } => {
+                for family in [&mut report.ipv4, &mut report.ipv6].into_iter().flatten() {
+                    family.address = self.ip(family.address);
+                    self.ping(&mut family.report);
+                }
+                report.error = report.error.as_deref().map(|e| self.text(e));
+            }
+            CheckOutcome::Custom { report } => {
+                report.command = self.text(&report.command);
+                report.message = report.message.as_deref().map(|m| self.text(m));
+            }
+        }
+    }
+
+    fn ping(&mut self, report: &mut PingReport) {
+        report.raw_output = self.text(&report.raw_output);
+    }
+
+    fn text(&mut self, text: &str) -> String {
+        if self.host_pattern.is_none() && !self.hosts.is_empty() {
+            let mut names: Vec<String> =
+                self.hosts.iter().map(|host| regex::escape(host)).collect();
+            // Longest first, so a name is not cut short by one it ends with.
+            names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+            let pattern = format!(r"(?i)\b(?:{})\b", names.join("|"));
+            self.host_pattern = Regex::new(&pattern).ok();
+        }
+        let mut text = text.to_string();
+        if let Some(pattern) = self.host_pattern.clone() {
+            text = pattern
+                .replace_all(&text, |found: &regex::Captures| self.host(&found[0]))
+                .into_owned();
+        }
+        let pattern = self.ipv4_pattern.clone();
+        text = pattern
+            .replace_all(&text, |found: &regex::Captures| match found[0].parse() {
+                Ok(address) => self.ip(IpAddr::V4(address)).to_string(),
+                Err(_) => found[0].to_string(),
+            })
+            .into_owned();
+        let pattern = self.ipv6_pattern.clone();
+        pattern
+            .replace_all(&text, |found: &regex::Captures| {
+                let candidate = &found[0];
+                // `::` alone and times like 12:30:00 are not addresses.
+                let address = candidate
+                    .contains(|c: char| c.is_ascii_hexdigit())
+                    .then(|| candidate.parse::<Ipv6Addr>().ok())
+                    .flatten();
+                match address {
+                    Some(address) => self.ip(IpAddr::V6(address)).to_string(),
+                    None => candidate.to_string(),
+                }
+            })
+            .into_owned()
+    }
+
+    fn ip(&mut self, address: IpAddr) -> IpAddr {
+        let key = address.to_string();
+        if let Some(stand_in) = self.stand_ins.get(&key) {
+            return stand_in.parse().unwrap_or(address);
+        }
+        let stand_in = match (address, self.mode) {
+            (IpAddr::V4(_), RedactMode::Mask) => {
+                self.next_ipv4 += 1;
+                IpAddr::V4(documentation_ipv4(self.next_ipv4))
+            }
+            // 198.18.0.0/15 holds 2^17 addresses.
+            (IpAddr::V4(_), RedactMode::Hash) => IpAddr::V4(Ipv4Addr::from(
+                u32::from(Ipv4Addr::new(198, 18, 0, 0)) + (fnv1a(&key) % (1 << 17)) as u32,
+            )),
+            (IpAddr::V6(_), mode) => {
+                let low = match mode {
+                    RedactMode::Mask => {
+                        self.next_ipv6 += 1;
+                        u64::from(self.next_ipv6)
+                    }
+                    RedactMode::Hash => fnv1a(&key),
+                };
+                IpAddr::V6(Ipv6Addr::from((0x2001_0db8_u128 << 96) | u128::from(low)))
+            }
+        };
+        self.remember(key, stand_in.to_string());
+        stand_in
+    }
+
+    fn host(&mut self, host: &str) -> String {
+        let key = host.to_lowercase();
+        if let Some(stand_in) = self.stand_ins.get(&key) {
+            return stand_in.clone();
+        }
+        let stand_in = match self.mode {
+            RedactMode::Mask => {
+                self.next_host += 1;
+                format!("host-{}.example", self.next_host)
+            }
+            RedactMode::Hash => format!("host-{:08x}.example", fnv1a(&key) as u32),
+        };
+        self.remember(key, stand_in.clone());
+        stand_in
+    }
+
+    fn remember(&mut self, original: String, stand_in: String) {
+        self.stand_ins.insert(stand_in.clone(), stand_in.clone());
+        self.stand_ins.insert(original, stand_in);
+    }
+}
+
+/// The `n`th address (from 1) of the three documentation ranges, 254 to
+/// each, starting over after the last.
+fn documentation_ipv4(n: u32) -> Ipv4Addr {
+    let [a, b, c] = [[192, 0, 2], [198, 51, 100], [203, 0, 113]][((n - 1) / 254 % 3) as usize];
+    Ipv4Addr::new(a, b, c, ((n - 1) % 254 + 1) as u8)
+}
+
+/// Host part of a URL, `host:port` or bare host.
+fn host_of(endpoint: &str) -> String {
+    let rest = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    if let Some(bracketed) = host.strip_prefix('[') {
+        return bracketed.split(']').next().unwrap_or_default().to_string();
+    }
+    match host.split_once(':') {
+        Some((name, port)) if !port.contains(':') => name.to_string(),
+        _ => host.to_string(),
+    }
+}
+
+/// 64-bit FNV-1a, stable across releases and platforms.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::TracerouteHop;
+    use crate::runner::tests::sample_result;
+
+    #[test]
+    fn replaces_addresses_and_hostnames_consistently() {
+        let hop = |number: u32, address: &str, hostname: Option<&str>| TracerouteHop {
+            number,
+            address: Some(address.parse().unwrap()),
+            hostname: hostname.map(str::to_string),
+            rtt_ms: Some(1.0),
+            loss_pct: None,
+            asn: None,
+            as_name: None,
+            country: None,
+        };
+        let mut result = sample_result("Backup LTE", true, None, None, 1.0, Some(true));
+        result.target = "lte-gw.corp.internal".into();
+        result.error = Some("no reply from 10.20.30.1 or fd00::1".into());
+        result.failed_targets = vec!["10.20.30.40".into()];
+        // Traceroute only.
+        result.checks.remove(0);
+        let CheckOutcome::Traceroute {
+            report: Some(report),
+            expected_gateway,
+            ..
+        } = &mut result.checks[0]
+        else {
+            unreachable!()
+        };
+        *expected_gateway = Some("10.20.30.1".parse().unwrap());
+        report.hop_count = Some(2);
+        report.hops = vec![
+            hop(1, "10.20.30.1", Some("core1.corp.internal")),
+            hop(2, "10.20.30.40", None),
+        ];
+        report.raw_output = " 1  core1.corp.internal (10.20.30.1)  1.0 ms\n\
+                              2  LTE-GW.corp.internal (10.20.30.40)  9.5 ms at 12:30:00\n"
+            .into();
+
+        let mut redactor = Redactor::new(RedactMode::Mask);
+        redactor.register_hosts(&result);
+        redactor.result(&mut result);
+        assert_eq!(result.target, "host-1.example");
+        assert_eq!(
+            result.error.as_deref(),
+            Some("no reply from 192.0.2.2 or 2001:db8::1")
+        );
+        assert_eq!(result.failed_targets, ["192.0.2.1"]);
+        let CheckOutcome::Traceroute {
+            report: Some(report),
+            expected_gateway,
+            ..
+        } = &result.checks[0]
+        else {
+            unreachable!()
+        };
+        assert_eq!(*expected_gateway, Some("192.0.2.2".parse().unwrap()));
+        assert_eq!(report.hops[0].hostname.as_deref(), Some("host-2.example"));
+        assert_eq!(
+            report.raw_output,
+            " 1  host-2.example (192.0.2.2)  1.0 ms\n\
+              2  host-1.example (192.0.2.1)  9.5 ms at 12:30:00\n"
+        );
+        // Already redacted text is left alone.
+        assert_eq!(redactor.text(&report.raw_output), report.raw_output);
+
+        let mut hashed = Redactor::new(RedactMode::Hash);
+        let first = hashed.text("10.20.30.1");
+        assert_eq!(Redactor::new(RedactMode::Hash).text("10.20.30.1"), first);
+        assert!(first.starts_with("198.1"), "{first}");
+        assert_eq!(
+            host_of("https://user@dns.corp.internal:8443/q"),
+            "dns.corp.internal"
+        );
+        assert_eq!(host_of("[fd00::53]:853"), "fd00::53");
+    }
+}
@@ -0,0 +1,258 @@
+//! Configs pulled over HTTP(S), so a fleet of probes can share one central
+//! line list.
+//!
+//! A `--config https://...` argument is downloaded into a local cache and the
+//! cached copy is then loaded like any other file. Requests carry the ETag of
+//! the cached copy, so an unchanged config is not sent again, and the cached
+//! copy keeps a probe running while the server is unreachable.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tracing::warn;
+
+use crate::secrets::Secret;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+/// Name of the cached copy when the URL path does not end in a file name.
+const FALLBACK_FILE_NAME: &str = "config.toml";
+
+/// How remote configs are fetched.
+#[derive(Debug, Clone)]
+pub struct RemoteOptions {
+    /// Sent as `Authorization: Bearer <token>`; only over HTTPS.
+    pub token: Option<Secret>,
+    pub cache_dir: PathBuf,
+}
+
+static OPTIONS: OnceLock<RemoteOptions> = OnceLock::new();
+
+/// Numbers the partial downloads of this process, so concurrent fetches
+/// never write the same file.
+static DOWNLOADS: AtomicU64 = AtomicU64::new(0);
+
+/// Set the token and cache directory used for every remote config. Only the
+/// first call has an effect; without one, configs are fetched anonymously
+/// into [`default_cache_dir`].
+pub fn configure(options: RemoteOptions) {
+    let _ = OPTIONS.set(options);
+}
+
+/// `$XDG_CACHE_HOME/icmpmolester/configs`, falling back to `~/.cache` and
+/// then the temporary directory.
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("icmpmolester")
+        .join("configs")
+}
+
+/// The URL a `--config` argument names, if it is one.
+pub fn url(path: &Path) -> Option<&str> {
+    path.to_str()
+        .filter(|raw| raw.starts_with("https://") || raw.starts_with("http://"))
+}
+
+/// Refresh the cached copy of `url` and return its path.
+pub fn fetch(url: &str) -> Result<PathBuf> {
+    match OPTIONS.get() {
+        Some(options) => fetch_with(url, options),
+        None => fetch_with(
+            url,
+            &RemoteOptions {
+                token: None,
+                cache_dir: default_cache_dir(),
+            },
+        ),
+    }
+}
+
+fn fetch_with(url: &str, options: &RemoteOptions) -> Result<PathBuf> {
+    let dir = options.cache_dir.join(cache_key(url));
+    let file = dir.join(file_name(url));
+    let etag_file = dir.join("etag");
+
+    let agent = ureq::AgentBuilder::new().timeout(FETCH_TIMEOUT).build();
+    let mut request = agent.get(url);
+    if let Some(token) = &options.token {
+        if !url.starts_with("https://") {
+            anyhow::bail!("Refusing to send the config token to {url} over plain HTTP");
+        }
+        request = request.set("Authorization", &format!("Bearer {}", token.reveal()?));
+    }
+    if file.exists() {
+        if let Ok(etag) = fs::read_to_string(&etag_file) {
+            request = request.set("If-None-Match", etag.trim());
+        }
+    }
+
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(err) if file.exists() => {
+            warn!(url, "Failed to fetch config, using the cached copy: {err}");
+            return Ok(file);
+        }
+        Err(err) => return Err(err).with_context(|| format!("Failed to fetch config {url}")),
+    };
+    if response.status() == 304 {
+        return Ok(file);
+    }
+    let etag = response.header("ETag").map(str::to_string);
+    let body = response
+        .into_string()
+        .with_context(|| format!("Failed to read config {url}"))?;
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create config cache {}", dir.display()))?;
+    // Write the config before its ETag: a stale ETag only costs a full download.
+    let partial = dir.join(format!(
+        ".partial-{}-{}",
+        std::process::id(),
+        DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&partial, body)
+        .and_then(|()| fs::rename(&partial, &file))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&partial);
+        })
+        .with_context(|| format!("Failed to cache config {url} in {}", file.display()))?;
+    match etag {
+        Some(etag) => fs::write(&etag_file, etag),
+        None => fs::remove_file(&etag_file).or_else(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(err),
+        }),
+    }
+    .with_context(|| format!("Failed to cache the ETag of config {url}"))?;
+    Ok(file)
+}
+
+/// Cache directory name for `url`: the URL with every character that is not
+/// alphanumeric, `.` or `-` replaced.
+fn cache_key(url: &str) -> String {
+    url.split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '.' || ch == '-' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// File name for the cached copy: the last path segment, which also names
+/// the batch when several configs are run together.
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let path = path.split_once("://").map_or(path, |(_, rest)| rest);
+    match path.split_once('/') {
+        Some((_, rest)) => match rest.rsplit('/').next() {
+            Some(name) if !name.is_empty() && name != "." && name != ".." => name,
+            _ => FALLBACK_FILE_NAME,
+        },
+        None => FALLBACK_FILE_NAME,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Answer each connection with the next canned response, returning the
+    /// request heads that were received.
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut head = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    head.push_str(&line);
+                }
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.push(head);
+            }
+            requests
+        });
+        (base, handle)
+    }
+
+    #[test]
+    fn caches_configs_and_revalidates_with_etags() {
+        let cache = tempfile::tempdir().unwrap();
+        let options = RemoteOptions {
+            token: None,
+            cache_dir: cache.path().to_path_buf(),
+        };
+        let (base, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 9\r\nConnection: close\r\n\r\nlines = 1",
+            "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let url = format!("{base}/fleet/acme.toml?probe=7");
+
+        let file = fetch_with(&url, &options).unwrap();
+        assert_eq!(file.file_name().unwrap(), "acme.toml");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "lines = 1");
+        assert_eq!(fetch_with(&url, &options).unwrap(), file);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "lines = 1");
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("If-None-Match"));
+        assert!(
+            requests[1].contains("If-None-Match: \"v1\""),
+            "{}",
+            requests[1]
+        );
+
+        // With the server gone, the cached copy is used.
+        assert_eq!(fetch_with(&url, &options).unwrap(), file);
+        let missing = format!("{base}/other.toml");
+        assert!(fetch_with(&missing, &options).is_err());
+
+        let with_token = RemoteOptions {
+            token: Some(Secret::Value("s3cret".into())),
+            ..options
+        };
+        let err = fetch_with(&url, &with_token).unwrap_err();
+        assert!(err.to_string().contains("plain HTTP"), "{err}");
+    }
+
+    #[test]
+    fn names_cached_copies_after_the_url() {
+        assert_eq!(file_name("https://example.com/a/lines.toml"), "lines.toml");
+        assert_eq!(
+            file_name("https://example.com/lines.toml?x=1"),
+            "lines.toml"
+        );
+        assert_eq!(file_name("https://example.com/"), FALLBACK_FILE_NAME);
+        assert_eq!(file_name("https://example.com"), FALLBACK_FILE_NAME);
+        assert_eq!(
+            cache_key("https://example.com:8443/a/lines.toml"),
+            "example.com_8443_a_lines.toml"
+        );
+        assert_eq!(
+            url(Path::new("https://example.com/x")),
+            Some("https://example.com/x")
+        );
+        assert_eq!(url(Path::new("lines.toml")), None);
+    }
+}
@@ -0,0 +1,458 @@
+//! Machine-readable report of a completed run.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::alerting::assess;
+use crate::checks::{CheckOutcome, Confirmation};
+use crate::diagnostics::TracerouteHop;
+use crate::history::Regression;
+use crate::iterations::IterationStats;
+use crate::runner::{LineResult, LineStatus, Rollup};
+
+/// Version of the JSON and NDJSON output, as `schema_version` in every
+/// document. Any change to their shape, even an added field, bumps it along
+/// with the schema printed by `--print-schema`.
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Identity and timing of one run, for correlating notifications with logs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunMetadata {
+    pub run_id: Uuid,
+    /// Host the run was made from.
+    pub hostname: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+impl RunMetadata {
+    /// Metadata for a run that has just finished, under a fresh run ID.
+    pub fn new(started_at: DateTime<Utc>, finished_at: DateTime<Utc>) -> Self {
+        Self {
+            run_id: Uuid::new_v4(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            started_at,
+            finished_at,
+        }
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        (self.finished_at - self.started_at).as_seconds_f64()
+    }
+
+    /// One line for the end of summaries.
+    pub fn describe(&self) -> String {
+        format!(
+            "Run {} on {}, started {}, took {:.1} s",
+            self.run_id,
+            self.hostname,
+            self.started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            self.duration_secs()
+        )
+    }
+}
+
+/// JSON document describing one run, e.g. for webhook callbacks.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub run: RunMetadata,
+    /// Every line's status weighed by its `weight`.
+    pub rollup: Rollup,
+    pub lines: Vec<LineReport>,
+}
+
+/// Outcome of one line within a [`RunReport`].
+#[derive(Debug, Clone, Serialize)]
+pub struct LineReport {
+    pub name: String,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch: Option<String>,
+    pub status: LineStatus,
+    pub weight: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// How long the line's checks took; absent when it was interrupted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f32>,
+    /// Custom status name when one matched, otherwise the built-in status.
+    pub display_status: String,
+    /// Why the checks could not run, or that the line is auto-disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution: Option<String>,
+    /// Targets that alerted before the line fell back to `target`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed_targets: Vec<String>,
+    /// Scenarios injected by `--simulate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulated: Option<String>,
+    /// Metrics well above the line's history baseline.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub regressions: Vec<Regression>,
+    /// Ping statistics over every iteration, with `--iterations`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iterations: Option<IterationStats>,
+    pub checks: Vec<CheckReport>,
+}
+
+/// Verdict of one check within a [`LineReport`]; skipped checks have no status.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    pub check: String,
+    pub status: Option<LineStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Re-probes of a ping whose loss was above the threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<Confirmation>,
+    /// The path a traceroute took.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hops: Vec<HopReport>,
+}
+
+/// One traceroute hop within a [`CheckReport`]; `address` is `None` for
+/// `* * *` rows.
+#[derive(Debug, Clone, Serialize)]
+pub struct HopReport {
+    pub number: u32,
+    pub address: Option<IpAddr>,
+    /// PTR or traceroute-reported name of the hop.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtt_ms: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loss_pct: Option<f32>,
+    /// Origin AS and country from `hop_enrichment`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+impl From<&TracerouteHop> for HopReport {
+    fn from(hop: &TracerouteHop) -> Self {
+        HopReport {
+            number: hop.number,
+            address: hop.address,
+            hostname: hop.hostname.clone(),
+            rtt_ms: hop.rtt_ms,
+            loss_pct: hop.loss_pct,
+            asn: hop.asn,
+            as_name: hop.as_name.clone(),
+            country: hop.country.clone(),
+        }
+    }
+}
+
+impl RunReport {
+    pub fn new(results: &[LineResult], run: &RunMetadata) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            run: run.clone(),
+            rollup: Rollup::of(results),
+            lines: results.iter().map(LineReport::new).collect(),
+        }
+    }
+}
+
+impl LineReport {
+    pub fn new(result: &LineResult) -> Self {
+        LineReport {
+            name: result.name.clone(),
+            target: result.target.clone(),
+            batch: result.batch.clone(),
+            status: result.status(),
+            weight: result.weight,
+            tags: result.tags.clone(),
+            duration_ms: result.duration_ms,
+            display_status: result.display_status().to_string(),
+            execution: result.execution_note(),
+            failed_targets: result.failed_targets.clone(),
+            simulated: result.simulated.clone(),
+            regressions: result.regressions.clone(),
+            iterations: result.iterations.clone(),
+            checks: result
+                .checks
+                .iter()
+                .map(|check| {
+                    let assessment = assess(check);
+                    CheckReport {
+                        check: check.label(),
+                        status: assessment.as_ref().map(|a| a.status),
+                        reason: assessment.and_then(|a| a.reason),
+                        confirmation: match check {
+                            CheckOutcome::Ping { confirmation, .. } => confirmation.clone(),
+                            _ => None,
+                        },
+                        hops: match check {
+                            CheckOutcome::Traceroute {
+                                report: Some(report),
+                                ..
+                            } => report.hops.iter().map(HopReport::from).collect(),
+                            _ => Vec::new(),
+                        },
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// One `--format ndjson` record: a line's report, stamped with when and
+/// where it finished.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamedLine {
+    pub schema_version: u32,
+    pub finished_at: DateTime<Utc>,
+    pub hostname: String,
+    #[serde(flatten)]
+    pub line: LineReport,
+}
+
+/// A line's result as one line of JSON, for streaming while the run goes on.
+pub fn format_ndjson_line(result: &LineResult) -> serde_json::Result<String> {
+    serde_json::to_string(&StreamedLine {
+        schema_version: SCHEMA_VERSION,
+        finished_at: Utc::now(),
+        hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+        line: LineReport::new(result),
+    })
+}
+
+/// Columns of [`format_csv`]. They only ever change by appending, so rows
+/// from different runs can be collected in one file.
+pub const CSV_HEADER: &str = "timestamp,run_id,hostname,batch,name,target,status,display_status,loss_pct,avg_latency_ms,hop_count,checks";
+
+/// The header and one row per line: when and where the run was made, the
+/// line's status, loss, average latency and hop count, and each check's
+/// status as `check=STATUS` joined with `;`. Values that were not measured
+/// are left empty.
+pub fn format_csv(results: &[LineResult], run: &RunMetadata) -> String {
+    let timestamp = run.started_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let run_id = run.run_id.to_string();
+    let mut csv = format!("{CSV_HEADER}\n");
+    for result in results {
+        let ping = result.ping();
+        let decimal = |value: Option<f32>| value.map(|v| format!("{v:.2}")).unwrap_or_default();
+        let checks: Vec<String> = result
+            .checks
+            .iter()
+            .map(|check| {
+                let status = check.status().map_or("SKIPPED", LineStatus::as_str);
+                format!("{}={status}", check.label())
+            })
+            .collect();
+        let row = [
+            timestamp.clone(),
+            run_id.clone(),
+            run.hostname.clone(),
+            result.batch.clone().unwrap_or_default(),
+            result.name.clone(),
+            result.target.clone(),
+            result.status().as_str().to_string(),
+            result.display_status().to_string(),
+            decimal(ping.and_then(|ping| ping.packet_loss_pct)),
+            decimal(ping.and_then(|ping| ping.average_latency_ms)),
+            result
+                .traceroute()
+                .and_then(|traceroute| traceroute.hop_count)
+                .map(|hops| hops.to_string())
+                .unwrap_or_default(),
+            checks.join(";"),
+        ];
+        let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quote `field` when it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// A Markdown summary: a heading, the overall rollup, one table row per
+/// line and the run's metadata. Rows of ALERT lines are bold, and each
+/// check is listed with its status and, when not OK, the reason.
+pub fn format_markdown(results: &[LineResult], run: &RunMetadata) -> String {
+    let mut markdown = String::from("## ICMPMolester report\n\n");
+    if results.len() > 1 {
+        markdown.push_str(&format!("Overall: {}\n\n", Rollup::of(results).describe()));
+    }
+    markdown.push_str(&markdown_table(results, "<br>"));
+    markdown.push_str(&format!("\n{}\n", run.describe()));
+    markdown
+}
+
+/// The table of [`format_markdown`], with each line's checks joined by
+/// `check_separator` for renderers that take no HTML.
+pub fn markdown_table(results: &[LineResult], check_separator: &str) -> String {
+    let mut markdown =
+        String::from("| Line | Target | Status | Loss | Latency | Hops | Checks |\n");
+    markdown.push_str("| --- | --- | --- | ---: | ---: | ---: | --- |\n");
+    for result in results {
+        let ping = result.ping();
+        let mut details: Vec<String> = result.execution_note().into_iter().collect();
+        for check in &result.checks {
+            let Some(assessment) = assess(check) else {
+                details.push(format!("{}: SKIPPED", check.label()));
+                continue;
+            };
+            let status = assessment.status.as_str();
+            details.push(match assessment.reason {
+                Some(reason) => format!("{}: {status} ({reason})", check.label()),
+                None => format!("{}: {status}", check.label()),
+            });
+        }
+        let row = [
+            result.key(),
+            result.target.clone(),
+            result.display_status().to_string(),
+            ping.and_then(|ping| ping.packet_loss_pct)
+                .map(|loss| format!("{loss:.2}%"))
+                .unwrap_or_default(),
+            ping.and_then(|ping| ping.average_latency_ms)
+                .map(|latency| format!("{latency:.2} ms"))
+                .unwrap_or_default(),
+            result
+                .traceroute()
+                .and_then(|traceroute| traceroute.hop_count)
+                .map(|hops| hops.to_string())
+                .unwrap_or_default(),
+            details
+                .iter()
+                .map(|detail| markdown_cell(detail))
+                .collect::<Vec<_>>()
+                .join(check_separator),
+        ];
+        let bold = result.status() == LineStatus::Alert;
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(column, cell)| {
+                // The checks column is escaped entry by entry above.
+                let cell = if column == row.len() - 1 {
+                    cell.clone()
+                } else {
+                    markdown_cell(cell)
+                };
+                if bold && !cell.is_empty() {
+                    format!("**{cell}**")
+                } else {
+                    cell
+                }
+            })
+            .collect();
+        markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    markdown
+}
+
+/// Keep `text` inside one table cell: pipes are escaped and line breaks
+/// become spaces.
+pub fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner;
+
+    fn sample_result() -> LineResult {
+        let mut result =
+            runner::tests::sample_result("Primary", true, Some(20.0), Some(12.0), 1.0, None);
+        result.batch = Some("acme".into());
+        result.duration_ms = Some(812.5);
+        // Ping only, without the skipped traceroute.
+        result.checks.truncate(1);
+        result
+    }
+
+    #[test]
+    fn serialises_line_and_check_verdicts() {
+        let result = sample_result();
+        let now = Utc::now();
+        let run = RunMetadata::new(now - chrono::TimeDelta::milliseconds(1500), now);
+        let json = serde_json::to_value(RunReport::new(&[result], &run)).unwrap();
+        assert_eq!(json["run_id"], run.run_id.to_string());
+        assert!(json["hostname"].is_string());
+        assert!(run.describe().ends_with("took 1.5 s"));
+        let line = &json["lines"][0];
+        assert_eq!(line["duration_ms"], 812.5);
+        assert_eq!(line["batch"], "acme");
+        assert_eq!(line["weight"], 1.0);
+        assert_eq!(json["rollup"]["impacted_pct"], 100.0);
+        assert_eq!(
+            line["status"],
+            serde_json::to_value(LineStatus::Alert).unwrap()
+        );
+        assert_eq!(line["checks"][0]["check"], "ping");
+        assert!(line["checks"][0]["reason"].is_string());
+    }
+
+    #[test]
+    fn formats_one_csv_row_per_line() {
+        let mut result = sample_result();
+        result.name = "Primary, \"FTTH\"".into();
+        let now = Utc::now();
+        let run = RunMetadata::new(now, now);
+        let csv = format_csv(&[result], &run);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], CSV_HEADER);
+        assert_eq!(
+            rows[1],
+            format!(
+                "{},{},{},acme,\"Primary, \"\"FTTH\"\"\",10.0.0.1,ALERT,ALERT,20.00,12.00,,ping=ALERT",
+                now.to_rfc3339_opts(SecondsFormat::Secs, true),
+                run.run_id,
+                run.hostname
+            )
+        );
+    }
+
+    #[test]
+    fn formats_markdown_table_with_bold_alerts() {
+        let alert = sample_result();
+        let mut ok = sample_result();
+        ok.name = "Backup | LTE".into();
+        ok.batch = None;
+        if let CheckOutcome::Ping { report, .. } = &mut ok.checks[0] {
+            report.packet_loss_pct = Some(0.0);
+        }
+        let now = Utc::now();
+        let run = RunMetadata::new(now, now);
+        let markdown = format_markdown(&[alert, ok], &run);
+        let rows: Vec<&str> = markdown.lines().collect();
+        assert_eq!(rows[0], "## ICMPMolester report");
+        assert!(rows[2].starts_with("Overall: ALERT"), "{markdown}");
+        assert_eq!(
+            rows[4],
+            "| Line | Target | Status | Loss | Latency | Hops | Checks |"
+        );
+        assert!(
+            rows[6].starts_with(
+                "| **acme/Primary** | **10.0.0.1** | **ALERT** | **20.00%** | **12.00 ms** |  | **ping: ALERT ("
+            ),
+            "{markdown}"
+        );
+        assert_eq!(
+            rows[7],
+            "| Backup \\| LTE | 10.0.0.1 | OK | 0.00% | 12.00 ms |  | ping: OK |"
+        );
+        assert_eq!(rows[9], run.describe());
+    }
+}
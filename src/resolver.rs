@@ -0,0 +1,526 @@
+//! Caching stub resolver shared by the network probes.
+//!
+//! Hostnames are resolved with plain UDP queries to the nameservers in
+//! `/etc/resolv.conf`, so answers can be cached for their TTL and NXDOMAIN or
+//! empty answers for the zone's SOA minimum. Names the stub does not handle
+//! (single labels, `/etc/hosts` entries, truncated answers, or no configured
+//! nameservers) go through the system resolver and are cached for
+//! [`SYSTEM_TTL`]. ping and traceroute still resolve their targets themselves.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+use crate::probes::dns::{DNS_HEADER_LEN, DNS_TYPE_A, DNS_TYPE_AAAA, build_query, query_id};
+
+const DNS_TYPE_SOA: u16 = 6;
+const DNS_RCODE_NXDOMAIN: u8 = 3;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Passes over the nameserver list before a lookup is reported as failed.
+const QUERY_ATTEMPTS: usize = 2;
+/// Cache lifetime for answers from the system resolver, which hides TTLs.
+const SYSTEM_TTL: Duration = Duration::from_secs(60);
+/// Negative cache lifetime when the nameserver sends no SOA record.
+const DEFAULT_NEGATIVE_TTL: u32 = 30;
+/// Upper bound on any cached entry, so a long TTL cannot pin a stale address.
+const MAX_TTL: u32 = 3600;
+const MAX_UDP_RESPONSE: usize = 4096;
+
+/// Process-wide resolver, so repeated checks in `serve` mode share one cache.
+pub fn shared() -> &'static Resolver {
+    static RESOLVER: OnceLock<Resolver> = OnceLock::new();
+    RESOLVER.get_or_init(Resolver::from_system)
+}
+
+/// Adapter that lets a blocking `ureq` agent resolve through [`shared`].
+///
+/// Must be created inside the runtime; the agent itself runs on a blocking
+/// thread, from which the lookup is driven with `block_on`.
+pub fn ureq_resolver() -> impl ureq::Resolver {
+    let runtime = tokio::runtime::Handle::current();
+    move |netloc: &str| {
+        let (host, port) = netloc
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+            .ok_or_else(|| io::Error::other(format!("invalid address '{netloc}'")))?;
+        runtime.block_on(shared().resolve(host, port))
+    }
+}
+
+pub struct Resolver {
+    nameservers: Vec<SocketAddr>,
+    hosts: HashSet<String>,
+    cache: Mutex<HashMap<String, CacheEntry>>,
+    stats: Mutex<ResolverStats>,
+}
+
+/// Cached addresses; an empty list is a negative entry.
+struct CacheEntry {
+    addresses: Vec<IpAddr>,
+    expires: Instant,
+}
+
+/// Resolution counters since the process started.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResolverStats {
+    /// Lookups answered from the cache, including negative entries.
+    pub cache_hits: u64,
+    /// Lookups that went to a nameserver or the system resolver.
+    pub queries: u64,
+    /// Queries that ended without an answer (timeouts, SERVFAIL, ...).
+    pub failures: u64,
+    /// Queries answered with NXDOMAIN or no addresses.
+    pub negative_answers: u64,
+    /// Extra attempts after a nameserver timed out or failed.
+    pub retries: u64,
+    pub total_latency_ms: f64,
+    pub max_latency_ms: f64,
+}
+
+impl ResolverStats {
+    pub fn average_latency_ms(&self) -> Option<f64> {
+        (self.queries > 0).then(|| self.total_latency_ms / self.queries as f64)
+    }
+}
+
+/// Addresses for one name and how long they may be cached.
+#[derive(Debug, PartialEq)]
+struct Lookup {
+    addresses: Vec<IpAddr>,
+    ttl: Duration,
+}
+
+/// Parsed response to a single A or AAAA query.
+#[derive(Debug, Default, PartialEq)]
+struct Answer {
+    rcode: u8,
+    truncated: bool,
+    addresses: Vec<IpAddr>,
+    /// Lowest TTL across the answer section.
+    ttl: Option<u32>,
+    /// Negative caching TTL from the authority section's SOA record.
+    negative_ttl: Option<u32>,
+}
+
+impl Resolver {
+    fn from_system() -> Self {
+        let read = |path: &str| std::fs::read_to_string(path).unwrap_or_default();
+        Self::new(
+            parse_nameservers(&read("/etc/resolv.conf")),
+            parse_hosts(&read("/etc/hosts")),
+        )
+    }
+
+    fn new(nameservers: Vec<SocketAddr>, hosts: HashSet<String>) -> Self {
+        Self {
+            nameservers,
+            hosts,
+            cache: Mutex::new(HashMap::new()),
+            stats: Mutex::new(ResolverStats::default()),
+        }
+    }
+
+    pub fn stats(&self) -> ResolverStats {
+        self.stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Resolve `host` to socket addresses on `port`, IPv4 first.
+    ///
+    /// IP literals (bracketed or not) are returned without a lookup.
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        let name = host.trim_end_matches('.').to_ascii_lowercase();
+        let addresses = match self.cached(&name) {
+            Some(addresses) => addresses,
+            None => self.query(&name).await?,
+        };
+        if addresses.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{host} has no addresses"),
+            ));
+        }
+        Ok(addresses
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+
+    fn cached(&self, name: &str) -> Option<Vec<IpAddr>> {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = cache.get(name)?;
+        if entry.expires <= Instant::now() {
+            cache.remove(name);
+            return None;
+        }
+        let addresses = entry.addresses.clone();
+        drop(cache);
+        self.stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cache_hits += 1;
+        Some(addresses)
+    }
+
+    async fn query(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        let started = Instant::now();
+        let use_stub =
+            !self.nameservers.is_empty() && name.contains('.') && !self.hosts.contains(name);
+        let (outcome, retries) = if use_stub {
+            self.stub_lookup(name).await
+        } else {
+            (system_lookup(name).await, 0)
+        };
+
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        {
+            let mut stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+            stats.queries += 1;
+            stats.retries += retries;
+            stats.total_latency_ms += latency_ms;
+            stats.max_latency_ms = stats.max_latency_ms.max(latency_ms);
+            match &outcome {
+                Ok(lookup) if lookup.addresses.is_empty() => stats.negative_answers += 1,
+                Ok(_) => {}
+                Err(_) => stats.failures += 1,
+            }
+        }
+
+        let lookup = outcome?;
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                name.to_string(),
+                CacheEntry {
+                    addresses: lookup.addresses.clone(),
+                    expires: Instant::now() + lookup.ttl,
+                },
+            );
+        Ok(lookup.addresses)
+    }
+
+    /// Try each nameserver in turn, up to [`QUERY_ATTEMPTS`] passes.
+    async fn stub_lookup(&self, name: &str) -> (io::Result<Lookup>, u64) {
+        let mut tries = 0;
+        let mut last_error = None;
+        for _ in 0..QUERY_ATTEMPTS {
+            for server in &self.nameservers {
+                tries += 1;
+                match ask(*server, name).await {
+                    Ok(Some(lookup)) => return (Ok(lookup), tries - 1),
+                    Ok(None) => return (system_lookup(name).await, tries - 1),
+                    Err(err) => last_error = Some(err),
+                }
+            }
+        }
+        let error = last_error.unwrap_or_else(|| io::Error::other("no nameservers configured"));
+        (Err(error), tries.saturating_sub(1))
+    }
+}
+
+/// Query A and AAAA together; `None` when an answer was truncated.
+async fn ask(server: SocketAddr, name: &str) -> io::Result<Option<Lookup>> {
+    let (v4, v6) = tokio::join!(
+        exchange(server, name, DNS_TYPE_A),
+        exchange(server, name, DNS_TYPE_AAAA)
+    );
+    let answers = [v4?, v6?];
+    if answers.iter().any(|answer| answer.truncated) {
+        return Ok(None);
+    }
+    if let Some(answer) = answers
+        .iter()
+        .find(|answer| answer.rcode != 0 && answer.rcode != DNS_RCODE_NXDOMAIN)
+    {
+        return Err(io::Error::other(format!(
+            "{server} returned rcode {} for {name}",
+            answer.rcode
+        )));
+    }
+
+    let addresses: Vec<IpAddr> = answers
+        .iter()
+        .flat_map(|answer| answer.addresses.iter().copied())
+        .collect();
+    let ttl = if addresses.is_empty() {
+        answers
+            .iter()
+            .filter_map(|answer| answer.negative_ttl)
+            .min()
+            .unwrap_or(DEFAULT_NEGATIVE_TTL)
+    } else {
+        answers
+            .iter()
+            .filter(|answer| !answer.addresses.is_empty())
+            .filter_map(|answer| answer.ttl)
+            .min()
+            .unwrap_or(0)
+    };
+    Ok(Some(Lookup {
+        addresses,
+        ttl: Duration::from_secs(u64::from(ttl.min(MAX_TTL))),
+    }))
+}
+
+async fn exchange(server: SocketAddr, name: &str, qtype: u16) -> io::Result<Answer> {
+    // Distinct IDs for the concurrent A and AAAA queries.
+    let id = query_id() ^ qtype;
+    let query = build_query(id, name, qtype).map_err(io::Error::other)?;
+    let local = if server.is_ipv6() {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+    } else {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(&query).await?;
+
+    let mut response = vec![0u8; MAX_UDP_RESPONSE];
+    let len = tokio::time::timeout(QUERY_TIMEOUT, socket.recv(&mut response))
+        .await
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "{server} did not answer within {} ms",
+                    QUERY_TIMEOUT.as_millis()
+                ),
+            )
+        })??;
+    parse_answer(id, &response[..len]).map_err(io::Error::other)
+}
+
+async fn system_lookup(name: &str) -> io::Result<Lookup> {
+    let mut addresses: Vec<IpAddr> = tokio::net::lookup_host((name, 0))
+        .await?
+        .map(|addr| addr.ip())
+        .collect();
+    addresses.sort_by_key(IpAddr::is_ipv6);
+    addresses.dedup();
+    Ok(Lookup {
+        addresses,
+        ttl: SYSTEM_TTL,
+    })
+}
+
+fn parse_answer(id: u16, body: &[u8]) -> Result<Answer> {
+    let read_u16 = |pos: usize| {
+        body.get(pos..pos + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .context("truncated DNS message")
+    };
+    let read_u32 = |pos: usize| {
+        body.get(pos..pos + 4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .context("truncated DNS message")
+    };
+
+    if body.len() < DNS_HEADER_LEN {
+        anyhow::bail!("short DNS response ({} bytes)", body.len());
+    }
+    let response_id = read_u16(0)?;
+    if response_id != id {
+        anyhow::bail!("DNS response ID {response_id} does not match query {id}");
+    }
+    if body[2] & 0x80 == 0 {
+        anyhow::bail!("DNS message is not a response");
+    }
+    let mut answer = Answer {
+        rcode: body[3] & 0x0f,
+        truncated: body[2] & 0x02 != 0,
+        ..Answer::default()
+    };
+    if answer.truncated {
+        return Ok(answer);
+    }
+
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+    let authority = read_u16(8)?;
+    let mut pos = DNS_HEADER_LEN;
+    for _ in 0..questions {
+        pos = skip_name(body, pos)? + 4;
+    }
+    for index in 0..answers + authority {
+        pos = skip_name(body, pos)?;
+        let rtype = read_u16(pos)?;
+        let ttl = read_u32(pos + 4)?;
+        let len = usize::from(read_u16(pos + 8)?);
+        let rdata = body
+            .get(pos + 10..pos + 10 + len)
+            .context("truncated DNS record")?;
+        pos += 10 + len;
+
+        if index < answers {
+            // CNAME TTLs count too: the alias may change before the target does.
+            answer.ttl = Some(answer.ttl.map_or(ttl, |lowest| lowest.min(ttl)));
+            match (rtype, rdata) {
+                (DNS_TYPE_A, &[a, b, c, d]) => answer.addresses.push(IpAddr::from([a, b, c, d])),
+                (DNS_TYPE_AAAA, rdata) if rdata.len() == 16 => {
+                    let octets: [u8; 16] = rdata.try_into()?;
+                    answer.addresses.push(IpAddr::from(octets));
+                }
+                _ => {}
+            }
+        } else if rtype == DNS_TYPE_SOA && len >= 4 {
+            // The SOA MINIMUM field is the last 32 bits of its RDATA (RFC 2308).
+            let minimum = u32::from_be_bytes(rdata[len - 4..].try_into()?);
+            answer.negative_ttl = Some(ttl.min(minimum));
+        }
+    }
+    Ok(answer)
+}
+
+/// Offset just past the (possibly compressed) domain name starting at `pos`.
+fn skip_name(body: &[u8], mut pos: usize) -> Result<usize> {
+    loop {
+        let len = *body.get(pos).context("truncated DNS name")?;
+        match len {
+            0 => return Ok(pos + 1),
+            pointer if pointer & 0xc0 == 0xc0 => return Ok(pos + 2),
+            label => pos += 1 + usize::from(label),
+        }
+    }
+}
+
+fn parse_nameservers(resolv_conf: &str) -> Vec<SocketAddr> {
+    resolv_conf
+        .lines()
+        .filter_map(|row| {
+            let mut fields = row.split_whitespace();
+            (fields.next() == Some("nameserver")).then_some(())?;
+            let ip = fields.next()?.parse::<IpAddr>().ok()?;
+            Some(SocketAddr::new(ip, 53))
+        })
+        .collect()
+}
+
+fn parse_hosts(hosts: &str) -> HashSet<String> {
+    hosts
+        .lines()
+        .map(|row| row.split('#').next().unwrap_or_default())
+        .flat_map(|row| row.split_whitespace().skip(1))
+        .map(str::to_ascii_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Response to `query` carrying `records` as (type, ttl, rdata) answers.
+    fn respond(query: &[u8], rcode: u8, records: &[(u16, u32, &[u8])]) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2] |= 0x80;
+        response[3] = rcode;
+        response[7] = records.len() as u8;
+        for (rtype, ttl, rdata) in records {
+            response.extend_from_slice(&[0xc0, 0x0c]);
+            response.extend_from_slice(&rtype.to_be_bytes());
+            response.extend_from_slice(&1u16.to_be_bytes());
+            response.extend_from_slice(&ttl.to_be_bytes());
+            response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            response.extend_from_slice(rdata);
+        }
+        response
+    }
+
+    #[test]
+    fn parses_answers_and_negative_ttl() {
+        let query = build_query(9, "example.com", DNS_TYPE_A).unwrap();
+        let cname = b"\x03www\xc0\x0c";
+        let response = respond(
+            &query,
+            0,
+            &[(5, 120, cname), (DNS_TYPE_A, 300, &[192, 0, 2, 10])],
+        );
+        let answer = parse_answer(9, &response).unwrap();
+        assert_eq!(answer.addresses, [IpAddr::from([192, 0, 2, 10])]);
+        assert_eq!(answer.ttl, Some(120));
+
+        // NXDOMAIN with an SOA (TTL 900, MINIMUM 60) in the authority section.
+        let mut nxdomain = respond(&query, DNS_RCODE_NXDOMAIN, &[]);
+        nxdomain[9] = 1;
+        let soa: Vec<u8> = [&[0u8, 0][..], &[0; 16], &60u32.to_be_bytes()].concat();
+        nxdomain.extend_from_slice(&[0xc0, 0x0c, 0, 6, 0, 1]);
+        nxdomain.extend_from_slice(&900u32.to_be_bytes());
+        nxdomain.extend_from_slice(&(soa.len() as u16).to_be_bytes());
+        nxdomain.extend_from_slice(&soa);
+        let answer = parse_answer(9, &nxdomain).unwrap();
+        assert_eq!(answer.rcode, DNS_RCODE_NXDOMAIN);
+        assert!(answer.addresses.is_empty());
+        assert_eq!(answer.negative_ttl, Some(60));
+
+        assert!(parse_answer(10, &response).is_err());
+    }
+
+    #[test]
+    fn reads_nameservers_and_hosts() {
+        let resolv = "# generated\nnameserver 192.0.2.53\nnameserver fe80::1%eth0\nsearch lan\nnameserver 2001:db8::53\n";
+        assert_eq!(
+            parse_nameservers(resolv),
+            [
+                "192.0.2.53:53".parse::<SocketAddr>().unwrap(),
+                "[2001:db8::53]:53".parse().unwrap(),
+            ]
+        );
+        let hosts = parse_hosts("127.0.0.1 localhost\n10.0.0.5 NAS.lan nas # storage\n");
+        assert!(hosts.contains("nas.lan") && hosts.contains("nas"));
+        assert!(!hosts.contains("storage"));
+    }
+
+    #[tokio::test]
+    async fn caches_answers_and_missing_names() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = server.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut query = [0u8; 512];
+            while let Ok((len, peer)) = server.recv_from(&mut query).await {
+                let query = &query[..len];
+                let qtype = u16::from_be_bytes([query[len - 4], query[len - 3]]);
+                let known = query.windows(6).any(|window| window == b"\x05known");
+                let response = match (known, qtype) {
+                    (true, DNS_TYPE_A) => respond(query, 0, &[(DNS_TYPE_A, 300, &[192, 0, 2, 1])]),
+                    (true, _) => respond(query, 0, &[]),
+                    (false, _) => respond(query, DNS_RCODE_NXDOMAIN, &[]),
+                };
+                server.send_to(&response, peer).await.unwrap();
+            }
+        });
+
+        let resolver = Resolver::new(vec![address], HashSet::new());
+        for _ in 0..2 {
+            assert_eq!(
+                resolver.resolve("known.example", 443).await.unwrap(),
+                ["192.0.2.1:443".parse::<SocketAddr>().unwrap()]
+            );
+        }
+        for _ in 0..2 {
+            let err = resolver.resolve("missing.example", 443).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        }
+        assert_eq!(
+            resolver.resolve("[2001:db8::1]", 53).await.unwrap(),
+            ["[2001:db8::1]:53".parse::<SocketAddr>().unwrap()]
+        );
+
+        let stats = resolver.stats();
+        assert_eq!(stats.queries, 2);
+        assert_eq!(stats.cache_hits, 2);
+        assert_eq!(stats.negative_answers, 1);
+        assert_eq!(stats.failures, 0);
+    }
+}
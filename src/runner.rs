@@ -1,170 +1,1455 @@
 //! Orchestrates diagnostics execution and presentation.
 
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
 use anyhow::{Context, Result};
-use futures::{StreamExt, TryStreamExt, stream};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Semaphore, watch};
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use crate::alerting::{assess, classify_custom, dependency_down};
+use crate::checks::{CheckOutcome, run_check};
+use crate::config::{
+    CheckKind, Config, ConfigFile, CustomStatus, HopLossPolicy, LineSettings, LoadOptions,
+    StatusColor, load_config_files,
+};
+use crate::diagnostics::{
+    DualStackReport, FamilyPing, PingReport, TracerouteHop, TracerouteReport,
+};
+use crate::histogram::Histogram;
+use crate::history::{HistoryRecord, HistorySender, Regression};
+use crate::iterations::IterationStats;
+use crate::probes::custom::CustomReport;
+use crate::probes::dns::DnsReport;
+use crate::probes::http::HttpReport;
+use crate::probes::ntp::NtpReport;
+use crate::probes::public_ip::PublicIpReport;
+use crate::probes::tls::TlsReport;
+use crate::simulate::Simulation;
+use crate::state::{HopDiff, RouteChange};
+use crate::style;
 
-use crate::config::Config;
-use crate::diagnostics::{PingReport, TracerouteReport, run_ping, run_traceroute};
+/// Lines probed at once when no limit is configured. Probes mostly wait on
+/// the network, so this is not tied to the CPU count.
+const DEFAULT_CONCURRENCY: usize = 32;
+
+/// Called with each line's result as soon as the line finishes.
+pub type LineCallback = Arc<dyn Fn(&LineResult) + Send + Sync>;
 
 /// Options that control how ICMPMolester runs diagnostics.
+#[derive(Clone)]
 pub struct RunOptions {
     pub skip_traceroute: bool,
     pub concurrency_limit: Option<usize>,
+    pub history: Option<HistorySender>,
+    /// Name of the config batch these lines belong to when several are run.
+    pub batch: Option<String>,
+    /// Auto-disabled lines keyed like [`LineResult::key`], with the settings
+    /// fingerprint they were disabled under. They are skipped until their
+    /// settings change.
+    pub disabled: BTreeMap<String, u64>,
+    /// Synthetic failures injected into the results (`--simulate`).
+    pub simulation: Option<Simulation>,
+    /// Only run lines carrying at least one of these tags; every line when empty.
+    pub tags: Vec<String>,
+    /// Only run lines whose schedule fires at this local minute (scheduled
+    /// runs in `serve`).
+    pub due: Option<NaiveDateTime>,
+    /// Stop at Ctrl-C, aborting the lines still running and returning them
+    /// as INTERRUPTED next to the finished ones.
+    pub interruptible: bool,
+    /// Profile and strictness used to load each config.
+    pub load: LoadOptions,
+    /// Streams results while the run goes on, e.g. for `--format ndjson`.
+    /// Lines still running when the run is interrupted are not reported.
+    pub on_line_finished: Option<LineCallback>,
 }
 
 /// Aggregated diagnostic outcome for a single broadband line.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LineResult {
     pub name: String,
     pub target: String,
-    pub loss_threshold: f32,
-    pub ping: PingReport,
-    pub traceroute: Option<TracerouteReport>,
-    pub traceroute_requested: bool,
-}
-
-/// Execute diagnostics for every configured line and collect results.
-pub async fn run_lines(config: Config, options: RunOptions) -> Result<Vec<LineResult>> {
-    let concurrency = options.concurrency_limit.unwrap_or_else(|| {
-        std::thread::available_parallelism()
-            .map(|n| n.get())
-            .unwrap_or(1)
+    pub checks: Vec<CheckOutcome>,
+    /// Config-defined status that matched this run, if any.
+    pub custom_status: Option<CustomStatus>,
+    /// Config batch the line came from when several configs are run together.
+    pub batch: Option<String>,
+    /// Why the line's checks could not be executed this run.
+    pub error: Option<String>,
+    /// Present when the line sets `disable_after_errors`.
+    pub auto_disable: Option<AutoDisable>,
+    /// Present when the line sets `alert_after_failures`.
+    pub alert_delay: Option<AlertDelay>,
+    /// Present when the line sets `flap_threshold`.
+    pub flap_detection: Option<FlapDetection>,
+    /// Targets tried before `target` whose ping, traceroute or dual-stack
+    /// checks alerted.
+    pub failed_targets: Vec<String>,
+    /// Operator note from the config, carried into notifications.
+    pub note: Option<String>,
+    pub runbook_url: Option<String>,
+    /// Scenarios injected by `--simulate`, when they apply to this line.
+    pub simulated: Option<String>,
+    /// Business impact of the line, see [`Rollup`].
+    pub weight: f32,
+    pub tags: Vec<String>,
+    /// Set when the line was skipped for planned maintenance.
+    pub snooze: Option<Snooze>,
+    /// The run was interrupted before the line finished; it has no checks.
+    pub interrupted: bool,
+    /// How long the line took, from acquiring its concurrency permit.
+    pub duration_ms: Option<f32>,
+    /// Metrics well above the line's history baseline, see
+    /// [`crate::history::Baseline`].
+    pub regressions: Vec<Regression>,
+    /// Ping statistics over every iteration, when the run had several.
+    pub iterations: Option<IterationStats>,
+    /// The line it depends on, when that line was down and this one was
+    /// not probed.
+    pub dependency_down: Option<String>,
+}
+
+/// Why a line is skipped for planned maintenance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Snooze {
+    /// `enabled = false` in the config.
+    Disabled,
+    /// `snooze_until` is still in the future.
+    Until(DateTime<Utc>),
+}
+
+/// Auto-disable bookkeeping for a line that sets `disable_after_errors`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoDisable {
+    /// Consecutive runs with execution errors that disable the line.
+    pub after_errors: u32,
+    /// Fingerprint of the line's settings; changing them re-enables the line.
+    pub fingerprint: u64,
+    /// The line is disabled: skipped this run, or disabled by this run's error.
+    pub active: bool,
+}
+
+/// Alert hysteresis for a line that sets `alert_after_failures`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlertDelay {
+    /// Consecutive failing runs before the line's alerts are sent.
+    pub after_failures: u32,
+    /// Consecutive failing runs up to and including this one, counted in the
+    /// alert state; `None` without `--state`, in which case nothing is held.
+    pub failing_runs: Option<u32>,
+}
+
+/// Flap detection for a line that sets `flap_threshold`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlapDetection {
+    pub threshold: u32,
+    pub window_minutes: u32,
+    /// Status changes within the window, counted in the alert state; `None`
+    /// without `--state`, in which case no line is flapping.
+    pub changes: Option<u32>,
+    /// The line is flapping.
+    pub flapping: bool,
+    /// The line was already flapping before this run, so its alerts are
+    /// suppressed.
+    pub suppressed: bool,
+}
+
+/// Overall health classification of a line for a single run, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LineStatus {
+    /// Skipped for planned maintenance; never worse than any other status.
+    Snoozed,
+    Ok,
+    /// The run was interrupted before the line finished.
+    Interrupted,
+    /// Not probed because the line it depends on is down; that line alerts
+    /// instead.
+    #[serde(rename = "DEPENDENCY_DOWN")]
+    DependencyDown,
+    Unknown,
+    Alert,
+    /// Disabled after repeated execution errors.
+    Errored,
+}
+
+impl LineStatus {
+    /// Every status, from best to worst.
+    pub const ALL: [LineStatus; 7] = [
+        LineStatus::Snoozed,
+        LineStatus::Ok,
+        LineStatus::Interrupted,
+        LineStatus::DependencyDown,
+        LineStatus::Unknown,
+        LineStatus::Alert,
+        LineStatus::Errored,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineStatus::Snoozed => "SNOOZED",
+            LineStatus::Ok => "OK",
+            LineStatus::Interrupted => "INTERRUPTED",
+            LineStatus::DependencyDown => "DEPENDENCY_DOWN",
+            LineStatus::Alert => "ALERT",
+            LineStatus::Unknown => "UNKNOWN",
+            LineStatus::Errored => "ERRORED",
+        }
+    }
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            LineStatus::Snoozed => "💤",
+            LineStatus::Ok => "✅",
+            LineStatus::Interrupted => "⏹️",
+            LineStatus::DependencyDown => "🔗",
+            LineStatus::Alert => "⚠️",
+            LineStatus::Unknown => "❔",
+            LineStatus::Errored => "⛔",
+        }
+    }
+
+    /// Whether lines that depend on a line in this status are unreachable.
+    pub fn is_down(self) -> bool {
+        matches!(self, LineStatus::Alert | LineStatus::DependencyDown)
+    }
+
+    pub fn color(self) -> StatusColor {
+        match self {
+            LineStatus::Snoozed => StatusColor::Blue,
+            LineStatus::Ok => StatusColor::Green,
+            LineStatus::Interrupted => StatusColor::Cyan,
+            LineStatus::DependencyDown => StatusColor::Blue,
+            LineStatus::Alert => StatusColor::Red,
+            LineStatus::Unknown => StatusColor::Yellow,
+            LineStatus::Errored => StatusColor::Magenta,
+        }
+    }
+}
+
+/// How a whole run went, as reported by the process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RunOutcome {
+    /// Every line is OK (or snoozed).
+    Ok,
+    /// At least one line is UNKNOWN.
+    Warning,
+    /// At least one line is ALERT.
+    Alert,
+    /// A line's checks could not be executed, it is disabled, or the run
+    /// itself failed.
+    ExecutionError,
+    /// The run was stopped with Ctrl-C before every line finished.
+    Interrupted,
+}
+
+impl RunOutcome {
+    /// Worst outcome among `results`.
+    pub fn of(results: &[LineResult]) -> Self {
+        results
+            .iter()
+            .map(|result| match result.status() {
+                _ if result.error.is_some() => RunOutcome::ExecutionError,
+                // The line it depends on reports the outage.
+                LineStatus::Snoozed | LineStatus::Ok | LineStatus::DependencyDown => RunOutcome::Ok,
+                LineStatus::Unknown => RunOutcome::Warning,
+                LineStatus::Alert => RunOutcome::Alert,
+                LineStatus::Errored => RunOutcome::ExecutionError,
+                LineStatus::Interrupted => RunOutcome::Interrupted,
+            })
+            .max()
+            .unwrap_or(RunOutcome::Ok)
+    }
+
+    pub fn exit_code(self) -> u8 {
+        match self {
+            RunOutcome::Ok => 0,
+            RunOutcome::Warning => 1,
+            RunOutcome::Alert => 2,
+            RunOutcome::ExecutionError => 3,
+            // 128 + SIGINT, as shells report a process stopped by Ctrl-C.
+            RunOutcome::Interrupted => 130,
+        }
+    }
+}
+
+/// Status of several lines weighed by their business impact.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Rollup {
+    /// Worst status among lines with a non-zero weight; UNKNOWN when there
+    /// are none.
+    pub status: LineStatus,
+    /// Share of the total weight held by ALERT or ERRORED lines, in percent.
+    pub impacted_pct: f32,
+}
+
+impl Rollup {
+    pub fn of<'a>(results: impl IntoIterator<Item = &'a LineResult>) -> Self {
+        let mut status = None;
+        let mut total = 0.0;
+        let mut impacted = 0.0;
+        for result in results {
+            if result.weight <= 0.0 || result.snooze.is_some() {
+                continue;
+            }
+            let line_status = result.status();
+            status = status.max(Some(line_status));
+            total += result.weight;
+            // A line behind a dead dependency is out of service all the same.
+            if matches!(
+                line_status,
+                LineStatus::Alert | LineStatus::Errored | LineStatus::DependencyDown
+            ) {
+                impacted += result.weight;
+            }
+        }
+        Rollup {
+            status: status.unwrap_or(LineStatus::Unknown),
+            impacted_pct: if total > 0.0 {
+                impacted / total * 100.0
+            } else {
+                0.0
+            },
+        }
+    }
+
+    /// Rollup of the lines in one config batch.
+    fn of_batch(results: &[LineResult], batch: &str) -> Self {
+        Self::of(
+            results
+                .iter()
+                .filter(|result| result.batch.as_deref() == Some(batch)),
+        )
+    }
+
+    /// Rollup of the lines in one tag group of a batch.
+    fn of_group(results: &[LineResult], batch: Option<&str>, tag: Option<&str>) -> Self {
+        Self::of(
+            results
+                .iter()
+                .filter(|result| result.batch.as_deref() == batch && result.group() == tag),
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {:.0}% of line weight impacted",
+            self.status.as_str(),
+            self.impacted_pct
+        )
+    }
+
+    fn compact(&self) -> String {
+        format!("{} {:.0}%", self.status.emoji(), self.impacted_pct)
+    }
+}
+
+impl LineResult {
+    /// Worst-of status across every check that ran; UNKNOWN when none did,
+    /// ERRORED when the line is auto-disabled and SNOOZED when it is skipped
+    /// for maintenance.
+    pub fn status(&self) -> LineStatus {
+        if self.snooze.is_some() {
+            return LineStatus::Snoozed;
+        }
+        if self.interrupted {
+            return LineStatus::Interrupted;
+        }
+        if self.dependency_down.is_some() {
+            return LineStatus::DependencyDown;
+        }
+        if self.is_disabled() {
+            return LineStatus::Errored;
+        }
+        self.checks
+            .iter()
+            .filter_map(CheckOutcome::status)
+            .max()
+            .unwrap_or(LineStatus::Unknown)
+    }
+
+    /// Identifier used for state and history, qualified by batch so that
+    /// equally named lines from different configs stay separate.
+    pub fn key(&self) -> String {
+        match &self.batch {
+            Some(batch) => format!("{batch}/{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Tag the line is grouped under in summaries: its first one.
+    pub fn group(&self) -> Option<&str> {
+        self.tags.first().map(String::as_str)
+    }
+
+    /// Whether the line is auto-disabled after repeated execution errors.
+    pub fn is_disabled(&self) -> bool {
+        self.auto_disable.as_ref().is_some_and(|auto| auto.active)
+    }
+
+    /// Execution problem to show beneath the line, if any.
+    pub fn execution_note(&self) -> Option<String> {
+        match (&self.error, &self.auto_disable) {
+            (Some(error), Some(auto)) if auto.active => Some(format!(
+                "disabled after {} consecutive execution errors: {error}",
+                auto.after_errors
+            )),
+            (None, _) if self.is_disabled() => {
+                Some("disabled after repeated execution errors".into())
+            }
+            (Some(error), _) => Some(format!("execution error: {error}")),
+            (None, _) => None,
+        }
+    }
+
+    /// Whether this run's alerts are held back because the line has not been
+    /// failing for `alert_after_failures` runs yet.
+    pub fn alert_held(&self) -> bool {
+        self.status() > LineStatus::Ok
+            && self.alert_delay.is_some_and(|delay| {
+                delay
+                    .failing_runs
+                    .is_some_and(|runs| runs < delay.after_failures)
+            })
+    }
+
+    /// Whether this run's alerts are suppressed because the line keeps
+    /// flapping; the run it started flapping is still notified.
+    pub fn flap_suppressed(&self) -> bool {
+        self.flap_detection.is_some_and(|flap| flap.suppressed)
+    }
+
+    /// How often a flapping line changed status, if it is flapping.
+    pub fn flap_note(&self) -> Option<String> {
+        let flap = self.flap_detection.filter(|flap| flap.flapping)?;
+        Some(format!(
+            "FLAPPING: {} status changes in the last {} min, further changes are \
+             not notified until it holds a status for {} min",
+            flap.changes.unwrap_or_default(),
+            flap.window_minutes,
+            flap.window_minutes
+        ))
+    }
+
+    /// How far a held line is from alerting, if its alerts are held.
+    pub fn alert_delay_note(&self) -> Option<String> {
+        let delay = self.alert_delay.filter(|_| self.alert_held())?;
+        Some(format!(
+            "failing run {} of {} before alerting",
+            delay.failing_runs.unwrap_or_default(),
+            delay.after_failures
+        ))
+    }
+
+    /// Why the line was skipped for maintenance, if it was.
+    pub fn snooze_note(&self) -> Option<String> {
+        self.snooze.map(|snooze| match snooze {
+            Snooze::Disabled => "disabled in the config".to_string(),
+            Snooze::Until(until) => format!(
+                "snoozed until {}",
+                until.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ),
+        })
+    }
+
+    /// Which line was down, when this one was not probed because of it.
+    pub fn dependency_note(&self) -> Option<String> {
+        self.dependency_down
+            .as_ref()
+            .map(|dependency| format!("not probed: {dependency} is down"))
+    }
+
+    /// Marks a line whose results were rewritten by `--simulate`.
+    pub fn simulation_note(&self) -> Option<String> {
+        self.simulated
+            .as_ref()
+            .map(|scenarios| format!("SIMULATED: {scenarios}"))
+    }
+
+    /// Which fallback targets were tried, if the line has moved off its first target.
+    pub fn fallback_note(&self) -> Option<String> {
+        if self.failed_targets.is_empty() {
+            return None;
+        }
+        let tried = self.failed_targets.join(", ");
+        Some(if self.status() == LineStatus::Alert {
+            format!("every target alerted (also tried {tried})")
+        } else {
+            format!("fell back to {} after {tried} alerted", self.target)
+        })
+    }
+
+    /// How re-probing the line's packet loss turned out, when it did.
+    pub fn confirmation_note(&self) -> Option<String> {
+        self.checks.iter().find_map(|check| match check {
+            CheckOutcome::Ping {
+                confirmation: Some(confirmation),
+                ..
+            } => Some(confirmation.describe()),
+            _ => None,
+        })
+    }
+
+    /// Latency percentiles and loss over every iteration of the run.
+    pub fn iteration_note(&self) -> Option<String> {
+        self.iterations.as_ref().map(IterationStats::describe)
+    }
+
+    /// Which metrics regressed against the line's history baseline.
+    pub fn baseline_note(&self) -> Option<String> {
+        if self.regressions.is_empty() {
+            return None;
+        }
+        let regressions: Vec<String> = self.regressions.iter().map(Regression::describe).collect();
+        Some(format!("above baseline: {}", regressions.join(", ")))
+    }
+
+    /// The line's note and runbook link, shown only when it is not OK.
+    pub fn next_steps(&self) -> Vec<String> {
+        if matches!(self.status(), LineStatus::Ok | LineStatus::Snoozed) {
+            return Vec::new();
+        }
+        self.note
+            .iter()
+            .cloned()
+            .chain(self.runbook_url.iter().map(|url| format!("runbook: {url}")))
+            .collect()
+    }
+
+    /// Status name shown to operators: the matching custom status, else the built-in one.
+    pub fn display_status(&self) -> &str {
+        match &self.custom_status {
+            Some(custom) => &custom.name,
+            None => self.status().as_str(),
+        }
+    }
+
+    /// Emoji used for this line in chat notifications.
+    pub fn status_emoji(&self) -> &str {
+        self.custom_status
+            .as_ref()
+            .and_then(|custom| custom.emoji.as_deref())
+            .unwrap_or_else(|| self.status().emoji())
+    }
+
+    pub fn status_color(&self) -> StatusColor {
+        self.custom_status
+            .as_ref()
+            .and_then(|custom| custom.color)
+            .unwrap_or_else(|| self.status().color())
+    }
+
+    /// Ping report, when the line ran a ping check.
+    pub fn ping(&self) -> Option<&PingReport> {
+        self.checks.iter().find_map(|check| match check {
+            CheckOutcome::Ping { report, .. } => Some(report),
+            _ => None,
+        })
+    }
+
+    /// Traceroute report, when the line ran (and did not skip) traceroute.
+    pub fn traceroute(&self) -> Option<&TracerouteReport> {
+        self.checks.iter().find_map(|check| match check {
+            CheckOutcome::Traceroute { report, .. } => report.as_ref(),
+            _ => None,
+        })
+    }
+}
+
+/// Run each config file as an isolated batch and merge the results in order.
+///
+/// Batches are only named when there is more than one, so single-config runs
+/// keep plain line names in reports, history and state.
+pub async fn run_configs(files: &[ConfigFile], options: RunOptions) -> Result<Vec<LineResult>> {
+    let configs = load_config_files(files, &options.load)?;
+    run_loaded(configs, options).await
+}
+
+/// [`run_configs`] for configs that are already loaded, such as the snapshot
+/// the scheduler swaps in between runs.
+///
+/// Every batch runs at once, sharing one concurrency limit.
+pub async fn run_loaded(
+    configs: Vec<(PathBuf, Config)>,
+    options: RunOptions,
+) -> Result<Vec<LineResult>> {
+    let limit = concurrency_limit(&options)?;
+    let batched = configs.len() > 1;
+    let batches = configs.into_iter().map(|(path, config)| {
+        let options = RunOptions {
+            batch: batched.then(|| batch_name(&path)),
+            ..options.clone()
+        };
+        run_lines(config, options, limit.clone())
     });
+    let results: Vec<LineResult> = try_join_all(batches).await?.into_iter().flatten().collect();
+    if results.is_empty() && !options.tags.is_empty() && options.due.is_none() {
+        anyhow::bail!("No configured line is tagged {}", options.tags.join(" or "));
+    }
+    Ok(results)
+}
 
-    stream::iter(config.lines)
-        .map(|line| {
-            let skip_traceroute = options.skip_traceroute;
-            async move {
-                let ping_report = run_ping(&line)
+/// Section name for a config batch: the file name without extension.
+pub fn batch_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Semaphore bounding how many lines are probed at once.
+fn concurrency_limit(options: &RunOptions) -> Result<Arc<Semaphore>> {
+    let permits = options.concurrency_limit.unwrap_or(DEFAULT_CONCURRENCY);
+    if permits == 0 {
+        anyhow::bail!("The concurrency limit must be at least 1");
+    }
+    Ok(Arc::new(Semaphore::new(permits)))
+}
+
+/// What every line of a batch is run with.
+struct LineContext {
+    skip_traceroute: bool,
+    history: Option<HistorySender>,
+    batch: Option<String>,
+    disabled: BTreeMap<String, u64>,
+    simulation: Option<Simulation>,
+    statuses: Vec<CustomStatus>,
+    now: DateTime<Utc>,
+    on_line_finished: Option<LineCallback>,
+}
+
+/// Execute diagnostics for every selected line, each as its own task once
+/// it holds a permit from `limit`.
+///
+/// Results are grouped by their first tag, groups in the order they first
+/// appear in the config and untagged lines last, then ordered by weight.
+async fn run_lines(
+    config: Config,
+    options: RunOptions,
+    limit: Arc<Semaphore>,
+) -> Result<Vec<LineResult>> {
+    let lines: Vec<LineSettings> = config
+        .lines
+        .into_iter()
+        .filter(|line| {
+            options.tags.is_empty() || line.tags.iter().any(|tag| options.tags.contains(tag))
+        })
+        .filter(|line| {
+            options.due.is_none_or(|due| {
+                line.schedule
+                    .as_ref()
+                    .is_some_and(|schedule| schedule.matches(due))
+            })
+        })
+        .collect();
+    let mut groups: Vec<String> = Vec::new();
+    for group in lines.iter().filter_map(|line| line.tags.first()) {
+        if !groups.contains(group) {
+            groups.push(group.clone());
+        }
+    }
+    let group_rank = |result: &LineResult| {
+        result.group().map_or(groups.len(), |group| {
+            groups.iter().position(|g| g == group).unwrap_or(0)
+        })
+    };
+
+    let interruptible = options.interruptible;
+    let context = Arc::new(LineContext {
+        skip_traceroute: options.skip_traceroute,
+        history: options.history,
+        batch: options.batch,
+        disabled: options.disabled,
+        simulation: options.simulation,
+        statuses: config.statuses,
+        now: Utc::now(),
+        on_line_finished: options.on_line_finished,
+    });
+    // Lines still running when the run is interrupted keep these.
+    let mut slots: Vec<LineResult> = lines
+        .iter()
+        .map(|line| LineResult {
+            interrupted: true,
+            ..new_result(line, &context)
+        })
+        .collect();
+    // Each line publishes its status for the lines that depend on it.
+    let verdicts: BTreeMap<String, watch::Sender<Option<LineStatus>>> = lines
+        .iter()
+        .map(|line| (line.name.clone(), watch::Sender::new(None)))
+        .collect();
+    let mut tasks = JoinSet::new();
+    for (index, line) in lines.into_iter().enumerate() {
+        let context = context.clone();
+        let limit = limit.clone();
+        let verdict = verdicts[&line.name].clone();
+        // A dependency left out of this run by tags or schedules is not waited for.
+        let dependency = line.depends_on.as_ref().and_then(|name| {
+            let status = verdicts.get(name)?.subscribe();
+            Some((name.clone(), status))
+        });
+        tasks.spawn(async move {
+            if let Some((name, mut status)) = dependency {
+                // Waited for before taking a permit, which the dependency may need.
+                let down = status
+                    .wait_for(Option::is_some)
                     .await
-                    .with_context(|| format!("Ping check failed for line '{}'", line.name))?;
-
-                let traceroute_report =
-                    if skip_traceroute {
-                        None
-                    } else {
-                        Some(run_traceroute(&line).await.with_context(|| {
-                            format!("Traceroute failed for line '{}'", line.name)
-                        })?)
+                    .is_ok_and(|status| status.is_some_and(dependency_down));
+                if down {
+                    let result = LineResult {
+                        dependency_down: Some(name),
+                        ..new_result(&line, &context)
                     };
+                    log_verdict(&result);
+                    if let Some(callback) = &context.on_line_finished {
+                        callback(&result);
+                    }
+                    verdict.send_replace(Some(result.status()));
+                    return anyhow::Ok((index, result));
+                }
+            }
+            let _permit = limit.acquire_owned().await?;
+            let started = Instant::now();
+            let mut result = run_line(line, &context).await?;
+            result.duration_ms = Some(started.elapsed().as_secs_f32() * 1000.0);
+            log_verdict(&result);
+            if let Some(callback) = &context.on_line_finished {
+                callback(&result);
+            }
+            verdict.send_replace(Some(result.status()));
+            anyhow::Ok((index, result))
+        });
+    }
+    drop(verdicts);
+    let interrupt = async {
+        if !interruptible || tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    tokio::pin!(interrupt);
+    // Dropping the set aborts the lines still running, on an early return as
+    // well as on interruption; their probe commands are killed with them.
+    loop {
+        tokio::select! {
+            joined = tasks.join_next() => {
+                let Some(joined) = joined else {
+                    break;
+                };
+                let (index, result) = joined.context("A line's diagnostics task panicked")??;
+                slots[index] = result;
+            }
+            () = &mut interrupt => break,
+        }
+    }
+    drop(tasks);
+    let mut results = slots;
+    // Stable, so equally weighted lines keep their config order.
+    results.sort_by(|a, b| {
+        group_rank(a)
+            .cmp(&group_rank(b))
+            .then(b.weight.total_cmp(&a.weight))
+    });
+    Ok(results)
+}
+
+/// What a line's result starts as, before any of its checks has run.
+fn new_result(line: &LineSettings, context: &LineContext) -> LineResult {
+    let simulated = context
+        .simulation
+        .as_ref()
+        .filter(|simulation| simulation.applies_to(&line.name))
+        .map(Simulation::describe);
+    LineResult {
+        name: line.name.clone(),
+        target: line.target.clone(),
+        checks: Vec::new(),
+        custom_status: None,
+        batch: context.batch.clone(),
+        error: None,
+        failed_targets: Vec::new(),
+        regressions: Vec::new(),
+        iterations: None,
+        dependency_down: None,
+        note: line.note.clone(),
+        runbook_url: line.runbook_url.clone(),
+        simulated,
+        weight: line.weight,
+        tags: line.tags.clone(),
+        snooze: snooze(line, context.now),
+        auto_disable: line.disable_after_errors.map(|after_errors| AutoDisable {
+            after_errors,
+            fingerprint: line.fingerprint(),
+            active: false,
+        }),
+        alert_delay: line.alert_after_failures.map(|after_failures| AlertDelay {
+            after_failures,
+            failing_runs: None,
+        }),
+        flap_detection: line.flap_threshold.map(|threshold| FlapDetection {
+            threshold,
+            window_minutes: line.flap_window_minutes,
+            changes: None,
+            flapping: false,
+            suppressed: false,
+        }),
+        interrupted: false,
+        duration_ms: None,
+    }
+}
+
+/// Run one line's checks, falling back through its targets, and record the
+/// result in the history store.
+async fn run_line(line: LineSettings, context: &LineContext) -> Result<LineResult> {
+    let skip_traceroute = context.skip_traceroute;
+    let history = &context.history;
+    let disabled = &context.disabled;
+    let statuses = &context.statuses;
+    let simulation = context
+        .simulation
+        .as_ref()
+        .filter(|simulation| simulation.applies_to(&line.name));
+    let mut result = new_result(&line, context);
+    if result.snooze.is_some() {
+        return Ok(result);
+    }
+    let key = result.key();
+    if let Some(auto) = result.auto_disable.as_mut() {
+        auto.active = disabled.get(&key) == Some(&auto.fingerprint);
+        if auto.active {
+            return Ok(result);
+        }
+    }
 
-                Ok(LineResult {
-                    name: line.name,
-                    target: line.target,
-                    loss_threshold: line.packet_loss_alert_threshold,
-                    ping: ping_report,
-                    traceroute: traceroute_report,
-                    traceroute_requested: !skip_traceroute,
-                })
+    let mut outcomes = Vec::new();
+    for kind in &line.checks {
+        match run_check(&line, *kind, skip_traceroute).await {
+            Ok(mut checks) => {
+                if let Some(simulation) = simulation {
+                    simulation.apply(&mut checks);
+                }
+                outcomes.push((*kind, checks));
             }
-        })
-        .buffer_unordered(concurrency)
-        .try_collect()
-        .await
+            Err(err) => {
+                result.error = Some(format!("{err:#}"));
+                break;
+            }
+        }
+    }
+
+    // Fall back through the remaining targets while the checks of
+    // the current one alert, replacing their outcomes in place.
+    let mut fallbacks = line.fallback_targets.iter();
+    while result.error.is_none() && target_alerts(&outcomes) {
+        let Some(fallback) = fallbacks.next() else {
+            break;
+        };
+        let fallback_line = LineSettings {
+            target: fallback.clone(),
+            ..line.clone()
+        };
+        for (kind, checks) in &mut outcomes {
+            if !kind.uses_target() {
+                continue;
+            }
+            match run_check(&fallback_line, *kind, skip_traceroute).await {
+                Ok(mut retried) => {
+                    if let Some(simulation) = simulation {
+                        simulation.apply(&mut retried);
+                    }
+                    *checks = retried;
+                }
+                Err(err) => {
+                    result.error = Some(format!("{err:#}"));
+                    break;
+                }
+            }
+        }
+        let failed = std::mem::replace(&mut result.target, fallback.clone());
+        result.failed_targets.push(failed);
+    }
+    result.checks = outcomes
+        .into_iter()
+        .flat_map(|(_, checks)| checks)
+        .collect();
+    result.custom_status = classify_custom(&result, statuses).cloned();
+
+    // Simulated failures would skew the long-term statistics.
+    if let (Some(history), None) = (&history, &result.simulated) {
+        history.record(HistoryRecord::from_result(&result)).await?;
+    }
+
+    Ok(result)
 }
 
-/// Stream a human-friendly summary of the diagnostic results to STDOUT.
-pub fn print_cli(results: &[LineResult]) {
-    for result in results {
-        println!("=== ICMPMolester: {} ({}) ===", result.name, result.target);
-        print_ping_summary(result);
+/// Why `line` is skipped for maintenance at `now`, if it is.
+fn snooze(line: &LineSettings, now: DateTime<Utc>) -> Option<Snooze> {
+    if !line.enabled {
+        return Some(Snooze::Disabled);
+    }
+    line.snooze_until
+        .filter(|until| *until > now)
+        .map(Snooze::Until)
+}
+
+/// Whether any check that probes the line's target alerted.
+fn target_alerts(outcomes: &[(CheckKind, Vec<CheckOutcome>)]) -> bool {
+    outcomes
+        .iter()
+        .filter(|(kind, _)| kind.uses_target())
+        .flat_map(|(_, checks)| checks)
+        .any(|check| check.status() == Some(LineStatus::Alert))
+}
 
-        match (&result.traceroute, result.traceroute_requested) {
-            (Some(report), _) => print_traceroute_summary(report),
-            (None, true) => println!("Traceroute status: ALERT command failed"),
-            (None, false) => println!("Traceroute: skipped"),
+/// Log how a finished line's status was reached, check by check.
+fn log_verdict(result: &LineResult) {
+    debug!(
+        line = %result.key(),
+        status = %result.status().as_str(),
+        duration_ms = ?result.duration_ms,
+        error = ?result.error,
+        "Line finished"
+    );
+    for check in &result.checks {
+        match assess(check) {
+            Some(assessment) => debug!(
+                line = %result.key(),
+                check = %check.label(),
+                status = %assessment.status.as_str(),
+                reason = ?assessment.reason,
+                "Check assessed"
+            ),
+            None => debug!(line = %result.key(), check = %check.label(), "Check skipped"),
         }
+    }
+}
 
+/// Stream a human-friendly summary of the diagnostic results to STDOUT.
+///
+/// `trends` holds each line's recent latencies from the history store, keyed
+/// like [`LineResult::key`], shown as a sparkline when there are enough.
+pub fn print_cli(
+    results: &[LineResult],
+    trends: &BTreeMap<String, Vec<Option<f32>>>,
+    only_alerts: bool,
+) {
+    let mut section = None;
+    let mut group = None;
+    for result in results {
+        if only_alerts && !needs_attention(result) {
+            continue;
+        }
+        if let Some(batch) = new_section(&mut section, result) {
+            let rollup = Rollup::of_batch(results, batch);
+            let header = format!("##### {batch}: {} #####", rollup.describe());
+            println!("{}", style::paint(&header, rollup.status.color()));
+            println!();
+        }
+        if let Some(tag) = new_group(&mut group, result) {
+            let rollup = Rollup::of_group(results, result.batch.as_deref(), tag);
+            let header = format!("--- {}: {} ---", group_label(tag), rollup.describe());
+            println!("{}", style::paint(&header, rollup.status.color()));
+            println!();
+        }
+        println!(
+            "{}",
+            style::bold(&format!(
+                "=== ICMPMolester: {} ({}) ===",
+                result.name, result.target
+            ))
+        );
+        let status = result.display_status();
+        if style::enabled() {
+            // Icons go with color only, so piped output stays plain text.
+            println!(
+                "Overall status: {} {}",
+                result.status_emoji(),
+                style::paint(status, result.status_color())
+            );
+        } else {
+            println!("Overall status: {status}");
+        }
+        if let Some(note) = result.execution_note() {
+            println!("{note}");
+        }
+        for note in result
+            .snooze_note()
+            .into_iter()
+            .chain(result.dependency_note())
+            .chain(result.alert_delay_note())
+            .chain(result.flap_note())
+            .chain(result.fallback_note())
+            .chain(result.confirmation_note())
+            .chain(result.iteration_note())
+            .chain(result.baseline_note())
+            .chain(result.simulation_note())
+        {
+            println!("{note}");
+        }
+        if let Some(trend) = trends.get(&result.key()) {
+            if trend.iter().flatten().count() >= 2 {
+                println!(
+                    "Latency trend (last {} runs): {}",
+                    trend.len(),
+                    style::sparkline(trend)
+                );
+            }
+        }
+        for step in result.next_steps() {
+            println!("{step}");
+        }
+        for check in &result.checks {
+            print_check(check);
+        }
         println!();
     }
+    if let Some(footer) = only_alerts.then(|| hidden_lines(results)).flatten() {
+        println!("{footer}");
+    }
+    if results.len() > 1 {
+        let rollup = Rollup::of(results);
+        let overall = format!("Overall: {}", rollup.describe());
+        println!("{}", style::paint(&overall, rollup.status.color()));
+    }
 }
 
 /// Produce a concise text summary suitable for notifications.
-pub fn format_summary(results: &[LineResult]) -> String {
+///
+/// Each line gets a header with its worst-of status followed by one indented
+/// sub-row per check. With `only_alerts`, OK and snoozed lines are only
+/// counted at the end.
+pub fn format_summary(results: &[LineResult], only_alerts: bool) -> String {
     let mut summary = String::from("ICMPMolester summary\n");
+    if results.len() > 1 {
+        summary.push_str(&format!("Overall: {}\n", Rollup::of(results).describe()));
+    }
 
+    let mut section = None;
+    let mut group = None;
     for result in results {
-        let loss_text = result
-            .ping
-            .packet_loss_pct
-            .map(|loss| format!("{loss:.2}%"))
-            .unwrap_or_else(|| "n/a".into());
-        let latency_text = result
-            .ping
-            .average_latency_ms
-            .map(|latency| format!("{latency:.2} ms"))
-            .unwrap_or_else(|| "n/a".into());
-        let loss_status = match result.ping.packet_loss_pct {
-            Some(loss) if loss > result.loss_threshold => "ALERT",
-            Some(_) => "OK",
-            None => "UNKNOWN",
+        if only_alerts && !needs_attention(result) {
+            continue;
+        }
+        if let Some(batch) = new_section(&mut section, result) {
+            let rollup = Rollup::of_batch(results, batch);
+            summary.push_str(&format!("\n[{batch}] {}\n", rollup.describe()));
+        }
+        if let Some(tag) = new_group(&mut group, result) {
+            let rollup = Rollup::of_group(results, result.batch.as_deref(), tag);
+            summary.push_str(&format!("\n{}: {}\n", group_label(tag), rollup.describe()));
+        }
+        summary.push_str(&format!(
+            "- {} ({}): {}\n",
+            result.name,
+            result.target,
+            result.display_status()
+        ));
+        for row in summary_rows(result) {
+            summary.push_str(&format!("    {row}\n"));
+        }
+    }
+    if let Some(footer) = only_alerts.then(|| hidden_lines(results)).flatten() {
+        summary.push_str(&format!("\n{footer}\n"));
+    }
+
+    summary
+}
+
+/// The rows [`format_summary`] lists under a line's header: its notes, next
+/// steps and one row per check, with named or annotated hops and changed
+/// routes indented below.
+pub fn summary_rows(result: &LineResult) -> Vec<String> {
+    let mut rows: Vec<String> = result
+        .execution_note()
+        .into_iter()
+        .chain(result.snooze_note())
+        .chain(result.dependency_note())
+        .chain(result.alert_delay_note())
+        .chain(result.flap_note())
+        .chain(result.fallback_note())
+        .chain(result.confirmation_note())
+        .chain(result.iteration_note())
+        .chain(result.baseline_note())
+        .chain(result.simulation_note())
+        .collect();
+    rows.extend(result.next_steps());
+    for check in &result.checks {
+        rows.push(check_summary(check));
+        let CheckOutcome::Traceroute {
+            report,
+            route_change,
+            ..
+        } = check
+        else {
+            continue;
         };
-        let ping_status = if result.ping.success { "OK" } else { "ALERT" };
-        let traceroute_status = match (&result.traceroute, result.traceroute_requested) {
-            (Some(report), _) if report.success => "OK",
-            (Some(_), _) => "ALERT",
-            (None, true) => "ALERT",
-            (None, false) => "SKIPPED",
+        // The path is only worth listing once its hops are named or
+        // annotated with their origin.
+        let annotated = |hop: &TracerouteHop| {
+            hop.hostname.is_some() || hop.asn.is_some() || hop.country.is_some()
         };
-        let hops_text = result
-            .traceroute
+        if let Some(report) = report
             .as_ref()
-            .and_then(|r| r.hop_count)
-            .map(|h| h.to_string())
-            .unwrap_or_else(|| "n/a".into());
+            .filter(|report| report.hops.iter().any(annotated))
+        {
+            rows.extend(
+                report
+                    .hops
+                    .iter()
+                    .map(|hop| format!("  {}", hop_summary(hop))),
+            );
+        }
+        for (marker, row) in route_change.iter().flat_map(route_diff_rows) {
+            rows.push(format!("  {marker} {row}"));
+        }
+    }
+    rows
+}
 
-        summary.push_str(&format!(
-            "- {} ({}): ping={ping_status}, loss={loss_text} ({loss_status}), latency={}, traceroute={}, hops={}\n",
-            result.name, result.target, latency_text, traceroute_status, hops_text
-        ));
+/// Whether `--only-alerts` keeps a line: anything but OK and snoozed lines.
+pub fn needs_attention(result: &LineResult) -> bool {
+    !matches!(result.status(), LineStatus::Ok | LineStatus::Snoozed)
+}
+
+/// What `--only-alerts` left out, e.g. `42 lines OK, 3 snoozed`; `None`
+/// when every line is shown.
+pub fn hidden_lines(results: &[LineResult]) -> Option<String> {
+    let count = |status| {
+        results
+            .iter()
+            .filter(|result| result.status() == status)
+            .count()
+    };
+    let (ok, snoozed) = (count(LineStatus::Ok), count(LineStatus::Snoozed));
+    if ok + snoozed == 0 {
+        return None;
     }
+    let mut footer = match ok {
+        1 => "1 line OK".to_string(),
+        n => format!("{n} lines OK"),
+    };
+    if snoozed > 0 {
+        footer.push_str(&format!(", {snoozed} snoozed"));
+    }
+    Some(footer)
+}
 
-    summary
+/// Before/after rows for a changed route, marked `-` (baseline) or `+` (now).
+///
+/// Empty when the path matches the baseline. Unchanged hops are kept, marked
+/// with a blank, so the diverging hops can be read in context.
+fn route_diff_rows(change: &RouteChange) -> Vec<(char, String)> {
+    if change.differing == 0 {
+        return Vec::new();
+    }
+    let address = |ip: Option<IpAddr>| ip.map_or_else(|| "*".to_string(), |ip| ip.to_string());
+    let row = |hop: u32, ip: Option<IpAddr>| format!("{hop:>2}  {}", address(ip));
+    let mut rows = Vec::new();
+    for hop in &change.hops {
+        match *hop {
+            HopDiff::Same { hop, address } => rows.push((' ', row(hop, address))),
+            HopDiff::Changed { hop, before, after } => {
+                rows.push(('-', row(hop, before)));
+                rows.push(('+', row(hop, after)));
+            }
+            HopDiff::Removed { hop, address } => rows.push(('-', row(hop, address))),
+            HopDiff::Added { hop, address } => rows.push(('+', row(hop, address))),
+        }
+    }
+    rows
 }
 
 /// Produce a condensed summary optimized for short transport channels (e.g. Telegram).
 pub fn format_compact_summary(results: &[LineResult]) -> String {
     let mut summary = String::from("ICMPMolester report\n");
+    if results.len() > 1 {
+        summary.push_str(&format!("Overall {}\n", Rollup::of(results).compact()));
+    }
 
+    let mut section = None;
+    let mut group = None;
     for result in results {
-        let ping_status = if result.ping.success { "✅" } else { "⚠️" };
-        let loss = result
-            .ping
-            .packet_loss_pct
-            .map(|loss| format!("{loss:.2}%"))
-            .unwrap_or_else(|| "n/a".into());
-        let loss_tag = match result.ping.packet_loss_pct {
-            Some(loss) if loss > result.loss_threshold => "alert",
-            Some(_) => "ok",
-            None => "n/a",
-        };
-        let traceroute_status = match (&result.traceroute, result.traceroute_requested) {
-            (Some(report), _) if report.success => "ok",
-            (Some(_), _) => "alert",
-            (None, true) => "alert",
-            (None, false) => "skip",
-        };
-        let hops = result
-            .traceroute
-            .as_ref()
-            .and_then(|r| r.hop_count)
-            .map(|h| h.to_string())
-            .unwrap_or_else(|| "n/a".into());
-
-        summary.push_str(&format!(
-            "• {name}: ping {ping_status} loss {loss} [{loss_tag}] hops {hops} path {traceroute_status}\n",
-            name = result.name
-        ));
+        if let Some(batch) = new_section(&mut section, result) {
+            let rollup = Rollup::of_batch(results, batch);
+            summary.push_str(&format!("\n📁 {batch} {}\n", rollup.compact()));
+        }
+        if let Some(tag) = new_group(&mut group, result) {
+            let rollup = Rollup::of_group(results, result.batch.as_deref(), tag);
+            summary.push_str(&format!("🏷️ {} {}\n", group_label(tag), rollup.compact()));
+        }
+        summary.push_str(&format!("• {} {}\n", result.name, result.status_emoji()));
+        for note in result
+            .execution_note()
+            .into_iter()
+            .chain(result.snooze_note())
+            .chain(result.dependency_note())
+            .chain(result.alert_delay_note())
+            .chain(result.flap_note())
+            .chain(result.fallback_note())
+            .chain(result.confirmation_note())
+            .chain(result.iteration_note())
+            .chain(result.baseline_note())
+            .chain(result.simulation_note())
+        {
+            summary.push_str(&format!("  {note}\n"));
+        }
+        for step in result.next_steps() {
+            summary.push_str(&format!("  {step}\n"));
+        }
+        for check in &result.checks {
+            summary.push_str(&format!("  {}\n", compact_check_summary(check)));
+        }
     }
 
     summary
 }
 
-fn print_ping_summary(result: &LineResult) {
-    println!("Ping status: {}", bool_to_status(result.ping.success));
-    match result.ping.packet_loss_pct {
+/// Tag group when `result` opens a new group within its batch; `Some(None)`
+/// heads the untagged lines that follow tagged ones.
+fn new_group<'a>(
+    current: &mut Option<(Option<&'a str>, Option<&'a str>)>,
+    result: &'a LineResult,
+) -> Option<Option<&'a str>> {
+    let key = (result.batch.as_deref(), result.group());
+    let previous = current.replace(key);
+    if previous == Some(key) {
+        return None;
+    }
+    match (key.1, previous) {
+        (Some(tag), _) => Some(Some(tag)),
+        (None, Some((batch, Some(_)))) if batch == key.0 => Some(None),
+        _ => None,
+    }
+}
+
+fn group_label(tag: Option<&str>) -> String {
+    tag.map_or_else(|| "(untagged)".to_string(), |tag| format!("#{tag}"))
+}
+
+/// Batch name when `result` opens a new per-config section.
+fn new_section<'a>(current: &mut Option<&'a str>, result: &'a LineResult) -> Option<&'a str> {
+    let batch = result.batch.as_deref()?;
+    if *current == Some(batch) {
+        return None;
+    }
+    *current = Some(batch);
+    Some(batch)
+}
+
+fn print_check(check: &CheckOutcome) {
+    match check {
+        CheckOutcome::Ping {
+            report,
+            loss_threshold,
+            duplicate_alert,
+            reorder_alert,
+            ..
+        } => print_ping_summary(report, *loss_threshold, *duplicate_alert, *reorder_alert),
+        CheckOutcome::Traceroute {
+            report: Some(report),
+            expected_gateway,
+            hop_tolerance,
+            route_change,
+            hop_loss,
+        } => {
+            print_traceroute_summary(
+                report,
+                *hop_tolerance,
+                route_change.as_ref(),
+                hop_loss.as_ref(),
+            );
+            if let Some(expected) = expected_gateway {
+                match report.first_hop_address() {
+                    Some(seen) if seen == *expected => println!("Gateway: {seen} (expected)"),
+                    Some(seen) => println!("Gateway: {seen} (ALERT expected {expected})"),
+                    None => println!("Gateway: no reply (ALERT expected {expected})"),
+                }
+            }
+        }
+        CheckOutcome::Traceroute { report: None, .. } => println!("Traceroute: skipped"),
+        CheckOutcome::Tls {
+            report,
+            expiry_alert_days,
+        } => print_tls_summary(report, *expiry_alert_days),
+        CheckOutcome::Ntp {
+            report,
+            offset_alert_ms,
+        } => print_ntp_summary(report, *offset_alert_ms),
+        CheckOutcome::Dns {
+            report,
+            latency_alert_ms,
+        } => print_dns_summary(report, *latency_alert_ms),
+        CheckOutcome::Http {
+            report,
+            latency_alert_ms,
+        } => print_http_summary(report, *latency_alert_ms),
+        CheckOutcome::PublicIp {
+            report,
+            changed_from,
+            wan_address,
+            ..
+        } => print_public_ip_summary(report, *changed_from, *wan_address),
+        CheckOutcome::DualStack {
+            report,
+            loss_threshold,
+            delta_alert_ms,
+        } => print_dual_stack_summary(report, *loss_threshold, *delta_alert_ms),
+        CheckOutcome::Custom { report } => print_custom_summary(report),
+    }
+}
+
+fn check_summary(check: &CheckOutcome) -> String {
+    let status = check.status().map_or("SKIPPED", LineStatus::as_str);
+    match check {
+        CheckOutcome::Ping {
+            report,
+            loss_threshold,
+            ..
+        } => {
+            let ping_status = if report.success { "OK" } else { "ALERT" };
+            let loss_text = report
+                .packet_loss_pct
+                .map(|loss| format!("{loss:.2}%"))
+                .unwrap_or_else(|| "n/a".into());
+            let loss_status = match report.packet_loss_pct {
+                Some(_) if report.loss_breached(*loss_threshold) => "ALERT",
+                Some(_) => "OK",
+                None => "UNKNOWN",
+            };
+            let latency_text = report
+                .average_latency_ms
+                .map(|latency| format!("{latency:.2} ms"))
+                .unwrap_or_else(|| "n/a".into());
+            let mut row = format!(
+                "ping={ping_status}, loss={loss_text} ({loss_status}), latency={latency_text}"
+            );
+            if report.duplicates > 0 {
+                row.push_str(&format!(", duplicates={}", report.duplicates));
+            }
+            if report.reordered > 0 {
+                row.push_str(&format!(", reordered={}", report.reordered));
+            }
+            row
+        }
+        CheckOutcome::Traceroute { report: None, .. } => "traceroute=SKIPPED".into(),
+        CheckOutcome::Traceroute {
+            report: Some(report),
+            expected_gateway,
+            route_change,
+            hop_loss,
+            ..
+        } => {
+            let hops = report
+                .hop_count
+                .map(|h| h.to_string())
+                .unwrap_or_else(|| "n/a".into());
+            let mut row = format!("traceroute={status}, hops={hops}");
+            if let Some(expected) = expected_gateway {
+                match report.first_hop_address() {
+                    Some(seen) if seen == *expected => {}
+                    Some(seen) => row.push_str(&format!(", gateway {seen} != {expected}")),
+                    None => row.push_str(&format!(", gateway silent != {expected}")),
+                }
+            }
+            if let Some(change) = route_change.as_ref().filter(|change| change.differing > 0) {
+                row.push_str(&format!(", route changed at {} hops", change.differing));
+            }
+            if let Some(loss) = hop_loss
+                .as_ref()
+                .and_then(|policy| report.persistent_loss(policy))
+            {
+                row.push_str(&format!(
+                    ", loss {:.1}% from hop {}",
+                    loss.final_loss_pct, loss.from_hop
+                ));
+            }
+            row
+        }
+        CheckOutcome::Tls { report, .. } => format!("tls={}", tls_summary(report, status)),
+        CheckOutcome::Ntp { report, .. } => format!("ntp={}", ntp_summary(report, status)),
+        CheckOutcome::Dns { report, .. } => {
+            format!("{}={}", check.label(), dns_summary(report, status))
+        }
+        CheckOutcome::Http { report, .. } => format!("http={}", http_summary(report, status)),
+        CheckOutcome::PublicIp {
+            report,
+            changed_from,
+            wan_address,
+            ..
+        } => {
+            let mut row = format!(
+                "public_ip={}",
+                public_ip_summary(report, *changed_from, status)
+            );
+            if let Some(translation) = wan_address.and_then(|wan| report.translation(wan)) {
+                row.push_str(&format!(", {}", translation.as_str()));
+            }
+            row
+        }
+        CheckOutcome::DualStack { report, .. } => {
+            format!("dual_stack={}", dual_stack_summary(report, status))
+        }
+        CheckOutcome::Custom { report } => format!("custom={}", custom_summary(report, status)),
+    }
+}
+
+fn compact_check_summary(check: &CheckOutcome) -> String {
+    let tag = match check.status() {
+        Some(LineStatus::Snoozed) => "snoozed",
+        Some(LineStatus::Ok) => "ok",
+        Some(LineStatus::Interrupted) => "interrupted",
+        Some(LineStatus::DependencyDown) => "dependency down",
+        Some(LineStatus::Alert) => "alert",
+        Some(LineStatus::Unknown) => "n/a",
+        Some(LineStatus::Errored) => "errored",
+        None => "skip",
+    };
+    match check {
+        CheckOutcome::Ping {
+            report,
+            loss_threshold,
+            ..
+        } => {
+            let ping_status = if report.success { "✅" } else { "⚠️" };
+            let loss = report
+                .packet_loss_pct
+                .map(|loss| format!("{loss:.2}%"))
+                .unwrap_or_else(|| "n/a".into());
+            let loss_tag = match report.packet_loss_pct {
+                Some(_) if report.loss_breached(*loss_threshold) => "alert",
+                Some(_) => "ok",
+                None => "n/a",
+            };
+            format!("ping {ping_status} loss {loss} [{loss_tag}]")
+        }
+        CheckOutcome::Traceroute { report, .. } => {
+            let hops = report
+                .as_ref()
+                .and_then(|r| r.hop_count)
+                .map(|h| h.to_string())
+                .unwrap_or_else(|| "n/a".into());
+            format!("path {tag} hops {hops}")
+        }
+        other => format!("{} {tag}", other.label()),
+    }
+}
+
+fn print_ping_summary(
+    report: &PingReport,
+    loss_threshold: f32,
+    duplicate_alert: Option<u32>,
+    reorder_alert: Option<u32>,
+) {
+    println!("Ping status: {}", bool_to_status(report.success));
+    match report.packet_loss_pct {
         Some(loss) => {
-            let status = if loss > result.loss_threshold {
+            let status = if report.loss_breached(loss_threshold) {
                 "ALERT above threshold"
             } else {
                 "OK within threshold"
@@ -174,17 +1459,48 @@ fn print_ping_summary(result: &LineResult) {
         None => println!("Packet loss: unavailable"),
     }
 
-    match result.ping.average_latency_ms {
+    match report.average_latency_ms {
         Some(latency) => println!("Average latency: {latency:.2} ms"),
         None => println!("Average latency: unavailable"),
     }
 
-    if !result.ping.success {
-        print_command_output("Ping output", &result.ping.raw_output);
+    let anomaly_status = |count: u32, limit: Option<u32>| match limit {
+        Some(limit) if count > limit => format!(" (ALERT above {limit})"),
+        _ => String::new(),
+    };
+    if report.duplicates > 0 {
+        println!(
+            "Duplicate replies: {}{}",
+            report.duplicates,
+            anomaly_status(report.duplicates, duplicate_alert)
+        );
+    }
+    if report.reordered > 0 {
+        println!(
+            "Out-of-order replies: {}{}",
+            report.reordered,
+            anomaly_status(report.reordered, reorder_alert)
+        );
+    }
+
+    if let Some(histogram) = Histogram::of_ping(report) {
+        println!("Latency histogram ({} replies):", histogram.replies());
+        for row in histogram.rows() {
+            println!("  {row}");
+        }
+    }
+
+    if !report.success {
+        print_command_output("Ping output", &report.raw_output);
     }
 }
 
-fn print_traceroute_summary(report: &TracerouteReport) {
+fn print_traceroute_summary(
+    report: &TracerouteReport,
+    hop_tolerance: Option<u32>,
+    route_change: Option<&RouteChange>,
+    hop_loss: Option<&HopLossPolicy>,
+) {
     println!("Traceroute status: {}", bool_to_status(report.success));
     match report.raw_output.lines().next() {
         Some(line) if !line.trim().is_empty() => println!("First hop: {line}"),
@@ -195,12 +1511,351 @@ fn print_traceroute_summary(report: &TracerouteReport) {
         Some(hops) => println!("Hops observed: {hops}"),
         None => println!("Hop count: unavailable"),
     }
+    for hop in &report.hops {
+        println!("  {}", hop_summary(hop));
+    }
+    if let Some(error) = &report.enrichment_error {
+        println!("  Hop enrichment failed: {error}");
+    }
+    match (route_change.map(|change| change.differing), hop_tolerance) {
+        (Some(0), _) => println!("Route: matches baseline"),
+        (Some(changed), Some(tolerance)) if changed > tolerance => {
+            println!("Route: {changed} hops differ from baseline (ALERT above {tolerance})")
+        }
+        (Some(changed), _) => println!("Route: {changed} hops differ from baseline"),
+        (None, _) => {}
+    }
+    if let Some(change) = route_change {
+        for (marker, row) in route_diff_rows(change) {
+            let line = format!("{marker} {row}");
+            match marker {
+                '-' => println!("  {}", style::paint(&line, StatusColor::Red)),
+                '+' => println!("  {}", style::paint(&line, StatusColor::Green)),
+                _ => println!("  {line}"),
+            }
+        }
+    }
+    if let Some(policy) = hop_loss {
+        let path_loss = report.persistent_loss(policy);
+        let first_persistent = path_loss.map_or(u32::MAX, |loss| loss.from_hop);
+        let ignored: Vec<String> = report
+            .hops
+            .iter()
+            .filter(|hop| hop.address.is_some() && hop.number < first_persistent)
+            .filter(|hop| {
+                hop.loss_pct
+                    .is_some_and(|loss| loss > policy.threshold(hop.number))
+            })
+            .map(|hop| hop.number.to_string())
+            .collect();
+        if !ignored.is_empty() {
+            println!(
+                "Hop loss: ignored at hop(s) {} (does not reach the destination)",
+                ignored.join(", ")
+            );
+        }
+        match path_loss {
+            Some(loss) => println!(
+                "Path loss: ALERT {:.1}% from hop {} persists to hop {} ({:.1}%)",
+                loss.from_loss_pct, loss.from_hop, loss.final_hop, loss.final_loss_pct
+            ),
+            None => println!("Path loss: OK"),
+        }
+    }
 
     if !report.success {
         print_command_output("Traceroute output", &report.raw_output);
     }
 }
 
+fn hop_summary(hop: &TracerouteHop) -> String {
+    let rtt = hop
+        .rtt_ms
+        .map(|ms| format!("  {ms:.2} ms"))
+        .unwrap_or_default();
+    let loss = hop
+        .loss_pct
+        .map(|pct| format!("  loss {pct:.1}%"))
+        .unwrap_or_default();
+    let origin = match (hop.asn, &hop.as_name, &hop.country) {
+        (None, _, None) => String::new(),
+        (asn, name, country) => {
+            let asn = asn.map(|asn| format!("AS{asn}"));
+            let parts: Vec<&str> = [asn.as_deref(), name.as_deref(), country.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect();
+            format!("  [{}]", parts.join(" / "))
+        }
+    };
+    match (&hop.hostname, hop.address) {
+        (Some(name), Some(address)) => {
+            format!("{:>2}  {name} ({address}){rtt}{loss}{origin}", hop.number)
+        }
+        (None, Some(address)) => format!("{:>2}  {address}{rtt}{loss}{origin}", hop.number),
+        (_, None) => format!("{:>2}  *{loss}", hop.number),
+    }
+}
+
+fn print_tls_summary(report: &TlsReport, expiry_alert_days: u32) {
+    let status = if report.success {
+        "OK handshake complete"
+    } else {
+        "ALERT handshake failed"
+    };
+    println!("TLS {}: {status}", report.endpoint);
+    if let Some(error) = &report.error {
+        println!("  Error: {error}");
+    }
+    if let Some(handshake) = report.handshake_ms {
+        println!("  Handshake: {handshake:.2} ms");
+    }
+    if let Some(protocol) = &report.protocol {
+        println!("  Protocol: {protocol}");
+    }
+    match report.days_until_expiry {
+        Some(days) if report.expiry_breached(expiry_alert_days) => {
+            println!("  Certificate expires in {days} days (ALERT below {expiry_alert_days} days)")
+        }
+        Some(days) => println!("  Certificate expires in {days} days"),
+        None if report.success => println!("  Certificate expiry: unavailable"),
+        None => {}
+    }
+}
+
+fn tls_summary(report: &TlsReport, status: &str) -> String {
+    if !report.success {
+        return format!("{status} (handshake failed)");
+    }
+    let expiry = report
+        .days_until_expiry
+        .map(|days| format!("{days}d"))
+        .unwrap_or_else(|| "n/a".into());
+    let protocol = report.protocol.as_deref().unwrap_or("n/a");
+    format!("{status} ({protocol}, expires {expiry})")
+}
+
+fn print_ntp_summary(report: &NtpReport, offset_alert_ms: f32) {
+    let status = if report.success {
+        "OK reply received"
+    } else {
+        "ALERT unreachable"
+    };
+    println!("NTP {}: {status}", report.server);
+    if let Some(error) = &report.error {
+        println!("  Error: {error}");
+    }
+    if let Some(offset) = report.offset_ms {
+        if report.offset_breached(offset_alert_ms) {
+            println!("  Offset: {offset:+.2} ms (ALERT beyond ±{offset_alert_ms:.0} ms)");
+        } else {
+            println!("  Offset: {offset:+.2} ms");
+        }
+    }
+    if let Some(delay) = report.delay_ms {
+        println!("  Delay: {delay:.2} ms");
+    }
+    if let Some(stratum) = report.stratum {
+        println!("  Stratum: {stratum}");
+    }
+}
+
+fn ntp_summary(report: &NtpReport, status: &str) -> String {
+    match report.offset_ms {
+        Some(offset) => format!("{status} (offset {offset:+.1} ms)"),
+        None => format!("{status} (unreachable)"),
+    }
+}
+
+fn print_dns_summary(report: &DnsReport, latency_alert_ms: f32) {
+    let status = if report.success {
+        "OK answered"
+    } else {
+        "ALERT query failed"
+    };
+    println!("{} {}: {status}", report.transport, report.resolver);
+    if let Some(error) = &report.error {
+        println!("  Error: {error}");
+    }
+    if let Some(latency) = report.latency_ms {
+        if report.latency_breached(latency_alert_ms) {
+            println!("  Query latency: {latency:.2} ms (ALERT above {latency_alert_ms:.0} ms)");
+        } else {
+            println!("  Query latency: {latency:.2} ms");
+        }
+    }
+    if let Some(answers) = report.answers {
+        println!("  Answers: {answers}");
+    }
+}
+
+fn dns_summary(report: &DnsReport, status: &str) -> String {
+    match report.latency_ms {
+        Some(latency) => format!("{status} ({latency:.1} ms)"),
+        None => format!("{status} (no answer)"),
+    }
+}
+
+fn print_http_summary(report: &HttpReport, latency_alert_ms: f32) {
+    let status = if report.success {
+        "OK expected response"
+    } else {
+        "ALERT request failed"
+    };
+    println!("HTTP {}: {status}", report.url);
+    if let Some(error) = &report.error {
+        println!("  Error: {error}");
+    }
+    if let Some(code) = report.status_code {
+        println!("  Status code: {code}");
+    }
+    if let Some(latency) = report.latency_ms {
+        if report.latency_breached(latency_alert_ms) {
+            println!("  Response time: {latency:.2} ms (ALERT above {latency_alert_ms:.0} ms)");
+        } else {
+            println!("  Response time: {latency:.2} ms");
+        }
+    }
+}
+
+fn http_summary(report: &HttpReport, status: &str) -> String {
+    match (report.status_code, report.latency_ms) {
+        (Some(code), Some(latency)) => format!("{status} ({code}, {latency:.1} ms)"),
+        _ => format!("{status} (no response)"),
+    }
+}
+
+fn print_custom_summary(report: &CustomReport) {
+    println!("Custom {}: {}", report.command, report.status.as_str());
+    if let Some(message) = &report.message {
+        println!("  Message: {message}");
+    }
+    match report.exit_code {
+        Some(code) => println!("  Exit code: {code} after {:.0} ms", report.duration_ms),
+        None => println!("  No exit code after {:.0} ms", report.duration_ms),
+    }
+    for (name, value) in &report.metrics {
+        println!("  {name}: {value}");
+    }
+}
+
+fn custom_summary(report: &CustomReport, status: &str) -> String {
+    match &report.message {
+        Some(message) => format!("{status} ({message})"),
+        None => status.to_string(),
+    }
+}
+
+fn print_public_ip_summary(
+    report: &PublicIpReport,
+    changed_from: Option<IpAddr>,
+    wan_address: Option<IpAddr>,
+) {
+    match (report.address, changed_from) {
+        (Some(address), Some(previous)) => {
+            println!("Public IP: {address} (ALERT changed from {previous})")
+        }
+        (Some(address), None) => println!("Public IP: {address}"),
+        (None, _) => println!("Public IP: ALERT lookup via {} failed", report.url),
+    }
+    if let Some(error) = &report.error {
+        println!("  Error: {error}");
+    }
+    if let Some(wan) = wan_address {
+        match report.translation(wan) {
+            Some(translation) => println!("  WAN {wan}: behind {}", translation.as_str()),
+            None if report.address.is_some() => println!("  WAN {wan}: no carrier NAT"),
+            None => {}
+        }
+    }
+}
+
+fn public_ip_summary(
+    report: &PublicIpReport,
+    changed_from: Option<IpAddr>,
+    status: &str,
+) -> String {
+    match (report.address, changed_from) {
+        (Some(address), Some(previous)) => format!("{status} ({address}, was {previous})"),
+        (Some(address), None) => format!("{status} ({address})"),
+        (None, _) => format!("{status} (lookup failed)"),
+    }
+}
+
+fn print_dual_stack_summary(
+    report: &DualStackReport,
+    loss_threshold: f32,
+    delta_alert_ms: Option<f32>,
+) {
+    if let Some(error) = &report.error {
+        println!("Dual-stack: UNKNOWN could not resolve target ({error})");
+        return;
+    }
+    let legs = [("IPv4", &report.ipv4), ("IPv6", &report.ipv6)];
+    for (family, leg) in legs {
+        let Some(leg) = leg else {
+            println!("Dual-stack {family}: no address, skipped");
+            continue;
+        };
+        let ping = &leg.report;
+        let loss = match ping.packet_loss_pct {
+            Some(loss) if ping.loss_breached(loss_threshold) => {
+                format!("{loss:.2}% (ALERT above threshold)")
+            }
+            Some(loss) => format!("{loss:.2}%"),
+            None => "unavailable".into(),
+        };
+        let latency = ping
+            .average_latency_ms
+            .map(|ms| format!("{ms:.2} ms"))
+            .unwrap_or_else(|| "unavailable".into());
+        println!(
+            "Dual-stack {family} ({}): {}, loss {loss}, latency {latency}",
+            leg.address,
+            bool_to_status(ping.success)
+        );
+    }
+    if let Some(delta) = report.latency_delta_ms() {
+        let status = match delta_alert_ms {
+            Some(limit) if delta.abs() > limit => format!(" (ALERT above {limit:.1} ms)"),
+            _ => String::new(),
+        };
+        println!("IPv6 - IPv4 latency: {delta:+.2} ms{status}");
+    }
+}
+
+fn dual_stack_summary(report: &DualStackReport, status: &str) -> String {
+    if report.error.is_some() {
+        return format!("{status} (unresolved)");
+    }
+    let leg = |family: &str, leg: &Option<FamilyPing>| match leg {
+        Some(leg) => {
+            let loss = leg
+                .report
+                .packet_loss_pct
+                .map(|loss| format!("{loss:.2}%"))
+                .unwrap_or_else(|| "n/a".into());
+            let latency = leg
+                .report
+                .average_latency_ms
+                .map(|ms| format!("{ms:.2} ms"))
+                .unwrap_or_else(|| "n/a".into());
+            format!("{family} {loss}/{latency}")
+        }
+        None => format!("no {family}"),
+    };
+    let mut summary = format!(
+        "{status} ({}, {}",
+        leg("v4", &report.ipv4),
+        leg("v6", &report.ipv6)
+    );
+    if let Some(delta) = report.latency_delta_ms() {
+        summary.push_str(&format!(", delta {delta:+.2} ms"));
+    }
+    summary.push(')');
+    summary
+}
+
 fn bool_to_status(success: bool) -> &'static str {
     if success {
         "OK success"
@@ -221,10 +1876,13 @@ fn print_command_output(title: &str, body: &str) {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::probes::dns::DnsTransport;
 
-    fn sample_result(
+    /// A line that pinged (and, unless `traceroute_success` is `None`,
+    /// traced) 10.0.0.1, for tests across the crate to adjust.
+    pub(crate) fn sample_result(
         name: &str,
         success: bool,
         packet_loss: Option<f32>,
@@ -235,19 +1893,54 @@ mod tests {
         LineResult {
             name: name.into(),
             target: "10.0.0.1".into(),
-            loss_threshold,
-            ping: PingReport {
-                success,
-                packet_loss_pct: packet_loss,
-                average_latency_ms: avg_latency,
-                raw_output: String::new(),
-            },
-            traceroute: traceroute_success.map(|ok| TracerouteReport {
-                success: ok,
-                hop_count: Some(5),
-                raw_output: String::new(),
-            }),
-            traceroute_requested: traceroute_success.is_some(),
+            custom_status: None,
+            batch: None,
+            error: None,
+            auto_disable: None,
+            alert_delay: None,
+            flap_detection: None,
+            failed_targets: Vec::new(),
+            regressions: Vec::new(),
+            iterations: None,
+            dependency_down: None,
+            note: None,
+            runbook_url: None,
+            simulated: None,
+            weight: 1.0,
+            tags: Vec::new(),
+            snooze: None,
+            interrupted: false,
+            duration_ms: None,
+            checks: vec![
+                CheckOutcome::Ping {
+                    report: PingReport {
+                        success,
+                        packet_loss_pct: packet_loss,
+                        average_latency_ms: avg_latency,
+                        jitter_ms: None,
+                        duplicates: 0,
+                        reordered: 0,
+                        raw_output: String::new(),
+                    },
+                    loss_threshold,
+                    duplicate_alert: None,
+                    reorder_alert: None,
+                    confirmation: None,
+                },
+                CheckOutcome::Traceroute {
+                    expected_gateway: None,
+                    hop_tolerance: None,
+                    route_change: None,
+                    hop_loss: None,
+                    report: traceroute_success.map(|ok| TracerouteReport {
+                        success: ok,
+                        hop_count: Some(5),
+                        hops: Vec::new(),
+                        enrichment_error: None,
+                        raw_output: String::new(),
+                    }),
+                },
+            ],
         }
     }
 
@@ -259,7 +1952,7 @@ mod tests {
             sample_result("Lab", true, None, None, 1.0, None),
         ];
 
-        let summary = format_summary(&results);
+        let summary = format_summary(&results, false);
         assert!(summary.contains("Primary"));
         assert!(summary.contains("loss=0.50% (OK)"));
         assert!(summary.contains("ping=ALERT"));
@@ -271,10 +1964,14 @@ mod tests {
 
     #[test]
     fn formats_compact_summary() {
-        let results = vec![
+        let mut results = vec![
             sample_result("Primary", true, Some(0.5), Some(12.3), 1.0, Some(true)),
             sample_result("Backup", false, Some(5.0), None, 1.0, Some(false)),
         ];
+        for result in &mut results {
+            result.note = Some(format!("Contact: {} ISP support", result.name));
+            result.runbook_url = Some("https://wiki.example.com/line-down".into());
+        }
 
         let summary = format_compact_summary(&results);
         assert!(summary.contains("ICMPMolester report"));
@@ -282,5 +1979,697 @@ mod tests {
         assert!(summary.contains("✅"));
         assert!(summary.contains("hops 5"));
         assert!(summary.contains("path alert"));
+        // Next steps are only shown for lines that need attention.
+        assert!(summary.contains("  Contact: Backup ISP support\n"));
+        assert!(summary.contains("  runbook: https://wiki.example.com/line-down\n"));
+        assert!(!summary.contains("Contact: Primary"));
+    }
+
+    #[test]
+    fn classifies_line_status() {
+        let ok = sample_result("Primary", true, Some(0.5), Some(12.3), 1.0, Some(true));
+        let lossy = sample_result("Lossy", true, Some(5.0), Some(12.3), 1.0, None);
+        let broken_path = sample_result("Path", true, Some(0.0), Some(9.0), 1.0, Some(false));
+        let unknown = sample_result("Lab", true, None, None, 1.0, None);
+
+        assert_eq!(ok.status(), LineStatus::Ok);
+        assert_eq!(lossy.status(), LineStatus::Alert);
+        assert_eq!(broken_path.status(), LineStatus::Alert);
+        assert_eq!(unknown.status(), LineStatus::Unknown);
+    }
+
+    #[test]
+    fn tls_expiry_raises_alert_in_summary() {
+        let mut result = sample_result("VPN", true, Some(0.0), Some(4.0), 1.0, None);
+        result.checks.push(CheckOutcome::Tls {
+            report: TlsReport {
+                endpoint: "vpn.example.com:443".into(),
+                success: true,
+                handshake_ms: Some(42.0),
+                protocol: Some("TLSv1.3".into()),
+                days_until_expiry: Some(3),
+                error: None,
+            },
+            expiry_alert_days: 14,
+        });
+
+        assert_eq!(result.status(), LineStatus::Alert);
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        assert!(summary.contains("tls=ALERT (TLSv1.3, expires 3d)"));
+        assert!(format_compact_summary(&[result]).contains("tls alert"));
+    }
+
+    #[test]
+    fn ntp_offset_and_reachability_drive_status() {
+        let mut result = sample_result("Branch", true, Some(0.0), Some(4.0), 1.0, None);
+        let ntp = |offset_ms| CheckOutcome::Ntp {
+            report: NtpReport {
+                server: "pool.ntp.org".into(),
+                success: true,
+                offset_ms: Some(offset_ms),
+                delay_ms: Some(30.0),
+                stratum: Some(2),
+                error: None,
+            },
+            offset_alert_ms: 500.0,
+        };
+        result.checks.push(ntp(-12.5));
+        assert_eq!(result.status(), LineStatus::Ok);
+        assert!(
+            format_summary(std::slice::from_ref(&result), false)
+                .contains("ntp=OK (offset -12.5 ms)")
+        );
+
+        *result.checks.last_mut().unwrap() = ntp(900.0);
+        assert_eq!(result.status(), LineStatus::Alert);
+        assert!(format_compact_summary(&[result]).contains("ntp alert"));
+    }
+
+    #[test]
+    fn slow_encrypted_resolver_alerts() {
+        let mut result = sample_result("Branch", true, Some(0.0), Some(4.0), 1.0, None);
+        result.checks.push(CheckOutcome::Dns {
+            report: DnsReport {
+                transport: DnsTransport::Doh,
+                resolver: "https://dns.example/dns-query".into(),
+                success: true,
+                latency_ms: Some(480.0),
+                answers: Some(1),
+                error: None,
+            },
+            latency_alert_ms: 250.0,
+        });
+
+        assert_eq!(result.status(), LineStatus::Alert);
+        assert!(
+            format_summary(std::slice::from_ref(&result), false).contains("doh=ALERT (480.0 ms)")
+        );
+        assert!(format_compact_summary(&[result]).contains("doh alert"));
+    }
+
+    #[test]
+    fn renders_each_check_as_sub_row_under_worst_of_header() {
+        let mut result = sample_result("Web", true, Some(0.0), Some(4.0), 1.0, Some(true));
+        result.checks.push(CheckOutcome::Http {
+            report: HttpReport {
+                url: "https://192.0.2.10/health".into(),
+                success: false,
+                status_code: Some(503),
+                latency_ms: Some(80.0),
+                error: Some("unexpected HTTP status 503".into()),
+            },
+            latency_alert_ms: 1_000.0,
+        });
+
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        let rows: Vec<_> = summary.lines().collect();
+        assert_eq!(rows[1], "- Web (10.0.0.1): ALERT");
+        assert!(rows[2].starts_with("    ping=OK"));
+        assert_eq!(rows[3], "    traceroute=OK, hops=5");
+        assert_eq!(rows[4], "    http=ALERT (503, 80.0 ms)");
+
+        let compact = format_compact_summary(&[result]);
+        assert!(compact.contains("• Web ⚠️"));
+        assert!(compact.contains("  http alert"));
+    }
+
+    #[test]
+    fn custom_status_replaces_ok_in_summaries() {
+        let mut result = sample_result("LTE", true, Some(0.4), Some(95.0), 1.0, Some(true));
+        result.custom_status = Some(CustomStatus {
+            name: "DEGRADED".into(),
+            color: Some(StatusColor::Yellow),
+            emoji: Some("🟡".into()),
+            loss_above_pct: None,
+            latency_above_ms: Some(80.0),
+        });
+
+        assert_eq!(result.status(), LineStatus::Ok);
+        assert!(
+            format_summary(std::slice::from_ref(&result), false)
+                .contains("- LTE (10.0.0.1): DEGRADED")
+        );
+        assert!(format_compact_summary(&[result]).contains("• LTE 🟡"));
+    }
+
+    #[test]
+    fn route_change_beyond_tolerance_alerts() {
+        let mut result = sample_result("Primary", true, Some(0.0), Some(4.0), 1.0, Some(true));
+        let CheckOutcome::Traceroute {
+            hop_tolerance,
+            route_change,
+            ..
+        } = &mut result.checks[1]
+        else {
+            unreachable!();
+        };
+        let ip = |addr: &str| Some(addr.parse::<IpAddr>().unwrap());
+        *hop_tolerance = Some(1);
+        *route_change = Some(RouteChange {
+            differing: 1,
+            hops: vec![HopDiff::Changed {
+                hop: 1,
+                before: ip("192.168.1.1"),
+                after: ip("192.168.1.254"),
+            }],
+        });
+        assert_eq!(result.status(), LineStatus::Ok);
+
+        if let CheckOutcome::Traceroute { route_change, .. } = &mut result.checks[1] {
+            *route_change = Some(RouteChange {
+                differing: 3,
+                hops: vec![
+                    HopDiff::Same {
+                        hop: 1,
+                        address: ip("192.168.1.1"),
+                    },
+                    HopDiff::Changed {
+                        hop: 2,
+                        before: ip("198.51.100.1"),
+                        after: None,
+                    },
+                    HopDiff::Changed {
+                        hop: 3,
+                        before: ip("198.51.100.2"),
+                        after: ip("203.0.113.7"),
+                    },
+                    HopDiff::Removed {
+                        hop: 4,
+                        address: ip("203.0.113.10"),
+                    },
+                ],
+            });
+        }
+        assert_eq!(result.status(), LineStatus::Alert);
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        assert!(summary.contains("traceroute=ALERT, hops=5, route changed at 3 hops"));
+        let diff: Vec<&str> = summary
+            .lines()
+            .skip_while(|row| !row.contains("route changed"))
+            .skip(1)
+            .take_while(|row| row.starts_with("      "))
+            .map(str::trim_end)
+            .collect();
+        assert_eq!(
+            diff,
+            [
+                "         1  192.168.1.1",
+                "      -  2  198.51.100.1",
+                "      +  2  *",
+                "      -  3  198.51.100.2",
+                "      +  3  203.0.113.7",
+                "      -  4  203.0.113.10",
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_which_fallback_target_answered() {
+        let mut result = sample_result("Anycast", true, Some(0.0), Some(4.0), 1.0, None);
+        assert_eq!(result.fallback_note(), None);
+
+        result.target = "1.1.1.1".into();
+        result.failed_targets = vec!["8.8.8.8".into()];
+        assert_eq!(
+            result.fallback_note().as_deref(),
+            Some("fell back to 1.1.1.1 after 8.8.8.8 alerted")
+        );
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        assert!(summary.contains("    fell back to 1.1.1.1 after 8.8.8.8 alerted"));
+
+        result.target = "9.9.9.9".into();
+        result.failed_targets.push("1.1.1.1".into());
+        let CheckOutcome::Ping { report, .. } = &mut result.checks[0] else {
+            unreachable!();
+        };
+        report.success = false;
+        assert_eq!(
+            result.fallback_note().as_deref(),
+            Some("every target alerted (also tried 8.8.8.8, 1.1.1.1)")
+        );
+    }
+
+    #[test]
+    fn disabled_lines_report_errored() {
+        let mut result = sample_result("Primary", true, Some(0.0), Some(4.0), 1.0, None);
+        result.checks.clear();
+        result.error = Some("Failed to execute ping".into());
+        assert_eq!(result.status(), LineStatus::Unknown);
+        assert_eq!(
+            result.execution_note().as_deref(),
+            Some("execution error: Failed to execute ping")
+        );
+
+        result.auto_disable = Some(AutoDisable {
+            after_errors: 3,
+            fingerprint: 1,
+            active: true,
+        });
+        assert_eq!(result.status(), LineStatus::Errored);
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        assert!(summary.contains("Primary (10.0.0.1): ERRORED"));
+        assert!(summary.contains("disabled after 3 consecutive execution errors"));
+        assert!(format_compact_summary(&[result]).contains("⛔"));
+    }
+
+    #[test]
+    fn groups_batches_into_sections() {
+        let mut results = vec![
+            sample_result("Uplink", true, Some(0.0), Some(4.0), 1.0, None),
+            sample_result("Uplink", false, Some(100.0), None, 1.0, None),
+        ];
+        results[0].batch = Some("acme".into());
+        results[1].batch = Some("globex".into());
+
+        assert_eq!(results[0].key(), "acme/Uplink");
+        let summary = format_summary(&results, false);
+        let acme = summary.find("[acme]").unwrap();
+        let globex = summary.find("[globex]").unwrap();
+        assert!(acme < globex);
+        assert_eq!(summary.matches("- Uplink").count(), 2);
+        assert!(format_compact_summary(&results).contains("📁 globex"));
+    }
+
+    #[test]
+    fn maps_the_worst_line_to_the_run_outcome() {
+        let ok = || sample_result("Fiber", true, Some(0.0), Some(12.0), 1.0, None);
+        let mut results = vec![ok(), ok()];
+        assert_eq!(RunOutcome::of(&results), RunOutcome::Ok);
+        assert_eq!(RunOutcome::of(&[]), RunOutcome::Ok);
+
+        results[1] = sample_result("LTE", true, None, None, 1.0, None);
+        assert_eq!(RunOutcome::of(&results).exit_code(), 1);
+        results[0] = sample_result("Fiber", false, Some(100.0), None, 1.0, None);
+        assert_eq!(RunOutcome::of(&results).exit_code(), 2);
+        results[1].checks.clear();
+        results[1].error = Some("ping not found".into());
+        assert_eq!(RunOutcome::of(&results).exit_code(), 3);
+    }
+
+    #[test]
+    fn weighs_rollups_by_line_weight() {
+        let mut results = vec![
+            sample_result("Fiber", false, Some(100.0), None, 1.0, None),
+            sample_result("LTE", true, Some(0.0), Some(40.0), 1.0, None),
+            sample_result("Lab", false, Some(100.0), None, 1.0, None),
+        ];
+        results[0].weight = 3.0;
+        results[2].weight = 0.0;
+
+        let rollup = Rollup::of(&results);
+        assert_eq!(rollup.status, LineStatus::Alert);
+        assert_eq!(rollup.impacted_pct, 75.0);
+        assert_eq!(
+            Rollup::of(&results[1..]),
+            Rollup {
+                status: LineStatus::Ok,
+                impacted_pct: 0.0
+            }
+        );
+        assert!(
+            format_summary(&results, false)
+                .starts_with("ICMPMolester summary\nOverall: ALERT, 75% of line weight impacted\n")
+        );
+
+        results[1].batch = Some("branch".into());
+        results[2].batch = Some("branch".into());
+        let compact = format_compact_summary(&results[1..]);
+        assert!(compact.contains("📁 branch ✅ 0%"), "{compact}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn runs_lines_in_parallel_and_keeps_their_order() {
+        let line = |name: &str, seconds: &str| {
+            let mut line = LineSettings::with_defaults(name, "192.0.2.1");
+            line.checks = vec![CheckKind::Custom];
+            line.custom_command = Some(format!("sleep {seconds}"));
+            line
+        };
+        let config = |names: [&str; 2]| Config {
+            lines: vec![line(names[0], "0.6"), line(names[1], "0.3")],
+            statuses: Vec::new(),
+        };
+        let options = RunOptions {
+            skip_traceroute: true,
+            concurrency_limit: Some(4),
+            history: None,
+            batch: None,
+            disabled: BTreeMap::new(),
+            simulation: None,
+            tags: Vec::new(),
+            due: None,
+            interruptible: false,
+            load: LoadOptions::default(),
+            on_line_finished: None,
+        };
+        let started = std::time::Instant::now();
+        let results = run_loaded(
+            vec![
+                (PathBuf::from("a.toml"), config(["A1", "A2"])),
+                (PathBuf::from("b.toml"), config(["B1", "B2"])),
+            ],
+            options.clone(),
+        )
+        .await
+        .unwrap();
+        // Run one after another, the four lines would take 1.8s.
+        assert!(
+            started.elapsed().as_secs_f32() < 1.2,
+            "{:?}",
+            started.elapsed()
+        );
+        let names: Vec<_> = results.iter().map(LineResult::key).collect();
+        assert_eq!(names, ["a/A1", "a/A2", "b/B1", "b/B2"]);
+        assert!(
+            results
+                .iter()
+                .all(|result| result.status() == LineStatus::Ok)
+        );
+
+        let options = RunOptions {
+            concurrency_limit: Some(0),
+            ..options
+        };
+        assert!(run_loaded(Vec::new(), options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_lines_in_the_order_they_finish() {
+        let line = |name: &str, command: &str| {
+            let mut line = LineSettings::with_defaults(name, "192.0.2.1");
+            line.checks = vec![CheckKind::Custom];
+            line.custom_command = Some(command.into());
+            line
+        };
+        let config = Config {
+            lines: vec![line("Slow", "sleep 0.3"), line("Fast", "true")],
+            statuses: Vec::new(),
+        };
+        let finished = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = finished.clone();
+        let options = RunOptions {
+            skip_traceroute: true,
+            concurrency_limit: None,
+            history: None,
+            batch: None,
+            disabled: BTreeMap::new(),
+            simulation: None,
+            tags: Vec::new(),
+            due: None,
+            interruptible: false,
+            load: LoadOptions::default(),
+            on_line_finished: Some(Arc::new(move |result: &LineResult| {
+                seen.lock().unwrap().push(result.key());
+            })),
+        };
+        let results = run_loaded(vec![(PathBuf::from("a.toml"), config)], options)
+            .await
+            .unwrap();
+        assert_eq!(*finished.lock().unwrap(), ["Fast", "Slow"]);
+        let names: Vec<_> = results.iter().map(LineResult::key).collect();
+        assert_eq!(names, ["Slow", "Fast"]);
+    }
+
+    #[tokio::test]
+    async fn lines_behind_a_down_dependency_are_not_probed() {
+        let line = |name: &str, command: &str, depends_on: Option<&str>| {
+            let mut line = LineSettings::with_defaults(name, "192.0.2.1");
+            line.checks = vec![CheckKind::Custom];
+            line.custom_command = Some(command.into());
+            line.depends_on = depends_on.map(str::to_string);
+            line
+        };
+        // Listed before their dependency and limited to one line at a time,
+        // so waiting must not hold the only permit.
+        let config = |router: &str| Config {
+            lines: vec![
+                line("Office", "true", Some("Modem")),
+                line("Modem", "true", Some("Router")),
+                line("Router", router, None),
+                line("Other", "true", None),
+            ],
+            statuses: Vec::new(),
+        };
+        let options = RunOptions {
+            skip_traceroute: true,
+            concurrency_limit: Some(1),
+            history: None,
+            batch: None,
+            disabled: BTreeMap::new(),
+            simulation: None,
+            tags: Vec::new(),
+            due: None,
+            interruptible: false,
+            load: LoadOptions::default(),
+            on_line_finished: None,
+        };
+        let results = run_loaded(
+            vec![(PathBuf::from("lines.toml"), config("false"))],
+            options.clone(),
+        )
+        .await
+        .unwrap();
+        let statuses: Vec<_> = results.iter().map(LineResult::status).collect();
+        assert_eq!(
+            statuses,
+            [
+                LineStatus::DependencyDown,
+                LineStatus::DependencyDown,
+                LineStatus::Alert,
+                LineStatus::Ok
+            ]
+        );
+        assert!(results[0].checks.is_empty());
+        assert_eq!(
+            results[0].dependency_note().as_deref(),
+            Some("not probed: Modem is down")
+        );
+        assert_eq!(RunOutcome::of(&results), RunOutcome::Alert);
+        assert_eq!(Rollup::of(&results).impacted_pct, 75.0);
+
+        let results = run_loaded(vec![(PathBuf::from("lines.toml"), config("true"))], options)
+            .await
+            .unwrap();
+        assert!(
+            results
+                .iter()
+                .all(|result| result.status() == LineStatus::Ok)
+        );
+    }
+
+    #[tokio::test]
+    async fn snoozed_lines_are_skipped_and_left_out_of_rollups() {
+        let mut maintenance = LineSettings::with_defaults("Backup", "192.0.2.2");
+        maintenance.snooze_until = Some(Utc::now() + chrono::TimeDelta::hours(1));
+        let mut disabled = LineSettings::with_defaults("Old", "192.0.2.3");
+        disabled.enabled = false;
+        let config = Config {
+            lines: vec![maintenance, disabled],
+            statuses: Vec::new(),
+        };
+        let options = RunOptions {
+            skip_traceroute: false,
+            concurrency_limit: Some(1),
+            history: None,
+            batch: None,
+            disabled: BTreeMap::new(),
+            simulation: None,
+            tags: Vec::new(),
+            due: None,
+            interruptible: false,
+            load: LoadOptions::default(),
+            on_line_finished: None,
+        };
+        // Nothing is probed, so this passes without ping installed.
+        let mut results = run_loaded(vec![(PathBuf::from("lines.toml"), config)], options)
+            .await
+            .unwrap();
+        assert!(results.iter().all(|result| result.checks.is_empty()));
+        assert_eq!(results[1].snooze, Some(Snooze::Disabled));
+        assert_eq!(results[0].status(), LineStatus::Snoozed);
+
+        let mut line = LineSettings::with_defaults("Fiber", "192.0.2.1");
+        line.snooze_until = Some(Utc::now() - chrono::TimeDelta::hours(1));
+        assert_eq!(snooze(&line, Utc::now()), None);
+
+        results.push(sample_result("Fiber", false, Some(100.0), None, 1.0, None));
+        assert_eq!(
+            Rollup::of(&results),
+            Rollup {
+                status: LineStatus::Alert,
+                impacted_pct: 100.0
+            }
+        );
+        let summary = format_summary(&results, false);
+        assert!(summary.contains("- Backup (192.0.2.2): SNOOZED\n    snoozed until "));
+        assert!(summary.contains("- Old (192.0.2.3): SNOOZED\n    disabled in the config\n"));
+        assert!(format_compact_summary(&results).contains("• Old 💤"));
+    }
+
+    #[test]
+    fn only_alerts_counts_healthy_lines_in_a_footer() {
+        let mut results = vec![
+            sample_result("Milan", true, Some(0.0), Some(4.0), 1.0, None),
+            sample_result("Turin", false, Some(100.0), None, 1.0, None),
+            sample_result("Lab", true, Some(0.0), Some(4.0), 1.0, None),
+        ];
+        results[2].snooze = Some(Snooze::Disabled);
+
+        let summary = format_summary(&results, true);
+        assert!(summary.contains("- Turin (10.0.0.1): ALERT\n"), "{summary}");
+        assert!(!summary.contains("Milan"), "{summary}");
+        assert!(summary.ends_with("\n1 line OK, 1 snoozed\n"), "{summary}");
+        assert!(summary.starts_with("ICMPMolester summary\nOverall: ALERT"));
+        assert!(format_summary(&results[..2], false).contains("- Milan"));
+    }
+
+    #[test]
+    fn groups_lines_by_first_tag() {
+        let mut results = vec![
+            sample_result("Milan", true, Some(0.0), Some(4.0), 1.0, None),
+            sample_result("Turin", false, Some(100.0), None, 1.0, None),
+            sample_result("Lab", true, Some(0.0), Some(4.0), 1.0, None),
+        ];
+        results[0].tags = vec!["north".into(), "fiber".into()];
+        results[1].tags = vec!["north".into()];
+
+        let summary = format_summary(&results, false);
+        let north = summary
+            .find("\n#north: ALERT, 50% of line weight impacted\n")
+            .unwrap();
+        let untagged = summary
+            .find("\n(untagged): OK, 0% of line weight impacted\n")
+            .unwrap();
+        assert!(north < summary.find("- Turin").unwrap());
+        assert!(untagged < summary.find("- Lab").unwrap());
+        assert_eq!(summary.matches("#north").count(), 1);
+
+        let compact = format_compact_summary(&results[2..]);
+        assert!(!compact.contains("🏷️"), "{compact}");
+    }
+
+    #[test]
+    fn wrong_first_hop_gateway_alerts() {
+        let mut result = sample_result("Primary", true, Some(0.0), Some(4.0), 1.0, Some(true));
+        let CheckOutcome::Traceroute {
+            report: Some(report),
+            expected_gateway,
+            ..
+        } = &mut result.checks[1]
+        else {
+            unreachable!();
+        };
+        report.hops.push(TracerouteHop {
+            number: 1,
+            address: Some("192.168.1.254".parse().unwrap()),
+            hostname: None,
+            rtt_ms: Some(0.4),
+            loss_pct: None,
+            asn: None,
+            as_name: None,
+            country: None,
+        });
+        *expected_gateway = Some("192.168.1.254".parse().unwrap());
+        assert_eq!(result.status(), LineStatus::Ok);
+
+        if let CheckOutcome::Traceroute {
+            expected_gateway, ..
+        } = &mut result.checks[1]
+        {
+            *expected_gateway = Some("192.168.1.1".parse().unwrap());
+        }
+        assert_eq!(result.status(), LineStatus::Alert);
+        assert!(
+            format_summary(std::slice::from_ref(&result), false)
+                .contains("traceroute=ALERT, hops=5, gateway 192.168.1.254 != 192.168.1.1")
+        );
+    }
+
+    #[test]
+    fn summary_lists_named_and_annotated_hops() {
+        let mut result = sample_result("Primary", true, Some(0.0), Some(4.0), 1.0, Some(true));
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        assert!(!summary.contains("    203.0.113.10"), "{summary}");
+
+        let CheckOutcome::Traceroute {
+            report: Some(report),
+            ..
+        } = &mut result.checks[1]
+        else {
+            unreachable!();
+        };
+        let hop = |number, address: Option<&str>, hostname: Option<&str>| TracerouteHop {
+            number,
+            address: address.map(|address| address.parse().unwrap()),
+            hostname: hostname.map(Into::into),
+            rtt_ms: address.map(|_| 8.0),
+            loss_pct: None,
+            asn: None,
+            as_name: None,
+            country: None,
+        };
+        report.hops = vec![
+            hop(1, Some("192.168.1.254"), None),
+            hop(2, None, None),
+            hop(3, Some("203.0.113.10"), Some("ae-1.bbr01.milan")),
+        ];
+
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        assert!(summary.contains("    traceroute=OK, hops=5\n"), "{summary}");
+        assert!(
+            summary.contains("\n       1  192.168.1.254  8.00 ms\n"),
+            "{summary}"
+        );
+        assert!(summary.contains("\n       2  *\n"), "{summary}");
+        assert!(
+            summary.contains("\n       3  ae-1.bbr01.milan (203.0.113.10)  8.00 ms\n"),
+            "{summary}"
+        );
+
+        // Origin annotations alone are enough to list the path.
+        if let CheckOutcome::Traceroute {
+            report: Some(report),
+            ..
+        } = &mut result.checks[1]
+        {
+            report.hops[2].hostname = None;
+            report.hops[2].asn = Some(64500);
+            report.hops[2].country = Some("IT".into());
+        }
+        let summary = format_summary(std::slice::from_ref(&result), false);
+        assert!(
+            summary.contains("\n       3  203.0.113.10  8.00 ms  [AS64500 / IT]\n"),
+            "{summary}"
+        );
+    }
+
+    #[test]
+    fn line_without_checks_is_unknown() {
+        let result = LineResult {
+            name: "Empty".into(),
+            target: "10.0.0.1".into(),
+            checks: Vec::new(),
+            custom_status: None,
+            batch: None,
+            error: None,
+            auto_disable: None,
+            alert_delay: None,
+            flap_detection: None,
+            failed_targets: Vec::new(),
+            regressions: Vec::new(),
+            iterations: None,
+            dependency_down: None,
+            note: None,
+            runbook_url: None,
+            simulated: None,
+            weight: 1.0,
+            tags: Vec::new(),
+            snooze: None,
+            interrupted: false,
+            duration_ms: None,
+        };
+        assert_eq!(result.status(), LineStatus::Unknown);
+        assert!(result.ping().is_none());
     }
 }
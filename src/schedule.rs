@@ -0,0 +1,507 @@
+//! Per-line cron schedules for the `serve` subcommand.
+//!
+//! A line with `schedule = "*/5 * * * *"` is checked whenever the expression
+//! matches the local time, so critical lines can be watched every minute and
+//! backup lines hourly from one long-running process. Config edits are
+//! swapped in between runs without restarting it.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{Datelike, Local, NaiveDateTime, TimeDelta, TimeZone, Timelike};
+use serde::Deserialize;
+
+use crate::config::{Config, ConfigFile, LoadOptions, load_config_files, read_configs};
+use crate::runner::batch_name;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead [`Schedule::next_after`] looks before giving up, which only
+/// happens for dates that never occur such as `0 0 30 2 *`.
+const LOOKAHEAD_DAYS: i64 = 4 * 366;
+
+/// A standard five-field cron expression: minute, hour, day of month, month
+/// and day of week.
+///
+/// Fields take `*`, numbers, `a-b` ranges, `/step` and comma-separated lists;
+/// months and weekdays also take three-letter names, and Sunday is 0 or 7.
+/// As in cron, a day matches when either the day of month or the day of week
+/// does, unless one of them is `*`. `@hourly`, `@daily`, `@weekly`,
+/// `@monthly` and `@yearly` are accepted as shorthands.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Schedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// Whether the schedule fires in the minute containing `at`.
+    pub fn matches(&self, at: NaiveDateTime) -> bool {
+        has(self.minutes, at.minute())
+            && has(self.hours, at.hour())
+            && has(self.months, at.month())
+            && self.matches_day(at)
+    }
+
+    fn matches_day(&self, at: NaiveDateTime) -> bool {
+        let day = has(self.days, at.day());
+        let weekday = has(self.weekdays, at.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first minute strictly after `after` at which the schedule fires.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut at = after.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let limit = at + TimeDelta::days(LOOKAHEAD_DAYS);
+        while at < limit {
+            if !has(self.months, at.month()) || !self.matches_day(at) {
+                at = at.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !has(self.hours, at.hour()) {
+                at = at.with_minute(0)? + TimeDelta::hours(1);
+            } else if !has(self.minutes, at.minute()) {
+                at += TimeDelta::minutes(1);
+            } else {
+                return Some(at);
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, String> {
+        let expression = raw.trim();
+        let expanded = match expression {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "schedule '{expression}' needs five fields (minute hour day month weekday)"
+            ));
+        };
+        let invalid = |err: String| format!("invalid schedule '{expression}': {err}");
+        let mut weekdays = parse_field(weekday, 0, 7, &WEEKDAY_NAMES).map_err(invalid)?;
+        // Sunday may be written as 7.
+        if has(weekdays, 7) {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            expression: expression.to_string(),
+            minutes: parse_field(minute, 0, 59, &[]).map_err(invalid)?,
+            hours: parse_field(hour, 0, 23, &[]).map_err(invalid)?,
+            days: parse_field(day, 1, 31, &[]).map_err(invalid)?,
+            months: parse_field(month, 1, 12, &MONTH_NAMES).map_err(invalid)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+}
+
+impl TryFrom<String> for Schedule {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, String> {
+        raw.parse()
+    }
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parse one cron field into a bit set of the values in `min..=max` it selects.
+/// `names` spell out the values from `min` upwards.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |raw: &str| -> Result<u32, String> {
+        let lower = raw.to_ascii_lowercase();
+        let parsed = match names.iter().position(|name| *name == lower) {
+            Some(index) => min + index as u32,
+            None => raw
+                .parse()
+                .map_err(|_| format!("'{raw}' is not a number or name"))?,
+        };
+        if !(min..=max).contains(&parsed) {
+            return Err(format!("{parsed} is outside {min}-{max}"));
+        }
+        Ok(parsed)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("'{step}' is not a valid step"))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` runs from 5 to the end of the range, as in cron.
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let single = value(range)?;
+                (single, single)
+            }
+        };
+        if start > end {
+            return Err(format!("range {start}-{end} is backwards"));
+        }
+        for selected in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << selected;
+        }
+    }
+    Ok(set)
+}
+
+/// Lines due at one scheduled minute, with the config snapshot to run them from.
+pub struct Tick {
+    pub due: NaiveDateTime,
+    /// The files the lines were loaded from, for the settings outside them.
+    pub files: Arc<[ConfigFile]>,
+    pub configs: Vec<(PathBuf, Config)>,
+}
+
+/// Configs as last loaded: the files as read and the lines in each.
+type Snapshot = (Arc<[ConfigFile]>, Vec<(PathBuf, Config)>);
+
+/// Check scheduled lines whenever one is due, until the process stops.
+///
+/// The configs are reloaded after every run and right before the next one,
+/// or straight away on SIGHUP, and the lines that were added, removed or
+/// changed are logged. A config that fails to load is reported and the last
+/// good one stays in use. `cycle` runs the lines due at the given local
+/// minute; a failed cycle is logged and the schedule carries on.
+pub async fn run_schedules<F, Fut>(config: &[PathBuf], options: &LoadOptions, mut cycle: F)
+where
+    F: FnMut(Tick) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut hangups = Hangups::new();
+    let mut current: Option<Snapshot> = None;
+    let mut announced: Option<Vec<(String, Schedule)>> = None;
+    loop {
+        reload(config, options, &mut current);
+        let Some((_, configs)) = &current else {
+            tokio::select! {
+                () = tokio::time::sleep(Duration::from_secs(60)) => {}
+                () = hangups.recv() => {}
+            }
+            continue;
+        };
+        let schedules = schedules_in(configs);
+        if announced.as_ref() != Some(&schedules) {
+            if schedules.is_empty() {
+                if announced.is_some() {
+                    println!("No lines are scheduled any more");
+                }
+            } else {
+                let listed: Vec<String> = schedules
+                    .iter()
+                    .map(|(line, schedule)| format!("{line} ({schedule})"))
+                    .collect();
+                println!("Scheduled lines: {}", listed.join(", "));
+            }
+            announced = Some(schedules.clone());
+        }
+
+        let now = Local::now().naive_local();
+        let Some(due) = schedules
+            .iter()
+            .filter_map(|(_, schedule)| schedule.next_after(now))
+            .min()
+        else {
+            // Nothing to run; check back for newly scheduled lines.
+            tokio::select! {
+                () = tokio::time::sleep(Duration::from_secs(60)) => {}
+                () = hangups.recv() => println!("Reloading configs on SIGHUP"),
+            }
+            continue;
+        };
+        // Across a DST change the local minute may be ambiguous or skipped.
+        let wait = Local
+            .from_local_datetime(&due)
+            .earliest()
+            .map_or(Duration::from_secs(60), |instant| {
+                (instant - Local::now()).to_std().unwrap_or_default()
+            });
+        tokio::select! {
+            () = tokio::time::sleep(wait) => {}
+            () = hangups.recv() => {
+                println!("Reloading configs on SIGHUP");
+                continue;
+            }
+        }
+        reload(config, options, &mut current);
+        let Some((files, configs)) = current.clone() else {
+            continue;
+        };
+        if let Err(err) = cycle(Tick {
+            due,
+            files,
+            configs,
+        })
+        .await
+        {
+            println!("Scheduled run at {} failed: {err:#}", due.format("%H:%M"));
+        }
+    }
+}
+
+/// Swap in freshly loaded configs, logging how their lines changed. On
+/// failure the previous configs stay in place.
+fn reload(paths: &[PathBuf], options: &LoadOptions, current: &mut Option<Snapshot>) {
+    let loaded =
+        read_configs(paths).and_then(|files| Ok((load_config_files(&files, options)?, files)));
+    match loaded {
+        Ok((configs, files)) => {
+            if let Some(changes) = current
+                .as_ref()
+                .and_then(|(_, previous)| describe_changes(previous, &configs))
+            {
+                println!("Config reloaded: {changes}");
+            }
+            *current = Some((files.into(), configs));
+        }
+        Err(err) if current.is_some() => {
+            println!("Config reload failed, keeping the previous config: {err:#}");
+        }
+        Err(err) => println!("Failed to load line schedules: {err:#}"),
+    }
+}
+
+/// Settings fingerprint of every line, keyed like [`crate::runner::LineResult::key`].
+fn line_fingerprints(configs: &[(PathBuf, Config)]) -> BTreeMap<String, u64> {
+    let batched = configs.len() > 1;
+    configs
+        .iter()
+        .flat_map(|(path, config)| {
+            config.lines.iter().map(move |line| {
+                let key = if batched {
+                    format!("{}/{}", batch_name(path), line.name)
+                } else {
+                    line.name.clone()
+                };
+                (key, line.fingerprint())
+            })
+        })
+        .collect()
+}
+
+/// Lines added, removed or changed between two config snapshots, or `None`
+/// when every line is unchanged.
+fn describe_changes(before: &[(PathBuf, Config)], after: &[(PathBuf, Config)]) -> Option<String> {
+    let before = line_fingerprints(before);
+    let after = line_fingerprints(after);
+    let added: Vec<&str> = after
+        .keys()
+        .filter(|line| !before.contains_key(*line))
+        .map(String::as_str)
+        .collect();
+    let removed: Vec<&str> = before
+        .keys()
+        .filter(|line| !after.contains_key(*line))
+        .map(String::as_str)
+        .collect();
+    let changed: Vec<&str> = after
+        .iter()
+        .filter(|(line, fingerprint)| before.get(*line).is_some_and(|old| old != *fingerprint))
+        .map(|(line, _)| line.as_str())
+        .collect();
+    let parts: Vec<String> = [("added", added), ("removed", removed), ("changed", changed)]
+        .into_iter()
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|(what, lines)| format!("{what} {}", lines.join(", ")))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("; "))
+}
+
+/// Every line with a schedule, by name.
+fn schedules_in(configs: &[(PathBuf, Config)]) -> Vec<(String, Schedule)> {
+    configs
+        .iter()
+        .flat_map(|(_, config)| &config.lines)
+        .filter_map(|line| Some((line.name.clone(), line.schedule.clone()?)))
+        .collect()
+}
+
+/// SIGHUP notifications, so `kill -HUP` applies config edits straight away.
+#[cfg(unix)]
+struct Hangups(Option<tokio::signal::unix::Signal>);
+
+#[cfg(unix)]
+impl Hangups {
+    fn new() -> Self {
+        use tokio::signal::unix::{SignalKind, signal};
+        Self(signal(SignalKind::hangup()).ok())
+    }
+
+    async fn recv(&mut self) {
+        if let Some(signal) = self.0.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+}
+
+#[cfg(not(unix))]
+struct Hangups;
+
+#[cfg(not(unix))]
+impl Hangups {
+    fn new() -> Self {
+        Self
+    }
+
+    async fn recv(&mut self) {
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LineSettings;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-07-01 is a Monday.
+        NaiveDate::from_ymd_opt(2024, 7, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_and_matches_cron_fields() {
+        let every_five: Schedule = "*/5 * * * *".parse().unwrap();
+        assert!(every_five.matches(at(1, 10, 35)));
+        assert!(!every_five.matches(at(1, 10, 36)));
+
+        let office: Schedule = "0,30 9-17 * * mon-fri".parse().unwrap();
+        assert!(office.matches(at(5, 17, 30)));
+        assert!(!office.matches(at(6, 9, 0)));
+        assert!(!office.matches(at(1, 18, 0)));
+
+        let sunday: Schedule = "0 0 * * 7".parse().unwrap();
+        assert!(sunday.matches(at(7, 0, 0)));
+        assert_eq!(
+            "@hourly".parse::<Schedule>().unwrap().to_string(),
+            "@hourly"
+        );
+
+        // Day of month and day of week combine with OR when both are set.
+        let either: Schedule = "0 12 15 * mon".parse().unwrap();
+        assert!(either.matches(at(8, 12, 0)));
+        assert!(either.matches(at(15, 12, 0)));
+        assert!(!either.matches(at(16, 12, 0)));
+
+        for bad in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "0 0 * foo *",
+        ] {
+            assert!(bad.parse::<Schedule>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn finds_the_next_run() {
+        let every_five: Schedule = "*/5 * * * *".parse().unwrap();
+        let now = at(1, 10, 35).with_second(12).unwrap();
+        assert_eq!(every_five.next_after(now), Some(at(1, 10, 40)));
+
+        let hourly: Schedule = "@hourly".parse().unwrap();
+        assert_eq!(hourly.next_after(at(1, 23, 0)), Some(at(2, 0, 0)));
+
+        let monday: Schedule = "30 6 * * 1".parse().unwrap();
+        assert_eq!(monday.next_after(at(1, 6, 30)), Some(at(8, 6, 30)));
+
+        let never: Schedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(never.next_after(at(1, 0, 0)), None);
+    }
+
+    #[test]
+    fn describes_reloaded_lines() {
+        let snapshot = |lines: Vec<LineSettings>| {
+            vec![(
+                PathBuf::from("lines.toml"),
+                Config {
+                    lines,
+                    statuses: Vec::new(),
+                },
+            )]
+        };
+        let fiber = LineSettings::with_defaults("Fiber", "192.0.2.1");
+        let lte = LineSettings::with_defaults("LTE", "192.0.2.2");
+        let before = snapshot(vec![fiber.clone(), lte.clone()]);
+        assert_eq!(describe_changes(&before, &before), None);
+
+        let mut slower = fiber.clone();
+        slower.ping_count = 20;
+        let after = snapshot(vec![
+            slower,
+            LineSettings::with_defaults("DSL", "192.0.2.3"),
+        ]);
+        assert_eq!(
+            describe_changes(&before, &after).as_deref(),
+            Some("added DSL; removed LTE; changed Fiber")
+        );
+
+        let mut two = after.clone();
+        two.push((
+            PathBuf::from("acme.toml"),
+            Config {
+                lines: vec![lte],
+                statuses: Vec::new(),
+            },
+        ));
+        assert_eq!(
+            describe_changes(&after, &two).as_deref(),
+            Some("added acme/LTE, lines/DSL, lines/Fiber; removed DSL, Fiber")
+        );
+    }
+}
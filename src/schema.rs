@@ -0,0 +1,379 @@
+//! JSON Schema of the `--format json` and `--format ndjson` output, printed
+//! by `--print-schema` for integrators to validate and generate code from.
+
+use serde_json::{Value, json};
+
+use crate::report::SCHEMA_VERSION;
+use crate::runner::LineStatus;
+
+const DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Schema of the run report printed by `--format json` (and served by
+/// `serve`).
+pub fn report_schema() -> Value {
+    let mut properties = run_properties();
+    properties["rollup"] = json!({ "$ref": "#/$defs/rollup" });
+    properties["lines"] = json!({ "type": "array", "items": { "$ref": "#/$defs/line" } });
+    json!({
+        "$schema": DRAFT,
+        "title": "ICMPMolester run report",
+        "type": "object",
+        "properties": properties,
+        "required": [
+            "schema_version", "run_id", "hostname", "started_at", "finished_at",
+            "rollup", "lines"
+        ],
+        "additionalProperties": false,
+        "$defs": definitions(),
+    })
+}
+
+/// Schema of one record printed by `--format ndjson`: a line of the report
+/// with when and where it finished.
+pub fn ndjson_line_schema() -> Value {
+    let mut line = definitions()["line"].clone();
+    line["properties"]["schema_version"] = schema_version();
+    line["properties"]["finished_at"] = date_time();
+    line["properties"]["hostname"] = json!({ "type": "string" });
+    for field in ["schema_version", "finished_at", "hostname"] {
+        line["required"]
+            .as_array_mut()
+            .expect("required is a list")
+            .push(json!(field));
+    }
+    let mut schema = json!({
+        "$schema": DRAFT,
+        "title": "ICMPMolester line record",
+        "$defs": definitions(),
+    });
+    for (key, value) in line.as_object().expect("line is an object") {
+        schema[key] = value.clone();
+    }
+    schema
+}
+
+fn schema_version() -> Value {
+    json!({ "const": SCHEMA_VERSION })
+}
+
+fn date_time() -> Value {
+    json!({ "type": "string", "format": "date-time" })
+}
+
+fn run_properties() -> Value {
+    json!({
+        "schema_version": schema_version(),
+        "run_id": { "type": "string", "format": "uuid" },
+        "hostname": { "type": "string", "description": "Host the run was made from" },
+        "started_at": date_time(),
+        "finished_at": date_time(),
+    })
+}
+
+fn definitions() -> Value {
+    let statuses: Vec<&str> = LineStatus::ALL
+        .iter()
+        .map(|status| status.as_str())
+        .collect();
+    let number_or_null = json!({ "type": ["number", "null"] });
+    let spread = json!({
+        "type": "object",
+        "properties": {
+            "samples": { "type": "integer", "minimum": 0 },
+            "mean": { "type": "number" },
+            "stddev": { "type": "number" },
+        },
+        "required": ["samples", "mean", "stddev"],
+        "additionalProperties": false,
+    });
+    json!({
+        "status": { "enum": statuses },
+        "rollup": {
+            "type": "object",
+            "properties": {
+                "status": { "$ref": "#/$defs/status" },
+                "impacted_pct": {
+                    "type": "number",
+                    "description": "Share of the total weight held by ALERT, ERRORED or DEPENDENCY_DOWN lines",
+                },
+            },
+            "required": ["status", "impacted_pct"],
+            "additionalProperties": false,
+        },
+        "line": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "target": { "type": "string" },
+                "batch": { "type": "string" },
+                "status": { "$ref": "#/$defs/status" },
+                "weight": { "type": "number" },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "duration_ms": { "type": "number" },
+                "display_status": {
+                    "type": "string",
+                    "description": "Custom status name when one matched, otherwise the built-in status",
+                },
+                "execution": {
+                    "type": "string",
+                    "description": "Why the checks could not run, or that the line is auto-disabled",
+                },
+                "failed_targets": { "type": "array", "items": { "type": "string" } },
+                "simulated": { "type": "string" },
+                "regressions": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "metric": { "enum": ["latency", "loss"] },
+                            "value": { "type": "number" },
+                            "baseline": spread,
+                        },
+                        "required": ["metric", "value", "baseline"],
+                        "additionalProperties": false,
+                    },
+                },
+                "iterations": {
+                    "type": "object",
+                    "properties": {
+                        "samples": { "type": "integer", "minimum": 0 },
+                        "loss_pct": number_or_null,
+                        "latency_p50_ms": number_or_null,
+                        "latency_p95_ms": number_or_null,
+                        "latency_max_ms": number_or_null,
+                    },
+                    "required": [
+                        "samples", "loss_pct", "latency_p50_ms", "latency_p95_ms",
+                        "latency_max_ms"
+                    ],
+                    "additionalProperties": false,
+                },
+                "checks": { "type": "array", "items": { "$ref": "#/$defs/check" } },
+            },
+            "required": ["name", "target", "status", "weight", "display_status", "checks"],
+            "additionalProperties": false,
+        },
+        "check": {
+            "type": "object",
+            "properties": {
+                "check": { "type": "string" },
+                "status": {
+                    "description": "Absent verdict (null) when the check was skipped",
+                    "anyOf": [{ "$ref": "#/$defs/status" }, { "type": "null" }],
+                },
+                "reason": { "type": "string" },
+                "confirmation": {
+                    "type": "object",
+                    "properties": {
+                        "probes": { "type": "integer", "minimum": 0 },
+                        "breached": { "type": "integer", "minimum": 0 },
+                        "required": { "type": "integer", "minimum": 0 },
+                    },
+                    "required": ["probes", "breached", "required"],
+                    "additionalProperties": false,
+                },
+                "hops": { "type": "array", "items": { "$ref": "#/$defs/hop" } },
+            },
+            "required": ["check", "status"],
+            "additionalProperties": false,
+        },
+        "hop": {
+            "type": "object",
+            "properties": {
+                "number": { "type": "integer", "minimum": 0 },
+                "address": {
+                    "description": "Absent (null) for hops that did not answer",
+                    "type": ["string", "null"],
+                },
+                "hostname": { "type": "string" },
+                "rtt_ms": { "type": "number" },
+                "loss_pct": { "type": "number" },
+                "asn": { "type": "integer", "minimum": 0 },
+                "as_name": { "type": "string" },
+                "country": { "type": "string", "description": "ISO 3166-1 alpha-2 code" },
+            },
+            "required": ["number", "address"],
+            "additionalProperties": false,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::{CheckOutcome, Confirmation};
+    use crate::diagnostics::TracerouteHop;
+    use crate::history::{Regression, Spread};
+    use crate::iterations::IterationStats;
+    use crate::report::{LineReport, RunMetadata, RunReport, StreamedLine};
+    use crate::runner::tests::sample_result;
+    use chrono::{TimeZone, Utc};
+
+    /// Check `value` against the parts of JSON Schema used above, enough to
+    /// catch output fields the schema does not describe.
+    fn validate(schema: &Value, root: &Value, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return validate(&root["$defs"][name], root, value, path);
+        }
+        if let Some(options) = schema["anyOf"].as_array() {
+            return match options
+                .iter()
+                .any(|option| validate(option, root, value, path).is_ok())
+            {
+                true => Ok(()),
+                false => Err(format!("{path}: {value} matches no option")),
+            };
+        }
+        if schema
+            .get("const")
+            .is_some_and(|constant| constant != value)
+        {
+            return Err(format!("{path}: {value} is not {}", schema["const"]));
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                return Err(format!("{path}: {value} is not one of {allowed:?}"));
+            }
+        }
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(single) => vec![single.as_str()],
+            Value::Array(many) => many.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let actual = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_u64() || n.is_i64() => "integer",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        let integer_as_number = actual == "integer" && types.contains(&"number");
+        if !types.is_empty() && !types.contains(&actual) && !integer_as_number {
+            return Err(format!("{path}: {actual} is not {types:?}"));
+        }
+        if let Value::Object(object) = value {
+            for required in schema["required"].as_array().into_iter().flatten() {
+                let field = required.as_str().unwrap_or_default();
+                if !object.contains_key(field) {
+                    return Err(format!("{path}: missing {field}"));
+                }
+            }
+            for (key, field) in object {
+                let field_schema = &schema["properties"][key];
+                if field_schema.is_null() {
+                    return Err(format!("{path}: {key} is not in the schema"));
+                }
+                validate(field_schema, root, field, &format!("{path}.{key}"))?;
+            }
+        }
+        if let Value::Array(items) = value {
+            for (index, item) in items.iter().enumerate() {
+                validate(&schema["items"], root, item, &format!("{path}[{index}]"))?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reports_match_their_schemas() {
+        let mut result = sample_result("Backup LTE", true, Some(40.0), Some(30.0), 1.0, Some(true));
+        result.target = "10.0.0.2".into();
+        result.batch = Some("site-a".into());
+        result.failed_targets = vec!["10.0.0.1".into()];
+        result.regressions = vec![Regression {
+            metric: "latency",
+            value: 80.0,
+            baseline: Spread {
+                samples: 20,
+                mean: 30.0,
+                stddev: 2.0,
+            },
+        }];
+        result.iterations = Some(IterationStats {
+            samples: 3,
+            loss_pct: Some(40.0),
+            latency_p50_ms: Some(31.0),
+            latency_p95_ms: None,
+            latency_max_ms: Some(33.0),
+        });
+        result.simulated = Some("loss=40".into());
+        result.tags = vec!["lte".into()];
+        result.duration_ms = Some(1200.0);
+        if let CheckOutcome::Ping { confirmation, .. } = &mut result.checks[0] {
+            *confirmation = Some(Confirmation {
+                probes: 3,
+                breached: 2,
+                required: 2,
+            });
+        }
+        if let CheckOutcome::Traceroute {
+            report: Some(report),
+            ..
+        } = &mut result.checks[1]
+        {
+            report.hop_count = Some(2);
+            report.hops = vec![
+                TracerouteHop {
+                    number: 1,
+                    address: None,
+                    hostname: None,
+                    rtt_ms: None,
+                    loss_pct: None,
+                    asn: None,
+                    as_name: None,
+                    country: None,
+                },
+                TracerouteHop {
+                    number: 2,
+                    address: Some("203.0.113.10".parse().unwrap()),
+                    hostname: Some("ae-1.bbr01.milan".into()),
+                    rtt_ms: Some(8.5),
+                    loss_pct: None,
+                    asn: Some(64500),
+                    as_name: Some("EXAMPLE-NET".into()),
+                    country: Some("IT".into()),
+                },
+            ];
+        }
+        // A skipped traceroute, whose report is null.
+        result.checks.insert(
+            1,
+            CheckOutcome::Traceroute {
+                report: None,
+                expected_gateway: None,
+                hop_tolerance: None,
+                route_change: None,
+                hop_loss: None,
+            },
+        );
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let run = RunMetadata::new(at, at);
+
+        let schema = report_schema();
+        let report =
+            serde_json::to_value(RunReport::new(std::slice::from_ref(&result), &run)).unwrap();
+        assert_eq!(validate(&schema, &schema, &report, "report"), Ok(()));
+        assert_eq!(report["lines"][0]["checks"][1]["status"], Value::Null);
+        assert_eq!(
+            report["lines"][0]["checks"][2]["hops"][1]["hostname"],
+            "ae-1.bbr01.milan"
+        );
+
+        let schema = ndjson_line_schema();
+        let line = serde_json::to_value(StreamedLine {
+            schema_version: SCHEMA_VERSION,
+            finished_at: at,
+            hostname: "probe-1".into(),
+            line: LineReport::new(&result),
+        })
+        .unwrap();
+        assert_eq!(validate(&schema, &schema, &line, "line"), Ok(()));
+
+        let mut extra = line.clone();
+        extra["unexpected"] = json!(1);
+        assert!(validate(&schema, &schema, &extra, "line").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Notification credentials kept off the command line.
+//!
+//! SMTP passwords and Telegram tokens can be given as a flag value, read
+//! from a file, taken from an environment variable, or, in builds with the
+//! `keyring` feature, looked up in the OS keyring. Sources are only read when
+//! a notification is about to be sent.
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+/// Keyring service under which ICMPMolester's secrets are stored.
+pub const KEYRING_SERVICE: &str = "icmpmolester";
+
+/// Where a credential comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Secret {
+    /// Given literally, e.g. `--email-password`.
+    Value(String),
+    /// First line of a file, such as a mounted Docker or Kubernetes secret.
+    File(PathBuf),
+    /// An environment variable.
+    Env(String),
+    /// An entry of [`KEYRING_SERVICE`] in the OS keyring.
+    Keyring(String),
+}
+
+impl Secret {
+    /// The first source given, if any. Callers ensure at most one is set.
+    pub fn first_of(
+        value: Option<&String>,
+        file: Option<&PathBuf>,
+        env: Option<&String>,
+        keyring: Option<&String>,
+    ) -> Option<Self> {
+        value
+            .cloned()
+            .map(Secret::Value)
+            .or_else(|| file.cloned().map(Secret::File))
+            .or_else(|| env.cloned().map(Secret::Env))
+            .or_else(|| keyring.cloned().map(Secret::Keyring))
+    }
+
+    /// Read the credential.
+    pub fn reveal(&self) -> Result<String> {
+        let secret = match self {
+            Secret::Value(value) => value.clone(),
+            Secret::File(path) => {
+                let contents = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                // Files written with `echo` end in a newline that is not part of the secret.
+                contents.lines().next().unwrap_or_default().to_string()
+            }
+            Secret::Env(name) => std::env::var(name)
+                .with_context(|| format!("Environment variable {name} is not set"))?,
+            Secret::Keyring(name) => keyring_get(name)?,
+        };
+        if secret.is_empty() {
+            anyhow::bail!("{self} is empty");
+        }
+        Ok(secret)
+    }
+}
+
+/// Names the source without revealing the secret.
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Secret::Value(_) => write!(f, "the command-line value"),
+            Secret::File(path) => write!(f, "file {}", path.display()),
+            Secret::Env(name) => write!(f, "environment variable {name}"),
+            Secret::Keyring(name) => write!(f, "keyring entry {KEYRING_SERVICE}/{name}"),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(name: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, name)
+        .with_context(|| format!("Cannot open keyring entry {KEYRING_SERVICE}/{name}"))
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_get(name: &str) -> Result<String> {
+    keyring_entry(name)?
+        .get_password()
+        .with_context(|| format!("Cannot read keyring entry {KEYRING_SERVICE}/{name}"))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_get(name: &str) -> Result<String> {
+    anyhow::bail!(
+        "Keyring entry {KEYRING_SERVICE}/{name} requested but this build lacks the `keyring` feature"
+    )
+}
+
+/// Store `secret` in the OS keyring for use as `--*-keyring <name>`.
+#[cfg(feature = "keyring")]
+pub fn keyring_set(name: &str, secret: &str) -> Result<()> {
+    keyring_entry(name)?
+        .set_password(secret)
+        .with_context(|| format!("Cannot write keyring entry {KEYRING_SERVICE}/{name}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_secrets_from_each_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smtp-password");
+        fs::write(&path, "hunter2\n").unwrap();
+        assert_eq!(Secret::File(path.clone()).reveal().unwrap(), "hunter2");
+        fs::write(&path, "\n").unwrap();
+        let err = Secret::File(path.clone()).reveal().unwrap_err();
+        assert_eq!(err.to_string(), format!("file {} is empty", path.display()));
+
+        // PATH is always set, so it stands in for a credential variable.
+        let path_var = std::env::var("PATH").unwrap();
+        assert_eq!(Secret::Env("PATH".into()).reveal().unwrap(), path_var);
+        assert!(
+            Secret::Env("ICMPMOLESTER_UNSET_SECRET".into())
+                .reveal()
+                .is_err()
+        );
+
+        let flag = "hunter2".to_string();
+        let secret = Secret::first_of(None, None, Some(&flag), None).unwrap();
+        assert_eq!(secret, Secret::Env("hunter2".into()));
+        assert_eq!(Secret::Value(flag).to_string(), "the command-line value");
+    }
+}
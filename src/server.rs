@@ -0,0 +1,350 @@
+//! Minimal HTTP API for the `serve` subcommand.
+//!
+//! `POST /api/run` starts an asynchronous run over the configured files and
+//! answers `202 Accepted` immediately. When the body carries a
+//! `callback_url`, the finished [`RunReport`] is POSTed there as JSON.
+//! `GET /api/resolver` returns the shared DNS cache's counters.
+//!
+//! [`spawn_metrics`] starts a separate listener that only answers
+//! `GET /metrics`, so Prometheus can scrape `serve` and `watch` without
+//! being able to trigger runs.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, anyhow};
+use chrono::Utc;
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+
+use crate::config::{LoadOptions, read_configs};
+use crate::metrics;
+use crate::report::{RunMetadata, RunReport};
+use crate::resolver;
+use crate::runner::{RunOptions, run_configs};
+
+/// Largest request (headers plus body) the server accepts.
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+const JSON: &str = "application/json";
+/// Prometheus text exposition format.
+const EXPOSITION: &str = "text/plain; version=0.0.4";
+
+/// Settings shared by every request.
+pub struct ServeOptions {
+    pub listen: SocketAddr,
+    pub config: Vec<PathBuf>,
+    pub skip_traceroute: bool,
+    pub concurrency_limit: Option<usize>,
+    pub load: LoadOptions,
+}
+
+struct ServerState {
+    options: ServeOptions,
+    next_run_id: AtomicU64,
+    /// Runs are serialised so overlapping triggers do not overload the lines.
+    run_slot: Semaphore,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RunRequest {
+    #[serde(default)]
+    callback_url: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// Accept connections until the process is stopped.
+pub async fn serve(options: ServeOptions) -> Result<()> {
+    let listener = TcpListener::bind(options.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", options.listen))?;
+    println!("Listening on http://{}", options.listen);
+
+    let state = Arc::new(ServerState {
+        options,
+        next_run_id: AtomicU64::new(1),
+        run_slot: Semaphore::new(1),
+    });
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream, &state).await {
+                println!("Request from {peer} failed: {err:#}");
+            }
+        });
+    }
+}
+
+/// Serve the latest run's metrics at `GET /metrics` in the background for
+/// as long as the process runs. Only binding `listen` can fail.
+pub async fn spawn_metrics(listen: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {listen}"))?;
+    println!("Serving metrics on http://{listen}/metrics");
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    println!("Metrics listener failed: {err}");
+                    return;
+                }
+            };
+            tokio::spawn(async move {
+                if let Err(err) = handle_metrics_connection(stream).await {
+                    println!("Metrics request from {peer} failed: {err:#}");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn handle_metrics_connection(mut stream: TcpStream) -> Result<()> {
+    let (status, content_type, body) = match read_request(&mut stream).await {
+        Ok(request) => match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => (200, EXPOSITION, metrics::exporter().render()),
+            (_, "/metrics") => (405, JSON, error_body("use GET")),
+            _ => (404, JSON, error_body("not found")),
+        },
+        Err(err) => (400, JSON, error_body(&format!("{err:#}"))),
+    };
+    write_response(&mut stream, status, content_type, &body).await
+}
+
+async fn handle_connection(mut stream: TcpStream, state: &Arc<ServerState>) -> Result<()> {
+    let (status, body) = match read_request(&mut stream).await {
+        Ok(request) => route(request, state),
+        Err(err) => (400, error_body(&format!("{err:#}"))),
+    };
+    write_response(&mut stream, status, JSON, &body).await
+}
+
+fn route(request: Request, state: &Arc<ServerState>) -> (u16, String) {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/api/run") => {
+            let run_request = if request.body.iter().all(u8::is_ascii_whitespace) {
+                RunRequest::default()
+            } else {
+                match serde_json::from_slice::<RunRequest>(&request.body) {
+                    Ok(parsed) => parsed,
+                    Err(err) => return (400, error_body(&format!("invalid JSON body: {err}"))),
+                }
+            };
+            if let Some(url) = &run_request.callback_url {
+                if !(url.starts_with("http://") || url.starts_with("https://")) {
+                    return (400, error_body("callback_url must be an http(s) URL"));
+                }
+            }
+
+            let run_id = state.next_run_id.fetch_add(1, Ordering::Relaxed);
+            let state = Arc::clone(state);
+            tokio::spawn(async move {
+                if let Err(err) = execute_run(run_id, run_request.callback_url, &state).await {
+                    println!("Run {run_id} failed: {err:#}");
+                }
+            });
+            (
+                202,
+                serde_json::json!({ "run_id": run_id, "status": "accepted" }).to_string(),
+            )
+        }
+        (_, "/api/run") => (405, error_body("use POST")),
+        ("GET", "/api/resolver") => {
+            let stats = resolver::shared().stats();
+            let mut body = serde_json::json!(stats);
+            body["average_latency_ms"] = serde_json::json!(stats.average_latency_ms());
+            (200, body.to_string())
+        }
+        (_, "/api/resolver") => (405, error_body("use GET")),
+        _ => (404, error_body("not found")),
+    }
+}
+
+async fn execute_run(run_id: u64, callback_url: Option<String>, state: &ServerState) -> Result<()> {
+    let _slot = state.run_slot.acquire().await?;
+    let started_at = Utc::now();
+    let failed = |_: &anyhow::Error| metrics::exporter().record_failed_run();
+    let files = read_configs(&state.options.config).inspect_err(failed)?;
+    let options = RunOptions {
+        skip_traceroute: state.options.skip_traceroute,
+        concurrency_limit: state.options.concurrency_limit,
+        history: None,
+        batch: None,
+        disabled: Default::default(),
+        simulation: None,
+        tags: Vec::new(),
+        due: None,
+        interruptible: false,
+        load: state.options.load.clone(),
+        on_line_finished: None,
+    };
+    let results = run_configs(&files, options).await.inspect_err(failed)?;
+    let run = RunMetadata::new(started_at, Utc::now());
+    metrics::exporter().record_run(&results, &run, None);
+    let report = RunReport::new(&results, &run);
+    println!(
+        "Run {run_id} ({}) finished with {} lines",
+        report.run.run_id,
+        report.lines.len()
+    );
+
+    if let Some(url) = callback_url {
+        let payload = serde_json::json!({ "run_id": run_id, "report": report });
+        tokio::task::spawn_blocking(move || {
+            ureq::post(&url)
+                .send_json(payload)
+                .map(|_| ())
+                .with_context(|| format!("Callback to {url} failed"))
+        })
+        .await
+        .context("Callback task panicked")??;
+    }
+    Ok(())
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(anyhow!("connection closed before headers were complete"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(pos) = find_header_end(&buffer) {
+            break pos;
+        }
+        if buffer.len() > MAX_REQUEST_BYTES {
+            return Err(anyhow!("request headers too large"));
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+    let content_length = content_length(&head)?;
+    if header_end + 4 + content_length > MAX_REQUEST_BYTES {
+        return Err(anyhow!("request body too large"));
+    }
+    while buffer.len() < header_end + 4 + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(anyhow!("connection closed before body was complete"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body = buffer[header_end + 4..header_end + 4 + content_length].to_vec();
+    parse_request_line(&head, body)
+}
+
+fn find_header_end(buffer: &[u8]) -> Option<usize> {
+    buffer.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+fn content_length(head: &str) -> Result<usize> {
+    head.lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse().context("invalid Content-Length"))
+        .unwrap_or(Ok(0))
+}
+
+fn parse_request_line(head: &str, body: Vec<u8>) -> Result<Request> {
+    let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(anyhow!("malformed request line"));
+    };
+    let path = target.split('?').next().unwrap_or(target);
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        body,
+    })
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    content_type: &str,
+    body: &str,
+) -> Result<()> {
+    let reason = match status {
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "OK",
+    };
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_request_head_and_length() {
+        let head = "POST /api/run?x=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 42";
+        assert_eq!(content_length(head).unwrap(), 42);
+        let request = parse_request_line(head, b"{}".to_vec()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/run");
+        assert_eq!(find_header_end(b"GET / HTTP/1.1\r\n\r\nbody"), Some(14));
+        assert!(content_length("GET / HTTP/1.1\r\nContent-Length: nope").is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_callbacks_and_unknown_routes() {
+        let state = Arc::new(ServerState {
+            options: ServeOptions {
+                listen: "127.0.0.1:0".parse().unwrap(),
+                config: Vec::new(),
+                skip_traceroute: true,
+                concurrency_limit: None,
+                load: LoadOptions::default(),
+            },
+            next_run_id: AtomicU64::new(1),
+            run_slot: Semaphore::new(1),
+        });
+        let request = |method: &str, path: &str, body: &str| Request {
+            method: method.into(),
+            path: path.into(),
+            body: body.as_bytes().to_vec(),
+        };
+
+        let (status, body) = route(
+            request("POST", "/api/run", r#"{"callback_url": "ftp://bot"}"#),
+            &state,
+        );
+        assert_eq!(status, 400);
+        assert!(body.contains("callback_url"));
+        assert_eq!(route(request("GET", "/api/run", ""), &state).0, 405);
+        assert_eq!(route(request("GET", "/nope", ""), &state).0, 404);
+
+        let (status, body) = route(request("GET", "/api/resolver", ""), &state);
+        assert_eq!(status, 200);
+        assert!(body.contains("\"cache_hits\""));
+    }
+}
@@ -0,0 +1,261 @@
+//! Synthetic failures for rehearsing the alerting pipeline (`--simulate`).
+//!
+//! Scenarios rewrite the outcomes of the ping, traceroute and dual-stack
+//! checks after they ran, so thresholds, alert state and notifications all
+//! see a failing line while the real one is left untouched.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::checks::CheckOutcome;
+use crate::diagnostics::{PingReport, TracerouteReport};
+
+/// One fabricated failure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scenario {
+    /// Ping reports this much packet loss.
+    Loss(f32),
+    /// Nothing answers: ping gets no replies, traceroute stops after the first hop.
+    Timeout,
+    /// Every traceroute hop between the first and the last moves to another router.
+    RouteChange,
+}
+
+impl Scenario {
+    fn describe(self) -> String {
+        match self {
+            Scenario::Loss(loss) => format!("loss {loss}%"),
+            Scenario::Timeout => "timeout".into(),
+            Scenario::RouteChange => "route change".into(),
+        }
+    }
+}
+
+/// Parse `loss=30`, `timeout` or `route-change`.
+pub fn parse_scenario(raw: &str) -> Result<Scenario, String> {
+    match raw.trim().split_once('=') {
+        Some(("loss", value)) => {
+            let loss: f32 = value
+                .parse()
+                .map_err(|_| format!("invalid loss percentage '{value}'"))?;
+            if !(0.0..=100.0).contains(&loss) {
+                return Err(format!("loss must be between 0 and 100, got {loss}"));
+            }
+            Ok(Scenario::Loss(loss))
+        }
+        None if raw.trim() == "timeout" => Ok(Scenario::Timeout),
+        None if raw.trim() == "route-change" => Ok(Scenario::RouteChange),
+        _ => Err(format!(
+            "unknown scenario '{raw}' (expected loss=<pct>, timeout or route-change)"
+        )),
+    }
+}
+
+/// Scenarios to inject, optionally limited to some lines.
+#[derive(Debug, Clone, Default)]
+pub struct Simulation {
+    pub scenarios: Vec<Scenario>,
+    /// Line names the scenarios apply to; every line when empty.
+    pub lines: Vec<String>,
+}
+
+impl Simulation {
+    pub fn applies_to(&self, line: &str) -> bool {
+        !self.scenarios.is_empty()
+            && (self.lines.is_empty() || self.lines.iter().any(|l| l == line))
+    }
+
+    pub fn describe(&self) -> String {
+        self.scenarios
+            .iter()
+            .map(|scenario| scenario.describe())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Rewrite the outcomes of one check according to every scenario.
+    pub fn apply(&self, checks: &mut [CheckOutcome]) {
+        for scenario in &self.scenarios {
+            for check in checks.iter_mut() {
+                match (scenario, check) {
+                    (Scenario::Loss(loss), CheckOutcome::Ping { report, .. }) => {
+                        simulate_loss(report, *loss);
+                    }
+                    (Scenario::Loss(loss), CheckOutcome::DualStack { report, .. }) => {
+                        for leg in report.ipv4.iter_mut().chain(report.ipv6.iter_mut()) {
+                            simulate_loss(&mut leg.report, *loss);
+                        }
+                    }
+                    (Scenario::Timeout, CheckOutcome::Ping { report, .. }) => {
+                        simulate_loss(report, 100.0);
+                    }
+                    (Scenario::Timeout, CheckOutcome::DualStack { report, .. }) => {
+                        for leg in report.ipv4.iter_mut().chain(report.ipv6.iter_mut()) {
+                            simulate_loss(&mut leg.report, 100.0);
+                        }
+                    }
+                    (
+                        Scenario::Timeout,
+                        CheckOutcome::Traceroute {
+                            report: Some(report),
+                            ..
+                        },
+                    ) => simulate_unanswered_hops(report),
+                    (
+                        Scenario::RouteChange,
+                        CheckOutcome::Traceroute {
+                            report: Some(report),
+                            ..
+                        },
+                    ) => simulate_route_change(report),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn simulate_loss(report: &mut PingReport, loss: f32) {
+    report.packet_loss_pct = Some(loss);
+    if loss >= 100.0 {
+        // Like ping itself, which exits non-zero when nothing came back.
+        report.success = false;
+        report.average_latency_ms = None;
+    }
+    report.raw_output = format!("[simulated loss {loss}%]\n{}", report.raw_output);
+}
+
+fn simulate_unanswered_hops(report: &mut TracerouteReport) {
+    for hop in report.hops.iter_mut().skip(1) {
+        hop.address = None;
+        hop.hostname = None;
+        hop.rtt_ms = None;
+    }
+    report.raw_output = format!("[simulated timeout]\n{}", report.raw_output);
+}
+
+fn simulate_route_change(report: &mut TracerouteReport) {
+    let last = report.hops.len().saturating_sub(1);
+    for (index, hop) in report.hops.iter_mut().enumerate() {
+        if index == 0 || index == last {
+            continue;
+        }
+        // TEST-NET-2, so a simulated router can never be mistaken for a real one.
+        hop.address = Some(IpAddr::V4(Ipv4Addr::new(198, 51, 100, hop.number as u8)));
+        hop.hostname = None;
+    }
+    report.raw_output = format!("[simulated route change]\n{}", report.raw_output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::TracerouteHop;
+
+    fn ping(loss: f32) -> CheckOutcome {
+        CheckOutcome::Ping {
+            report: PingReport {
+                success: true,
+                packet_loss_pct: Some(loss),
+                average_latency_ms: Some(9.0),
+                jitter_ms: None,
+                duplicates: 0,
+                reordered: 0,
+                raw_output: String::new(),
+            },
+            loss_threshold: 1.0,
+            duplicate_alert: None,
+            reorder_alert: None,
+            confirmation: None,
+        }
+    }
+
+    fn traceroute() -> CheckOutcome {
+        let hop = |number: u32, last: u8| TracerouteHop {
+            number,
+            address: Some(IpAddr::from([10, 0, 0, last])),
+            hostname: None,
+            rtt_ms: Some(1.0),
+            loss_pct: None,
+            asn: None,
+            as_name: None,
+            country: None,
+        };
+        CheckOutcome::Traceroute {
+            report: Some(TracerouteReport {
+                success: true,
+                hop_count: Some(3),
+                hops: vec![hop(1, 1), hop(2, 2), hop(3, 3)],
+                enrichment_error: None,
+                raw_output: String::new(),
+            }),
+            expected_gateway: None,
+            hop_tolerance: Some(0),
+            route_change: None,
+            hop_loss: None,
+        }
+    }
+
+    fn hop_addresses(check: &CheckOutcome) -> Vec<Option<IpAddr>> {
+        let CheckOutcome::Traceroute {
+            report: Some(report),
+            ..
+        } = check
+        else {
+            unreachable!();
+        };
+        report.hops.iter().map(|hop| hop.address).collect()
+    }
+
+    #[test]
+    fn parses_scenarios() {
+        assert_eq!(parse_scenario("loss=30"), Ok(Scenario::Loss(30.0)));
+        assert_eq!(parse_scenario("timeout"), Ok(Scenario::Timeout));
+        assert_eq!(parse_scenario("route-change"), Ok(Scenario::RouteChange));
+        assert!(parse_scenario("loss=130").is_err());
+        assert!(parse_scenario("loss=lots").is_err());
+        assert!(parse_scenario("flood").is_err());
+    }
+
+    #[test]
+    fn rewrites_outcomes_for_selected_lines() {
+        let simulation = Simulation {
+            scenarios: vec![Scenario::Loss(30.0), Scenario::RouteChange],
+            lines: vec!["Primary".into()],
+        };
+        assert!(simulation.applies_to("Primary"));
+        assert!(!simulation.applies_to("Backup"));
+        assert_eq!(simulation.describe(), "loss 30%, route change");
+
+        let mut checks = vec![ping(0.0), traceroute()];
+        simulation.apply(&mut checks);
+        let CheckOutcome::Ping { report, .. } = &checks[0] else {
+            unreachable!();
+        };
+        assert!(report.success);
+        assert_eq!(report.packet_loss_pct, Some(30.0));
+        assert_eq!(
+            hop_addresses(&checks[1]),
+            [
+                Some(IpAddr::from([10, 0, 0, 1])),
+                Some(IpAddr::from([198, 51, 100, 2])),
+                Some(IpAddr::from([10, 0, 0, 3])),
+            ]
+        );
+
+        let timeout = Simulation {
+            scenarios: vec![Scenario::Timeout],
+            lines: Vec::new(),
+        };
+        let mut checks = vec![ping(0.0), traceroute()];
+        timeout.apply(&mut checks);
+        let CheckOutcome::Ping { report, .. } = &checks[0] else {
+            unreachable!();
+        };
+        assert!(!report.success);
+        assert_eq!(report.average_latency_ms, None);
+        assert_eq!(
+            hop_addresses(&checks[1]),
+            [Some(IpAddr::from([10, 0, 0, 1])), None, None]
+        );
+    }
+}
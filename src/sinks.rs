@@ -0,0 +1,50 @@
+//! Sinks configured under `[output]`, written after every run.
+
+pub mod graphite;
+pub mod influxdb;
+pub mod mqtt;
+pub mod syslog;
+
+use anyhow::Result;
+use tracing::debug;
+
+use crate::config::OutputSettings;
+use crate::report::RunMetadata;
+use crate::runner::LineResult;
+
+/// Write the run to every configured sink. A failing sink does not keep the
+/// others from being written; all failures are reported together.
+pub fn write(settings: &OutputSettings, results: &[LineResult], run: &RunMetadata) -> Result<()> {
+    if results.is_empty() {
+        return Ok(());
+    }
+    let mut failures = Vec::new();
+    if let Some(influxdb) = &settings.influxdb {
+        debug!(url = %influxdb.url, points = results.len(), "Writing to InfluxDB");
+        if let Err(err) = influxdb::write(influxdb, results, run) {
+            failures.push(format!("InfluxDB: {err:#}"));
+        }
+    }
+    if let Some(graphite) = &settings.graphite {
+        debug!(host = %graphite.host, port = graphite.port, "Writing to Graphite");
+        if let Err(err) = graphite::write(graphite, results, run) {
+            failures.push(format!("Graphite: {err:#}"));
+        }
+    }
+    if let Some(syslog) = &settings.syslog {
+        debug!(socket = %syslog.socket.display(), "Writing to syslog");
+        if let Err(err) = syslog::write(syslog, results, run) {
+            failures.push(format!("Syslog: {err:#}"));
+        }
+    }
+    if let Some(mqtt) = &settings.mqtt {
+        debug!(host = %mqtt.host, port = mqtt.port, "Publishing to MQTT");
+        if let Err(err) = mqtt::write(mqtt, results, run) {
+            failures.push(format!("MQTT: {err:#}"));
+        }
+    }
+    if !failures.is_empty() {
+        anyhow::bail!("Failed to write run results: {}", failures.join("; "));
+    }
+    Ok(())
+}
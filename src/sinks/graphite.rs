@@ -0,0 +1,109 @@
+//! Graphite sink: Carbon's plaintext protocol over TCP, one
+//! `<prefix>.<line>.<metric> <value> <timestamp>` row per measurement.
+
+use std::io::Write as _;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::config::GraphiteSettings;
+use crate::report::RunMetadata;
+use crate::runner::{LineResult, LineStatus};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Render `results` as plaintext rows stamped with the run's end in seconds.
+///
+/// Each line contributes `loss`, `latency_ms`, `jitter_ms`, `hop_count` and
+/// `duration_ms` where measured, plus `ok` (1 when the line is OK).
+pub fn plaintext(results: &[LineResult], run: &RunMetadata, prefix: &str) -> String {
+    let timestamp = run.finished_at.timestamp();
+    let mut body = String::new();
+    for result in results {
+        let path = format!("{prefix}.{}", path_component(&result.key()));
+        let ping = result.ping();
+        let metrics = [
+            ("loss", ping.and_then(|ping| ping.packet_loss_pct)),
+            ("latency_ms", ping.and_then(|ping| ping.average_latency_ms)),
+            ("jitter_ms", ping.and_then(|ping| ping.jitter_ms)),
+            (
+                "hop_count",
+                result
+                    .traceroute()
+                    .and_then(|traceroute| traceroute.hop_count)
+                    .map(|hops| hops as f32),
+            ),
+            ("duration_ms", result.duration_ms),
+            (
+                "ok",
+                Some(f32::from(u8::from(result.status() == LineStatus::Ok))),
+            ),
+        ];
+        for (name, value) in metrics {
+            if let Some(value) = value {
+                body.push_str(&format!("{path}.{name} {value} {timestamp}\n"));
+            }
+        }
+    }
+    body
+}
+
+/// Send this run's rows to the configured Carbon server.
+pub fn write(settings: &GraphiteSettings, results: &[LineResult], run: &RunMetadata) -> Result<()> {
+    let destination = format!("{}:{}", settings.host, settings.port);
+    let address = destination
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {destination}"))?
+        .next()
+        .ok_or_else(|| anyhow!("{destination} resolved to no address"))?;
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {destination}"))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    stream
+        .write_all(plaintext(results, run, &settings.prefix).as_bytes())
+        .with_context(|| format!("Failed to send metrics to {destination}"))
+}
+
+/// A line key as one path component: anything but letters, digits, `-` and
+/// `_` becomes `_`, so dots and spaces cannot add levels.
+fn path_component(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckOutcome;
+    use crate::runner::tests::sample_result;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn renders_rows_per_line_metric() {
+        let mut result = sample_result("Primary FTTH 1.0", true, Some(0.0), Some(12.5), 1.0, None);
+        result.batch = Some("site-a".into());
+        if let CheckOutcome::Ping { report, .. } = &mut result.checks[0] {
+            report.jitter_ms = Some(0.3);
+        }
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let ts = at.timestamp();
+        assert_eq!(
+            plaintext(&[result], &RunMetadata::new(at, at), "noc.icmp"),
+            format!(
+                "noc.icmp.site-a_Primary_FTTH_1_0.loss 0 {ts}\n\
+                 noc.icmp.site-a_Primary_FTTH_1_0.latency_ms 12.5 {ts}\n\
+                 noc.icmp.site-a_Primary_FTTH_1_0.jitter_ms 0.3 {ts}\n\
+                 noc.icmp.site-a_Primary_FTTH_1_0.ok 1 {ts}\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! InfluxDB sink: one point of line protocol per line after every run.
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::config::{InfluxDbDestination, InfluxDbSettings};
+use crate::report::RunMetadata;
+use crate::runner::LineResult;
+
+/// Render `results` as line protocol, one point per line tagged with the
+/// host, line key and target and stamped with the run's end in seconds.
+/// Values a line did not measure are left out.
+pub fn line_protocol(results: &[LineResult], run: &RunMetadata, measurement: &str) -> String {
+    let timestamp = run.finished_at.timestamp();
+    let mut body = String::new();
+    for result in results {
+        let ping = result.ping();
+        let mut fields = vec![format!("status={}", string_field(result.status().as_str()))];
+        let mut float = |key: &str, value: Option<f32>| {
+            if let Some(value) = value {
+                fields.push(format!("{key}={value}"));
+            }
+        };
+        float("loss_pct", ping.and_then(|ping| ping.packet_loss_pct));
+        float("latency_ms", ping.and_then(|ping| ping.average_latency_ms));
+        float("jitter_ms", ping.and_then(|ping| ping.jitter_ms));
+        float("duration_ms", result.duration_ms);
+        if let Some(hops) = result
+            .traceroute()
+            .and_then(|traceroute| traceroute.hop_count)
+        {
+            fields.push(format!("hop_count={hops}i"));
+        }
+        body.push_str(&format!(
+            "{},host={},line={},target={} {} {timestamp}\n",
+            escape(measurement, ", "),
+            escape(&run.hostname, ",= "),
+            escape(&result.key(), ",= "),
+            escape(&result.target, ",= "),
+            fields.join(","),
+        ));
+    }
+    body
+}
+
+/// Write this run's points to the configured server.
+pub fn write(settings: &InfluxDbSettings, results: &[LineResult], run: &RunMetadata) -> Result<()> {
+    let body = line_protocol(results, run, &settings.measurement);
+    let mut request = match &settings.destination {
+        InfluxDbDestination::Bucket { bucket, org } => {
+            let request = ureq::post(&format!("{}/api/v2/write", settings.url))
+                .query("bucket", bucket)
+                .query("precision", "s");
+            match org {
+                Some(org) => request.query("org", org),
+                None => request,
+            }
+        }
+        InfluxDbDestination::Database(database) => ureq::post(&format!("{}/write", settings.url))
+            .query("db", database)
+            .query("precision", "s"),
+    };
+    if let Some(token) = &settings.token {
+        let token = token
+            .reveal()
+            .context("Failed to read output.influxdb.token")?;
+        request = request.set("Authorization", &format!("Token {token}"));
+    }
+    match request
+        .set("Content-Type", "text/plain; charset=utf-8")
+        .send_string(&body)
+    {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, resp)) => {
+            let text = resp
+                .into_string()
+                .unwrap_or_else(|_| "<no body>".to_string());
+            Err(anyhow!("InfluxDB responded with status {code}: {text}"))
+        }
+        Err(err) => Err(anyhow!(err).context(format!("Failed to write to {}", settings.url))),
+    }
+}
+
+/// Backslash-escape `special` characters (and backslashes) in a measurement,
+/// tag key or tag value.
+fn escape(value: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn string_field(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::tests::sample_result;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn renders_one_point_per_line() {
+        let mut result = sample_result("Primary FTTH", true, Some(20.0), Some(12.5), 1.0, None);
+        result.batch = Some("site=a".into());
+        result.duration_ms = Some(250.0);
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let mut run = RunMetadata::new(at, at);
+        run.hostname = "probe 1".into();
+        assert_eq!(
+            line_protocol(&[result], &run, "icmpmolester"),
+            format!(
+                "icmpmolester,host=probe\\ 1,line=site\\=a/Primary\\ FTTH,target=10.0.0.1 \
+                 status=\"ALERT\",loss_pct=20,latency_ms=12.5,duration_ms=250 {}\n",
+                at.timestamp()
+            )
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! MQTT sink: each line's status and metrics published under
+//! `<prefix>/<line>/<name>`, MQTT 3.1.1 at QoS 0, retained by default so a
+//! dashboard that subscribes later still sees the last run.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::SecondsFormat;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConnection, StreamOwned};
+
+use crate::config::MqttSettings;
+use crate::probes::tls::client_config;
+use crate::report::RunMetadata;
+use crate::runner::{LineResult, Rollup};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+const KEEP_ALIVE_SECS: u16 = 60;
+
+/// The `(topic, payload)` pairs of one run: the rollup status, then per line
+/// its status, `loss_pct`, `latency_ms`, `jitter_ms`, `hop_count` and
+/// `duration_ms` where measured, and when it was `updated`.
+pub fn messages(results: &[LineResult], run: &RunMetadata, prefix: &str) -> Vec<(String, String)> {
+    let mut messages = vec![(
+        format!("{prefix}/status"),
+        Rollup::of(results).status.as_str().to_string(),
+    )];
+    let updated = run.finished_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+    for result in results {
+        let topic = format!("{prefix}/{}", topic_level(&result.key()));
+        messages.push((
+            format!("{topic}/status"),
+            result.display_status().to_string(),
+        ));
+        let ping = result.ping();
+        let metrics = [
+            ("loss_pct", ping.and_then(|ping| ping.packet_loss_pct)),
+            ("latency_ms", ping.and_then(|ping| ping.average_latency_ms)),
+            ("jitter_ms", ping.and_then(|ping| ping.jitter_ms)),
+            (
+                "hop_count",
+                result
+                    .traceroute()
+                    .and_then(|traceroute| traceroute.hop_count)
+                    .map(|hops| hops as f32),
+            ),
+            ("duration_ms", result.duration_ms),
+        ];
+        for (name, value) in metrics {
+            if let Some(value) = value {
+                messages.push((format!("{topic}/{name}"), value.to_string()));
+            }
+        }
+        messages.push((format!("{topic}/updated"), updated.clone()));
+    }
+    messages
+}
+
+/// Publish this run's messages to the configured broker.
+pub fn write(settings: &MqttSettings, results: &[LineResult], run: &RunMetadata) -> Result<()> {
+    let destination = format!("{}:{}", settings.host, settings.port);
+    let address = destination
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {destination}"))?
+        .next()
+        .ok_or_else(|| anyhow!("{destination} resolved to no address"))?;
+    let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {destination}"))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let password = settings
+        .password
+        .as_ref()
+        .map(|source| {
+            source
+                .reveal()
+                .context("Failed to read output.mqtt.password")
+        })
+        .transpose()?;
+    let client_id = settings
+        .client_id
+        .clone()
+        .unwrap_or_else(|| format!("icmpmolester-{}", run.hostname));
+    let session = Session {
+        client_id: &client_id,
+        username: settings.username.as_deref(),
+        password: password.as_deref(),
+        retain: settings.retain,
+    };
+    let messages = messages(results, run, &settings.topic_prefix);
+    if settings.tls {
+        let name = ServerName::try_from(settings.host.clone())
+            .with_context(|| format!("Invalid TLS server name {}", settings.host))?;
+        let connection = ClientConnection::new(client_config(), name)
+            .context("Failed to start the TLS session")?;
+        session.publish(&mut StreamOwned::new(connection, stream), &messages)
+    } else {
+        session.publish(&mut &stream, &messages)
+    }
+    .with_context(|| format!("Failed to publish to {destination}"))
+}
+
+/// One connection's worth of MQTT: connect, publish every message, disconnect.
+struct Session<'a> {
+    client_id: &'a str,
+    username: Option<&'a str>,
+    password: Option<&'a str>,
+    retain: bool,
+}
+
+impl Session<'_> {
+    fn publish(
+        &self,
+        stream: &mut (impl Read + Write),
+        messages: &[(String, String)],
+    ) -> Result<()> {
+        stream.write_all(&self.connect_packet())?;
+        let mut connack = [0u8; 4];
+        stream
+            .read_exact(&mut connack)
+            .context("The broker did not acknowledge the connection")?;
+        if connack[..2] != [0x20, 0x02] {
+            anyhow::bail!("The broker answered with an unexpected packet");
+        }
+        match connack[3] {
+            0 => {}
+            4 => anyhow::bail!("The broker rejected the username or password"),
+            5 => anyhow::bail!("The broker refused the connection as not authorized"),
+            code => anyhow::bail!("The broker refused the connection (code {code})"),
+        }
+        let mut packets = Vec::new();
+        for (topic, payload) in messages {
+            packets.extend(self.publish_packet(topic, payload));
+        }
+        // DISCONNECT, so the broker drops the session without a will.
+        packets.extend([0xE0, 0x00]);
+        stream.write_all(&packets)?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn connect_packet(&self) -> Vec<u8> {
+        // Clean session, plus the username and password flags.
+        let mut flags = 0x02;
+        let mut body = Vec::new();
+        push_string(&mut body, "MQTT");
+        body.push(4);
+        if self.username.is_some() {
+            flags |= 0x80;
+        }
+        if self.password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend(KEEP_ALIVE_SECS.to_be_bytes());
+        push_string(&mut body, self.client_id);
+        for field in [self.username, self.password].into_iter().flatten() {
+            push_string(&mut body, field);
+        }
+        packet(0x10, &body)
+    }
+
+    fn publish_packet(&self, topic: &str, payload: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        push_string(&mut body, topic);
+        body.extend(payload.as_bytes());
+        packet(0x30 | u8::from(self.retain), &body)
+    }
+}
+
+/// A fixed header (`kind` and the remaining length) followed by `body`.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        if length == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend(body);
+    packet
+}
+
+/// A UTF-8 string prefixed with its length in two bytes.
+fn push_string(buffer: &mut Vec<u8>, value: &str) {
+    let bytes = &value.as_bytes()[..value.len().min(u16::MAX as usize)];
+    buffer.extend((bytes.len() as u16).to_be_bytes());
+    buffer.extend(bytes);
+}
+
+/// A line key as one topic level: `/` and the `+` and `#` wildcards become
+/// `_`, so a line name cannot add levels or be unsubscribable.
+fn topic_level(key: &str) -> String {
+    key.chars()
+        .map(|c| if matches!(c, '/' | '+' | '#') { '_' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::tests::sample_result;
+    use chrono::{TimeZone, Utc};
+
+    /// A broker connection that accepts the session and records what is sent.
+    struct FakeBroker {
+        replies: &'static [u8],
+        sent: Vec<u8>,
+    }
+
+    impl Read for FakeBroker {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buffer)
+        }
+    }
+
+    impl Write for FakeBroker {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            self.sent.write(buffer)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn publishes_retained_line_metrics() {
+        let result = sample_result("Primary FTTH/1", true, Some(0.0), Some(12.5), 1.0, None);
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let messages = messages(&[result], &RunMetadata::new(at, at), "home/icmp");
+        let pairs: Vec<(&str, &str)> = messages
+            .iter()
+            .map(|(topic, payload)| (topic.as_str(), payload.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("home/icmp/status", "OK"),
+                ("home/icmp/Primary FTTH_1/status", "OK"),
+                ("home/icmp/Primary FTTH_1/loss_pct", "0"),
+                ("home/icmp/Primary FTTH_1/latency_ms", "12.5"),
+                ("home/icmp/Primary FTTH_1/updated", "2026-05-01T12:00:00Z"),
+            ]
+        );
+
+        let mut broker = FakeBroker {
+            replies: &[0x20, 0x02, 0x00, 0x00],
+            sent: Vec::new(),
+        };
+        let session = Session {
+            client_id: "probe",
+            username: Some("u"),
+            password: Some("p"),
+            retain: true,
+        };
+        session
+            .publish(&mut broker, &[("a/b".into(), "OK".into())])
+            .unwrap();
+        assert_eq!(
+            broker.sent,
+            [
+                &[0x10, 23, 0, 4][..],
+                b"MQTT",
+                &[4, 0xC2, 0, 60, 0, 5],
+                b"probe",
+                &[0, 1, b'u', 0, 1, b'p'],
+                &[0x31, 7, 0, 3],
+                b"a/bOK",
+                &[0xE0, 0],
+            ]
+            .concat()
+        );
+
+        let mut refused = FakeBroker {
+            replies: &[0x20, 0x02, 0x00, 0x04],
+            sent: Vec::new(),
+        };
+        let err = session.publish(&mut refused, &[]).unwrap_err();
+        assert!(err.to_string().contains("username or password"), "{err}");
+    }
+}
@@ -0,0 +1,193 @@
+//! Syslog sink: one message per line at a priority matching its status, plus
+//! a run summary, sent to the local syslog daemon or the systemd journal.
+
+use anyhow::Result;
+use chrono::Local;
+
+use crate::alerting::assess;
+use crate::config::{SyslogSettings, SyslogTransport};
+use crate::report::RunMetadata;
+use crate::runner::{LineResult, LineStatus, Rollup};
+
+const ERR: u8 = 3;
+const WARNING: u8 = 4;
+const NOTICE: u8 = 5;
+const INFO: u8 = 6;
+
+/// One log record with its syslog severity.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub severity: u8,
+    pub message: String,
+    /// Line the record is about; `None` for the run summary.
+    pub line: Option<LineFields>,
+}
+
+/// Journal fields of a line record.
+#[derive(Debug, PartialEq)]
+pub struct LineFields {
+    pub key: String,
+    pub target: String,
+    pub status: String,
+}
+
+/// ALERT and ERRORED lines log as `err`, UNKNOWN, DEPENDENCY_DOWN and
+/// INTERRUPTED ones as `warning`, the rest as `info`.
+fn severity(status: LineStatus) -> u8 {
+    match status {
+        LineStatus::Alert | LineStatus::Errored => ERR,
+        LineStatus::Unknown | LineStatus::DependencyDown | LineStatus::Interrupted => WARNING,
+        LineStatus::Ok | LineStatus::Snoozed => INFO,
+    }
+}
+
+/// A record per line, then a `notice` summarising the run.
+pub fn records(results: &[LineResult], run: &RunMetadata) -> Vec<Record> {
+    let mut records: Vec<Record> = results
+        .iter()
+        .map(|result| {
+            let mut reasons: Vec<String> = result.execution_note().into_iter().collect();
+            reasons.extend(result.checks.iter().filter_map(|check| {
+                let reason = assess(check)?.reason?;
+                Some(format!("{}: {reason}", check.label()))
+            }));
+            let mut message = format!(
+                "{} ({}) {}",
+                result.key(),
+                result.target,
+                result.display_status()
+            );
+            if !reasons.is_empty() {
+                message.push_str(&format!(": {}", reasons.join("; ")));
+            }
+            Record {
+                severity: severity(result.status()),
+                message,
+                line: Some(LineFields {
+                    key: result.key(),
+                    target: result.target.clone(),
+                    status: result.display_status().to_string(),
+                }),
+            }
+        })
+        .collect();
+    records.push(Record {
+        severity: NOTICE,
+        message: format!("{}: {}", run.describe(), Rollup::of(results).describe()),
+        line: None,
+    });
+    records
+}
+
+/// An RFC 3164 datagram as `syslog(3)` sends it to `/dev/log`.
+fn rfc3164(settings: &SyslogSettings, record: &Record) -> Vec<u8> {
+    format!(
+        "<{}>{} {}[{}]: {}",
+        u16::from(settings.facility) * 8 + u16::from(record.severity),
+        Local::now().format("%b %e %H:%M:%S"),
+        settings.ident,
+        std::process::id(),
+        record.message.replace('\n', " ")
+    )
+    .into_bytes()
+}
+
+/// A datagram in the journal's native protocol.
+fn journal_entry(settings: &SyslogSettings, record: &Record, run: &RunMetadata) -> Vec<u8> {
+    let mut entry = Vec::new();
+    let mut field = |name: &str, value: &str| {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            // Multi-line values are length-prefixed instead of `=`-separated.
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    };
+    field("MESSAGE", &record.message);
+    field("PRIORITY", &record.severity.to_string());
+    field("SYSLOG_FACILITY", &settings.facility.to_string());
+    field("SYSLOG_IDENTIFIER", &settings.ident);
+    field("ICMPMOLESTER_RUN_ID", &run.run_id.to_string());
+    if let Some(line) = &record.line {
+        field("ICMPMOLESTER_LINE", &line.key);
+        field("ICMPMOLESTER_TARGET", &line.target);
+        field("ICMPMOLESTER_STATUS", &line.status);
+    }
+    entry
+}
+
+/// Log this run's records through the configured transport.
+#[cfg(unix)]
+pub fn write(settings: &SyslogSettings, results: &[LineResult], run: &RunMetadata) -> Result<()> {
+    use anyhow::Context;
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound().context("Failed to create a Unix socket")?;
+    socket
+        .connect(&settings.socket)
+        .with_context(|| format!("Failed to connect to {}", settings.socket.display()))?;
+    for record in records(results, run) {
+        let datagram = match settings.transport {
+            SyslogTransport::Syslog => rfc3164(settings, &record),
+            SyslogTransport::Journald => journal_entry(settings, &record, run),
+        };
+        socket
+            .send(&datagram)
+            .with_context(|| format!("Failed to log to {}", settings.socket.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn write(_: &SyslogSettings, _: &[LineResult], _: &RunMetadata) -> Result<()> {
+    anyhow::bail!("Syslog and the systemd journal are only available on Unix")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::tests::sample_result;
+    use chrono::{TimeZone, Utc};
+    use std::path::PathBuf;
+
+    #[test]
+    fn logs_alerts_as_errors_with_journal_fields() {
+        let mut result = sample_result("Backup LTE", true, Some(40.0), Some(30.0), 1.0, None);
+        result.target = "10.0.0.2".into();
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let run = RunMetadata::new(at, at);
+        let records = records(&[result], &run);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].severity, ERR);
+        assert!(
+            records[0]
+                .message
+                .starts_with("Backup LTE (10.0.0.2) ALERT: ping: "),
+            "{}",
+            records[0].message
+        );
+        assert_eq!(records[1].severity, NOTICE);
+        assert!(
+            records[1]
+                .message
+                .ends_with("ALERT, 100% of line weight impacted")
+        );
+
+        let settings = SyslogSettings {
+            transport: SyslogTransport::Journald,
+            facility: 3,
+            ident: "icmpmolester".into(),
+            socket: PathBuf::from("/run/systemd/journal/socket"),
+        };
+        let entry = String::from_utf8(journal_entry(&settings, &records[0], &run)).unwrap();
+        assert!(entry.contains("PRIORITY=3\n"), "{entry}");
+        assert!(entry.contains("ICMPMOLESTER_LINE=Backup LTE\n"), "{entry}");
+        assert!(entry.contains("ICMPMOLESTER_STATUS=ALERT\n"), "{entry}");
+        let datagram = String::from_utf8(rfc3164(&settings, &records[0])).unwrap();
+        assert!(datagram.starts_with("<27>"), "{datagram}");
+    }
+}
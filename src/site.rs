@@ -0,0 +1,371 @@
+//! `report site`: a static HTML status page built from the history database,
+//! with each line's current status, daily uptime bars and recent incidents.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, Utc};
+use serde::Serialize;
+use tera::Tera;
+
+use crate::history::{StatusSample, load_status_samples};
+use crate::redact;
+use crate::runner::LineStatus;
+
+/// Incidents listed at the bottom of the page, newest first.
+const RECENT_INCIDENTS: usize = 20;
+
+/// Options for the `report site` subcommand.
+pub struct SiteOptions {
+    pub history: PathBuf,
+    pub output: PathBuf,
+    pub days: u32,
+    pub title: String,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusPage {
+    title: String,
+    generated_at: String,
+    days: u32,
+    summary: String,
+    summary_class: &'static str,
+    lines: Vec<LinePage>,
+    incidents: Vec<Incident>,
+}
+
+#[derive(Debug, Serialize)]
+struct LinePage {
+    name: String,
+    target: String,
+    status: &'static str,
+    status_class: String,
+    uptime: String,
+    days: Vec<DayBar>,
+}
+
+#[derive(Debug, Serialize)]
+struct DayBar {
+    class: &'static str,
+    summary: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Incident {
+    line: String,
+    started_at: String,
+    ended_at: Option<String>,
+    duration: String,
+}
+
+/// Runs counted towards uptime: snoozed, interrupted and errored runs say
+/// nothing about the line itself.
+pub fn counts(status: LineStatus) -> bool {
+    !matches!(
+        status,
+        LineStatus::Snoozed | LineStatus::Interrupted | LineStatus::Errored
+    )
+}
+
+/// Write `index.html` for the last `days` days (today included, in UTC)
+/// into the output directory.
+pub fn run_site(options: &SiteOptions) -> Result<()> {
+    let now = Utc::now();
+    let first_day = now.date_naive() - Days::new(u64::from(options.days) - 1);
+    let since = first_day.and_time(NaiveTime::MIN).and_utc().timestamp();
+    let samples = load_status_samples(&options.history, since)?;
+    let page = StatusPage::build(&options.title, &samples, first_day, options.days, now);
+    let html = page.render()?;
+    fs::create_dir_all(&options.output)
+        .with_context(|| format!("Failed to create {}", options.output.display()))?;
+    let path = options.output.join("index.html");
+    fs::write(&path, html).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote the status page to {}", path.display());
+    Ok(())
+}
+
+impl StatusPage {
+    fn build(
+        title: &str,
+        samples: &[StatusSample],
+        first_day: NaiveDate,
+        days: u32,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let mut by_line: BTreeMap<&str, Vec<&StatusSample>> = BTreeMap::new();
+        for sample in samples {
+            by_line.entry(&sample.line).or_default().push(sample);
+        }
+
+        let mut lines = Vec::new();
+        let mut incidents = Vec::new();
+        let mut down = 0;
+        for (name, samples) in by_line {
+            let Some(latest) = samples.last() else {
+                continue;
+            };
+            if latest.status.is_down() {
+                down += 1;
+            }
+            let bars = (0..days)
+                .map(|offset| {
+                    let day = first_day + Days::new(u64::from(offset));
+                    let statuses: Vec<LineStatus> = samples
+                        .iter()
+                        .filter(|sample| date_of(sample.recorded_at) == Some(day))
+                        .map(|sample| sample.status)
+                        .collect();
+                    DayBar::of(day, &statuses)
+                })
+                .collect();
+            let statuses: Vec<LineStatus> = samples.iter().map(|sample| sample.status).collect();
+            lines.push(LinePage {
+                name: name.to_string(),
+                target: redact::target(&latest.target).into_owned(),
+                status: latest.status.as_str(),
+                status_class: latest.status.as_str().to_lowercase(),
+                uptime: uptime_pct(&statuses)
+                    .map(|pct| format!("{pct:.2}% uptime"))
+                    .unwrap_or_else(|| "no data".into()),
+                days: bars,
+            });
+            incidents.extend(Incident::of_line(name, &samples, now));
+        }
+        // Newest first; the timestamps sort as text.
+        incidents.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        incidents.truncate(RECENT_INCIDENTS);
+
+        let (summary, summary_class) = match down {
+            0 => ("All lines operational".to_string(), "ok"),
+            1 => ("1 line down".to_string(), "alert"),
+            n => (format!("{n} lines down"), "alert"),
+        };
+        StatusPage {
+            title: title.to_string(),
+            generated_at: format_time(now.timestamp()),
+            days,
+            summary,
+            summary_class,
+            lines,
+            incidents,
+        }
+    }
+
+    fn render(&self) -> Result<String> {
+        let context =
+            tera::Context::from_serialize(self).context("Failed to prepare the status page")?;
+        Tera::one_off(PAGE_TEMPLATE, &context, true).context("Failed to render the status page")
+    }
+}
+
+impl DayBar {
+    /// An `up` day had no downtime (`degraded` if it had UNKNOWN runs), a
+    /// `partial` one was down for less than half of its runs, a `down` one
+    /// for the rest.
+    fn of(day: NaiveDate, statuses: &[LineStatus]) -> Self {
+        let date = day.format("%Y-%m-%d");
+        let Some(pct) = uptime_pct(statuses) else {
+            return DayBar {
+                class: "none",
+                summary: format!("{date}: no data"),
+            };
+        };
+        let class = if pct >= 100.0 {
+            if statuses.contains(&LineStatus::Unknown) {
+                "degraded"
+            } else {
+                "up"
+            }
+        } else if pct > 50.0 {
+            "partial"
+        } else {
+            "down"
+        };
+        DayBar {
+            class,
+            summary: format!("{date}: {pct:.1}% uptime"),
+        }
+    }
+}
+
+impl Incident {
+    fn of_line(line: &str, samples: &[&StatusSample], now: DateTime<Utc>) -> Vec<Self> {
+        down_spans(samples)
+            .into_iter()
+            .map(|(start, end)| Incident {
+                line: line.to_string(),
+                started_at: format_time(start),
+                ended_at: end.map(format_time),
+                duration: format_span(end.unwrap_or(now.timestamp()) - start),
+            })
+            .collect()
+    }
+}
+
+/// Stretches of consecutive down runs of one line, as `(start, end)` unix
+/// timestamps; one still running at the last sample has no end.
+pub fn down_spans(samples: &[&StatusSample]) -> Vec<(i64, Option<i64>)> {
+    let mut spans = Vec::new();
+    let mut started = None;
+    for sample in samples.iter().filter(|sample| counts(sample.status)) {
+        match (started, sample.status.is_down()) {
+            (None, true) => started = Some(sample.recorded_at),
+            (Some(start), false) => {
+                spans.push((start, Some(sample.recorded_at)));
+                started = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = started {
+        spans.push((start, None));
+    }
+    spans
+}
+
+/// Share of counted runs that were not down, in percent.
+pub fn uptime_pct(statuses: &[LineStatus]) -> Option<f64> {
+    let counted: Vec<_> = statuses.iter().filter(|status| counts(**status)).collect();
+    if counted.is_empty() {
+        return None;
+    }
+    let up = counted.iter().filter(|status| !status.is_down()).count();
+    Some(up as f64 / counted.len() as f64 * 100.0)
+}
+
+fn date_of(timestamp: i64) -> Option<NaiveDate> {
+    DateTime::from_timestamp(timestamp, 0).map(|at| at.date_naive())
+}
+
+pub fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+/// `2d 3h`, `1h 5m` or `12m`, to the minute.
+pub fn format_span(secs: i64) -> String {
+    let minutes = secs.max(0) / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+const PAGE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{ title }}</title>
+<style>
+body { font-family: system-ui, sans-serif; max-width: 960px; margin: 2em auto; padding: 0 1em; color: #222; }
+.summary { padding: 1em; border-radius: 6px; color: #fff; font-weight: bold; }
+.summary.ok { background: #2e9e4f; }
+.summary.alert { background: #d0392b; }
+.line { margin: 1.5em 0; }
+.line header { display: flex; justify-content: space-between; }
+.status { font-weight: bold; }
+.status.ok { color: #2e9e4f; }
+.status.unknown { color: #c98a00; }
+.status.alert, .status.dependency_down { color: #d0392b; }
+.status.snoozed, .status.interrupted, .status.errored { color: #777; }
+.target, .uptime, footer { color: #666; font-size: 0.9em; }
+.bars { display: flex; gap: 2px; margin: 0.4em 0; }
+.bars span { flex: 1; height: 2em; border-radius: 2px; }
+.bars .up { background: #2e9e4f; }
+.bars .degraded { background: #c98a00; }
+.bars .partial { background: #e67e22; }
+.bars .down { background: #d0392b; }
+.bars .none { background: #ddd; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #eee; }
+</style>
+</head>
+<body>
+<h1>{{ title }}</h1>
+<p class="summary {{ summary_class }}">{{ summary }}</p>
+{% for line in lines %}
+<section class="line">
+<header>
+<span>{{ line.name }} <span class="target">{{ line.target }}</span></span>
+<span class="status {{ line.status_class }}">{{ line.status }}</span>
+</header>
+<div class="bars">{% for day in line.days %}<span class="{{ day.class }}" title="{{ day.summary }}"></span>{% endfor %}</div>
+<div class="uptime">{{ line.uptime }} over the last {{ days }} days</div>
+</section>
+{% endfor %}
+<h2>Recent incidents</h2>
+{% if incidents %}
+<table>
+<tr><th>Line</th><th>Started</th><th>Ended</th><th>Duration</th></tr>
+{% for incident in incidents %}
+<tr><td>{{ incident.line }}</td><td>{{ incident.started_at }}</td><td>{% if incident.ended_at %}{{ incident.ended_at }}{% else %}ongoing{% endif %}</td><td>{{ incident.duration }}</td></tr>
+{% endfor %}
+</table>
+{% else %}
+<p>No incidents in the last {{ days }} days.</p>
+{% endif %}
+<footer>Generated {{ generated_at }}</footer>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn builds_uptime_bars_and_incidents() {
+        let day = |d: u32, h: u32| {
+            Utc.with_ymd_and_hms(2026, 5, d, h, 0, 0)
+                .unwrap()
+                .timestamp()
+        };
+        let sample = |line: &str, recorded_at: i64, status: LineStatus| StatusSample {
+            line: line.into(),
+            target: "192.0.2.1".into(),
+            recorded_at,
+            status,
+        };
+        let samples = [
+            sample("<Backup>", day(1, 10), LineStatus::Ok),
+            sample("<Backup>", day(1, 11), LineStatus::Alert),
+            sample("<Backup>", day(1, 12), LineStatus::Snoozed),
+            sample("<Backup>", day(1, 13), LineStatus::Ok),
+            sample("<Backup>", day(3, 10), LineStatus::Alert),
+            sample("Fibre", day(2, 10), LineStatus::Ok),
+            sample("Fibre", day(3, 10), LineStatus::Unknown),
+        ];
+        let first_day = NaiveDate::from_ymd_opt(2026, 5, 1).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 5, 3, 12, 30, 0).unwrap();
+        let page = StatusPage::build("Lines", &samples, first_day, 3, now);
+
+        assert_eq!(page.summary, "1 line down");
+        let backup = &page.lines[0];
+        assert_eq!(backup.status, "ALERT");
+        assert_eq!(backup.uptime, "50.00% uptime");
+        let classes: Vec<_> = backup.days.iter().map(|day| day.class).collect();
+        assert_eq!(classes, ["partial", "none", "down"]);
+        assert_eq!(backup.days[0].summary, "2026-05-01: 66.7% uptime");
+        let classes: Vec<_> = page.lines[1].days.iter().map(|day| day.class).collect();
+        assert_eq!(classes, ["none", "up", "degraded"]);
+
+        assert_eq!(page.incidents.len(), 2);
+        assert_eq!(page.incidents[0].started_at, "2026-05-03 10:00 UTC");
+        assert_eq!(page.incidents[0].ended_at, None);
+        assert_eq!(page.incidents[0].duration, "2h 30m");
+        assert_eq!(page.incidents[1].duration, "2h 0m");
+
+        let html = page.render().unwrap();
+        assert!(html.contains("&lt;Backup&gt;"), "{html}");
+        assert!(html.contains("<td>ongoing</td>"), "{html}");
+    }
+}
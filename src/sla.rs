@@ -0,0 +1,312 @@
+//! `report sla`: per-line availability, loss, latency and outages over a
+//! period of the history database, as evidence for service level reviews.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use tera::Tera;
+
+use crate::history::{MetricSample, StatusSample, load_metric_samples, load_status_samples};
+use crate::iterations::{mean, percentile};
+use crate::redact;
+use crate::report::markdown_cell;
+use crate::site::{down_spans, format_span, format_time, uptime_pct};
+
+/// How `report sla` prints its table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SlaFormat {
+    Text,
+    Markdown,
+    Html,
+}
+
+/// Options for the `report sla` subcommand.
+pub struct SlaOptions {
+    pub history: PathBuf,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub format: SlaFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct SlaReport {
+    since: String,
+    until: String,
+    generated_at: String,
+    lines: Vec<SlaLine>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct SlaLine {
+    name: String,
+    target: String,
+    /// Runs recorded in the period, counted or not.
+    runs: usize,
+    /// Share of counted runs that were not down.
+    availability_pct: Option<f64>,
+    mean_loss_pct: Option<f32>,
+    latency_p95_ms: Option<f32>,
+    outages: usize,
+    /// Time from the first down run of each outage to the next run up,
+    /// summed; an ongoing outage counts until the end of the period.
+    downtime: String,
+}
+
+/// Print the SLA report for the period between `since` and `until`.
+pub fn run_sla(options: &SlaOptions) -> Result<()> {
+    let (since, until) = (options.since.timestamp(), options.until.timestamp());
+    let statuses: Vec<StatusSample> = load_status_samples(&options.history, since)?
+        .into_iter()
+        .filter(|sample| sample.recorded_at <= until)
+        .collect();
+    let metrics = load_metric_samples(&options.history, since, until)?;
+    let report = SlaReport::build(&statuses, &metrics, options.since, options.until);
+    if report.lines.is_empty() {
+        anyhow::bail!(
+            "No history recorded between {} and {}",
+            report.since,
+            report.until
+        );
+    }
+    let rendered = match options.format {
+        SlaFormat::Text => report.text(),
+        SlaFormat::Markdown => report.markdown(),
+        SlaFormat::Html => report.html()?,
+    };
+    print!("{rendered}");
+    Ok(())
+}
+
+impl SlaReport {
+    fn build(
+        statuses: &[StatusSample],
+        metrics: &[MetricSample],
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Self {
+        let mut by_line: BTreeMap<&str, (Vec<&StatusSample>, Vec<&MetricSample>)> = BTreeMap::new();
+        for sample in statuses {
+            by_line.entry(&sample.line).or_default().0.push(sample);
+        }
+        for sample in metrics {
+            by_line.entry(&sample.line).or_default().1.push(sample);
+        }
+        let lines = by_line
+            .into_iter()
+            .map(|(name, (statuses, metrics))| {
+                let losses: Vec<f32> = metrics.iter().filter_map(|m| m.packet_loss_pct).collect();
+                let mut latencies: Vec<f32> = metrics
+                    .iter()
+                    .filter_map(|m| m.average_latency_ms)
+                    .collect();
+                latencies.sort_by(f32::total_cmp);
+                let spans = down_spans(&statuses);
+                let downtime = spans
+                    .iter()
+                    .map(|(start, end)| end.unwrap_or(until.timestamp()) - start)
+                    .sum();
+                let target = metrics
+                    .last()
+                    .map(|m| &m.target)
+                    .or(statuses.last().map(|s| &s.target))
+                    .map(|target| redact::target(target).into_owned())
+                    .unwrap_or_default();
+                let status_list: Vec<_> = statuses.iter().map(|sample| sample.status).collect();
+                SlaLine {
+                    name: name.to_string(),
+                    target,
+                    runs: statuses.len().max(metrics.len()),
+                    availability_pct: uptime_pct(&status_list),
+                    mean_loss_pct: mean(&losses),
+                    latency_p95_ms: percentile(&latencies, 95.0),
+                    outages: spans.len(),
+                    downtime: format_span(downtime),
+                }
+            })
+            .collect();
+        SlaReport {
+            since: format_time(since.timestamp()),
+            until: format_time(until.timestamp()),
+            generated_at: format_time(Utc::now().timestamp()),
+            lines,
+        }
+    }
+
+    fn rows(&self) -> Vec<[String; 8]> {
+        self.lines
+            .iter()
+            .map(|line| {
+                [
+                    line.name.clone(),
+                    line.target.clone(),
+                    line.availability_pct
+                        .map_or_else(|| "n/a".into(), |pct| format!("{pct:.3}%")),
+                    line.mean_loss_pct
+                        .map_or_else(|| "n/a".into(), |loss| format!("{loss:.2}%")),
+                    line.latency_p95_ms
+                        .map_or_else(|| "n/a".into(), |latency| format!("{latency:.1} ms")),
+                    line.outages.to_string(),
+                    line.downtime.clone(),
+                    line.runs.to_string(),
+                ]
+            })
+            .collect()
+    }
+
+    /// Columns padded to their widest cell.
+    fn text(&self) -> String {
+        let mut table = vec![HEADERS.map(str::to_string)];
+        table.extend(self.rows());
+        let widths: Vec<usize> = (0..HEADERS.len())
+            .map(|column| {
+                table
+                    .iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut text = format!("SLA report, {} to {}\n\n", self.since, self.until);
+        for row in &table {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            text.push_str(cells.join("  ").trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    fn markdown(&self) -> String {
+        let mut markdown = format!("## SLA report\n\n{} to {}\n\n", self.since, self.until);
+        markdown.push_str(&format!("| {} |\n", HEADERS.join(" | ")));
+        markdown.push_str("| --- | --- | ---: | ---: | ---: | ---: | ---: | ---: |\n");
+        for row in self.rows() {
+            let cells: Vec<String> = row.iter().map(|cell| markdown_cell(cell)).collect();
+            markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+        markdown
+    }
+
+    fn html(&self) -> Result<String> {
+        let mut context =
+            tera::Context::from_serialize(self).context("Failed to prepare the SLA report")?;
+        context.insert("headers", &HEADERS);
+        context.insert("rows", &self.rows());
+        Tera::one_off(PAGE_TEMPLATE, &context, true).context("Failed to render the SLA report")
+    }
+}
+
+const HEADERS: [&str; 8] = [
+    "Line",
+    "Target",
+    "Availability",
+    "Mean loss",
+    "p95 latency",
+    "Outages",
+    "Downtime",
+    "Runs",
+];
+
+const PAGE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>SLA report</title>
+<style>
+body { font-family: system-ui, sans-serif; max-width: 960px; margin: 2em auto; padding: 0 1em; color: #222; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: right; padding: 0.3em 0.6em; border-bottom: 1px solid #eee; }
+th:nth-child(-n+2), td:nth-child(-n+2) { text-align: left; }
+footer { color: #666; font-size: 0.9em; margin-top: 1em; }
+</style>
+</head>
+<body>
+<h1>SLA report</h1>
+<p>{{ since }} to {{ until }}</p>
+<table>
+<tr>{% for header in headers %}<th>{{ header }}</th>{% endfor %}</tr>
+{% for row in rows %}
+<tr>{% for cell in row %}<td>{{ cell }}</td>{% endfor %}</tr>
+{% endfor %}
+</table>
+<footer>Generated {{ generated_at }}</footer>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::LineStatus;
+    use chrono::TimeZone;
+
+    #[test]
+    fn summarises_availability_loss_latency_and_outages() {
+        let since = Utc.with_ymd_and_hms(2026, 5, 1, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2026, 5, 1, 2, 0, 0).unwrap();
+        let at = |minutes: i64| since.timestamp() + minutes * 60;
+        let runs = [
+            (0, LineStatus::Ok, Some(0.0), Some(10.0)),
+            (10, LineStatus::Alert, Some(100.0), None),
+            (20, LineStatus::Alert, Some(100.0), None),
+            (30, LineStatus::Ok, Some(0.0), Some(12.0)),
+            (40, LineStatus::Snoozed, Some(50.0), Some(90.0)),
+            (50, LineStatus::Ok, Some(0.0), Some(11.0)),
+            (60, LineStatus::Alert, Some(100.0), None),
+        ];
+        let statuses: Vec<StatusSample> = runs
+            .iter()
+            .map(|&(minute, status, _, _)| StatusSample {
+                line: "Fibre".into(),
+                target: "192.0.2.1".into(),
+                recorded_at: at(minute),
+                status,
+            })
+            .collect();
+        let metrics: Vec<MetricSample> = runs
+            .iter()
+            .map(|&(minute, _, loss, latency)| MetricSample {
+                line: "Fibre".into(),
+                target: "192.0.2.1".into(),
+                recorded_at: at(minute),
+                packet_loss_pct: loss,
+                average_latency_ms: latency,
+            })
+            .collect();
+
+        let report = SlaReport::build(&statuses, &metrics, since, until);
+        assert_eq!(
+            report.lines,
+            vec![SlaLine {
+                name: "Fibre".into(),
+                target: "192.0.2.1".into(),
+                runs: 7,
+                availability_pct: Some(50.0),
+                mean_loss_pct: Some(50.0),
+                latency_p95_ms: Some(90.0),
+                outages: 2,
+                // 10 to 30 minutes, then 60 minutes to the end of the period.
+                downtime: "1h 20m".into(),
+            }]
+        );
+        assert_eq!(
+            report.text(),
+            "SLA report, 2026-05-01 00:00 UTC to 2026-05-01 02:00 UTC\n\n\
+             Line   Target     Availability  Mean loss  p95 latency  Outages  Downtime  Runs\n\
+             Fibre  192.0.2.1  50.000%       50.00%     90.0 ms      2        1h 20m    7\n"
+        );
+        assert!(
+            report
+                .markdown()
+                .contains("| Fibre | 192.0.2.1 | 50.000% |")
+        );
+        assert!(report.html().unwrap().contains("<td>1h 20m</td>"));
+    }
+}
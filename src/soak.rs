@@ -0,0 +1,325 @@
+//! Time-boxed soak test of a single line for the `soak` subcommand.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use tokio::time::MissedTickBehavior;
+
+use crate::config::{LineSettings, LoadOptions, load_configs};
+use crate::diagnostics::{PingReport, run_ping};
+use crate::iterations::percentile;
+
+/// Upper bounds (inclusive) of the loss buckets reported at the end of a soak.
+const LOSS_BUCKETS: [(f32, &str); 4] =
+    [(0.0, "0%"), (5.0, "≤5%"), (25.0, "≤25%"), (99.99, "<100%")];
+
+/// Parameters of a soak run.
+pub struct SoakOptions {
+    pub config: Vec<PathBuf>,
+    pub line: String,
+    pub duration: Duration,
+    pub interval: Duration,
+    pub load: LoadOptions,
+}
+
+/// Parse durations such as `90s`, `10m`, `1h` or `1h30m`.
+pub fn parse_duration(raw: &str) -> Result<Duration, String> {
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for ch in raw.trim().chars() {
+        if ch.is_ascii_digit() {
+            digits.push(ch);
+            continue;
+        }
+        let unit = match ch {
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => 86_400,
+            _ => return Err(format!("unknown duration unit '{ch}' in '{raw}'")),
+        };
+        let value: u64 = digits
+            .parse()
+            .map_err(|_| format!("missing number before '{ch}' in '{raw}'"))?;
+        total += value * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(format!("'{raw}' needs a unit (s, m, h or d)"));
+    }
+    if total == 0 {
+        return Err("duration must be greater than zero".into());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// One ping round of the soak.
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    at: DateTime<Utc>,
+    loss_pct: Option<f32>,
+    latency_ms: Option<f32>,
+    outage: bool,
+}
+
+impl Sample {
+    fn from_report(at: DateTime<Utc>, report: &PingReport) -> Self {
+        let outage = match report.packet_loss_pct {
+            Some(loss) => loss >= 100.0,
+            None => !report.success,
+        };
+        Self {
+            at,
+            loss_pct: report.packet_loss_pct,
+            latency_ms: report.average_latency_ms,
+            outage,
+        }
+    }
+}
+
+/// Consecutive rounds in which the line did not answer at all.
+#[derive(Debug, Clone, PartialEq)]
+struct Outage {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    rounds: usize,
+}
+
+/// Accumulated samples of a soak run.
+#[derive(Debug, Default)]
+struct SoakStats {
+    samples: Vec<Sample>,
+}
+
+impl SoakStats {
+    fn record(&mut self, sample: Sample) {
+        self.samples.push(sample);
+    }
+
+    fn mean_loss(&self) -> Option<f32> {
+        let losses: Vec<f32> = self.samples.iter().filter_map(|s| s.loss_pct).collect();
+        (!losses.is_empty()).then(|| losses.iter().sum::<f32>() / losses.len() as f32)
+    }
+
+    /// Count of rounds per loss bucket, with total outages last.
+    fn loss_distribution(&self) -> Vec<(&'static str, usize)> {
+        let mut counts: Vec<(&'static str, usize)> =
+            LOSS_BUCKETS.iter().map(|(_, label)| (*label, 0)).collect();
+        counts.push(("100%", 0));
+        for loss in self.samples.iter().filter_map(|s| s.loss_pct) {
+            let idx = LOSS_BUCKETS
+                .iter()
+                .position(|(bound, _)| loss <= *bound)
+                .unwrap_or(LOSS_BUCKETS.len());
+            counts[idx].1 += 1;
+        }
+        counts
+    }
+
+    /// Nearest-rank percentile of per-round average latency.
+    fn latency_percentile(&self, pct: f32) -> Option<f32> {
+        let mut latencies: Vec<f32> = self.samples.iter().filter_map(|s| s.latency_ms).collect();
+        latencies.sort_by(f32::total_cmp);
+        percentile(&latencies, pct)
+    }
+
+    fn outages(&self) -> Vec<Outage> {
+        let mut outages: Vec<Outage> = Vec::new();
+        let mut in_outage = false;
+        for sample in &self.samples {
+            match (sample.outage, in_outage) {
+                (true, true) => {
+                    let current = outages.last_mut().expect("open outage");
+                    current.end = sample.at;
+                    current.rounds += 1;
+                }
+                (true, false) => outages.push(Outage {
+                    start: sample.at,
+                    end: sample.at,
+                    rounds: 1,
+                }),
+                (false, _) => {}
+            }
+            in_outage = sample.outage;
+        }
+        outages
+    }
+}
+
+/// Ping one line every `interval` until `duration` elapses (or Ctrl-C), then report.
+pub async fn run_soak(options: &SoakOptions) -> Result<()> {
+    let line = find_line(&options.config, &options.line, &options.load)?;
+    println!(
+        "Soaking {} ({}) for {}s, one round every {}s. Press Ctrl-C to stop early.",
+        line.name,
+        line.target,
+        options.duration.as_secs(),
+        options.interval.as_secs()
+    );
+
+    let mut stats = SoakStats::default();
+    let deadline = tokio::time::sleep(options.duration);
+    tokio::pin!(deadline);
+    let mut ticker = tokio::time::interval(options.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            _ = tokio::signal::ctrl_c() => {
+                println!("Interrupted; reporting collected rounds.");
+                break;
+            }
+            _ = ticker.tick() => {
+                let report = run_ping(&line).await?;
+                let sample = Sample::from_report(Utc::now(), &report);
+                println!("{}", format_sample(stats.samples.len() + 1, &sample));
+                stats.record(sample);
+            }
+        }
+    }
+
+    print!("{}", format_report(&line, &stats));
+    Ok(())
+}
+
+fn find_line(config: &[PathBuf], name: &str, options: &LoadOptions) -> Result<LineSettings> {
+    for (_, config) in load_configs(config, options)? {
+        if let Some(line) = config.lines.into_iter().find(|line| line.name == name) {
+            return Ok(line);
+        }
+    }
+    anyhow::bail!("No line named '{name}' in the given config(s)")
+}
+
+fn format_sample(round: usize, sample: &Sample) -> String {
+    let loss = sample
+        .loss_pct
+        .map(|loss| format!("{loss:.1}%"))
+        .unwrap_or_else(|| "n/a".into());
+    let latency = sample
+        .latency_ms
+        .map(|ms| format!("{ms:.2} ms"))
+        .unwrap_or_else(|| "n/a".into());
+    let marker = if sample.outage { " OUTAGE" } else { "" };
+    format!(
+        "[{}] round {round}: loss {loss}, latency {latency}{marker}",
+        format_time(sample.at)
+    )
+}
+
+fn format_report(line: &LineSettings, stats: &SoakStats) -> String {
+    let mut report = format!(
+        "\n=== Soak report: {} ({}) ===\nRounds: {}\n",
+        line.name,
+        line.target,
+        stats.samples.len()
+    );
+    if let (Some(first), Some(last)) = (stats.samples.first(), stats.samples.last()) {
+        report.push_str(&format!(
+            "Window: {} -> {}\n",
+            format_time(first.at),
+            format_time(last.at)
+        ));
+    }
+    match stats.mean_loss() {
+        Some(loss) => report.push_str(&format!("Mean packet loss: {loss:.2}%\n")),
+        None => report.push_str("Mean packet loss: unavailable\n"),
+    }
+
+    report.push_str("Loss distribution (rounds):\n");
+    for (label, count) in stats.loss_distribution() {
+        report.push_str(&format!("  {label:>5}: {count}\n"));
+    }
+
+    report.push_str("Latency percentiles (per-round average):\n");
+    for pct in [50.0, 90.0, 99.0, 100.0] {
+        let value = stats
+            .latency_percentile(pct)
+            .map(|ms| format!("{ms:.2} ms"))
+            .unwrap_or_else(|| "n/a".into());
+        let label = if pct == 100.0 {
+            "max".to_string()
+        } else {
+            format!("p{pct:.0}")
+        };
+        report.push_str(&format!("  {label:>4}: {value}\n"));
+    }
+
+    let outages = stats.outages();
+    if outages.is_empty() {
+        report.push_str("Outages: none\n");
+    } else {
+        report.push_str(&format!("Outages: {}\n", outages.len()));
+        for outage in outages {
+            let seconds = (outage.end - outage.start).num_seconds();
+            report.push_str(&format!(
+                "  {} -> {} ({} rounds, {seconds}s)\n",
+                format_time(outage.start),
+                format_time(outage.end),
+                outage.rounds
+            ));
+        }
+    }
+    report
+}
+
+fn format_time(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(secs: i64, loss: f32, latency: Option<f32>) -> Sample {
+        Sample {
+            at: Utc.timestamp_opt(secs, 0).unwrap(),
+            loss_pct: Some(loss),
+            latency_ms: latency,
+            outage: loss >= 100.0,
+        }
+    }
+
+    #[test]
+    fn parses_compound_durations() {
+        assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5_400)));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("0s").is_err());
+    }
+
+    #[test]
+    fn summarises_loss_latency_and_outages() {
+        let mut stats = SoakStats::default();
+        for (secs, loss, latency) in [
+            (0, 0.0, Some(10.0)),
+            (10, 0.0, Some(12.0)),
+            (20, 100.0, None),
+            (30, 100.0, None),
+            (40, 20.0, Some(40.0)),
+            (50, 100.0, None),
+            (60, 0.0, Some(11.0)),
+        ] {
+            stats.record(sample(secs, loss, latency));
+        }
+
+        let distribution = stats.loss_distribution();
+        assert_eq!(distribution[0], ("0%", 3));
+        assert_eq!(distribution[2], ("≤25%", 1));
+        assert_eq!(distribution[4], ("100%", 3));
+
+        assert_eq!(stats.latency_percentile(50.0), Some(11.0));
+        assert_eq!(stats.latency_percentile(100.0), Some(40.0));
+
+        let outages = stats.outages();
+        assert_eq!(outages.len(), 2);
+        assert_eq!(outages[0].rounds, 2);
+        assert_eq!((outages[0].end - outages[0].start).num_seconds(), 10);
+        assert_eq!(outages[1].rounds, 1);
+    }
+}
@@ -0,0 +1,873 @@
+//! Versioned on-disk alert state persisted between runs.
+//!
+//! The state file records the last known status of every line together with
+//! silences, acknowledgements and recent transitions so restarts and upgrades
+//! resume where the previous process stopped instead of re-alerting.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::runner::LineStatus;
+
+/// Format version written by this build. New fields must default when
+/// missing; bump this only for changes older builds cannot read, and
+/// upgrade older documents in [`check_version`] then.
+pub const STATE_FORMAT_VERSION: u32 = 1;
+
+/// Maximum number of transition timestamps retained per line.
+pub const MAX_RECENT_TRANSITIONS: usize = 32;
+
+/// Complete persisted alert state.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertState {
+    pub version: u32,
+    #[serde(default)]
+    pub saved_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub lines: BTreeMap<String, LineState>,
+    #[serde(default)]
+    pub silences: Vec<Silence>,
+    #[serde(default)]
+    pub channels: BTreeMap<String, ChannelStats>,
+    #[serde(default)]
+    pub routes: BTreeMap<String, RouteBaseline>,
+    #[serde(default)]
+    pub public_ips: BTreeMap<String, PublicIpRecord>,
+    #[serde(default)]
+    pub execution_errors: BTreeMap<String, ExecutionErrors>,
+    /// Consecutive failing runs of lines that set `alert_after_failures`.
+    #[serde(default)]
+    pub failing_runs: BTreeMap<String, u32>,
+    /// Last notification per channel and destination, for
+    /// `--notify-dedup-window`.
+    #[serde(default)]
+    pub sent: BTreeMap<String, SentNotification>,
+}
+
+/// Last observed status of a single line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineState {
+    pub status: LineStatus,
+    pub since: DateTime<Utc>,
+    pub last_checked: DateTime<Utc>,
+    #[serde(default)]
+    pub recent_transitions: Vec<DateTime<Utc>>,
+    #[serde(default)]
+    pub acknowledged: Option<Acknowledgement>,
+    /// Set while the line changes status too often to be notified of each change.
+    #[serde(default)]
+    pub flapping: Option<Flapping>,
+}
+
+/// When a line started flapping and the status it was last notified with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Flapping {
+    pub since: DateTime<Utc>,
+    pub notified: LineStatus,
+}
+
+/// How a line's flapping changed with its latest observation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlapChange {
+    Started,
+    /// The line held one status for a whole window; carries the status it
+    /// was last notified with before it started flapping.
+    Settled {
+        notified: LineStatus,
+    },
+}
+
+/// Operator acknowledgement of an ongoing alert; cleared on recovery.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Acknowledgement {
+    pub at: DateTime<Utc>,
+    #[serde(default)]
+    pub by: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Suppresses notifications for a line until the given instant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Silence {
+    pub line: String,
+    pub until: DateTime<Utc>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Delivery statistics for one notification channel (e.g. `email`, `telegram`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelStats {
+    pub attempts: u64,
+    pub failures: u64,
+    #[serde(default)]
+    pub last_success: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_failure: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub total_send_ms: f64,
+}
+
+impl ChannelStats {
+    /// Account for one delivery attempt.
+    pub fn record(&mut self, outcome: Result<(), String>, send_ms: f64, now: DateTime<Utc>) {
+        self.attempts += 1;
+        self.total_send_ms += send_ms;
+        match outcome {
+            Ok(()) => self.last_success = Some(now),
+            Err(error) => {
+                self.failures += 1;
+                self.last_failure = Some(now);
+                self.last_error = Some(error);
+            }
+        }
+    }
+
+    /// Mean time spent per delivery attempt.
+    pub fn average_send_ms(&self) -> Option<f64> {
+        (self.attempts > 0).then(|| self.total_send_ms / self.attempts as f64)
+    }
+}
+
+/// A notification as it was last delivered to one destination.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentNotification {
+    pub at: DateTime<Utc>,
+    /// The active alerts it carried, from [`crate::alerting::alert_keys`].
+    pub alerts: Vec<String>,
+}
+
+/// Traceroute path a line is expected to take; `None` marks a silent hop.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RouteBaseline {
+    pub hops: Vec<Option<IpAddr>>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl RouteBaseline {
+    /// Number of hop positions where `path` departs from the baseline.
+    ///
+    /// Silent hops (`* * *`) on either side match anything, and every hop one
+    /// path has beyond the other's length counts as a difference.
+    pub fn distance(&self, path: &[Option<IpAddr>]) -> u32 {
+        let len = self.hops.len().max(path.len());
+        let differing = (0..len)
+            .filter(|&idx| match (self.hops.get(idx), path.get(idx)) {
+                (Some(Some(expected)), Some(Some(seen))) => expected != seen,
+                (Some(_), Some(_)) => false,
+                _ => true,
+            })
+            .count();
+        u32::try_from(differing).unwrap_or(u32::MAX)
+    }
+
+    /// Hop-by-hop comparison of `path` against the baseline.
+    pub fn diff(&self, path: &[Option<IpAddr>]) -> Vec<HopDiff> {
+        let len = self.hops.len().max(path.len());
+        (0..len)
+            .map(|idx| {
+                let hop = u32::try_from(idx + 1).unwrap_or(u32::MAX);
+                match (self.hops.get(idx).copied(), path.get(idx).copied()) {
+                    (Some(Some(before)), Some(Some(after))) if before != after => {
+                        HopDiff::Changed {
+                            hop,
+                            before: Some(before),
+                            after: Some(after),
+                        }
+                    }
+                    (Some(before), Some(after)) => HopDiff::Same {
+                        hop,
+                        address: after.or(before),
+                    },
+                    (Some(before), None) => HopDiff::Removed {
+                        hop,
+                        address: before,
+                    },
+                    (None, after) => HopDiff::Added {
+                        hop,
+                        address: after.flatten(),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// One hop position of a traced path compared with the line's baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HopDiff {
+    /// Same router, or a silent hop on either side.
+    Same { hop: u32, address: Option<IpAddr> },
+    Changed {
+        hop: u32,
+        before: Option<IpAddr>,
+        after: Option<IpAddr>,
+    },
+    /// Hop beyond the end of the baseline.
+    Added { hop: u32, address: Option<IpAddr> },
+    /// Baseline hop the new path no longer reaches.
+    Removed { hop: u32, address: Option<IpAddr> },
+}
+
+/// How a traced path compares with the line's route baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteChange {
+    /// Number of hop positions that differ.
+    pub differing: u32,
+    pub hops: Vec<HopDiff>,
+}
+
+/// Last public address observed for a line and how often it has changed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublicIpRecord {
+    pub address: IpAddr,
+    pub since: DateTime<Utc>,
+    #[serde(default)]
+    pub changes: u64,
+}
+
+/// Consecutive runs in which a line's checks could not be executed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionErrors {
+    pub consecutive: u32,
+    pub last_error: String,
+    pub last_failure: DateTime<Utc>,
+    /// Set once the line has been auto-disabled.
+    #[serde(default)]
+    pub disabled: Option<DisabledLine>,
+}
+
+/// When a line was auto-disabled and the settings it was disabled under.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisabledLine {
+    pub at: DateTime<Utc>,
+    pub fingerprint: u64,
+}
+
+/// Status change produced when a line's new observation differs from the stored one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub line: String,
+    pub from: Option<LineStatus>,
+    pub to: LineStatus,
+    /// How long the line had been in `from`, when known.
+    pub lasted: Option<Duration>,
+}
+
+impl Default for AlertState {
+    fn default() -> Self {
+        Self {
+            version: STATE_FORMAT_VERSION,
+            saved_at: None,
+            lines: BTreeMap::new(),
+            silences: Vec::new(),
+            channels: BTreeMap::new(),
+            routes: BTreeMap::new(),
+            public_ips: BTreeMap::new(),
+            execution_errors: BTreeMap::new(),
+            failing_runs: BTreeMap::new(),
+            sent: BTreeMap::new(),
+        }
+    }
+}
+
+impl AlertState {
+    /// Load state from disk, starting empty when the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read state from {}", path.display()));
+            }
+        };
+        let value: serde_json::Value = serde_json::from_str(&raw)
+            .with_context(|| format!("Failed to parse state file {}", path.display()))?;
+        check_version(&value)
+            .with_context(|| format!("Cannot read state file {}", path.display()))?;
+        serde_json::from_value(value)
+            .with_context(|| format!("Invalid state file layout in {}", path.display()))
+    }
+
+    /// Persist state atomically: write a sibling temp file, fsync, then rename.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        self.version = STATE_FORMAT_VERSION;
+        self.saved_at = Some(Utc::now());
+        let body = serde_json::to_vec_pretty(self).context("Failed to serialise alert state")?;
+        write_atomically(path, &body)
+            .with_context(|| format!("Failed to write state to {}", path.display()))
+    }
+
+    /// Record a fresh observation for `line`, returning the transition if the status changed.
+    pub fn observe(
+        &mut self,
+        line: &str,
+        status: LineStatus,
+        now: DateTime<Utc>,
+    ) -> Option<Transition> {
+        match self.lines.get_mut(line) {
+            Some(entry) => {
+                entry.last_checked = now;
+                if entry.status == status {
+                    return None;
+                }
+                let from = entry.status;
+                let lasted = now - entry.since;
+                entry.status = status;
+                entry.since = now;
+                entry.recent_transitions.push(now);
+                let excess = entry
+                    .recent_transitions
+                    .len()
+                    .saturating_sub(MAX_RECENT_TRANSITIONS);
+                entry.recent_transitions.drain(..excess);
+                if status == LineStatus::Ok {
+                    entry.acknowledged = None;
+                }
+                Some(Transition {
+                    line: line.to_string(),
+                    from: Some(from),
+                    to: status,
+                    lasted: Some(lasted),
+                })
+            }
+            None => {
+                self.lines.insert(
+                    line.to_string(),
+                    LineState {
+                        status,
+                        since: now,
+                        last_checked: now,
+                        recent_transitions: Vec::new(),
+                        acknowledged: None,
+                        flapping: None,
+                    },
+                );
+                Some(Transition {
+                    line: line.to_string(),
+                    from: None,
+                    to: status,
+                    lasted: None,
+                })
+            }
+        }
+    }
+
+    /// Whether an unexpired silence covers `line`.
+    pub fn is_silenced(&self, line: &str, now: DateTime<Utc>) -> bool {
+        self.silences
+            .iter()
+            .any(|silence| silence.line == line && silence.until > now)
+    }
+
+    /// Whether `destination` was sent exactly `alerts` less than `window`
+    /// before `now`.
+    pub fn is_repeat(
+        &self,
+        destination: &str,
+        alerts: &[String],
+        window: Duration,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.sent
+            .get(destination)
+            .is_some_and(|sent| sent.alerts == alerts && now - sent.at < window)
+    }
+
+    /// Remember that `destination` was just sent `alerts`.
+    pub fn record_sent(&mut self, destination: &str, alerts: Vec<String>, now: DateTime<Utc>) {
+        self.sent.insert(
+            destination.to_string(),
+            SentNotification { at: now, alerts },
+        );
+    }
+
+    /// Delivery statistics for `channel`, created on first use.
+    pub fn channel_mut(&mut self, channel: &str) -> &mut ChannelStats {
+        self.channels.entry(channel.to_string()).or_default()
+    }
+
+    /// Compare `path` with the line's saved route.
+    ///
+    /// The first path seen (or any path when `rebaseline` is set) becomes the
+    /// baseline and compares as unchanged. A changed path never replaces the
+    /// baseline on its own, so the alert persists until an operator accepts it.
+    pub fn compare_route(
+        &mut self,
+        line: &str,
+        path: &[Option<IpAddr>],
+        rebaseline: bool,
+        now: DateTime<Utc>,
+    ) -> RouteChange {
+        if rebaseline || !self.routes.contains_key(line) {
+            self.routes.insert(
+                line.to_string(),
+                RouteBaseline {
+                    hops: path.to_vec(),
+                    recorded_at: now,
+                },
+            );
+        }
+        let baseline = &self.routes[line];
+        RouteChange {
+            differing: baseline.distance(path),
+            hops: baseline.diff(path),
+        }
+    }
+
+    /// Store the public address seen for `line`, returning the previous one if it changed.
+    pub fn observe_public_ip(
+        &mut self,
+        line: &str,
+        address: IpAddr,
+        now: DateTime<Utc>,
+    ) -> Option<IpAddr> {
+        let record = self
+            .public_ips
+            .entry(line.to_string())
+            .or_insert(PublicIpRecord {
+                address,
+                since: now,
+                changes: 0,
+            });
+        if record.address == address {
+            return None;
+        }
+        let previous = std::mem::replace(&mut record.address, address);
+        record.since = now;
+        record.changes += 1;
+        Some(previous)
+    }
+
+    /// Lines currently auto-disabled, with the settings fingerprint they were disabled under.
+    pub fn disabled_lines(&self) -> BTreeMap<String, u64> {
+        self.execution_errors
+            .iter()
+            .filter_map(|(line, errors)| {
+                Some((line.clone(), errors.disabled.as_ref()?.fingerprint))
+            })
+            .collect()
+    }
+
+    /// Count a run of `line`, returning `true` when this error disables it.
+    ///
+    /// A run without `error` resets the count and re-enables the line.
+    pub fn record_execution(
+        &mut self,
+        line: &str,
+        error: Option<&str>,
+        after_errors: u32,
+        fingerprint: u64,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let Some(error) = error else {
+            self.execution_errors.remove(line);
+            return false;
+        };
+        let entry = self
+            .execution_errors
+            .entry(line.to_string())
+            .or_insert(ExecutionErrors {
+                consecutive: 0,
+                last_error: String::new(),
+                last_failure: now,
+                disabled: None,
+            });
+        entry.consecutive += 1;
+        entry.last_error = error.to_string();
+        entry.last_failure = now;
+        if entry.disabled.is_some() || entry.consecutive < after_errors {
+            return false;
+        }
+        entry.disabled = Some(DisabledLine {
+            at: now,
+            fingerprint,
+        });
+        true
+    }
+
+    /// Count a run of `line` towards `alert_after_failures`, returning how
+    /// many runs in a row have now failed. A passing run resets the count.
+    pub fn record_failure(&mut self, line: &str, failing: bool) -> u32 {
+        if !failing {
+            self.failing_runs.remove(line);
+            return 0;
+        }
+        let runs = self.failing_runs.entry(line.to_string()).or_default();
+        *runs += 1;
+        *runs
+    }
+
+    /// Manually re-enable an auto-disabled line; `false` if it was not disabled.
+    pub fn enable_line(&mut self, line: &str) -> bool {
+        self.execution_errors
+            .remove(line)
+            .is_some_and(|errors| errors.disabled.is_some())
+    }
+
+    /// Count `line`'s status changes within `window` before `now` and update
+    /// whether it is flapping, returning the count and what changed.
+    ///
+    /// A line starts flapping at more than `threshold` changes and settles
+    /// once it has held one status for a whole window. Call after
+    /// [`AlertState::observe`] has recorded the run.
+    pub fn record_flapping(
+        &mut self,
+        line: &str,
+        threshold: u32,
+        window: Duration,
+        now: DateTime<Utc>,
+    ) -> (u32, Option<FlapChange>) {
+        let Some(entry) = self.lines.get_mut(line) else {
+            return (0, None);
+        };
+        let start = now - window;
+        let changes = entry
+            .recent_transitions
+            .iter()
+            .filter(|at| **at > start)
+            .count() as u32;
+        let change = match entry.flapping {
+            None if changes > threshold => {
+                // The change that tipped it over is still notified.
+                entry.flapping = Some(Flapping {
+                    since: now,
+                    notified: entry.status,
+                });
+                Some(FlapChange::Started)
+            }
+            Some(flapping) if entry.since <= start => {
+                entry.flapping = None;
+                Some(FlapChange::Settled {
+                    notified: flapping.notified,
+                })
+            }
+            _ => None,
+        };
+        (changes, change)
+    }
+
+    /// Drop silences that have already expired.
+    pub fn prune_silences(&mut self, now: DateTime<Utc>) {
+        self.silences.retain(|silence| silence.until > now);
+    }
+}
+
+/// Check that a raw state document is in the format this build reads.
+///
+/// v1 is the only format so far, so there is nothing to upgrade yet;
+/// documents written by a newer release are rejected rather than silently
+/// truncated.
+fn check_version(value: &serde_json::Value) -> Result<()> {
+    let version = value
+        .get("version")
+        .and_then(serde_json::Value::as_u64)
+        .context("State file has no format version")?;
+
+    match version {
+        v if v == u64::from(STATE_FORMAT_VERSION) => Ok(()),
+        v if v > u64::from(STATE_FORMAT_VERSION) => anyhow::bail!(
+            "State format v{v} was written by a newer ICMPMolester (this build reads up to v{STATE_FORMAT_VERSION})"
+        ),
+        v => anyhow::bail!("Unsupported state format v{v}"),
+    }
+}
+
+/// Numbers the temporary files of this process, so concurrent writes never
+/// share one.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// Replace `path` with `body` through a temporary file in the same
+/// directory, so readers see either the old or the new contents.
+pub fn write_atomically(path: &Path, body: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .context("Path has no file name")?
+        .to_string_lossy();
+    let tmp_path = dir.join(format!(
+        ".{file_name}.tmp-{}-{}",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(body)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp_path);
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn records_transitions_and_clears_ack_on_recovery() {
+        let mut state = AlertState::default();
+        let first = state.observe("Primary", LineStatus::Ok, at(0)).unwrap();
+        assert_eq!(first.from, None);
+        assert!(state.observe("Primary", LineStatus::Ok, at(60)).is_none());
+
+        let down = state
+            .observe("Primary", LineStatus::Alert, at(120))
+            .unwrap();
+        assert_eq!(down.from, Some(LineStatus::Ok));
+        state.lines.get_mut("Primary").unwrap().acknowledged = Some(Acknowledgement {
+            at: at(130),
+            by: Some("noc".into()),
+            note: None,
+        });
+
+        let up = state.observe("Primary", LineStatus::Ok, at(180)).unwrap();
+        assert_eq!(up.to, LineStatus::Ok);
+        assert_eq!(up.lasted, Some(Duration::seconds(60)));
+        let entry = &state.lines["Primary"];
+        assert_eq!(entry.since, at(180));
+        assert_eq!(entry.recent_transitions, vec![at(120), at(180)]);
+        assert!(entry.acknowledged.is_none());
+    }
+
+    #[test]
+    fn round_trips_through_atomic_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        let mut state = AlertState::default();
+        state.observe("Backup", LineStatus::Alert, at(10));
+        state.silences.push(Silence {
+            line: "Backup".into(),
+            until: at(1_000),
+            reason: Some("maintenance".into()),
+        });
+        state.save(&path).unwrap();
+
+        let loaded = AlertState::load(&path).unwrap();
+        assert_eq!(loaded, state);
+        assert!(loaded.is_silenced("Backup", at(500)));
+        assert!(!loaded.is_silenced("Backup", at(1_000)));
+
+        let leftovers: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(leftovers.len(), 1);
+    }
+
+    #[test]
+    fn accumulates_channel_delivery_stats() {
+        let mut state = AlertState::default();
+        state.channel_mut("telegram").record(Ok(()), 120.0, at(10));
+        state
+            .channel_mut("telegram")
+            .record(Err("HTTP 502".into()), 80.0, at(20));
+
+        let stats = &state.channels["telegram"];
+        assert_eq!(stats.attempts, 2);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.last_success, Some(at(10)));
+        assert_eq!(stats.last_error.as_deref(), Some("HTTP 502"));
+        assert_eq!(stats.average_send_ms(), Some(100.0));
+        assert_eq!(ChannelStats::default().average_send_ms(), None);
+    }
+
+    #[test]
+    fn route_baseline_tolerates_silent_hops() {
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+        let mut state = AlertState::default();
+        let original = [ip("192.0.2.1"), ip("198.51.100.1"), ip("203.0.113.10")];
+        let first = state.compare_route("Primary", &original, false, at(0));
+        assert_eq!(first.differing, 0);
+        assert!(
+            first
+                .hops
+                .iter()
+                .all(|hop| matches!(hop, HopDiff::Same { .. }))
+        );
+
+        let with_silent_hop = [ip("192.0.2.1"), None, ip("203.0.113.10")];
+        assert_eq!(
+            state
+                .compare_route("Primary", &with_silent_hop, false, at(60))
+                .differing,
+            0
+        );
+
+        let rerouted = [
+            ip("192.0.2.1"),
+            ip("198.51.100.99"),
+            ip("198.51.100.200"),
+            ip("203.0.113.10"),
+        ];
+        let change = state.compare_route("Primary", &rerouted, false, at(120));
+        assert_eq!(change.differing, 3);
+        assert_eq!(
+            change.hops[1],
+            HopDiff::Changed {
+                hop: 2,
+                before: ip("198.51.100.1"),
+                after: ip("198.51.100.99"),
+            }
+        );
+        assert_eq!(
+            change.hops[3],
+            HopDiff::Added {
+                hop: 4,
+                address: ip("203.0.113.10"),
+            }
+        );
+        assert_eq!(state.routes["Primary"].recorded_at, at(0));
+
+        let rebased = state.compare_route("Primary", &rerouted, true, at(180));
+        assert_eq!(rebased.differing, 0);
+        assert_eq!(
+            state
+                .compare_route("Primary", &rerouted, false, at(240))
+                .differing,
+            0
+        );
+    }
+
+    #[test]
+    fn tracks_public_ip_changes() {
+        let mut state = AlertState::default();
+        let first: IpAddr = "203.0.113.7".parse().unwrap();
+        let second: IpAddr = "203.0.113.99".parse().unwrap();
+
+        assert_eq!(state.observe_public_ip("Primary", first, at(0)), None);
+        assert_eq!(state.observe_public_ip("Primary", first, at(60)), None);
+        assert_eq!(
+            state.observe_public_ip("Primary", second, at(120)),
+            Some(first)
+        );
+        let record = &state.public_ips["Primary"];
+        assert_eq!(record.address, second);
+        assert_eq!(record.since, at(120));
+        assert_eq!(record.changes, 1);
+    }
+
+    #[test]
+    fn disables_lines_after_consecutive_execution_errors() {
+        let mut state = AlertState::default();
+        assert!(!state.record_execution("Primary", Some("no ping"), 2, 7, at(0)));
+        assert!(!state.record_execution("Primary", None, 2, 7, at(60)));
+        assert!(!state.record_execution("Primary", Some("no ping"), 2, 7, at(120)));
+        assert!(state.record_execution("Primary", Some("no ping"), 2, 7, at(180)));
+        assert!(!state.record_execution("Primary", Some("no ping"), 2, 7, at(240)));
+        assert_eq!(state.disabled_lines().get("Primary"), Some(&7));
+        assert_eq!(state.execution_errors["Primary"].consecutive, 3);
+
+        assert!(state.enable_line("Primary"));
+        assert!(!state.enable_line("Primary"));
+        assert!(state.disabled_lines().is_empty());
+    }
+
+    #[test]
+    fn missing_file_loads_empty_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = AlertState::load(&dir.path().join("absent.json")).unwrap();
+        assert!(state.lines.is_empty());
+        assert_eq!(state.version, STATE_FORMAT_VERSION);
+    }
+
+    #[test]
+    fn rejects_state_from_newer_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, r#"{"version": 99, "lines": {}}"#).unwrap();
+        let err = AlertState::load(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("newer"));
+    }
+
+    #[test]
+    fn concurrent_writes_use_their_own_temporary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        std::thread::scope(|scope| {
+            for writer in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..20 {
+                        write_atomically(path, format!("{writer}").as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+        assert!(fs::read_to_string(&path).unwrap().parse::<u32>().unwrap() < 8);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn recognises_repeated_notifications_within_the_window() {
+        let mut state = AlertState::default();
+        let window = Duration::hours(1);
+        let alerts = vec!["WARNING DSL packet_loss".to_string()];
+        assert!(!state.is_repeat("telegram 42", &alerts, window, at(0)));
+
+        state.record_sent("telegram 42", alerts.clone(), at(0));
+        assert!(state.is_repeat("telegram 42", &alerts, window, at(1_800)));
+        assert!(!state.is_repeat("email noc@example.org", &alerts, window, at(1_800)));
+        assert!(!state.is_repeat("telegram 42", &alerts, window, at(3_600)));
+
+        let worse = vec!["CRITICAL DSL packet_loss".to_string()];
+        assert!(!state.is_repeat("telegram 42", &worse, window, at(1_800)));
+    }
+
+    #[test]
+    fn detects_flapping_and_settles_after_a_quiet_window() {
+        let mut state = AlertState::default();
+        let window = Duration::minutes(10);
+        state.observe("DSL", LineStatus::Ok, at(0));
+        let mut started = Vec::new();
+        for (n, status) in [LineStatus::Alert, LineStatus::Ok, LineStatus::Alert]
+            .into_iter()
+            .enumerate()
+        {
+            let now = at(60 * (n as i64 + 1));
+            state.observe("DSL", status, now);
+            started.push(state.record_flapping("DSL", 2, window, now));
+        }
+        assert_eq!(
+            started,
+            [(1, None), (2, None), (3, Some(FlapChange::Started))]
+        );
+        assert_eq!(
+            state.lines["DSL"].flapping.map(|f| f.notified),
+            Some(LineStatus::Alert)
+        );
+
+        state.observe("DSL", LineStatus::Ok, at(240));
+        assert_eq!(state.record_flapping("DSL", 2, window, at(240)), (4, None));
+        assert_eq!(state.record_flapping("DSL", 2, window, at(600)).1, None);
+        assert_eq!(
+            state.record_flapping("DSL", 2, window, at(840)),
+            (
+                0,
+                Some(FlapChange::Settled {
+                    notified: LineStatus::Alert
+                })
+            )
+        );
+        assert!(state.lines["DSL"].flapping.is_none());
+    }
+}
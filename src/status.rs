@@ -0,0 +1,79 @@
+//! Rendering of persisted alert state for the `status` subcommand.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::state::AlertState;
+
+/// Print line statuses and notification channel health from the state file.
+pub fn print_status(state: &AlertState) {
+    let saved = state
+        .saved_at
+        .map(format_time)
+        .unwrap_or_else(|| "never".into());
+    println!("State format v{} (last saved {saved})", state.version);
+
+    println!();
+    println!("Lines:");
+    if state.lines.is_empty() {
+        println!("  (none recorded yet)");
+    }
+    for (name, line) in &state.lines {
+        let ack = if line.acknowledged.is_some() {
+            " [acknowledged]"
+        } else {
+            ""
+        };
+        let flapping = line
+            .flapping
+            .map(|flapping| format!(" [flapping since {}]", format_time(flapping.since)))
+            .unwrap_or_default();
+        println!(
+            "  {name}: {} since {} (last checked {}){ack}{flapping}",
+            line.status.as_str(),
+            format_time(line.since),
+            format_time(line.last_checked)
+        );
+    }
+
+    for (name, errors) in &state.execution_errors {
+        match &errors.disabled {
+            Some(disabled) => println!(
+                "  {name}: disabled since {} after {} execution errors: {}",
+                format_time(disabled.at),
+                errors.consecutive,
+                errors.last_error
+            ),
+            None => println!(
+                "  {name}: {} consecutive execution errors, last: {}",
+                errors.consecutive, errors.last_error
+            ),
+        }
+    }
+
+    println!();
+    println!("Notification channels:");
+    if state.channels.is_empty() {
+        println!("  (no deliveries recorded yet)");
+    }
+    for (channel, stats) in &state.channels {
+        let last_success = stats
+            .last_success
+            .map(format_time)
+            .unwrap_or_else(|| "never".into());
+        let average = stats
+            .average_send_ms()
+            .map(|ms| format!("{ms:.1} ms"))
+            .unwrap_or_else(|| "n/a".into());
+        println!(
+            "  {channel}: attempts={}, failures={}, last_success={last_success}, avg_send={average}",
+            stats.attempts, stats.failures
+        );
+        if let (Some(at), Some(error)) = (stats.last_failure, &stats.last_error) {
+            println!("    last failure {}: {error}", format_time(at));
+        }
+    }
+}
+
+fn format_time(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
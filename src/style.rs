@@ -0,0 +1,118 @@
+//! Colors, icons and sparklines for terminal output. Whether output is
+//! styled is decided once per process by `--color`, `NO_COLOR` and whether
+//! stdout is a terminal.
+
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+
+use crate::config::StatusColor;
+
+/// `--color`: when terminal output is styled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// When stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Settle whether output is styled. Only the first call counts; output
+/// printed before it uses [`ColorChoice::Auto`].
+pub fn init(choice: ColorChoice) {
+    let _ = ENABLED.set(resolve(
+        choice,
+        no_color_set(),
+        std::io::stdout().is_terminal(),
+    ));
+}
+
+/// Whether terminal output is styled.
+pub fn enabled() -> bool {
+    *ENABLED.get_or_init(|| {
+        resolve(
+            ColorChoice::Auto,
+            no_color_set(),
+            std::io::stdout().is_terminal(),
+        )
+    })
+}
+
+/// `NO_COLOR` disables color when set to anything but an empty string
+/// (<https://no-color.org>).
+fn no_color_set() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+fn resolve(choice: ColorChoice, no_color: bool, terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => terminal && !no_color,
+    }
+}
+
+/// `text` in `color`, or unchanged when output is not styled.
+pub fn paint(text: &str, color: StatusColor) -> String {
+    if enabled() {
+        format!("\x1b[{}m{text}\x1b[0m", color.ansi_code())
+    } else {
+        text.to_string()
+    }
+}
+
+/// Samples as block characters scaled between the lowest and highest one;
+/// a blank marks a missing sample, such as a run without a reply.
+pub fn sparkline(samples: &[Option<f32>]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let measured = samples.iter().flatten().copied();
+    let low = measured.clone().fold(f32::INFINITY, f32::min);
+    let high = measured.fold(f32::NEG_INFINITY, f32::max);
+    samples
+        .iter()
+        .map(|sample| match sample {
+            Some(value) if high > low => {
+                let level = ((value - low) / (high - low) * (BARS.len() - 1) as f32).round();
+                BARS[level as usize]
+            }
+            Some(_) => BARS[0],
+            None => ' ',
+        })
+        .collect()
+}
+
+/// `text` in bold, or unchanged when output is not styled.
+pub fn bold(text: &str) -> String {
+    if enabled() {
+        format!("\x1b[1m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_color_and_pipes_turn_auto_off() {
+        assert!(resolve(ColorChoice::Auto, false, true));
+        assert!(!resolve(ColorChoice::Auto, true, true));
+        assert!(!resolve(ColorChoice::Auto, false, false));
+        assert!(resolve(ColorChoice::Always, true, false));
+        assert!(!resolve(ColorChoice::Never, false, true));
+    }
+
+    #[test]
+    fn sparkline_scales_between_lowest_and_highest_sample() {
+        assert_eq!(
+            sparkline(&[Some(10.0), Some(17.0), None, Some(80.0)]),
+            "▁▂ █"
+        );
+        assert_eq!(sparkline(&[Some(5.0), Some(5.0)]), "▁▁");
+    }
+}
@@ -0,0 +1,232 @@
+//! `--report-template` and `[notifications.templates]`: the run summary
+//! rendered from a user's Tera template instead of the built-in wording.
+//!
+//! Templates see `run` (`run_id`, `hostname`, `started_at`, `finished_at`),
+//! `rollup` (`status`, `impacted_pct`), `counts` (`total` and the number of
+//! lines in each status, e.g. `counts.ALERT`) and `lines`, each an entry of
+//! the JSON report's `lines` plus `key`, `emoji`, `note`, `runbook_url` and
+//! the measured `loss_pct`, `latency_ms`, `jitter_ms` and `hop_count`.
+//! `alerting` holds the lines that need attention. With enough ping
+//! replies, `latency_histogram` lists their buckets (`low_ms`, `high_ms`,
+//! `count` and `scale_pct`, relative to the fullest bucket).
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tera::Tera;
+
+use crate::config::{ChannelTemplateSettings, TemplateBody};
+use crate::histogram::{Bucket, Histogram};
+use crate::report::{LineReport, RunMetadata};
+use crate::runner::{LineResult, LineStatus, Rollup, needs_attention};
+
+/// A parsed report template.
+pub struct ReportTemplate {
+    tera: Tera,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct TemplateLine<'a> {
+    #[serde(flatten)]
+    report: LineReport,
+    key: String,
+    emoji: &'a str,
+    note: Option<&'a str>,
+    runbook_url: Option<&'a str>,
+    loss_pct: Option<f32>,
+    latency_ms: Option<f32>,
+    jitter_ms: Option<f32>,
+    hop_count: Option<u32>,
+    latency_histogram: Option<Vec<Bucket>>,
+}
+
+impl ReportTemplate {
+    /// Read and parse the template at `path`. Files named `.html` or `.xml`
+    /// have their values HTML-escaped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read report template {}", path.display()))?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "report".into());
+        Self::parse(&name, &source)
+            .with_context(|| format!("Invalid report template {}", path.display()))
+    }
+
+    /// Parse `source` as the template called `name`.
+    pub fn parse(name: &str, source: &str) -> Result<Self> {
+        let mut tera = Tera::default();
+        tera.add_raw_template(name, source)?;
+        Ok(Self {
+            tera,
+            name: name.to_string(),
+        })
+    }
+
+    /// The summary of a run.
+    pub fn render(&self, results: &[LineResult], run: &RunMetadata) -> Result<String> {
+        let lines: Vec<TemplateLine> = results
+            .iter()
+            .map(|result| {
+                let ping = result.ping();
+                TemplateLine {
+                    report: LineReport::new(result),
+                    key: result.key(),
+                    emoji: result.status_emoji(),
+                    note: result.note.as_deref(),
+                    runbook_url: result.runbook_url.as_deref(),
+                    loss_pct: ping.and_then(|ping| ping.packet_loss_pct),
+                    latency_ms: ping.and_then(|ping| ping.average_latency_ms),
+                    jitter_ms: ping.and_then(|ping| ping.jitter_ms),
+                    hop_count: result
+                        .traceroute()
+                        .and_then(|traceroute| traceroute.hop_count),
+                    latency_histogram: ping
+                        .and_then(Histogram::of_ping)
+                        .map(|histogram| histogram.buckets),
+                }
+            })
+            .collect();
+        let alerting: Vec<&TemplateLine> = results
+            .iter()
+            .zip(&lines)
+            .filter(|(result, _)| needs_attention(result))
+            .map(|(_, line)| line)
+            .collect();
+        let mut counts = BTreeMap::from([("total", results.len())]);
+        for status in LineStatus::ALL {
+            let count = results
+                .iter()
+                .filter(|result| result.status() == status)
+                .count();
+            counts.insert(status.as_str(), count);
+        }
+        let mut context = tera::Context::new();
+        context.insert("run", run);
+        context.insert("rollup", &Rollup::of(results));
+        context.insert("counts", &counts);
+        context.insert("lines", &lines);
+        context.insert("alerting", &alerting);
+        // Tera's own message only says that rendering failed; the cause
+        // names the missing variable or filter.
+        self.tera.render(&self.name, &context).map_err(|err| {
+            let mut message = err.to_string();
+            let mut source = std::error::Error::source(&err);
+            while let Some(cause) = source {
+                message.push_str(&format!(": {cause}"));
+                source = cause.source();
+            }
+            anyhow::anyhow!(message)
+        })
+    }
+}
+
+/// One notification channel's own templates, from
+/// `[notifications.templates.<channel>]`.
+pub struct ChannelTemplate {
+    subject: Option<ReportTemplate>,
+    body: Option<ReportTemplate>,
+}
+
+impl ChannelTemplate {
+    /// Parse the channel's inline templates and read its `body_file`.
+    pub fn load(channel: &str, settings: &ChannelTemplateSettings) -> Result<Self> {
+        let subject = settings
+            .subject
+            .as_deref()
+            .map(|source| ReportTemplate::parse(&format!("{channel}.subject"), source))
+            .transpose()
+            .with_context(|| format!("Invalid notifications.templates.{channel}.subject"))?;
+        let body = match &settings.body {
+            Some(TemplateBody::Inline(source)) => Some(
+                ReportTemplate::parse(&format!("{channel}.body"), source)
+                    .with_context(|| format!("Invalid notifications.templates.{channel}.body"))?,
+            ),
+            Some(TemplateBody::File(path)) => Some(ReportTemplate::load(path)?),
+            None => None,
+        };
+        Ok(Self { subject, body })
+    }
+
+    /// The rendered subject, on one line, and body; `None` for whichever
+    /// the channel leaves to the defaults.
+    pub fn render(
+        &self,
+        results: &[LineResult],
+        run: &RunMetadata,
+    ) -> Result<(Option<String>, Option<String>)> {
+        let subject = self
+            .subject
+            .as_ref()
+            .map(|template| template.render(results, run))
+            .transpose()?
+            .map(|subject| subject.split_whitespace().collect::<Vec<_>>().join(" "));
+        let body = self
+            .body
+            .as_ref()
+            .map(|template| template.render(results, run))
+            .transpose()?;
+        Ok((subject, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::tests::sample_result;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn renders_lines_and_rollup() {
+        let mut result = sample_result("Backup LTE", true, Some(40.0), Some(31.5), 1.0, None);
+        result.target = "10.0.0.2".into();
+        result.note = Some("Vodafone contract 1234".into());
+        let template = ReportTemplate::parse(
+            "report.tera",
+            "Overall {{ rollup.status }}\n\
+             {% for line in lines %}{{ line.name }}: {{ line.status }}, \
+             {{ line.loss_pct }}% loss ({{ line.note }})\n{% endfor %}",
+        )
+        .unwrap();
+        let at = Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap();
+        let run = RunMetadata::new(at, at);
+        assert_eq!(
+            template
+                .render(std::slice::from_ref(&result), &run)
+                .unwrap(),
+            "Overall ALERT\nBackup LTE: ALERT, 40% loss (Vodafone contract 1234)\n"
+        );
+
+        let channel = ChannelTemplate::load(
+            "telegram",
+            &ChannelTemplateSettings {
+                subject: Some(
+                    "{{ counts.ALERT }}/{{ counts.total }} down:\n{{ counts.OK }} OK".into(),
+                ),
+                body: Some(TemplateBody::Inline(
+                    "{% for line in alerting %}{{ line.key }} {{ line.latency_ms }} ms{% endfor %}"
+                        .into(),
+                )),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            channel.render(&[result], &run).unwrap(),
+            (
+                Some("1/1 down: 0 OK".into()),
+                Some("Backup LTE 31.5 ms".into())
+            )
+        );
+
+        let err = ReportTemplate::parse("bad.tera", "{{ lines | no_such_filter }}")
+            .unwrap()
+            .render(&[], &RunMetadata::new(at, at))
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("no_such_filter"), "{err:#}");
+    }
+}
@@ -0,0 +1,424 @@
+//! `tui`: a live dashboard of every line for NOC wallboards.
+//!
+//! Every line is run at a fixed interval, like `watch`, but the results
+//! update a full-screen table instead of scrolling past. The dashboard only
+//! displays: it keeps no alert state and sends no notifications, so it can
+//! run next to a `watch` or `serve` that does.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::time::MissedTickBehavior;
+
+use crate::config::{LoadOptions, StatusColor, load_configs};
+use crate::runner::{LineResult, RunOptions, batch_name, run_loaded};
+use crate::style;
+
+/// Latency samples kept per line for its sparkline.
+const HISTORY: usize = 30;
+
+/// Settings for the `tui` subcommand.
+#[derive(Debug, Clone)]
+pub struct TuiOptions {
+    pub config: Vec<PathBuf>,
+    pub interval: Duration,
+    pub skip_traceroute: bool,
+    pub concurrency_limit: Option<usize>,
+    pub tags: Vec<String>,
+    pub load: LoadOptions,
+}
+
+/// Show the dashboard until the operator quits.
+pub async fn run_tui(options: TuiOptions) -> Result<()> {
+    // Fail, and print any config warnings, before the screen is taken over.
+    load_configs(&options.config, &options.load)?;
+    let mut terminal = ratatui::init();
+    let outcome = Dashboard::new(options).run(&mut terminal).await;
+    ratatui::restore();
+    outcome
+}
+
+/// A finished run: every line, or the one line re-probed on demand.
+struct Finished {
+    line: Option<String>,
+    results: Result<Vec<LineResult>>,
+}
+
+/// One row of the table.
+struct LineRow {
+    key: String,
+    target: String,
+    status: String,
+    color: StatusColor,
+    loss_pct: Option<f32>,
+    latency_ms: Option<f32>,
+    /// Average latency of the last runs, oldest first; `None` when no reply
+    /// came back.
+    latencies: Vec<Option<f32>>,
+    /// When the line first showed its current status.
+    changed_at: DateTime<Local>,
+    probing: bool,
+}
+
+impl LineRow {
+    fn new(result: &LineResult) -> Self {
+        let mut row = Self {
+            key: result.key(),
+            target: result.target.clone(),
+            status: String::new(),
+            color: StatusColor::Yellow,
+            loss_pct: None,
+            latency_ms: None,
+            latencies: Vec::new(),
+            changed_at: Local::now(),
+            probing: false,
+        };
+        row.update(result);
+        row
+    }
+
+    fn update(&mut self, result: &LineResult) {
+        let status = result.display_status();
+        if status != self.status {
+            self.status = status.to_string();
+            self.changed_at = Local::now();
+        }
+        self.target = result.target.clone();
+        self.color = result.status_color();
+        let ping = result.ping();
+        self.loss_pct = ping.and_then(|ping| ping.packet_loss_pct);
+        self.latency_ms = ping.and_then(|ping| ping.average_latency_ms);
+        if self.latencies.len() == HISTORY {
+            self.latencies.remove(0);
+        }
+        self.latencies.push(self.latency_ms);
+        self.probing = false;
+    }
+}
+
+/// What the run loop does after a key press.
+enum Action {
+    None,
+    RunAll,
+    Quit,
+}
+
+struct Dashboard {
+    options: TuiOptions,
+    rows: Vec<LineRow>,
+    table: TableState,
+    /// Whether a run of every line is in progress.
+    running: bool,
+    next_run: Instant,
+    last_run: Option<DateTime<Local>>,
+    /// Why the last run failed, if it did.
+    error: Option<String>,
+}
+
+impl Dashboard {
+    fn new(options: TuiOptions) -> Self {
+        Self {
+            next_run: Instant::now() + options.interval,
+            options,
+            rows: Vec::new(),
+            table: TableState::default().with_selected(Some(0)),
+            running: false,
+            last_run: None,
+            error: None,
+        }
+    }
+
+    async fn run(mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let (event_tx, mut events) = mpsc::unbounded_channel();
+        std::thread::spawn(move || read_events(event_tx));
+        let (done_tx, mut done) = mpsc::unbounded_channel();
+        let mut ticker = tokio::time::interval(self.options.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Keeps the countdown to the next run moving.
+        let mut redraw = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            terminal.draw(|frame| self.render(frame))?;
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(Event::Key(key)) => match self.on_key(key.code, key.modifiers, &done_tx) {
+                        Action::Quit => break,
+                        // The run starts at the tick, which then counts from here.
+                        Action::RunAll => ticker.reset_immediately(),
+                        Action::None => {}
+                    },
+                    Some(_) => {}
+                    None => break,
+                },
+                Some(finished) = done.recv() => self.apply(finished),
+                _ = ticker.tick() => {
+                    self.next_run = Instant::now() + self.options.interval;
+                    self.probe(None, &done_tx);
+                }
+                _ = redraw.tick() => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn on_key(
+        &mut self,
+        code: KeyCode,
+        modifiers: KeyModifiers,
+        done: &UnboundedSender<Finished>,
+    ) -> Action {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Action::Quit,
+            KeyCode::Down | KeyCode::Char('j') => self.table.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.table.select_previous(),
+            KeyCode::Enter | KeyCode::Char('r') => {
+                if let Some(row) = self.table.selected().and_then(|index| self.rows.get(index)) {
+                    let key = row.key.clone();
+                    self.probe(Some(key), done);
+                }
+            }
+            KeyCode::Char('a') => return Action::RunAll,
+            _ => {}
+        }
+        Action::None
+    }
+
+    /// Start a run of every line, or of `line` alone, in the background.
+    fn probe(&mut self, line: Option<String>, done: &UnboundedSender<Finished>) {
+        match &line {
+            Some(key) => {
+                let Some(row) = self.rows.iter_mut().find(|row| row.key == *key) else {
+                    return;
+                };
+                if row.probing {
+                    return;
+                }
+                row.probing = true;
+            }
+            None => {
+                if self.running {
+                    return;
+                }
+                self.running = true;
+                for row in &mut self.rows {
+                    row.probing = true;
+                }
+            }
+        }
+        let options = self.options.clone();
+        let done = done.clone();
+        tokio::spawn(async move {
+            let results = probe(&options, line.as_deref()).await;
+            let _ = done.send(Finished { line, results });
+        });
+    }
+
+    fn apply(&mut self, finished: Finished) {
+        let full = finished.line.is_none();
+        if full {
+            self.running = false;
+        }
+        let results = match finished.results {
+            Ok(results) => results,
+            Err(err) => {
+                self.error = Some(format!("{err:#}"));
+                for row in &mut self.rows {
+                    if full || finished.line.as_ref() == Some(&row.key) {
+                        row.probing = false;
+                    }
+                }
+                return;
+            }
+        };
+        self.error = None;
+        if !full {
+            for result in &results {
+                let key = result.key();
+                if let Some(row) = self.rows.iter_mut().find(|row| row.key == key) {
+                    row.update(result);
+                }
+            }
+            return;
+        }
+        // Follow the config: new lines appear and removed ones go.
+        let mut previous = std::mem::take(&mut self.rows);
+        for result in &results {
+            let key = result.key();
+            match previous.iter().position(|row| row.key == key) {
+                Some(index) => {
+                    let mut row = previous.swap_remove(index);
+                    row.update(result);
+                    self.rows.push(row);
+                }
+                None => self.rows.push(LineRow::new(result)),
+            }
+        }
+        self.last_run = Some(Local::now());
+        if self
+            .table
+            .selected()
+            .is_none_or(|index| index >= self.rows.len())
+        {
+            self.table.select((!self.rows.is_empty()).then_some(0));
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let [title, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let progress = if self.running {
+            "running…".to_string()
+        } else {
+            let left = self.next_run.saturating_duration_since(Instant::now());
+            format!("next run in {}s", left.as_secs())
+        };
+        let last = self
+            .last_run
+            .map_or("never".to_string(), |at| at.format("%H:%M:%S").to_string());
+        frame.render_widget(
+            Paragraph::new(format!(
+                "ICMPMolester — every {}s, {progress}, last run {last}",
+                self.options.interval.as_secs()
+            ))
+            .style(bold()),
+            title,
+        );
+
+        let rows = self.rows.iter().map(|row| {
+            let status = if row.probing {
+                format!("{} …", row.status)
+            } else {
+                row.status.clone()
+            };
+            Row::new([
+                Cell::from(row.key.clone()),
+                Cell::from(row.target.clone()),
+                Cell::from(status).style(status_style(row.color)),
+                Cell::from(
+                    row.loss_pct
+                        .map_or("-".into(), |loss| format!("{loss:.1}%")),
+                ),
+                Cell::from(
+                    row.latency_ms
+                        .map_or("-".into(), |latency| format!("{latency:.1} ms")),
+                ),
+                Cell::from(style::sparkline(&row.latencies)),
+                Cell::from(row.changed_at.format("%H:%M:%S").to_string()),
+            ])
+        });
+        let header = Row::new([
+            "Line", "Target", "Status", "Loss", "Latency", "Trend", "Since",
+        ])
+        .style(bold());
+        let widths = [
+            Constraint::Fill(2),
+            Constraint::Fill(1),
+            Constraint::Length(18),
+            Constraint::Length(7),
+            Constraint::Length(10),
+            Constraint::Length(HISTORY as u16),
+            Constraint::Length(8),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::bordered())
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(table, body, &mut self.table);
+
+        let help = match &self.error {
+            Some(error) => {
+                Line::from(format!("Run failed: {error}")).style(status_style(StatusColor::Red))
+            }
+            None => Line::from("↑/↓ select  r re-probe line  a run all  q quit"),
+        };
+        frame.render_widget(Paragraph::new(help), footer);
+    }
+}
+
+/// Run every configured line, or only the one keyed `only`.
+async fn probe(options: &TuiOptions, only: Option<&str>) -> Result<Vec<LineResult>> {
+    let mut configs = load_configs(&options.config, &options.load)?;
+    if let Some(key) = only {
+        // Keep every config, even emptied, so batches keep their names.
+        let batched = configs.len() > 1;
+        for (path, config) in &mut configs {
+            let batch = batched.then(|| batch_name(path));
+            config.lines.retain(|line| match &batch {
+                Some(batch) => format!("{batch}/{}", line.name) == key,
+                None => line.name == key,
+            });
+        }
+    }
+    let run = RunOptions {
+        skip_traceroute: options.skip_traceroute,
+        concurrency_limit: options.concurrency_limit,
+        history: None,
+        batch: None,
+        disabled: Default::default(),
+        simulation: None,
+        tags: options.tags.clone(),
+        due: None,
+        interruptible: false,
+        load: options.load.clone(),
+        on_line_finished: None,
+    };
+    run_loaded(configs, run).await
+}
+
+/// Forward terminal events until the dashboard stops listening.
+fn read_events(events: UnboundedSender<Event>) {
+    loop {
+        match event::poll(Duration::from_millis(250)) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Press => {}
+                Ok(event) => {
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            },
+            Ok(false) if events.is_closed() => return,
+            Ok(false) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+fn bold() -> Style {
+    if style::enabled() {
+        Style::new().add_modifier(Modifier::BOLD)
+    } else {
+        Style::new()
+    }
+}
+
+fn status_style(color: StatusColor) -> Style {
+    if !style::enabled() {
+        return Style::new();
+    }
+    Style::new().fg(match color {
+        StatusColor::Red => Color::Red,
+        StatusColor::Green => Color::Green,
+        StatusColor::Yellow => Color::Yellow,
+        StatusColor::Blue => Color::Blue,
+        StatusColor::Magenta => Color::Magenta,
+        StatusColor::Cyan => Color::Cyan,
+    })
+}
@@ -0,0 +1,293 @@
+//! Just enough XMPP to post notifications: STARTTLS, SASL PLAIN, resource
+//! binding, then one message per recipient and room before closing the
+//! stream. The password is only ever sent over TLS.
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConnection, StreamOwned};
+
+use crate::probes::tls::client_config;
+use crate::tunnel::Tunnel;
+
+/// Client-to-server port, used unless a server is given.
+pub const CLIENT_PORT: u16 = 5222;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const IO_TIMEOUT: Duration = Duration::from_secs(15);
+const RESOURCE: &str = "icmpmolester";
+
+/// Where one message goes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recipient {
+    /// A user, sent a `chat` message.
+    Chat(String),
+    /// A multi-user chat room, joined as `nick` and sent a `groupchat` message.
+    Room { jid: String, nick: String },
+}
+
+/// Log in as `jid` and send `body` to every recipient.
+///
+/// Connects to `server` (`host[:port]`) when given, otherwise to the JID's
+/// domain on [`CLIENT_PORT`]; DNS SRV records are not consulted.
+pub fn send(
+    jid: &str,
+    password: &str,
+    server: Option<&str>,
+    tunnel: Option<&Tunnel>,
+    recipients: &[Recipient],
+    body: &str,
+) -> Result<()> {
+    let (local, domain) = split_jid(jid)?;
+    let (host, port) = match server {
+        Some(server) => match server.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("Invalid port in XMPP server {server}"))?,
+            ),
+            None => (server, CLIENT_PORT),
+        },
+        None => (domain, CLIENT_PORT),
+    };
+    let address: SocketAddr = match tunnel {
+        Some(tunnel) => tunnel
+            .forward(host, port)
+            .with_context(|| format!("Failed to open tunnel via {tunnel}"))?,
+        None => (host, port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {host}:{port}"))?
+            .next()
+            .ok_or_else(|| anyhow!("{host}:{port} resolved to no address"))?,
+    };
+    let tcp = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {host}:{port}"))?;
+    tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut plain = XmlStream::new(&tcp);
+    let features = plain.open(domain)?;
+    if !features.contains("urn:ietf:params:xml:ns:xmpp-tls") {
+        anyhow::bail!("{host} does not offer STARTTLS; refusing to log in without TLS");
+    }
+    plain.write("<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>")?;
+    if !plain
+        .read_until(&["<proceed", "<failure"])?
+        .contains("<proceed")
+    {
+        anyhow::bail!("{host} refused to start TLS");
+    }
+
+    // The certificate must name the JID's domain, whatever host serves it.
+    let name = ServerName::try_from(domain.to_string())
+        .with_context(|| format!("Invalid XMPP domain {domain}"))?;
+    let connection =
+        ClientConnection::new(client_config(), name).context("Failed to start the TLS session")?;
+    let mut tls = XmlStream::new(StreamOwned::new(connection, &tcp));
+    let features = tls.open(domain)?;
+    if !features.contains(">PLAIN<") {
+        anyhow::bail!("{host} offers no PLAIN login");
+    }
+    tls.write(&format!(
+        "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>{}</auth>",
+        sasl_plain(local, password)
+    ))?;
+    if !tls
+        .read_until(&["<success", "<failure"])?
+        .contains("<success")
+    {
+        anyhow::bail!("The XMPP server rejected the JID or password");
+    }
+
+    tls.open(domain)?;
+    tls.write(&format!(
+        "<iq type='set' id='bind'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'>\
+         <resource>{RESOURCE}</resource></bind></iq>"
+    ))?;
+    if is_error(&tls.read_until(&["</iq>"])?) {
+        anyhow::bail!("The XMPP server refused to bind a resource");
+    }
+
+    for (index, recipient) in recipients.iter().enumerate() {
+        if let Recipient::Room { jid, nick } = recipient {
+            tls.write(&format!(
+                "<presence to='{}/{}'><x xmlns='http://jabber.org/protocol/muc'>\
+                 <history maxstanzas='0'/></x></presence>",
+                escape(jid),
+                escape(nick)
+            ))?;
+            // Our own presence in the room carries status code 110.
+            let joined = tls.read_until(&[
+                "code='110'",
+                "code=\"110\"",
+                "type='error'",
+                "type=\"error\"",
+            ])?;
+            if is_error(&joined) {
+                anyhow::bail!("Could not join {jid} as {nick}");
+            }
+        }
+        tls.write(&message_stanza(recipient, index, body))?;
+    }
+    tls.write("</stream:stream>")?;
+    Ok(())
+}
+
+/// Split a bare JID into its local part and domain.
+pub fn split_jid(jid: &str) -> Result<(&str, &str)> {
+    match jid.split_once('@') {
+        Some((local, domain))
+            if !local.is_empty() && !domain.is_empty() && !domain.contains(['/', '@']) =>
+        {
+            Ok((local, domain))
+        }
+        _ => Err(anyhow!("{jid} is not a bare JID such as probe@example.org")),
+    }
+}
+
+fn sasl_plain(local: &str, password: &str) -> String {
+    STANDARD.encode(format!("\0{local}\0{password}"))
+}
+
+fn message_stanza(recipient: &Recipient, index: usize, body: &str) -> String {
+    let (to, kind) = match recipient {
+        Recipient::Chat(jid) => (jid, "chat"),
+        Recipient::Room { jid, .. } => (jid, "groupchat"),
+    };
+    format!(
+        "<message to='{}' type='{kind}' id='icmpmolester-{index}'><body>{}</body></message>",
+        escape(to),
+        escape(body)
+    )
+}
+
+fn is_error(reply: &str) -> bool {
+    reply.contains("type='error'") || reply.contains("type=\"error\"")
+}
+
+/// Escape text for an XML attribute or element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+/// One side of an XML stream, read by looking for markers rather than parsed:
+/// the client only needs to know which of a few replies arrived.
+struct XmlStream<S> {
+    stream: S,
+    buffer: String,
+}
+
+impl<S: Read + Write> XmlStream<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: String::new(),
+        }
+    }
+
+    /// Open a stream to `domain` and return its features.
+    fn open(&mut self, domain: &str) -> Result<String> {
+        self.write(&format!(
+            "<?xml version='1.0'?><stream:stream to='{}' version='1.0' \
+             xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'>",
+            escape(domain)
+        ))?;
+        self.read_until(&["</stream:features>", "<stream:features/>"])
+    }
+
+    fn write(&mut self, xml: &str) -> Result<()> {
+        self.stream
+            .write_all(xml.as_bytes())
+            .and_then(|()| self.stream.flush())
+            .context("Failed to write to the XMPP server")
+    }
+
+    /// Read until one of `markers` has arrived along with the end of its
+    /// tag, returning everything up to there; the rest stays buffered.
+    fn read_until(&mut self, markers: &[&str]) -> Result<String> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            let end = markers
+                .iter()
+                .filter_map(|marker| {
+                    let at = self.buffer.find(marker)?;
+                    Some(at + self.buffer[at..].find('>')? + 1)
+                })
+                .min();
+            if let Some(end) = end {
+                let rest = self.buffer.split_off(end);
+                return Ok(std::mem::replace(&mut self.buffer, rest));
+            }
+            if self.buffer.contains("</stream:stream>") || self.buffer.contains("<stream:error") {
+                anyhow::bail!("The XMPP server closed the stream: {}", self.buffer.trim());
+            }
+            let read = self
+                .stream
+                .read(&mut chunk)
+                .context("Failed to read from the XMPP server")?;
+            if read == 0 {
+                anyhow::bail!("The XMPP server closed the connection");
+            }
+            self.buffer
+                .push_str(&String::from_utf8_lossy(&chunk[..read]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_login_and_message_stanzas() {
+        assert_eq!(
+            split_jid("probe@example.org").unwrap(),
+            ("probe", "example.org")
+        );
+        assert!(split_jid("example.org").is_err());
+        assert!(split_jid("probe@example.org/phone").is_err());
+
+        assert_eq!(sasl_plain("probe", "s3cr3t"), "AHByb2JlAHMzY3IzdA==");
+
+        assert_eq!(
+            message_stanza(
+                &Recipient::Chat("noc@example.org".into()),
+                0,
+                "Backup <LTE>: ALERT & 'down'"
+            ),
+            "<message to='noc@example.org' type='chat' id='icmpmolester-0'>\
+             <body>Backup &lt;LTE&gt;: ALERT &amp; &apos;down&apos;</body></message>"
+        );
+        let room = Recipient::Room {
+            jid: "noc@conference.example.org".into(),
+            nick: "probe".into(),
+        };
+        assert!(message_stanza(&room, 1, "OK").contains("type='groupchat' id='icmpmolester-1'"));
+
+        let mut stream = XmlStream::new(std::io::Cursor::new(
+            b"<stream:features><starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>\
+              </stream:features><proceed xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>"
+                .to_vec(),
+        ));
+        assert!(
+            stream
+                .read_until(&["</stream:features>"])
+                .unwrap()
+                .contains("starttls")
+        );
+        assert!(
+            stream
+                .read_until(&["<proceed"])
+                .unwrap()
+                .starts_with("<proceed")
+        );
+    }
+}
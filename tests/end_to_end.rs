@@ -0,0 +1,427 @@
+//! Full runs of the CLI against scripted `ping`/`traceroute` shims, checked
+//! down to the webhook notification. Needs `--features test-harness`.
+#![cfg(unix)]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use icmpmolester::harness::{FakeTools, Script, WebhookReceiver};
+
+const HEALTHY_PING: &str = "\
+PING 192.0.2.10 (192.0.2.10) 56(84) bytes of data.
+64 bytes from 192.0.2.10: icmp_seq=1 ttl=57 time=11.8 ms
+64 bytes from 192.0.2.10: icmp_seq=2 ttl=57 time=12.1 ms
+
+--- 192.0.2.10 ping statistics ---
+2 packets transmitted, 2 received, 0% packet loss, time 1001ms
+rtt min/avg/max/mdev = 11.800/11.950/12.100/0.150 ms
+";
+
+const LOSSY_PING: &str = "\
+PING 192.0.2.20 (192.0.2.20) 56(84) bytes of data.
+64 bytes from 192.0.2.20: icmp_seq=2 ttl=57 time=30.4 ms
+
+--- 192.0.2.20 ping statistics ---
+2 packets transmitted, 1 received, 50% packet loss, time 1001ms
+rtt min/avg/max/mdev = 30.400/30.400/30.400/0.000 ms
+";
+
+const TRACEROUTE: &str = "\
+traceroute to 192.0.2.10 (192.0.2.10), 30 hops max, 60 byte packets
+ 1  192.168.1.1  0.512 ms  0.480 ms  0.466 ms
+ 2  192.0.2.10  6.821 ms  6.790 ms  6.902 ms
+";
+
+const CONFIG: &str = r#"
+[defaults]
+ping_count = 2
+
+[[lines]]
+name = "Fiber"
+target = "192.0.2.10"
+
+[[lines]]
+name = "LTE"
+target = "192.0.2.20"
+checks = ["ping"]
+"#;
+
+fn run(tools: &FakeTools, config: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_icmpmolester"))
+        .arg("--config")
+        .arg(config)
+        .args(args)
+        .env("PATH", tools.path_env())
+        .output()
+        .unwrap()
+}
+
+fn write_config(tools: &FakeTools) -> std::path::PathBuf {
+    let path = tools.dir().join("lines.toml");
+    fs::write(&path, CONFIG).unwrap();
+    path
+}
+
+#[test]
+fn lossy_line_alerts_through_to_the_webhook() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .script("ping", "192.0.2.10", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script(
+            "ping",
+            "192.0.2.20",
+            Script::new(LOSSY_PING).delay(Duration::from_millis(200)),
+        )
+        .unwrap()
+        .script("traceroute", "192.0.2.10", Script::new(TRACEROUTE))
+        .unwrap();
+    let config = write_config(&tools);
+    let webhook = WebhookReceiver::start().unwrap();
+
+    let output = run(&tools, &config, &["--webhook-url", &webhook.url()]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // An alerting line makes the run exit with code 2.
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    assert!(
+        stdout.contains("Notification dispatched via webhook"),
+        "{stdout}"
+    );
+
+    let payloads = webhook.payloads();
+    assert_eq!(payloads.len(), 1);
+    let payload = &payloads[0];
+    assert_eq!(payload["status"], "ALERT");
+    assert_eq!(payload["alert_count"], 1);
+    assert_eq!(payload["alerts"][0]["line"], "LTE");
+    assert_eq!(payload["alerts"][0]["check"], "ping");
+    let summary = payload["summary"].as_str().unwrap();
+    assert!(summary.contains("Fiber (192.0.2.10): OK"), "{summary}");
+    assert!(summary.contains("hops=2"), "{summary}");
+
+    assert_eq!(tools.invocations("ping").len(), 2);
+    assert!(tools.invocations("traceroute")[0].ends_with("192.0.2.10"));
+}
+
+#[test]
+fn notify_on_change_reports_lines_first_seen_alerting() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .script("ping", "192.0.2.10", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script("ping", "192.0.2.20", Script::new(LOSSY_PING))
+        .unwrap();
+    let config = write_config(&tools);
+    let state = tools.dir().join("state.json");
+    let webhook = WebhookReceiver::start().unwrap();
+    let args = [
+        "--skip-traceroute",
+        "--notify-on-change",
+        "--state",
+        state.to_str().unwrap(),
+        "--webhook-url",
+        &webhook.url(),
+    ];
+
+    // On a fresh state file the alerting line is new, so it is reported...
+    let output = run(&tools, &config, &args);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    assert_eq!(webhook.payloads().len(), 1, "{stdout}");
+    assert_eq!(webhook.payloads()[0]["alerts"][0]["line"], "LTE");
+
+    // ...but not again while nothing changes.
+    let output = run(&tools, &config, &args);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    assert_eq!(webhook.payloads().len(), 1, "{stdout}");
+}
+
+#[test]
+fn dedup_window_holds_back_repeated_alerts() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .script("ping", "192.0.2.10", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script("ping", "192.0.2.20", Script::new(LOSSY_PING))
+        .unwrap();
+    let config = write_config(&tools);
+    let state = tools.dir().join("state.json");
+    let webhook = WebhookReceiver::start().unwrap();
+    let args = [
+        "--skip-traceroute",
+        "--state",
+        state.to_str().unwrap(),
+        "--notify-dedup-window",
+        "1h",
+        "--webhook-url",
+        &webhook.url(),
+    ];
+
+    let output = run(&tools, &config, &args);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    assert_eq!(webhook.payloads().len(), 1, "{stdout}");
+
+    // The same alert within the hour is not sent again.
+    let output = run(&tools, &config, &args);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    assert!(
+        stdout.contains("held back: same alerts as last sent"),
+        "{stdout}"
+    );
+    assert_eq!(webhook.payloads().len(), 1, "{stdout}");
+
+    // The record keys the webhook by a digest of its URL, never the path.
+    let saved = fs::read_to_string(&state).unwrap();
+    assert!(!saved.contains("/hook"), "{saved}");
+}
+
+/// Serve `body` to every GET, counting the requests.
+fn serve_config(body: &'static str) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/lines.toml", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&requests);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                line.clear();
+            }
+            counted.fetch_add(1, Ordering::SeqCst);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = reader.get_mut().write_all(response.as_bytes());
+        }
+    });
+    (url, requests)
+}
+
+#[test]
+fn remote_configs_are_fetched_once_per_run() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .script("ping", "192.0.2.10", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script("ping", "192.0.2.20", Script::new(LOSSY_PING))
+        .unwrap();
+    let (url, requests) = serve_config(CONFIG);
+    let cache = tools.dir().join("cache");
+    let webhook = WebhookReceiver::start().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_icmpmolester"))
+        .args(["--config", &url, "--config-cache"])
+        .arg(&cache)
+        .args(["--skip-traceroute", "--webhook-url", &webhook.url()])
+        .env("PATH", tools.path_env())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    assert_eq!(webhook.payloads().len(), 1, "{stdout}");
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn discord_hears_only_the_lines_routed_to_it() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .script("ping", "192.0.2.10", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script("ping", "192.0.2.20", Script::new(LOSSY_PING))
+        .unwrap();
+    let discord = WebhookReceiver::start().unwrap();
+    let config = tools.dir().join("lines.toml");
+    fs::write(
+        &config,
+        CONFIG.replace(
+            "checks = [\"ping\"]",
+            &format!(
+                "checks = [\"ping\"]\ndiscord_webhook = \"{}\"",
+                discord.url()
+            ),
+        ),
+    )
+    .unwrap();
+
+    let output = run(&tools, &config, &["--skip-traceroute"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    let payloads = discord.payloads();
+    assert_eq!(payloads.len(), 1, "{stdout}");
+    let text = payloads[0].to_string();
+    assert!(text.contains("LTE"), "{text}");
+    assert!(!text.contains("Fiber"), "{text}");
+}
+
+#[test]
+fn a_failing_channel_does_not_stop_the_others() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .script("ping", "192.0.2.10", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script("ping", "192.0.2.20", Script::new(LOSSY_PING))
+        .unwrap();
+    let config = write_config(&tools);
+    let teams = WebhookReceiver::start().unwrap();
+    // Nothing listens on a port that was just released.
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap();
+    let dead = format!("http://{}/hook", closed.local_addr().unwrap());
+    drop(closed);
+
+    let output = run(
+        &tools,
+        &config,
+        &[
+            "--skip-traceroute",
+            "--webhook-url",
+            &dead,
+            "--teams-webhook",
+            &teams.url(),
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert_eq!(teams.payloads().len(), 1, "{stderr}");
+    assert!(stderr.contains("webhook:"), "{stderr}");
+}
+
+#[test]
+fn machine_readable_output_keeps_status_messages_off_stdout() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .script("ping", "192.0.2.10", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script("ping", "192.0.2.20", Script::new(LOSSY_PING))
+        .unwrap();
+    let config = write_config(&tools);
+    let state = tools.dir().join("state.json");
+    let webhook = WebhookReceiver::start().unwrap();
+
+    let output = run(
+        &tools,
+        &config,
+        &[
+            "--skip-traceroute",
+            "--output",
+            "ndjson",
+            "--state",
+            state.to_str().unwrap(),
+            "--webhook-url",
+            &webhook.url(),
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(webhook.payloads().len(), 1, "{stdout}");
+    for line in stdout.lines() {
+        assert!(
+            serde_json::from_str::<serde_json::Value>(line).is_ok(),
+            "{stdout}"
+        );
+    }
+    assert!(
+        stderr.contains("Notification dispatched via webhook"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn failing_tools_mark_the_line_as_alerting() {
+    let mut tools = FakeTools::new().unwrap();
+    tools
+        .fallback("ping", Script::new(HEALTHY_PING))
+        .unwrap()
+        .script(
+            "traceroute",
+            "192.0.2.10",
+            Script::new("traceroute: connect: Network is unreachable\n").exit_code(1),
+        )
+        .unwrap();
+    let config = write_config(&tools);
+
+    let output = run(&tools, &config, &["--skip-traceroute"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(!stdout.contains("ALERT"), "{stdout}");
+    assert!(tools.invocations("traceroute").is_empty());
+
+    let output = run(&tools, &config, &[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Overall status: ALERT"), "{stdout}");
+    assert!(stdout.contains("Network is unreachable"), "{stdout}");
+}
+
+#[test]
+fn runs_with_history_finish_writing_and_exit() {
+    let mut tools = FakeTools::new().unwrap();
+    tools.fallback("ping", Script::new(HEALTHY_PING)).unwrap();
+    let config = write_config(&tools);
+    let history = tools.dir().join("history.db");
+
+    let mut stdout = String::new();
+    for _ in 0..2 {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_icmpmolester"))
+            .arg("--config")
+            .arg(&config)
+            .args(["--skip-traceroute", "--history"])
+            .arg(&history)
+            .env("PATH", tools.path_env())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // A run that keeps the history writer open never exits.
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break status;
+            }
+            if Instant::now() > deadline {
+                child.kill().unwrap();
+                panic!("the run did not exit after writing history");
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        assert!(status.success());
+        stdout = String::from_utf8_lossy(&child.wait_with_output().unwrap().stdout).into_owned();
+    }
+    assert!(history.exists());
+    // Both runs' latency is the same, so the sparkline is flat.
+    assert!(
+        stdout.contains("Latency trend (last 2 runs): ▁▁"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn email_graphs_take_the_history_from_the_config() {
+    let mut tools = FakeTools::new().unwrap();
+    tools.fallback("ping", Script::new(HEALTHY_PING)).unwrap();
+    let history = tools.dir().join("history.db");
+    let config = tools.dir().join("lines.toml");
+    fs::write(
+        &config,
+        format!("[run]\nhistory = \"{}\"\n{CONFIG}", history.display()),
+    )
+    .unwrap();
+
+    let output = run(
+        &tools,
+        &config,
+        &["--skip-traceroute", "--email-graphs", "24h"],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(history.exists());
+}
@@ -0,0 +1,99 @@
+//! Runs the public parsers against every sample under `tests/corpus`.
+//!
+//! Each `<kind>/<name>.txt` holds raw tool output and `<kind>/<name>.toml`
+//! what the parsers should extract from it; see the README for the format.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use icmpmolester::parse::{self, Hop, PingSummary};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct ExpectedRoute {
+    /// Passed to `hop_count`; the hop count is only checked when set.
+    target: Option<String>,
+    hop_count: Option<u32>,
+    #[serde(default)]
+    hops: Vec<Hop>,
+}
+
+fn samples(kind: &str) -> Vec<(PathBuf, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/corpus")
+        .join(kind);
+    let mut samples: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|err| panic!("cannot read {}: {err}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .map(|path| {
+            let raw = fs::read_to_string(&path).unwrap();
+            (path, raw)
+        })
+        .collect();
+    samples.sort();
+    assert!(!samples.is_empty(), "no samples in {}", dir.display());
+    samples
+}
+
+fn expected<T: for<'de> Deserialize<'de>>(sample: &Path) -> T {
+    let path = sample.with_extension("toml");
+    let raw =
+        fs::read_to_string(&path).unwrap_or_else(|err| panic!("missing {}: {err}", path.display()));
+    toml::from_str(&raw).unwrap_or_else(|err| panic!("invalid {}: {err}", path.display()))
+}
+
+/// Feed every prefix of the sample to `parser`, as a cut-off run would.
+fn survives_truncation(raw: &str, parser: impl Fn(&str)) {
+    for (end, _) in raw.char_indices() {
+        parser(&raw[..end]);
+    }
+}
+
+#[test]
+fn ping_samples() {
+    for (path, raw) in samples("ping") {
+        let want: PingSummary = expected(&path);
+        assert_eq!(parse::ping_summary(&raw), want, "{}", path.display());
+        survives_truncation(&raw, |text| {
+            parse::ping_summary(text);
+            parse::reply_rtts(text);
+        });
+    }
+}
+
+#[test]
+fn traceroute_samples() {
+    for (path, raw) in samples("traceroute") {
+        let want: ExpectedRoute = expected(&path);
+        assert_eq!(
+            parse::traceroute_hops(&raw),
+            want.hops,
+            "{}",
+            path.display()
+        );
+        if let Some(target) = &want.target {
+            assert_eq!(
+                parse::hop_count(&raw, target),
+                want.hop_count,
+                "{}",
+                path.display()
+            );
+        }
+        survives_truncation(&raw, |text| {
+            parse::traceroute_hops(text);
+            parse::hop_count(text, want.target.as_deref().unwrap_or_default());
+        });
+    }
+}
+
+#[test]
+fn mtr_samples() {
+    for (path, raw) in samples("mtr") {
+        let want: ExpectedRoute = expected(&path);
+        assert_eq!(parse::mtr_hops(&raw), want.hops, "{}", path.display());
+        survives_truncation(&raw, |text| {
+            parse::mtr_hops(text);
+        });
+    }
+}